@@ -0,0 +1,238 @@
+//! The owning pointer the AST holds its child nodes through, and the arena a parse allocates
+//! them in.
+//!
+//! A parse makes and throws away a great many small nodes, and allocating each on its own
+//! was most of its time.  `NodeArena` hands out memory from large chunks instead and frees
+//! them all at once.  A `Boxed` owns its node like a `Box` does, so the AST can still be
+//! taken apart, rewritten and cloned; nodes that are made outside a parse, by the builder
+//! or the optimizer, and clones of parsed nodes are simply boxed on the heap.
+
+use std::alloc::Layout;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem::{self, MaybeUninit};
+use std::ops::{Deref, DerefMut};
+use std::ptr::{self, NonNull};
+
+/// The size of the chunks the arena allocates from.  Larger nodes get a chunk of their own.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Memory for the nodes of one parse, freed when the arena is dropped.
+///
+/// The arena only provides the memory: the nodes are dropped by the `Boxed` that owns each of
+/// them, which the `'a` of `alloc` keeps from outliving the arena.
+pub struct NodeArena {
+    chunks: RefCell<Vec<NonNull<[MaybeUninit<u8>]>>>,
+    /// The free part of the last chunk.
+    next: Cell<*mut u8>,
+    end: Cell<*mut u8>,
+    /// Whether to box every node instead, as parses did before there was an arena.
+    boxing: bool,
+}
+
+impl Default for NodeArena {
+    fn default() -> Self {
+        Self {
+            chunks: RefCell::new(vec![]),
+            next: Cell::new(ptr::null_mut()),
+            end: Cell::new(ptr::null_mut()),
+            boxing: false,
+        }
+    }
+}
+
+impl fmt::Debug for NodeArena {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NodeArena").field("chunks", &self.chunks.borrow().len()).finish()
+    }
+}
+
+impl NodeArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An arena that boxes every node on the heap, for comparing the two.
+    pub fn boxing() -> Self {
+        let mut arena = Self::default();
+        arena.boxing = true;
+        arena
+    }
+
+    /// Moves `value` into the arena.
+    pub fn alloc<'a, T>(&'a self, value: T) -> Boxed<'a, T> {
+        let layout = Layout::new::<T>();
+        if self.boxing || layout.size() == 0 {
+            return Boxed::new(value);
+        }
+        let ptr = self.reserve(layout).cast::<T>();
+        // SAFETY: `reserve` returned memory for a `T` that nothing else uses.
+        unsafe { ptr.as_ptr().write(value) };
+        Boxed { ptr, in_arena: true, _owns: PhantomData }
+    }
+
+    /// Memory for `layout`, from the last chunk if it has room and from a new one otherwise.
+    fn reserve(&self, layout: Layout) -> NonNull<u8> {
+        let next = self.next.get();
+        let padding = next.align_offset(layout.align());
+        let free = self.end.get() as usize - next as usize;
+        if !next.is_null() && padding + layout.size() <= free {
+            // SAFETY: the padding and the value fit in what is left of the chunk.
+            let start = unsafe { next.add(padding) };
+            self.next.set(unsafe { start.add(layout.size()) });
+            return NonNull::new(start).expect("chunk pointer");
+        }
+        let size = CHUNK_SIZE.max(layout.size() + layout.align());
+        let chunk = NonNull::from(Box::leak(Box::<[u8]>::new_uninit_slice(size)));
+        let start = chunk.cast::<u8>().as_ptr();
+        self.chunks.borrow_mut().push(chunk);
+        self.next.set(start);
+        // SAFETY: one past the end of the chunk.
+        self.end.set(unsafe { start.add(size) });
+        self.reserve(layout)
+    }
+}
+
+impl Drop for NodeArena {
+    fn drop(&mut self) {
+        for chunk in self.chunks.get_mut().drain(..) {
+            // SAFETY: each chunk was leaked from a box in `reserve`, and the nodes in it have
+            // been dropped by their `Boxed`, which cannot outlive the arena.
+            drop(unsafe { Box::from_raw(chunk.as_ptr()) });
+        }
+    }
+}
+
+/// An owned child node, in a `NodeArena` or on the heap.
+pub struct Boxed<'a, T> {
+    ptr: NonNull<T>,
+    in_arena: bool,
+    _owns: PhantomData<(T, &'a NodeArena)>,
+}
+
+impl<'a, T> Boxed<'a, T> {
+    /// Boxes `value` on the heap.
+    pub fn new(value: T) -> Self {
+        let ptr = NonNull::from(Box::leak(Box::new(value)));
+        Self { ptr, in_arena: false, _owns: PhantomData }
+    }
+
+    /// Moves the node out.
+    pub fn into_inner(this: Self) -> T {
+        let this = mem::ManuallyDrop::new(this);
+        // SAFETY: the node is read once and its memory is not used again; a heap box is
+        // freed without dropping the node a second time.
+        unsafe {
+            let value = this.ptr.as_ptr().read();
+            if !this.in_arena {
+                drop(Box::from_raw(this.ptr.as_ptr().cast::<MaybeUninit<T>>()));
+            }
+            value
+        }
+    }
+}
+
+impl<T> Drop for Boxed<'_, T> {
+    fn drop(&mut self) {
+        // SAFETY: the node is owned by this `Boxed` alone.  Arena memory is freed with the
+        // arena.
+        unsafe {
+            match self.in_arena {
+                true => ptr::drop_in_place(self.ptr.as_ptr()),
+                false => drop(Box::from_raw(self.ptr.as_ptr())),
+            }
+        }
+    }
+}
+
+impl<T> Deref for Boxed<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: the node is valid for as long as its owner.
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> DerefMut for Boxed<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: as for `deref`, and `&mut self` makes the access unique.
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T> AsRef<T> for Boxed<'_, T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T> AsMut<T> for Boxed<'_, T> {
+    fn as_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+impl<T> std::borrow::Borrow<T> for Boxed<'_, T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+/// A clone is boxed on the heap, wherever the original is.
+impl<T: Clone> Clone for Boxed<'_, T> {
+    fn clone(&self) -> Self {
+        Self::new(T::clone(self))
+    }
+}
+
+impl<T: Default> Default for Boxed<'_, T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Boxed<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        T::fmt(self, f)
+    }
+}
+
+impl<T: PartialEq> PartialEq for Boxed<'_, T> {
+    fn eq(&self, other: &Self) -> bool {
+        T::eq(self, other)
+    }
+}
+
+impl<T: Eq> Eq for Boxed<'_, T> {}
+
+impl<T: std::hash::Hash> std::hash::Hash for Boxed<'_, T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        T::hash(self, state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::{Boxed, NodeArena};
+
+    #[test]
+    fn nodes_are_dropped_once_by_their_owner() {
+        let counter = Rc::new(());
+        let arena = NodeArena::new();
+        let kept: Vec<Boxed<(Rc<()>, [u64; 3])>> = (0..10_000).map(|_| arena.alloc((counter.clone(), [7; 3]))).collect();
+        let moved = Boxed::into_inner(arena.alloc((counter.clone(), [8; 3])));
+        let cloned = kept[5].clone();
+        assert_eq!(Rc::strong_count(&counter), 10_003);
+        assert_eq!(cloned.1, [7; 3]);
+        drop(kept);
+        drop(cloned);
+        assert_eq!(Rc::strong_count(&counter), 2);
+        assert_eq!(moved.1, [8; 3]);
+        let big = arena.alloc([1u8; 100_000]);
+        assert_eq!(big[99_999], 1);
+    }
+}
@@ -0,0 +1,506 @@
+//! Functions for building well-formed nodes in code, for tools that generate Python rather
+//! than parse it.
+//!
+//! ```ignore
+//! let hello = func_def("hello", params([]), [expr_stmt(call(name("print"), [str_lit("hi")]))]);
+//! let source = module([hello.into()]).to_source();
+//! ```
+//!
+//! Numeric and string literals become `Expression::Constant`s, since there is no source text
+//! to point at; `True`, `False` and `None` are names, as the parser produces them.  Tokens the
+//! nodes need internally are shared placeholders, and every span is `Span::default()`.
+
+use std::convert::TryFrom;
+
+use super::{
+    Annotation, Arg, AsName, Assign, AssignEqual, AssignTarget, AssignTargetExpression, Attribute, BaseSlice,
+    BinaryOp, BinaryOperation, BooleanOp, BooleanOperation, Boxed, Call, ClassDef, CompOp, Comparison, ComparisonTarget,
+    CompoundStatement, Constant, ConstantValue, Dict, DictElement, Dot, Element, Else, Expr, Expression, For, From,
+    FunctionDef, If, Import, ImportAlias, ImportFrom, ImportNames, ImportStar, IndentedBlock, Index, Lambda, List,
+    Module, Name, NameOrAttribute, OrElse, Param, Parameters, Raise, Return, SimpleStatementLine, SmallStatement,
+    Span, Statement, Subscript, SubscriptElement, Suite, Tuple, UnaryOp, UnaryOperation, While,
+};
+use crate::bigint::BigInt;
+use crate::tokenizer::{Position, TType, Token};
+
+const fn placeholder(r#type: TType, text: &'static str) -> Token<'static> {
+    Token {
+        r#type,
+        start: Position { col: 0, line: 0 },
+        end: Position { col: 0, line: 0 },
+        text,
+    }
+}
+
+static EQUAL: Token<'static> = placeholder(TType::Op, "=");
+static NEWLINE: Token<'static> = placeholder(TType::Newline, "\n");
+static INDENT: Token<'static> = placeholder(TType::Indent, "    ");
+static DEDENT: Token<'static> = placeholder(TType::Dedent, "");
+static END_MARKER: Token<'static> = placeholder(TType::EndMarker, "");
+
+fn assign_equal<'a>() -> AssignEqual<'a> {
+    AssignEqual { tok: &EQUAL }
+}
+
+fn constant<'a>(value: ConstantValue) -> Expression<'a> {
+    Expression::Constant(Boxed::new(Constant { value, span: Span::default() }))
+}
+
+pub fn name<'a>(value: &'a str) -> Expression<'a> {
+    Expression::Name(Boxed::new(Name { value, span: Span::default() }))
+}
+
+/// `value.attr`
+pub fn attr<'a>(value: Expression<'a>, attr: &'a str) -> Expression<'a> {
+    Expression::Attribute(Boxed::new(Attribute {
+        value: Boxed::new(value),
+        attr: Name { value: attr, span: Span::default() },
+        span: Span::default(),
+    }))
+}
+
+/// A dotted name such as `os.path.join`.
+pub fn dotted<'a>(path: &'a str) -> Expression<'a> {
+    let mut parts = path.split('.');
+    let first = name(parts.next().unwrap_or(path));
+    parts.fold(first, attr)
+}
+
+pub fn none<'a>() -> Expression<'a> {
+    name("None")
+}
+
+pub fn bool_lit<'a>(value: bool) -> Expression<'a> {
+    name(if value { "True" } else { "False" })
+}
+
+pub fn int_lit<'a>(value: i64) -> Expression<'a> {
+    constant(ConstantValue::Int(BigInt::from_i64(value)))
+}
+
+pub fn float_lit<'a>(value: f64) -> Expression<'a> {
+    constant(ConstantValue::Float(value))
+}
+
+pub fn str_lit<'a>(value: &str) -> Expression<'a> {
+    constant(ConstantValue::Str(value.to_string()))
+}
+
+pub fn bytes_lit<'a>(value: &[u8]) -> Expression<'a> {
+    constant(ConstantValue::Bytes(value.to_vec()))
+}
+
+pub fn tuple<'a>(elements: impl IntoIterator<Item = Expression<'a>>) -> Expression<'a> {
+    Expression::Tuple(Boxed::new(Tuple {
+        elements: elements.into_iter().map(Element::from).collect(),
+        span: Span::default(),
+    }))
+}
+
+pub fn list<'a>(elements: impl IntoIterator<Item = Expression<'a>>) -> Expression<'a> {
+    Expression::List(Boxed::new(List {
+        elements: elements.into_iter().map(Element::from).collect(),
+        span: Span::default(),
+    }))
+}
+
+pub fn dict<'a>(items: impl IntoIterator<Item = (Expression<'a>, Expression<'a>)>) -> Expression<'a> {
+    Expression::Dict(Boxed::new(Dict {
+        elements: items
+            .into_iter()
+            .map(|(key, value)| DictElement::Simple { key, value })
+            .collect(),
+        span: Span::default(),
+    }))
+}
+
+/// `value[index]`
+pub fn subscript<'a>(value: Expression<'a>, index: Expression<'a>) -> Expression<'a> {
+    Expression::Subscript(Boxed::new(Subscript {
+        value: Boxed::new(value),
+        slice: vec![SubscriptElement {
+            slice: BaseSlice::Index(Boxed::new(Index { value: index })),
+        }],
+        span: Span::default(),
+    }))
+}
+
+pub fn binop<'a>(left: Expression<'a>, operator: BinaryOp, right: Expression<'a>) -> Expression<'a> {
+    Expression::BinaryOperation(Boxed::new(BinaryOperation {
+        left: Boxed::new(left),
+        operator,
+        right: Boxed::new(right),
+        lpar: vec![],
+        rpar: vec![],
+        span: Span::default(),
+    }))
+}
+
+pub fn boolop<'a>(left: Expression<'a>, operator: BooleanOp, right: Expression<'a>) -> Expression<'a> {
+    Expression::BooleanOperation(Boxed::new(BooleanOperation {
+        left: Boxed::new(left),
+        operator,
+        right: Boxed::new(right),
+        lpar: vec![],
+        rpar: vec![],
+        span: Span::default(),
+    }))
+}
+
+pub fn unary<'a>(operator: UnaryOp, expression: Expression<'a>) -> Expression<'a> {
+    Expression::UnaryOperation(Boxed::new(UnaryOperation {
+        operator,
+        expression: Boxed::new(expression),
+        span: Span::default(),
+    }))
+}
+
+pub fn compare<'a>(left: Expression<'a>, operator: CompOp, comparator: Expression<'a>) -> Expression<'a> {
+    Expression::Comparison(Boxed::new(Comparison {
+        left: Boxed::new(left),
+        comparisons: vec![ComparisonTarget { operator, comparator }],
+        span: Span::default(),
+    }))
+}
+
+pub fn lambda<'a>(params: Parameters<'a>, body: Expression<'a>) -> Expression<'a> {
+    Expression::Lambda(Boxed::new(Lambda {
+        params: Boxed::new(params),
+        body: Boxed::new(body),
+        span: Span::default(),
+    }))
+}
+
+/// `func(args...)`.  Plain expressions become positional arguments; use [`keyword`],
+/// [`star_arg`] and [`kwargs`] for the other kinds.
+pub fn call<'a, A: Into<Arg<'a>>>(func: Expression<'a>, args: impl IntoIterator<Item = A>) -> Expression<'a> {
+    Expression::Call(Boxed::new(Call {
+        func: Boxed::new(func),
+        args: args.into_iter().map(Into::into).collect(),
+        span: Span::default(),
+    }))
+}
+
+impl<'a> std::convert::From<Expression<'a>> for Arg<'a> {
+    fn from(value: Expression<'a>) -> Self {
+        Self {
+            value,
+            keyword: None,
+            equal: None,
+            comma: None,
+            star: "",
+        }
+    }
+}
+
+/// `keyword=value`
+pub fn keyword<'a>(keyword: &'a str, value: Expression<'a>) -> Arg<'a> {
+    Arg {
+        keyword: Some(Name { value: keyword, span: Span::default() }),
+        equal: Some(assign_equal()),
+        ..value.into()
+    }
+}
+
+/// `*value`
+pub fn star_arg<'a>(value: Expression<'a>) -> Arg<'a> {
+    Arg { star: "*", ..value.into() }
+}
+
+/// `**value`
+pub fn kwargs<'a>(value: Expression<'a>) -> Arg<'a> {
+    Arg { star: "**", ..value.into() }
+}
+
+pub fn param<'a>(name: &'a str) -> Param<'a> {
+    Param {
+        name: Name { value: name, span: Span::default() },
+        ..Default::default()
+    }
+}
+
+impl<'a> Param<'a> {
+    pub fn with_default(self, default: Expression<'a>) -> Self {
+        Self {
+            equal: Some(assign_equal()),
+            default: Some(default),
+            ..self
+        }
+    }
+
+    pub fn with_annotation(self, annotation: Expression<'a>) -> Self {
+        Self {
+            annotation: Some(Annotation { annotation }),
+            ..self
+        }
+    }
+}
+
+/// Positional-or-keyword parameters, as in `def f(a, b=1)`.
+pub fn params<'a>(params: impl IntoIterator<Item = Param<'a>>) -> Parameters<'a> {
+    Parameters {
+        params: params.into_iter().collect(),
+        ..Default::default()
+    }
+}
+
+fn simple<'a>(stmt: SmallStatement<'a>) -> Statement<'a> {
+    Statement::Simple(SimpleStatementLine {
+        body: vec![stmt],
+        span: Span::default(),
+    })
+}
+
+fn block<'a>(body: impl IntoIterator<Item = Statement<'a>>) -> Suite<'a> {
+    Suite::IndentedBlock(IndentedBlock {
+        body: body.into_iter().collect(),
+        indent: None,
+        newline_tok: &NEWLINE,
+        indent_tok: &INDENT,
+        dedent_tok: &DEDENT,
+    })
+}
+
+fn assign_target<'a>(target: Expression<'a>) -> AssignTargetExpression<'a> {
+    AssignTargetExpression::try_from(target)
+        .unwrap_or_else(|target| panic!("cannot assign to {}", target.to_source()))
+}
+
+pub fn pass<'a>() -> Statement<'a> {
+    simple(SmallStatement::Pass)
+}
+
+pub fn expr_stmt<'a>(value: Expression<'a>) -> Statement<'a> {
+    simple(SmallStatement::Expr(Expr { value }))
+}
+
+/// `target = ... = value`
+///
+/// # Panics
+///
+/// If a target is not assignable, e.g. a call or a literal.
+pub fn assign<'a>(targets: impl IntoIterator<Item = Expression<'a>>, value: Expression<'a>) -> Statement<'a> {
+    simple(SmallStatement::Assign(Assign {
+        targets: targets
+            .into_iter()
+            .map(|target| AssignTarget {
+                target: assign_target(target),
+            })
+            .collect(),
+        value,
+    }))
+}
+
+pub fn return_stmt<'a>(value: Option<Expression<'a>>) -> Statement<'a> {
+    simple(SmallStatement::Return(Return { value, span: Span::default() }))
+}
+
+pub fn raise<'a>(exc: Option<Expression<'a>>, cause: Option<Expression<'a>>) -> Statement<'a> {
+    simple(SmallStatement::Raise(Raise {
+        exc,
+        cause: cause.map(|item| From { item }),
+    }))
+}
+
+fn import_alias<'a>((module, asname): (&'a str, Option<&'a str>)) -> ImportAlias<'a> {
+    let name = match dotted(module) {
+        Expression::Attribute(a) => NameOrAttribute::A(a),
+        Expression::Name(n) => NameOrAttribute::N(n),
+        _ => unreachable!("dotted only builds names and attributes"),
+    };
+    ImportAlias {
+        name,
+        asname: asname.map(|asname| AsName {
+            name: AssignTargetExpression::Name(Boxed::new(Name { value: asname, span: Span::default() })),
+        }),
+    }
+}
+
+/// `import a.b, c as d` from `[("a.b", None), ("c", Some("d"))]`.
+pub fn import<'a>(names: impl IntoIterator<Item = (&'a str, Option<&'a str>)>) -> Statement<'a> {
+    simple(SmallStatement::Import(Import {
+        names: names.into_iter().map(import_alias).collect(),
+    }))
+}
+
+/// `from module import a, b as c`.  Leading dots in `module` make the import relative, and
+/// a name of `*` imports everything.
+pub fn import_from<'a>(module: &'a str, names: impl IntoIterator<Item = (&'a str, Option<&'a str>)>) -> Statement<'a> {
+    let dotted_module = module.trim_start_matches('.');
+    let level = module.len() - dotted_module.len();
+    let aliases: Vec<ImportAlias> = names.into_iter().map(import_alias).collect();
+    let star = matches!(aliases.as_slice(), [ImportAlias { name: NameOrAttribute::N(n), asname: None }] if n.value == "*");
+    simple(SmallStatement::ImportFrom(ImportFrom {
+        module: match dotted_module {
+            "" => None,
+            module => Some(import_alias((module, None)).name),
+        },
+        names: if star {
+            ImportNames::Star(ImportStar {})
+        } else {
+            ImportNames::Aliases(aliases)
+        },
+        relative: (0..level).map(|_| Dot {}).collect(),
+    }))
+}
+
+pub fn func_def<'a>(
+    name: &'a str,
+    params: Parameters<'a>,
+    body: impl IntoIterator<Item = Statement<'a>>,
+) -> FunctionDef<'a> {
+    FunctionDef {
+        name: Name { value: name, span: Span::default() },
+        type_params: vec![],
+        params,
+        body: block(body),
+        decorators: vec![],
+        returns: None,
+        asynchronous: None,
+        span: Span::default(),
+    }
+}
+
+impl<'a> FunctionDef<'a> {
+    pub fn with_returns(self, returns: Expression<'a>) -> Self {
+        Self {
+            returns: Some(Annotation { annotation: returns }),
+            ..self
+        }
+    }
+}
+
+pub fn class_def<'a>(
+    name: &'a str,
+    bases: impl IntoIterator<Item = Expression<'a>>,
+    body: impl IntoIterator<Item = Statement<'a>>,
+) -> ClassDef<'a> {
+    ClassDef {
+        name: Name { value: name, span: Span::default() },
+        type_params: vec![],
+        body: block(body),
+        bases: bases.into_iter().map(Arg::from).collect(),
+        keywords: vec![],
+        decorators: vec![],
+        span: Span::default(),
+    }
+}
+
+/// An `if` with an optional `else`; nest another `if_stmt` in `orelse` for an `elif`.
+pub fn if_stmt<'a>(
+    test: Expression<'a>,
+    body: impl IntoIterator<Item = Statement<'a>>,
+    orelse: impl IntoIterator<Item = Statement<'a>>,
+) -> Statement<'a> {
+    let orelse: Vec<Statement> = orelse.into_iter().collect();
+    let orelse = match <[Statement; 1]>::try_from(orelse) {
+        Ok([Statement::Compound(CompoundStatement::If(elif))]) => {
+            Some(Boxed::new(OrElse::Elif(If { is_elif: true, ..elif })))
+        }
+        Ok([other]) => Some(Boxed::new(OrElse::Else(Else { body: block([other]) }))),
+        Err(orelse) if orelse.is_empty() => None,
+        Err(orelse) => Some(Boxed::new(OrElse::Else(Else { body: block(orelse) }))),
+    };
+    Statement::Compound(CompoundStatement::If(If {
+        test,
+        body: block(body),
+        orelse,
+        is_elif: false,
+        span: Span::default(),
+    }))
+}
+
+/// # Panics
+///
+/// If `target` is not assignable.
+pub fn for_stmt<'a>(
+    target: Expression<'a>,
+    iter: Expression<'a>,
+    body: impl IntoIterator<Item = Statement<'a>>,
+) -> Statement<'a> {
+    Statement::Compound(CompoundStatement::For(For {
+        target: assign_target(target),
+        iter,
+        body: block(body),
+        orelse: None,
+        asynchronous: None,
+        span: Span::default(),
+    }))
+}
+
+pub fn while_stmt<'a>(test: Expression<'a>, body: impl IntoIterator<Item = Statement<'a>>) -> Statement<'a> {
+    Statement::Compound(CompoundStatement::While(While {
+        test,
+        body: block(body),
+        orelse: None,
+        span: Span::default(),
+    }))
+}
+
+pub fn module<'a>(body: impl IntoIterator<Item = Statement<'a>>) -> Module<'a> {
+    Module {
+        body: body.into_iter().collect(),
+        default_indent: "    ",
+        default_newline: "\n",
+        has_trailing_newline: true,
+        encoding: "utf-8".to_string(),
+        eof_tok: &END_MARKER,
+    }
+}
+
+impl<'a> std::convert::From<FunctionDef<'a>> for Statement<'a> {
+    fn from(f: FunctionDef<'a>) -> Self {
+        Statement::Compound(CompoundStatement::FunctionDef(f))
+    }
+}
+
+impl<'a> std::convert::From<ClassDef<'a>> for Statement<'a> {
+    fn from(c: ClassDef<'a>) -> Self {
+        Statement::Compound(CompoundStatement::ClassDef(c))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_printable_module() {
+        let greet = func_def(
+            "greet",
+            params([param("who").with_annotation(name("str")), param("times").with_default(int_lit(1))]),
+            [
+                for_stmt(
+                    name("_"),
+                    call(name("range"), [name("times")]),
+                    [expr_stmt(call(name("print"), [str_lit("hi"), name("who")]))],
+                ),
+                return_stmt(Some(binop(binop(int_lit(1), BinaryOp::Add, int_lit(2)), BinaryOp::Multiply, int_lit(3)))),
+            ],
+        )
+        .with_returns(name("int"));
+        let m = module([
+            import_from("..pkg", [("thing", Some("other"))]),
+            greet.into(),
+            if_stmt(
+                compare(name("__name__"), CompOp::Equal, str_lit("__main__")),
+                [assign([name("x"), attr(name("self"), "y")], tuple([int_lit(-1)]))],
+                [if_stmt(bool_lit(false), [pass()], [])],
+            ),
+            expr_stmt(call(dotted("os.path.join"), [keyword("sep", str_lit("it's"))])),
+        ]);
+        assert_eq!(
+            m.to_source(),
+            "from ..pkg import thing as other\n\
+             def greet(who: str, times=1) -> int:\n    \
+                 for _ in range(times):\n        \
+                     print('hi', who)\n    \
+                 return (1 + 2) * 3\n\
+             if __name__ == '__main__':\n    \
+                 x = self.y = (-1,)\n\
+             elif False:\n    \
+                 pass\n\
+             os.path.join(sep=\"it's\")\n"
+        );
+    }
+}
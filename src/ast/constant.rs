@@ -0,0 +1,75 @@
+use super::span::Span;
+use crate::bigint::BigInt;
+
+/// A value computed ahead of time, e.g. by constant folding.  The parser itself never
+/// produces these; literals keep their source text until something evaluates them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Constant {
+    pub value: ConstantValue,
+    /// The span of the expression this value was folded from.
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum ConstantValue {
+    None,
+    Ellipsis,
+    Bool(bool),
+    Int(BigInt),
+    Float(f64),
+    Complex { real: f64, imag: f64 },
+    Str(std::string::String),
+    Bytes(Vec<u8>),
+    Tuple(Vec<ConstantValue>),
+    FrozenSet(Vec<ConstantValue>),
+}
+
+impl ConstantValue {
+    /// Python truthiness, as used by `if`, `not`, `and` and `or`.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Self::None => false,
+            Self::Ellipsis => true,
+            Self::Bool(b) => *b,
+            Self::Int(i) => !i.is_zero(),
+            Self::Float(f) => *f != 0.0,
+            Self::Complex { real, imag } => *real != 0.0 || *imag != 0.0,
+            Self::Str(s) => !s.is_empty(),
+            Self::Bytes(b) => !b.is_empty(),
+            Self::Tuple(items) | Self::FrozenSet(items) => !items.is_empty(),
+        }
+    }
+
+    /// Number of constants this value holds, counting nested tuples and frozensets.
+    pub fn total_items(&self) -> usize {
+        match self {
+            Self::Tuple(items) | Self::FrozenSet(items) => {
+                items.len() + items.iter().map(|item| item.total_items()).sum::<usize>()
+            }
+            _ => 0,
+        }
+    }
+}
+
+/// Constants compare by identity of their representation the way CPython's compiler does
+/// when it deduplicates them: `1`, `1.0` and `True` are distinct, and so are `0.0` and
+/// `-0.0`.
+impl PartialEq for ConstantValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::None, Self::None) | (Self::Ellipsis, Self::Ellipsis) => true,
+            (Self::Bool(l), Self::Bool(r)) => l == r,
+            (Self::Int(l), Self::Int(r)) => l == r,
+            (Self::Float(l), Self::Float(r)) => l.to_bits() == r.to_bits(),
+            (Self::Complex { real: lr, imag: li }, Self::Complex { real: rr, imag: ri }) => {
+                lr.to_bits() == rr.to_bits() && li.to_bits() == ri.to_bits()
+            }
+            (Self::Str(l), Self::Str(r)) => l == r,
+            (Self::Bytes(l), Self::Bytes(r)) => l == r,
+            (Self::Tuple(l), Self::Tuple(r)) | (Self::FrozenSet(l), Self::FrozenSet(r)) => l == r,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for ConstantValue {}
@@ -4,6 +4,8 @@ use crate::parser::TokVec;
 use peg::Parse;
 use thiserror::Error;
 
+use super::span::Span;
+
 #[allow(clippy::enum_variant_names)]
 #[derive(Debug, Error, PartialEq, Eq)]
 pub enum ParserError<'a> {
@@ -16,4 +18,20 @@ pub enum ParserError<'a> {
     ),
     #[error("invalid operator")]
     OperatorError,
-}
\ No newline at end of file
+    #[error("{0}")]
+    SyntaxError(SyntaxError),
+}
+/// An error CPython raises while compiling a module that the grammar itself accepts, such as
+/// `return` at module level.  `span` is the statement the error was found in.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("{} (line {})", .message, .span.start.line)]
+pub struct SyntaxError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl SyntaxError {
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        Self { message: message.into(), span }
+    }
+}
@@ -1,4 +1,4 @@
-use std::{mem::swap, rc::Rc};
+use std::mem::swap;
 
 use crate::tokenizer::Token;
 
@@ -7,10 +7,13 @@ use super::op::{
 };
 
 use super::statement::Annotation;
+use super::constant::Constant;
+use super::span::Span;
+use super::boxed::Boxed;
 
 use super::traits::WithComma;
 
-type TokenRef<'a> = Rc<Token<'a>>;
+type TokenRef<'a> = &'a Token<'a>;
 
 // Atomic nodes
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -21,64 +24,100 @@ pub struct Comma {
 #[derive(Clone, PartialEq, Eq, Default, Debug)]
 pub struct Name<'a> {
     pub value: &'a str,
+    pub span: Span,
 }
 
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub enum NameOrAttribute<'a> {
-    N(Box<Name<'a>>),
-    A(Box<Attribute<'a>>),
+    N(Boxed<'a, Name<'a>>),
+    A(Boxed<'a, Attribute<'a>>),
+}
+
+impl<'a> NameOrAttribute<'a> {
+    /// The dotted name as written, e.g. `"os.path"` for `import os.path`.
+    pub fn dotted_name(&self) -> std::string::String {
+        fn push(expr: &Expression, out: &mut std::string::String) {
+            match expr {
+                Expression::Name(n) => out.push_str(n.value),
+                Expression::Attribute(a) => {
+                    push(&a.value, out);
+                    out.push('.');
+                    out.push_str(a.attr.value);
+                }
+                _ => {}
+            }
+        }
+        let mut out = std::string::String::new();
+        match self {
+            Self::N(n) => out.push_str(n.value),
+            Self::A(a) => {
+                push(&a.value, &mut out);
+                out.push('.');
+                out.push_str(a.attr.value);
+            }
+        }
+        out
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Integer<'a> {
     //Because it can be 1234 and 1_234 it must be stored as a string
     pub value: &'a str,
+    pub span: Span,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Float<'a> {
     pub value: &'a str,
+    pub span: Span,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Binary<'a> {
     pub value: &'a str,
+    pub span: Span,
 }
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct BinaryOperation<'a> {
-    pub left: Box<Expression<'a>>,
+    pub left: Boxed<'a, Expression<'a>>,
     pub operator: BinaryOp,
-    pub right: Box<Expression<'a>>,
+    pub right: Boxed<'a, Expression<'a>>,
     pub lpar: Vec<LeftParen<'a>>,
     pub rpar: Vec<RightParen<'a>>,
+    pub span: Span,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 #[cfg_attr(feature = "py", derive(TryIntoPy))]
 pub struct BooleanOperation<'a> {
-    pub left: Box<Expression<'a>>,
+    pub left: Boxed<'a, Expression<'a>>,
     pub operator: BooleanOp,
-    pub right: Box<Expression<'a>>,
+    pub right: Boxed<'a, Expression<'a>>,
     pub lpar: Vec<LeftParen<'a>>,
     pub rpar: Vec<RightParen<'a>>,
+    pub span: Span,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Hexidecimal<'a> {
     pub value: &'a str,
+    pub span: Span,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Imaginary<'a> {
     pub value: &'a str,
+    pub span: Span,
 }
 
 // Semi-atomic/more complex nodes
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Comparison<'a> {
     // kind of surprised Rust lets me make this recursive/orobus pattern
-    pub left: Box<Expression<'a>>,
+    pub left: Boxed<'a, Expression<'a>>,
     pub comparisons: Vec<ComparisonTarget<'a>>,
+    pub span: Span,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -93,18 +132,19 @@ pub enum Element<'a> {
     Simple {
         value: Expression<'a>,
     },
-    Starred(Box<StarredElement<'a>>),
+    Starred(Boxed<'a, StarredElement<'a>>),
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct StarredElement<'a> {
-    pub value: Box<Expression<'a>>,
+    pub value: Boxed<'a, Expression<'a>>,
+    pub span: Span,
 }
 
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
 pub struct Ellipsis {
-
+    pub span: Span,
 }
 
 
@@ -114,38 +154,38 @@ pub struct Ellipsis {
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Expression<'a> {
-    Name(Box<Name<'a>>),
-    Ellipsis,
-    Integer(Box<Integer<'a>>),
-    Float(Box<Float<'a>>),
-    Binary(Box<Binary<'a>>),
-    Hexidecimal(Box<Hexidecimal<'a>>),
-    Imaginary(Box<Imaginary<'a>>),
-    Comparison(Box<Comparison<'a>>),
-    UnaryOperation(Box<UnaryOperation<'a>>),
-    BinaryOperation(Box<BinaryOperation<'a>>),
-    BooleanOperation(Box<BooleanOperation<'a>>),
-    Attribute(Box<Attribute<'a>>),
-    Tuple(Box<Tuple<'a>>),
-    Call(Box<Call<'a>>),
-    GeneratorExp(Box<GeneratorExp<'a>>),
-    ListComp(Box<ListComp<'a>>),
-    SetComp(Box<SetComp<'a>>),
-    DictComp(Box<DictComp<'a>>),
-    List(Box<List<'a>>),
-    Set(Box<Set<'a>>),
-    Dict(Box<Dict<'a>>),
-    Subscript(Box<Subscript<'a>>),
-    StarredElement(Box<StarredElement<'a>>),
-    IfExp(Box<IfExp<'a>>),
-    Lambda(Box<Lambda<'a>>),
-    Yield(Box<Yield<'a>>),
-    Await(Box<Await<'a>>),
-    SimpleString(Box<SimpleString<'a>>),
-    ConcatenatedString(Box<ConcatenatedString<'a>>),
-    FormattedString(Box<FormattedString<'a>>),
-    NamedExpr(Box<NamedExpr<'a>>),
-
+    Name(Boxed<'a, Name<'a>>),
+    Ellipsis(Boxed<'a, Ellipsis>),
+    Integer(Boxed<'a, Integer<'a>>),
+    Float(Boxed<'a, Float<'a>>),
+    Binary(Boxed<'a, Binary<'a>>),
+    Hexidecimal(Boxed<'a, Hexidecimal<'a>>),
+    Imaginary(Boxed<'a, Imaginary<'a>>),
+    Comparison(Boxed<'a, Comparison<'a>>),
+    UnaryOperation(Boxed<'a, UnaryOperation<'a>>),
+    BinaryOperation(Boxed<'a, BinaryOperation<'a>>),
+    BooleanOperation(Boxed<'a, BooleanOperation<'a>>),
+    Attribute(Boxed<'a, Attribute<'a>>),
+    Tuple(Boxed<'a, Tuple<'a>>),
+    Call(Boxed<'a, Call<'a>>),
+    GeneratorExp(Boxed<'a, GeneratorExp<'a>>),
+    ListComp(Boxed<'a, ListComp<'a>>),
+    SetComp(Boxed<'a, SetComp<'a>>),
+    DictComp(Boxed<'a, DictComp<'a>>),
+    List(Boxed<'a, List<'a>>),
+    Set(Boxed<'a, Set<'a>>),
+    Dict(Boxed<'a, Dict<'a>>),
+    Subscript(Boxed<'a, Subscript<'a>>),
+    StarredElement(Boxed<'a, StarredElement<'a>>),
+    IfExp(Boxed<'a, IfExp<'a>>),
+    Lambda(Boxed<'a, Lambda<'a>>),
+    Yield(Boxed<'a, Yield<'a>>),
+    Await(Boxed<'a, Await<'a>>),
+    SimpleString(Boxed<'a, SimpleString<'a>>),
+    ConcatenatedString(Boxed<'a, ConcatenatedString<'a>>),
+    FormattedString(Boxed<'a, FormattedString<'a>>),
+    NamedExpr(Boxed<'a, NamedExpr<'a>>),
+    Constant(Boxed<'a, Constant>),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -159,27 +199,31 @@ pub struct Arg<'a> {
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Attribute<'a> {
-    pub value: Box<Expression<'a>>,
+    pub value: Boxed<'a, Expression<'a>>,
     pub attr: Name<'a>,
+    pub span: Span,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Default)]
 pub struct Tuple<'a> {
     pub elements: Vec<Element<'a>>,
+    pub span: Span,
 }
 
 
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Call<'a> {
-    pub func: Box<Expression<'a>>,
+    pub func: Boxed<'a, Expression<'a>>,
     pub args: Vec<Arg<'a>>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct GeneratorExp<'a> {
-    pub elt: Box<Expression<'a>>,
-    pub for_in: Box<CompFor<'a>>,
+    pub elt: Boxed<'a, Expression<'a>>,
+    pub for_in: Boxed<'a, CompFor<'a>>,
+    pub span: Span,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -187,7 +231,7 @@ pub struct CompFor<'a> {
     pub target: AssignTargetExpression<'a>,
     pub iter: Expression<'a>,
     pub ifs: Vec<CompIf<'a>>,
-    pub inner_for_in: Option<Box<CompFor<'a>>>,
+    pub inner_for_in: Option<Boxed<'a, CompFor<'a>>>,
     pub asynchronous: Option<Asynchronous>,
 }
 
@@ -212,23 +256,24 @@ pub struct CompIf<'a> {
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum AssignTargetExpression<'a> {
-    Name(Box<Name<'a>>),
-    Attribute(Box<Attribute<'a>>),
-    StarredElement(Box<StarredElement<'a>>),
-    Tuple(Box<Tuple<'a>>),
-    List(Box<List<'a>>),
-    Subscript(Box<Subscript<'a>>),
+    Name(Boxed<'a, Name<'a>>),
+    Attribute(Boxed<'a, Attribute<'a>>),
+    StarredElement(Boxed<'a, StarredElement<'a>>),
+    Tuple(Boxed<'a, Tuple<'a>>),
+    List(Boxed<'a, List<'a>>),
+    Subscript(Boxed<'a, Subscript<'a>>),
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct Subscript<'a> {
-    pub value: Box<Expression<'a>>,
+    pub value: Boxed<'a, Expression<'a>>,
     pub slice: Vec<SubscriptElement<'a>>,
+    pub span: Span,
 }
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum BaseSlice<'a> {
-    Index(Box<Index<'a>>),
-    Slice(Box<Slice<'a>>),
+    Index(Boxed<'a, Index<'a>>),
+    Slice(Boxed<'a, Slice<'a>>),
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
@@ -250,34 +295,40 @@ pub struct SubscriptElement<'a> {
 
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct ListComp<'a> {
-    pub elt: Box<Expression<'a>>,
-    pub for_in: Box<CompFor<'a>>,
+    pub elt: Boxed<'a, Expression<'a>>,
+    pub for_in: Boxed<'a, CompFor<'a>>,
+    pub span: Span,
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct SetComp<'a> {
-    pub elt: Box<Expression<'a>>,
-    pub for_in: Box<CompFor<'a>>,
+    pub elt: Boxed<'a, Expression<'a>>,
+    pub for_in: Boxed<'a, CompFor<'a>>,
+    pub span: Span,
 }
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct DictComp<'a> {
-    pub key: Box<Expression<'a>>,
-    pub value: Box<Expression<'a>>,
-    pub for_in: Box<CompFor<'a>>,
+    pub key: Boxed<'a, Expression<'a>>,
+    pub value: Boxed<'a, Expression<'a>>,
+    pub for_in: Boxed<'a, CompFor<'a>>,
+    pub span: Span,
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct List<'a> {
     pub elements: Vec<Element<'a>>,
+    pub span: Span,
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct Set<'a> {
     pub elements: Vec<Element<'a>>,
+    pub span: Span,
 }
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Dict<'a> {
     pub elements: Vec<DictElement<'a>>,
+    pub span: Span,
 }
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum DictElement<'a> {
@@ -295,15 +346,17 @@ pub struct StarredDictElement<'a> {
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct IfExp<'a> {
-    pub test: Box<Expression<'a>>,
-    pub body: Box<Expression<'a>>,
-    pub orelse: Box<Expression<'a>>,
+    pub test: Boxed<'a, Expression<'a>>,
+    pub body: Boxed<'a, Expression<'a>>,
+    pub orelse: Boxed<'a, Expression<'a>>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Lambda<'a> {
-    pub params: Box<Parameters<'a>>,
-    pub body: Box<Expression<'a>>,
+    pub params: Boxed<'a, Parameters<'a>>,
+    pub body: Boxed<'a, Expression<'a>>,
+    pub span: Span,
 }
 
 #[derive(Default, PartialEq, Eq, Debug, Clone)]
@@ -356,19 +409,20 @@ pub struct ParamSlash {
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum StarArg<'a> {
-    Star(Box<ParamStar>),
-    Param(Box<Param<'a>>),
+    Star(Boxed<'a, ParamStar>),
+    Param(Boxed<'a, Param<'a>>),
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct Yield<'a> {
-    pub value: Option<Box<YieldValue<'a>>>,
+    pub value: Option<Boxed<'a, YieldValue<'a>>>,
+    pub span: Span,
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub enum YieldValue<'a> {
-    Expression(Box<Expression<'a>>),
-    From(Box<From<'a>>),
+    Expression(Boxed<'a, Expression<'a>>),
+    From(Boxed<'a, From<'a>>),
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
@@ -378,7 +432,8 @@ pub struct From<'a> {
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Await<'a> {
-    pub expression: Box<Expression<'a>>,
+    pub expression: Boxed<'a, Expression<'a>>,
+    pub span: Span,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -392,11 +447,13 @@ pub struct SimpleString<'a> {
     /// characters, and any escape characters present in the original source code,
     /// such as ``r"my string\n"``.
     pub value: &'a str,
+    pub span: Span,
 }
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct ConcatenatedString<'a> {
-    pub left: Box<String<'a>>,
-    pub right: Box<String<'a>>,
+    pub left: Boxed<'a, String<'a>>,
+    pub right: Boxed<'a, String<'a>>,
+    pub span: Span,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -411,12 +468,13 @@ pub struct FormattedString<'a> {
     pub parts: Vec<FormattedStringContent<'a>>,
     pub start: &'a str,
     pub end: &'a str,
+    pub span: Span,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum FormattedStringContent<'a> {
     Text(FormattedStringText<'a>),
-    Expression(Box<FormattedStringExpression<'a>>),
+    Expression(Boxed<'a, FormattedStringExpression<'a>>),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -435,14 +493,16 @@ pub struct FormattedStringExpression<'a> {
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct NamedExpr<'a> {
-    pub target: Box<Expression<'a>>,
-    pub value: Box<Expression<'a>>,
+    pub target: Boxed<'a, Expression<'a>>,
+    pub value: Boxed<'a, Expression<'a>>,
+    pub span: Span,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct UnaryOperation<'a> {
     pub operator: UnaryOp,
-    pub expression: Box<Expression<'a>>,
+    pub expression: Boxed<'a, Expression<'a>>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -474,6 +534,58 @@ pub struct LeftParen<'a> {
 }
 
 
+impl<'a> Expression<'a> {
+    /// Where the expression was parsed from, leaving out any parentheses around it, the way
+    /// CPython's `ast` positions do.  Nodes made by `builder` have an empty span, and a folded
+    /// `Constant` keeps the span of the expression it replaced.
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Name(n) => n.span,
+            Self::Ellipsis(e) => e.span,
+            Self::Integer(i) => i.span,
+            Self::Float(f) => f.span,
+            Self::Binary(b) => b.span,
+            Self::Hexidecimal(h) => h.span,
+            Self::Imaginary(i) => i.span,
+            Self::Comparison(c) => c.span,
+            Self::UnaryOperation(u) => u.span,
+            Self::BinaryOperation(b) => b.span,
+            Self::BooleanOperation(b) => b.span,
+            Self::Attribute(a) => a.span,
+            Self::Tuple(t) => t.span,
+            Self::Call(c) => c.span,
+            Self::GeneratorExp(g) => g.span,
+            Self::ListComp(l) => l.span,
+            Self::SetComp(s) => s.span,
+            Self::DictComp(d) => d.span,
+            Self::List(l) => l.span,
+            Self::Set(s) => s.span,
+            Self::Dict(d) => d.span,
+            Self::Subscript(s) => s.span,
+            Self::StarredElement(s) => s.span,
+            Self::IfExp(i) => i.span,
+            Self::Lambda(l) => l.span,
+            Self::Yield(y) => y.span,
+            Self::Await(a) => a.span,
+            Self::SimpleString(s) => s.span,
+            Self::ConcatenatedString(c) => c.span,
+            Self::FormattedString(f) => f.span,
+            Self::NamedExpr(n) => n.span,
+            Self::Constant(c) => c.span,
+        }
+    }
+}
+
+impl<'a> String<'a> {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Simple(s) => s.span,
+            Self::Concatenated(c) => c.span,
+            Self::Formatted(f) => f.span,
+        }
+    }
+}
+
 // Converters
 
 impl<'a> std::convert::From<Expression<'a>> for Element<'a> {
@@ -494,12 +606,42 @@ impl<'a> std::convert::From<NameOrAttribute<'a>> for Expression<'a> {
     }
 }
 
+impl<'a> std::convert::From<AssignTargetExpression<'a>> for Expression<'a> {
+    fn from(x: AssignTargetExpression<'a>) -> Self {
+        match x {
+            AssignTargetExpression::Name(n) => Self::Name(n),
+            AssignTargetExpression::Attribute(a) => Self::Attribute(a),
+            AssignTargetExpression::StarredElement(s) => Self::StarredElement(s),
+            AssignTargetExpression::Tuple(t) => Self::Tuple(t),
+            AssignTargetExpression::List(l) => Self::List(l),
+            AssignTargetExpression::Subscript(s) => Self::Subscript(s),
+        }
+    }
+}
+
+/// Fails with the original expression when it can't be assigned to, e.g. a call.
+impl<'a> std::convert::TryFrom<Expression<'a>> for AssignTargetExpression<'a> {
+    type Error = Expression<'a>;
+
+    fn try_from(e: Expression<'a>) -> Result<Self, Self::Error> {
+        match e {
+            Expression::Name(n) => Ok(Self::Name(n)),
+            Expression::Attribute(a) => Ok(Self::Attribute(a)),
+            Expression::StarredElement(s) => Ok(Self::StarredElement(s)),
+            Expression::Tuple(t) => Ok(Self::Tuple(t)),
+            Expression::List(l) => Ok(Self::List(l)),
+            Expression::Subscript(s) => Ok(Self::Subscript(s)),
+            other => Err(other),
+        }
+    }
+}
+
 impl<'a> std::convert::From<String<'a>> for Expression<'a> {
     fn from(s: String<'a>) -> Self {
         match s {
-            String::Simple(s) => Self::SimpleString(Box::new(s)),
-            String::Concatenated(s) => Self::ConcatenatedString(Box::new(s)),
-            String::Formatted(s) => Self::FormattedString(Box::new(s)),
+            String::Simple(s) => Self::SimpleString(Boxed::new(s)),
+            String::Concatenated(s) => Self::ConcatenatedString(Boxed::new(s)),
+            String::Formatted(s) => Self::FormattedString(Boxed::new(s)),
         }
     }
 }
@@ -0,0 +1,261 @@
+//! Docstrings and signatures of modules, classes and functions, for documentation tools.
+
+use std::fmt;
+
+use super::strings::StringValue;
+use super::{
+    Annotation, ClassDef, Decorator, Expression, FunctionDef, Module, Param, Parameters, SmallStatement, StarArg,
+    Statement, Suite,
+};
+
+impl<'a> Module<'a> {
+    /// The module docstring, cleaned up like `inspect.cleandoc`.
+    pub fn docstring(&self) -> Option<std::string::String> {
+        docstring_of(self.body.first()?)
+    }
+}
+
+impl<'a> ClassDef<'a> {
+    /// The class docstring, cleaned up like `inspect.cleandoc`.
+    pub fn docstring(&self) -> Option<std::string::String> {
+        suite_docstring(&self.body)
+    }
+}
+
+impl<'a> FunctionDef<'a> {
+    /// The function docstring, cleaned up like `inspect.cleandoc`.
+    pub fn docstring(&self) -> Option<std::string::String> {
+        suite_docstring(&self.body)
+    }
+
+    /// The signature, with the defaults as they are written in `source`, the source the
+    /// function was parsed from.
+    pub fn signature(&self, source: &str) -> Signature<'a> {
+        Signature {
+            parameters: self.params.signature(source),
+            returns: self.returns.clone(),
+            decorators: self.decorators.clone(),
+        }
+    }
+}
+
+fn suite_docstring(suite: &Suite) -> Option<std::string::String> {
+    match suite {
+        Suite::IndentedBlock(block) => docstring_of(block.body.first()?),
+        Suite::SimpleStatementSuite(suite) => small_statement_docstring(suite.body.first()?),
+    }
+}
+
+fn docstring_of(stmt: &Statement) -> Option<std::string::String> {
+    match stmt {
+        Statement::Simple(line) => small_statement_docstring(line.body.first()?),
+        Statement::Compound(_) | Statement::Error(_) => None,
+    }
+}
+
+/// Only str literals count; a bytes literal or an f-string in first position is just an
+/// expression statement.
+fn small_statement_docstring(stmt: &SmallStatement) -> Option<std::string::String> {
+    let SmallStatement::Expr(expr) = stmt else { return None };
+    let value = match &expr.value {
+        Expression::SimpleString(s) => s.parse_value().ok()?,
+        Expression::ConcatenatedString(c) => c.parse_value()?.ok()?,
+        _ => return None,
+    };
+    match value {
+        StringValue::Str(s) => Some(cleandoc(&s)),
+        StringValue::Bytes(_) => None,
+    }
+}
+
+/// Python's `inspect.cleandoc`: expands tabs, strips the indentation common to every line
+/// after the first, and drops blank lines at the start and end.
+///
+/// Any Unicode whitespace counts as indentation, so the margin is a number of chars, as
+/// Python's is, rather than of bytes.
+pub fn cleandoc(doc: &str) -> std::string::String {
+    let expanded: Vec<std::string::String> = doc.split('\n').map(expand_tabs).collect();
+    let margin = expanded
+        .iter()
+        .skip(1)
+        .filter_map(|line| {
+            let content = line.trim_start();
+            (!content.is_empty()).then(|| line[..line.len() - content.len()].chars().count())
+        })
+        .min()
+        .unwrap_or(0);
+    let mut lines: Vec<&str> = Vec::with_capacity(expanded.len());
+    for (idx, line) in expanded.iter().enumerate() {
+        if idx == 0 {
+            lines.push(line.trim_start());
+        } else {
+            let cut = line.char_indices().nth(margin).map_or(line.len(), |(at, _)| at);
+            lines.push(&line[cut..]);
+        }
+    }
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    let start = lines.iter().position(|line| !line.is_empty()).unwrap_or(lines.len());
+    lines[start..].join("\n")
+}
+
+/// `str.expandtabs()` with the default tab size of 8.
+fn expand_tabs(line: &str) -> std::string::String {
+    let mut out = std::string::String::with_capacity(line.len());
+    let mut column = 0;
+    for c in line.chars() {
+        if c == '\t' {
+            let spaces = 8 - column % 8;
+            out.extend(std::iter::repeat_n(' ', spaces));
+            column += spaces;
+        } else {
+            out.push(c);
+            column += 1;
+        }
+    }
+    out
+}
+
+/// How an argument is bound to a parameter, matching `inspect.Parameter.kind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamKind {
+    PositionalOnly,
+    PositionalOrKeyword,
+    VarPositional,
+    KeywordOnly,
+    VarKeyword,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureParam<'a> {
+    pub name: &'a str,
+    pub kind: ParamKind,
+    /// Source text of the default value, e.g. `"None"` or `"(1, 2)"`, comments and all.
+    pub default: Option<std::string::String>,
+    pub annotation: Option<Annotation<'a>>,
+}
+
+/// A function signature in the shape of `inspect.Signature`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature<'a> {
+    /// Parameters in declaration order.
+    pub parameters: Vec<SignatureParam<'a>>,
+    pub returns: Option<Annotation<'a>>,
+    pub decorators: Vec<Decorator<'a>>,
+}
+
+impl<'a> Parameters<'a> {
+    /// The parameters in declaration order with their kinds, e.g. for a lambda.  The defaults
+    /// are sliced from `source`, the source the parameters were parsed from; a default made
+    /// by `builder`, which has no source, is unparsed instead.
+    pub fn signature(&self, source: &str) -> Vec<SignatureParam<'a>> {
+        let default_text = |default: &Expression| match default.span().text(source) {
+            Some(text) if !text.is_empty() => text.to_string(),
+            _ => default.to_source(),
+        };
+        let param = |p: &Param<'a>, kind| SignatureParam {
+            name: p.name.value,
+            kind,
+            default: p.default.as_ref().map(default_text),
+            annotation: p.annotation.clone(),
+        };
+        let mut out = vec![];
+        out.extend(self.posonly_params.iter().map(|p| param(p, ParamKind::PositionalOnly)));
+        out.extend(self.params.iter().map(|p| param(p, ParamKind::PositionalOrKeyword)));
+        if let Some(StarArg::Param(p)) = &self.star_arg {
+            out.push(param(p, ParamKind::VarPositional));
+        }
+        out.extend(self.kwonly_params.iter().map(|p| param(p, ParamKind::KeywordOnly)));
+        if let Some(p) = &self.star_kwarg {
+            out.push(param(p, ParamKind::VarKeyword));
+        }
+        out
+    }
+}
+
+/// Formats like `str(inspect.signature(f))`: `(a, /, b=1, *args, c: int, **kw) -> str`.
+impl<'a> fmt::Display for Signature<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts: Vec<std::string::String> = vec![];
+        let mut seen_star = false;
+        for (idx, param) in self.parameters.iter().enumerate() {
+            let mut text = match param.kind {
+                ParamKind::VarPositional => {
+                    seen_star = true;
+                    format!("*{}", param.name)
+                }
+                ParamKind::VarKeyword => format!("**{}", param.name),
+                ParamKind::KeywordOnly if !seen_star => {
+                    seen_star = true;
+                    parts.push("*".to_string());
+                    param.name.to_string()
+                }
+                _ => param.name.to_string(),
+            };
+            if let Some(annotation) = &param.annotation {
+                text.push_str(": ");
+                text.push_str(&annotation.annotation.to_source());
+            }
+            if let Some(default) = &param.default {
+                text.push_str(if param.annotation.is_some() { " = " } else { "=" });
+                text.push_str(default);
+            }
+            parts.push(text);
+            let next_kind = self.parameters.get(idx + 1).map(|next| next.kind);
+            if param.kind == ParamKind::PositionalOnly && next_kind != Some(ParamKind::PositionalOnly) {
+                parts.push("/".to_string());
+            }
+        }
+        write!(f, "({})", parts.join(", "))?;
+        if let Some(returns) = &self.returns {
+            write!(f, " -> {}", returns.annotation.to_source())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::cleandoc;
+    use crate::ast::{CompoundStatement, Statement};
+    use crate::parser::{parse_module, TokenArena};
+
+    #[test]
+    fn cleandoc_strips_common_indentation() {
+        assert_eq!(cleandoc("  Summary.\n\n    Details\n      more\n    "), "Summary.\n\nDetails\n  more");
+        assert_eq!(cleandoc("\n\tTabbed\n"), "Tabbed");
+    }
+
+    #[test]
+    fn cleandoc_counts_the_margin_in_chars() {
+        assert_eq!(cleandoc("Summary.\n\u{3000}\u{3000}Wide\n\u{3000}\u{3000}\u{3000}more\n"), "Summary.\nWide\n\u{3000}more");
+        assert_eq!(cleandoc("Summary.\n\u{a0}\u{a0}a\n\u{a0}\u{a0}\u{a0}b"), "Summary.\na\n\u{a0}b");
+        // Only whitespace is margin: a line indented by less keeps its other chars.
+        assert_eq!(cleandoc("x\n  \u{e9}t\u{e9}\n \u{e9}"), "x\n \u{e9}t\u{e9}\n\u{e9}");
+    }
+
+    #[test]
+    fn extracts_docstring_and_signature() {
+        let source = "def f(a, /, b: int = 1 + 2, *args, c, **kw) -> str:\n    \"\"\"Doc.\"\"\"\n";
+        let arena = TokenArena::from_source(source).expect("tokenize");
+        let module = parse_module(&arena, "__test__").expect("parse");
+        assert_eq!(module.docstring(), None);
+        let Statement::Compound(CompoundStatement::FunctionDef(f)) = &module.body[0] else { panic!("expected a def") };
+        assert_eq!(f.docstring().as_deref(), Some("Doc."));
+        assert_eq!(f.signature(source).to_string(), "(a, /, b: int = 1 + 2, *args, c, **kw) -> str");
+    }
+
+    #[test]
+    fn defaults_keep_their_source_text() {
+        let source = "def f(a=0x_FF, b=[1,  # one\n       2], *, c='x' 'y'):\n    pass\n";
+        let arena = TokenArena::from_source(source).expect("tokenize");
+        let module = parse_module(&arena, "__test__").expect("parse");
+        let Statement::Compound(CompoundStatement::FunctionDef(f)) = &module.body[0] else { panic!("expected a def") };
+        let defaults: Vec<_> = f.signature(source).parameters.into_iter().map(|p| p.default).collect();
+        assert_eq!(
+            defaults,
+            [Some("0x_FF".to_string()), Some("[1,  # one\n       2]".to_string()), Some("'x' 'y'".to_string())]
+        );
+    }
+}
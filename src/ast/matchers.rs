@@ -0,0 +1,1050 @@
+//! Declarative patterns over expressions and statements, in the spirit of LibCST's matchers.
+//!
+//! A lint rule such as "calls to `os.system`" becomes
+//!
+//! ```ignore
+//! let rule = call(attr(name("os"), "system"));
+//! for found in find_all(&module, &rule) { /* report found.span */ }
+//! ```
+//!
+//! instead of a stack of nested `match` statements.  Matchers compose with `OneOf`,
+//! `AllOf`, `Not` and `Where`, and `Capture` records the node it matched under a name.
+//! Matches and captures borrow the nodes from the module rather than copying them.
+//! `StmtMatcher` does the same for statements, with `ExprMatcher`s for their parts, and
+//! `find_statements` reports the statements that match.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use regex::Regex;
+
+use super::strings::StringValue;
+use super::visitor::{self, Visitor};
+use super::{
+    Arg, AssignTargetExpression, Attribute, BaseSlice, BinaryOp, BooleanOp, CompFor, CompOp, CompoundStatement,
+    ConstantValue, DictElement, Element, Expression, List, Module, Name, Param, Parameters, SmallStatement, Span,
+    StarArg, StarredElement, Statement, Subscript, Suite, Tuple, UnaryOp, YieldValue,
+};
+
+/// Matches a piece of text such as a name or an attribute.
+#[derive(Clone, Debug)]
+pub enum TextMatcher {
+    Any,
+    Exact(std::string::String),
+    OneOf(Vec<std::string::String>),
+    Regex(Regex),
+}
+
+impl TextMatcher {
+    pub fn matches(&self, text: &str) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Exact(expected) => expected == text,
+            Self::OneOf(options) => options.iter().any(|option| option == text),
+            Self::Regex(re) => re.is_match(text),
+        }
+    }
+}
+
+impl From<&str> for TextMatcher {
+    fn from(text: &str) -> Self {
+        Self::Exact(text.to_string())
+    }
+}
+
+/// The node a matcher is tried against, borrowed from the tree.  Assignment and `for`
+/// targets are matched as the expressions they are written as, and the starred elements of
+/// a display as `*value`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Node<'r, 'a> {
+    Expression(&'r Expression<'a>),
+    Target(&'r AssignTargetExpression<'a>),
+    Starred(&'r StarredElement<'a>),
+}
+
+impl<'r, 'a> Node<'r, 'a> {
+    pub fn span(self) -> Span {
+        match self {
+            Self::Expression(e) => e.span(),
+            Self::Target(AssignTargetExpression::Name(n)) => n.span,
+            Self::Target(AssignTargetExpression::Attribute(a)) => a.span,
+            Self::Target(AssignTargetExpression::StarredElement(s)) => s.span,
+            Self::Target(AssignTargetExpression::Tuple(t)) => t.span,
+            Self::Target(AssignTargetExpression::List(l)) => l.span,
+            Self::Target(AssignTargetExpression::Subscript(s)) => s.span,
+            Self::Starred(s) => s.span,
+        }
+    }
+
+    /// The node, unless it is a target or a starred element, which are not expressions.
+    pub fn expression(self) -> Option<&'r Expression<'a>> {
+        match self {
+            Self::Expression(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> Option<&'r Name<'a>> {
+        match self {
+            Self::Expression(Expression::Name(n)) | Self::Target(AssignTargetExpression::Name(n)) => Some(n),
+            _ => None,
+        }
+    }
+
+    fn attribute(self) -> Option<&'r Attribute<'a>> {
+        match self {
+            Self::Expression(Expression::Attribute(a)) | Self::Target(AssignTargetExpression::Attribute(a)) => Some(a),
+            _ => None,
+        }
+    }
+
+    fn subscript(self) -> Option<&'r Subscript<'a>> {
+        match self {
+            Self::Expression(Expression::Subscript(s)) | Self::Target(AssignTargetExpression::Subscript(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn starred(self) -> Option<&'r StarredElement<'a>> {
+        match self {
+            Self::Expression(Expression::StarredElement(s))
+            | Self::Target(AssignTargetExpression::StarredElement(s)) => Some(s),
+            Self::Starred(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn tuple(self) -> Option<&'r Tuple<'a>> {
+        match self {
+            Self::Expression(Expression::Tuple(t)) | Self::Target(AssignTargetExpression::Tuple(t)) => Some(t),
+            _ => None,
+        }
+    }
+
+    fn list(self) -> Option<&'r List<'a>> {
+        match self {
+            Self::Expression(Expression::List(l)) | Self::Target(AssignTargetExpression::List(l)) => Some(l),
+            _ => None,
+        }
+    }
+}
+
+/// Names bound by `ExprMatcher::Capture`, to the nodes they matched.
+pub type Captures<'r, 'a> = HashMap<&'static str, Node<'r, 'a>>;
+
+/// A pattern for one `Expression`.
+#[derive(Clone)]
+pub enum ExprMatcher {
+    /// Matches anything.
+    Any,
+    Name(TextMatcher),
+    Attribute(Box<AttributeMatcher>),
+    Call(Box<CallMatcher>),
+    Subscript(Box<SubscriptMatcher>),
+    BinaryOperation(Box<BinaryOperationMatcher>),
+    UnaryOperation(Box<UnaryOperationMatcher>),
+    BooleanOperation(Box<BooleanOperationMatcher>),
+    Comparison(Box<ComparisonMatcher>),
+    /// `body if test else orelse`
+    IfExp(Box<IfExpMatcher>),
+    Lambda(Box<LambdaMatcher>),
+    /// `*value`, in a display, a call or an assignment target.
+    Starred(Box<ExprMatcher>),
+    /// `name := value`
+    NamedExpr(Box<NamedExprMatcher>),
+    /// `await value`
+    Await(Box<ExprMatcher>),
+    /// `yield value`; `None` accepts any `yield`, including a bare one, but not `yield from`.
+    Yield(Option<Box<ExprMatcher>>),
+    /// `yield from value`
+    YieldFrom(Box<ExprMatcher>),
+    /// A str literal, or an implicit concatenation of them, by value.
+    Str(TextMatcher),
+    /// Any f-string.
+    FormattedString,
+    /// An int literal by value, in any base.
+    Int(i64),
+    /// A float literal by value.
+    Float(f64),
+    /// `...`
+    Ellipsis,
+    /// A tuple or list display whose elements match in order.
+    Sequence(Vec<ExprMatcher>),
+    Tuple(Vec<ExprMatcher>),
+    List(Vec<ExprMatcher>),
+    Set(Vec<ExprMatcher>),
+    /// A dict display whose items match in order.
+    Dict(Vec<DictElementMatcher>),
+    ListComp(Box<ComprehensionMatcher>),
+    SetComp(Box<ComprehensionMatcher>),
+    GeneratorExp(Box<ComprehensionMatcher>),
+    DictComp(Box<DictCompMatcher>),
+    OneOf(Vec<ExprMatcher>),
+    AllOf(Vec<ExprMatcher>),
+    Not(Box<ExprMatcher>),
+    /// Matches when the function returns true.  Targets and starred elements never match.
+    Where(Rc<dyn Fn(&Expression) -> bool>),
+    /// Matches like the inner matcher and records the node under the name.
+    Capture(&'static str, Box<ExprMatcher>),
+}
+
+#[derive(Clone)]
+pub struct AttributeMatcher {
+    pub value: ExprMatcher,
+    pub attr: TextMatcher,
+}
+
+#[derive(Clone)]
+pub struct CallMatcher {
+    pub func: ExprMatcher,
+    /// `None` accepts any arguments.
+    pub args: Option<Vec<ArgMatcher>>,
+}
+
+#[derive(Clone)]
+pub struct SubscriptMatcher {
+    pub value: ExprMatcher,
+    /// Matches a single index, e.g. the `"HOME"` in `os.environ["HOME"]`; slices never match.
+    pub index: ExprMatcher,
+}
+
+#[derive(Clone)]
+pub struct BinaryOperationMatcher {
+    pub left: ExprMatcher,
+    /// `None` accepts any operator.
+    pub operator: Option<BinaryOp>,
+    pub right: ExprMatcher,
+}
+
+#[derive(Clone)]
+pub struct UnaryOperationMatcher {
+    /// `None` accepts any operator.
+    pub operator: Option<UnaryOp>,
+    pub expression: ExprMatcher,
+}
+
+#[derive(Clone)]
+pub struct BooleanOperationMatcher {
+    pub left: ExprMatcher,
+    /// `None` accepts `and` and `or`.
+    pub operator: Option<BooleanOp>,
+    pub right: ExprMatcher,
+}
+
+#[derive(Clone)]
+pub struct ComparisonMatcher {
+    pub left: ExprMatcher,
+    /// The operators and the operands after them, as many as the chain has.  A `None`
+    /// operator accepts any.
+    pub comparisons: Vec<(Option<CompOp>, ExprMatcher)>,
+}
+
+#[derive(Clone)]
+pub struct IfExpMatcher {
+    pub test: ExprMatcher,
+    pub body: ExprMatcher,
+    pub orelse: ExprMatcher,
+}
+
+#[derive(Clone)]
+pub struct LambdaMatcher {
+    /// The names of all the parameters in order, `*args` and `**kwargs` included; `None`
+    /// accepts any parameters.
+    pub params: Option<Vec<TextMatcher>>,
+    pub body: ExprMatcher,
+}
+
+#[derive(Clone)]
+pub struct NamedExprMatcher {
+    pub target: ExprMatcher,
+    pub value: ExprMatcher,
+}
+
+/// A pattern for the element of a list, set or generator comprehension and its first `for`.
+#[derive(Clone)]
+pub struct ComprehensionMatcher {
+    pub elt: ExprMatcher,
+    pub target: ExprMatcher,
+    pub iter: ExprMatcher,
+}
+
+/// A pattern for the key and value of a dict comprehension and its first `for`.
+#[derive(Clone)]
+pub struct DictCompMatcher {
+    pub key: ExprMatcher,
+    pub value: ExprMatcher,
+    pub target: ExprMatcher,
+    pub iter: ExprMatcher,
+}
+
+/// A pattern for one item of a dict display.
+#[derive(Clone)]
+pub enum DictElementMatcher {
+    /// `key: value`
+    Pair(ExprMatcher, ExprMatcher),
+    /// `**value`
+    DoubleStar(ExprMatcher),
+    /// Any single item.
+    Any,
+}
+
+/// A pattern for one argument of a call.
+#[derive(Clone)]
+pub enum ArgMatcher {
+    Positional(ExprMatcher),
+    Keyword(TextMatcher, ExprMatcher),
+    /// `*value`
+    Star(ExprMatcher),
+    /// `**value`
+    DoubleStar(ExprMatcher),
+    /// Any single argument.
+    Any,
+    /// Any number of arguments, including none.
+    ZeroOrMore,
+}
+
+pub fn name(text: impl Into<TextMatcher>) -> ExprMatcher {
+    ExprMatcher::Name(text.into())
+}
+
+pub fn attr(value: ExprMatcher, attr: impl Into<TextMatcher>) -> ExprMatcher {
+    ExprMatcher::Attribute(Box::new(AttributeMatcher {
+        value,
+        attr: attr.into(),
+    }))
+}
+
+/// A call to `func` with any arguments.
+pub fn call(func: ExprMatcher) -> ExprMatcher {
+    ExprMatcher::Call(Box::new(CallMatcher { func, args: None }))
+}
+
+/// A call to `func` whose arguments match `args` in order.
+pub fn call_with(func: ExprMatcher, args: Vec<ArgMatcher>) -> ExprMatcher {
+    ExprMatcher::Call(Box::new(CallMatcher { func, args: Some(args) }))
+}
+
+pub fn subscript(value: ExprMatcher, index: ExprMatcher) -> ExprMatcher {
+    ExprMatcher::Subscript(Box::new(SubscriptMatcher { value, index }))
+}
+
+/// `left operator right`, with a single operator.
+pub fn compare(left: ExprMatcher, operator: CompOp, right: ExprMatcher) -> ExprMatcher {
+    ExprMatcher::Comparison(Box::new(ComparisonMatcher { left, comparisons: vec![(Some(operator), right)] }))
+}
+
+pub fn unary(operator: UnaryOp, expression: ExprMatcher) -> ExprMatcher {
+    ExprMatcher::UnaryOperation(Box::new(UnaryOperationMatcher { operator: Some(operator), expression }))
+}
+
+pub fn boolop(left: ExprMatcher, operator: BooleanOp, right: ExprMatcher) -> ExprMatcher {
+    ExprMatcher::BooleanOperation(Box::new(BooleanOperationMatcher { left, operator: Some(operator), right }))
+}
+
+pub fn capture(name: &'static str, matcher: ExprMatcher) -> ExprMatcher {
+    ExprMatcher::Capture(name, Box::new(matcher))
+}
+
+impl ExprMatcher {
+    pub fn matches<'r, 'a>(&self, node: Node<'r, 'a>, captures: &mut Captures<'r, 'a>) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Name(text) => node.name().is_some_and(|n| text.matches(n.value)),
+            Self::Attribute(m) => node
+                .attribute()
+                .is_some_and(|a| m.attr.matches(a.attr.value) && m.value.matches_expr(&a.value, captures)),
+            Self::Call(m) => match node.expression() {
+                Some(Expression::Call(c)) => {
+                    m.func.matches_expr(&c.func, captures)
+                        && m.args.as_ref().is_none_or(|args| match_args(args, &c.args, captures))
+                }
+                _ => false,
+            },
+            Self::Subscript(m) => node.subscript().is_some_and(|s| match s.slice.as_slice() {
+                [element] => match &element.slice {
+                    BaseSlice::Index(index) => {
+                        m.value.matches_expr(&s.value, captures) && m.index.matches_expr(&index.value, captures)
+                    }
+                    BaseSlice::Slice(_) => false,
+                },
+                _ => false,
+            }),
+            Self::BinaryOperation(m) => match node.expression() {
+                Some(Expression::BinaryOperation(b)) => {
+                    m.operator.as_ref().is_none_or(|op| *op == b.operator)
+                        && m.left.matches_expr(&b.left, captures)
+                        && m.right.matches_expr(&b.right, captures)
+                }
+                _ => false,
+            },
+            Self::UnaryOperation(m) => match node.expression() {
+                Some(Expression::UnaryOperation(u)) => {
+                    m.operator.as_ref().is_none_or(|op| *op == u.operator)
+                        && m.expression.matches_expr(&u.expression, captures)
+                }
+                _ => false,
+            },
+            Self::BooleanOperation(m) => match node.expression() {
+                Some(Expression::BooleanOperation(b)) => {
+                    m.operator.as_ref().is_none_or(|op| *op == b.operator)
+                        && m.left.matches_expr(&b.left, captures)
+                        && m.right.matches_expr(&b.right, captures)
+                }
+                _ => false,
+            },
+            Self::Comparison(m) => match node.expression() {
+                Some(Expression::Comparison(c)) => {
+                    c.comparisons.len() == m.comparisons.len()
+                        && m.left.matches_expr(&c.left, captures)
+                        && c.comparisons.iter().zip(&m.comparisons).all(|(target, (op, m))| {
+                            op.as_ref().is_none_or(|op| *op == target.operator)
+                                && m.matches_expr(&target.comparator, captures)
+                        })
+                }
+                _ => false,
+            },
+            Self::IfExp(m) => match node.expression() {
+                Some(Expression::IfExp(i)) => {
+                    m.test.matches_expr(&i.test, captures)
+                        && m.body.matches_expr(&i.body, captures)
+                        && m.orelse.matches_expr(&i.orelse, captures)
+                }
+                _ => false,
+            },
+            Self::Lambda(m) => match node.expression() {
+                Some(Expression::Lambda(l)) => {
+                    m.params.as_ref().is_none_or(|names| {
+                        let params: Vec<&Param> = all_params(&l.params).collect();
+                        params.len() == names.len()
+                            && params.iter().zip(names).all(|(param, name)| name.matches(param.name.value))
+                    }) && m.body.matches_expr(&l.body, captures)
+                }
+                _ => false,
+            },
+            Self::Starred(inner) => node.starred().is_some_and(|s| inner.matches_expr(&s.value, captures)),
+            Self::NamedExpr(m) => match node.expression() {
+                Some(Expression::NamedExpr(n)) => {
+                    m.target.matches_expr(&n.target, captures) && m.value.matches_expr(&n.value, captures)
+                }
+                _ => false,
+            },
+            Self::Await(inner) => match node.expression() {
+                Some(Expression::Await(a)) => inner.matches_expr(&a.expression, captures),
+                _ => false,
+            },
+            Self::Yield(inner) => match node.expression() {
+                Some(Expression::Yield(y)) => match (inner, y.value.as_deref()) {
+                    (_, Some(YieldValue::From(_))) => false,
+                    (None, _) => true,
+                    (Some(inner), Some(YieldValue::Expression(value))) => inner.matches_expr(value, captures),
+                    (Some(_), None) => false,
+                },
+                _ => false,
+            },
+            Self::YieldFrom(inner) => match node.expression() {
+                Some(Expression::Yield(y)) => match y.value.as_deref() {
+                    Some(YieldValue::From(from)) => inner.matches_expr(&from.item, captures),
+                    _ => false,
+                },
+                _ => false,
+            },
+            Self::Str(text) => node.expression().and_then(str_value).is_some_and(|value| text.matches(&value)),
+            Self::FormattedString => matches!(node.expression(), Some(Expression::FormattedString(_))),
+            Self::Int(expected) => node.expression().and_then(int_value) == Some(*expected),
+            Self::Float(expected) => node.expression().and_then(float_value) == Some(*expected),
+            Self::Ellipsis => match node.expression() {
+                Some(Expression::Ellipsis(_)) => true,
+                Some(Expression::Constant(c)) => matches!(c.value, ConstantValue::Ellipsis),
+                _ => false,
+            },
+            Self::Sequence(elements) => match node.tuple().map(|t| &t.elements).or(node.list().map(|l| &l.elements)) {
+                Some(actual) => match_elements(elements, actual, captures),
+                None => false,
+            },
+            Self::Tuple(elements) => node.tuple().is_some_and(|t| match_elements(elements, &t.elements, captures)),
+            Self::List(elements) => node.list().is_some_and(|l| match_elements(elements, &l.elements, captures)),
+            Self::Set(elements) => match node.expression() {
+                Some(Expression::Set(s)) => match_elements(elements, &s.elements, captures),
+                _ => false,
+            },
+            Self::Dict(elements) => match node.expression() {
+                Some(Expression::Dict(d)) => {
+                    d.elements.len() == elements.len()
+                        && d.elements.iter().zip(elements).all(|(element, m)| match (m, element) {
+                            (DictElementMatcher::Any, _) => true,
+                            (DictElementMatcher::Pair(k, v), DictElement::Simple { key, value }) => {
+                                k.matches_expr(key, captures) && v.matches_expr(value, captures)
+                            }
+                            (DictElementMatcher::DoubleStar(m), DictElement::Starred(s)) => {
+                                m.matches_expr(&s.value, captures)
+                            }
+                            _ => false,
+                        })
+                }
+                _ => false,
+            },
+            Self::ListComp(m) => match node.expression() {
+                Some(Expression::ListComp(l)) => m.matches(&l.elt, &l.for_in, captures),
+                _ => false,
+            },
+            Self::SetComp(m) => match node.expression() {
+                Some(Expression::SetComp(s)) => m.matches(&s.elt, &s.for_in, captures),
+                _ => false,
+            },
+            Self::GeneratorExp(m) => match node.expression() {
+                Some(Expression::GeneratorExp(g)) => m.matches(&g.elt, &g.for_in, captures),
+                _ => false,
+            },
+            Self::DictComp(m) => match node.expression() {
+                Some(Expression::DictComp(d)) => {
+                    m.key.matches_expr(&d.key, captures)
+                        && m.value.matches_expr(&d.value, captures)
+                        && attempt(&m.target, Node::Target(&d.for_in.target), captures)
+                        && m.iter.matches_expr(&d.for_in.iter, captures)
+                }
+                _ => false,
+            },
+            Self::OneOf(options) => options.iter().any(|option| attempt(option, node, captures)),
+            Self::AllOf(all) => {
+                let mut trial = captures.clone();
+                if all.iter().all(|m| m.matches(node, &mut trial)) {
+                    *captures = trial;
+                    true
+                } else {
+                    false
+                }
+            }
+            Self::Not(inner) => !inner.matches(node, &mut captures.clone()),
+            Self::Where(predicate) => node.expression().is_some_and(|expr| predicate(expr)),
+            Self::Capture(name, inner) => {
+                if inner.matches(node, captures) {
+                    captures.insert(name, node);
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    fn matches_expr<'r, 'a>(&self, expr: &'r Expression<'a>, captures: &mut Captures<'r, 'a>) -> bool {
+        self.matches(Node::Expression(expr), captures)
+    }
+}
+
+impl ComprehensionMatcher {
+    fn matches<'r, 'a>(&self, elt: &'r Expression<'a>, for_in: &'r CompFor<'a>, captures: &mut Captures<'r, 'a>) -> bool {
+        self.elt.matches_expr(elt, captures)
+            && attempt(&self.target, Node::Target(&for_in.target), captures)
+            && self.iter.matches_expr(&for_in.iter, captures)
+    }
+}
+
+/// Runs a matcher that may fail partway, keeping its captures only if it succeeds.
+fn attempt<'r, 'a>(matcher: &ExprMatcher, node: Node<'r, 'a>, captures: &mut Captures<'r, 'a>) -> bool {
+    let mut trial = captures.clone();
+    if matcher.matches(node, &mut trial) {
+        *captures = trial;
+        true
+    } else {
+        false
+    }
+}
+
+/// Matches the elements of a display in order, a starred element as `*value`.
+fn match_elements<'r, 'a>(matchers: &[ExprMatcher], elements: &'r [Element<'a>], captures: &mut Captures<'r, 'a>) -> bool {
+    elements.len() == matchers.len()
+        && elements.iter().zip(matchers).all(|(element, m)| match element {
+            Element::Simple { value } => m.matches_expr(value, captures),
+            Element::Starred(s) => m.matches(Node::Starred(s), captures),
+        })
+}
+
+fn match_arg<'r, 'a>(matcher: &ArgMatcher, arg: &'r Arg<'a>, captures: &mut Captures<'r, 'a>) -> bool {
+    let value = |m: &ExprMatcher, captures: &mut Captures<'r, 'a>| attempt(m, Node::Expression(&arg.value), captures);
+    match matcher {
+        ArgMatcher::Positional(m) => arg.star.is_empty() && arg.keyword.is_none() && value(m, captures),
+        ArgMatcher::Keyword(keyword, m) => {
+            arg.keyword.as_ref().is_some_and(|k| keyword.matches(k.value)) && value(m, captures)
+        }
+        ArgMatcher::Star(m) => arg.star == "*" && value(m, captures),
+        ArgMatcher::DoubleStar(m) => arg.star == "**" && value(m, captures),
+        ArgMatcher::Any | ArgMatcher::ZeroOrMore => true,
+    }
+}
+
+fn match_args<'r, 'a>(matchers: &[ArgMatcher], args: &'r [Arg<'a>], captures: &mut Captures<'r, 'a>) -> bool {
+    match matchers.split_first() {
+        None => args.is_empty(),
+        Some((ArgMatcher::ZeroOrMore, rest)) => (0..=args.len()).any(|skip| {
+            let mut trial = captures.clone();
+            if match_args(rest, &args[skip..], &mut trial) {
+                *captures = trial;
+                true
+            } else {
+                false
+            }
+        }),
+        Some((first, rest)) => match args.split_first() {
+            Some((arg, remaining)) => {
+                let mut trial = captures.clone();
+                if match_arg(first, arg, &mut trial) && match_args(rest, remaining, &mut trial) {
+                    *captures = trial;
+                    true
+                } else {
+                    false
+                }
+            }
+            None => false,
+        },
+    }
+}
+
+fn all_params<'r, 'a>(params: &'r Parameters<'a>) -> impl Iterator<Item = &'r Param<'a>> {
+    let star_arg = match &params.star_arg {
+        Some(StarArg::Param(param)) => Some(&**param),
+        _ => None,
+    };
+    params
+        .posonly_params
+        .iter()
+        .chain(&params.params)
+        .chain(star_arg)
+        .chain(&params.kwonly_params)
+        .chain(&params.star_kwarg)
+}
+
+fn str_value(expr: &Expression) -> Option<std::string::String> {
+    let value = match expr {
+        Expression::SimpleString(s) => s.parse_value().ok()?,
+        Expression::ConcatenatedString(c) => c.parse_value()?.ok()?,
+        Expression::Constant(c) => match &c.value {
+            ConstantValue::Str(s) => return Some(s.clone()),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    match value {
+        StringValue::Str(s) => Some(s),
+        StringValue::Bytes(_) => None,
+    }
+}
+
+fn int_value(expr: &Expression) -> Option<i64> {
+    let value = match expr {
+        Expression::Integer(i) => i.parse_value().ok()?,
+        Expression::Hexidecimal(h) => h.parse_value().ok()?,
+        Expression::Binary(b) => b.parse_value().ok()?,
+        Expression::Constant(c) => match &c.value {
+            ConstantValue::Int(i) => i.clone(),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    value.to_i64()
+}
+
+fn float_value(expr: &Expression) -> Option<f64> {
+    match expr {
+        Expression::Float(f) => f.parse_value().ok(),
+        Expression::Constant(c) => match c.value {
+            ConstantValue::Float(f) => Some(f),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// A pattern for one statement: a compound statement, or one of the simple statements on a
+/// line.
+#[derive(Clone)]
+pub enum StmtMatcher {
+    /// Matches any statement.
+    Any,
+    /// An expression statement, such as a bare call.
+    Expr(ExprMatcher),
+    Assign(Box<AssignMatcher>),
+    /// `return`, with a value matching the inner matcher; `None` accepts any `return`.
+    Return(Option<ExprMatcher>),
+    /// `import module` or `from module import ...`, by the module's dotted name.  A relative
+    /// module keeps its leading dots, e.g. `..pkg`.
+    Import(TextMatcher),
+    /// A function definition by name.
+    FunctionDef(TextMatcher),
+    /// A class definition by name.
+    ClassDef(TextMatcher),
+    /// An `if` statement by its test; an `elif` is part of the `if` it follows.
+    If(ExprMatcher),
+    While(ExprMatcher),
+    For(Box<ForMatcher>),
+    OneOf(Vec<StmtMatcher>),
+    AllOf(Vec<StmtMatcher>),
+    Not(Box<StmtMatcher>),
+}
+
+#[derive(Clone)]
+pub struct AssignMatcher {
+    /// Matches when any target of a chained assignment such as `a = b = value` does.
+    pub target: ExprMatcher,
+    pub value: ExprMatcher,
+}
+
+#[derive(Clone)]
+pub struct ForMatcher {
+    pub target: ExprMatcher,
+    pub iter: ExprMatcher,
+}
+
+pub fn expr_stmt(value: ExprMatcher) -> StmtMatcher {
+    StmtMatcher::Expr(value)
+}
+
+/// `target = value`
+pub fn assign(target: ExprMatcher, value: ExprMatcher) -> StmtMatcher {
+    StmtMatcher::Assign(Box::new(AssignMatcher { target, value }))
+}
+
+/// `for target in iter`
+pub fn for_loop(target: ExprMatcher, iter: ExprMatcher) -> StmtMatcher {
+    StmtMatcher::For(Box::new(ForMatcher { target, iter }))
+}
+
+/// The statement a `StmtMatcher` is tried against, borrowed from the tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatementRef<'r, 'a> {
+    Small(&'r SmallStatement<'a>),
+    Compound(&'r CompoundStatement<'a>),
+}
+
+impl StmtMatcher {
+    pub fn matches<'r, 'a>(&self, stmt: StatementRef<'r, 'a>, captures: &mut Captures<'r, 'a>) -> bool {
+        use StatementRef::{Compound, Small};
+        match (self, stmt) {
+            (Self::Any, _) => true,
+            (Self::Expr(m), Small(SmallStatement::Expr(e))) => m.matches_expr(&e.value, captures),
+            (Self::Assign(m), Small(SmallStatement::Assign(a))) => {
+                a.targets.iter().any(|t| attempt(&m.target, Node::Target(&t.target), captures))
+                    && m.value.matches_expr(&a.value, captures)
+            }
+            (Self::Return(m), Small(SmallStatement::Return(r))) => match (m, &r.value) {
+                (None, _) => true,
+                (Some(m), Some(value)) => m.matches_expr(value, captures),
+                (Some(_), None) => false,
+            },
+            (Self::Import(text), Small(SmallStatement::Import(i))) => {
+                i.names.iter().any(|alias| text.matches(&alias.name.dotted_name()))
+            }
+            (Self::Import(text), Small(SmallStatement::ImportFrom(i))) => {
+                let mut module = ".".repeat(i.relative.len());
+                if let Some(name) = &i.module {
+                    module.push_str(&name.dotted_name());
+                }
+                text.matches(&module)
+            }
+            (Self::FunctionDef(text), Compound(CompoundStatement::FunctionDef(f))) => text.matches(f.name.value),
+            (Self::ClassDef(text), Compound(CompoundStatement::ClassDef(c))) => text.matches(c.name.value),
+            (Self::If(m), Compound(CompoundStatement::If(i))) => m.matches_expr(&i.test, captures),
+            (Self::While(m), Compound(CompoundStatement::While(w))) => m.matches_expr(&w.test, captures),
+            (Self::For(m), Compound(CompoundStatement::For(f))) => {
+                attempt(&m.target, Node::Target(&f.target), captures) && m.iter.matches_expr(&f.iter, captures)
+            }
+            (Self::OneOf(options), _) => options.iter().any(|option| {
+                let mut trial = captures.clone();
+                option.matches(stmt, &mut trial) && {
+                    *captures = trial;
+                    true
+                }
+            }),
+            (Self::AllOf(all), _) => {
+                let mut trial = captures.clone();
+                all.iter().all(|m| m.matches(stmt, &mut trial)) && {
+                    *captures = trial;
+                    true
+                }
+            }
+            (Self::Not(inner), _) => !inner.matches(stmt, &mut captures.clone()),
+            _ => false,
+        }
+    }
+}
+
+/// One place `find_all` matched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Found<'r, 'a> {
+    pub node: Node<'r, 'a>,
+    /// The span of `node` itself.
+    pub span: Span,
+    pub captures: Captures<'r, 'a>,
+}
+
+/// Every expression in the module matching `matcher`, outermost first and in source order.
+/// Nested matches are all reported, so `f(f(x))` matches `call(name("f"))` twice.
+pub fn find_all<'r, 'a>(module: &'r Module<'a>, matcher: &ExprMatcher) -> Vec<Found<'r, 'a>> {
+    let mut finder = Finder { matcher, found: vec![] };
+    finder.visit_module(module);
+    finder.found
+}
+
+struct Finder<'m, 'r, 'a> {
+    matcher: &'m ExprMatcher,
+    found: Vec<Found<'r, 'a>>,
+}
+
+impl<'m, 'r, 'a> Finder<'m, 'r, 'a> {
+    fn check(&mut self, node: Node<'r, 'a>) {
+        let mut captures = Captures::new();
+        if self.matcher.matches(node, &mut captures) {
+            self.found.push(Found { node, span: node.span(), captures });
+        }
+    }
+}
+
+impl<'m, 'r, 'a> Visitor<'r, 'a> for Finder<'m, 'r, 'a> {
+    fn visit_expression(&mut self, node: &'r Expression<'a>) {
+        self.check(Node::Expression(node));
+        visitor::walk_expression(self, node);
+    }
+
+    fn visit_assign_target(&mut self, node: &'r AssignTargetExpression<'a>) {
+        self.check(Node::Target(node));
+        visitor::walk_assign_target(self, node);
+    }
+}
+
+/// One place `find_statements` matched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FoundStatement<'r, 'a> {
+    /// The matching statement.
+    pub node: StatementRef<'r, 'a>,
+    /// The span of the statement, or for a simple statement that of the whole line it is on,
+    /// since simple statements don't carry spans of their own.
+    pub span: Span,
+    pub captures: Captures<'r, 'a>,
+}
+
+/// Every statement in the module matching `matcher`, including those nested in the bodies
+/// of other statements, outermost first and in source order.
+pub fn find_statements<'r, 'a>(module: &'r Module<'a>, matcher: &StmtMatcher) -> Vec<FoundStatement<'r, 'a>> {
+    let mut finder = StatementFinder { matcher, line: Span::default(), found: vec![] };
+    finder.visit_module(module);
+    finder.found
+}
+
+struct StatementFinder<'m, 'r, 'a> {
+    matcher: &'m StmtMatcher,
+    /// The line, or the body after a compound statement's colon, being visited.
+    line: Span,
+    found: Vec<FoundStatement<'r, 'a>>,
+}
+
+impl<'m, 'r, 'a> Visitor<'r, 'a> for StatementFinder<'m, 'r, 'a> {
+    fn visit_statement(&mut self, node: &'r Statement<'a>) {
+        if let Statement::Simple(line) = node {
+            self.line = line.span;
+        }
+        visitor::walk_statement(self, node);
+    }
+
+    fn visit_suite(&mut self, node: &'r Suite<'a>) {
+        if let Suite::SimpleStatementSuite(suite) = node {
+            self.line = suite.span;
+        }
+        visitor::walk_suite(self, node);
+    }
+
+    fn visit_small_statement(&mut self, node: &'r SmallStatement<'a>) {
+        let mut captures = Captures::new();
+        let node = StatementRef::Small(node);
+        if self.matcher.matches(node, &mut captures) {
+            self.found.push(FoundStatement { node, span: self.line, captures });
+        }
+    }
+
+    fn visit_compound_statement(&mut self, node: &'r CompoundStatement<'a>) {
+        let mut captures = Captures::new();
+        if self.matcher.matches(StatementRef::Compound(node), &mut captures) {
+            let span = node.span();
+            self.found.push(FoundStatement { node: StatementRef::Compound(node), span, captures });
+        }
+        visitor::walk_compound_statement(self, node);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_module, TokenArena};
+
+    /// The source text `span` covers, for spans within one line.
+    fn text(source: &str, span: Span) -> &str {
+        assert_eq!(span.start.line, span.end.line);
+        let line = source.lines().nth(span.start.line - 1).expect("line");
+        &line[span.start.col..span.end.col]
+    }
+
+    #[test]
+    fn finds_nested_calls_with_captures() {
+        let source = "os.system(cmd)\nx = os.environ[\"HOME\"] + subprocess.run(\"ls\", shell=True)\n";
+        let arena = TokenArena::from_source(source).expect("tokenize");
+        let module = parse_module(&arena, "__test__").expect("parse");
+
+        let os_system = call(attr(name("os"), "system"));
+        let found = find_all(&module, &os_system);
+        assert_eq!(found.len(), 1);
+        assert_eq!(text(source, found[0].span), "os.system(cmd)");
+
+        let shell_true = call_with(
+            capture("func", attr(name(TextMatcher::OneOf(vec!["subprocess".into(), "os".into()])), TextMatcher::Any)),
+            vec![ArgMatcher::ZeroOrMore, ArgMatcher::Keyword("shell".into(), name("True")), ArgMatcher::ZeroOrMore],
+        );
+        let found = find_all(&module, &shell_true);
+        assert_eq!(found.len(), 1);
+        assert_eq!(text(source, found[0].span), "subprocess.run(\"ls\", shell=True)");
+        assert_eq!(text(source, found[0].captures["func"].span()), "subprocess.run");
+
+        let environ = ExprMatcher::OneOf(vec![
+            subscript(attr(name("os"), "environ"), ExprMatcher::Str(TextMatcher::Regex(Regex::new("^HO").unwrap()))),
+            call(attr(attr(name("os"), "environ"), "get")),
+        ]);
+        let found = find_all(&module, &environ);
+        assert_eq!(found.len(), 1);
+        assert_eq!(text(source, found[0].span), "os.environ[\"HOME\"]");
+        assert!(find_all(&module, &ExprMatcher::Not(Box::new(ExprMatcher::Any))).is_empty());
+    }
+
+    #[test]
+    fn reports_each_nested_match_with_its_own_span() {
+        let source = "def g():\n    return f(1, f(f(x)))\n";
+        let arena = TokenArena::from_source(source).expect("tokenize");
+        let module = parse_module(&arena, "__test__").expect("parse");
+
+        let found = find_all(&module, &capture("arg", call_with(name("f"), vec![ArgMatcher::Any])));
+        let spans: Vec<&str> = found.iter().map(|found| text(source, found.span)).collect();
+        assert_eq!(spans, ["f(f(x))", "f(x)"]);
+        assert_eq!(found[1].captures["arg"], found[1].node);
+
+        let found = find_all(&module, &ExprMatcher::Int(1));
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].span.start.line, found[0].span.start.col), (2, 13));
+    }
+
+    #[test]
+    fn matches_every_expression_kind() {
+        let source = "\
+a = not x and -y or z
+if 0 < n <= 10: pass
+b = {1: 2, **rest}
+c = lambda p, *q: p if q else ...
+d = [i * 2 for i in items], {*s}, (w := 1.5)
+async def f():
+    yield await g()
+    yield from h
+e, *f = g
+";
+        let arena = TokenArena::from_source(source).expect("tokenize");
+        let module = parse_module(&arena, "__test__").expect("parse");
+        let spans = |matcher: &ExprMatcher| -> Vec<String> {
+            find_all(&module, matcher).iter().map(|found| text(source, found.span).to_string()).collect()
+        };
+
+        assert_eq!(spans(&unary(UnaryOp::Not, name("x"))), ["not x"]);
+        assert_eq!(spans(&unary(UnaryOp::Minus, ExprMatcher::Any)), ["-y"]);
+        assert_eq!(spans(&boolop(ExprMatcher::Any, BooleanOp::Or, name("z"))), ["not x and -y or z"]);
+        assert_eq!(spans(&boolop(unary(UnaryOp::Not, ExprMatcher::Any), BooleanOp::And, ExprMatcher::Any)), ["not x and -y"]);
+        assert!(spans(&compare(ExprMatcher::Int(0), CompOp::LessThan, name("n"))).is_empty());
+        let chain = ExprMatcher::Comparison(Box::new(ComparisonMatcher {
+            left: ExprMatcher::Int(0),
+            comparisons: vec![(Some(CompOp::LessThan), name("n")), (None, ExprMatcher::Int(10))],
+        }));
+        assert_eq!(spans(&chain), ["0 < n <= 10"]);
+
+        let dict = ExprMatcher::Dict(vec![
+            DictElementMatcher::Pair(ExprMatcher::Int(1), ExprMatcher::Any),
+            DictElementMatcher::DoubleStar(name("rest")),
+        ]);
+        assert_eq!(spans(&dict), ["{1: 2, **rest}"]);
+
+        let lambda = ExprMatcher::Lambda(Box::new(LambdaMatcher {
+            params: Some(vec!["p".into(), "q".into()]),
+            body: ExprMatcher::IfExp(Box::new(IfExpMatcher {
+                test: name("q"),
+                body: name("p"),
+                orelse: ExprMatcher::Ellipsis,
+            })),
+        }));
+        assert_eq!(spans(&lambda), ["lambda p, *q: p if q else ..."]);
+
+        let comp = ExprMatcher::ListComp(Box::new(ComprehensionMatcher {
+            elt: ExprMatcher::BinaryOperation(Box::new(BinaryOperationMatcher {
+                left: name("i"),
+                operator: Some(BinaryOp::Multiply),
+                right: ExprMatcher::Int(2),
+            })),
+            target: name("i"),
+            iter: name("items"),
+        }));
+        assert_eq!(spans(&comp), ["[i * 2 for i in items]"]);
+        assert_eq!(spans(&ExprMatcher::Set(vec![ExprMatcher::Starred(Box::new(name("s")))])), ["{*s}"]);
+        let walrus = ExprMatcher::NamedExpr(Box::new(NamedExprMatcher { target: name("w"), value: ExprMatcher::Float(1.5) }));
+        assert_eq!(spans(&walrus), ["w := 1.5"]);
+        assert_eq!(spans(&ExprMatcher::Yield(Some(Box::new(ExprMatcher::Await(Box::new(call(name("g")))))))), ["yield await g()"]);
+        assert_eq!(spans(&ExprMatcher::Yield(None)), ["yield await g()"]);
+        assert_eq!(spans(&ExprMatcher::YieldFrom(Box::new(name("h")))), ["yield from h"]);
+
+        // Targets are matched in place, as the expressions they are written as.
+        let unpack = ExprMatcher::Tuple(vec![name("e"), capture("rest", ExprMatcher::Starred(Box::new(name("f"))))]);
+        let found = find_all(&module, &unpack);
+        assert_eq!(found.len(), 1);
+        assert!(matches!(found[0].node, Node::Target(AssignTargetExpression::Tuple(_))));
+        assert_eq!(text(source, found[0].captures["rest"].span()), "*f");
+    }
+
+    #[test]
+    fn finds_statements_in_nested_bodies() {
+        let source = "\
+import os.path
+from ..pkg import mod
+class C:
+    def method(self):
+        x = 1; y = os.getcwd()
+        for item in range(3):
+            if item: y = x = item
+        return y
+";
+        let arena = TokenArena::from_source(source).expect("tokenize");
+        let module = parse_module(&arena, "__test__").expect("parse");
+        let lines = |found: &[FoundStatement]| found.iter().map(|f| f.span.start.line).collect::<Vec<_>>();
+
+        let found = find_statements(&module, &assign(name("y"), ExprMatcher::Any));
+        assert_eq!(lines(&found), [5, 7]);
+        assert_eq!(text(source, found[0].span), "x = 1; y = os.getcwd()");
+        assert!(matches!(found[0].node, StatementRef::Small(SmallStatement::Assign(_))));
+
+        let calls = assign(capture("target", ExprMatcher::Any), capture("value", call(ExprMatcher::Any)));
+        let found = find_statements(&module, &calls);
+        assert_eq!(found.len(), 1);
+        assert_eq!(text(source, found[0].captures["target"].span()), "y");
+        assert_eq!(text(source, found[0].captures["value"].span()), "os.getcwd()");
+
+        let found = find_statements(&module, &for_loop(capture("t", name("item")), call(name("range"))));
+        assert_eq!(lines(&found), [6]);
+        assert_eq!(found[0].span.end.line, 7);
+        assert!(found[0].captures.contains_key("t"));
+
+        let defs = StmtMatcher::OneOf(vec![
+            StmtMatcher::ClassDef("C".into()),
+            StmtMatcher::FunctionDef(TextMatcher::Regex(Regex::new("^meth").unwrap())),
+        ]);
+        assert_eq!(lines(&find_statements(&module, &defs)), [3, 4]);
+        assert_eq!(lines(&find_statements(&module, &StmtMatcher::If(name("item")))), [7]);
+        assert_eq!(lines(&find_statements(&module, &StmtMatcher::Return(Some(name("y"))))), [8]);
+        assert_eq!(lines(&find_statements(&module, &StmtMatcher::Import("os.path".into()))), [1]);
+        assert_eq!(lines(&find_statements(&module, &StmtMatcher::Import("..pkg".into()))), [2]);
+
+        let not_assign = StmtMatcher::AllOf(vec![
+            StmtMatcher::Not(Box::new(assign(ExprMatcher::Any, ExprMatcher::Any))),
+            StmtMatcher::Not(Box::new(StmtMatcher::Import(TextMatcher::Any))),
+        ]);
+        assert_eq!(lines(&find_statements(&module, &not_assign)), [3, 4, 6, 7, 8]);
+    }
+}
@@ -4,13 +4,27 @@ mod op;
 mod module;
 mod error;
 pub mod numbers;
+pub mod strings;
+mod constant;
 mod traits;
 mod whitespace;
+mod span;
+pub mod visitor;
+pub mod unparse;
+pub mod builder;
+pub mod matchers;
+pub mod inspect;
+mod validate;
+mod boxed;
 
-pub use module::Module;
+pub use module::{FunctionType, Module};
+pub use span::Span;
+pub use boxed::{Boxed, NodeArena};
+pub use constant::{Constant, ConstantValue};
+pub use inspect::{ParamKind, Signature, SignatureParam};
 
 pub use expression::{
-    Comma, Name, Integer, Float, Binary, Hexidecimal, Imaginary, Comparison, ComparisonTarget, Element, StarredElement,
+    Comma, Name, Ellipsis, Integer, Float, Binary, Hexidecimal, Imaginary, Comparison, ComparisonTarget, Element, StarredElement,
     Arg, Attribute, Tuple, Call, GeneratorExp, CompFor, CompIf,
     AssignTargetExpression,
     Subscript, BaseSlice, Index, Slice, SubscriptElement, ListComp, SetComp, DictComp, List, Set, Dict, DictElement,
@@ -37,8 +51,8 @@ pub use op::{
 };
 pub use statement::{
     AnnAssign, Annotation, AsName, Assert, Assign, AssignTarget, AugAssign,
-    CompoundStatement, Expr, Statement, SmallStatement, OrElse, Suite, Return, Raise, Global, Nonlocal, Del, Import, ImportFrom, ImportNames, ImportAlias,
-    FunctionDef, ClassDef, If, While, For, With, Try, TryStar, ExceptHandler, ExceptStarHandler, Else, WithItem, Finally, Match, MatchPattern, StarrableMatchSequenceElement,
+    CompoundStatement, Expr, Statement, ErrorStatement, SmallStatement, OrElse, Suite, Return, Break, Continue, Raise, Global, Nonlocal, Del, Import, ImportFrom, ImportNames, ImportAlias,
+    FunctionDef, ClassDef, TypeAlias, TypeParam, TypeParamKind, If, While, For, With, Try, TryStar, ExceptHandler, ExceptStarHandler, Else, WithItem, Finally, Match, MatchPattern, StarrableMatchSequenceElement,
     Decorator, MatchCase, MatchStar, MatchMappingElement, MatchSequenceElement, MatchKeywordElement, SimpleStatementLine, SimpleStatementSuite,
     MatchTuple, MatchMapping, MatchClass,
     DelTargetExpression,
@@ -54,7 +68,7 @@ pub use statement::{
     Dot,
 };
 
-pub use crate::ast::error::ParserError;
+pub use crate::ast::error::{ParserError, SyntaxError};
 
 pub use traits::WithComma;
 
@@ -1,10 +1,9 @@
-use std::rc::Rc;
-
 use crate::tokenizer::Token;
 
+use super::expression::Expression;
 use super::statement::Statement;
 
-type TokenRef<'a> = Rc<Token<'a>>;
+type TokenRef<'a> = &'a Token<'a>;
 
 pub struct Module<'a> {
     pub body: Vec<Statement<'a>>,
@@ -15,4 +14,12 @@ pub struct Module<'a> {
     pub encoding: String,
 
     pub(crate) eof_tok: TokenRef<'a>,
-}
\ No newline at end of file
+}
+
+/// A signature type comment, `# type: (int, str) -> bool`, parsed in `func_type` mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionType<'a> {
+    /// The argument types, without the `*` or `**` of variadic ones.
+    pub argtypes: Vec<Expression<'a>>,
+    pub returns: Expression<'a>,
+}
@@ -6,7 +6,9 @@
 use once_cell::sync::Lazy;
 use regex::Regex;
 
-use super::{Expression, Float, Imaginary, Integer};
+use super::{Binary, Expression, Float, Hexidecimal, Imaginary, Integer, NodeArena, Span};
+use crate::bigint::BigInt;
+use crate::tokenizer::TokError;
 
 static HEX: &str = r"0[xX](?:_?[0-9a-fA-F])+";
 static BIN: &str = r"0[bB](?:_?[01])+";
@@ -44,26 +46,179 @@ static IMAGINARY_RE: Lazy<Regex> = Lazy::new(|| {
     .expect("regex")
 });
 
-pub(crate) fn parse_number(raw: &str) -> Expression {
+/// The value of a numeric literal once it has been fully evaluated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NumberValue {
+    Integer(BigInt),
+    Float(f64),
+    /// The imaginary part of a complex literal such as ``1.5j``
+    Imaginary(f64),
+}
+
+pub(crate) fn parse_number<'a>(raw: &'a str, span: Span, arena: &'a NodeArena) -> Result<Expression<'a>, TokError> {
     if INTEGER_RE.is_match(raw) {
-        Expression::Integer(Box::new(Integer {
+        Ok(Expression::Integer(arena.alloc(Integer {
             value: raw,
-
-        }))
+            span,
+        })))
     } else if FLOAT_RE.is_match(raw) {
-        Expression::Float(Box::new(Float {
+        Ok(Expression::Float(arena.alloc(Float {
             value: raw,
-
-        }))
+            span,
+        })))
     } else if IMAGINARY_RE.is_match(raw) {
-        Expression::Imaginary(Box::new(Imaginary {
+        Ok(Expression::Imaginary(arena.alloc(Imaginary {
             value: raw,
+            span,
+        })))
+    } else {
+        Err(classify_bad_number(raw))
+    }
+}
 
-        }))
+/// Evaluates any numeric literal.
+pub fn parse_number_value(raw: &str) -> Result<NumberValue, TokError> {
+    if INTEGER_RE.is_match(raw) {
+        integer_value(raw).map(NumberValue::Integer)
+    } else if FLOAT_RE.is_match(raw) {
+        float_value(raw).map(NumberValue::Float)
+    } else if IMAGINARY_RE.is_match(raw) {
+        imaginary_value(raw).map(NumberValue::Imaginary)
     } else {
-        Expression::Integer(Box::new(Integer {
-            value: raw,
+        Err(classify_bad_number(raw))
+    }
+}
+
+/// Evaluates an integer literal in any base, e.g. ``0x_FF``, ``0o17``, ``0b1010`` or ``1_000``.
+pub fn integer_value(raw: &str) -> Result<BigInt, TokError> {
+    if !INTEGER_RE.is_match(raw) {
+        return Err(classify_bad_number(raw));
+    }
+    let (radix, digits) = match raw.get(..2) {
+        Some("0x") | Some("0X") => (16, &raw[2..]),
+        Some("0o") | Some("0O") => (8, &raw[2..]),
+        Some("0b") | Some("0B") => (2, &raw[2..]),
+        _ => (10, raw),
+    };
+    let digits: std::string::String = digits.chars().filter(|c| *c != '_').collect();
+    BigInt::from_str_radix(&digits, radix).ok_or_else(|| classify_bad_number(raw))
+}
+
+/// Evaluates a float literal.  Rust's float parsing is correctly rounded, so the result is
+/// the nearest IEEE-754 double.  Literals too large for a double become ``inf``, as they do
+/// in CPython.
+pub fn float_value(raw: &str) -> Result<f64, TokError> {
+    if !FLOAT_RE.is_match(raw) {
+        return Err(classify_bad_number(raw));
+    }
+    strip_underscores(raw).parse::<f64>().map_err(|_| TokError::BadDecimal)
+}
+
+/// Evaluates the imaginary part of a literal like ``3.14j``.
+pub fn imaginary_value(raw: &str) -> Result<f64, TokError> {
+    if !IMAGINARY_RE.is_match(raw) {
+        return Err(classify_bad_number(raw));
+    }
+    let body = &raw[..raw.len() - 1];
+    strip_underscores(body).parse::<f64>().map_err(|_| TokError::BadDecimal)
+}
+
+fn strip_underscores(raw: &str) -> std::string::String {
+    raw.chars().filter(|c| *c != '_').collect()
+}
+
+/// Picks the same complaint CPython's tokenizer gives for a malformed number.
+fn classify_bad_number(raw: &str) -> TokError {
+    let mut chars = raw.chars();
+    match (chars.next(), chars.next()) {
+        (Some('0'), Some('x' | 'X')) => TokError::BadHexadecimal,
+        (Some('0'), Some('o' | 'O')) => {
+            match raw[2..].chars().find(|c| c.is_ascii_digit() && *c > '7') {
+                Some(bad) => TokError::BadOctalDigit(bad),
+                None => TokError::BadOctal,
+            }
+        }
+        (Some('0'), Some('b' | 'B')) => {
+            match raw[2..].chars().find(|c| c.is_ascii_digit() && *c > '1') {
+                Some(bad) => TokError::BadBinaryDigit(bad),
+                None => TokError::BadBinary,
+            }
+        }
+        (Some('0'), Some(next)) if next.is_ascii_digit() || next == '_' => {
+            if raw.chars().all(|c| c.is_ascii_digit() || c == '_') {
+                TokError::BadDecimalLeadingZeros
+            } else {
+                TokError::BadDecimal
+            }
+        }
+        _ => TokError::BadDecimal,
+    }
+}
+
+impl<'a> Integer<'a> {
+    pub fn parse_value(&self) -> Result<BigInt, TokError> {
+        integer_value(self.value)
+    }
+}
+
+impl<'a> Hexidecimal<'a> {
+    pub fn parse_value(&self) -> Result<BigInt, TokError> {
+        integer_value(self.value)
+    }
+}
+
+impl<'a> Binary<'a> {
+    pub fn parse_value(&self) -> Result<BigInt, TokError> {
+        integer_value(self.value)
+    }
+}
+
+impl<'a> Float<'a> {
+    pub fn parse_value(&self) -> Result<f64, TokError> {
+        float_value(self.value)
+    }
+}
+
+impl<'a> Imaginary<'a> {
+    pub fn parse_value(&self) -> Result<f64, TokError> {
+        imaginary_value(self.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{integer_value, float_value, imaginary_value, parse_number, NodeArena, Span};
+    use crate::bigint::BigInt;
+    use crate::tokenizer::TokError;
+
+    #[test]
+    fn integers_in_every_base() {
+        assert_eq!(integer_value("1_000").unwrap(), BigInt::from_i64(1000));
+        assert_eq!(integer_value("0x_FF").unwrap(), BigInt::from_i64(255));
+        assert_eq!(integer_value("0o17").unwrap(), BigInt::from_i64(15));
+        assert_eq!(integer_value("0b1010").unwrap(), BigInt::from_i64(10));
+        assert_eq!(
+            integer_value("123456789012345678901234567890").unwrap().to_string(),
+            "123456789012345678901234567890"
+        );
+    }
+
+    #[test]
+    fn floats_and_imaginary() {
+        assert_eq!(float_value("1_0.5e1_0").unwrap(), 10.5e10);
+        assert_eq!(float_value(".1").unwrap(), 0.1);
+        assert_eq!(float_value("1e400").unwrap(), f64::INFINITY);
+        assert_eq!(imaginary_value("2.5j").unwrap(), 2.5);
+        assert_eq!(imaginary_value("10J").unwrap(), 10.0);
+    }
 
-        }))
+    #[test]
+    fn malformed_literals_are_errors() {
+        let arena = NodeArena::new();
+        assert_eq!(parse_number("0x", Span::default(), &arena).unwrap_err(), TokError::BadHexadecimal);
+        assert_eq!(parse_number("0o19", Span::default(), &arena).unwrap_err(), TokError::BadOctalDigit('9'));
+        assert_eq!(parse_number("0b102", Span::default(), &arena).unwrap_err(), TokError::BadBinaryDigit('2'));
+        assert_eq!(parse_number("012", Span::default(), &arena).unwrap_err(), TokError::BadDecimalLeadingZeros);
+        assert_eq!(parse_number("1__0", Span::default(), &arena).unwrap_err(), TokError::BadDecimal);
     }
 }
@@ -1,8 +1,6 @@
-use std::rc::Rc;
-
 use crate::tokenizer::Token;
 
-type TokenRef<'a> = Rc<Token<'a>>;
+type TokenRef<'a> = &'a Token<'a>;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct AssignEqual<'a> {
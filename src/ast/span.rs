@@ -0,0 +1,41 @@
+use crate::tokenizer::{Position, Token};
+
+/// The region of source a node was parsed from, from the start of its first token to the end
+/// of its last one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Self {
+        Self { start, end }
+    }
+
+    pub fn from_token(tok: &Token) -> Self {
+        Self::new(tok.start, tok.end)
+    }
+
+    pub fn between(first: &Token, last: &Token) -> Self {
+        Self::new(first.start, last.end)
+    }
+
+    /// Widens this span so that it ends where `other` ends.
+    pub fn to(self, other: Span) -> Self {
+        Self::new(self.start, other.end)
+    }
+
+    /// The text of `source` the span covers, if `source` is what it was parsed from.
+    pub fn text<'s>(&self, source: &'s str) -> Option<&'s str> {
+        let offset = |pos: Position| {
+            let line_start = match pos.line {
+                0 => return None,
+                1 => 0,
+                line => source.match_indices('\n').nth(line - 2)?.0 + 1,
+            };
+            Some(line_start + pos.col)
+        };
+        source.get(offset(self.start)?..offset(self.end)?)
+    }
+}
@@ -1,13 +1,13 @@
 
-use std::rc::Rc;
-
 use crate::tokenizer::Token;
 
 use super::expression::{Arg, AssignTargetExpression, Asynchronous, Expression, From, Parameters, StarredElement, Tuple, List, Subscript, Name, NameOrAttribute, Comma, Element, Attribute};
 use super::op::{ AugOp, AssignEqual, BitOr, ImportStar};
 use super::traits::WithComma;
+use super::span::Span;
+use super::boxed::Boxed;
 
-type TokenRef<'a> = Rc<Token<'a>>;
+type TokenRef<'a> = &'a Token<'a>;
 
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct AugAssign<'a> {
@@ -33,10 +33,12 @@ pub enum CompoundStatement<'a> {
 #[derive(Eq, PartialEq, Clone, Debug)]
 pub struct ClassDef<'a> {
     pub name: Name<'a>,
+    pub type_params: Vec<TypeParam<'a>>,
     pub body: Suite<'a>,
     pub bases: Vec<Arg<'a>>,
     pub keywords: Vec<Arg<'a>>,
     pub decorators: Vec<Decorator<'a>>,
+    pub span: Span,
 }
 
 impl<'a> ClassDef<'a> {
@@ -48,12 +50,13 @@ impl<'a> ClassDef<'a> {
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct FunctionDef<'a> {
     pub name: Name<'a>,
+    pub type_params: Vec<TypeParam<'a>>,
     pub params: Parameters<'a>,
     pub body: Suite<'a>,
     pub decorators: Vec<Decorator<'a>>,
     pub returns: Option<Annotation<'a>>,
     pub asynchronous: Option<Asynchronous,>,
-
+    pub span: Span,
 }
 
 impl<'a> FunctionDef<'a> {
@@ -62,6 +65,35 @@ impl<'a> FunctionDef<'a> {
     }
 }
 
+/// A type parameter of a generic class, function or type alias (PEP 695).
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TypeParam<'a> {
+    pub kind: TypeParamKind<'a>,
+    pub name: Name<'a>,
+    /// The PEP 696 default, as in `T = int`.  For a `TypeVarTuple` it can be starred.
+    pub default: Option<Expression<'a>>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TypeParamKind<'a> {
+    /// `T` or `T: bound`.  A tuple bound, as in `T: (int, str)`, is a list of constraints.
+    TypeVar { bound: Option<Expression<'a>> },
+    /// `*Ts`
+    TypeVarTuple,
+    /// `**P`
+    ParamSpec,
+}
+
+impl<'a> TypeParam<'a> {
+    /// The constraints of a `TypeVar` written `T: (int, str)`.
+    pub fn constraints(&self) -> Option<&[Element<'a>]> {
+        match &self.kind {
+            TypeParamKind::TypeVar { bound: Some(Expression::Tuple(tuple)) } => Some(&tuple.elements),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct For<'a> {
     pub target: AssignTargetExpression<'a>,
@@ -69,6 +101,7 @@ pub struct For<'a> {
     pub body: Suite<'a>,
     pub orelse: Option<Else<'a>>,
     pub asynchronous: Option<Asynchronous,>,
+    pub span: Span,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -84,8 +117,9 @@ pub struct If<'a> {
     pub body: Suite<'a>,
 
     /// An optional ``elif`` or ``else`` clause. ``If`` signifies an ``elif`` block.
-    pub orelse: Option<Box<OrElse<'a>>>,
+    pub orelse: Option<Boxed<'a, OrElse<'a>>>,
     pub is_elif: bool,
+    pub span: Span,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -102,6 +136,7 @@ pub enum ImportNames<'a> {
 pub struct Match<'a> {
     pub subject: Expression<'a>,
     pub cases: Vec<MatchCase<'a>>,
+    pub span: Span,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -153,8 +188,8 @@ pub enum MatchPattern<'a> {
     Sequence(MatchSequence<'a>),
     Mapping(MatchMapping<'a>),
     Class(MatchClass<'a>),
-    As(Box<MatchAs<'a>>),
-    Or(Box<MatchOr<'a>>),
+    As(Boxed<'a, MatchAs<'a>>),
+    Or(Boxed<'a, MatchOr<'a>>),
 }
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct MatchOr<'a> {
@@ -202,12 +237,24 @@ pub struct NameItem<'a> {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Nonlocal<'a> {
     pub names: Vec<NameItem<'a>>,
+    pub span: Span,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Statement<'a> {
     Simple(SimpleStatementLine<'a>),
     Compound(CompoundStatement<'a>),
+    /// Only produced by `parse_module_recovering`.
+    Error(ErrorStatement<'a>),
+}
+
+/// Source the recovering parser skipped: the rest of the logical line a statement failed on,
+/// and any block indented under it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ErrorStatement<'a> {
+    /// From the first skipped token to the end of the line, like a simple statement.
+    pub span: Span,
+    pub(crate) tokens: &'a [Token<'a>],
 }
 
 #[derive(Clone, Eq, PartialEq, Debug)]
@@ -219,6 +266,7 @@ pub enum Suite<'a> {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct SimpleStatementLine<'a> {
     pub body: Vec<SmallStatement<'a>>,
+    pub span: Span,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -226,14 +274,15 @@ pub struct SimpleStatementSuite<'a> {
     /// Sequence of small statements. All but the last statement are required to have
     /// a semicolon.
     pub body: Vec<SmallStatement<'a>>,
+    pub span: Span,
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub enum SmallStatement<'a> {
     Pass,
     //TODO double check that Python doesn't have named break/continues
-    Break,
-    Continue,
+    Break(Break),
+    Continue(Continue),
     Return(Return<'a>),
     Expr(Expr<'a>),
     Assert(Assert<'a>),
@@ -246,6 +295,7 @@ pub enum SmallStatement<'a> {
     Nonlocal(Nonlocal<'a>),
     AugAssign(AugAssign<'a>),
     Del(Del<'a>),
+    TypeAlias(TypeAlias<'a>),
 }
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum StarrableMatchSequenceElement<'a> {
@@ -253,6 +303,14 @@ pub enum StarrableMatchSequenceElement<'a> {
     Starred(MatchStar<'a>),
 }
 
+/// `type Name[params] = value` (PEP 695).
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TypeAlias<'a> {
+    pub name: Name<'a>,
+    pub type_params: Vec<TypeParam<'a>>,
+    pub value: Expression<'a>,
+}
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Raise<'a> {
     pub exc: Option<Expression<'a>>,
@@ -262,6 +320,17 @@ pub struct Raise<'a> {
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Return<'a> {
     pub value: Option<Expression<'a>>,
+    pub span: Span,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Break {
+    pub span: Span,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Continue {
+    pub span: Span,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -270,6 +339,7 @@ pub struct Try<'a> {
     pub handlers: Vec<ExceptHandler<'a>>,
     pub orelse: Option<Else<'a>>,
     pub finalbody: Option<Finally<'a>>,
+    pub span: Span,
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
@@ -278,6 +348,7 @@ pub struct TryStar<'a> {
     pub handlers: Vec<ExceptStarHandler<'a>>,
     pub orelse: Option<Else<'a>>,
     pub finalbody: Option<Finally<'a>>,
+    pub span: Span,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -358,6 +429,9 @@ pub enum OrElse<'a> {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Decorator<'a> {
     pub decorator: Expression<'a>,
+    /// From the `@` to the end of the expression.  The decorated statement's own span starts
+    /// at its `def` or `class` keyword, as in CPython.
+    pub span: Span,
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
@@ -367,11 +441,11 @@ pub struct Del<'a> {
 
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub enum DelTargetExpression<'a> {
-    Name(Box<Name<'a>>),
-    Attribute(Box<Attribute<'a>>),
-    Tuple(Box<Tuple<'a>>),
-    List(Box<List<'a>>),
-    Subscript(Box<Subscript<'a>>),
+    Name(Boxed<'a, Name<'a>>),
+    Attribute(Boxed<'a, Attribute<'a>>),
+    Tuple(Boxed<'a, Tuple<'a>>),
+    List(Boxed<'a, List<'a>>),
+    Subscript(Boxed<'a, Subscript<'a>>),
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
@@ -406,6 +480,7 @@ pub struct While<'a> {
     pub test: Expression<'a>,
     pub body: Suite<'a>,
     pub orelse: Option<Else<'a>>,
+    pub span: Span,
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
@@ -413,6 +488,7 @@ pub struct With<'a> {
     pub items: Vec<WithItem<'a>>,
     pub body: Suite<'a>,
     pub asynchronous: Option<Asynchronous,>,
+    pub span: Span,
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
@@ -438,6 +514,53 @@ pub struct IndentedBlock<'a> {
     pub(crate) dedent_tok: TokenRef<'a>,
 }
 
+impl<'a> Statement<'a> {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Simple(s) => s.span,
+            Self::Compound(c) => c.span(),
+            Self::Error(e) => e.span,
+        }
+    }
+}
+
+impl<'a> CompoundStatement<'a> {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::FunctionDef(f) => f.span,
+            Self::If(i) => i.span,
+            Self::For(f) => f.span,
+            Self::While(w) => w.span,
+            Self::ClassDef(c) => c.span,
+            Self::Try(t) => t.span,
+            Self::TryStar(t) => t.span,
+            Self::With(w) => w.span,
+            Self::Match(m) => m.span,
+        }
+    }
+}
+
+impl<'a> Suite<'a> {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::IndentedBlock(b) => b.span(),
+            Self::SimpleStatementSuite(s) => s.span,
+        }
+    }
+}
+
+impl<'a> IndentedBlock<'a> {
+    /// Runs from the indent to the end of the last statement in the block.
+    pub fn span(&self) -> Span {
+        let end = self
+            .body
+            .last()
+            .map(|s| s.span().end)
+            .unwrap_or(self.dedent_tok.start);
+        Span::new(self.indent_tok.start, end)
+    }
+}
+
 impl<'a> WithComma<'a> for ImportAlias<'a> {
     fn with_comma(self, comma: Comma) -> ImportAlias<'a> {
 
@@ -0,0 +1,251 @@
+use super::{ConcatenatedString, FormattedStringText, SimpleString, String};
+use crate::tokenizer::TokError;
+
+/// The value of a string literal once its prefix, quotes and escapes have been processed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StringValue {
+    Str(std::string::String),
+    Bytes(Vec<u8>),
+}
+
+/// Splits a literal such as ``rb'''x'''`` into its lowercased prefix and the text between
+/// the quotes.
+pub fn split_literal(raw: &str) -> (std::string::String, &str) {
+    let quote_at = raw.find(['\'', '"']).unwrap_or(raw.len());
+    let prefix = raw[..quote_at].to_ascii_lowercase();
+    let quoted = &raw[quote_at..];
+    let quote_len = if quoted.starts_with("'''") || quoted.starts_with("\"\"\"") { 3 } else { 1 };
+    let body = quoted
+        .get(quote_len..quoted.len().saturating_sub(quote_len))
+        .unwrap_or("");
+    (prefix, body)
+}
+
+/// Evaluates a plain (not formatted) string or bytes literal, e.g. ``b'\x00'`` or ``r"\d"``.
+pub fn parse_string_value(raw: &str) -> Result<StringValue, TokError> {
+    let (prefix, body) = split_literal(raw);
+    let is_raw = prefix.contains('r');
+    if prefix.contains('b') {
+        if !body.is_ascii() {
+            return Err(TokError::NonAsciiBytes);
+        }
+        if is_raw {
+            Ok(StringValue::Bytes(body.as_bytes().to_vec()))
+        } else {
+            decode_bytes(body).map(StringValue::Bytes)
+        }
+    } else if is_raw {
+        Ok(StringValue::Str(body.to_string()))
+    } else {
+        decode_str(body).map(StringValue::Str)
+    }
+}
+
+/// Evaluates the literal text between replacement fields of an f-string.  `start` is the
+/// f-string's opening token, e.g. ``rf"``, and decides whether escapes are processed.
+pub fn fstring_text_value(start: &str, text: &str) -> Result<std::string::String, TokError> {
+    let text = text.replace("{{", "{").replace("}}", "}");
+    if start.to_ascii_lowercase().contains('r') {
+        Ok(text)
+    } else {
+        decode_str(&text)
+    }
+}
+
+/// Reads exactly `count` hex digits starting at `start`.
+fn read_hex(chars: &[char], start: usize, count: usize) -> Option<u32> {
+    let digits = chars.get(start..start + count)?;
+    let mut value = 0u32;
+    for digit in digits {
+        value = value * 16 + digit.to_digit(16)?;
+    }
+    Some(value)
+}
+
+/// Reads one to three octal digits starting at `start`.
+fn read_octal(chars: &[char], start: usize) -> (u32, usize) {
+    let mut value = 0;
+    let mut len = 0;
+    while len < 3 {
+        match chars.get(start + len).and_then(|c| c.to_digit(8)) {
+            Some(digit) => value = value * 8 + digit,
+            None => break,
+        }
+        len += 1;
+    }
+    (value, len)
+}
+
+/// The escapes shared by str and bytes literals.  Returns the value and the number of
+/// characters consumed after the backslash, or `None` for an unrecognised escape, which
+/// Python keeps verbatim.
+fn simple_escape(chars: &[char], idx: usize) -> Option<(Option<u32>, usize)> {
+    let value = match chars.get(idx)? {
+        '\n' => return Some((None, 1)),
+        '\\' => '\\' as u32,
+        '\'' => '\'' as u32,
+        '"' => '"' as u32,
+        'a' => 0x07,
+        'b' => 0x08,
+        'f' => 0x0c,
+        'n' => '\n' as u32,
+        'r' => '\r' as u32,
+        't' => '\t' as u32,
+        'v' => 0x0b,
+        '0'..='7' => {
+            let (value, len) = read_octal(chars, idx);
+            return Some((Some(value), len));
+        }
+        _ => return None,
+    };
+    Some((Some(value), 1))
+}
+
+fn decode_str(body: &str) -> Result<std::string::String, TokError> {
+    let chars: Vec<char> = body.chars().collect();
+    let mut out = std::string::String::with_capacity(body.len());
+    let mut idx = 0;
+    while idx < chars.len() {
+        if chars[idx] != '\\' {
+            out.push(chars[idx]);
+            idx += 1;
+            continue;
+        }
+        let start = idx;
+        idx += 1;
+        if let Some((value, len)) = simple_escape(&chars, idx) {
+            if let Some(value) = value {
+                out.push(char::from_u32(value).ok_or(TokError::IllegalUnicodeCharacter(start, idx + len - 1))?);
+            }
+            idx += len;
+            continue;
+        }
+        let (width, name) = match chars.get(idx) {
+            Some('x') => (2, "\\xXX"),
+            Some('u') => (4, "\\uXXXX"),
+            Some('U') => (8, "\\UXXXXXXXX"),
+            Some('N') => {
+                // Named escapes need the Unicode character database, which this crate does
+                // not carry, so every name is reported as unknown.
+                let end = chars[idx..].iter().position(|c| *c == '}').map(|p| idx + p).unwrap_or(chars.len() - 1);
+                return Err(TokError::UnknownUnicodeName(start, end));
+            }
+            _ => {
+                // Unknown escapes are kept as written.
+                out.push('\\');
+                continue;
+            }
+        };
+        let value = read_hex(&chars, idx + 1, width)
+            .ok_or(TokError::TruncatedEscape(start, (idx + width).min(chars.len() - 1), name))?;
+        let chr = char::from_u32(value).ok_or(TokError::IllegalUnicodeCharacter(start, idx + width))?;
+        out.push(chr);
+        idx += 1 + width;
+    }
+    Ok(out)
+}
+
+fn decode_bytes(body: &str) -> Result<Vec<u8>, TokError> {
+    let chars: Vec<char> = body.chars().collect();
+    let mut out = Vec::with_capacity(body.len());
+    let mut idx = 0;
+    while idx < chars.len() {
+        if chars[idx] != '\\' {
+            out.push(chars[idx] as u8);
+            idx += 1;
+            continue;
+        }
+        let start = idx;
+        idx += 1;
+        if let Some((value, len)) = simple_escape(&chars, idx) {
+            if let Some(value) = value {
+                out.push((value & 0xff) as u8);
+            }
+            idx += len;
+            continue;
+        }
+        if chars.get(idx) == Some(&'x') {
+            let value = read_hex(&chars, idx + 1, 2).ok_or(TokError::BadBytesEscape(start))?;
+            out.push(value as u8);
+            idx += 3;
+        } else {
+            out.push(b'\\');
+        }
+    }
+    Ok(out)
+}
+
+impl<'a> SimpleString<'a> {
+    pub fn parse_value(&self) -> Result<StringValue, TokError> {
+        parse_string_value(self.value)
+    }
+}
+
+impl<'a> ConcatenatedString<'a> {
+    /// The value of implicitly concatenated literals such as ``"a" 'b'``.  Returns `None`
+    /// when one of the parts is an f-string, whose value is only known at runtime.
+    pub fn parse_value(&self) -> Option<Result<StringValue, TokError>> {
+        let left = string_value(&self.left)?;
+        let right = string_value(&self.right)?;
+        Some(left.and_then(|left| right.and_then(|right| concat(left, right))))
+    }
+}
+
+fn string_value(s: &String) -> Option<Result<StringValue, TokError>> {
+    match s {
+        String::Simple(s) => Some(s.parse_value()),
+        String::Concatenated(c) => c.parse_value(),
+        String::Formatted(_) => None,
+    }
+}
+
+fn concat(left: StringValue, right: StringValue) -> Result<StringValue, TokError> {
+    match (left, right) {
+        (StringValue::Str(mut l), StringValue::Str(r)) => {
+            l.push_str(&r);
+            Ok(StringValue::Str(l))
+        }
+        (StringValue::Bytes(mut l), StringValue::Bytes(r)) => {
+            l.extend(r);
+            Ok(StringValue::Bytes(l))
+        }
+        // Mixing bytes and str is CPython's "cannot mix bytes and nonbytes literals".
+        _ => Err(TokError::Default),
+    }
+}
+
+impl<'a> FormattedStringText<'a> {
+    pub fn parse_value(&self, start: &str) -> Result<std::string::String, TokError> {
+        fstring_text_value(start, self.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_string_value, StringValue};
+    use crate::tokenizer::TokError;
+
+    fn text(raw: &str) -> std::string::String {
+        match parse_string_value(raw).unwrap() {
+            StringValue::Str(s) => s,
+            StringValue::Bytes(_) => panic!("expected str"),
+        }
+    }
+
+    #[test]
+    fn escapes_prefixes_and_quotes() {
+        assert_eq!(text(r#""a\tb\n""#), "a\tb\n");
+        assert_eq!(text(r#"'\x41é\U0001F600\101'"#), "A\u{e9}\u{1F600}A");
+        assert_eq!(text(r#"r"\d+""#), "\\d+");
+        assert_eq!(text(r#""\d""#), "\\d");
+        assert_eq!(text("'''multi\\\nline'''"), "multiline");
+        assert_eq!(parse_string_value(r#"b'\x00\xff'"#).unwrap(), StringValue::Bytes(vec![0, 255]));
+    }
+
+    #[test]
+    fn malformed_escapes_are_errors() {
+        assert_eq!(parse_string_value(r#""\x1""#).unwrap_err(), TokError::TruncatedEscape(0, 2, "\\xXX"));
+        assert_eq!(parse_string_value(r#"b"\x1""#).unwrap_err(), TokError::BadBytesEscape(0));
+        assert_eq!(parse_string_value("b'\u{e9}'").unwrap_err(), TokError::NonAsciiBytes);
+    }
+}
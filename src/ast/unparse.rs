@@ -0,0 +1,1086 @@
+//! Turns nodes back into Python source, after CPython's `ast.unparse`.
+//!
+//! Literals are written with the text they were parsed from.  Comments and blank lines are
+//! not part of the tree, so they are lost.  Parentheses come from operator precedence rather
+//! than the original source, so `(a + b) * c` round trips but `(a) + b` comes back as `a + b`.
+
+use std::fmt::Write;
+
+use super::{
+    Arg, AsName, AugOp, BaseSlice, BinaryOp, BooleanOp, CompFor, CompOp, CompoundStatement, ConstantValue, Decorator,
+    DictElement, Element, Else, Expression, Finally, FormattedStringContent, If, ImportAlias, ImportNames, MatchPattern,
+    MatchSequence, Module, NameItem, OrElse, Param, Parameters, SmallStatement, StarArg, StarrableMatchSequenceElement,
+    Statement, String, Suite, TypeParam, TypeParamKind, UnaryOp, YieldValue,
+};
+
+/// Binding strength of an expression position, weakest first.  A node is parenthesized when
+/// it binds more weakly than the position it is written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Precedence {
+    NamedExpr,
+    Tuple,
+    Yield,
+    Test,
+    Or,
+    And,
+    Not,
+    Cmp,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shift,
+    Arith,
+    Term,
+    Factor,
+    Power,
+    Await,
+    Atom,
+}
+
+impl Precedence {
+    fn next(self) -> Self {
+        use Precedence::*;
+        match self {
+            NamedExpr => Tuple,
+            Tuple => Yield,
+            Yield => Test,
+            Test => Or,
+            Or => And,
+            And => Not,
+            Not => Cmp,
+            Cmp => BitOr,
+            BitOr => BitXor,
+            BitXor => BitAnd,
+            BitAnd => Shift,
+            Shift => Arith,
+            Arith => Term,
+            Term => Factor,
+            Factor => Power,
+            Power => Await,
+            Await | Atom => Atom,
+        }
+    }
+}
+
+impl<'a> Expression<'a> {
+    /// This expression as Python source, parenthesized so it can be written anywhere an
+    /// expression is expected, such as a default value or an annotation.
+    pub fn to_source(&self) -> std::string::String {
+        let mut unparser = Unparser::default();
+        unparser.expression(self, Precedence::Test);
+        unparser.out
+    }
+}
+
+impl<'a> Parameters<'a> {
+    /// The parameter list as written between the parentheses of a `def`, without them.
+    pub fn to_source(&self) -> std::string::String {
+        let mut unparser = Unparser::default();
+        unparser.parameters(self);
+        unparser.out
+    }
+}
+
+impl<'a> Module<'a> {
+    /// The module as Python source, indented with `default_indent`.
+    pub fn to_source(&self) -> std::string::String {
+        let mut unparser = Unparser {
+            indent: self.default_indent.to_string(),
+            ..Default::default()
+        };
+        for stmt in &self.body {
+            unparser.statement(stmt);
+        }
+        unparser.out
+    }
+}
+
+impl<'a> Statement<'a> {
+    /// The statement as Python source at the outermost indentation level, ending in a
+    /// newline.
+    pub fn to_source(&self) -> std::string::String {
+        let mut unparser = Unparser::default();
+        unparser.statement(self);
+        unparser.out
+    }
+}
+
+pub(crate) struct Unparser {
+    pub(crate) out: std::string::String,
+    /// One level of indentation.
+    indent: std::string::String,
+    /// Written at the start of every line before the indentation, to unparse a statement
+    /// that sits inside an existing block.
+    margin: std::string::String,
+    depth: usize,
+}
+
+impl Default for Unparser {
+    fn default() -> Self {
+        Self {
+            out: std::string::String::new(),
+            indent: "    ".to_string(),
+            margin: std::string::String::new(),
+            depth: 0,
+        }
+    }
+}
+
+impl Unparser {
+    pub(crate) fn with_margin(indent: &str, margin: &str) -> Self {
+        Self {
+            indent: indent.to_string(),
+            margin: margin.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn write(&mut self, text: &str) {
+        self.out.push_str(text);
+    }
+
+    fn delimit<F: FnOnce(&mut Self)>(&mut self, parens: bool, body: F) {
+        if parens {
+            self.write("(");
+        }
+        body(self);
+        if parens {
+            self.write(")");
+        }
+    }
+
+    fn comma_separated<T, F: FnMut(&mut Self, &T)>(&mut self, items: &[T], mut item: F) {
+        for (idx, value) in items.iter().enumerate() {
+            if idx > 0 {
+                self.write(", ");
+            }
+            item(self, value);
+        }
+    }
+
+    pub(crate) fn expression(&mut self, expr: &Expression, prec: Precedence) {
+        match expr {
+            Expression::Name(n) => self.write(n.value),
+            Expression::Ellipsis(_) => self.write("..."),
+            Expression::Integer(i) => self.write(i.value),
+            Expression::Float(f) => self.write(f.value),
+            Expression::Binary(b) => self.write(b.value),
+            Expression::Hexidecimal(h) => self.write(h.value),
+            Expression::Imaginary(i) => self.write(i.value),
+            Expression::Comparison(c) => self.delimit(prec > Precedence::Cmp, |u| {
+                u.expression(&c.left, Precedence::BitOr);
+                for target in &c.comparisons {
+                    u.write(comparison_operator(&target.operator));
+                    u.expression(&target.comparator, Precedence::BitOr);
+                }
+            }),
+            Expression::UnaryOperation(op) => {
+                let (text, op_prec) = match op.operator {
+                    UnaryOp::Not => ("not ", Precedence::Not),
+                    UnaryOp::Plus => ("+", Precedence::Factor),
+                    UnaryOp::Minus => ("-", Precedence::Factor),
+                    UnaryOp::BitInvert => ("~", Precedence::Factor),
+                };
+                self.delimit(prec > op_prec, |u| {
+                    u.write(text);
+                    u.expression(&op.expression, op_prec);
+                })
+            }
+            Expression::BinaryOperation(op) => {
+                let (text, op_prec) = binary_operator(&op.operator);
+                // `**` is the one right associative operator.
+                let (left, right) = if op.operator == BinaryOp::Power {
+                    (op_prec.next(), op_prec)
+                } else {
+                    (op_prec, op_prec.next())
+                };
+                self.delimit(prec > op_prec, |u| {
+                    u.expression(&op.left, left);
+                    u.write(text);
+                    u.expression(&op.right, right);
+                })
+            }
+            Expression::BooleanOperation(op) => {
+                let (text, op_prec) = match op.operator {
+                    BooleanOp::And => (" and ", Precedence::And),
+                    BooleanOp::Or => (" or ", Precedence::Or),
+                };
+                self.delimit(prec > op_prec, |u| {
+                    u.expression(&op.left, op_prec);
+                    u.write(text);
+                    u.expression(&op.right, op_prec.next());
+                })
+            }
+            Expression::Attribute(a) => {
+                self.expression(&a.value, Precedence::Atom);
+                // `1.real` would lex as a float.
+                if matches!(a.value.as_ref(), Expression::Integer(_)) {
+                    self.write(" ");
+                }
+                self.write(".");
+                self.write(a.attr.value);
+            }
+            Expression::Tuple(t) => self.tuple(&t.elements, prec),
+            Expression::Call(c) => {
+                self.expression(&c.func, Precedence::Atom);
+                self.write("(");
+                self.comma_separated(&c.args, |u, arg| u.arg(arg));
+                self.write(")");
+            }
+            Expression::GeneratorExp(g) => {
+                self.write("(");
+                self.expression(&g.elt, Precedence::Test);
+                self.comp_for(&g.for_in);
+                self.write(")");
+            }
+            Expression::ListComp(l) => {
+                self.write("[");
+                self.expression(&l.elt, Precedence::Test);
+                self.comp_for(&l.for_in);
+                self.write("]");
+            }
+            Expression::SetComp(s) => {
+                self.write("{");
+                self.expression(&s.elt, Precedence::Test);
+                self.comp_for(&s.for_in);
+                self.write("}");
+            }
+            Expression::DictComp(d) => {
+                self.write("{");
+                self.expression(&d.key, Precedence::Test);
+                self.write(": ");
+                self.expression(&d.value, Precedence::Test);
+                self.comp_for(&d.for_in);
+                self.write("}");
+            }
+            Expression::List(l) => {
+                self.write("[");
+                self.comma_separated(&l.elements, |u, element| u.element(element));
+                self.write("]");
+            }
+            Expression::Set(s) if s.elements.is_empty() => self.write("{*()}"),
+            Expression::Set(s) => {
+                self.write("{");
+                self.comma_separated(&s.elements, |u, element| u.element(element));
+                self.write("}");
+            }
+            Expression::Dict(d) => {
+                self.write("{");
+                self.comma_separated(&d.elements, |u, element| match element {
+                    DictElement::Simple { key, value } => {
+                        u.expression(key, Precedence::Test);
+                        u.write(": ");
+                        u.expression(value, Precedence::Test);
+                    }
+                    DictElement::Starred(s) => {
+                        u.write("**");
+                        u.expression(&s.value, Precedence::BitOr);
+                    }
+                });
+                self.write("}");
+            }
+            Expression::Subscript(s) => {
+                self.expression(&s.value, Precedence::Atom);
+                self.write("[");
+                self.comma_separated(&s.slice, |u, element| match &element.slice {
+                    BaseSlice::Index(index) => u.expression(&index.value, Precedence::Tuple),
+                    BaseSlice::Slice(slice) => {
+                        if let Some(lower) = &slice.lower {
+                            u.expression(lower, Precedence::Test);
+                        }
+                        u.write(":");
+                        if let Some(upper) = &slice.upper {
+                            u.expression(upper, Precedence::Test);
+                        }
+                        if let Some(step) = &slice.step {
+                            u.write(":");
+                            u.expression(step, Precedence::Test);
+                        }
+                    }
+                });
+                self.write("]");
+            }
+            Expression::StarredElement(s) => {
+                self.write("*");
+                self.expression(&s.value, Precedence::BitOr);
+            }
+            Expression::IfExp(i) => self.delimit(prec > Precedence::Test, |u| {
+                u.expression(&i.body, Precedence::Or);
+                u.write(" if ");
+                u.expression(&i.test, Precedence::Or);
+                u.write(" else ");
+                u.expression(&i.orelse, Precedence::Test);
+            }),
+            Expression::Lambda(l) => self.delimit(prec > Precedence::Test, |u| {
+                u.write("lambda");
+                if !l.params.is_empty() {
+                    u.write(" ");
+                    u.parameters(&l.params);
+                }
+                u.write(": ");
+                u.expression(&l.body, Precedence::Test);
+            }),
+            Expression::Yield(y) => self.delimit(prec > Precedence::Yield, |u| {
+                u.write("yield");
+                match y.value.as_deref() {
+                    Some(YieldValue::Expression(value)) => {
+                        u.write(" ");
+                        u.expression(value, Precedence::Tuple);
+                    }
+                    Some(YieldValue::From(from)) => {
+                        u.write(" from ");
+                        u.expression(&from.item, Precedence::Test);
+                    }
+                    None => {}
+                }
+            }),
+            Expression::Await(a) => self.delimit(prec > Precedence::Await, |u| {
+                u.write("await ");
+                u.expression(&a.expression, Precedence::Atom);
+            }),
+            Expression::SimpleString(s) => self.write(s.value),
+            Expression::ConcatenatedString(c) => {
+                self.string(&c.left);
+                self.write(" ");
+                self.string(&c.right);
+            }
+            Expression::FormattedString(f) => {
+                self.write(f.start);
+                self.fstring_parts(&f.parts);
+                self.write(f.end);
+            }
+            Expression::NamedExpr(n) => self.delimit(prec > Precedence::NamedExpr, |u| {
+                u.expression(&n.target, Precedence::Atom);
+                u.write(" := ");
+                u.expression(&n.value, Precedence::Test);
+            }),
+            Expression::Constant(c) => self.constant(&c.value, prec),
+        }
+    }
+
+    fn tuple(&mut self, elements: &[Element], prec: Precedence) {
+        // `x = 1,` is legal but easy to misread, so one-element tuples always get parentheses.
+        let parens = elements.len() < 2 || prec > Precedence::Tuple;
+        self.delimit(parens, |u| {
+            u.comma_separated(elements, |u, element| u.element(element));
+            if elements.len() == 1 {
+                u.write(",");
+            }
+        });
+    }
+
+    fn element(&mut self, element: &Element) {
+        match element {
+            Element::Simple { value } => self.expression(value, Precedence::Test),
+            Element::Starred(s) => {
+                self.write("*");
+                self.expression(&s.value, Precedence::BitOr);
+            }
+        }
+    }
+
+    fn arg(&mut self, arg: &Arg) {
+        self.write(arg.star);
+        if let Some(keyword) = &arg.keyword {
+            self.write(keyword.value);
+            self.write("=");
+        }
+        let prec = if arg.star.is_empty() { Precedence::Test } else { Precedence::BitOr };
+        self.expression(&arg.value, prec);
+    }
+
+    fn comp_for(&mut self, comp: &CompFor) {
+        if comp.asynchronous.is_some() {
+            self.write(" async");
+        }
+        self.write(" for ");
+        let target: Expression = comp.target.clone().into();
+        self.expression(&target, Precedence::Tuple);
+        self.write(" in ");
+        self.expression(&comp.iter, Precedence::Or);
+        for cond in &comp.ifs {
+            self.write(" if ");
+            self.expression(&cond.test, Precedence::Or);
+        }
+        if let Some(inner) = &comp.inner_for_in {
+            self.comp_for(inner);
+        }
+    }
+
+    fn string(&mut self, s: &String) {
+        let expr: Expression = s.clone().into();
+        self.expression(&expr, Precedence::Atom);
+    }
+
+    fn fstring_parts(&mut self, parts: &[FormattedStringContent]) {
+        for part in parts {
+            match part {
+                FormattedStringContent::Text(t) => self.write(t.value),
+                FormattedStringContent::Expression(e) => {
+                    self.write("{");
+                    let start = self.out.len();
+                    self.expression(&e.expression, Precedence::Test);
+                    // `{{` would be read back as an escaped brace.
+                    if self.out[start..].starts_with('{') {
+                        self.out.insert(start, ' ');
+                    }
+                    if e.equal.is_some() {
+                        self.write("=");
+                    }
+                    if let Some(conversion) = e.conversion {
+                        self.write("!");
+                        self.write(conversion);
+                    }
+                    if let Some(spec) = &e.format_spec {
+                        self.write(":");
+                        self.fstring_parts(spec);
+                    }
+                    self.write("}");
+                }
+            }
+        }
+    }
+
+    pub(crate) fn parameters(&mut self, params: &Parameters) {
+        let mut first = true;
+        let mut sep = |u: &mut Self| {
+            if !first {
+                u.write(", ");
+            }
+            first = false;
+        };
+        for param in &params.posonly_params {
+            sep(self);
+            self.param(param);
+        }
+        if params.posonly_ind.is_some() || !params.posonly_params.is_empty() {
+            sep(self);
+            self.write("/");
+        }
+        for param in &params.params {
+            sep(self);
+            self.param(param);
+        }
+        match &params.star_arg {
+            Some(StarArg::Star(_)) => {
+                sep(self);
+                self.write("*");
+            }
+            Some(StarArg::Param(param)) => {
+                sep(self);
+                self.write("*");
+                self.param(param);
+            }
+            None => {}
+        }
+        for param in &params.kwonly_params {
+            sep(self);
+            self.param(param);
+        }
+        if let Some(param) = &params.star_kwarg {
+            sep(self);
+            self.write("**");
+            self.param(param);
+        }
+    }
+
+    fn param(&mut self, param: &Param) {
+        self.write(param.name.value);
+        if let Some(annotation) = &param.annotation {
+            self.write(": ");
+            self.expression(&annotation.annotation, Precedence::Test);
+        }
+        if let Some(default) = &param.default {
+            self.write(if param.annotation.is_some() { " = " } else { "=" });
+            self.expression(default, Precedence::Test);
+        }
+    }
+
+    /// `[T: int, *Ts, **P]`, or nothing if there are no type parameters.
+    fn type_params(&mut self, params: &[TypeParam]) {
+        if params.is_empty() {
+            return;
+        }
+        self.write("[");
+        self.comma_separated(params, |u, param| {
+            match &param.kind {
+                TypeParamKind::TypeVar { .. } => {}
+                TypeParamKind::TypeVarTuple => u.write("*"),
+                TypeParamKind::ParamSpec => u.write("**"),
+            }
+            u.write(param.name.value);
+            if let TypeParamKind::TypeVar { bound: Some(bound) } = &param.kind {
+                u.write(": ");
+                u.expression(bound, Precedence::Test);
+            }
+            if let Some(default) = &param.default {
+                u.write(" = ");
+                u.expression(default, Precedence::Test);
+            }
+        });
+        self.write("]");
+    }
+
+    fn start_line(&mut self) {
+        self.out.push_str(&self.margin);
+        for _ in 0..self.depth {
+            self.out.push_str(&self.indent);
+        }
+    }
+
+    pub(crate) fn statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Simple(line) => {
+                self.start_line();
+                self.small_statements(&line.body);
+                self.write("\n");
+            }
+            Statement::Compound(compound) => self.compound_statement(compound),
+            // The recovering parser keeps no more of a statement that failed than its span.
+            Statement::Error(_) => {}
+        }
+    }
+
+    fn small_statements(&mut self, body: &[SmallStatement]) {
+        for (idx, stmt) in body.iter().enumerate() {
+            if idx > 0 {
+                self.write("; ");
+            }
+            self.small_statement(stmt);
+        }
+    }
+
+    fn small_statement(&mut self, stmt: &SmallStatement) {
+        match stmt {
+            SmallStatement::Pass => self.write("pass"),
+            SmallStatement::Break(_) => self.write("break"),
+            SmallStatement::Continue(_) => self.write("continue"),
+            SmallStatement::Return(r) => {
+                self.write("return");
+                if let Some(value) = &r.value {
+                    self.write(" ");
+                    self.expression(value, Precedence::Tuple);
+                }
+            }
+            SmallStatement::Expr(e) => self.expression(&e.value, Precedence::Tuple),
+            SmallStatement::Assert(a) => {
+                self.write("assert ");
+                self.expression(&a.test, Precedence::Test);
+                if let Some(msg) = &a.msg {
+                    self.write(", ");
+                    self.expression(msg, Precedence::Test);
+                }
+            }
+            SmallStatement::Import(i) => {
+                self.write("import ");
+                self.comma_separated(&i.names, |u, alias| u.import_alias(alias));
+            }
+            SmallStatement::ImportFrom(i) => {
+                self.write("from ");
+                for _ in &i.relative {
+                    self.write(".");
+                }
+                if let Some(module) = &i.module {
+                    let module: Expression = module.clone().into();
+                    self.expression(&module, Precedence::Atom);
+                }
+                self.write(" import ");
+                match &i.names {
+                    ImportNames::Star(_) => self.write("*"),
+                    ImportNames::Aliases(aliases) => self.comma_separated(aliases, |u, alias| u.import_alias(alias)),
+                }
+            }
+            SmallStatement::Assign(a) => {
+                for target in &a.targets {
+                    let target: Expression = target.target.clone().into();
+                    self.expression(&target, Precedence::Tuple);
+                    self.write(" = ");
+                }
+                self.expression(&a.value, Precedence::Tuple);
+            }
+            SmallStatement::AnnAssign(a) => {
+                let target: Expression = a.target.clone().into();
+                self.expression(&target, Precedence::Atom);
+                self.write(": ");
+                self.expression(&a.annotation.annotation, Precedence::Test);
+                if let Some(value) = &a.value {
+                    self.write(" = ");
+                    self.expression(value, Precedence::Tuple);
+                }
+            }
+            SmallStatement::Raise(r) => {
+                self.write("raise");
+                if let Some(exc) = &r.exc {
+                    self.write(" ");
+                    self.expression(exc, Precedence::Test);
+                }
+                if let Some(cause) = &r.cause {
+                    self.write(" from ");
+                    self.expression(&cause.item, Precedence::Test);
+                }
+            }
+            SmallStatement::Global(g) => {
+                self.write("global ");
+                self.names(&g.names);
+            }
+            SmallStatement::Nonlocal(n) => {
+                self.write("nonlocal ");
+                self.names(&n.names);
+            }
+            SmallStatement::AugAssign(a) => {
+                let target: Expression = a.target.clone().into();
+                self.expression(&target, Precedence::Tuple);
+                self.write(augmented_operator(&a.operator));
+                self.expression(&a.value, Precedence::Tuple);
+            }
+            SmallStatement::Del(d) => {
+                self.write("del ");
+                let target: Expression = d.target.clone().into();
+                self.expression(&target, Precedence::Tuple);
+            }
+            SmallStatement::TypeAlias(t) => {
+                self.write("type ");
+                self.write(t.name.value);
+                self.type_params(&t.type_params);
+                self.write(" = ");
+                self.expression(&t.value, Precedence::Test);
+            }
+        }
+    }
+
+    fn names(&mut self, names: &[NameItem]) {
+        self.comma_separated(names, |u, item| u.write(item.name.value));
+    }
+
+    fn import_alias(&mut self, alias: &ImportAlias) {
+        let name: Expression = alias.name.clone().into();
+        self.expression(&name, Precedence::Atom);
+        self.as_name(&alias.asname);
+    }
+
+    fn as_name(&mut self, asname: &Option<AsName>) {
+        if let Some(asname) = asname {
+            self.write(" as ");
+            let target: Expression = asname.name.clone().into();
+            self.expression(&target, Precedence::Atom);
+        }
+    }
+
+    fn decorators(&mut self, decorators: &[Decorator]) {
+        for decorator in decorators {
+            self.start_line();
+            self.write("@");
+            self.expression(&decorator.decorator, Precedence::NamedExpr);
+            self.write("\n");
+        }
+    }
+
+    fn compound_statement(&mut self, stmt: &CompoundStatement) {
+        match stmt {
+            CompoundStatement::FunctionDef(f) => {
+                self.decorators(&f.decorators);
+                self.start_line();
+                if f.asynchronous.is_some() {
+                    self.write("async ");
+                }
+                self.write("def ");
+                self.write(f.name.value);
+                self.type_params(&f.type_params);
+                self.write("(");
+                self.parameters(&f.params);
+                self.write(")");
+                if let Some(returns) = &f.returns {
+                    self.write(" -> ");
+                    self.expression(&returns.annotation, Precedence::Test);
+                }
+                self.suite(&f.body);
+            }
+            CompoundStatement::ClassDef(c) => {
+                self.decorators(&c.decorators);
+                self.start_line();
+                self.write("class ");
+                self.write(c.name.value);
+                self.type_params(&c.type_params);
+                if !c.bases.is_empty() || !c.keywords.is_empty() {
+                    self.write("(");
+                    let args: Vec<&Arg> = c.bases.iter().chain(c.keywords.iter()).collect();
+                    self.comma_separated(&args, |u, arg| u.arg(arg));
+                    self.write(")");
+                }
+                self.suite(&c.body);
+            }
+            CompoundStatement::If(i) => self.if_statement(i, "if "),
+            CompoundStatement::For(f) => {
+                self.start_line();
+                if f.asynchronous.is_some() {
+                    self.write("async ");
+                }
+                self.write("for ");
+                let target: Expression = f.target.clone().into();
+                self.expression(&target, Precedence::Tuple);
+                self.write(" in ");
+                self.expression(&f.iter, Precedence::Tuple);
+                self.suite(&f.body);
+                self.else_clause(&f.orelse);
+            }
+            CompoundStatement::While(w) => {
+                self.start_line();
+                self.write("while ");
+                self.expression(&w.test, Precedence::NamedExpr);
+                self.suite(&w.body);
+                self.else_clause(&w.orelse);
+            }
+            CompoundStatement::Try(t) => {
+                self.start_line();
+                self.write("try");
+                self.suite(&t.body);
+                for handler in &t.handlers {
+                    self.start_line();
+                    self.write("except");
+                    if let Some(kind) = &handler.r#type {
+                        self.write(" ");
+                        self.expression(kind, Precedence::Test);
+                    }
+                    self.as_name(&handler.name);
+                    self.suite(&handler.body);
+                }
+                self.else_clause(&t.orelse);
+                self.finally_clause(&t.finalbody);
+            }
+            CompoundStatement::TryStar(t) => {
+                self.start_line();
+                self.write("try");
+                self.suite(&t.body);
+                for handler in &t.handlers {
+                    self.start_line();
+                    self.write("except* ");
+                    self.expression(&handler.r#type, Precedence::Test);
+                    self.as_name(&handler.name);
+                    self.suite(&handler.body);
+                }
+                self.else_clause(&t.orelse);
+                self.finally_clause(&t.finalbody);
+            }
+            CompoundStatement::With(w) => {
+                self.start_line();
+                if w.asynchronous.is_some() {
+                    self.write("async ");
+                }
+                self.write("with ");
+                self.comma_separated(&w.items, |u, item| {
+                    u.expression(&item.item, Precedence::Test);
+                    u.as_name(&item.asname);
+                });
+                self.suite(&w.body);
+            }
+            CompoundStatement::Match(m) => {
+                self.start_line();
+                self.write("match ");
+                self.expression(&m.subject, Precedence::Tuple);
+                self.write(":\n");
+                self.depth += 1;
+                for case in &m.cases {
+                    self.start_line();
+                    self.write("case ");
+                    self.pattern(&case.pattern, false);
+                    if let Some(guard) = &case.guard {
+                        self.write(" if ");
+                        self.expression(guard, Precedence::NamedExpr);
+                    }
+                    self.suite(&case.body);
+                }
+                self.depth -= 1;
+            }
+        }
+    }
+
+    fn if_statement(&mut self, stmt: &If, keyword: &str) {
+        self.start_line();
+        self.write(keyword);
+        self.expression(&stmt.test, Precedence::NamedExpr);
+        self.suite(&stmt.body);
+        match stmt.orelse.as_deref() {
+            Some(OrElse::Elif(elif)) => self.if_statement(elif, "elif "),
+            Some(OrElse::Else(orelse)) => self.else_clause(&Some(orelse.clone())),
+            None => {}
+        }
+    }
+
+    fn else_clause(&mut self, orelse: &Option<Else>) {
+        if let Some(orelse) = orelse {
+            self.start_line();
+            self.write("else");
+            self.suite(&orelse.body);
+        }
+    }
+
+    fn finally_clause(&mut self, finalbody: &Option<Finally>) {
+        if let Some(finalbody) = finalbody {
+            self.start_line();
+            self.write("finally");
+            self.suite(&finalbody.body);
+        }
+    }
+
+    /// Writes the `:` ending a clause header and the block under it.
+    fn suite(&mut self, suite: &Suite) {
+        match suite {
+            Suite::IndentedBlock(block) => {
+                self.write(":\n");
+                self.depth += 1;
+                if block.body.is_empty() {
+                    self.start_line();
+                    self.write("pass\n");
+                }
+                for stmt in &block.body {
+                    self.statement(stmt);
+                }
+                self.depth -= 1;
+            }
+            Suite::SimpleStatementSuite(suite) => {
+                self.write(": ");
+                self.small_statements(&suite.body);
+                self.write("\n");
+            }
+        }
+    }
+
+    /// `nested` is set inside `|` alternatives, where an `as` pattern needs parentheses.
+    fn pattern(&mut self, pattern: &MatchPattern, nested: bool) {
+        match pattern {
+            MatchPattern::Value(v) => self.expression(&v.value, Precedence::Test),
+            MatchPattern::Singleton(s) => self.write(s.value.value),
+            MatchPattern::Sequence(MatchSequence::MatchList(l)) => {
+                self.write("[");
+                self.comma_separated(&l.patterns, |u, element| u.sequence_pattern(element));
+                self.write("]");
+            }
+            MatchPattern::Sequence(MatchSequence::MatchTuple(t)) => {
+                self.write("(");
+                self.comma_separated(&t.patterns, |u, element| u.sequence_pattern(element));
+                if t.patterns.len() == 1 {
+                    self.write(",");
+                }
+                self.write(")");
+            }
+            MatchPattern::Mapping(m) => {
+                self.write("{");
+                self.comma_separated(&m.elements, |u, element| {
+                    u.expression(&element.key, Precedence::Test);
+                    u.write(": ");
+                    u.pattern(&element.pattern, false);
+                });
+                if let Some(rest) = &m.rest {
+                    if !m.elements.is_empty() {
+                        self.write(", ");
+                    }
+                    self.write("**");
+                    self.write(rest.value);
+                }
+                self.write("}");
+            }
+            MatchPattern::Class(c) => {
+                let cls: Expression = c.cls.clone().into();
+                self.expression(&cls, Precedence::Atom);
+                self.write("(");
+                self.comma_separated(&c.patterns, |u, element| u.pattern(&element.value, false));
+                if !c.patterns.is_empty() && !c.kwds.is_empty() {
+                    self.write(", ");
+                }
+                self.comma_separated(&c.kwds, |u, keyword| {
+                    u.write(keyword.key.value);
+                    u.write("=");
+                    u.pattern(&keyword.pattern, false);
+                });
+                self.write(")");
+            }
+            MatchPattern::As(a) => match (&a.pattern, &a.name) {
+                (None, Some(name)) => self.write(name.value),
+                (None, None) => self.write("_"),
+                (Some(inner), name) => self.delimit(nested, |u| {
+                    u.pattern(inner, false);
+                    u.write(" as ");
+                    u.write(name.as_ref().map_or("_", |name| name.value));
+                }),
+            },
+            MatchPattern::Or(o) => self.delimit(nested, |u| {
+                for (idx, element) in o.patterns.iter().enumerate() {
+                    if idx > 0 {
+                        u.write(" | ");
+                    }
+                    u.pattern(&element.pattern, true);
+                }
+            }),
+        }
+    }
+
+    fn sequence_pattern(&mut self, element: &StarrableMatchSequenceElement) {
+        match element {
+            StarrableMatchSequenceElement::Simple(s) => self.pattern(&s.value, false),
+            StarrableMatchSequenceElement::Starred(s) => {
+                self.write("*");
+                self.write(s.name.as_ref().map_or("_", |name| name.value));
+            }
+        }
+    }
+
+    fn constant(&mut self, value: &ConstantValue, prec: Precedence) {
+        match value {
+            ConstantValue::None => self.write("None"),
+            ConstantValue::Ellipsis => self.write("..."),
+            ConstantValue::Bool(true) => self.write("True"),
+            ConstantValue::Bool(false) => self.write("False"),
+            ConstantValue::Int(i) => {
+                let text = i.to_string();
+                self.delimit(i.is_negative() && prec > Precedence::Factor, |u| u.write(&text));
+            }
+            ConstantValue::Float(f) => {
+                let text = float_source(*f);
+                self.delimit(text.starts_with('-') && prec > Precedence::Factor, |u| u.write(&text));
+            }
+            ConstantValue::Complex { real, imag } => {
+                let imag = format!("{}j", float_source(*imag));
+                if *real == 0.0 && real.is_sign_positive() {
+                    self.delimit(imag.starts_with('-') && prec > Precedence::Factor, |u| u.write(&imag));
+                } else {
+                    self.delimit(prec > Precedence::Arith, |u| {
+                        u.write(&float_source(*real));
+                        u.write(" + ");
+                        u.write(&imag);
+                    });
+                }
+            }
+            ConstantValue::Str(s) => self.write(&str_repr(s)),
+            ConstantValue::Bytes(b) => self.write(&bytes_repr(b)),
+            ConstantValue::Tuple(items) => self.delimit(items.is_empty() || prec > Precedence::Tuple, |u| {
+                u.comma_separated(items, |u, item| u.constant(item, Precedence::Test));
+                if items.len() == 1 {
+                    u.write(",");
+                }
+            }),
+            ConstantValue::FrozenSet(items) => {
+                self.write("frozenset(");
+                if !items.is_empty() {
+                    self.write("{");
+                    self.comma_separated(items, |u, item| u.constant(item, Precedence::Test));
+                    self.write("}");
+                }
+                self.write(")");
+            }
+        }
+    }
+}
+
+fn comparison_operator(op: &CompOp) -> &'static str {
+    match op {
+        CompOp::LessThan => " < ",
+        CompOp::GreaterThan => " > ",
+        CompOp::LessThanEqual => " <= ",
+        CompOp::GreaterThanEqual => " >= ",
+        CompOp::Equal => " == ",
+        CompOp::NotEqual => " != ",
+        CompOp::In => " in ",
+        CompOp::NotIn => " not in ",
+        CompOp::Is => " is ",
+        CompOp::IsNot => " is not ",
+    }
+}
+
+fn augmented_operator(op: &AugOp) -> &'static str {
+    match op {
+        AugOp::AddAssign => " += ",
+        AugOp::SubtractAssign => " -= ",
+        AugOp::MultiplyAssign => " *= ",
+        AugOp::MatrixMultiplyAssign => " @= ",
+        AugOp::DivideAssign => " /= ",
+        AugOp::ModuloAssign => " %= ",
+        AugOp::BitAndAssign => " &= ",
+        AugOp::BitOrAssign => " |= ",
+        AugOp::BitXorAssign => " ^= ",
+        AugOp::LeftShiftAssign => " <<= ",
+        AugOp::RightShiftAssign => " >>= ",
+        AugOp::PowerAssign => " **= ",
+        AugOp::FloorDivideAssign => " //= ",
+    }
+}
+
+fn binary_operator(op: &BinaryOp) -> (&'static str, Precedence) {
+    match op {
+        BinaryOp::Add => (" + ", Precedence::Arith),
+        BinaryOp::Subtract => (" - ", Precedence::Arith),
+        BinaryOp::Multiply => (" * ", Precedence::Term),
+        BinaryOp::Divide => (" / ", Precedence::Term),
+        BinaryOp::FloorDivide => (" // ", Precedence::Term),
+        BinaryOp::Modulo => (" % ", Precedence::Term),
+        BinaryOp::MatrixMultiply => (" @ ", Precedence::Term),
+        BinaryOp::Power => (" ** ", Precedence::Power),
+        BinaryOp::LeftShift => (" << ", Precedence::Shift),
+        BinaryOp::RightShift => (" >> ", Precedence::Shift),
+        BinaryOp::BitOr => (" | ", Precedence::BitOr),
+        BinaryOp::BitXor => (" ^ ", Precedence::BitXor),
+        BinaryOp::BitAnd => (" & ", Precedence::BitAnd),
+    }
+}
+
+/// A float literal that reads back as the same value.  Infinities overflow to `inf` the
+/// same way `ast.unparse` writes them.
+fn float_source(value: f64) -> std::string::String {
+    if value.is_nan() {
+        "(1e309 - 1e309)".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "1e309" } else { "-1e309" }.to_string()
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+/// Python's `repr` of a str.
+pub(crate) fn str_repr(s: &str) -> std::string::String {
+    let quote = if s.contains('\'') && !s.contains('"') { '"' } else { '\'' };
+    let mut out = std::string::String::with_capacity(s.len() + 2);
+    out.push(quote);
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c == quote => {
+                out.push('\\');
+                out.push(c);
+            }
+            c if (c as u32) < 0x20 || c as u32 == 0x7f => {
+                let _ = write!(out, "\\x{:02x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push(quote);
+    out
+}
+
+/// Python's `repr` of a bytes object.
+pub(crate) fn bytes_repr(b: &[u8]) -> std::string::String {
+    let quote = if b.contains(&b'\'') && !b.contains(&b'"') { b'"' } else { b'\'' };
+    let mut out = std::string::String::with_capacity(b.len() + 3);
+    out.push('b');
+    out.push(quote as char);
+    for &byte in b {
+        match byte {
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            byte if byte == quote => {
+                out.push('\\');
+                out.push(byte as char);
+            }
+            0x20..=0x7e => out.push(byte as char),
+            byte => {
+                let _ = write!(out, "\\x{:02x}", byte);
+            }
+        }
+    }
+    out.push(quote as char);
+    out
+}
@@ -0,0 +1,558 @@
+use super::error::SyntaxError;
+use super::expression::*;
+use super::module::Module;
+use super::span::Span;
+use super::statement::*;
+use super::visitor::{self, Visitor};
+
+impl<'a> Module<'a> {
+    /// Reports the errors CPython raises after parsing, e.g. `break` outside of a loop or
+    /// assigning to a call.  The grammar accepts all of these, so a module is only valid
+    /// Python once this returns an empty list.
+    pub fn validate(&self) -> Vec<SyntaxError> {
+        let mut validator = Validator::default();
+        validator.visit_module(self);
+        validator.errors
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ScopeKind {
+    Module,
+    Class,
+    Function { is_async: bool },
+    Lambda,
+}
+
+struct Scope<'a> {
+    kind: ScopeKind,
+    params: Vec<&'a str>,
+}
+
+struct Validator<'a> {
+    errors: Vec<SyntaxError>,
+    scopes: Vec<Scope<'a>>,
+    in_loop: bool,
+    /// Set while inside the body of a comprehension, to the name CPython uses for it.
+    comprehension: Option<&'static str>,
+}
+
+impl<'a> Default for Validator<'a> {
+    fn default() -> Self {
+        Self {
+            errors: vec![],
+            scopes: vec![Scope { kind: ScopeKind::Module, params: vec![] }],
+            in_loop: false,
+            comprehension: None,
+        }
+    }
+}
+
+impl<'a> Validator<'a> {
+    /// Reports an error at `span`, the span of the node that is wrong, as CPython does.
+    fn error(&mut self, span: Span, message: impl Into<std::string::String>) {
+        self.errors.push(SyntaxError::new(message, span));
+    }
+
+    fn scope(&self) -> &Scope<'a> {
+        self.scopes.last().expect("module scope")
+    }
+
+    fn in_async_function(&self) -> bool {
+        self.scope().kind == ScopeKind::Function { is_async: true }
+    }
+
+    /// Runs `body` in a fresh function or class scope, where loops and comprehensions of the
+    /// enclosing code no longer apply.
+    fn with_scope(&mut self, kind: ScopeKind, params: Vec<&'a str>, body: impl FnOnce(&mut Self)) {
+        let in_loop = std::mem::replace(&mut self.in_loop, false);
+        let comprehension = self.comprehension.take();
+        self.scopes.push(Scope { kind, params });
+        body(self);
+        self.scopes.pop();
+        self.in_loop = in_loop;
+        self.comprehension = comprehension;
+    }
+
+    fn check_params(&mut self, params: &Parameters<'a>) -> Vec<&'a str> {
+        let mut names: Vec<&'a str> = vec![];
+        for param in all_params(params) {
+            let name = param.name.value;
+            if names.contains(&name) {
+                self.error(param.name.span, format!("duplicate argument '{}' in function definition", name));
+            } else {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    fn check_keywords<'r>(&mut self, args: impl IntoIterator<Item = &'r Arg<'a>>)
+    where
+        'a: 'r,
+    {
+        let mut names: Vec<&'a str> = vec![];
+        for arg in args {
+            let Some(keyword) = &arg.keyword else { continue };
+            if names.contains(&keyword.value) {
+                let span = Span::new(keyword.span.start, arg.value.span().end);
+                self.error(span, format!("keyword argument repeated: {}", keyword.value));
+            } else {
+                names.push(keyword.value);
+            }
+        }
+    }
+
+    fn check_type_params(&mut self, params: &[TypeParam<'a>]) {
+        let mut names: Vec<&'a str> = vec![];
+        let mut seen_default = false;
+        for param in params {
+            let name = param.name.value;
+            if names.contains(&name) {
+                self.error(param.name.span, format!("duplicate type parameter '{}'", name));
+            }
+            names.push(name);
+            if param.default.is_some() {
+                seen_default = true;
+            } else if seen_default {
+                let message = format!("non-default type parameter '{}' follows default type parameter", name);
+                self.error(param.name.span, message);
+            }
+        }
+    }
+
+    fn check_declaration(&mut self, names: &[NameItem<'a>], declaration: &str) {
+        let scope = self.scope();
+        let conflicts: Vec<&Name<'a>> = names
+            .iter()
+            .map(|item| &item.name)
+            .filter(|name| scope.params.contains(&name.value))
+            .collect();
+        for name in conflicts {
+            self.error(name.span, format!("name '{}' is parameter and {}", name.value, declaration));
+        }
+    }
+
+    fn check_assign_target(&mut self, target: &AssignTargetExpression<'a>) {
+        match target {
+            AssignTargetExpression::Name(n) => self.check_store_name(n),
+            AssignTargetExpression::Tuple(t) => self.check_target_elements(t.span, &t.elements),
+            AssignTargetExpression::List(l) => self.check_target_elements(l.span, &l.elements),
+            AssignTargetExpression::StarredElement(s) => {
+                self.error(s.span, "starred assignment target must be in a list or tuple")
+            }
+            AssignTargetExpression::Attribute(_) | AssignTargetExpression::Subscript(_) => {}
+        }
+    }
+
+    /// Checks the elements of the tuple or list target at `span`.
+    fn check_target_elements(&mut self, span: Span, elements: &[Element<'a>]) {
+        let mut starred = 0;
+        for element in elements {
+            match element {
+                Element::Simple { value } => self.check_target_expression(value),
+                Element::Starred(s) => {
+                    starred += 1;
+                    self.check_target_expression(&s.value);
+                }
+            }
+        }
+        if starred > 1 {
+            self.error(span, "multiple starred expressions in assignment");
+        }
+    }
+
+    fn check_target_expression(&mut self, expr: &Expression<'a>) {
+        match expr {
+            Expression::Name(n) => self.check_store_name(n),
+            Expression::Attribute(_) | Expression::Subscript(_) => {}
+            Expression::Tuple(t) => self.check_target_elements(t.span, &t.elements),
+            Expression::List(l) => self.check_target_elements(l.span, &l.elements),
+            Expression::StarredElement(s) => self.error(s.span, "starred assignment target must be in a list or tuple"),
+            other => self.error(other.span(), format!("cannot assign to {}", expression_name(other))),
+        }
+    }
+
+    fn check_store_name(&mut self, name: &Name<'a>) {
+        if matches!(name.value, "True" | "False" | "None" | "__debug__") {
+            self.error(name.span, format!("cannot assign to {}", name.value));
+        }
+    }
+
+    fn check_del_target(&mut self, target: &DelTargetExpression<'a>) {
+        match target {
+            DelTargetExpression::Tuple(t) => self.check_del_elements(&t.elements),
+            DelTargetExpression::List(l) => self.check_del_elements(&l.elements),
+            DelTargetExpression::Name(n) => self.check_del_expression(&Expression::Name(n.clone())),
+            DelTargetExpression::Attribute(_) | DelTargetExpression::Subscript(_) => {}
+        }
+    }
+
+    fn check_del_elements(&mut self, elements: &[Element<'a>]) {
+        for element in elements {
+            match element {
+                Element::Simple { value } => self.check_del_expression(value),
+                Element::Starred(s) => self.error(s.span, "cannot delete starred"),
+            }
+        }
+    }
+
+    fn check_del_expression(&mut self, expr: &Expression<'a>) {
+        match expr {
+            Expression::Name(n) if matches!(n.value, "True" | "False" | "None" | "__debug__") => {
+                self.error(n.span, format!("cannot delete {}", n.value))
+            }
+            Expression::Name(_) | Expression::Attribute(_) | Expression::Subscript(_) => {}
+            Expression::Tuple(t) => self.check_del_elements(&t.elements),
+            Expression::List(l) => self.check_del_elements(&l.elements),
+            other => self.error(other.span(), format!("cannot delete {}", expression_name(other))),
+        }
+    }
+
+    /// Checks the comprehension at `span`.
+    fn visit_comprehension(&mut self, span: Span, kind: &'static str, for_in: &CompFor<'a>, elts: &[&Expression<'a>]) {
+        let is_async = comp_fors(for_in).any(|comp| comp.asynchronous.is_some());
+        if is_async && kind != "generator expression" && !self.in_async_function() {
+            self.error(span, "asynchronous comprehension outside of an asynchronous function");
+        }
+        // The outermost iterable is evaluated in the enclosing scope.
+        self.visit_expression(&for_in.iter);
+        let comprehension = self.comprehension.replace(kind);
+        self.check_assign_target(&for_in.target);
+        self.visit_assign_target(&for_in.target);
+        for comp_if in &for_in.ifs {
+            self.visit_expression(&comp_if.test);
+        }
+        if let Some(inner) = &for_in.inner_for_in {
+            self.visit_comp_for(inner);
+        }
+        for elt in elts {
+            self.visit_expression(elt);
+        }
+        self.comprehension = comprehension;
+    }
+}
+
+impl<'r, 'a> Visitor<'r, 'a> for Validator<'a> {
+    fn visit_small_statement(&mut self, node: &'r SmallStatement<'a>) {
+        match node {
+            SmallStatement::Break(b) if !self.in_loop => self.error(b.span, "'break' outside loop"),
+            SmallStatement::Continue(c) if !self.in_loop => self.error(c.span, "'continue' not properly in loop"),
+            SmallStatement::Return(r) if !matches!(self.scope().kind, ScopeKind::Function { .. }) => {
+                self.error(r.span, "'return' outside function")
+            }
+            SmallStatement::Global(g) => self.check_declaration(&g.names, "global"),
+            SmallStatement::Nonlocal(n) => {
+                if self.scope().kind == ScopeKind::Module {
+                    self.error(n.span, "nonlocal declaration not allowed at module level");
+                }
+                self.check_declaration(&n.names, "nonlocal");
+            }
+            SmallStatement::Assign(a) => {
+                for target in &a.targets {
+                    self.check_assign_target(&target.target);
+                }
+            }
+            SmallStatement::AugAssign(a) => {
+                let kind = match &a.target {
+                    AssignTargetExpression::Tuple(t) => Some(("tuple", t.span)),
+                    AssignTargetExpression::List(l) => Some(("list", l.span)),
+                    AssignTargetExpression::StarredElement(s) => Some(("starred", s.span)),
+                    _ => None,
+                };
+                if let Some((kind, span)) = kind {
+                    self.error(span, format!("'{}' is an illegal expression for augmented assignment", kind));
+                }
+            }
+            SmallStatement::AnnAssign(a) => match &a.target {
+                AssignTargetExpression::Tuple(t) => self.error(t.span, "only single target (not tuple) can be annotated"),
+                AssignTargetExpression::List(l) => self.error(l.span, "only single target (not list) can be annotated"),
+                target => self.check_assign_target(target),
+            },
+            SmallStatement::Del(d) => self.check_del_target(&d.target),
+            SmallStatement::TypeAlias(t) => self.check_type_params(&t.type_params),
+            _ => {}
+        }
+        visitor::walk_small_statement(self, node);
+    }
+
+    fn visit_compound_statement(&mut self, node: &'r CompoundStatement<'a>) {
+        match node {
+            CompoundStatement::FunctionDef(f) => {
+                for decorator in &f.decorators {
+                    self.visit_expression(&decorator.decorator);
+                }
+                self.check_type_params(&f.type_params);
+                self.visit_parameters(&f.params);
+                if let Some(returns) = &f.returns {
+                    self.visit_expression(&returns.annotation);
+                }
+                let params = self.check_params(&f.params);
+                let kind = ScopeKind::Function { is_async: f.asynchronous.is_some() };
+                self.with_scope(kind, params, |v| v.visit_suite(&f.body));
+            }
+            CompoundStatement::ClassDef(c) => {
+                for decorator in &c.decorators {
+                    self.visit_expression(&decorator.decorator);
+                }
+                self.check_type_params(&c.type_params);
+                self.check_keywords(c.bases.iter().chain(c.keywords.iter()));
+                for arg in c.bases.iter().chain(c.keywords.iter()) {
+                    self.visit_arg(arg);
+                }
+                self.with_scope(ScopeKind::Class, vec![], |v| v.visit_suite(&c.body));
+            }
+            CompoundStatement::For(f) => {
+                if f.asynchronous.is_some() && !self.in_async_function() {
+                    self.error(f.span, "'async for' outside async function");
+                }
+                self.check_assign_target(&f.target);
+                self.visit_assign_target(&f.target);
+                self.visit_expression(&f.iter);
+                self.visit_loop(&f.body, &f.orelse);
+            }
+            CompoundStatement::While(w) => {
+                self.visit_expression(&w.test);
+                self.visit_loop(&w.body, &w.orelse);
+            }
+            CompoundStatement::With(w) => {
+                if w.asynchronous.is_some() && !self.in_async_function() {
+                    self.error(w.span, "'async with' outside async function");
+                }
+                for item in &w.items {
+                    if let Some(asname) = &item.asname {
+                        self.check_assign_target(&asname.name);
+                    }
+                }
+                visitor::walk_compound_statement(self, node);
+            }
+            _ => visitor::walk_compound_statement(self, node),
+        }
+    }
+
+    fn visit_type_param(&mut self, node: &'r TypeParam<'a>) {
+        match (&node.kind, &node.default) {
+            // The one place a bare starred expression is allowed: `*Ts = *tuple[int]`.
+            (TypeParamKind::TypeVarTuple, Some(Expression::StarredElement(s))) => self.visit_expression(&s.value),
+            _ => visitor::walk_type_param(self, node),
+        }
+    }
+
+    fn visit_expression(&mut self, node: &'r Expression<'a>) {
+        match node {
+            Expression::Yield(y) => {
+                if let Some(kind) = self.comprehension {
+                    self.error(y.span, format!("'yield' inside {}", kind));
+                } else if matches!(self.scope().kind, ScopeKind::Module | ScopeKind::Class) {
+                    self.error(y.span, "'yield' outside function");
+                }
+            }
+            Expression::Await(a) => match self.scope().kind {
+                ScopeKind::Module | ScopeKind::Class => self.error(a.span, "'await' outside function"),
+                ScopeKind::Function { is_async: true } => {}
+                _ => self.error(a.span, "'await' outside async function"),
+            },
+            Expression::StarredElement(s) => self.error(s.span, "can't use starred expression here"),
+            Expression::Call(c) => self.check_keywords(&c.args),
+            Expression::NamedExpr(n) if !matches!(*n.target, Expression::Name(_)) => self.error(
+                n.target.span(),
+                format!("cannot use assignment expressions with {}", expression_name(&n.target)),
+            ),
+            Expression::ListComp(l) => {
+                return self.visit_comprehension(l.span, "list comprehension", &l.for_in, &[&l.elt])
+            }
+            Expression::SetComp(s) => {
+                return self.visit_comprehension(s.span, "set comprehension", &s.for_in, &[&s.elt])
+            }
+            Expression::DictComp(d) => {
+                return self.visit_comprehension(d.span, "dict comprehension", &d.for_in, &[&d.key, &d.value])
+            }
+            Expression::GeneratorExp(g) => {
+                return self.visit_comprehension(g.span, "generator expression", &g.for_in, &[&g.elt])
+            }
+            Expression::Lambda(l) => {
+                self.visit_parameters(&l.params);
+                let params = self.check_params(&l.params);
+                self.with_scope(ScopeKind::Lambda, params, |v| v.visit_expression(&l.body));
+                return;
+            }
+            _ => {}
+        }
+        visitor::walk_expression(self, node);
+    }
+
+    fn visit_comp_for(&mut self, node: &'r CompFor<'a>) {
+        self.check_assign_target(&node.target);
+        visitor::walk_comp_for(self, node);
+    }
+
+    fn visit_param(&mut self, node: &'r Param<'a>) {
+        // Only the annotation and default are evaluated where the function is defined.
+        if let Some(annotation) = &node.annotation {
+            self.visit_expression(&annotation.annotation);
+        }
+        if let Some(default) = &node.default {
+            self.visit_expression(default);
+        }
+    }
+}
+
+impl<'a> Validator<'a> {
+    fn visit_loop(&mut self, body: &Suite<'a>, orelse: &Option<Else<'a>>) {
+        let in_loop = std::mem::replace(&mut self.in_loop, true);
+        self.visit_suite(body);
+        self.in_loop = in_loop;
+        if let Some(orelse) = orelse {
+            self.visit_suite(&orelse.body);
+        }
+    }
+}
+
+fn all_params<'r, 'a>(params: &'r Parameters<'a>) -> impl Iterator<Item = &'r Param<'a>> {
+    let star_arg = match &params.star_arg {
+        Some(StarArg::Param(param)) => Some(param.as_ref()),
+        _ => None,
+    };
+    params
+        .posonly_params
+        .iter()
+        .chain(params.params.iter())
+        .chain(star_arg)
+        .chain(params.kwonly_params.iter())
+        .chain(params.star_kwarg.iter())
+}
+
+fn comp_fors<'r, 'a>(first: &'r CompFor<'a>) -> impl Iterator<Item = &'r CompFor<'a>> {
+    std::iter::successors(Some(first), |comp| comp.inner_for_in.as_deref())
+}
+
+/// The name CPython uses for an expression in "cannot assign to ..." style messages.
+fn expression_name(expr: &Expression) -> &'static str {
+    match expr {
+        Expression::Name(_) => "name",
+        Expression::Ellipsis(_) => "ellipsis",
+        Expression::Integer(_)
+        | Expression::Float(_)
+        | Expression::Binary(_)
+        | Expression::Hexidecimal(_)
+        | Expression::Imaginary(_)
+        | Expression::SimpleString(_)
+        | Expression::ConcatenatedString(_)
+        | Expression::Constant(_) => "literal",
+        Expression::FormattedString(_) => "f-string expression",
+        Expression::Comparison(_) => "comparison",
+        Expression::UnaryOperation(_) | Expression::BinaryOperation(_) | Expression::BooleanOperation(_) => {
+            "expression"
+        }
+        Expression::Attribute(_) => "attribute",
+        Expression::Subscript(_) => "subscript",
+        Expression::StarredElement(_) => "starred",
+        Expression::Tuple(_) => "tuple",
+        Expression::List(_) => "list",
+        Expression::Call(_) => "function call",
+        Expression::GeneratorExp(_) => "generator expression",
+        Expression::ListComp(_) => "list comprehension",
+        Expression::SetComp(_) => "set comprehension",
+        Expression::DictComp(_) => "dict comprehension",
+        Expression::Set(_) => "set display",
+        Expression::Dict(_) => "dict literal",
+        Expression::IfExp(_) => "conditional expression",
+        Expression::Lambda(_) => "lambda",
+        Expression::Yield(_) => "yield expression",
+        Expression::Await(_) => "await expression",
+        Expression::NamedExpr(_) => "named expression",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse_module, TokenArena};
+
+    /// The messages `validate` reports for `source`, with their lines.
+    fn errors(source: &str) -> Vec<(String, usize)> {
+        let arena = TokenArena::from_source(source).expect("tokenize");
+        let module = parse_module(&arena, "<test>").expect("parse");
+        module.validate().into_iter().map(|err| (err.message, err.span.start.line)).collect()
+    }
+
+    fn error(source: &str) -> String {
+        match &errors(source)[..] {
+            [(message, _)] => message.clone(),
+            other => panic!("expected one error for {source:?}, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn reports_module_level_errors_with_their_lines() {
+        let found = errors("x = 1\nreturn 1\nbreak\n");
+        assert_eq!(found, [("'return' outside function".to_string(), 2), ("'break' outside loop".to_string(), 3)]);
+    }
+
+    #[test]
+    fn reports_errors_at_the_offending_node() {
+        let source = concat!(
+            "x = [\n    (yield)\n]\n",
+            "def f(a,\n      b,\n      a):\n    x = 1; (\n        1); break\n",
+            "(a,\n *b,\n *c) = d\n",
+            "del (a,\n     __debug__)\n",
+        );
+        let found = errors(source);
+        let found: Vec<(&str, usize)> = found.iter().map(|(message, line)| (message.as_str(), *line)).collect();
+        assert_eq!(
+            found,
+            [
+                ("'yield' outside function", 2),
+                ("duplicate argument 'a' in function definition", 6),
+                ("'break' outside loop", 8),
+                ("multiple starred expressions in assignment", 9),
+                ("cannot delete __debug__", 13),
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_function_only_statements() {
+        assert_eq!(error("yield 1\n"), "'yield' outside function");
+        assert_eq!(error("class A:\n    return\n"), "'return' outside function");
+        assert_eq!(error("x = [(yield y) for y in z]\n"), "'yield' inside list comprehension");
+        assert_eq!(error("nonlocal x\n"), "nonlocal declaration not allowed at module level");
+        assert_eq!(error("await x\n"), "'await' outside function");
+        assert_eq!(error("def f():\n    await x\n"), "'await' outside async function");
+        assert_eq!(error("def f():\n    async for x in y:\n        pass\n"), "'async for' outside async function");
+        assert_eq!(error("def f():\n    async with x:\n        pass\n"), "'async with' outside async function");
+        assert_eq!(
+            error("def f():\n    return [x async for x in y]\n"),
+            "asynchronous comprehension outside of an asynchronous function"
+        );
+        assert!(errors("async def f():\n    await x\n    return [x async for x in y]\n").is_empty());
+    }
+
+    #[test]
+    fn checks_loops() {
+        assert_eq!(error("continue\n"), "'continue' not properly in loop");
+        assert_eq!(error("for x in y:\n    def f():\n        break\n"), "'break' outside loop");
+        assert!(errors("while x:\n    if y:\n        break\n    continue\nelse:\n    pass\n").is_empty());
+    }
+
+    #[test]
+    fn checks_parameters_and_declarations() {
+        assert_eq!(error("def f(a, b, a):\n    pass\n"), "duplicate argument 'a' in function definition");
+        assert_eq!(error("lambda x, *, x: 0\n"), "duplicate argument 'x' in function definition");
+        assert_eq!(error("def f(a):\n    global a\n"), "name 'a' is parameter and global");
+        assert_eq!(error("def g():\n    def f(a):\n        nonlocal a\n"), "name 'a' is parameter and nonlocal");
+        assert_eq!(error("def f[T, T](): pass\n"), "duplicate type parameter 'T'");
+        assert_eq!(error("class A[T=int, U]: pass\n"), "non-default type parameter 'U' follows default type parameter");
+        assert_eq!(error("f(a=1, a=2)\n"), "keyword argument repeated: a");
+        assert_eq!(error("class A(x, metaclass=M, metaclass=N): pass\n"), "keyword argument repeated: metaclass");
+        assert!(errors("f(a=1, **a)\n").is_empty());
+    }
+
+    #[test]
+    fn checks_targets() {
+        assert_eq!(error("*a = b\n"), "starred assignment target must be in a list or tuple");
+        assert_eq!(error("*a, *b = c\n"), "multiple starred expressions in assignment");
+        assert_eq!(error("print(*a)\nx = *a\n"), "can't use starred expression here");
+        assert_eq!(error("del __debug__\n"), "cannot delete __debug__");
+        assert_eq!(error("__debug__ = 1\n"), "cannot assign to __debug__");
+    }
+}
@@ -0,0 +1,1155 @@
+use super::expression::*;
+use super::module::Module;
+use super::statement::*;
+
+/// Read-only traversal over a parsed `Module`.
+///
+/// Every `visit_*` method defaults to the matching `walk_*` function, which visits the
+/// node's children.  Override a method to inspect a node and call the `walk_*` function
+/// from the override to keep descending, or leave it out to prune that subtree.  The nodes
+/// are borrowed for `'r`, so a visitor can keep references to them.
+pub trait Visitor<'r, 'a> {
+    fn visit_module(&mut self, node: &'r Module<'a>) {
+        walk_module(self, node);
+    }
+
+    fn visit_statement(&mut self, node: &'r Statement<'a>) {
+        walk_statement(self, node);
+    }
+
+    fn visit_small_statement(&mut self, node: &'r SmallStatement<'a>) {
+        walk_small_statement(self, node);
+    }
+
+    fn visit_compound_statement(&mut self, node: &'r CompoundStatement<'a>) {
+        walk_compound_statement(self, node);
+    }
+
+    fn visit_suite(&mut self, node: &'r Suite<'a>) {
+        walk_suite(self, node);
+    }
+
+    fn visit_expression(&mut self, node: &'r Expression<'a>) {
+        walk_expression(self, node);
+    }
+
+    fn visit_assign_target(&mut self, node: &'r AssignTargetExpression<'a>) {
+        walk_assign_target(self, node);
+    }
+
+    fn visit_del_target(&mut self, node: &'r DelTargetExpression<'a>) {
+        walk_del_target(self, node);
+    }
+
+    fn visit_parameters(&mut self, node: &'r Parameters<'a>) {
+        walk_parameters(self, node);
+    }
+
+    fn visit_param(&mut self, node: &'r Param<'a>) {
+        walk_param(self, node);
+    }
+
+    fn visit_comp_for(&mut self, node: &'r CompFor<'a>) {
+        walk_comp_for(self, node);
+    }
+
+    fn visit_arg(&mut self, node: &'r Arg<'a>) {
+        walk_arg(self, node);
+    }
+
+    fn visit_match_pattern(&mut self, node: &'r MatchPattern<'a>) {
+        walk_match_pattern(self, node);
+    }
+
+    fn visit_type_param(&mut self, node: &'r TypeParam<'a>) {
+        walk_type_param(self, node);
+    }
+
+    fn visit_name(&mut self, _node: &'r Name<'a>) {}
+}
+
+pub fn walk_module<'r, 'a, V: Visitor<'r, 'a> + ?Sized>(v: &mut V, node: &'r Module<'a>) {
+    for stmt in &node.body {
+        v.visit_statement(stmt);
+    }
+}
+
+pub fn walk_statement<'r, 'a, V: Visitor<'r, 'a> + ?Sized>(v: &mut V, node: &'r Statement<'a>) {
+    match node {
+        Statement::Simple(line) => {
+            for stmt in &line.body {
+                v.visit_small_statement(stmt);
+            }
+        }
+        Statement::Compound(stmt) => v.visit_compound_statement(stmt),
+        Statement::Error(_) => {}
+    }
+}
+
+pub fn walk_small_statement<'r, 'a, V: Visitor<'r, 'a> + ?Sized>(v: &mut V, node: &'r SmallStatement<'a>) {
+    match node {
+        SmallStatement::Pass | SmallStatement::Break(_) | SmallStatement::Continue(_) => {}
+        SmallStatement::Return(r) => {
+            if let Some(value) = &r.value {
+                v.visit_expression(value);
+            }
+        }
+        SmallStatement::Expr(e) => v.visit_expression(&e.value),
+        SmallStatement::Assert(a) => {
+            v.visit_expression(&a.test);
+            if let Some(msg) = &a.msg {
+                v.visit_expression(msg);
+            }
+        }
+        SmallStatement::Import(i) => {
+            for alias in &i.names {
+                walk_import_alias(v, alias);
+            }
+        }
+        SmallStatement::ImportFrom(i) => {
+            if let Some(module) = &i.module {
+                walk_name_or_attribute(v, module);
+            }
+            if let ImportNames::Aliases(aliases) = &i.names {
+                for alias in aliases {
+                    walk_import_alias(v, alias);
+                }
+            }
+        }
+        SmallStatement::Assign(a) => {
+            for target in &a.targets {
+                v.visit_assign_target(&target.target);
+            }
+            v.visit_expression(&a.value);
+        }
+        SmallStatement::AnnAssign(a) => {
+            v.visit_assign_target(&a.target);
+            v.visit_expression(&a.annotation.annotation);
+            if let Some(value) = &a.value {
+                v.visit_expression(value);
+            }
+        }
+        SmallStatement::Raise(r) => {
+            if let Some(exc) = &r.exc {
+                v.visit_expression(exc);
+            }
+            if let Some(cause) = &r.cause {
+                v.visit_expression(&cause.item);
+            }
+        }
+        SmallStatement::Global(g) => {
+            for item in &g.names {
+                v.visit_name(&item.name);
+            }
+        }
+        SmallStatement::Nonlocal(n) => {
+            for item in &n.names {
+                v.visit_name(&item.name);
+            }
+        }
+        SmallStatement::AugAssign(a) => {
+            v.visit_assign_target(&a.target);
+            v.visit_expression(&a.value);
+        }
+        SmallStatement::Del(d) => v.visit_del_target(&d.target),
+        SmallStatement::TypeAlias(t) => {
+            v.visit_name(&t.name);
+            for param in &t.type_params {
+                v.visit_type_param(param);
+            }
+            v.visit_expression(&t.value);
+        }
+    }
+}
+
+pub fn walk_compound_statement<'r, 'a, V: Visitor<'r, 'a> + ?Sized>(v: &mut V, node: &'r CompoundStatement<'a>) {
+    match node {
+        CompoundStatement::FunctionDef(f) => {
+            for decorator in &f.decorators {
+                v.visit_expression(&decorator.decorator);
+            }
+            v.visit_name(&f.name);
+            for param in &f.type_params {
+                v.visit_type_param(param);
+            }
+            v.visit_parameters(&f.params);
+            if let Some(returns) = &f.returns {
+                v.visit_expression(&returns.annotation);
+            }
+            v.visit_suite(&f.body);
+        }
+        CompoundStatement::ClassDef(c) => {
+            for decorator in &c.decorators {
+                v.visit_expression(&decorator.decorator);
+            }
+            v.visit_name(&c.name);
+            for param in &c.type_params {
+                v.visit_type_param(param);
+            }
+            for arg in c.bases.iter().chain(c.keywords.iter()) {
+                v.visit_arg(arg);
+            }
+            v.visit_suite(&c.body);
+        }
+        CompoundStatement::If(i) => walk_if(v, i),
+        CompoundStatement::For(f) => {
+            v.visit_assign_target(&f.target);
+            v.visit_expression(&f.iter);
+            v.visit_suite(&f.body);
+            if let Some(orelse) = &f.orelse {
+                v.visit_suite(&orelse.body);
+            }
+        }
+        CompoundStatement::While(w) => {
+            v.visit_expression(&w.test);
+            v.visit_suite(&w.body);
+            if let Some(orelse) = &w.orelse {
+                v.visit_suite(&orelse.body);
+            }
+        }
+        CompoundStatement::Try(t) => {
+            v.visit_suite(&t.body);
+            for handler in &t.handlers {
+                if let Some(kind) = &handler.r#type {
+                    v.visit_expression(kind);
+                }
+                if let Some(name) = &handler.name {
+                    v.visit_assign_target(&name.name);
+                }
+                v.visit_suite(&handler.body);
+            }
+            walk_try_tail(v, &t.orelse, &t.finalbody);
+        }
+        CompoundStatement::TryStar(t) => {
+            v.visit_suite(&t.body);
+            for handler in &t.handlers {
+                v.visit_expression(&handler.r#type);
+                if let Some(name) = &handler.name {
+                    v.visit_assign_target(&name.name);
+                }
+                v.visit_suite(&handler.body);
+            }
+            walk_try_tail(v, &t.orelse, &t.finalbody);
+        }
+        CompoundStatement::With(w) => {
+            for item in &w.items {
+                v.visit_expression(&item.item);
+                if let Some(asname) = &item.asname {
+                    v.visit_assign_target(&asname.name);
+                }
+            }
+            v.visit_suite(&w.body);
+        }
+        CompoundStatement::Match(m) => {
+            v.visit_expression(&m.subject);
+            for case in &m.cases {
+                v.visit_match_pattern(&case.pattern);
+                if let Some(guard) = &case.guard {
+                    v.visit_expression(guard);
+                }
+                v.visit_suite(&case.body);
+            }
+        }
+    }
+}
+
+fn walk_if<'r, 'a, V: Visitor<'r, 'a> + ?Sized>(v: &mut V, node: &'r If<'a>) {
+    v.visit_expression(&node.test);
+    v.visit_suite(&node.body);
+    match node.orelse.as_deref() {
+        Some(OrElse::Elif(elif)) => walk_if(v, elif),
+        Some(OrElse::Else(orelse)) => v.visit_suite(&orelse.body),
+        None => {}
+    }
+}
+
+fn walk_try_tail<'r, 'a, V: Visitor<'r, 'a> + ?Sized>(v: &mut V, orelse: &'r Option<Else<'a>>, finalbody: &'r Option<Finally<'a>>) {
+    if let Some(orelse) = orelse {
+        v.visit_suite(&orelse.body);
+    }
+    if let Some(finalbody) = finalbody {
+        v.visit_suite(&finalbody.body);
+    }
+}
+
+fn walk_import_alias<'r, 'a, V: Visitor<'r, 'a> + ?Sized>(v: &mut V, node: &'r ImportAlias<'a>) {
+    walk_name_or_attribute(v, &node.name);
+    if let Some(asname) = &node.asname {
+        v.visit_assign_target(&asname.name);
+    }
+}
+
+fn walk_name_or_attribute<'r, 'a, V: Visitor<'r, 'a> + ?Sized>(v: &mut V, node: &'r NameOrAttribute<'a>) {
+    match node {
+        NameOrAttribute::N(n) => v.visit_name(n),
+        NameOrAttribute::A(a) => {
+            v.visit_expression(&a.value);
+            v.visit_name(&a.attr);
+        }
+    }
+}
+
+pub fn walk_suite<'r, 'a, V: Visitor<'r, 'a> + ?Sized>(v: &mut V, node: &'r Suite<'a>) {
+    match node {
+        Suite::IndentedBlock(block) => {
+            for stmt in &block.body {
+                v.visit_statement(stmt);
+            }
+        }
+        Suite::SimpleStatementSuite(suite) => {
+            for stmt in &suite.body {
+                v.visit_small_statement(stmt);
+            }
+        }
+    }
+}
+
+pub fn walk_expression<'r, 'a, V: Visitor<'r, 'a> + ?Sized>(v: &mut V, node: &'r Expression<'a>) {
+    match node {
+        Expression::Name(n) => v.visit_name(n),
+        Expression::Ellipsis(_)
+        | Expression::Integer(_)
+        | Expression::Float(_)
+        | Expression::Binary(_)
+        | Expression::Hexidecimal(_)
+        | Expression::Imaginary(_)
+        | Expression::SimpleString(_)
+        | Expression::Constant(_) => {}
+        Expression::Comparison(c) => {
+            v.visit_expression(&c.left);
+            for target in &c.comparisons {
+                v.visit_expression(&target.comparator);
+            }
+        }
+        Expression::UnaryOperation(u) => v.visit_expression(&u.expression),
+        Expression::BinaryOperation(b) => {
+            v.visit_expression(&b.left);
+            v.visit_expression(&b.right);
+        }
+        Expression::BooleanOperation(b) => {
+            v.visit_expression(&b.left);
+            v.visit_expression(&b.right);
+        }
+        Expression::Attribute(a) => {
+            v.visit_expression(&a.value);
+            v.visit_name(&a.attr);
+        }
+        Expression::Tuple(t) => walk_elements(v, &t.elements),
+        Expression::List(l) => walk_elements(v, &l.elements),
+        Expression::Set(s) => walk_elements(v, &s.elements),
+        Expression::Call(c) => {
+            v.visit_expression(&c.func);
+            for arg in &c.args {
+                v.visit_arg(arg);
+            }
+        }
+        Expression::GeneratorExp(g) => {
+            v.visit_comp_for(&g.for_in);
+            v.visit_expression(&g.elt);
+        }
+        Expression::ListComp(l) => {
+            v.visit_comp_for(&l.for_in);
+            v.visit_expression(&l.elt);
+        }
+        Expression::SetComp(s) => {
+            v.visit_comp_for(&s.for_in);
+            v.visit_expression(&s.elt);
+        }
+        Expression::DictComp(d) => {
+            v.visit_comp_for(&d.for_in);
+            v.visit_expression(&d.key);
+            v.visit_expression(&d.value);
+        }
+        Expression::Dict(d) => {
+            for element in &d.elements {
+                match element {
+                    DictElement::Simple { key, value } => {
+                        v.visit_expression(key);
+                        v.visit_expression(value);
+                    }
+                    DictElement::Starred(s) => v.visit_expression(&s.value),
+                }
+            }
+        }
+        Expression::Subscript(s) => walk_subscript(v, s),
+        Expression::StarredElement(s) => v.visit_expression(&s.value),
+        Expression::IfExp(i) => {
+            v.visit_expression(&i.test);
+            v.visit_expression(&i.body);
+            v.visit_expression(&i.orelse);
+        }
+        Expression::Lambda(l) => {
+            v.visit_parameters(&l.params);
+            v.visit_expression(&l.body);
+        }
+        Expression::Yield(y) => match y.value.as_deref() {
+            Some(YieldValue::Expression(e)) => v.visit_expression(e),
+            Some(YieldValue::From(f)) => v.visit_expression(&f.item),
+            None => {}
+        },
+        Expression::Await(a) => v.visit_expression(&a.expression),
+        Expression::ConcatenatedString(c) => {
+            walk_string(v, &c.left);
+            walk_string(v, &c.right);
+        }
+        Expression::FormattedString(f) => walk_formatted_parts(v, &f.parts),
+        Expression::NamedExpr(n) => {
+            v.visit_expression(&n.target);
+            v.visit_expression(&n.value);
+        }
+    }
+}
+
+fn walk_elements<'r, 'a, V: Visitor<'r, 'a> + ?Sized>(v: &mut V, elements: &'r [Element<'a>]) {
+    for element in elements {
+        match element {
+            Element::Simple { value } => v.visit_expression(value),
+            Element::Starred(s) => v.visit_expression(&s.value),
+        }
+    }
+}
+
+fn walk_subscript<'r, 'a, V: Visitor<'r, 'a> + ?Sized>(v: &mut V, node: &'r Subscript<'a>) {
+    v.visit_expression(&node.value);
+    for element in &node.slice {
+        match &element.slice {
+            BaseSlice::Index(i) => v.visit_expression(&i.value),
+            BaseSlice::Slice(s) => {
+                for part in [&s.lower, &s.upper, &s.step].into_iter().flatten() {
+                    v.visit_expression(part);
+                }
+            }
+        }
+    }
+}
+
+fn walk_string<'r, 'a, V: Visitor<'r, 'a> + ?Sized>(v: &mut V, node: &'r String<'a>) {
+    match node {
+        String::Simple(_) => {}
+        String::Concatenated(c) => {
+            walk_string(v, &c.left);
+            walk_string(v, &c.right);
+        }
+        String::Formatted(f) => walk_formatted_parts(v, &f.parts),
+    }
+}
+
+fn walk_formatted_parts<'r, 'a, V: Visitor<'r, 'a> + ?Sized>(v: &mut V, parts: &'r [FormattedStringContent<'a>]) {
+    for part in parts {
+        if let FormattedStringContent::Expression(e) = part {
+            v.visit_expression(&e.expression);
+            if let Some(spec) = &e.format_spec {
+                walk_formatted_parts(v, spec);
+            }
+        }
+    }
+}
+
+pub fn walk_assign_target<'r, 'a, V: Visitor<'r, 'a> + ?Sized>(v: &mut V, node: &'r AssignTargetExpression<'a>) {
+    match node {
+        AssignTargetExpression::Name(n) => v.visit_name(n),
+        AssignTargetExpression::Attribute(a) => {
+            v.visit_expression(&a.value);
+            v.visit_name(&a.attr);
+        }
+        AssignTargetExpression::StarredElement(s) => v.visit_expression(&s.value),
+        AssignTargetExpression::Tuple(t) => walk_elements(v, &t.elements),
+        AssignTargetExpression::List(l) => walk_elements(v, &l.elements),
+        AssignTargetExpression::Subscript(s) => walk_subscript(v, s),
+    }
+}
+
+pub fn walk_del_target<'r, 'a, V: Visitor<'r, 'a> + ?Sized>(v: &mut V, node: &'r DelTargetExpression<'a>) {
+    match node {
+        DelTargetExpression::Name(n) => v.visit_name(n),
+        DelTargetExpression::Attribute(a) => {
+            v.visit_expression(&a.value);
+            v.visit_name(&a.attr);
+        }
+        DelTargetExpression::Tuple(t) => walk_elements(v, &t.elements),
+        DelTargetExpression::List(l) => walk_elements(v, &l.elements),
+        DelTargetExpression::Subscript(s) => walk_subscript(v, s),
+    }
+}
+
+pub fn walk_parameters<'r, 'a, V: Visitor<'r, 'a> + ?Sized>(v: &mut V, node: &'r Parameters<'a>) {
+    for param in node.posonly_params.iter().chain(node.params.iter()) {
+        v.visit_param(param);
+    }
+    if let Some(StarArg::Param(param)) = &node.star_arg {
+        v.visit_param(param);
+    }
+    for param in &node.kwonly_params {
+        v.visit_param(param);
+    }
+    if let Some(param) = &node.star_kwarg {
+        v.visit_param(param);
+    }
+}
+
+pub fn walk_param<'r, 'a, V: Visitor<'r, 'a> + ?Sized>(v: &mut V, node: &'r Param<'a>) {
+    v.visit_name(&node.name);
+    if let Some(annotation) = &node.annotation {
+        v.visit_expression(&annotation.annotation);
+    }
+    if let Some(default) = &node.default {
+        v.visit_expression(default);
+    }
+}
+
+pub fn walk_comp_for<'r, 'a, V: Visitor<'r, 'a> + ?Sized>(v: &mut V, node: &'r CompFor<'a>) {
+    v.visit_expression(&node.iter);
+    v.visit_assign_target(&node.target);
+    for comp_if in &node.ifs {
+        v.visit_expression(&comp_if.test);
+    }
+    if let Some(inner) = &node.inner_for_in {
+        v.visit_comp_for(inner);
+    }
+}
+
+pub fn walk_arg<'r, 'a, V: Visitor<'r, 'a> + ?Sized>(v: &mut V, node: &'r Arg<'a>) {
+    if let Some(keyword) = &node.keyword {
+        v.visit_name(keyword);
+    }
+    v.visit_expression(&node.value);
+}
+
+pub fn walk_type_param<'r, 'a, V: Visitor<'r, 'a> + ?Sized>(v: &mut V, node: &'r TypeParam<'a>) {
+    v.visit_name(&node.name);
+    if let TypeParamKind::TypeVar { bound: Some(bound) } = &node.kind {
+        v.visit_expression(bound);
+    }
+    if let Some(default) = &node.default {
+        v.visit_expression(default);
+    }
+}
+
+pub fn walk_match_pattern<'r, 'a, V: Visitor<'r, 'a> + ?Sized>(v: &mut V, node: &'r MatchPattern<'a>) {
+    match node {
+        MatchPattern::Value(value) => v.visit_expression(&value.value),
+        MatchPattern::Singleton(_) => {}
+        MatchPattern::Sequence(seq) => {
+            let patterns = match seq {
+                MatchSequence::MatchList(l) => &l.patterns,
+                MatchSequence::MatchTuple(t) => &t.patterns,
+            };
+            for element in patterns {
+                match element {
+                    StarrableMatchSequenceElement::Simple(s) => v.visit_match_pattern(&s.value),
+                    StarrableMatchSequenceElement::Starred(s) => {
+                        if let Some(name) = &s.name {
+                            v.visit_name(name);
+                        }
+                    }
+                }
+            }
+        }
+        MatchPattern::Mapping(m) => {
+            for element in &m.elements {
+                v.visit_expression(&element.key);
+                v.visit_match_pattern(&element.pattern);
+            }
+            if let Some(rest) = &m.rest {
+                v.visit_name(rest);
+            }
+        }
+        MatchPattern::Class(c) => {
+            walk_name_or_attribute(v, &c.cls);
+            for element in &c.patterns {
+                v.visit_match_pattern(&element.value);
+            }
+            for kwd in &c.kwds {
+                v.visit_name(&kwd.key);
+                v.visit_match_pattern(&kwd.pattern);
+            }
+        }
+        MatchPattern::As(a) => {
+            if let Some(pattern) = &a.pattern {
+                v.visit_match_pattern(pattern);
+            }
+            if let Some(name) = &a.name {
+                v.visit_name(name);
+            }
+        }
+        MatchPattern::Or(o) => {
+            for element in &o.patterns {
+                v.visit_match_pattern(&element.pattern);
+            }
+        }
+    }
+}
+
+/// Mutable counterpart of `Visitor`, for passes that rewrite the tree in place.
+pub trait VisitorMut<'a> {
+    fn visit_module(&mut self, node: &mut Module<'a>) {
+        walk_module_mut(self, node);
+    }
+
+    fn visit_statement(&mut self, node: &mut Statement<'a>) {
+        walk_statement_mut(self, node);
+    }
+
+    fn visit_small_statement(&mut self, node: &mut SmallStatement<'a>) {
+        walk_small_statement_mut(self, node);
+    }
+
+    fn visit_compound_statement(&mut self, node: &mut CompoundStatement<'a>) {
+        walk_compound_statement_mut(self, node);
+    }
+
+    fn visit_suite(&mut self, node: &mut Suite<'a>) {
+        walk_suite_mut(self, node);
+    }
+
+    fn visit_expression(&mut self, node: &mut Expression<'a>) {
+        walk_expression_mut(self, node);
+    }
+
+    fn visit_assign_target(&mut self, node: &mut AssignTargetExpression<'a>) {
+        walk_assign_target_mut(self, node);
+    }
+
+    fn visit_del_target(&mut self, node: &mut DelTargetExpression<'a>) {
+        walk_del_target_mut(self, node);
+    }
+
+    fn visit_parameters(&mut self, node: &mut Parameters<'a>) {
+        walk_parameters_mut(self, node);
+    }
+
+    fn visit_param(&mut self, node: &mut Param<'a>) {
+        walk_param_mut(self, node);
+    }
+
+    fn visit_comp_for(&mut self, node: &mut CompFor<'a>) {
+        walk_comp_for_mut(self, node);
+    }
+
+    fn visit_arg(&mut self, node: &mut Arg<'a>) {
+        walk_arg_mut(self, node);
+    }
+
+    fn visit_match_pattern(&mut self, node: &mut MatchPattern<'a>) {
+        walk_match_pattern_mut(self, node);
+    }
+
+    fn visit_type_param(&mut self, node: &mut TypeParam<'a>) {
+        walk_type_param_mut(self, node);
+    }
+
+    fn visit_name(&mut self, _node: &mut Name<'a>) {}
+}
+
+pub fn walk_module_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, node: &mut Module<'a>) {
+    for stmt in &mut node.body {
+        v.visit_statement(stmt);
+    }
+}
+
+pub fn walk_statement_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, node: &mut Statement<'a>) {
+    match node {
+        Statement::Simple(line) => {
+            for stmt in &mut line.body {
+                v.visit_small_statement(stmt);
+            }
+        }
+        Statement::Compound(stmt) => v.visit_compound_statement(stmt),
+        Statement::Error(_) => {}
+    }
+}
+
+pub fn walk_small_statement_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, node: &mut SmallStatement<'a>) {
+    match node {
+        SmallStatement::Pass | SmallStatement::Break(_) | SmallStatement::Continue(_) => {}
+        SmallStatement::Return(r) => {
+            if let Some(value) = &mut r.value {
+                v.visit_expression(value);
+            }
+        }
+        SmallStatement::Expr(e) => v.visit_expression(&mut e.value),
+        SmallStatement::Assert(a) => {
+            v.visit_expression(&mut a.test);
+            if let Some(msg) = &mut a.msg {
+                v.visit_expression(msg);
+            }
+        }
+        SmallStatement::Import(i) => {
+            for alias in &mut i.names {
+                walk_import_alias_mut(v, alias);
+            }
+        }
+        SmallStatement::ImportFrom(i) => {
+            if let Some(module) = &mut i.module {
+                walk_name_or_attribute_mut(v, module);
+            }
+            if let ImportNames::Aliases(aliases) = &mut i.names {
+                for alias in aliases {
+                    walk_import_alias_mut(v, alias);
+                }
+            }
+        }
+        SmallStatement::Assign(a) => {
+            for target in &mut a.targets {
+                v.visit_assign_target(&mut target.target);
+            }
+            v.visit_expression(&mut a.value);
+        }
+        SmallStatement::AnnAssign(a) => {
+            v.visit_assign_target(&mut a.target);
+            v.visit_expression(&mut a.annotation.annotation);
+            if let Some(value) = &mut a.value {
+                v.visit_expression(value);
+            }
+        }
+        SmallStatement::Raise(r) => {
+            if let Some(exc) = &mut r.exc {
+                v.visit_expression(exc);
+            }
+            if let Some(cause) = &mut r.cause {
+                v.visit_expression(&mut cause.item);
+            }
+        }
+        SmallStatement::Global(g) => {
+            for item in &mut g.names {
+                v.visit_name(&mut item.name);
+            }
+        }
+        SmallStatement::Nonlocal(n) => {
+            for item in &mut n.names {
+                v.visit_name(&mut item.name);
+            }
+        }
+        SmallStatement::AugAssign(a) => {
+            v.visit_assign_target(&mut a.target);
+            v.visit_expression(&mut a.value);
+        }
+        SmallStatement::Del(d) => v.visit_del_target(&mut d.target),
+        SmallStatement::TypeAlias(t) => {
+            v.visit_name(&mut t.name);
+            for param in &mut t.type_params {
+                v.visit_type_param(param);
+            }
+            v.visit_expression(&mut t.value);
+        }
+    }
+}
+
+pub fn walk_compound_statement_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, node: &mut CompoundStatement<'a>) {
+    match node {
+        CompoundStatement::FunctionDef(f) => {
+            for decorator in &mut f.decorators {
+                v.visit_expression(&mut decorator.decorator);
+            }
+            v.visit_name(&mut f.name);
+            for param in &mut f.type_params {
+                v.visit_type_param(param);
+            }
+            v.visit_parameters(&mut f.params);
+            if let Some(returns) = &mut f.returns {
+                v.visit_expression(&mut returns.annotation);
+            }
+            v.visit_suite(&mut f.body);
+        }
+        CompoundStatement::ClassDef(c) => {
+            for decorator in &mut c.decorators {
+                v.visit_expression(&mut decorator.decorator);
+            }
+            v.visit_name(&mut c.name);
+            for param in &mut c.type_params {
+                v.visit_type_param(param);
+            }
+            for arg in c.bases.iter_mut().chain(c.keywords.iter_mut()) {
+                v.visit_arg(arg);
+            }
+            v.visit_suite(&mut c.body);
+        }
+        CompoundStatement::If(i) => walk_if_mut(v, i),
+        CompoundStatement::For(f) => {
+            v.visit_assign_target(&mut f.target);
+            v.visit_expression(&mut f.iter);
+            v.visit_suite(&mut f.body);
+            if let Some(orelse) = &mut f.orelse {
+                v.visit_suite(&mut orelse.body);
+            }
+        }
+        CompoundStatement::While(w) => {
+            v.visit_expression(&mut w.test);
+            v.visit_suite(&mut w.body);
+            if let Some(orelse) = &mut w.orelse {
+                v.visit_suite(&mut orelse.body);
+            }
+        }
+        CompoundStatement::Try(t) => {
+            v.visit_suite(&mut t.body);
+            for handler in &mut t.handlers {
+                if let Some(kind) = &mut handler.r#type {
+                    v.visit_expression(kind);
+                }
+                if let Some(name) = &mut handler.name {
+                    v.visit_assign_target(&mut name.name);
+                }
+                v.visit_suite(&mut handler.body);
+            }
+            walk_try_tail_mut(v, &mut t.orelse, &mut t.finalbody);
+        }
+        CompoundStatement::TryStar(t) => {
+            v.visit_suite(&mut t.body);
+            for handler in &mut t.handlers {
+                v.visit_expression(&mut handler.r#type);
+                if let Some(name) = &mut handler.name {
+                    v.visit_assign_target(&mut name.name);
+                }
+                v.visit_suite(&mut handler.body);
+            }
+            walk_try_tail_mut(v, &mut t.orelse, &mut t.finalbody);
+        }
+        CompoundStatement::With(w) => {
+            for item in &mut w.items {
+                v.visit_expression(&mut item.item);
+                if let Some(asname) = &mut item.asname {
+                    v.visit_assign_target(&mut asname.name);
+                }
+            }
+            v.visit_suite(&mut w.body);
+        }
+        CompoundStatement::Match(m) => {
+            v.visit_expression(&mut m.subject);
+            for case in &mut m.cases {
+                v.visit_match_pattern(&mut case.pattern);
+                if let Some(guard) = &mut case.guard {
+                    v.visit_expression(guard);
+                }
+                v.visit_suite(&mut case.body);
+            }
+        }
+    }
+}
+
+fn walk_if_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, node: &mut If<'a>) {
+    v.visit_expression(&mut node.test);
+    v.visit_suite(&mut node.body);
+    match node.orelse.as_deref_mut() {
+        Some(OrElse::Elif(elif)) => walk_if_mut(v, elif),
+        Some(OrElse::Else(orelse)) => v.visit_suite(&mut orelse.body),
+        None => {}
+    }
+}
+
+fn walk_try_tail_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, orelse: &mut Option<Else<'a>>, finalbody: &mut Option<Finally<'a>>) {
+    if let Some(orelse) = orelse {
+        v.visit_suite(&mut orelse.body);
+    }
+    if let Some(finalbody) = finalbody {
+        v.visit_suite(&mut finalbody.body);
+    }
+}
+
+fn walk_import_alias_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, node: &mut ImportAlias<'a>) {
+    walk_name_or_attribute_mut(v, &mut node.name);
+    if let Some(asname) = &mut node.asname {
+        v.visit_assign_target(&mut asname.name);
+    }
+}
+
+fn walk_name_or_attribute_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, node: &mut NameOrAttribute<'a>) {
+    match node {
+        NameOrAttribute::N(n) => v.visit_name(n),
+        NameOrAttribute::A(a) => {
+            v.visit_expression(&mut a.value);
+            v.visit_name(&mut a.attr);
+        }
+    }
+}
+
+pub fn walk_suite_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, node: &mut Suite<'a>) {
+    match node {
+        Suite::IndentedBlock(block) => {
+            for stmt in &mut block.body {
+                v.visit_statement(stmt);
+            }
+        }
+        Suite::SimpleStatementSuite(suite) => {
+            for stmt in &mut suite.body {
+                v.visit_small_statement(stmt);
+            }
+        }
+    }
+}
+
+pub fn walk_expression_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, node: &mut Expression<'a>) {
+    match node {
+        Expression::Name(n) => v.visit_name(n),
+        Expression::Ellipsis(_)
+        | Expression::Integer(_)
+        | Expression::Float(_)
+        | Expression::Binary(_)
+        | Expression::Hexidecimal(_)
+        | Expression::Imaginary(_)
+        | Expression::SimpleString(_)
+        | Expression::Constant(_) => {}
+        Expression::Comparison(c) => {
+            v.visit_expression(&mut c.left);
+            for target in &mut c.comparisons {
+                v.visit_expression(&mut target.comparator);
+            }
+        }
+        Expression::UnaryOperation(u) => v.visit_expression(&mut u.expression),
+        Expression::BinaryOperation(b) => {
+            v.visit_expression(&mut b.left);
+            v.visit_expression(&mut b.right);
+        }
+        Expression::BooleanOperation(b) => {
+            v.visit_expression(&mut b.left);
+            v.visit_expression(&mut b.right);
+        }
+        Expression::Attribute(a) => {
+            v.visit_expression(&mut a.value);
+            v.visit_name(&mut a.attr);
+        }
+        Expression::Tuple(t) => walk_elements_mut(v, &mut t.elements),
+        Expression::List(l) => walk_elements_mut(v, &mut l.elements),
+        Expression::Set(s) => walk_elements_mut(v, &mut s.elements),
+        Expression::Call(c) => {
+            v.visit_expression(&mut c.func);
+            for arg in &mut c.args {
+                v.visit_arg(arg);
+            }
+        }
+        Expression::GeneratorExp(g) => {
+            v.visit_comp_for(&mut g.for_in);
+            v.visit_expression(&mut g.elt);
+        }
+        Expression::ListComp(l) => {
+            v.visit_comp_for(&mut l.for_in);
+            v.visit_expression(&mut l.elt);
+        }
+        Expression::SetComp(s) => {
+            v.visit_comp_for(&mut s.for_in);
+            v.visit_expression(&mut s.elt);
+        }
+        Expression::DictComp(d) => {
+            v.visit_comp_for(&mut d.for_in);
+            v.visit_expression(&mut d.key);
+            v.visit_expression(&mut d.value);
+        }
+        Expression::Dict(d) => {
+            for element in &mut d.elements {
+                match element {
+                    DictElement::Simple { key, value } => {
+                        v.visit_expression(key);
+                        v.visit_expression(value);
+                    }
+                    DictElement::Starred(s) => v.visit_expression(&mut s.value),
+                }
+            }
+        }
+        Expression::Subscript(s) => walk_subscript_mut(v, s),
+        Expression::StarredElement(s) => v.visit_expression(&mut s.value),
+        Expression::IfExp(i) => {
+            v.visit_expression(&mut i.test);
+            v.visit_expression(&mut i.body);
+            v.visit_expression(&mut i.orelse);
+        }
+        Expression::Lambda(l) => {
+            v.visit_parameters(&mut l.params);
+            v.visit_expression(&mut l.body);
+        }
+        Expression::Yield(y) => match y.value.as_deref_mut() {
+            Some(YieldValue::Expression(e)) => v.visit_expression(e),
+            Some(YieldValue::From(f)) => v.visit_expression(&mut f.item),
+            None => {}
+        },
+        Expression::Await(a) => v.visit_expression(&mut a.expression),
+        Expression::ConcatenatedString(c) => {
+            walk_string_mut(v, &mut c.left);
+            walk_string_mut(v, &mut c.right);
+        }
+        Expression::FormattedString(f) => walk_formatted_parts_mut(v, &mut f.parts),
+        Expression::NamedExpr(n) => {
+            v.visit_expression(&mut n.target);
+            v.visit_expression(&mut n.value);
+        }
+    }
+}
+
+fn walk_elements_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, elements: &mut [Element<'a>]) {
+    for element in elements {
+        match element {
+            Element::Simple { value } => v.visit_expression(value),
+            Element::Starred(s) => v.visit_expression(&mut s.value),
+        }
+    }
+}
+
+fn walk_subscript_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, node: &mut Subscript<'a>) {
+    v.visit_expression(&mut node.value);
+    for element in &mut node.slice {
+        match &mut element.slice {
+            BaseSlice::Index(i) => v.visit_expression(&mut i.value),
+            BaseSlice::Slice(s) => {
+                let s = &mut **s;
+                for part in [&mut s.lower, &mut s.upper, &mut s.step].into_iter().flatten() {
+                    v.visit_expression(part);
+                }
+            }
+        }
+    }
+}
+
+fn walk_string_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, node: &mut String<'a>) {
+    match node {
+        String::Simple(_) => {}
+        String::Concatenated(c) => {
+            walk_string_mut(v, &mut c.left);
+            walk_string_mut(v, &mut c.right);
+        }
+        String::Formatted(f) => walk_formatted_parts_mut(v, &mut f.parts),
+    }
+}
+
+fn walk_formatted_parts_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, parts: &mut [FormattedStringContent<'a>]) {
+    for part in parts {
+        if let FormattedStringContent::Expression(e) = part {
+            v.visit_expression(&mut e.expression);
+            if let Some(spec) = &mut e.format_spec {
+                walk_formatted_parts_mut(v, spec);
+            }
+        }
+    }
+}
+
+pub fn walk_assign_target_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, node: &mut AssignTargetExpression<'a>) {
+    match node {
+        AssignTargetExpression::Name(n) => v.visit_name(n),
+        AssignTargetExpression::Attribute(a) => {
+            v.visit_expression(&mut a.value);
+            v.visit_name(&mut a.attr);
+        }
+        AssignTargetExpression::StarredElement(s) => v.visit_expression(&mut s.value),
+        AssignTargetExpression::Tuple(t) => walk_elements_mut(v, &mut t.elements),
+        AssignTargetExpression::List(l) => walk_elements_mut(v, &mut l.elements),
+        AssignTargetExpression::Subscript(s) => walk_subscript_mut(v, s),
+    }
+}
+
+pub fn walk_del_target_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, node: &mut DelTargetExpression<'a>) {
+    match node {
+        DelTargetExpression::Name(n) => v.visit_name(n),
+        DelTargetExpression::Attribute(a) => {
+            v.visit_expression(&mut a.value);
+            v.visit_name(&mut a.attr);
+        }
+        DelTargetExpression::Tuple(t) => walk_elements_mut(v, &mut t.elements),
+        DelTargetExpression::List(l) => walk_elements_mut(v, &mut l.elements),
+        DelTargetExpression::Subscript(s) => walk_subscript_mut(v, s),
+    }
+}
+
+pub fn walk_parameters_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, node: &mut Parameters<'a>) {
+    for param in node.posonly_params.iter_mut().chain(node.params.iter_mut()) {
+        v.visit_param(param);
+    }
+    if let Some(StarArg::Param(param)) = &mut node.star_arg {
+        v.visit_param(param);
+    }
+    for param in &mut node.kwonly_params {
+        v.visit_param(param);
+    }
+    if let Some(param) = &mut node.star_kwarg {
+        v.visit_param(param);
+    }
+}
+
+pub fn walk_param_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, node: &mut Param<'a>) {
+    v.visit_name(&mut node.name);
+    if let Some(annotation) = &mut node.annotation {
+        v.visit_expression(&mut annotation.annotation);
+    }
+    if let Some(default) = &mut node.default {
+        v.visit_expression(default);
+    }
+}
+
+pub fn walk_comp_for_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, node: &mut CompFor<'a>) {
+    v.visit_expression(&mut node.iter);
+    v.visit_assign_target(&mut node.target);
+    for comp_if in &mut node.ifs {
+        v.visit_expression(&mut comp_if.test);
+    }
+    if let Some(inner) = &mut node.inner_for_in {
+        v.visit_comp_for(inner);
+    }
+}
+
+pub fn walk_arg_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, node: &mut Arg<'a>) {
+    if let Some(keyword) = &mut node.keyword {
+        v.visit_name(keyword);
+    }
+    v.visit_expression(&mut node.value);
+}
+
+pub fn walk_type_param_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, node: &mut TypeParam<'a>) {
+    v.visit_name(&mut node.name);
+    if let TypeParamKind::TypeVar { bound: Some(bound) } = &mut node.kind {
+        v.visit_expression(bound);
+    }
+    if let Some(default) = &mut node.default {
+        v.visit_expression(default);
+    }
+}
+
+pub fn walk_match_pattern_mut<'a, V: VisitorMut<'a> + ?Sized>(v: &mut V, node: &mut MatchPattern<'a>) {
+    match node {
+        MatchPattern::Value(value) => v.visit_expression(&mut value.value),
+        MatchPattern::Singleton(_) => {}
+        MatchPattern::Sequence(seq) => {
+            let patterns = match seq {
+                MatchSequence::MatchList(l) => &mut l.patterns,
+                MatchSequence::MatchTuple(t) => &mut t.patterns,
+            };
+            for element in patterns {
+                match element {
+                    StarrableMatchSequenceElement::Simple(s) => v.visit_match_pattern(&mut s.value),
+                    StarrableMatchSequenceElement::Starred(s) => {
+                        if let Some(name) = &mut s.name {
+                            v.visit_name(name);
+                        }
+                    }
+                }
+            }
+        }
+        MatchPattern::Mapping(m) => {
+            for element in &mut m.elements {
+                v.visit_expression(&mut element.key);
+                v.visit_match_pattern(&mut element.pattern);
+            }
+            if let Some(rest) = &mut m.rest {
+                v.visit_name(rest);
+            }
+        }
+        MatchPattern::Class(c) => {
+            walk_name_or_attribute_mut(v, &mut c.cls);
+            for element in &mut c.patterns {
+                v.visit_match_pattern(&mut element.value);
+            }
+            for kwd in &mut c.kwds {
+                v.visit_name(&mut kwd.key);
+                v.visit_match_pattern(&mut kwd.pattern);
+            }
+        }
+        MatchPattern::As(a) => {
+            if let Some(pattern) = &mut a.pattern {
+                v.visit_match_pattern(pattern);
+            }
+            if let Some(name) = &mut a.name {
+                v.visit_name(name);
+            }
+        }
+        MatchPattern::Or(o) => {
+            for element in &mut o.patterns {
+                v.visit_match_pattern(&mut element.pattern);
+            }
+        }
+    }
+}
@@ -0,0 +1,295 @@
+//! Addition, multiplication, division and powers.
+//!
+//! The magnitude helpers work on little endian digit slices; the `BigInt` methods handle
+//! the signs on top of them.
+
+use std::cmp::Ordering;
+use std::ops::{Add, Mul, Sub};
+
+use super::{cmp_magnitude, div_small_in_place, BigInt, DIGIT_BITS};
+
+fn add_magnitude(left: &[u32], right: &[u32]) -> Vec<u32> {
+    let (long, short) = if left.len() >= right.len() { (left, right) } else { (right, left) };
+    let mut result = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+    for (idx, digit) in long.iter().enumerate() {
+        let sum = *digit as u64 + *short.get(idx).unwrap_or(&0) as u64 + carry;
+        result.push(sum as u32);
+        carry = sum >> DIGIT_BITS;
+    }
+    if carry > 0 {
+        result.push(carry as u32);
+    }
+    result
+}
+
+/// `left - right` for `left >= right`.
+fn sub_magnitude(left: &[u32], right: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(left.len());
+    let mut borrow = 0i64;
+    for (idx, digit) in left.iter().enumerate() {
+        let mut diff = *digit as i64 - *right.get(idx).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if diff < 0 {
+            diff += 1 << DIGIT_BITS;
+            borrow = 1;
+        }
+        result.push(diff as u32);
+    }
+    debug_assert_eq!(borrow, 0, "magnitude subtraction underflow");
+    result
+}
+
+fn mul_magnitude(left: &[u32], right: &[u32]) -> Vec<u32> {
+    if left.is_empty() || right.is_empty() {
+        return vec![];
+    }
+    let mut result = vec![0u32; left.len() + right.len()];
+    for (i, a) in left.iter().enumerate() {
+        if *a == 0 {
+            continue;
+        }
+        let mut carry = 0u64;
+        for (j, b) in right.iter().enumerate() {
+            let product = *a as u64 * *b as u64 + result[i + j] as u64 + carry;
+            result[i + j] = product as u32;
+            carry = product >> DIGIT_BITS;
+        }
+        result[i + right.len()] = carry as u32;
+    }
+    result
+}
+
+/// Shifts a magnitude left by fewer than `DIGIT_BITS` bits, growing it by one digit.
+fn shl_digits(mag: &[u32], shift: u32) -> Vec<u32> {
+    let mut result = Vec::with_capacity(mag.len() + 1);
+    let mut carry = 0u32;
+    for digit in mag {
+        result.push((digit << shift) | carry);
+        carry = if shift == 0 { 0 } else { digit >> (DIGIT_BITS - shift) };
+    }
+    result.push(carry);
+    result
+}
+
+/// The quotient and remainder of two magnitudes, by Knuth's algorithm D.  `right` is not
+/// zero.
+fn divmod_magnitude(left: &[u32], right: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if cmp_magnitude(left, right) == Ordering::Less {
+        return (vec![], left.to_vec());
+    }
+    if right.len() == 1 {
+        let mut quotient = left.to_vec();
+        let rem = div_small_in_place(&mut quotient, right[0]);
+        return (quotient, if rem == 0 { vec![] } else { vec![rem] });
+    }
+    // Normalize so that the top digit of the divisor has its high bit set, which keeps
+    // each estimated quotient digit at most two too large.
+    let shift = right.last().expect("non-zero divisor").leading_zeros();
+    let divisor = {
+        let mut shifted = shl_digits(right, shift);
+        shifted.pop();
+        shifted
+    };
+    let mut rem = shl_digits(left, shift);
+    let n = divisor.len();
+    let m = rem.len() - n;
+    let base = 1u64 << DIGIT_BITS;
+    let mut quotient = vec![0u32; m];
+    for j in (0..m).rev() {
+        let top = ((rem[j + n] as u64) << DIGIT_BITS) | rem[j + n - 1] as u64;
+        let mut qhat = top / divisor[n - 1] as u64;
+        let mut rhat = top % divisor[n - 1] as u64;
+        while qhat >= base || qhat * divisor[n - 2] as u64 > ((rhat << DIGIT_BITS) | rem[j + n - 2] as u64) {
+            qhat -= 1;
+            rhat += divisor[n - 1] as u64;
+            if rhat >= base {
+                break;
+            }
+        }
+        let mut borrow = 0i64;
+        for i in 0..n {
+            let product = qhat * divisor[i] as u64;
+            let diff = rem[i + j] as i64 - borrow - (product & (base - 1)) as i64;
+            rem[i + j] = diff as u32;
+            borrow = (product >> DIGIT_BITS) as i64 - (diff >> DIGIT_BITS);
+        }
+        let diff = rem[j + n] as i64 - borrow;
+        rem[j + n] = diff as u32;
+        quotient[j] = qhat as u32;
+        if diff < 0 {
+            // The estimate was one too large: add the divisor back.
+            quotient[j] -= 1;
+            let mut carry = 0u64;
+            for i in 0..n {
+                let sum = rem[i + j] as u64 + divisor[i] as u64 + carry;
+                rem[i + j] = sum as u32;
+                carry = sum >> DIGIT_BITS;
+            }
+            rem[j + n] = rem[j + n].wrapping_add(carry as u32);
+        }
+    }
+    rem.truncate(n);
+    let rem = BigInt::from_magnitude(rem).shr_magnitude(shift as u64).mag;
+    (quotient, rem)
+}
+
+impl BigInt {
+    pub(super) fn from_magnitude(mag: Vec<u32>) -> Self {
+        let mut result = Self { negative: false, mag };
+        result.normalize();
+        result
+    }
+
+    fn with_sign(mag: Vec<u32>, negative: bool) -> Self {
+        let mut result = Self { negative, mag };
+        result.normalize();
+        result
+    }
+
+    pub fn abs(&self) -> Self {
+        Self { negative: false, mag: self.mag.clone() }
+    }
+
+    /// Floor division and modulo, with Python's signs: the remainder takes the sign of the
+    /// divisor.  None when dividing by zero.
+    pub fn div_mod_floor(&self, other: &Self) -> Option<(Self, Self)> {
+        if other.is_zero() {
+            return None;
+        }
+        let (quotient, rem) = divmod_magnitude(&self.mag, &other.mag);
+        let mut quotient = Self::with_sign(quotient, self.negative != other.negative);
+        let mut rem = Self::with_sign(rem, self.negative);
+        if !rem.is_zero() && self.negative != other.negative {
+            quotient = &quotient - &Self::from(1);
+            rem = &rem + other;
+        }
+        Some((quotient, rem))
+    }
+
+    /// `self ** exp` for a non-negative exponent.
+    pub fn pow(&self, mut exp: u64) -> Self {
+        let mut result = Self::from(1);
+        let mut base = self.clone();
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = &result * &base;
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = &base * &base;
+            }
+        }
+        result
+    }
+
+    /// `pow(self, exp, modulus)` for a non-negative exponent, with the sign of the modulus.
+    /// None when the modulus is zero.
+    pub fn mod_pow(&self, exp: &Self, modulus: &Self) -> Option<Self> {
+        let magnitude = modulus.abs();
+        let (_, base) = self.div_mod_floor(&magnitude)?;
+        let mut result = Self::from(1).div_mod_floor(&magnitude)?.1;
+        for bit in (0..exp.bit_length()).rev() {
+            result = (&result * &result).div_mod_floor(&magnitude)?.1;
+            if exp.bit(bit) {
+                result = (&result * &base).div_mod_floor(&magnitude)?.1;
+            }
+        }
+        if modulus.negative && !result.is_zero() {
+            result = &result + modulus;
+        }
+        Some(result)
+    }
+
+    /// The inverse of `self` modulo `modulus`, in `0..|modulus|`, if there is one.
+    pub fn mod_inverse(&self, modulus: &Self) -> Option<Self> {
+        let magnitude = modulus.abs();
+        let (_, value) = self.div_mod_floor(&magnitude)?;
+        let (mut old_r, mut r) = (value, magnitude.clone());
+        let (mut old_s, mut s) = (Self::from(1), Self::zero());
+        while !r.is_zero() {
+            let (quotient, rem) = old_r.div_mod_floor(&r)?;
+            old_r = std::mem::replace(&mut r, rem);
+            let next = &old_s - &(&quotient * &s);
+            old_s = std::mem::replace(&mut s, next);
+        }
+        if old_r != Self::from(1) {
+            return None;
+        }
+        Some(old_s.div_mod_floor(&magnitude)?.1)
+    }
+
+    /// Whether bit `index` of the magnitude is set.
+    fn bit(&self, index: u64) -> bool {
+        let digit = (index / DIGIT_BITS as u64) as usize;
+        self.mag.get(digit).is_some_and(|d| d >> (index % DIGIT_BITS as u64) & 1 == 1)
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::with_sign(add_magnitude(&self.mag, &other.mag), self.negative);
+        }
+        match cmp_magnitude(&self.mag, &other.mag) {
+            Ordering::Less => BigInt::with_sign(sub_magnitude(&other.mag, &self.mag), other.negative),
+            _ => BigInt::with_sign(sub_magnitude(&self.mag, &other.mag), self.negative),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other.clone()
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        BigInt::with_sign(mul_magnitude(&self.mag, &other.mag), self.negative != other.negative)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BigInt;
+
+    fn big(text: &str) -> BigInt {
+        match text.strip_prefix('-') {
+            Some(digits) => -BigInt::from_str_radix(digits, 10).unwrap(),
+            None => BigInt::from_str_radix(text, 10).unwrap(),
+        }
+    }
+
+    #[test]
+    fn floor_division_follows_the_divisor_sign() {
+        let cases = [
+            ("7", "2", "3", "1"),
+            ("-7", "2", "-4", "1"),
+            ("7", "-2", "-4", "-1"),
+            ("-7", "-2", "3", "-1"),
+            ("123456789012345678901234567890", "987654321987", "124999998748520313", "645722545959"),
+            ("-340282366920938463463374607431768211456", "18446744073709551617", "-18446744073709551616", "18446744073709551616"),
+        ];
+        for (a, b, quotient, rem) in cases {
+            let (q, r) = big(a).div_mod_floor(&big(b)).unwrap();
+            assert_eq!((q.to_string(), r.to_string()), (quotient.to_string(), rem.to_string()), "{} divmod {}", a, b);
+        }
+        assert!(big("1").div_mod_floor(&BigInt::zero()).is_none());
+    }
+
+    #[test]
+    fn powers_and_modular_inverses() {
+        assert_eq!(big("3").pow(100).to_string(), "515377520732011331036461129765621272702107522001");
+        assert_eq!(big("3").mod_pow(&big("200"), &big("1000000007")).unwrap().to_string(), "136318165");
+        assert_eq!(big("3").mod_pow(&big("2"), &big("-5")).unwrap().to_string(), "-1");
+        assert_eq!(big("38").mod_inverse(&big("97")).unwrap().to_string(), "23");
+        assert!(big("4").mod_inverse(&big("8")).is_none());
+    }
+}
@@ -0,0 +1,195 @@
+//! Shifts, bitwise operators and byte conversions.
+//!
+//! Negative values behave as if they had an infinite two's complement representation, as
+//! Python's do: `-1 >> 5 == -1` and `-6 & 0xff == 250`.
+
+use std::ops::{BitAnd, BitOr, BitXor, Not, Shl, Shr};
+
+use super::{BigInt, DIGIT_BITS};
+
+impl BigInt {
+    /// The value as `len` two's complement digits, `len` being enough to hold it with a
+    /// sign bit.
+    fn to_twos_complement(&self, len: usize) -> Vec<u32> {
+        let mut digits = self.mag.clone();
+        digits.resize(len, 0);
+        if self.negative {
+            let mut carry = true;
+            for digit in digits.iter_mut() {
+                let (sum, overflow) = (!*digit).overflowing_add(carry as u32);
+                *digit = sum;
+                carry = overflow;
+            }
+        }
+        digits
+    }
+
+    fn from_twos_complement(mut digits: Vec<u32>) -> Self {
+        let negative = digits.last().is_some_and(|top| top >> (DIGIT_BITS - 1) == 1);
+        if negative {
+            let mut carry = true;
+            for digit in digits.iter_mut() {
+                let (sum, overflow) = (!*digit).overflowing_add(carry as u32);
+                *digit = sum;
+                carry = overflow;
+            }
+        }
+        let mut result = Self { negative, mag: digits };
+        result.normalize();
+        result
+    }
+
+    fn bitwise(&self, other: &Self, op: fn(u32, u32) -> u32) -> Self {
+        let len = self.mag.len().max(other.mag.len()) + 1;
+        let left = self.to_twos_complement(len);
+        let right = other.to_twos_complement(len);
+        Self::from_twos_complement(left.iter().zip(&right).map(|(a, b)| op(*a, *b)).collect())
+    }
+
+    /// The number of one bits in the magnitude, `int.bit_count()`.
+    pub fn bit_count(&self) -> u64 {
+        self.mag.iter().map(|digit| digit.count_ones() as u64).sum()
+    }
+
+    /// `int.to_bytes(length, byteorder, signed=signed)`: None when the value does not fit,
+    /// or is negative and `signed` is false.
+    pub fn to_bytes(&self, length: usize, little: bool, signed: bool) -> Option<Vec<u8>> {
+        if self.negative && !signed {
+            return None;
+        }
+        let bits = length as u64 * 8;
+        let fits = match (signed, self.negative) {
+            (false, _) => self.bit_length() <= bits,
+            (true, false) => self.bit_length() < bits,
+            // -2**(bits - 1) is the most negative value that fits.
+            (true, true) => {
+                let above = &self.abs() - &Self::from(1);
+                above.bit_length() < bits
+            }
+        };
+        if !fits {
+            return None;
+        }
+        let digits = self.to_twos_complement(length.div_ceil(4) + 1);
+        let mut bytes: Vec<u8> = digits.iter().flat_map(|digit| digit.to_le_bytes()).take(length).collect();
+        if !little {
+            bytes.reverse();
+        }
+        Some(bytes)
+    }
+
+    /// `int.from_bytes(bytes, byteorder, signed=signed)`.
+    pub fn from_bytes(bytes: &[u8], little: bool, signed: bool) -> Self {
+        let mut ordered = bytes.to_vec();
+        if !little {
+            ordered.reverse();
+        }
+        let negative = signed && ordered.last().is_some_and(|top| top & 0x80 != 0);
+        ordered.resize(ordered.len().div_ceil(4) * 4 + 4, if negative { 0xff } else { 0 });
+        let digits = ordered
+            .chunks(4)
+            .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect();
+        Self::from_twos_complement(digits)
+    }
+}
+
+impl Shl<u64> for &BigInt {
+    type Output = BigInt;
+
+    fn shl(self, shift: u64) -> BigInt {
+        if self.is_zero() {
+            return BigInt::zero();
+        }
+        let digit_shift = (shift / DIGIT_BITS as u64) as usize;
+        let bit_shift = (shift % DIGIT_BITS as u64) as u32;
+        let mut mag = vec![0u32; digit_shift];
+        mag.reserve(self.mag.len() + 1);
+        let mut carry = 0u32;
+        for digit in &self.mag {
+            mag.push((digit << bit_shift) | carry);
+            carry = if bit_shift == 0 { 0 } else { digit >> (DIGIT_BITS - bit_shift) };
+        }
+        mag.push(carry);
+        let mut result = BigInt { negative: self.negative, mag };
+        result.normalize();
+        result
+    }
+}
+
+impl Shr<u64> for &BigInt {
+    type Output = BigInt;
+
+    /// Rounds towards negative infinity, so negative values never shift to zero.
+    fn shr(self, shift: u64) -> BigInt {
+        if !self.negative {
+            return self.shr_magnitude(shift);
+        }
+        let above = &self.abs() - &BigInt::from(1);
+        -(&above.shr_magnitude(shift) + &BigInt::from(1))
+    }
+}
+
+impl BitAnd for &BigInt {
+    type Output = BigInt;
+
+    fn bitand(self, other: &BigInt) -> BigInt {
+        self.bitwise(other, |a, b| a & b)
+    }
+}
+
+impl BitOr for &BigInt {
+    type Output = BigInt;
+
+    fn bitor(self, other: &BigInt) -> BigInt {
+        self.bitwise(other, |a, b| a | b)
+    }
+}
+
+impl BitXor for &BigInt {
+    type Output = BigInt;
+
+    fn bitxor(self, other: &BigInt) -> BigInt {
+        self.bitwise(other, |a, b| a ^ b)
+    }
+}
+
+impl Not for &BigInt {
+    type Output = BigInt;
+
+    /// `~x == -x - 1`.
+    fn not(self) -> BigInt {
+        -(self + &BigInt::from(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BigInt;
+
+    #[test]
+    fn negative_values_act_as_twos_complement() {
+        let big = &BigInt::from(1) << 100;
+        let minus_six = BigInt::from(-6);
+        assert_eq!((&minus_six & &BigInt::from(0xff)).to_string(), "250");
+        assert_eq!((&minus_six | &big).to_string(), "-6");
+        assert_eq!((&minus_six ^ &big).to_string(), "-1267650600228229401496703205382");
+        assert_eq!((&-big.clone() >> 99).to_string(), "-2");
+        assert_eq!((&BigInt::from(-1) >> 1000).to_string(), "-1");
+        assert_eq!((&BigInt::from(-5) >> 1).to_string(), "-3");
+        assert_eq!((!&big).to_string(), "-1267650600228229401496703205377");
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let value = BigInt::from(-129);
+        let bytes = value.to_bytes(2, false, true).unwrap();
+        assert_eq!(bytes, vec![0xff, 0x7f]);
+        assert_eq!(BigInt::from_bytes(&bytes, false, true), value);
+        assert_eq!(BigInt::from_bytes(&bytes, false, false).to_string(), "65407");
+        assert_eq!(BigInt::from(-128).to_bytes(1, true, true), Some(vec![0x80]));
+        assert_eq!(BigInt::from(128).to_bytes(1, true, true), None);
+        assert_eq!(BigInt::from(-1).to_bytes(1, true, false), None);
+        assert_eq!(BigInt::from(0).to_bytes(0, true, false), Some(vec![]));
+    }
+}
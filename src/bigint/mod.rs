@@ -0,0 +1,295 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+
+mod arith;
+mod bits;
+
+/// Arbitrary precision signed integer.
+///
+/// The magnitude is stored as little endian base 2**32 digits with no trailing zero digits,
+/// so zero is an empty vector and is never negative.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BigInt {
+    negative: bool,
+    mag: Vec<u32>,
+}
+
+const DIGIT_BITS: u32 = 32;
+
+impl BigInt {
+    pub fn zero() -> Self {
+        Self::default()
+    }
+
+    pub fn from_u64(value: u64) -> Self {
+        let mut result = Self {
+            negative: false,
+            mag: vec![value as u32, (value >> DIGIT_BITS) as u32],
+        };
+        result.normalize();
+        result
+    }
+
+    pub fn from_i64(value: i64) -> Self {
+        let mut result = Self::from_u64(value.unsigned_abs());
+        result.negative = value < 0 && !result.is_zero();
+        result
+    }
+
+    /// The integer part of a finite float, exactly.
+    pub fn from_f64(value: f64) -> Option<Self> {
+        if !value.is_finite() {
+            return None;
+        }
+        let bits = value.trunc().abs().to_bits();
+        let exponent = (bits >> 52) as i64;
+        if exponent == 0 {
+            return Some(Self::zero());
+        }
+        let mantissa = (bits & ((1 << 52) - 1)) | (1 << 52);
+        let shift = exponent - 1075;
+        let magnitude = if shift >= 0 {
+            &Self::from_u64(mantissa) << shift as u64
+        } else {
+            Self::from_u64(mantissa >> -shift)
+        };
+        Some(if value < 0.0 { -magnitude } else { magnitude })
+    }
+
+    /// Parses a run of digits in `radix` (2..=36).  No sign, prefix or underscores are
+    /// accepted here; callers strip those first.
+    pub fn from_str_radix(digits: &str, radix: u32) -> Option<Self> {
+        if digits.is_empty() || !(2..=36).contains(&radix) {
+            return None;
+        }
+        // Take as many digits at a time as fit in a digit of the result.
+        let (chunk_len, _) = chunk_power(radix);
+        let chars: Vec<char> = digits.chars().collect();
+        let mut result = Self::zero();
+        for chunk in chars.chunks(chunk_len) {
+            let mut value = 0u32;
+            for chr in chunk {
+                value = value * radix + chr.to_digit(radix)?;
+            }
+            result.mul_small_add(radix.pow(chunk.len() as u32), value);
+        }
+        Some(result)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mag.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn is_odd(&self) -> bool {
+        self.mag.first().is_some_and(|digit| digit & 1 == 1)
+    }
+
+    /// Number of bits needed to represent the magnitude, `int.bit_length()`.
+    pub fn bit_length(&self) -> u64 {
+        match self.mag.last() {
+            None => 0,
+            Some(top) => {
+                (self.mag.len() as u64 - 1) * DIGIT_BITS as u64 + (DIGIT_BITS - top.leading_zeros()) as u64
+            }
+        }
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.mag.len() > 2 {
+            return None;
+        }
+        let mut magnitude: u64 = 0;
+        for (idx, digit) in self.mag.iter().enumerate() {
+            magnitude |= (*digit as u64) << (idx as u32 * DIGIT_BITS);
+        }
+        if self.negative {
+            if magnitude <= i64::MAX as u64 + 1 {
+                Some((-(magnitude as i128)) as i64)
+            } else {
+                None
+            }
+        } else if magnitude <= i64::MAX as u64 {
+            Some(magnitude as i64)
+        } else {
+            None
+        }
+    }
+
+    /// Correctly rounded conversion (round half to even).  Returns `None` when the value is
+    /// too large for a float, which Python reports as an `OverflowError`.
+    pub fn to_f64(&self) -> Option<f64> {
+        let bits = self.bit_length();
+        let magnitude = if bits <= 64 {
+            self.low_u64() as f64
+        } else {
+            // Keep the top 64 bits and fold everything below into a sticky bit so that the
+            // u64 -> f64 conversion sees whether the discarded tail was non zero.
+            let shift = bits - 64;
+            let top = self.shr_magnitude(shift);
+            let sticky = self.has_low_bits(shift);
+            let top = top.low_u64() | sticky as u64;
+            if shift > i32::MAX as u64 {
+                return None;
+            }
+            (top as f64) * 2f64.powi(shift as i32)
+        };
+        if magnitude.is_infinite() {
+            return None;
+        }
+        Some(if self.negative { -magnitude } else { magnitude })
+    }
+
+    pub fn to_str_radix(&self, radix: u32) -> String {
+        assert!((2..=36).contains(&radix), "radix out of range");
+        if self.is_zero() {
+            return "0".to_string();
+        }
+        let (chunk_len, chunk) = chunk_power(radix);
+        let mut digits: Vec<char> = Vec::new();
+        let mut work = self.mag.clone();
+        while !work.is_empty() {
+            let mut rem = div_small_in_place(&mut work, chunk);
+            for _ in 0..chunk_len {
+                if work.is_empty() && rem == 0 {
+                    break;
+                }
+                digits.push(std::char::from_digit(rem % radix, radix).expect("digit"));
+                rem /= radix;
+            }
+        }
+        if self.negative {
+            digits.push('-');
+        }
+        digits.iter().rev().collect()
+    }
+
+    fn low_u64(&self) -> u64 {
+        let lo = *self.mag.first().unwrap_or(&0) as u64;
+        let hi = *self.mag.get(1).unwrap_or(&0) as u64;
+        lo | (hi << DIGIT_BITS)
+    }
+
+    fn shr_magnitude(&self, shift: u64) -> Self {
+        let digit_shift = (shift / DIGIT_BITS as u64) as usize;
+        let bit_shift = (shift % DIGIT_BITS as u64) as u32;
+        if digit_shift >= self.mag.len() {
+            return Self::zero();
+        }
+        let mut mag = Vec::with_capacity(self.mag.len() - digit_shift);
+        for idx in digit_shift..self.mag.len() {
+            let low = self.mag[idx] as u64;
+            let high = *self.mag.get(idx + 1).unwrap_or(&0) as u64;
+            mag.push((((high << DIGIT_BITS) | low) >> bit_shift) as u32);
+        }
+        let mut result = Self { negative: false, mag };
+        result.normalize();
+        result
+    }
+
+    fn has_low_bits(&self, bits: u64) -> bool {
+        let full = (bits / DIGIT_BITS as u64) as usize;
+        let rest = (bits % DIGIT_BITS as u64) as u32;
+        if self.mag.iter().take(full).any(|digit| *digit != 0) {
+            return true;
+        }
+        match self.mag.get(full) {
+            Some(digit) if rest > 0 => digit & ((1u32 << rest) - 1) != 0,
+            _ => false,
+        }
+    }
+
+    fn mul_small_add(&mut self, mul: u32, add: u32) {
+        let mut carry = add as u64;
+        for digit in self.mag.iter_mut() {
+            let product = *digit as u64 * mul as u64 + carry;
+            *digit = product as u32;
+            carry = product >> DIGIT_BITS;
+        }
+        if carry > 0 {
+            self.mag.push(carry as u32);
+        }
+        self.normalize();
+    }
+
+    fn normalize(&mut self) {
+        while let Some(0) = self.mag.last() {
+            self.mag.pop();
+        }
+        if self.mag.is_empty() {
+            self.negative = false;
+        }
+    }
+}
+
+/// The largest power of `radix` that fits in a digit, and its exponent.
+fn chunk_power(radix: u32) -> (usize, u32) {
+    let mut len = 1;
+    let mut power = radix;
+    while let Some(next) = power.checked_mul(radix) {
+        power = next;
+        len += 1;
+    }
+    (len, power)
+}
+
+/// Divides a little endian magnitude by `div` in place and returns the remainder.
+fn div_small_in_place(mag: &mut Vec<u32>, div: u32) -> u32 {
+    let mut rem: u64 = 0;
+    for digit in mag.iter_mut().rev() {
+        let cur = (rem << DIGIT_BITS) | *digit as u64;
+        *digit = (cur / div as u64) as u32;
+        rem = cur % div as u64;
+    }
+    while let Some(0) = mag.last() {
+        mag.pop();
+    }
+    rem as u32
+}
+
+fn cmp_magnitude(left: &[u32], right: &[u32]) -> Ordering {
+    left.len()
+        .cmp(&right.len())
+        .then_with(|| left.iter().rev().cmp(right.iter().rev()))
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_magnitude(&self.mag, &other.mag),
+            (true, true) => cmp_magnitude(&other.mag, &self.mag),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for BigInt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_str_radix(10))
+    }
+}
+
+impl std::convert::From<i64> for BigInt {
+    fn from(value: i64) -> Self {
+        Self::from_i64(value)
+    }
+}
+
+impl std::ops::Neg for BigInt {
+    type Output = BigInt;
+
+    fn neg(mut self) -> BigInt {
+        self.negative = !self.negative && !self.is_zero();
+        self
+    }
+}
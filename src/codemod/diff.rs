@@ -0,0 +1,141 @@
+//! Line-based unified diffs, for showing what a codemod would change without writing it.
+
+/// Lines of unchanged context around each hunk, as in `diff -u`.
+const CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Line {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Renders the change from `old` to `new` in unified format with `a/` and `b/` prefixed
+/// file names.  Returns an empty string when the two are identical.
+pub fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let script = diff_lines(&old_lines, &new_lines);
+    if script.iter().all(|&op| op == Line::Equal) {
+        return String::new();
+    }
+
+    // Where each operation starts in the old and the new file.
+    let mut positions = Vec::with_capacity(script.len() + 1);
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for &op in &script {
+        positions.push((old_pos, new_pos));
+        match op {
+            Line::Equal => {
+                old_pos += 1;
+                new_pos += 1;
+            }
+            Line::Delete => old_pos += 1,
+            Line::Insert => new_pos += 1,
+        }
+    }
+    positions.push((old_pos, new_pos));
+
+    let mut out = format!("--- a/{path}\n+++ b/{path}\n");
+    for (start, end) in hunks(&script) {
+        let (old_start, new_start) = positions[start];
+        let (old_end, new_end) = positions[end];
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(old_start, old_end - old_start),
+            hunk_range(new_start, new_end - new_start)
+        ));
+        for (idx, &op) in script[start..end].iter().enumerate() {
+            let (old_idx, new_idx) = positions[start + idx];
+            let (marker, line) = match op {
+                Line::Equal => (' ', old_lines[old_idx]),
+                Line::Delete => ('-', old_lines[old_idx]),
+                Line::Insert => ('+', new_lines[new_idx]),
+            };
+            out.push(marker);
+            out.push_str(line);
+            if !line.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    out
+}
+
+/// `start,len` with a 1-based start; an empty range names the line before it, like GNU diff.
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
+
+/// Groups the changed lines of `script` into hunks with up to `CONTEXT` lines around them,
+/// merging hunks whose context would touch.
+fn hunks(script: &[Line]) -> Vec<(usize, usize)> {
+    let mut out: Vec<(usize, usize)> = vec![];
+    for (idx, &op) in script.iter().enumerate() {
+        if op == Line::Equal {
+            continue;
+        }
+        let start = idx.saturating_sub(CONTEXT);
+        let end = (idx + 1 + CONTEXT).min(script.len());
+        match out.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => out.push((start, end)),
+        }
+    }
+    out
+}
+
+/// Myers' O(ND) shortest edit script between two lists of lines.
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<Line> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = n + m;
+    let idx = |k: isize| (k + max) as usize;
+    let mut v = vec![0isize; 2 * max as usize + 2];
+    let mut trace = vec![];
+
+    'search: for d in 0..=max {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[idx(k - 1)] < v[idx(k + 1)]) {
+                v[idx(k + 1)]
+            } else {
+                v[idx(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx(k)] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut script = vec![];
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && v[idx(k - 1)] < v[idx(k + 1)]) { k + 1 } else { k - 1 };
+        let prev_x = v[idx(prev_k)];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            script.push(Line::Equal);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            script.push(if x == prev_x { Line::Insert } else { Line::Delete });
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    script.reverse();
+    script
+}
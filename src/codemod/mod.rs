@@ -0,0 +1,313 @@
+//! Source-to-source rewrites for large API migrations.
+//!
+//! A [`Codemod`] looks at a parsed module and records edits: a statement replaced by a new
+//! node, or a span or a piece of source text replaced by new text.  The edits are applied to
+//! the original source, so everything they do not touch, comments and formatting included,
+//! comes out byte for byte the same.  Edits that overlap one already accepted are skipped and
+//! reported as conflicts rather than guessed at.
+
+mod diff;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::ast::unparse::Unparser;
+use crate::ast::{CompoundStatement, Decorator, Module, Span, Statement};
+use crate::parser::{parse_module, TokenArena};
+use crate::tokenizer::{Position, TokError};
+
+pub use diff::unified_diff;
+
+/// A rewrite over one module.
+pub trait Codemod {
+    /// Records the edits this codemod wants to make to `module` in `edits`.
+    fn transform<'a>(&mut self, module: &Module<'a>, edits: &mut Edits<'a>);
+}
+
+#[derive(Debug, Error)]
+pub enum CodemodError {
+    #[error("tokenizer error: {0}")]
+    Tokenizer(#[from] TokError),
+    #[error("{0}")]
+    Parser(String),
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Replaces the source bytes `start..end` with `text`.  An insertion has `start == end`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+/// The edits a codemod has proposed for one source file.
+pub struct Edits<'a> {
+    source: &'a str,
+    /// Byte offset of the start of each line.
+    line_starts: Vec<usize>,
+    indent: &'a str,
+    edits: Vec<Edit>,
+}
+
+impl<'a> Edits<'a> {
+    fn new(source: &'a str, indent: &'a str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(source.match_indices('\n').map(|(idx, _)| idx + 1));
+        Self { source, line_starts, indent, edits: vec![] }
+    }
+
+    pub fn source(&self) -> &'a str {
+        self.source
+    }
+
+    pub fn edits(&self) -> &[Edit] {
+        &self.edits
+    }
+
+    fn offset(&self, pos: Position) -> usize {
+        let line_start = self.line_starts.get(pos.line.saturating_sub(1)).copied().unwrap_or(self.source.len());
+        (line_start + pos.col).min(self.source.len())
+    }
+
+    /// Replaces the source covered by `span` with `text`.
+    pub fn replace_span(&mut self, span: Span, text: impl Into<String>) {
+        let (start, end) = (self.offset(span.start), self.offset(span.end));
+        self.edits.push(Edit { start, end, text: text.into() });
+    }
+
+    /// Replaces `fragment`, which has to be a slice of the module's source such as a
+    /// `Name::value`, with `text`.  Returns false and records nothing if it is not.
+    pub fn replace_text(&mut self, fragment: &str, text: impl Into<String>) -> bool {
+        let base = self.source.as_ptr() as usize;
+        let Some(start) = (fragment.as_ptr() as usize).checked_sub(base) else { return false };
+        if start + fragment.len() > self.source.len() {
+            return false;
+        }
+        self.edits.push(Edit { start, end: start + fragment.len(), text: text.into() });
+        true
+    }
+
+    /// Replaces `old` with `new` written at `old`'s indentation.  A decorated function or
+    /// class is replaced together with its decorators.
+    pub fn replace_statement(&mut self, old: &Statement, new: &Statement) {
+        let (start, end) = self.statement_range(old);
+        let text = self.unparse_at(start, new);
+        self.edits.push(Edit { start, end, text: text.trim_end_matches('\n').to_string() });
+    }
+
+    /// Inserts `new` on its own line before `old`, at the same indentation.
+    pub fn insert_before(&mut self, old: &Statement, new: &Statement) {
+        let (start, _) = self.statement_range(old);
+        let mut text = self.unparse_at(start, new);
+        text.push_str(self.margin(start));
+        self.edits.push(Edit { start, end: start, text });
+    }
+
+    /// Removes the lines `old` is written on, including any comment after it.  Removing the
+    /// only statement of a block leaves the block empty; replace it with `pass` instead.
+    pub fn remove_statement(&mut self, old: &Statement) {
+        let (start, end) = self.statement_range(old);
+        let line_start = start - self.margin(start).len();
+        let line_end = self.source[end..].find('\n').map_or(self.source.len(), |idx| end + idx + 1);
+        self.edits.push(Edit { start: line_start, end: line_end, text: String::new() });
+    }
+
+    fn statement_range(&self, stmt: &Statement) -> (usize, usize) {
+        let decorators: &[Decorator] = match stmt {
+            Statement::Compound(CompoundStatement::FunctionDef(f)) => &f.decorators,
+            Statement::Compound(CompoundStatement::ClassDef(c)) => &c.decorators,
+            _ => &[],
+        };
+        let span = stmt.span();
+        let start = decorators.first().map_or(span.start, |decorator| decorator.span.start);
+        (self.offset(start), self.offset(span.end))
+    }
+
+    /// The whitespace between the start of the line and `offset`.
+    fn margin(&self, offset: usize) -> &'a str {
+        let line_start = self.source[..offset].rfind('\n').map_or(0, |idx| idx + 1);
+        let prefix = &self.source[line_start..offset];
+        &prefix[..prefix.len() - prefix.trim_start().len()]
+    }
+
+    /// `stmt` as source for a statement starting at `offset`: later lines carry the margin of
+    /// the line it starts on, the first line does not since the margin is already there.
+    fn unparse_at(&self, offset: usize, stmt: &Statement) -> String {
+        let margin = self.margin(offset);
+        let mut unparser = Unparser::with_margin(self.indent, margin);
+        unparser.statement(stmt);
+        unparser.out.split_off(margin.len())
+    }
+}
+
+/// Applies the non-overlapping `edits` to `source` in order of position.  Returns the new
+/// source and the edits that were skipped because they overlap an earlier one.
+pub fn apply_edits(source: &str, mut edits: Vec<Edit>) -> (String, Vec<Edit>) {
+    edits.sort_by_key(|edit| (edit.start, edit.end));
+    let mut out = String::with_capacity(source.len());
+    let mut conflicts = vec![];
+    let mut copied = 0;
+    for edit in edits {
+        if edit.start < copied {
+            conflicts.push(edit);
+            continue;
+        }
+        out.push_str(&source[copied..edit.start]);
+        out.push_str(&edit.text);
+        copied = edit.end;
+    }
+    out.push_str(&source[copied..]);
+    (out, conflicts)
+}
+
+/// The result of running a codemod over one source text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rewrite {
+    pub source: String,
+    pub changed: bool,
+    pub conflicts: Vec<Edit>,
+}
+
+/// Parses `source`, runs `codemod` over it and applies its edits.  `name` is used in error
+/// messages.
+pub fn rewrite<C: Codemod + ?Sized>(codemod: &mut C, source: &str, name: &str) -> Result<Rewrite, CodemodError> {
+    let arena = TokenArena::from_source(source)?;
+    let module = parse_module(&arena, name).map_err(|err| CodemodError::Parser(format!("{name}: {err}")))?;
+    let mut edits = Edits::new(source, module.default_indent);
+    codemod.transform(&module, &mut edits);
+    let (new_source, conflicts) = apply_edits(source, edits.edits);
+    Ok(Rewrite { changed: new_source != source, source: new_source, conflicts })
+}
+
+/// What a codemod did, or would do, to one file.
+#[derive(Debug)]
+pub struct FileReport {
+    pub path: PathBuf,
+    /// Unified diff of the rewrite; empty if nothing changed or the file could not be read
+    /// or parsed.
+    pub diff: String,
+    pub conflicts: Vec<Edit>,
+    pub error: Option<CodemodError>,
+}
+
+/// Runs `codemod` over `path`, or over every `.py` file below it if it is a directory,
+/// skipping hidden directories and `__pycache__`.  Changed files are written back unless
+/// `dry_run` is set; either way each file's report carries the diff.
+pub fn run_path<C: Codemod + ?Sized>(codemod: &mut C, path: &Path, dry_run: bool) -> Vec<FileReport> {
+    let mut files = vec![];
+    let mut reports = vec![];
+    if let Err(err) = collect_python_files(path, &mut files) {
+        reports.push(FileReport { path: path.to_path_buf(), diff: String::new(), conflicts: vec![], error: Some(err.into()) });
+    }
+    for file in files {
+        let mut report = FileReport { path: file, diff: String::new(), conflicts: vec![], error: None };
+        if let Err(err) = run_file(codemod, &mut report, dry_run) {
+            report.error = Some(err);
+        }
+        reports.push(report);
+    }
+    reports
+}
+
+fn run_file<C: Codemod + ?Sized>(codemod: &mut C, report: &mut FileReport, dry_run: bool) -> Result<(), CodemodError> {
+    let source = fs::read_to_string(&report.path)?;
+    let name = report.path.display().to_string();
+    let result = rewrite(codemod, &source, &name)?;
+    report.conflicts = result.conflicts;
+    if result.changed {
+        report.diff = unified_diff(&name, &source, &result.source);
+        if !dry_run {
+            fs::write(&report.path, &result.source)?;
+        }
+    }
+    Ok(())
+}
+
+fn collect_python_files(path: &Path, out: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if !path.is_dir() {
+        out.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries: Vec<PathBuf> = fs::read_dir(path)?.map(|entry| entry.map(|e| e.path())).collect::<Result<_, _>>()?;
+    entries.sort();
+    for entry in entries {
+        let file_name = entry.file_name().and_then(|name| name.to_str()).unwrap_or("");
+        if entry.is_dir() {
+            if !file_name.starts_with('.') && file_name != "__pycache__" {
+                collect_python_files(&entry, out)?;
+            }
+        } else if entry.extension().is_some_and(|ext| ext == "py") {
+            out.push(entry);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rewrite, unified_diff, Codemod, CodemodError, Edits};
+    use crate::tokenizer::TokError;
+    use crate::ast::builder::{call, keyword, name, return_stmt};
+    use crate::ast::{CompoundStatement, Module, NameOrAttribute, SmallStatement, Statement, Suite};
+
+    /// Renames `old_api` imports to `new_api` and rewrites `return fetch(x, retries=n)` to
+    /// `return fetch(x, attempts=n)`.
+    struct Migrate;
+
+    impl Codemod for Migrate {
+        fn transform<'a>(&mut self, module: &Module<'a>, edits: &mut Edits<'a>) {
+            for stmt in &module.body {
+                match stmt {
+                    Statement::Simple(line) => {
+                        for small in &line.body {
+                            let module_name = match small {
+                                SmallStatement::Import(import) => Some(&import.names[0].name),
+                                SmallStatement::ImportFrom(import) => import.module.as_ref(),
+                                _ => None,
+                            };
+                            if let Some(NameOrAttribute::N(n)) = module_name {
+                                if n.value == "old_api" {
+                                    edits.replace_text(n.value, "new_api");
+                                }
+                            }
+                        }
+                    }
+                    Statement::Compound(CompoundStatement::FunctionDef(f)) => {
+                        let Suite::IndentedBlock(block) = &f.body else { continue };
+                        let old = &block.body[0];
+                        let new = return_stmt(Some(call(name("fetch"), [name("path").into(), keyword("attempts", name("n"))])));
+                        edits.replace_statement(old, &new);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rewrites_source_and_keeps_comments() {
+        let source = "import old_api  # keep me\nfrom old_api import fetch\n\n\n@cached\ndef load(path, n):\n    # read it\n    return fetch(path,  retries=n)  # old\n";
+        let result = rewrite(&mut Migrate, source, "example.py").expect("rewrite");
+        let expected = "import new_api  # keep me\nfrom new_api import fetch\n\n\n@cached\ndef load(path, n):\n    # read it\n    return fetch(path, attempts=n)  # old\n";
+        assert_eq!(result.source, expected);
+        assert!(result.changed && result.conflicts.is_empty());
+
+        let diff = unified_diff("example.py", source, &result.source);
+        assert!(diff.starts_with("--- a/example.py\n+++ b/example.py\n@@ -1,8 +1,8 @@\n-import old_api  # keep me\n"));
+        assert!(diff.contains("\n-    return fetch(path,  retries=n)  # old\n+    return fetch(path, attempts=n)  # old\n"));
+    }
+
+    #[test]
+    fn rewrites_after_continuation_lines_and_fstrings() {
+        let source = "import old_api\nmsg = f'{old_api!r:>{width}}' \\\n    'tail'\nvalues = [\n    1,\n]\nfrom old_api import fetch\n";
+        let result = rewrite(&mut Migrate, source, "example.py").expect("rewrite");
+        assert_eq!(result.source, source.replace("import old_api", "import new_api").replace("from old_api", "from new_api"));
+
+        let err = rewrite(&mut Migrate, "values = [1,\n", "example.py").unwrap_err();
+        assert!(matches!(err, CodemodError::Tokenizer(TokError::UnclosedParen('['))));
+    }
+}
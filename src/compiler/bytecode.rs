@@ -0,0 +1,406 @@
+//! The instruction set and code objects the compiler produces, after CPython's bytecode.
+//!
+//! Instructions are an enum rather than a byte string, each with its argument already
+//! decoded.  Jump targets are instruction indexes.  Exceptions are handled with a block
+//! stack, like CPython 3.10: `SetupFinally` pushes a handler, `PopBlock` removes it, and a
+//! raised exception truncates the value stack to the depth it had when the handler was
+//! pushed, pushes the exception and jumps to the handler.
+
+use std::fmt::Write;
+use std::rc::Rc;
+
+use crate::ast::ConstantValue;
+
+/// The function's locals are in an array rather than a dict.
+pub const CO_OPTIMIZED: u32 = 0x1;
+/// Each call gets a new dict or array of locals.
+pub const CO_NEWLOCALS: u32 = 0x2;
+/// Takes `*args`.
+pub const CO_VARARGS: u32 = 0x4;
+/// Takes `**kwargs`.
+pub const CO_VARKEYWORDS: u32 = 0x8;
+/// Defined inside a function.
+pub const CO_NESTED: u32 = 0x10;
+pub const CO_GENERATOR: u32 = 0x20;
+/// Has no cell or free variables.
+pub const CO_NOFREE: u32 = 0x40;
+pub const CO_COROUTINE: u32 = 0x80;
+pub const CO_ASYNC_GENERATOR: u32 = 0x200;
+
+/// `MakeFunction` flags saying which optional values are on the stack below the code.
+pub const MAKE_DEFAULTS: u32 = 0x1;
+pub const MAKE_KWDEFAULTS: u32 = 0x2;
+pub const MAKE_ANNOTATIONS: u32 = 0x4;
+pub const MAKE_CLOSURE: u32 = 0x8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOperator {
+    Positive,
+    Negative,
+    Invert,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    MatrixMultiply,
+    TrueDivide,
+    FloorDivide,
+    Remainder,
+    Power,
+    LeftShift,
+    RightShift,
+    And,
+    Or,
+    Xor,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComparisonOperator {
+    Less,
+    LessEqual,
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+}
+
+/// The `!s`, `!r` or `!a` of an f-string replacement field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conversion {
+    None,
+    Str,
+    Repr,
+    Ascii,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeParamFlavor {
+    TypeVar,
+    /// A `TypeVar` whose bound is a tuple of constraints.
+    ConstrainedTypeVar,
+    TypeVarTuple,
+    ParamSpec,
+}
+
+/// One instruction.  "TOS" is the top of the stack and "TOS1" the item below it.  Name
+/// arguments index `names`, local ones `varnames`, and deref ones `cellvars` followed by
+/// `freevars`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Nop,
+    Pop,
+    /// Pushes the `n`th item counting from the top, starting at 1.
+    Copy(u32),
+    /// Swaps TOS with the `n`th item counting from the top, starting at 1.
+    Swap(u32),
+
+    LoadConst(u32),
+    LoadName(u32),
+    StoreName(u32),
+    DeleteName(u32),
+    LoadGlobal(u32),
+    StoreGlobal(u32),
+    DeleteGlobal(u32),
+    LoadFast(u32),
+    StoreFast(u32),
+    DeleteFast(u32),
+    LoadDeref(u32),
+    StoreDeref(u32),
+    DeleteDeref(u32),
+    /// A free variable read in a class body: the class namespace wins over the cell.
+    LoadClassDeref(u32),
+    /// Pushes the namespace of a module or class body.
+    LoadLocals,
+    /// Pops a class namespace and pushes the name from it, or else the global or builtin:
+    /// a global read in an annotation scope inside a class body.
+    LoadFromDictOrGlobals(u32),
+    /// Pops a class namespace and pushes the name from it, or else the free variable: a free
+    /// variable read in an annotation scope inside a class body.
+    LoadFromDictOrDeref(u32),
+    /// Pushes the cell itself, to build a closure.
+    LoadClosure(u32),
+    LoadAttr(u32),
+    /// TOS.name = TOS1
+    StoreAttr(u32),
+    DeleteAttr(u32),
+    /// TOS1[TOS]
+    BinarySubscr,
+    /// TOS1[TOS] = TOS2
+    StoreSubscr,
+    DeleteSubscr,
+    /// `slice(lower, upper)` or `slice(lower, upper, step)` from 2 or 3 items.
+    BuildSlice(u32),
+
+    UnaryOp(UnaryOperator),
+    BinaryOp(BinaryOperator),
+    /// The augmented assignment form, which tries `__iadd__` and friends first.
+    InplaceOp(BinaryOperator),
+    CompareOp(ComparisonOperator),
+    /// `is`, or `is not` when the argument is true.
+    IsOp(bool),
+    /// `in`, or `not in` when the argument is true.
+    ContainsOp(bool),
+
+    BuildTuple(u32),
+    BuildList(u32),
+    BuildSet(u32),
+    /// A dict from `n` key and value pairs.
+    BuildMap(u32),
+    /// Concatenates `n` strs.
+    BuildString(u32),
+    /// Pops TOS and appends it to the list `n` items down.  Used by comprehensions and
+    /// displays with starred items.
+    ListAppend(u32),
+    SetAdd(u32),
+    /// Pops a value and then a key and sets them in the dict `n` items down.
+    MapAdd(u32),
+    ListExtend(u32),
+    SetUpdate(u32),
+    DictUpdate(u32),
+    /// `DictUpdate` for `**kwargs` in a call, which fails on repeated keys.
+    DictMerge(u32),
+    ListToTuple,
+    /// Formats TOS, or TOS1 with the format spec in TOS.
+    FormatValue { conversion: Conversion, has_spec: bool },
+    /// Replaces TOS with its `n` items, the first one on top.
+    UnpackSequence(u32),
+    /// Like `UnpackSequence` with a starred target, which gets a list of what is left.
+    UnpackEx { before: u32, after: u32 },
+
+    Jump(u32),
+    PopJumpIfFalse(u32),
+    PopJumpIfTrue(u32),
+    PopJumpIfNone(u32),
+    PopJumpIfNotNone(u32),
+    /// Jumps leaving TOS if it is false, otherwise pops it.
+    JumpIfFalseOrPop(u32),
+    JumpIfTrueOrPop(u32),
+    GetIter,
+    /// Pushes the next item of the iterator on top, or pops the iterator and jumps once it
+    /// is exhausted.
+    ForIter(u32),
+
+    /// Makes a function from the code on top, with the `MAKE_*` values below it.
+    MakeFunction(u32),
+    /// Calls the callable under `n` arguments.
+    Call(u32),
+    /// Like `Call` but TOS is a tuple naming the last arguments, which are keywords.
+    CallKw(u32),
+    /// Calls TOS2 (or TOS1) with the args tuple in TOS1 (or TOS), and the kwargs dict in
+    /// TOS when the argument is true.
+    CallFunctionEx(bool),
+    ReturnValue,
+
+    YieldValue,
+    /// Sends TOS to the iterator in TOS1 until it is exhausted, yielding what it yields.
+    YieldFrom,
+    GetYieldFromIter,
+    GetAwaitable,
+    GetAiter,
+    /// Pushes an awaitable for the next item of the async iterator on top.
+    GetAnext,
+    /// Handler ending an `async for`: pops the exception and the iterator if the exception
+    /// is a `StopAsyncIteration`, otherwise re-raises it.
+    EndAsyncFor,
+
+    /// Imports the module named by the argument with the `fromlist` in TOS and the level in
+    /// TOS1.
+    ImportName(u32),
+    /// Pushes the attribute from the module on top, leaving the module.
+    ImportFrom(u32),
+    ImportStar,
+
+    LoadBuildClass,
+    /// Creates `__annotations__` in the local namespace if it is not there.
+    SetupAnnotations,
+    LoadAssertionError,
+
+    /// Pushes an exception handler.
+    SetupFinally(u32),
+    /// Pushes the handler of a `with` block, for the stack depth below TOS.
+    SetupWith(u32),
+    PopBlock,
+    /// Replaces the exception on top with the exception being handled before it and then
+    /// the exception itself, which becomes the one being handled.
+    PushExcInfo,
+    /// Pops the exception saved by `PushExcInfo` and makes it the one being handled again.
+    PopExcept,
+    /// `raise`, `raise TOS` or `raise TOS1 from TOS`.
+    Raise(u32),
+    /// Raises TOS again, keeping its traceback.
+    Reraise,
+    /// Replaces the exception type on top with whether the exception in TOS1 matches it.
+    CheckExcMatch,
+    /// `except*` matching: replaces the exception type and the exception below it with what
+    /// did not match and what did (None if nothing did).
+    CheckEgMatch,
+    /// Pops the original exception group and the list of exceptions raised or left
+    /// unhandled in `except*` clauses, and pushes what to re-raise or None.
+    PrepReraiseStar,
+    /// Replaces the context manager on top with its `__exit__` and then the result of its
+    /// `__enter__`.
+    BeforeWith,
+    BeforeAsyncWith,
+    /// Calls the `__exit__` three items down with the exception on top.
+    WithExceptStart,
+
+    MatchMapping,
+    MatchSequence,
+    /// Pushes a tuple of the values of the keys tuple on top in the mapping below it, or None
+    /// if one is missing.
+    MatchKeys,
+    /// Pops the subject, the class and the tuple of keyword attribute names and pushes a
+    /// tuple of the matched attributes, or None.  The argument counts positional patterns.
+    MatchClass(u32),
+    GetLen,
+    /// Replaces the keys tuple on top with a copy of the mapping below without those keys.
+    CopyDictWithoutKeys,
+
+    /// Pops a name, a bound (or constraints) and a default, each None if absent.
+    MakeTypeParam(TypeParamFlavor),
+    /// Pops a tuple of type parameters, a name and a function that evaluates the value.
+    MakeTypeAlias,
+}
+
+impl Instruction {
+    /// The jump target of a jump or handler setup instruction.
+    pub fn jump_target(&self) -> Option<u32> {
+        let mut copy = *self;
+        copy.target_mut().copied()
+    }
+
+    pub(crate) fn target_mut(&mut self) -> Option<&mut u32> {
+        match self {
+            Self::Jump(target)
+            | Self::PopJumpIfFalse(target)
+            | Self::PopJumpIfTrue(target)
+            | Self::PopJumpIfNone(target)
+            | Self::PopJumpIfNotNone(target)
+            | Self::JumpIfFalseOrPop(target)
+            | Self::JumpIfTrueOrPop(target)
+            | Self::ForIter(target)
+            | Self::SetupFinally(target)
+            | Self::SetupWith(target) => Some(target),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Const {
+    Value(ConstantValue),
+    Code(Rc<CodeObject>),
+}
+
+/// From `start` on, up to the next entry, instructions come from line `line`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineEntry {
+    pub start: u32,
+    pub line: u32,
+}
+
+/// The compiled form of a module, class body, function, lambda or comprehension.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeObject {
+    pub name: String,
+    pub qualname: String,
+    pub filename: String,
+    pub first_lineno: usize,
+    pub flags: u32,
+    /// Positional parameters, including positional-only ones.
+    pub argcount: u32,
+    pub posonly_argcount: u32,
+    pub kwonly_argcount: u32,
+    pub instructions: Vec<Instruction>,
+    /// For functions and lambdas the first constant is the docstring, or None.
+    pub consts: Vec<Const>,
+    /// Attribute, global and non-fast local names.
+    pub names: Vec<String>,
+    /// Parameters first, then the other fast locals.
+    pub varnames: Vec<String>,
+    /// Locals closed over by nested blocks.  A parameter that is a cell is in `varnames`
+    /// too and its value moves into the cell when the frame starts.
+    pub cellvars: Vec<String>,
+    pub freevars: Vec<String>,
+    pub linetable: Vec<LineEntry>,
+}
+
+impl CodeObject {
+    /// The source line of the instruction at `offset`.
+    pub fn line_number(&self, offset: usize) -> usize {
+        let idx = self.linetable.partition_point(|entry| entry.start as usize <= offset);
+        match idx {
+            0 => self.first_lineno,
+            idx => self.linetable[idx - 1].line as usize,
+        }
+    }
+
+    /// The name of the cell or free variable a deref instruction refers to.
+    pub fn deref_name(&self, idx: u32) -> &str {
+        let idx = idx as usize;
+        match self.cellvars.get(idx) {
+            Some(name) => name,
+            None => &self.freevars[idx - self.cellvars.len()],
+        }
+    }
+
+    /// The code objects of the blocks nested directly in this one.
+    pub fn children(&self) -> impl Iterator<Item = &Rc<CodeObject>> {
+        self.consts.iter().filter_map(|c| match c {
+            Const::Code(code) => Some(code),
+            Const::Value(_) => None,
+        })
+    }
+
+    /// A listing in the spirit of `dis.dis`, one instruction per line, followed by the
+    /// listings of nested code objects.
+    pub fn disassemble(&self) -> String {
+        let mut out = String::new();
+        self.disassemble_into(&mut out);
+        out
+    }
+
+    fn disassemble_into(&self, out: &mut String) {
+        let _ = writeln!(out, "Disassembly of <code object {}>:", self.qualname);
+        let targets: Vec<u32> = self.instructions.iter().filter_map(|i| i.jump_target()).collect();
+        let mut last_line = None;
+        for (offset, instr) in self.instructions.iter().enumerate() {
+            let line = self.line_number(offset);
+            let line_col = if last_line == Some(line) { String::new() } else { line.to_string() };
+            last_line = Some(line);
+            let marker = if targets.contains(&(offset as u32)) { ">>" } else { "" };
+            let _ = write!(out, "{:>4} {:>3} {:>5} {:?}", line_col, marker, offset, instr);
+            if let Some(note) = self.describe(instr) {
+                let _ = write!(out, " ({})", note);
+            }
+            out.push('\n');
+        }
+        for child in self.children() {
+            out.push('\n');
+            child.disassemble_into(out);
+        }
+    }
+
+    fn describe(&self, instr: &Instruction) -> Option<String> {
+        use Instruction::*;
+        match *instr {
+            LoadConst(idx) => Some(match &self.consts[idx as usize] {
+                Const::Value(value) => format!("{:?}", value),
+                Const::Code(code) => format!("<code object {}>", code.qualname),
+            }),
+            LoadName(idx) | StoreName(idx) | DeleteName(idx) | LoadGlobal(idx) | StoreGlobal(idx)
+            | DeleteGlobal(idx) | LoadAttr(idx) | StoreAttr(idx) | DeleteAttr(idx) | ImportName(idx)
+            | ImportFrom(idx) | LoadFromDictOrGlobals(idx) => Some(self.names[idx as usize].clone()),
+            LoadFast(idx) | StoreFast(idx) | DeleteFast(idx) => Some(self.varnames[idx as usize].clone()),
+            LoadDeref(idx) | StoreDeref(idx) | DeleteDeref(idx) | LoadClassDeref(idx) | LoadFromDictOrDeref(idx)
+            | LoadClosure(idx) => {
+                Some(self.deref_name(idx).to_string())
+            }
+            _ => None,
+        }
+    }
+}
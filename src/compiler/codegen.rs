@@ -0,0 +1,2706 @@
+//! Lowers a `Module` to `CodeObject`s, after CPython's `compile.c`.
+//!
+//! `compile` validates the module, builds its `symtable`, runs the optimizer and then walks
+//! the tree once, emitting one code object per block.  Name lookups follow the scopes the
+//! symbol table resolved: fast locals in functions, cells and free variables for closures,
+//! and dict lookups in module and class bodies.
+//!
+//! Expressions have no spans, so a nested block is matched to its symbol table by name and
+//! statement line, in the order both walks reach them.  The symbol table visits each
+//! statement's parts in the order they are compiled for this to hold.
+//!
+//! A generic function, class or type alias is made by a function compiled from its
+//! annotation scope, which binds the type parameters and is called right away.  The value of
+//! a type alias is compiled to a function of its own, called the first time it is read.
+//!
+//! Exception handling uses the block stack described in `bytecode`.  `break`, `continue`
+//! and `return` inside `try` and `with` blocks are compiled by emitting the cleanup of each
+//! block they leave inline, including a copy of any `finally` body.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::ast::strings::StringValue;
+use crate::ast::{
+    Arg, AssignTargetExpression, Attribute, AugOp, BaseSlice, BinaryOp, BooleanOp, ClassDef, CompFor, CompOp,
+    Comparison, CompoundStatement, ConstantValue, DelTargetExpression, Dict, DictElement, Element, Expression,
+    FormattedStringContent, FormattedStringExpression, FunctionDef, If, ImportNames, Lambda, Match, MatchPattern,
+    MatchSequence, Module, OrElse, Parameters, SmallStatement, Span, StarArg, StarrableMatchSequenceElement,
+    Statement, String as StringNode, Subscript, SubscriptElement, Suite, SyntaxError, Try, TryStar, TypeAlias,
+    TypeParam, TypeParamKind, UnaryOp, With, YieldValue,
+};
+use crate::bigint::BigInt;
+
+use super::bytecode::*;
+use super::optimizer::{constant_of, optimize, OptimizerOptions};
+use super::symtable::{symtable, Scope, SymbolTable, SymbolTableType};
+
+type Result<T> = std::result::Result<T, SyntaxError>;
+
+/// CPython's `CO_MAXBLOCKS`.
+const MAX_BLOCKS: usize = 20;
+
+/// Compiles `module`, which came from `filename`, into the code object for its body.  The
+/// module is optimized in place on the way.
+pub fn compile(module: &mut Module, filename: &str, options: &OptimizerOptions) -> Result<CodeObject> {
+    if let Some(error) = module.validate().into_iter().next() {
+        return Err(error);
+    }
+    let table = symtable(module)?;
+    optimize(module, options);
+    let mut compiler = Compiler {
+        filename,
+        optimize: options.optimize,
+        future_annotations: has_future_annotations(module),
+        units: vec![],
+        span: Span::default(),
+        line: 1,
+    };
+    compiler.compile_module(module, &table)
+}
+
+fn has_future_annotations(module: &Module) -> bool {
+    module.body.iter().any(|stmt| match stmt {
+        Statement::Simple(line) => line.body.iter().any(|small| match small {
+            SmallStatement::ImportFrom(i) => {
+                i.module.as_ref().is_some_and(|m| m.dotted_name() == "__future__")
+                    && matches!(&i.names, ImportNames::Aliases(aliases)
+                        if aliases.iter().any(|alias| alias.name.dotted_name() == "annotations"))
+            }
+            _ => false,
+        }),
+        Statement::Compound(_) | Statement::Error(_) => false,
+    })
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Label(usize);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Ctx {
+    Load,
+    Store,
+    Del,
+}
+
+/// What a `break`, `continue` or `return` has to clean up when it leaves a block.
+#[derive(Clone, Copy)]
+enum FBlock<'c, 'a> {
+    WhileLoop { start: Label, exit: Label },
+    /// The iterator is on the stack.
+    ForLoop { start: Label, exit: Label },
+    TryExcept,
+    FinallyTry(&'c Suite<'a>),
+    /// The `finally` body run for an exception, with the previous and current exception on
+    /// the stack.
+    FinallyEnd,
+    /// `__exit__` is on the stack.
+    With { is_async: bool },
+    /// An `except` body, with the previous exception on the stack and the name it binds.
+    HandlerCleanup(Option<&'a str>),
+    ExceptStarHandler,
+    /// A value on the stack, such as a return value kept while a `finally` body runs.
+    PopValue,
+}
+
+impl<'c, 'a> FBlock<'c, 'a> {
+    fn is_loop(&self) -> bool {
+        matches!(self, Self::WhileLoop { .. } | Self::ForLoop { .. })
+    }
+}
+
+#[derive(Clone, Copy)]
+enum UnitKind<'c, 'a> {
+    Module,
+    Class,
+    Function(&'c Parameters<'a>),
+    Lambda(&'c Parameters<'a>),
+    Comprehension { is_async: bool },
+    /// The type parameters of a generic, whose parameters are the defaults of a generic
+    /// function, or the value of a type alias.
+    Annotation,
+}
+
+/// The code object being built for one block.
+struct Unit<'c, 'a> {
+    table: &'c SymbolTable<'a>,
+    /// The next child of `table` that has not been compiled yet.
+    next_child: usize,
+    name: String,
+    qualname: String,
+    /// The class whose private names are mangled in this block.
+    private: Option<&'a str>,
+    first_lineno: usize,
+    flags: u32,
+    argcount: u32,
+    posonly_argcount: u32,
+    kwonly_argcount: u32,
+    instructions: Vec<Instruction>,
+    lines: Vec<u32>,
+    /// Where each label was bound.  Jumps hold label numbers until the unit is finished.
+    labels: Vec<Option<u32>>,
+    consts: Vec<Const>,
+    names: Vec<String>,
+    name_index: HashMap<String, u32>,
+    varnames: Vec<String>,
+    cellvars: Vec<String>,
+    freevars: Vec<String>,
+    fblocks: Vec<FBlock<'c, 'a>>,
+}
+
+#[derive(Clone, Copy)]
+enum CompKind<'c, 'a> {
+    List(&'c Expression<'a>),
+    Set(&'c Expression<'a>),
+    Dict(&'c Expression<'a>, &'c Expression<'a>),
+    Generator(&'c Expression<'a>),
+}
+
+impl<'c, 'a> CompKind<'c, 'a> {
+    fn table_name(&self) -> &'static str {
+        match self {
+            Self::List(_) => "listcomp",
+            Self::Set(_) => "setcomp",
+            Self::Dict(..) => "dictcomp",
+            Self::Generator(_) => "genexpr",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Collection {
+    Tuple,
+    List,
+    Set,
+}
+
+/// A statement list of a block body, which is either indented statements or small
+/// statements on the header's line.
+#[derive(Clone, Copy)]
+enum Body<'c, 'a> {
+    Statements(&'c [Statement<'a>]),
+    Small(&'c [SmallStatement<'a>]),
+}
+
+impl<'c, 'a> Body<'c, 'a> {
+    fn of(suite: &'c Suite<'a>) -> Self {
+        match suite {
+            Suite::IndentedBlock(block) => Self::Statements(&block.body),
+            Suite::SimpleStatementSuite(suite) => Self::Small(&suite.body),
+        }
+    }
+
+    fn first_small(&self) -> Option<&'c SmallStatement<'a>> {
+        match self {
+            Self::Statements(stmts) => match stmts.first()? {
+                Statement::Simple(line) => line.body.first(),
+                Statement::Compound(_) | Statement::Error(_) => None,
+            },
+            Self::Small(smalls) => smalls.first(),
+        }
+    }
+
+    /// The docstring, if the first statement is a string literal.
+    fn docstring(&self) -> Option<std::string::String> {
+        match self.first_small()? {
+            SmallStatement::Expr(e) => match constant_of(&e.value) {
+                Some(ConstantValue::Str(s)) => Some(s),
+                _ => match &e.value {
+                    Expression::ConcatenatedString(c) => match c.parse_value()? {
+                        Ok(StringValue::Str(s)) => Some(s),
+                        _ => None,
+                    },
+                    _ => None,
+                },
+            },
+            _ => None,
+        }
+    }
+
+    /// Whether an annotated assignment runs in this body, outside nested functions and
+    /// classes, so the body needs an `__annotations__` dict.
+    fn has_annotations(&self) -> bool {
+        match self {
+            Self::Small(smalls) => smalls.iter().any(|s| matches!(s, SmallStatement::AnnAssign(_))),
+            Self::Statements(stmts) => stmts.iter().any(|stmt| match stmt {
+                Statement::Simple(line) => Self::Small(&line.body).has_annotations(),
+                Statement::Compound(c) => compound_suites(c).into_iter().any(|s| Self::of(s).has_annotations()),
+                Statement::Error(_) => false,
+            }),
+        }
+    }
+}
+
+/// The suites of a compound statement that run in the same scope as the statement.
+fn compound_suites<'c, 'a>(stmt: &'c CompoundStatement<'a>) -> Vec<&'c Suite<'a>> {
+    let mut suites = vec![];
+    match stmt {
+        CompoundStatement::FunctionDef(_) | CompoundStatement::ClassDef(_) => {}
+        CompoundStatement::If(i) => {
+            let mut branch = Some(i);
+            while let Some(i) = branch {
+                suites.push(&i.body);
+                branch = match i.orelse.as_deref() {
+                    Some(OrElse::Elif(elif)) => Some(elif),
+                    Some(OrElse::Else(orelse)) => {
+                        suites.push(&orelse.body);
+                        None
+                    }
+                    None => None,
+                };
+            }
+        }
+        CompoundStatement::For(f) => {
+            suites.push(&f.body);
+            suites.extend(f.orelse.iter().map(|e| &e.body));
+        }
+        CompoundStatement::While(w) => {
+            suites.push(&w.body);
+            suites.extend(w.orelse.iter().map(|e| &e.body));
+        }
+        CompoundStatement::Try(t) => {
+            suites.push(&t.body);
+            suites.extend(t.handlers.iter().map(|h| &h.body));
+            suites.extend(t.orelse.iter().map(|e| &e.body));
+            suites.extend(t.finalbody.iter().map(|f| &f.body));
+        }
+        CompoundStatement::TryStar(t) => {
+            suites.push(&t.body);
+            suites.extend(t.handlers.iter().map(|h| &h.body));
+            suites.extend(t.orelse.iter().map(|e| &e.body));
+            suites.extend(t.finalbody.iter().map(|f| &f.body));
+        }
+        CompoundStatement::With(w) => suites.push(&w.body),
+        CompoundStatement::Match(m) => suites.extend(m.cases.iter().map(|c| &c.body)),
+    }
+    suites
+}
+
+/// State for compiling one `case` pattern.  A failed match jumps to `fail_pop[n]`, which
+/// pops the `n` items the pattern left on the stack before trying the next case.
+#[derive(Default)]
+struct PatternContext {
+    fail_pop: Vec<Label>,
+    /// Items pushed by enclosing patterns that a failure has to pop.
+    on_top: usize,
+}
+
+/// A piece of an f-string or of implicitly concatenated strings.
+enum Piece<'c, 'a> {
+    Text(std::string::String),
+    Field(&'c FormattedStringExpression<'a>, &'a str),
+}
+
+struct Compiler<'c, 'a> {
+    filename: &'c str,
+    optimize: u8,
+    /// `from __future__ import annotations` is in effect: annotations are stored as strings.
+    future_annotations: bool,
+    units: Vec<Unit<'c, 'a>>,
+    /// The statement being compiled.  Errors point at it and nested blocks are looked up by
+    /// its line.
+    span: Span,
+    /// The line instructions are attributed to.
+    line: usize,
+}
+
+impl<'c, 'a> Compiler<'c, 'a> {
+    fn error(&self, message: impl Into<std::string::String>) -> SyntaxError {
+        SyntaxError::new(message, self.span)
+    }
+
+    fn unit(&self) -> &Unit<'c, 'a> {
+        self.units.last().expect("compilation unit")
+    }
+
+    fn unit_mut(&mut self) -> &mut Unit<'c, 'a> {
+        self.units.last_mut().expect("compilation unit")
+    }
+
+    // Units
+
+    fn compile_module(&mut self, module: &'c Module<'a>, table: &'c SymbolTable<'a>) -> Result<CodeObject> {
+        self.enter_unit(table, "<module>", UnitKind::Module);
+        self.compile_namespace_body(Body::Statements(&module.body))?;
+        self.load_const(ConstantValue::None);
+        self.emit(Instruction::ReturnValue);
+        Ok(self.leave_unit())
+    }
+
+    fn enter_unit(&mut self, table: &'c SymbolTable<'a>, name: &str, kind: UnitKind<'c, 'a>) {
+        let qualname = self.qualname(name);
+        let private = match kind {
+            UnitKind::Class => Some(table.name()),
+            _ => self.units.last().and_then(|unit| unit.private),
+        };
+        let mangle = |name: &str| mangle(private, name);
+
+        let mut flags = 0;
+        let mut varnames = vec![];
+        let (mut argcount, mut posonly_argcount, mut kwonly_argcount) = (0, 0, 0);
+        let mut consts = vec![];
+        match kind {
+            UnitKind::Function(params) | UnitKind::Lambda(params) => {
+                let positional = params.posonly_params.iter().chain(params.params.iter());
+                let star_arg = match &params.star_arg {
+                    Some(StarArg::Param(param)) => Some(param.as_ref()),
+                    _ => None,
+                };
+                let ordered = positional.chain(params.kwonly_params.iter()).chain(star_arg).chain(params.star_kwarg.iter());
+                varnames.extend(ordered.map(|param| mangle(param.name.value)));
+                argcount = (params.posonly_params.len() + params.params.len()) as u32;
+                posonly_argcount = params.posonly_params.len() as u32;
+                kwonly_argcount = params.kwonly_params.len() as u32;
+                if star_arg.is_some() {
+                    flags |= CO_VARARGS;
+                }
+                if params.star_kwarg.is_some() {
+                    flags |= CO_VARKEYWORDS;
+                }
+                // The docstring of a function, filled in by `compile_function_def`.
+                consts.push(Const::Value(ConstantValue::None));
+            }
+            UnitKind::Comprehension { .. } => {
+                varnames.push(".0".to_string());
+                argcount = 1;
+            }
+            UnitKind::Annotation => {
+                varnames.extend(table.parameters().into_iter().map(str::to_string));
+                argcount = varnames.len() as u32;
+            }
+            UnitKind::Module | UnitKind::Class => {}
+        }
+
+        let mut cellvars = vec![];
+        let mut freevars = vec![];
+        for sym in table.symbols() {
+            let name = mangle(sym.name());
+            match sym.scope() {
+                Scope::Local if table.is_optimized() && !varnames.contains(&name) => varnames.push(name),
+                Scope::Cell => cellvars.push(name),
+                Scope::Free => freevars.push(name),
+                Scope::Local if sym.is_free_class() => freevars.push(name),
+                _ => {}
+            }
+        }
+        if table.needs_class_closure() {
+            cellvars.push("__class__".to_string());
+        }
+        if table.needs_classdict() {
+            cellvars.push("__classdict__".to_string());
+        }
+        cellvars.sort();
+        freevars.sort();
+        freevars.dedup();
+
+        if table.is_optimized() {
+            flags |= CO_OPTIMIZED | CO_NEWLOCALS;
+            if table.is_nested() {
+                flags |= CO_NESTED;
+            }
+            let is_async = match kind {
+                UnitKind::Comprehension { is_async } => is_async,
+                _ => table.is_coroutine(),
+            };
+            flags |= match (table.is_generator(), is_async) {
+                (true, true) => CO_ASYNC_GENERATOR,
+                (true, false) => CO_GENERATOR,
+                (false, true) => CO_COROUTINE,
+                (false, false) => 0,
+            };
+        }
+        if cellvars.is_empty() && freevars.is_empty() {
+            flags |= CO_NOFREE;
+        }
+
+        self.units.push(Unit {
+            table,
+            next_child: 0,
+            name: name.to_string(),
+            qualname,
+            private,
+            first_lineno: if matches!(kind, UnitKind::Module) { 1 } else { self.span.start.line },
+            flags,
+            argcount,
+            posonly_argcount,
+            kwonly_argcount,
+            instructions: vec![],
+            lines: vec![],
+            labels: vec![],
+            consts,
+            names: vec![],
+            name_index: HashMap::new(),
+            varnames,
+            cellvars,
+            freevars,
+            fblocks: vec![],
+        });
+    }
+
+    fn leave_unit(&mut self) -> CodeObject {
+        let unit = self.units.pop().expect("compilation unit");
+        let mut instructions = unit.instructions;
+        for instr in instructions.iter_mut() {
+            if let Some(target) = instr.target_mut() {
+                *target = unit.labels[*target as usize].expect("jump to an unbound label");
+            }
+        }
+        let mut linetable: Vec<LineEntry> = vec![];
+        for (offset, line) in unit.lines.iter().enumerate() {
+            if linetable.last().map(|entry| entry.line) != Some(*line) {
+                linetable.push(LineEntry { start: offset as u32, line: *line });
+            }
+        }
+        CodeObject {
+            name: unit.name,
+            qualname: unit.qualname,
+            filename: self.filename.to_string(),
+            first_lineno: unit.first_lineno,
+            flags: unit.flags,
+            argcount: unit.argcount,
+            posonly_argcount: unit.posonly_argcount,
+            kwonly_argcount: unit.kwonly_argcount,
+            instructions,
+            consts: unit.consts,
+            names: unit.names,
+            varnames: unit.varnames,
+            cellvars: unit.cellvars,
+            freevars: unit.freevars,
+            linetable,
+        }
+    }
+
+    /// CPython's `__qualname__` for a block named `name` nested in the current one.  What an
+    /// annotation scope defines is named as if it was defined outside it.
+    fn qualname(&self, name: &str) -> std::string::String {
+        let mut parents = self.units.iter().rev().skip_while(|unit| unit.table.kind() == SymbolTableType::TypeParameters);
+        let Some(parent) = parents.next() else { return name.to_string() };
+        let declared_global = parent.table.lookup(name).is_some_and(|sym| sym.scope() == Scope::GlobalExplicit);
+        match parent.table.kind() {
+            SymbolTableType::Module => name.to_string(),
+            _ if declared_global => name.to_string(),
+            SymbolTableType::Function | SymbolTableType::TypeParameters | SymbolTableType::TypeAlias => {
+                format!("{}.<locals>.{}", parent.qualname, name)
+            }
+            SymbolTableType::Class => format!("{}.{}", parent.qualname, name),
+        }
+    }
+
+    /// The symbol table of the next nested block called `name` defined on the current line.
+    fn child_table(&mut self, name: &str) -> Result<&'c SymbolTable<'a>> {
+        let line = self.span.start.line;
+        let unit = self.unit_mut();
+        let children = unit.table.children();
+        let matches = |idx: &usize| children[*idx].name() == name && children[*idx].lineno() == line;
+        // Blocks in code the optimizer removed are skipped over.  Code compiled twice, like a
+        // `finally` body, finds its blocks again from the start.
+        let found = (unit.next_child..children.len()).find(matches).or_else(|| (0..children.len()).find(matches));
+        match found {
+            Some(idx) => {
+                unit.next_child = idx + 1;
+                Ok(&children[idx])
+            }
+            None => Err(self.error(format!("no symbol table for '{}'", name))),
+        }
+    }
+
+    /// Compiles `body` a second time, such as a `finally` body copied into each exit from
+    /// its `try` block, without disturbing the order blocks are matched up in.
+    fn compile_copy(&mut self, body: &'c Suite<'a>) -> Result<()> {
+        let next_child = self.unit().next_child;
+        let result = self.compile_suite(body);
+        self.unit_mut().next_child = next_child;
+        result
+    }
+
+    // Emitting
+
+    fn emit(&mut self, instr: Instruction) {
+        let line = self.line as u32;
+        let unit = self.unit_mut();
+        unit.instructions.push(instr);
+        unit.lines.push(line);
+    }
+
+    fn new_label(&mut self) -> Label {
+        let unit = self.unit_mut();
+        unit.labels.push(None);
+        Label(unit.labels.len() - 1)
+    }
+
+    fn bind(&mut self, label: Label) {
+        let unit = self.unit_mut();
+        unit.labels[label.0] = Some(unit.instructions.len() as u32);
+    }
+
+    fn emit_jump(&mut self, jump: fn(u32) -> Instruction, label: Label) {
+        self.emit(jump(label.0 as u32));
+    }
+
+    fn add_const(&mut self, value: Const) -> u32 {
+        let consts = &mut self.unit_mut().consts;
+        // Code objects are never shared, and the docstring slot of a function stays first.
+        if let Const::Value(_) = value {
+            if let Some(idx) = consts.iter().position(|c| *c == value) {
+                return idx as u32;
+            }
+        }
+        consts.push(value);
+        (consts.len() - 1) as u32
+    }
+
+    fn load_const(&mut self, value: ConstantValue) {
+        let idx = self.add_const(Const::Value(value));
+        self.emit(Instruction::LoadConst(idx));
+    }
+
+    fn load_str(&mut self, value: &str) {
+        self.load_const(ConstantValue::Str(value.to_string()));
+    }
+
+    fn name_idx(&mut self, name: &str) -> u32 {
+        let unit = self.unit_mut();
+        if let Some(idx) = unit.name_index.get(name) {
+            return *idx;
+        }
+        unit.names.push(name.to_string());
+        let idx = (unit.names.len() - 1) as u32;
+        unit.name_index.insert(name.to_string(), idx);
+        idx
+    }
+
+    fn mangle(&self, name: &str) -> std::string::String {
+        mangle(self.unit().private, name)
+    }
+
+    fn push_fblock(&mut self, block: FBlock<'c, 'a>) -> Result<()> {
+        if self.unit().fblocks.len() >= MAX_BLOCKS {
+            return Err(self.error("too many statically nested blocks"));
+        }
+        self.unit_mut().fblocks.push(block);
+        Ok(())
+    }
+
+    fn pop_fblock(&mut self) {
+        self.unit_mut().fblocks.pop();
+    }
+
+    // Names
+
+    fn name_op(&mut self, name: &str, ctx: Ctx) -> Result<()> {
+        if ctx == Ctx::Load {
+            let constant = match name {
+                "None" => Some(ConstantValue::None),
+                "True" => Some(ConstantValue::Bool(true)),
+                "False" => Some(ConstantValue::Bool(false)),
+                "__debug__" => Some(ConstantValue::Bool(self.optimize == 0)),
+                _ => None,
+            };
+            if let Some(constant) = constant {
+                self.load_const(constant);
+                return Ok(());
+            }
+        }
+        let mangled = self.mangle(name);
+        let table = self.unit().table;
+        let optimized = table.is_optimized();
+        let scope = table.lookup(name).map(|sym| sym.scope());
+        // An annotation scope in a class body looks in the class namespace first.
+        if ctx == Ctx::Load && table.can_see_class_scope() && name != "__classdict__" {
+            let instr = match scope {
+                Some(Scope::Free) => Some(Instruction::LoadFromDictOrDeref(self.deref_index(&mangled)?)),
+                Some(Scope::GlobalImplicit) => Some(Instruction::LoadFromDictOrGlobals(self.name_idx(&mangled))),
+                _ => None,
+            };
+            if let Some(instr) = instr {
+                let classdict = self.deref_index("__classdict__")?;
+                self.emit(Instruction::LoadDeref(classdict));
+                self.emit(instr);
+                return Ok(());
+            }
+        }
+        let instr = match scope {
+            Some(Scope::Free | Scope::Cell) => {
+                let idx = self.deref_index(&mangled)?;
+                match ctx {
+                    Ctx::Load if table.kind() == SymbolTableType::Class => Instruction::LoadClassDeref(idx),
+                    Ctx::Load => Instruction::LoadDeref(idx),
+                    Ctx::Store => Instruction::StoreDeref(idx),
+                    Ctx::Del => Instruction::DeleteDeref(idx),
+                }
+            }
+            Some(Scope::Local) if optimized => {
+                let unit = self.unit_mut();
+                let idx = match unit.varnames.iter().position(|v| *v == mangled) {
+                    Some(idx) => idx,
+                    None => {
+                        unit.varnames.push(mangled);
+                        unit.varnames.len() - 1
+                    }
+                } as u32;
+                match ctx {
+                    Ctx::Load => Instruction::LoadFast(idx),
+                    Ctx::Store => Instruction::StoreFast(idx),
+                    Ctx::Del => Instruction::DeleteFast(idx),
+                }
+            }
+            Some(Scope::GlobalExplicit) | Some(Scope::GlobalImplicit) if optimized => self.global_op(&mangled, ctx),
+            Some(Scope::GlobalExplicit) => self.global_op(&mangled, ctx),
+            _ => {
+                let idx = self.name_idx(&mangled);
+                match ctx {
+                    Ctx::Load => Instruction::LoadName(idx),
+                    Ctx::Store => Instruction::StoreName(idx),
+                    Ctx::Del => Instruction::DeleteName(idx),
+                }
+            }
+        };
+        self.emit(instr);
+        Ok(())
+    }
+
+    fn global_op(&mut self, name: &str, ctx: Ctx) -> Instruction {
+        let idx = self.name_idx(name);
+        match ctx {
+            Ctx::Load => Instruction::LoadGlobal(idx),
+            Ctx::Store => Instruction::StoreGlobal(idx),
+            Ctx::Del => Instruction::DeleteGlobal(idx),
+        }
+    }
+
+    /// Stores TOS under a name the compiler made up, like `__doc__`.
+    fn store_dunder(&mut self, name: &str) {
+        let idx = self.name_idx(name);
+        self.emit(Instruction::StoreName(idx));
+    }
+
+    fn deref_index(&self, name: &str) -> Result<u32> {
+        let unit = self.unit();
+        if let Some(idx) = unit.cellvars.iter().position(|v| v == name) {
+            return Ok(idx as u32);
+        }
+        match unit.freevars.iter().position(|v| v == name) {
+            Some(idx) => Ok((unit.cellvars.len() + idx) as u32),
+            None => Err(self.error(format!("no cell for '{}'", name))),
+        }
+    }
+
+    /// Makes a function from `code`, with the closure it needs and whatever `flags` says is
+    /// already on the stack.
+    fn make_closure(&mut self, code: CodeObject, mut flags: u32) -> Result<()> {
+        if !code.freevars.is_empty() {
+            for name in &code.freevars {
+                let idx = self.deref_index(name)?;
+                self.emit(Instruction::LoadClosure(idx));
+            }
+            self.emit(Instruction::BuildTuple(code.freevars.len() as u32));
+            flags |= MAKE_CLOSURE;
+        }
+        let idx = self.add_const(Const::Code(Rc::new(code)));
+        self.emit(Instruction::LoadConst(idx));
+        self.emit(Instruction::MakeFunction(flags));
+        Ok(())
+    }
+
+    // Statements
+
+    /// The body of a module or class, whose docstring and annotations go in its namespace.
+    fn compile_namespace_body(&mut self, body: Body<'c, 'a>) -> Result<()> {
+        if body.has_annotations() {
+            self.emit(Instruction::SetupAnnotations);
+        }
+        let docstring = body.docstring();
+        if let Some(doc) = &docstring {
+            if self.optimize < 2 {
+                self.load_str(doc);
+                self.store_dunder("__doc__");
+            }
+        }
+        self.compile_body(body, docstring.is_some())
+    }
+
+    fn compile_body(&mut self, body: Body<'c, 'a>, skip_docstring: bool) -> Result<()> {
+        match body {
+            Body::Statements(stmts) => {
+                for (idx, stmt) in stmts.iter().enumerate() {
+                    match stmt {
+                        Statement::Simple(line) if idx == 0 && skip_docstring => {
+                            let (span, saved_line) = (std::mem::replace(&mut self.span, line.span), self.line);
+                            self.line = line.span.start.line;
+                            for small in &line.body[1..] {
+                                self.compile_small_statement(small)?;
+                            }
+                            self.span = span;
+                            self.line = saved_line;
+                        }
+                        _ => self.compile_statement(stmt)?,
+                    }
+                }
+            }
+            Body::Small(smalls) => {
+                let skip = usize::from(skip_docstring);
+                for small in &smalls[skip..] {
+                    self.compile_small_statement(small)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_suite(&mut self, suite: &'c Suite<'a>) -> Result<()> {
+        self.compile_body(Body::of(suite), false)
+    }
+
+    fn compile_statement(&mut self, stmt: &'c Statement<'a>) -> Result<()> {
+        let span = std::mem::replace(&mut self.span, stmt.span());
+        let line = std::mem::replace(&mut self.line, stmt.span().start.line);
+        let result = match stmt {
+            Statement::Simple(line) => line.body.iter().try_for_each(|small| self.compile_small_statement(small)),
+            Statement::Compound(stmt) => self.compile_compound_statement(stmt),
+            Statement::Error(_) => Err(self.error("invalid syntax")),
+        };
+        self.span = span;
+        self.line = line;
+        result
+    }
+
+    fn compile_small_statement(&mut self, stmt: &'c SmallStatement<'a>) -> Result<()> {
+        match stmt {
+            SmallStatement::Pass | SmallStatement::Global(_) | SmallStatement::Nonlocal(_) => Ok(()),
+            SmallStatement::Break(_) => self.compile_break(),
+            SmallStatement::Continue(_) => self.compile_continue(),
+            SmallStatement::Return(r) => self.compile_return(r.value.as_ref()),
+            SmallStatement::Expr(e) => {
+                // A constant on its own, like a string used as a comment, does nothing.
+                if constant_of(&e.value).is_some() {
+                    return Ok(());
+                }
+                self.compile_expression(&e.value)?;
+                self.emit(Instruction::Pop);
+                Ok(())
+            }
+            SmallStatement::Assert(a) => {
+                if self.optimize > 0 {
+                    return Ok(());
+                }
+                let end = self.new_label();
+                self.compile_jump_if(&a.test, true, end)?;
+                self.emit(Instruction::LoadAssertionError);
+                if let Some(msg) = &a.msg {
+                    self.compile_expression(msg)?;
+                    self.emit(Instruction::Call(1));
+                }
+                self.emit(Instruction::Raise(1));
+                self.bind(end);
+                Ok(())
+            }
+            SmallStatement::Import(i) => {
+                for alias in &i.names {
+                    let dotted = alias.name.dotted_name();
+                    self.load_const(ConstantValue::Int(BigInt::zero()));
+                    self.load_const(ConstantValue::None);
+                    let idx = self.name_idx(&dotted);
+                    self.emit(Instruction::ImportName(idx));
+                    match &alias.asname {
+                        Some(asname) => {
+                            // `import a.b.c as d` binds the submodule, not `a`.
+                            for part in dotted.split('.').skip(1) {
+                                let idx = self.name_idx(part);
+                                self.emit(Instruction::ImportFrom(idx));
+                                self.emit(Instruction::Swap(2));
+                                self.emit(Instruction::Pop);
+                            }
+                            self.compile_store(&asname.name)?;
+                        }
+                        None => {
+                            let top = dotted.split('.').next().unwrap_or(&dotted);
+                            self.name_op(top, Ctx::Store)?;
+                        }
+                    }
+                }
+                Ok(())
+            }
+            SmallStatement::ImportFrom(i) => {
+                self.load_const(ConstantValue::Int(BigInt::from(i.relative.len() as i64)));
+                let names = match &i.names {
+                    ImportNames::Star(_) => vec![ConstantValue::Str("*".to_string())],
+                    ImportNames::Aliases(aliases) => {
+                        aliases.iter().map(|alias| ConstantValue::Str(alias.name.dotted_name())).collect()
+                    }
+                };
+                self.load_const(ConstantValue::Tuple(names));
+                let module = i.module.as_ref().map(|m| m.dotted_name()).unwrap_or_default();
+                let idx = self.name_idx(&module);
+                self.emit(Instruction::ImportName(idx));
+                match &i.names {
+                    ImportNames::Star(_) => self.emit(Instruction::ImportStar),
+                    ImportNames::Aliases(aliases) => {
+                        for alias in aliases {
+                            let name = alias.name.dotted_name();
+                            let idx = self.name_idx(&name);
+                            self.emit(Instruction::ImportFrom(idx));
+                            match &alias.asname {
+                                Some(asname) => self.compile_store(&asname.name)?,
+                                None => self.name_op(&name, Ctx::Store)?,
+                            }
+                        }
+                        self.emit(Instruction::Pop);
+                    }
+                }
+                Ok(())
+            }
+            SmallStatement::Assign(a) => {
+                self.compile_expression(&a.value)?;
+                for (idx, target) in a.targets.iter().enumerate() {
+                    if idx + 1 < a.targets.len() {
+                        self.emit(Instruction::Copy(1));
+                    }
+                    self.compile_store(&target.target)?;
+                }
+                Ok(())
+            }
+            SmallStatement::AnnAssign(a) => {
+                if let Some(value) = &a.value {
+                    self.compile_expression(value)?;
+                    self.compile_store(&a.target)?;
+                }
+                // Annotations of locals in a function are never evaluated.
+                if self.unit().table.is_optimized() {
+                    return Ok(());
+                }
+                self.compile_annotation(&a.annotation.annotation)?;
+                match &a.target {
+                    AssignTargetExpression::Name(n) => {
+                        let idx = self.name_idx("__annotations__");
+                        self.emit(Instruction::LoadName(idx));
+                        let name = self.mangle(n.value);
+                        self.load_str(&name);
+                        self.emit(Instruction::StoreSubscr);
+                    }
+                    _ => self.emit(Instruction::Pop),
+                }
+                Ok(())
+            }
+            SmallStatement::Raise(r) => {
+                let mut argc = 0;
+                if let Some(exc) = &r.exc {
+                    self.compile_expression(exc)?;
+                    argc += 1;
+                    if let Some(cause) = &r.cause {
+                        self.compile_expression(&cause.item)?;
+                        argc += 1;
+                    }
+                }
+                self.emit(Instruction::Raise(argc));
+                Ok(())
+            }
+            SmallStatement::AugAssign(a) => self.compile_aug_assign(&a.target, &a.operator, &a.value),
+            SmallStatement::Del(d) => match &d.target {
+                DelTargetExpression::Name(n) => self.name_op(n.value, Ctx::Del),
+                DelTargetExpression::Attribute(a) => self.compile_attribute(a, Ctx::Del),
+                DelTargetExpression::Subscript(s) => self.compile_subscript(s, Ctx::Del),
+                DelTargetExpression::Tuple(t) => self.compile_delete_elements(&t.elements),
+                DelTargetExpression::List(l) => self.compile_delete_elements(&l.elements),
+            },
+            SmallStatement::TypeAlias(t) => {
+                if t.type_params.is_empty() {
+                    self.compile_type_alias(t)?;
+                } else {
+                    self.compile_generic(t.name.value, &t.type_params, 0, |this| this.compile_type_alias(t))?;
+                }
+                self.name_op(t.name.value, Ctx::Store)
+            }
+        }
+    }
+
+    fn compile_annotation(&mut self, annotation: &'c Expression<'a>) -> Result<()> {
+        if self.future_annotations {
+            self.load_str(&annotation.to_source());
+            Ok(())
+        } else {
+            self.compile_expression(annotation)
+        }
+    }
+
+    fn compile_aug_assign(&mut self, target: &'c AssignTargetExpression<'a>, op: &AugOp, value: &'c Expression<'a>) -> Result<()> {
+        let op = Instruction::InplaceOp(aug_operator(op));
+        match target {
+            AssignTargetExpression::Name(n) => {
+                self.name_op(n.value, Ctx::Load)?;
+                self.compile_expression(value)?;
+                self.emit(op);
+                self.name_op(n.value, Ctx::Store)
+            }
+            AssignTargetExpression::Attribute(a) => {
+                self.compile_expression(&a.value)?;
+                self.emit(Instruction::Copy(1));
+                let idx = self.attr_idx(a.attr.value);
+                self.emit(Instruction::LoadAttr(idx));
+                self.compile_expression(value)?;
+                self.emit(op);
+                self.emit(Instruction::Swap(2));
+                self.emit(Instruction::StoreAttr(idx));
+                Ok(())
+            }
+            AssignTargetExpression::Subscript(s) => {
+                self.compile_expression(&s.value)?;
+                self.compile_slice(&s.slice)?;
+                self.emit(Instruction::Copy(2));
+                self.emit(Instruction::Copy(2));
+                self.emit(Instruction::BinarySubscr);
+                self.compile_expression(value)?;
+                self.emit(op);
+                self.emit(Instruction::Swap(3));
+                self.emit(Instruction::Swap(2));
+                self.emit(Instruction::StoreSubscr);
+                Ok(())
+            }
+            _ => Err(self.error("illegal expression for augmented assignment")),
+        }
+    }
+
+    fn compile_delete_elements(&mut self, elements: &'c [Element<'a>]) -> Result<()> {
+        for element in elements {
+            match element {
+                Element::Simple { value } => self.compile_delete_expression(value)?,
+                Element::Starred(_) => return Err(self.error("cannot delete starred")),
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_delete_expression(&mut self, expr: &'c Expression<'a>) -> Result<()> {
+        match expr {
+            Expression::Name(n) => self.name_op(n.value, Ctx::Del),
+            Expression::Attribute(a) => self.compile_attribute(a, Ctx::Del),
+            Expression::Subscript(s) => self.compile_subscript(s, Ctx::Del),
+            Expression::Tuple(t) => self.compile_delete_elements(&t.elements),
+            Expression::List(l) => self.compile_delete_elements(&l.elements),
+            _ => Err(self.error("cannot delete expression")),
+        }
+    }
+
+    fn compile_break(&mut self) -> Result<()> {
+        match self.unwind_fblock_stack(false, true)? {
+            Some(block @ FBlock::ForLoop { exit, .. }) => {
+                self.unwind_fblock(block, false)?;
+                self.emit_jump(Instruction::Jump, exit);
+                Ok(())
+            }
+            Some(FBlock::WhileLoop { exit, .. }) => {
+                self.emit_jump(Instruction::Jump, exit);
+                Ok(())
+            }
+            _ => Err(self.error("'break' outside loop")),
+        }
+    }
+
+    fn compile_continue(&mut self) -> Result<()> {
+        match self.unwind_fblock_stack(false, true)? {
+            Some(FBlock::ForLoop { start, .. } | FBlock::WhileLoop { start, .. }) => {
+                self.emit_jump(Instruction::Jump, start);
+                Ok(())
+            }
+            _ => Err(self.error("'continue' not properly in loop")),
+        }
+    }
+
+    fn compile_return(&mut self, value: Option<&'c Expression<'a>>) -> Result<()> {
+        if !self.unit().table.is_optimized() {
+            return Err(self.error("'return' outside function"));
+        }
+        let constant = match value {
+            None => Some(ConstantValue::None),
+            Some(value) => constant_of(value),
+        };
+        let preserve_tos = constant.is_none();
+        if let Some(value) = value.filter(|_| preserve_tos) {
+            self.compile_expression(value)?;
+        }
+        self.unwind_fblock_stack(preserve_tos, false)?;
+        if let Some(constant) = constant {
+            self.load_const(constant);
+        }
+        self.emit(Instruction::ReturnValue);
+        Ok(())
+    }
+
+    /// Emits the cleanup for every block a jump out of the innermost loop (or out of the
+    /// function) leaves.  Returns the loop it stopped at.
+    fn unwind_fblock_stack(&mut self, preserve_tos: bool, stop_at_loop: bool) -> Result<Option<FBlock<'c, 'a>>> {
+        let Some(top) = self.unit_mut().fblocks.pop() else { return Ok(None) };
+        if stop_at_loop && top.is_loop() {
+            self.unit_mut().fblocks.push(top);
+            return Ok(Some(top));
+        }
+        let result = self
+            .unwind_fblock(top, preserve_tos)
+            .and_then(|_| self.unwind_fblock_stack(preserve_tos, stop_at_loop));
+        self.unit_mut().fblocks.push(top);
+        result
+    }
+
+    /// Emits the cleanup for leaving `block` early.  With `preserve_tos` the value on top,
+    /// such as a return value, is kept there.
+    fn unwind_fblock(&mut self, block: FBlock<'c, 'a>, preserve_tos: bool) -> Result<()> {
+        match block {
+            FBlock::WhileLoop { .. } => {}
+            FBlock::ForLoop { .. } | FBlock::PopValue => {
+                if preserve_tos {
+                    self.emit(Instruction::Swap(2));
+                }
+                self.emit(Instruction::Pop);
+            }
+            FBlock::TryExcept => self.emit(Instruction::PopBlock),
+            FBlock::FinallyTry(finalbody) => {
+                self.emit(Instruction::PopBlock);
+                if preserve_tos {
+                    self.push_fblock(FBlock::PopValue)?;
+                }
+                self.compile_copy(finalbody)?;
+                if preserve_tos {
+                    self.pop_fblock();
+                }
+            }
+            FBlock::FinallyEnd => {
+                if preserve_tos {
+                    self.emit(Instruction::Swap(2));
+                }
+                self.emit(Instruction::Pop);
+                self.emit(Instruction::PopBlock);
+                if preserve_tos {
+                    self.emit(Instruction::Swap(2));
+                }
+                self.emit(Instruction::PopExcept);
+            }
+            FBlock::With { is_async } => {
+                self.emit(Instruction::PopBlock);
+                if preserve_tos {
+                    self.emit(Instruction::Swap(2));
+                }
+                self.call_exit_with_nones(is_async);
+            }
+            FBlock::HandlerCleanup(name) => {
+                self.emit(Instruction::PopBlock);
+                if preserve_tos {
+                    self.emit(Instruction::Swap(2));
+                }
+                self.emit(Instruction::PopExcept);
+                if let Some(name) = name {
+                    self.clear_name(name)?;
+                }
+            }
+            FBlock::ExceptStarHandler => {
+                return Err(self.error("'break', 'continue' and 'return' cannot appear in an except* block"))
+            }
+        }
+        Ok(())
+    }
+
+    /// `name = None; del name`, so the exception bound by `except ... as name` does not
+    /// keep the frame alive through its traceback.
+    fn clear_name(&mut self, name: &str) -> Result<()> {
+        self.load_const(ConstantValue::None);
+        self.name_op(name, Ctx::Store)?;
+        self.name_op(name, Ctx::Del)
+    }
+
+    /// Calls the `__exit__` on top with three Nones and drops the result.
+    fn call_exit_with_nones(&mut self, is_async: bool) {
+        for _ in 0..3 {
+            self.load_const(ConstantValue::None);
+        }
+        self.emit(Instruction::Call(3));
+        if is_async {
+            self.emit_await();
+        }
+        self.emit(Instruction::Pop);
+    }
+
+    fn emit_await(&mut self) {
+        self.emit(Instruction::GetAwaitable);
+        self.load_const(ConstantValue::None);
+        self.emit(Instruction::YieldFrom);
+    }
+
+    /// The handler that restores the previous exception and re-raises, for exceptions
+    /// raised while handling one.  Expects the previous exception, the one being handled
+    /// and the new one on the stack.
+    fn emit_cleanup_reraise(&mut self) {
+        self.emit(Instruction::Swap(3));
+        self.emit(Instruction::PopExcept);
+        self.emit(Instruction::Pop);
+        self.emit(Instruction::Reraise);
+    }
+
+    fn compile_compound_statement(&mut self, stmt: &'c CompoundStatement<'a>) -> Result<()> {
+        match stmt {
+            CompoundStatement::FunctionDef(f) => self.compile_function_def(f),
+            CompoundStatement::ClassDef(c) => self.compile_class_def(c),
+            CompoundStatement::If(i) => self.compile_if(i),
+            CompoundStatement::While(w) => {
+                let (start, orelse, exit) = (self.new_label(), self.new_label(), self.new_label());
+                self.bind(start);
+                if !constant_of(&w.test).is_some_and(|test| test.is_truthy()) {
+                    self.compile_jump_if(&w.test, false, orelse)?;
+                }
+                self.push_fblock(FBlock::WhileLoop { start, exit })?;
+                self.compile_suite(&w.body)?;
+                self.pop_fblock();
+                self.emit_jump(Instruction::Jump, start);
+                self.bind(orelse);
+                if let Some(orelse) = &w.orelse {
+                    self.compile_suite(&orelse.body)?;
+                }
+                self.bind(exit);
+                Ok(())
+            }
+            CompoundStatement::For(f) => {
+                let is_async = f.asynchronous.is_some();
+                let (start, orelse, exit) = (self.new_label(), self.new_label(), self.new_label());
+                self.compile_expression(&f.iter)?;
+                self.emit(if is_async { Instruction::GetAiter } else { Instruction::GetIter });
+                self.bind(start);
+                self.emit_next_item(is_async, orelse);
+                self.compile_store(&f.target)?;
+                self.push_fblock(FBlock::ForLoop { start, exit })?;
+                self.compile_suite(&f.body)?;
+                self.pop_fblock();
+                self.emit_jump(Instruction::Jump, start);
+                self.bind(orelse);
+                if is_async {
+                    self.emit(Instruction::EndAsyncFor);
+                }
+                if let Some(orelse) = &f.orelse {
+                    self.compile_suite(&orelse.body)?;
+                }
+                self.bind(exit);
+                Ok(())
+            }
+            CompoundStatement::Try(t) => match &t.finalbody {
+                Some(finalbody) => self.compile_try_finally(stmt, &finalbody.body),
+                None => self.compile_try_except(t),
+            },
+            CompoundStatement::TryStar(t) => match &t.finalbody {
+                Some(finalbody) => self.compile_try_finally(stmt, &finalbody.body),
+                None => self.compile_try_star(t),
+            },
+            CompoundStatement::With(w) => self.compile_with(w, 0),
+            CompoundStatement::Match(m) => self.compile_match(m),
+        }
+    }
+
+    /// The start of a `for` loop with the iterator on top: pushes the next item, or jumps
+    /// to `exhausted`.  An exhausted async iterator arrives there with a
+    /// `StopAsyncIteration` for `EndAsyncFor`.
+    fn emit_next_item(&mut self, is_async: bool, exhausted: Label) {
+        if is_async {
+            self.emit_jump(Instruction::SetupFinally, exhausted);
+            self.emit(Instruction::GetAnext);
+            self.load_const(ConstantValue::None);
+            self.emit(Instruction::YieldFrom);
+            self.emit(Instruction::PopBlock);
+        } else {
+            self.emit_jump(Instruction::ForIter, exhausted);
+        }
+    }
+
+    fn compile_if(&mut self, stmt: &'c If<'a>) -> Result<()> {
+        let end = self.new_label();
+        let next = if stmt.orelse.is_some() { self.new_label() } else { end };
+        self.compile_jump_if(&stmt.test, false, next)?;
+        self.compile_suite(&stmt.body)?;
+        if let Some(orelse) = stmt.orelse.as_deref() {
+            self.emit_jump(Instruction::Jump, end);
+            self.bind(next);
+            match orelse {
+                OrElse::Elif(elif) => {
+                    let line = std::mem::replace(&mut self.line, elif.span.start.line);
+                    self.compile_if(elif)?;
+                    self.line = line;
+                }
+                OrElse::Else(orelse) => self.compile_suite(&orelse.body)?,
+            }
+        }
+        self.bind(end);
+        Ok(())
+    }
+
+    /// `try`/`finally`, where `stmt` is the `try` statement with any `except` clauses.
+    fn compile_try_finally(&mut self, stmt: &'c CompoundStatement<'a>, finalbody: &'c Suite<'a>) -> Result<()> {
+        let (handler, cleanup, exit) = (self.new_label(), self.new_label(), self.new_label());
+        self.emit_jump(Instruction::SetupFinally, handler);
+        self.push_fblock(FBlock::FinallyTry(finalbody))?;
+        match stmt {
+            CompoundStatement::Try(t) if t.handlers.is_empty() => self.compile_suite(&t.body)?,
+            CompoundStatement::Try(t) => self.compile_try_except(t)?,
+            CompoundStatement::TryStar(t) => self.compile_try_star(t)?,
+            _ => unreachable!("finally on a statement other than try"),
+        }
+        self.emit(Instruction::PopBlock);
+        self.pop_fblock();
+        let next_child = self.unit().next_child;
+        self.compile_suite(finalbody)?;
+        self.emit_jump(Instruction::Jump, exit);
+
+        // An exception: run the body with it as the exception being handled, then re-raise.
+        self.bind(handler);
+        self.emit(Instruction::PushExcInfo);
+        self.emit_jump(Instruction::SetupFinally, cleanup);
+        self.push_fblock(FBlock::FinallyEnd)?;
+        let after_body = std::mem::replace(&mut self.unit_mut().next_child, next_child);
+        self.compile_suite(finalbody)?;
+        self.unit_mut().next_child = after_body;
+        self.pop_fblock();
+        self.emit(Instruction::PopBlock);
+        self.emit(Instruction::Swap(2));
+        self.emit(Instruction::PopExcept);
+        self.emit(Instruction::Reraise);
+        self.bind(cleanup);
+        self.emit_cleanup_reraise();
+        self.bind(exit);
+        Ok(())
+    }
+
+    fn compile_try_except(&mut self, stmt: &'c Try<'a>) -> Result<()> {
+        let (except, cleanup, end) = (self.new_label(), self.new_label(), self.new_label());
+        self.emit_jump(Instruction::SetupFinally, except);
+        self.push_fblock(FBlock::TryExcept)?;
+        self.compile_suite(&stmt.body)?;
+        self.pop_fblock();
+        self.emit(Instruction::PopBlock);
+        if let Some(orelse) = &stmt.orelse {
+            self.compile_suite(&orelse.body)?;
+        }
+        self.emit_jump(Instruction::Jump, end);
+
+        // The exception is on the stack.  Exceptions raised while picking a handler go to
+        // `cleanup`, which restores the exception that was being handled before.
+        self.bind(except);
+        self.emit(Instruction::PushExcInfo);
+        self.emit_jump(Instruction::SetupFinally, cleanup);
+        for handler in &stmt.handlers {
+            let next = self.new_label();
+            if let Some(kind) = &handler.r#type {
+                self.compile_expression(kind)?;
+                self.emit(Instruction::CheckExcMatch);
+                self.emit_jump(Instruction::PopJumpIfFalse, next);
+            }
+            self.emit(Instruction::PopBlock);
+            let name = match &handler.name {
+                Some(asname) => {
+                    self.compile_store(&asname.name)?;
+                    match &asname.name {
+                        AssignTargetExpression::Name(n) => Some(n.value),
+                        _ => None,
+                    }
+                }
+                None => {
+                    self.emit(Instruction::Pop);
+                    None
+                }
+            };
+
+            // Only the previous exception is left on the stack now.
+            let body_cleanup = self.new_label();
+            self.emit_jump(Instruction::SetupFinally, body_cleanup);
+            self.push_fblock(FBlock::HandlerCleanup(name))?;
+            self.compile_suite(&handler.body)?;
+            self.pop_fblock();
+            self.emit(Instruction::PopBlock);
+            self.emit(Instruction::PopExcept);
+            if let Some(name) = name {
+                self.clear_name(name)?;
+            }
+            self.emit_jump(Instruction::Jump, end);
+
+            self.bind(body_cleanup);
+            if let Some(name) = name {
+                self.clear_name(name)?;
+            }
+            self.emit(Instruction::Swap(2));
+            self.emit(Instruction::PopExcept);
+            self.emit(Instruction::Reraise);
+            self.bind(next);
+        }
+        // No handler matched: `cleanup` re-raises the copy, with the exception left below it
+        // as its handlers expect.
+        self.emit(Instruction::Copy(1));
+        self.emit(Instruction::Reraise);
+        self.bind(cleanup);
+        self.emit_cleanup_reraise();
+        self.bind(end);
+        Ok(())
+    }
+
+    /// `try`/`except*`.  While the handlers run the stack holds the previous exception, the
+    /// original exception group, a list of what the handlers raised and the part of the
+    /// group no handler has matched yet.
+    fn compile_try_star(&mut self, stmt: &'c TryStar<'a>) -> Result<()> {
+        let (except, cleanup, reraise, end) = (self.new_label(), self.new_label(), self.new_label(), self.new_label());
+        self.emit_jump(Instruction::SetupFinally, except);
+        self.push_fblock(FBlock::TryExcept)?;
+        self.compile_suite(&stmt.body)?;
+        self.pop_fblock();
+        self.emit(Instruction::PopBlock);
+        if let Some(orelse) = &stmt.orelse {
+            self.compile_suite(&orelse.body)?;
+        }
+        self.emit_jump(Instruction::Jump, end);
+
+        self.bind(except);
+        self.emit(Instruction::PushExcInfo);
+        self.emit_jump(Instruction::SetupFinally, cleanup);
+        self.emit(Instruction::BuildList(0));
+        self.emit(Instruction::Copy(2));
+        for handler in &stmt.handlers {
+            let (no_match, raised, next) = (self.new_label(), self.new_label(), self.new_label());
+            self.compile_expression(&handler.r#type)?;
+            self.emit(Instruction::CheckEgMatch);
+            self.emit(Instruction::Copy(1));
+            self.emit_jump(Instruction::PopJumpIfNone, no_match);
+            let name = match &handler.name {
+                Some(asname) => {
+                    self.compile_store(&asname.name)?;
+                    match &asname.name {
+                        AssignTargetExpression::Name(n) => Some(n.value),
+                        _ => None,
+                    }
+                }
+                None => {
+                    self.emit(Instruction::Pop);
+                    None
+                }
+            };
+            self.emit_jump(Instruction::SetupFinally, raised);
+            self.push_fblock(FBlock::ExceptStarHandler)?;
+            self.compile_suite(&handler.body)?;
+            self.pop_fblock();
+            self.emit(Instruction::PopBlock);
+            if let Some(name) = name {
+                self.clear_name(name)?;
+            }
+            self.emit_jump(Instruction::Jump, next);
+
+            // Keep what the handler raised, to re-raise with whatever is left at the end.
+            self.bind(raised);
+            if let Some(name) = name {
+                self.clear_name(name)?;
+            }
+            self.emit(Instruction::ListAppend(2));
+            self.emit_jump(Instruction::Jump, next);
+
+            self.bind(no_match);
+            self.emit(Instruction::Pop);
+            self.bind(next);
+        }
+        self.emit(Instruction::ListAppend(1));
+        self.emit(Instruction::PrepReraiseStar);
+        self.emit(Instruction::Copy(1));
+        self.emit_jump(Instruction::PopJumpIfNotNone, reraise);
+        self.emit(Instruction::Pop);
+        self.emit(Instruction::PopBlock);
+        self.emit(Instruction::PopExcept);
+        self.emit_jump(Instruction::Jump, end);
+
+        self.bind(reraise);
+        self.emit(Instruction::PopBlock);
+        self.emit(Instruction::Swap(2));
+        self.emit(Instruction::PopExcept);
+        self.emit(Instruction::Reraise);
+        self.bind(cleanup);
+        self.emit_cleanup_reraise();
+        self.bind(end);
+        Ok(())
+    }
+
+    /// The `with` item at `idx` and, nested inside it, the rest of the items and the body.
+    fn compile_with(&mut self, stmt: &'c With<'a>, idx: usize) -> Result<()> {
+        let is_async = stmt.asynchronous.is_some();
+        let item = &stmt.items[idx];
+        let (exc, cleanup, suppress, end) = (self.new_label(), self.new_label(), self.new_label(), self.new_label());
+        self.compile_expression(&item.item)?;
+        if is_async {
+            self.emit(Instruction::BeforeAsyncWith);
+            self.emit_await();
+        } else {
+            self.emit(Instruction::BeforeWith);
+        }
+        self.emit_jump(Instruction::SetupWith, exc);
+        self.push_fblock(FBlock::With { is_async })?;
+        match &item.asname {
+            Some(asname) => self.compile_store(&asname.name)?,
+            None => self.emit(Instruction::Pop),
+        }
+        if idx + 1 < stmt.items.len() {
+            self.compile_with(stmt, idx + 1)?;
+        } else {
+            self.compile_suite(&stmt.body)?;
+        }
+        self.pop_fblock();
+        self.emit(Instruction::PopBlock);
+        self.call_exit_with_nones(is_async);
+        self.emit_jump(Instruction::Jump, end);
+
+        // `__exit__` and the exception are on the stack.
+        self.bind(exc);
+        self.emit(Instruction::PushExcInfo);
+        self.emit_jump(Instruction::SetupFinally, cleanup);
+        self.emit(Instruction::WithExceptStart);
+        if is_async {
+            self.emit_await();
+        }
+        self.emit_jump(Instruction::PopJumpIfTrue, suppress);
+        self.emit(Instruction::Reraise);
+        self.bind(suppress);
+        self.emit(Instruction::PopBlock);
+        self.emit(Instruction::Pop);
+        self.emit(Instruction::PopExcept);
+        self.emit(Instruction::Pop);
+        self.emit_jump(Instruction::Jump, end);
+        self.bind(cleanup);
+        self.emit_cleanup_reraise();
+        self.bind(end);
+        Ok(())
+    }
+
+    fn compile_function_def(&mut self, stmt: &'c FunctionDef<'a>) -> Result<()> {
+        for decorator in &stmt.decorators {
+            self.compile_expression(&decorator.decorator)?;
+        }
+        let flags = self.compile_defaults(&stmt.params)?;
+        if stmt.type_params.is_empty() {
+            self.compile_function(stmt, flags)?;
+        } else {
+            // The defaults are passed to the annotation scope.
+            let argc = (flags & (MAKE_DEFAULTS | MAKE_KWDEFAULTS)).count_ones();
+            self.compile_generic(stmt.name.value, &stmt.type_params, argc, |this| {
+                if flags & MAKE_DEFAULTS != 0 {
+                    this.name_op(".defaults", Ctx::Load)?;
+                }
+                if flags & MAKE_KWDEFAULTS != 0 {
+                    this.name_op(".kwdefaults", Ctx::Load)?;
+                }
+                this.compile_function(stmt, flags)?;
+                this.set_type_params();
+                Ok(())
+            })?;
+        }
+        for _ in &stmt.decorators {
+            self.emit(Instruction::Call(1));
+        }
+        self.name_op(stmt.name.value, Ctx::Store)
+    }
+
+    /// Makes the function for `stmt`, with the defaults `flags` says are on the stack.
+    fn compile_function(&mut self, stmt: &'c FunctionDef<'a>, mut flags: u32) -> Result<()> {
+        flags |= self.compile_annotations(&stmt.params, stmt.returns.as_ref().map(|r| &r.annotation))?;
+        let body = Body::of(&stmt.body);
+        let docstring = body.docstring();
+        let table = self.child_table(stmt.name.value)?;
+        self.enter_unit(table, stmt.name.value, UnitKind::Function(&stmt.params));
+        if let Some(doc) = docstring.as_ref().filter(|_| self.optimize < 2) {
+            self.unit_mut().consts[0] = Const::Value(ConstantValue::Str(doc.clone()));
+        }
+        let result = self.compile_body(body, docstring.is_some());
+        self.load_const(ConstantValue::None);
+        self.emit(Instruction::ReturnValue);
+        let code = self.leave_unit();
+        result?;
+        self.make_closure(code, flags)
+    }
+
+    /// Calls the annotation scope of the generic `name` with the `argc` arguments on the
+    /// stack.  The scope binds `params`, pushes a tuple of them and returns what `compile`
+    /// leaves on top of that.
+    fn compile_generic(
+        &mut self,
+        name: &str,
+        params: &'c [TypeParam<'a>],
+        argc: u32,
+        compile: impl FnOnce(&mut Self) -> Result<()>,
+    ) -> Result<()> {
+        let table = self.child_table(name)?;
+        self.enter_unit(table, &format!("<generic parameters of {}>", name), UnitKind::Annotation);
+        let result = self.compile_type_params(params).and_then(|()| compile(self));
+        self.emit(Instruction::ReturnValue);
+        let code = self.leave_unit();
+        result?;
+        self.make_closure(code, 0)?;
+        // The function goes below its arguments.
+        for n in (2..=argc + 1).rev() {
+            self.emit(Instruction::Swap(n));
+        }
+        self.emit(Instruction::Call(argc));
+        Ok(())
+    }
+
+    /// Sets the `__type_params__` of the function or class on top to the tuple below it.
+    fn set_type_params(&mut self) {
+        self.emit(Instruction::Swap(2));
+        self.emit(Instruction::Copy(2));
+        let idx = self.name_idx("__type_params__");
+        self.emit(Instruction::StoreAttr(idx));
+    }
+
+    /// Pushes the tuple of positional defaults and the dict of keyword-only ones, if there
+    /// are any, and returns the `MakeFunction` flags for them.
+    fn compile_defaults(&mut self, params: &'c Parameters<'a>) -> Result<u32> {
+        let mut flags = 0;
+        let defaults: Vec<_> = params.posonly_params.iter().chain(params.params.iter()).filter_map(|p| p.default.as_ref()).collect();
+        if !defaults.is_empty() {
+            for default in &defaults {
+                self.compile_expression(default)?;
+            }
+            self.emit(Instruction::BuildTuple(defaults.len() as u32));
+            flags |= MAKE_DEFAULTS;
+        }
+        let kwdefaults: Vec<_> = params.kwonly_params.iter().filter(|p| p.default.is_some()).collect();
+        if !kwdefaults.is_empty() {
+            for param in &kwdefaults {
+                let name = self.mangle(param.name.value);
+                self.load_str(&name);
+                self.compile_expression(param.default.as_ref().expect("default"))?;
+            }
+            self.emit(Instruction::BuildMap(kwdefaults.len() as u32));
+            flags |= MAKE_KWDEFAULTS;
+        }
+        Ok(flags)
+    }
+
+    fn compile_annotations(&mut self, params: &'c Parameters<'a>, returns: Option<&'c Expression<'a>>) -> Result<u32> {
+        let star_arg = match &params.star_arg {
+            Some(StarArg::Param(param)) => Some(param.as_ref()),
+            _ => None,
+        };
+        let all = params
+            .posonly_params
+            .iter()
+            .chain(params.params.iter())
+            .chain(star_arg)
+            .chain(params.kwonly_params.iter())
+            .chain(params.star_kwarg.iter());
+        let mut count = 0;
+        for param in all {
+            if let Some(annotation) = &param.annotation {
+                let name = self.mangle(param.name.value);
+                self.load_str(&name);
+                self.compile_annotation(&annotation.annotation)?;
+                count += 1;
+            }
+        }
+        if let Some(returns) = returns {
+            self.load_str("return");
+            self.compile_annotation(returns)?;
+            count += 1;
+        }
+        if count == 0 {
+            return Ok(0);
+        }
+        self.emit(Instruction::BuildMap(count));
+        Ok(MAKE_ANNOTATIONS)
+    }
+
+    /// Binds the type parameters in the annotation scope being compiled and pushes a tuple
+    /// of them.  Bounds and defaults are evaluated right away.
+    fn compile_type_params(&mut self, params: &'c [TypeParam<'a>]) -> Result<()> {
+        for param in params {
+            self.load_str(param.name.value);
+            let flavor = match &param.kind {
+                TypeParamKind::TypeVar { bound: Some(bound) } => {
+                    self.compile_expression(bound)?;
+                    match param.constraints() {
+                        Some(_) => TypeParamFlavor::ConstrainedTypeVar,
+                        None => TypeParamFlavor::TypeVar,
+                    }
+                }
+                TypeParamKind::TypeVar { bound: None } => {
+                    self.load_const(ConstantValue::None);
+                    TypeParamFlavor::TypeVar
+                }
+                TypeParamKind::TypeVarTuple => {
+                    self.load_const(ConstantValue::None);
+                    TypeParamFlavor::TypeVarTuple
+                }
+                TypeParamKind::ParamSpec => {
+                    self.load_const(ConstantValue::None);
+                    TypeParamFlavor::ParamSpec
+                }
+            };
+            match &param.default {
+                Some(Expression::StarredElement(s)) => self.compile_expression(&s.value)?,
+                Some(default) => self.compile_expression(default)?,
+                None => self.load_const(ConstantValue::None),
+            }
+            self.emit(Instruction::MakeTypeParam(flavor));
+            self.emit(Instruction::Copy(1));
+            self.name_op(param.name.value, Ctx::Store)?;
+        }
+        self.emit(Instruction::BuildTuple(params.len() as u32));
+        Ok(())
+    }
+
+    /// Pushes the `TypeAliasType` for `stmt`, on top of the tuple of its type parameters if
+    /// it has any.  The value is compiled to a function, called when it is first needed.
+    fn compile_type_alias(&mut self, stmt: &'c TypeAlias<'a>) -> Result<()> {
+        let name = stmt.name.value;
+        if stmt.type_params.is_empty() {
+            self.emit(Instruction::BuildTuple(0));
+        }
+        self.load_str(name);
+        let table = self.child_table(name)?;
+        self.enter_unit(table, name, UnitKind::Annotation);
+        let result = self.compile_expression(&stmt.value);
+        self.emit(Instruction::ReturnValue);
+        let code = self.leave_unit();
+        result?;
+        self.make_closure(code, 0)?;
+        self.emit(Instruction::MakeTypeAlias);
+        Ok(())
+    }
+
+    fn compile_class_def(&mut self, stmt: &'c ClassDef<'a>) -> Result<()> {
+        for decorator in &stmt.decorators {
+            self.compile_expression(&decorator.decorator)?;
+        }
+        if stmt.type_params.is_empty() {
+            self.compile_class(stmt)?;
+        } else {
+            self.compile_generic(stmt.name.value, &stmt.type_params, 0, |this| {
+                this.compile_class(stmt)?;
+                this.set_type_params();
+                Ok(())
+            })?;
+        }
+        for _ in &stmt.decorators {
+            self.emit(Instruction::Call(1));
+        }
+        self.name_op(stmt.name.value, Ctx::Store)
+    }
+
+    /// Makes the class for `stmt`: runs its body and calls the metaclass.
+    fn compile_class(&mut self, stmt: &'c ClassDef<'a>) -> Result<()> {
+        self.emit(Instruction::LoadBuildClass);
+
+        let table = self.child_table(stmt.name.value)?;
+        self.enter_unit(table, stmt.name.value, UnitKind::Class);
+        let result = self.compile_class_body(stmt);
+        let code = self.leave_unit();
+        result?;
+        self.make_closure(code, 0)?;
+        self.load_str(stmt.name.value);
+        let args: Vec<&'c Arg<'a>> = stmt.bases.iter().chain(stmt.keywords.iter()).collect();
+        self.compile_call_args(2, &args)
+    }
+
+    fn compile_class_body(&mut self, stmt: &'c ClassDef<'a>) -> Result<()> {
+        if self.unit().table.needs_classdict() {
+            self.emit(Instruction::LoadLocals);
+            let idx = self.deref_index("__classdict__")?;
+            self.emit(Instruction::StoreDeref(idx));
+        }
+        let idx = self.name_idx("__name__");
+        self.emit(Instruction::LoadName(idx));
+        self.store_dunder("__module__");
+        let qualname = self.unit().qualname.clone();
+        self.load_str(&qualname);
+        self.store_dunder("__qualname__");
+        self.compile_namespace_body(Body::of(&stmt.body))?;
+        // `type.__new__` fills in the `__class__` cell of the methods from `__classcell__`.
+        if self.unit().table.needs_class_closure() {
+            let idx = self.deref_index("__class__")?;
+            self.emit(Instruction::LoadClosure(idx));
+            self.emit(Instruction::Copy(1));
+            self.store_dunder("__classcell__");
+        } else {
+            self.load_const(ConstantValue::None);
+        }
+        self.emit(Instruction::ReturnValue);
+        Ok(())
+    }
+
+    // Pattern matching
+
+    fn compile_match(&mut self, stmt: &'c Match<'a>) -> Result<()> {
+        let end = self.new_label();
+        self.compile_expression(&stmt.subject)?;
+        for case in &stmt.cases {
+            let mut pc = PatternContext::default();
+            self.emit(Instruction::Copy(1));
+            self.compile_pattern(&case.pattern, &mut pc)?;
+            if let Some(guard) = &case.guard {
+                let fail = self.fail_label(&mut pc, 0);
+                self.compile_jump_if(guard, false, fail)?;
+            }
+            self.emit(Instruction::Pop);
+            self.compile_suite(&case.body)?;
+            self.emit_jump(Instruction::Jump, end);
+            self.emit_fail_pops(&mut pc);
+        }
+        self.emit(Instruction::Pop);
+        self.bind(end);
+        Ok(())
+    }
+
+    /// The label a failed match with `extra` items of its own on the stack jumps to.
+    fn fail_label(&mut self, pc: &mut PatternContext, extra: usize) -> Label {
+        let pops = pc.on_top + extra;
+        while pc.fail_pop.len() <= pops {
+            let label = self.new_label();
+            pc.fail_pop.push(label);
+        }
+        pc.fail_pop[pops]
+    }
+
+    fn jump_to_fail(&mut self, pc: &mut PatternContext, extra: usize, jump: fn(u32) -> Instruction) {
+        let label = self.fail_label(pc, extra);
+        self.emit_jump(jump, label);
+    }
+
+    fn emit_fail_pops(&mut self, pc: &mut PatternContext) {
+        for (pops, label) in pc.fail_pop.iter().enumerate().rev() {
+            self.bind(*label);
+            if pops > 0 {
+                self.emit(Instruction::Pop);
+            }
+        }
+        pc.fail_pop.clear();
+    }
+
+    /// Matches the subject on top against `pattern`, consuming it, and binds the names
+    /// the pattern captures.  Falls through if it matched.
+    fn compile_pattern(&mut self, pattern: &'c MatchPattern<'a>, pc: &mut PatternContext) -> Result<()> {
+        match pattern {
+            MatchPattern::Value(value) => {
+                self.compile_expression(&value.value)?;
+                self.emit(Instruction::CompareOp(ComparisonOperator::Equal));
+                self.jump_to_fail(pc, 0, Instruction::PopJumpIfFalse);
+            }
+            MatchPattern::Singleton(singleton) => {
+                self.name_op(singleton.value.value, Ctx::Load)?;
+                self.emit(Instruction::IsOp(false));
+                self.jump_to_fail(pc, 0, Instruction::PopJumpIfFalse);
+            }
+            MatchPattern::As(a) => match (&a.pattern, &a.name) {
+                (None, None) => self.emit(Instruction::Pop),
+                (None, Some(name)) => self.name_op(name.value, Ctx::Store)?,
+                (Some(pattern), name) => {
+                    self.emit(Instruction::Copy(1));
+                    pc.on_top += 1;
+                    self.compile_pattern(pattern, pc)?;
+                    pc.on_top -= 1;
+                    match name {
+                        Some(name) => self.name_op(name.value, Ctx::Store)?,
+                        None => self.emit(Instruction::Pop),
+                    }
+                }
+            },
+            MatchPattern::Or(or) => {
+                let end = self.new_label();
+                for alternative in &or.patterns {
+                    let mut alt_pc = PatternContext::default();
+                    self.emit(Instruction::Copy(1));
+                    self.compile_pattern(&alternative.pattern, &mut alt_pc)?;
+                    self.emit(Instruction::Pop);
+                    self.emit_jump(Instruction::Jump, end);
+                    self.emit_fail_pops(&mut alt_pc);
+                }
+                self.emit(Instruction::Pop);
+                self.jump_to_fail(pc, 0, Instruction::Jump);
+                self.bind(end);
+            }
+            MatchPattern::Sequence(sequence) => {
+                let patterns = match sequence {
+                    MatchSequence::MatchList(l) => &l.patterns,
+                    MatchSequence::MatchTuple(t) => &t.patterns,
+                };
+                let star = patterns.iter().position(|p| matches!(p, StarrableMatchSequenceElement::Starred(_)));
+                let count = patterns.len();
+                self.emit(Instruction::MatchSequence);
+                self.jump_to_fail(pc, 1, Instruction::PopJumpIfFalse);
+                self.emit(Instruction::GetLen);
+                let (min_len, cmp) = match star {
+                    Some(_) => (count - 1, ComparisonOperator::GreaterEqual),
+                    None => (count, ComparisonOperator::Equal),
+                };
+                self.load_const(ConstantValue::Int(BigInt::from(min_len as i64)));
+                self.emit(Instruction::CompareOp(cmp));
+                self.jump_to_fail(pc, 1, Instruction::PopJumpIfFalse);
+                if count == 0 {
+                    self.emit(Instruction::Pop);
+                    return Ok(());
+                }
+                self.emit(match star {
+                    Some(idx) => Instruction::UnpackEx { before: idx as u32, after: (count - idx - 1) as u32 },
+                    None => Instruction::UnpackSequence(count as u32),
+                });
+                pc.on_top += count;
+                for element in patterns {
+                    pc.on_top -= 1;
+                    match element {
+                        StarrableMatchSequenceElement::Simple(s) => self.compile_pattern(&s.value, pc)?,
+                        StarrableMatchSequenceElement::Starred(s) => match &s.name {
+                            Some(name) => self.name_op(name.value, Ctx::Store)?,
+                            None => self.emit(Instruction::Pop),
+                        },
+                    }
+                }
+            }
+            MatchPattern::Mapping(mapping) => {
+                let count = mapping.elements.len();
+                self.emit(Instruction::MatchMapping);
+                self.jump_to_fail(pc, 1, Instruction::PopJumpIfFalse);
+                if count == 0 && mapping.rest.is_none() {
+                    self.emit(Instruction::Pop);
+                    return Ok(());
+                }
+                if count > 0 {
+                    self.emit(Instruction::GetLen);
+                    self.load_const(ConstantValue::Int(BigInt::from(count as i64)));
+                    self.emit(Instruction::CompareOp(ComparisonOperator::GreaterEqual));
+                    self.jump_to_fail(pc, 1, Instruction::PopJumpIfFalse);
+                }
+                for element in &mapping.elements {
+                    self.compile_expression(&element.key)?;
+                }
+                self.emit(Instruction::BuildTuple(count as u32));
+                self.emit(Instruction::MatchKeys);
+                self.emit(Instruction::Copy(1));
+                self.jump_to_fail(pc, 3, Instruction::PopJumpIfNone);
+                self.emit(Instruction::UnpackSequence(count as u32));
+                pc.on_top += 2 + count;
+                for element in &mapping.elements {
+                    pc.on_top -= 1;
+                    self.compile_pattern(&element.pattern, pc)?;
+                }
+                pc.on_top -= 2;
+                match &mapping.rest {
+                    Some(rest) => {
+                        self.emit(Instruction::CopyDictWithoutKeys);
+                        self.name_op(rest.value, Ctx::Store)?;
+                    }
+                    None => self.emit(Instruction::Pop),
+                }
+                self.emit(Instruction::Pop);
+            }
+            MatchPattern::Class(class) => {
+                let cls: Expression<'a> = class.cls.clone().into();
+                match &cls {
+                    Expression::Name(n) => self.name_op(n.value, Ctx::Load)?,
+                    _ => self.compile_owned_expression(&cls)?,
+                }
+                let kwnames = class.kwds.iter().map(|kwd| ConstantValue::Str(kwd.key.value.to_string())).collect();
+                self.load_const(ConstantValue::Tuple(kwnames));
+                self.emit(Instruction::MatchClass(class.patterns.len() as u32));
+                self.emit(Instruction::Copy(1));
+                self.jump_to_fail(pc, 1, Instruction::PopJumpIfNone);
+                let count = class.patterns.len() + class.kwds.len();
+                if count == 0 {
+                    self.emit(Instruction::Pop);
+                    return Ok(());
+                }
+                self.emit(Instruction::UnpackSequence(count as u32));
+                pc.on_top += count;
+                let subpatterns = class.patterns.iter().map(|p| &p.value).chain(class.kwds.iter().map(|k| &k.pattern));
+                for subpattern in subpatterns {
+                    pc.on_top -= 1;
+                    self.compile_pattern(subpattern, pc)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Compiles a dotted name built on the fly, such as the class of a class pattern.  It
+    /// holds nothing but names and attributes, so no nested block can be in it.
+    fn compile_owned_expression(&mut self, expr: &Expression<'a>) -> Result<()> {
+        match expr {
+            Expression::Name(n) => self.name_op(n.value, Ctx::Load),
+            Expression::Attribute(a) => {
+                self.compile_owned_expression(&a.value)?;
+                let idx = self.attr_idx(a.attr.value);
+                self.emit(Instruction::LoadAttr(idx));
+                Ok(())
+            }
+            _ => Err(self.error("invalid class pattern")),
+        }
+    }
+
+    // Expressions
+
+    fn literal<T, E: std::fmt::Display>(&self, value: std::result::Result<T, E>) -> Result<T> {
+        value.map_err(|err| self.error(err.to_string()))
+    }
+
+    fn compile_expression(&mut self, expr: &'c Expression<'a>) -> Result<()> {
+        match expr {
+            Expression::Name(n) => self.name_op(n.value, Ctx::Load)?,
+            Expression::Ellipsis(_) => self.load_const(ConstantValue::Ellipsis),
+            Expression::Integer(i) => {
+                let value = self.literal(i.parse_value())?;
+                self.load_const(ConstantValue::Int(value));
+            }
+            Expression::Hexidecimal(h) => {
+                let value = self.literal(h.parse_value())?;
+                self.load_const(ConstantValue::Int(value));
+            }
+            Expression::Binary(b) => {
+                let value = self.literal(b.parse_value())?;
+                self.load_const(ConstantValue::Int(value));
+            }
+            Expression::Float(f) => {
+                let value = self.literal(f.parse_value())?;
+                self.load_const(ConstantValue::Float(value));
+            }
+            Expression::Imaginary(i) => {
+                let imag = self.literal(i.parse_value())?;
+                self.load_const(ConstantValue::Complex { real: 0.0, imag });
+            }
+            Expression::Comparison(c) => self.compile_comparison(c)?,
+            Expression::UnaryOperation(u) => {
+                self.compile_expression(&u.expression)?;
+                self.emit(Instruction::UnaryOp(match u.operator {
+                    UnaryOp::Plus => UnaryOperator::Positive,
+                    UnaryOp::Minus => UnaryOperator::Negative,
+                    UnaryOp::BitInvert => UnaryOperator::Invert,
+                    UnaryOp::Not => UnaryOperator::Not,
+                }));
+            }
+            Expression::BinaryOperation(b) => {
+                self.compile_expression(&b.left)?;
+                self.compile_expression(&b.right)?;
+                self.emit(Instruction::BinaryOp(binary_operator(&b.operator)));
+            }
+            Expression::BooleanOperation(b) => {
+                let end = self.new_label();
+                self.compile_expression(&b.left)?;
+                let jump = match b.operator {
+                    BooleanOp::And => Instruction::JumpIfFalseOrPop,
+                    BooleanOp::Or => Instruction::JumpIfTrueOrPop,
+                };
+                self.emit_jump(jump, end);
+                self.compile_expression(&b.right)?;
+                self.bind(end);
+            }
+            Expression::Attribute(a) => self.compile_attribute(a, Ctx::Load)?,
+            Expression::Tuple(t) => self.compile_collection(&t.elements, Collection::Tuple)?,
+            Expression::List(l) => self.compile_collection(&l.elements, Collection::List)?,
+            Expression::Set(s) => self.compile_collection(&s.elements, Collection::Set)?,
+            Expression::Dict(d) => self.compile_dict(d)?,
+            Expression::Call(c) => {
+                self.compile_expression(&c.func)?;
+                let args: Vec<&'c Arg<'a>> = c.args.iter().collect();
+                self.compile_call_args(0, &args)?;
+            }
+            Expression::GeneratorExp(g) => self.compile_comprehension(&g.for_in, CompKind::Generator(&g.elt))?,
+            Expression::ListComp(l) => self.compile_comprehension(&l.for_in, CompKind::List(&l.elt))?,
+            Expression::SetComp(s) => self.compile_comprehension(&s.for_in, CompKind::Set(&s.elt))?,
+            Expression::DictComp(d) => self.compile_comprehension(&d.for_in, CompKind::Dict(&d.key, &d.value))?,
+            Expression::Subscript(s) => self.compile_subscript(s, Ctx::Load)?,
+            Expression::StarredElement(_) => return Err(self.error("can't use starred expression here")),
+            Expression::IfExp(i) => {
+                let (orelse, end) = (self.new_label(), self.new_label());
+                self.compile_jump_if(&i.test, false, orelse)?;
+                self.compile_expression(&i.body)?;
+                self.emit_jump(Instruction::Jump, end);
+                self.bind(orelse);
+                self.compile_expression(&i.orelse)?;
+                self.bind(end);
+            }
+            Expression::Lambda(l) => self.compile_lambda(l)?,
+            Expression::Yield(y) => match y.value.as_deref() {
+                None => {
+                    self.load_const(ConstantValue::None);
+                    self.emit(Instruction::YieldValue);
+                }
+                Some(YieldValue::Expression(value)) => {
+                    self.compile_expression(value)?;
+                    self.emit(Instruction::YieldValue);
+                }
+                Some(YieldValue::From(from)) => {
+                    self.compile_expression(&from.item)?;
+                    self.emit(Instruction::GetYieldFromIter);
+                    self.load_const(ConstantValue::None);
+                    self.emit(Instruction::YieldFrom);
+                }
+            },
+            Expression::Await(a) => {
+                self.compile_expression(&a.expression)?;
+                self.emit_await();
+            }
+            Expression::SimpleString(s) => {
+                let value = self.literal(s.parse_value())?;
+                self.load_const(string_constant(value));
+            }
+            Expression::ConcatenatedString(c) => match c.parse_value() {
+                Some(value) => {
+                    let value = self.literal(value)?;
+                    self.load_const(string_constant(value));
+                }
+                None => {
+                    let mut pieces = vec![];
+                    self.collect_string_pieces(&c.left, &mut pieces)?;
+                    self.collect_string_pieces(&c.right, &mut pieces)?;
+                    self.compile_pieces(pieces)?;
+                }
+            },
+            Expression::FormattedString(f) => {
+                let mut pieces = vec![];
+                self.collect_fstring_parts(&f.parts, f.start, &mut pieces)?;
+                self.compile_pieces(pieces)?;
+            }
+            Expression::NamedExpr(n) => {
+                self.compile_expression(&n.value)?;
+                self.emit(Instruction::Copy(1));
+                self.compile_store_expression(&n.target)?;
+            }
+            Expression::Constant(c) => self.load_const(c.value.clone()),
+        }
+        Ok(())
+    }
+
+    /// Jumps to `target` if `expr` is `cond`, short-circuiting `and`, `or` and `not`.
+    fn compile_jump_if(&mut self, expr: &'c Expression<'a>, cond: bool, target: Label) -> Result<()> {
+        match expr {
+            Expression::UnaryOperation(u) if u.operator == UnaryOp::Not => {
+                self.compile_jump_if(&u.expression, !cond, target)
+            }
+            Expression::BooleanOperation(b) => {
+                let is_or = b.operator == BooleanOp::Or;
+                if is_or == cond {
+                    self.compile_jump_if(&b.left, cond, target)?;
+                    self.compile_jump_if(&b.right, cond, target)
+                } else {
+                    let next = self.new_label();
+                    self.compile_jump_if(&b.left, !cond, next)?;
+                    self.compile_jump_if(&b.right, cond, target)?;
+                    self.bind(next);
+                    Ok(())
+                }
+            }
+            _ => {
+                self.compile_expression(expr)?;
+                self.emit_jump(if cond { Instruction::PopJumpIfTrue } else { Instruction::PopJumpIfFalse }, target);
+                Ok(())
+            }
+        }
+    }
+
+    fn emit_compare(&mut self, op: &CompOp) {
+        self.emit(match op {
+            CompOp::LessThan => Instruction::CompareOp(ComparisonOperator::Less),
+            CompOp::LessThanEqual => Instruction::CompareOp(ComparisonOperator::LessEqual),
+            CompOp::Equal => Instruction::CompareOp(ComparisonOperator::Equal),
+            CompOp::NotEqual => Instruction::CompareOp(ComparisonOperator::NotEqual),
+            CompOp::GreaterThan => Instruction::CompareOp(ComparisonOperator::Greater),
+            CompOp::GreaterThanEqual => Instruction::CompareOp(ComparisonOperator::GreaterEqual),
+            CompOp::Is => Instruction::IsOp(false),
+            CompOp::IsNot => Instruction::IsOp(true),
+            CompOp::In => Instruction::ContainsOp(false),
+            CompOp::NotIn => Instruction::ContainsOp(true),
+        });
+    }
+
+    /// `a < b < c` evaluates `b` once and stops at the first false comparison.
+    fn compile_comparison(&mut self, comparison: &'c Comparison<'a>) -> Result<()> {
+        self.compile_expression(&comparison.left)?;
+        let (last, init) = comparison.comparisons.split_last().expect("comparison without operators");
+        if init.is_empty() {
+            self.compile_expression(&last.comparator)?;
+            self.emit_compare(&last.operator);
+            return Ok(());
+        }
+        let (cleanup, end) = (self.new_label(), self.new_label());
+        for target in init {
+            self.compile_expression(&target.comparator)?;
+            self.emit(Instruction::Swap(2));
+            self.emit(Instruction::Copy(2));
+            self.emit_compare(&target.operator);
+            self.emit_jump(Instruction::JumpIfFalseOrPop, cleanup);
+        }
+        self.compile_expression(&last.comparator)?;
+        self.emit_compare(&last.operator);
+        self.emit_jump(Instruction::Jump, end);
+        self.bind(cleanup);
+        self.emit(Instruction::Swap(2));
+        self.emit(Instruction::Pop);
+        self.bind(end);
+        Ok(())
+    }
+
+    fn attr_idx(&mut self, attr: &str) -> u32 {
+        let name = self.mangle(attr);
+        self.name_idx(&name)
+    }
+
+    /// Loads, stores (with the value below) or deletes an attribute.
+    fn compile_attribute(&mut self, attr: &'c Attribute<'a>, ctx: Ctx) -> Result<()> {
+        self.compile_expression(&attr.value)?;
+        let idx = self.attr_idx(attr.attr.value);
+        self.emit(match ctx {
+            Ctx::Load => Instruction::LoadAttr(idx),
+            Ctx::Store => Instruction::StoreAttr(idx),
+            Ctx::Del => Instruction::DeleteAttr(idx),
+        });
+        Ok(())
+    }
+
+    fn compile_subscript(&mut self, subscript: &'c Subscript<'a>, ctx: Ctx) -> Result<()> {
+        self.compile_expression(&subscript.value)?;
+        self.compile_slice(&subscript.slice)?;
+        self.emit(match ctx {
+            Ctx::Load => Instruction::BinarySubscr,
+            Ctx::Store => Instruction::StoreSubscr,
+            Ctx::Del => Instruction::DeleteSubscr,
+        });
+        Ok(())
+    }
+
+    /// Pushes the index of a subscript: a value, a slice, or a tuple of several of those.
+    fn compile_slice(&mut self, elements: &'c [SubscriptElement<'a>]) -> Result<()> {
+        let is_starred = |e: &SubscriptElement| {
+            matches!(&e.slice, BaseSlice::Index(i) if matches!(i.value, Expression::StarredElement(_)))
+        };
+        let starred = elements.iter().any(is_starred);
+        if let [element] = elements {
+            if !starred {
+                return self.compile_slice_element(element);
+            }
+        }
+        if !starred {
+            for element in elements {
+                self.compile_slice_element(element)?;
+            }
+            self.emit(Instruction::BuildTuple(elements.len() as u32));
+            return Ok(());
+        }
+        self.emit(Instruction::BuildList(0));
+        for element in elements {
+            match &element.slice {
+                BaseSlice::Index(i) => match &i.value {
+                    Expression::StarredElement(s) => {
+                        self.compile_expression(&s.value)?;
+                        self.emit(Instruction::ListExtend(1));
+                    }
+                    _ => {
+                        self.compile_slice_element(element)?;
+                        self.emit(Instruction::ListAppend(1));
+                    }
+                },
+                BaseSlice::Slice(_) => {
+                    self.compile_slice_element(element)?;
+                    self.emit(Instruction::ListAppend(1));
+                }
+            }
+        }
+        self.emit(Instruction::ListToTuple);
+        Ok(())
+    }
+
+    fn compile_slice_element(&mut self, element: &'c SubscriptElement<'a>) -> Result<()> {
+        match &element.slice {
+            BaseSlice::Index(i) => self.compile_expression(&i.value),
+            BaseSlice::Slice(s) => {
+                for part in [&s.lower, &s.upper] {
+                    match part {
+                        Some(part) => self.compile_expression(part)?,
+                        None => self.load_const(ConstantValue::None),
+                    }
+                }
+                match &s.step {
+                    Some(step) => {
+                        self.compile_expression(step)?;
+                        self.emit(Instruction::BuildSlice(3));
+                    }
+                    None => self.emit(Instruction::BuildSlice(2)),
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn compile_collection(&mut self, elements: &'c [Element<'a>], kind: Collection) -> Result<()> {
+        if let Collection::Tuple = kind {
+            let constants: Option<Vec<_>> = elements
+                .iter()
+                .map(|e| match e {
+                    Element::Simple { value } => constant_of(value),
+                    Element::Starred(_) => None,
+                })
+                .collect();
+            if let Some(constants) = constants.filter(|c| !c.is_empty()) {
+                self.load_const(ConstantValue::Tuple(constants));
+                return Ok(());
+            }
+        }
+        let starred = elements.iter().position(|e| matches!(e, Element::Starred(_)));
+        let prefix = starred.unwrap_or(elements.len());
+        for element in &elements[..prefix] {
+            if let Element::Simple { value } = element {
+                self.compile_expression(value)?;
+            }
+        }
+        let count = prefix as u32;
+        if starred.is_none() {
+            self.emit(match kind {
+                Collection::Tuple => Instruction::BuildTuple(count),
+                Collection::List => Instruction::BuildList(count),
+                Collection::Set => Instruction::BuildSet(count),
+            });
+            return Ok(());
+        }
+        let is_set = matches!(kind, Collection::Set);
+        self.emit(if is_set { Instruction::BuildSet(count) } else { Instruction::BuildList(count) });
+        for element in &elements[prefix..] {
+            match element {
+                Element::Simple { value } => {
+                    self.compile_expression(value)?;
+                    self.emit(if is_set { Instruction::SetAdd(1) } else { Instruction::ListAppend(1) });
+                }
+                Element::Starred(s) => {
+                    self.compile_expression(&s.value)?;
+                    self.emit(if is_set { Instruction::SetUpdate(1) } else { Instruction::ListExtend(1) });
+                }
+            }
+        }
+        if let Collection::Tuple = kind {
+            self.emit(Instruction::ListToTuple);
+        }
+        Ok(())
+    }
+
+    fn compile_dict(&mut self, dict: &'c Dict<'a>) -> Result<()> {
+        let starred = dict.elements.iter().any(|e| matches!(e, DictElement::Starred(_)));
+        if !starred {
+            for element in &dict.elements {
+                if let DictElement::Simple { key, value } = element {
+                    self.compile_expression(key)?;
+                    self.compile_expression(value)?;
+                }
+            }
+            self.emit(Instruction::BuildMap(dict.elements.len() as u32));
+            return Ok(());
+        }
+        self.emit(Instruction::BuildMap(0));
+        for element in &dict.elements {
+            match element {
+                DictElement::Simple { key, value } => {
+                    self.compile_expression(key)?;
+                    self.compile_expression(value)?;
+                    self.emit(Instruction::MapAdd(1));
+                }
+                DictElement::Starred(s) => {
+                    self.compile_expression(&s.value)?;
+                    self.emit(Instruction::DictUpdate(1));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls the callable below `pushed` positional arguments already on the stack, with
+    /// `args` after them.
+    fn compile_call_args(&mut self, pushed: u32, args: &[&'c Arg<'a>]) -> Result<()> {
+        if args.iter().all(|arg| arg.star.is_empty()) {
+            let mut kwnames = vec![];
+            for arg in args {
+                self.compile_expression(&arg.value)?;
+                if let Some(keyword) = &arg.keyword {
+                    kwnames.push(ConstantValue::Str(keyword.value.to_string()));
+                }
+            }
+            let argc = pushed + args.len() as u32;
+            if kwnames.is_empty() {
+                self.emit(Instruction::Call(argc));
+            } else {
+                self.load_const(ConstantValue::Tuple(kwnames));
+                self.emit(Instruction::CallKw(argc));
+            }
+            return Ok(());
+        }
+
+        // With `*args` or `**kwargs` the arguments are collected in a tuple and a dict.
+        self.emit(Instruction::BuildList(pushed));
+        for arg in args.iter().filter(|arg| arg.keyword.is_none() && arg.star != "**") {
+            self.compile_expression(&arg.value)?;
+            self.emit(if arg.star == "*" { Instruction::ListExtend(1) } else { Instruction::ListAppend(1) });
+        }
+        self.emit(Instruction::ListToTuple);
+        let keywords: Vec<_> = args.iter().filter(|arg| arg.keyword.is_some() || arg.star == "**").collect();
+        if !keywords.is_empty() {
+            self.emit(Instruction::BuildMap(0));
+            for arg in keywords {
+                if let Some(keyword) = &arg.keyword {
+                    self.load_str(keyword.value);
+                    self.compile_expression(&arg.value)?;
+                    self.emit(Instruction::BuildMap(1));
+                } else {
+                    self.compile_expression(&arg.value)?;
+                }
+                self.emit(Instruction::DictMerge(1));
+            }
+        }
+        self.emit(Instruction::CallFunctionEx(args.iter().any(|arg| arg.keyword.is_some() || arg.star == "**")));
+        Ok(())
+    }
+
+    fn compile_lambda(&mut self, lambda: &'c Lambda<'a>) -> Result<()> {
+        let flags = self.compile_defaults(&lambda.params)?;
+        let table = self.child_table("lambda")?;
+        self.enter_unit(table, "<lambda>", UnitKind::Lambda(&lambda.params));
+        let result = self.compile_expression(&lambda.body);
+        self.emit(Instruction::ReturnValue);
+        let code = self.leave_unit();
+        result?;
+        self.make_closure(code, flags)
+    }
+
+    /// A comprehension is a function called with an iterator over its first iterable, which
+    /// is evaluated in the enclosing scope.
+    fn compile_comprehension(&mut self, for_in: &'c CompFor<'a>, kind: CompKind<'c, 'a>) -> Result<()> {
+        let mut is_async = false;
+        let mut clause = Some(for_in);
+        while let Some(c) = clause {
+            is_async |= c.asynchronous.is_some();
+            clause = c.inner_for_in.as_deref();
+        }
+        self.compile_expression(&for_in.iter)?;
+        self.emit(if for_in.asynchronous.is_some() { Instruction::GetAiter } else { Instruction::GetIter });
+
+        let table = self.child_table(kind.table_name())?;
+        let name = format!("<{}>", kind.table_name());
+        self.enter_unit(table, &name, UnitKind::Comprehension { is_async });
+        self.emit(match kind {
+            CompKind::List(_) => Instruction::BuildList(0),
+            CompKind::Set(_) => Instruction::BuildSet(0),
+            CompKind::Dict(..) => Instruction::BuildMap(0),
+            CompKind::Generator(_) => Instruction::Nop,
+        });
+        let result = self.compile_comprehension_loop(for_in, 0, kind);
+        if let CompKind::Generator(_) = kind {
+            self.load_const(ConstantValue::None);
+        }
+        self.emit(Instruction::ReturnValue);
+        let code = self.leave_unit();
+        result?;
+
+        self.make_closure(code, 0)?;
+        self.emit(Instruction::Swap(2));
+        self.emit(Instruction::Call(1));
+        if is_async && !matches!(kind, CompKind::Generator(_)) {
+            self.emit_await();
+        }
+        Ok(())
+    }
+
+    /// One `for` clause of a comprehension, with `depth` iterators of outer clauses on the
+    /// stack.
+    fn compile_comprehension_loop(&mut self, clause: &'c CompFor<'a>, depth: u32, kind: CompKind<'c, 'a>) -> Result<()> {
+        let is_async = clause.asynchronous.is_some();
+        let (start, exhausted) = (self.new_label(), self.new_label());
+        if depth == 0 {
+            self.emit(Instruction::LoadFast(0));
+        } else {
+            self.compile_expression(&clause.iter)?;
+            self.emit(if is_async { Instruction::GetAiter } else { Instruction::GetIter });
+        }
+        self.bind(start);
+        self.emit_next_item(is_async, exhausted);
+        self.compile_store(&clause.target)?;
+        for comp_if in &clause.ifs {
+            self.compile_jump_if(&comp_if.test, false, start)?;
+        }
+        match &clause.inner_for_in {
+            Some(inner) => self.compile_comprehension_loop(inner, depth + 1, kind)?,
+            None => match kind {
+                CompKind::List(elt) => {
+                    self.compile_expression(elt)?;
+                    self.emit(Instruction::ListAppend(depth + 2));
+                }
+                CompKind::Set(elt) => {
+                    self.compile_expression(elt)?;
+                    self.emit(Instruction::SetAdd(depth + 2));
+                }
+                CompKind::Dict(key, value) => {
+                    self.compile_expression(key)?;
+                    self.compile_expression(value)?;
+                    self.emit(Instruction::MapAdd(depth + 2));
+                }
+                CompKind::Generator(elt) => {
+                    self.compile_expression(elt)?;
+                    self.emit(Instruction::YieldValue);
+                    self.emit(Instruction::Pop);
+                }
+            },
+        }
+        self.emit_jump(Instruction::Jump, start);
+        self.bind(exhausted);
+        if is_async {
+            self.emit(Instruction::EndAsyncFor);
+        }
+        Ok(())
+    }
+
+    fn collect_string_pieces(&self, string: &'c StringNode<'a>, out: &mut Vec<Piece<'c, 'a>>) -> Result<()> {
+        match string {
+            StringNode::Simple(s) => match self.literal(s.parse_value())? {
+                StringValue::Str(s) => out.push(Piece::Text(s)),
+                StringValue::Bytes(_) => return Err(self.error("cannot mix bytes and nonbytes literals")),
+            },
+            StringNode::Concatenated(c) => {
+                self.collect_string_pieces(&c.left, out)?;
+                self.collect_string_pieces(&c.right, out)?;
+            }
+            StringNode::Formatted(f) => self.collect_fstring_parts(&f.parts, f.start, out)?,
+        }
+        Ok(())
+    }
+
+    fn collect_fstring_parts(
+        &self,
+        parts: &'c [FormattedStringContent<'a>],
+        start: &'a str,
+        out: &mut Vec<Piece<'c, 'a>>,
+    ) -> Result<()> {
+        for part in parts {
+            match part {
+                FormattedStringContent::Text(t) => out.push(Piece::Text(self.literal(t.parse_value(start))?)),
+                FormattedStringContent::Expression(e) => {
+                    // `f"{x=}"` shows the expression's source before its value.
+                    if e.equal.is_some() {
+                        out.push(Piece::Text(format!("{}=", e.expression.to_source())));
+                    }
+                    out.push(Piece::Field(e, start));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Pushes the str the pieces of an f-string make up.
+    fn compile_pieces(&mut self, pieces: Vec<Piece<'c, 'a>>) -> Result<()> {
+        let mut merged: Vec<Piece> = vec![];
+        for piece in pieces {
+            match (merged.last_mut(), piece) {
+                (Some(Piece::Text(last)), Piece::Text(text)) => last.push_str(&text),
+                (_, Piece::Text(text)) if text.is_empty() => {}
+                (_, piece) => merged.push(piece),
+            }
+        }
+        if merged.is_empty() {
+            self.load_str("");
+            return Ok(());
+        }
+        let count = merged.len() as u32;
+        for piece in merged {
+            match piece {
+                Piece::Text(text) => self.load_str(&text),
+                Piece::Field(field, start) => self.compile_formatted_field(field, start)?,
+            }
+        }
+        if count > 1 {
+            self.emit(Instruction::BuildString(count));
+        }
+        Ok(())
+    }
+
+    fn compile_formatted_field(&mut self, field: &'c FormattedStringExpression<'a>, start: &'a str) -> Result<()> {
+        self.compile_expression(&field.expression)?;
+        let conversion = match field.conversion {
+            Some("s") => Conversion::Str,
+            Some("r") => Conversion::Repr,
+            Some("a") => Conversion::Ascii,
+            _ if field.equal.is_some() && field.format_spec.is_none() => Conversion::Repr,
+            _ => Conversion::None,
+        };
+        let has_spec = match &field.format_spec {
+            Some(spec) => {
+                let mut pieces = vec![];
+                self.collect_fstring_parts(spec, start, &mut pieces)?;
+                self.compile_pieces(pieces)?;
+                true
+            }
+            None => false,
+        };
+        self.emit(Instruction::FormatValue { conversion, has_spec });
+        Ok(())
+    }
+
+    // Assignment targets
+
+    /// Stores the value on top in `target`.
+    fn compile_store(&mut self, target: &'c AssignTargetExpression<'a>) -> Result<()> {
+        match target {
+            AssignTargetExpression::Name(n) => self.name_op(n.value, Ctx::Store),
+            AssignTargetExpression::Attribute(a) => self.compile_attribute(a, Ctx::Store),
+            AssignTargetExpression::Subscript(s) => self.compile_subscript(s, Ctx::Store),
+            AssignTargetExpression::Tuple(t) => self.compile_unpack(&t.elements),
+            AssignTargetExpression::List(l) => self.compile_unpack(&l.elements),
+            AssignTargetExpression::StarredElement(_) => {
+                Err(self.error("starred assignment target must be in a list or tuple"))
+            }
+        }
+    }
+
+    fn compile_store_expression(&mut self, expr: &'c Expression<'a>) -> Result<()> {
+        match expr {
+            Expression::Name(n) => self.name_op(n.value, Ctx::Store),
+            Expression::Attribute(a) => self.compile_attribute(a, Ctx::Store),
+            Expression::Subscript(s) => self.compile_subscript(s, Ctx::Store),
+            Expression::Tuple(t) => self.compile_unpack(&t.elements),
+            Expression::List(l) => self.compile_unpack(&l.elements),
+            _ => Err(self.error("cannot assign to expression")),
+        }
+    }
+
+    fn compile_unpack(&mut self, elements: &'c [Element<'a>]) -> Result<()> {
+        let mut starred = elements.iter().enumerate().filter(|(_, e)| matches!(e, Element::Starred(_)));
+        let star = starred.next().map(|(idx, _)| idx);
+        if starred.next().is_some() {
+            return Err(self.error("multiple starred expressions in assignment"));
+        }
+        self.emit(match star {
+            Some(idx) => Instruction::UnpackEx { before: idx as u32, after: (elements.len() - idx - 1) as u32 },
+            None => Instruction::UnpackSequence(elements.len() as u32),
+        });
+        for element in elements {
+            match element {
+                Element::Simple { value } => self.compile_store_expression(value)?,
+                Element::Starred(s) => self.compile_store_expression(&s.value)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// CPython's private name mangling: `__spam` in class `Ham` becomes `_Ham__spam`.
+fn mangle(private: Option<&str>, name: &str) -> std::string::String {
+    let Some(class) = private else { return name.to_string() };
+    let class = class.trim_start_matches('_');
+    if !name.starts_with("__") || name.ends_with("__") || name.contains('.') || class.is_empty() {
+        return name.to_string();
+    }
+    format!("_{}{}", class, name)
+}
+
+fn string_constant(value: StringValue) -> ConstantValue {
+    match value {
+        StringValue::Str(s) => ConstantValue::Str(s),
+        StringValue::Bytes(b) => ConstantValue::Bytes(b),
+    }
+}
+
+fn binary_operator(op: &BinaryOp) -> BinaryOperator {
+    match op {
+        BinaryOp::Add => BinaryOperator::Add,
+        BinaryOp::Subtract => BinaryOperator::Subtract,
+        BinaryOp::Multiply => BinaryOperator::Multiply,
+        BinaryOp::MatrixMultiply => BinaryOperator::MatrixMultiply,
+        BinaryOp::Divide => BinaryOperator::TrueDivide,
+        BinaryOp::FloorDivide => BinaryOperator::FloorDivide,
+        BinaryOp::Modulo => BinaryOperator::Remainder,
+        BinaryOp::Power => BinaryOperator::Power,
+        BinaryOp::LeftShift => BinaryOperator::LeftShift,
+        BinaryOp::RightShift => BinaryOperator::RightShift,
+        BinaryOp::BitAnd => BinaryOperator::And,
+        BinaryOp::BitOr => BinaryOperator::Or,
+        BinaryOp::BitXor => BinaryOperator::Xor,
+    }
+}
+
+fn aug_operator(op: &AugOp) -> BinaryOperator {
+    match op {
+        AugOp::AddAssign => BinaryOperator::Add,
+        AugOp::SubtractAssign => BinaryOperator::Subtract,
+        AugOp::MultiplyAssign => BinaryOperator::Multiply,
+        AugOp::MatrixMultiplyAssign => BinaryOperator::MatrixMultiply,
+        AugOp::DivideAssign => BinaryOperator::TrueDivide,
+        AugOp::FloorDivideAssign => BinaryOperator::FloorDivide,
+        AugOp::ModuloAssign => BinaryOperator::Remainder,
+        AugOp::PowerAssign => BinaryOperator::Power,
+        AugOp::LeftShiftAssign => BinaryOperator::LeftShift,
+        AugOp::RightShiftAssign => BinaryOperator::RightShift,
+        AugOp::BitAndAssign => BinaryOperator::And,
+        AugOp::BitOrAssign => BinaryOperator::Or,
+        AugOp::BitXorAssign => BinaryOperator::Xor,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compile;
+    use crate::compiler::bytecode::{CodeObject, Instruction, CO_GENERATOR, CO_NESTED, CO_VARARGS};
+    use crate::compiler::OptimizerOptions;
+    use crate::parser::{parse_module, TokenArena};
+
+    fn compile_source(source: &str) -> Result<CodeObject, String> {
+        let arena = TokenArena::from_source(source).map_err(|err| err.to_string())?;
+        let mut module = parse_module(&arena, "<test>").map_err(|err| err.to_string())?;
+        compile(&mut module, "<test>", &OptimizerOptions::default()).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn compiles_each_fixture() {
+        // Legacy `async` names, bad indentation, assigning to an expression and to `True`.
+        let expected_errors = ["test_async.py", "indent_error_file.py", "test_float.py", "test_correct_newlines.py"];
+        let mut paths: Vec<_> = std::fs::read_dir("test_fixtures")
+            .expect("fixtures")
+            .map(|entry| entry.expect("fixture").path())
+            .collect();
+        paths.sort();
+        for path in paths {
+            let name = path.file_name().unwrap().to_string_lossy().to_string();
+            let source = std::fs::read_to_string(&path).expect("read fixture");
+            let result = compile_source(&source);
+            if expected_errors.contains(&name.as_str()) {
+                assert!(result.is_err(), "{} compiled", name);
+                continue;
+            }
+            let code = result.unwrap_or_else(|err| panic!("{}: {}", name, err));
+            assert_eq!(code.instructions.last(), Some(&Instruction::ReturnValue), "{}", name);
+        }
+    }
+
+    #[test]
+    fn lays_out_closures_and_blocks() {
+        let source = "\
+def outer(a, *rest, k=1):
+    total = [x * a for x in rest]
+    def inner():
+        nonlocal total
+        yield total
+    try:
+        return inner
+    finally:
+        del total
+
+class A(Base):
+    def get(self):
+        return super().get()
+";
+        let code = compile_source(source).expect("compile");
+        let outer = code.children().next().expect("outer");
+        assert_eq!(outer.qualname, "outer");
+        assert_eq!((outer.argcount, outer.kwonly_argcount), (1, 1));
+        assert_eq!(outer.varnames, ["a", "k", "rest", "inner"]);
+        assert_eq!(outer.cellvars, ["a", "total"]);
+        assert!(outer.flags & CO_VARARGS != 0);
+
+        let children: Vec<_> = outer.children().collect();
+        assert_eq!(children[0].qualname, "outer.<locals>.<listcomp>");
+        assert_eq!(children[0].freevars, ["a"]);
+        assert_eq!(children[1].qualname, "outer.<locals>.inner");
+        assert_eq!(children[1].freevars, ["total"]);
+        assert!(children[1].flags & CO_GENERATOR != 0 && children[1].flags & CO_NESTED != 0);
+        // The `finally` body runs on the way out of `return` and when an exception escapes.
+        let deletes = outer.instructions.iter().filter(|i| matches!(i, Instruction::DeleteDeref(_))).count();
+        assert_eq!(deletes, 3);
+
+        let class = code.children().nth(1).expect("class body");
+        assert_eq!(class.cellvars, ["__class__"]);
+        let method = class.children().next().expect("method");
+        assert_eq!(method.qualname, "A.get");
+        assert_eq!(method.freevars, ["__class__"]);
+        assert_eq!((method.first_lineno, method.line_number(0)), (12, 13));
+    }
+}
@@ -0,0 +1,9 @@
+pub mod bytecode;
+pub mod codegen;
+pub mod optimizer;
+pub mod symtable;
+
+pub use bytecode::{CodeObject, Const, Instruction};
+pub use codegen::compile;
+pub use optimizer::{optimize, OptimizerOptions};
+pub use symtable::{symtable, Scope, Symbol, SymbolTable, SymbolTableType};
@@ -0,0 +1,787 @@
+//! Rewrites a `Module` in place before code generation, after CPython's `ast_opt.c`.
+//!
+//! Folding happens bottom up: once every operand of an operator has become a constant the
+//! operator itself is replaced by an `Expression::Constant`.  Anything that would raise at
+//! runtime (division by zero, a negative shift count) or produce an oversized result is left
+//! alone so the error still happens when the code runs.
+//!
+//! Removing a dead branch also removes the names it binds and any `yield` in it, both of
+//! which still decide how the enclosing function is compiled, so build the `symtable` for a
+//! module before optimizing it.
+
+use std::cmp::Ordering;
+
+use crate::ast::visitor::{self, VisitorMut};
+use crate::ast::{
+    BinaryOp, BooleanOp, Boxed, CompFor, CompOp, CompoundStatement, Constant, ConstantValue, Element, Expression,
+    Module, SimpleStatementLine, SmallStatement, Span, Statement, Suite, UnaryOp,
+};
+use crate::ast::strings::StringValue;
+use crate::bigint::BigInt;
+
+/// Folded ints may use at most this many bits.
+const MAX_INT_SIZE: u64 = 128;
+/// Folded tuples may hold at most this many items.
+const MAX_COLLECTION_SIZE: usize = 256;
+/// Folded str and bytes values may be at most this long.
+const MAX_STR_SIZE: usize = 4096;
+/// Folded tuples may hold at most this many items counting nested tuples.
+const MAX_TOTAL_ITEMS: usize = 1024;
+
+/// Ints up to this magnitude convert to a float exactly.
+const EXACT_FLOAT_INT: i64 = 1 << 53;
+
+#[derive(Debug, Clone)]
+pub struct OptimizerOptions {
+    /// The `-O` level.  Above zero `__debug__` is false, so `if __debug__:` blocks go away.
+    pub optimize: u8,
+    /// Fold operators whose operands are all constants.
+    pub fold_constants: bool,
+    /// Turn constant list and set displays used with `in` or as a `for` iterable into
+    /// tuples and frozensets.
+    pub fold_membership: bool,
+    /// Drop `if`/`elif` branches and `while` loops whose test is a constant.
+    pub remove_dead_branches: bool,
+    /// Merge implicitly concatenated string literals into one constant.
+    pub merge_strings: bool,
+}
+
+impl Default for OptimizerOptions {
+    fn default() -> Self {
+        Self {
+            optimize: 0,
+            fold_constants: true,
+            fold_membership: true,
+            remove_dead_branches: true,
+            merge_strings: true,
+        }
+    }
+}
+
+pub fn optimize(module: &mut Module, options: &OptimizerOptions) {
+    Optimizer { options }.visit_module(module);
+}
+
+struct Optimizer<'o> {
+    options: &'o OptimizerOptions,
+}
+
+impl<'o> Optimizer<'o> {
+    /// Replaces constant `if` and `while` statements in `body` by the statements that would
+    /// actually run.  Blocks that end up empty get a `pass` so they stay well formed.
+    fn prune(&self, body: &mut Vec<Statement>, keep_nonempty: bool) {
+        if !self.options.remove_dead_branches {
+            return;
+        }
+        let first_span = body.first().map(|stmt| stmt.span());
+        let old = std::mem::take(body);
+        for stmt in old {
+            match stmt {
+                Statement::Compound(CompoundStatement::If(mut stmt)) => match constant_of(&stmt.test) {
+                    Some(test) if test.is_truthy() => suite_statements(stmt.body, body),
+                    Some(_) => match stmt.orelse.take().map(Boxed::into_inner) {
+                        Some(crate::ast::OrElse::Elif(mut elif)) => {
+                            elif.is_elif = false;
+                            // The elif may itself be dead now that it is the first branch.
+                            let mut rest = vec![Statement::Compound(CompoundStatement::If(elif))];
+                            self.prune(&mut rest, false);
+                            body.extend(rest);
+                        }
+                        Some(crate::ast::OrElse::Else(orelse)) => suite_statements(orelse.body, body),
+                        None => {}
+                    },
+                    None => body.push(Statement::Compound(CompoundStatement::If(stmt))),
+                },
+                Statement::Compound(CompoundStatement::While(stmt))
+                    if constant_of(&stmt.test).is_some_and(|test| !test.is_truthy()) =>
+                {
+                    if let Some(orelse) = stmt.orelse {
+                        suite_statements(orelse.body, body);
+                    }
+                }
+                stmt => body.push(stmt),
+            }
+        }
+        if keep_nonempty && body.is_empty() {
+            body.push(pass_statement(first_span.unwrap_or_default()));
+        }
+    }
+
+    fn fold(&self, expr: &mut Expression) -> Option<ConstantValue> {
+        match expr {
+            Expression::Name(n) if n.value == "__debug__" && self.options.fold_constants => {
+                Some(ConstantValue::Bool(self.options.optimize == 0))
+            }
+            Expression::UnaryOperation(u) if self.options.fold_constants => {
+                fold_unary(&u.operator, &constant_of(&u.expression)?)
+            }
+            Expression::BinaryOperation(b) if self.options.fold_constants => {
+                fold_binary(&b.operator, &constant_of(&b.left)?, &constant_of(&b.right)?)
+            }
+            Expression::Comparison(c) => {
+                if self.options.fold_membership {
+                    for target in c.comparisons.iter_mut() {
+                        if matches!(target.operator, CompOp::In | CompOp::NotIn) {
+                            fold_container(&mut target.comparator);
+                        }
+                    }
+                }
+                if !self.options.fold_constants {
+                    return None;
+                }
+                let mut left = constant_of(&c.left)?;
+                for target in &c.comparisons {
+                    let right = constant_of(&target.comparator)?;
+                    if !compare(&target.operator, &left, &right)? {
+                        return Some(ConstantValue::Bool(false));
+                    }
+                    left = right;
+                }
+                Some(ConstantValue::Bool(true))
+            }
+            Expression::Tuple(t) if self.options.fold_constants => t
+                .elements
+                .iter()
+                .map(|element| match element {
+                    Element::Simple { value } => constant_of(value),
+                    Element::Starred(_) => None,
+                })
+                .collect::<Option<Vec<_>>>()
+                .map(ConstantValue::Tuple),
+            Expression::ConcatenatedString(c) if self.options.merge_strings => match c.parse_value()? {
+                Ok(StringValue::Str(s)) => Some(ConstantValue::Str(s)),
+                Ok(StringValue::Bytes(b)) => Some(ConstantValue::Bytes(b)),
+                Err(_) => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// `a and b` / `a or b` with a constant `a` is whichever operand decides the result.
+    fn fold_boolean(&self, expr: &mut Expression) {
+        let Expression::BooleanOperation(b) = expr else { return };
+        let Some(left) = constant_of(&b.left) else { return };
+        let keep_left = match b.operator {
+            BooleanOp::And => !left.is_truthy(),
+            BooleanOp::Or => left.is_truthy(),
+        };
+        let side = if keep_left { &mut b.left } else { &mut b.right };
+        let side = std::mem::replace(side.as_mut(), Expression::Ellipsis(Boxed::default()));
+        *expr = side;
+    }
+
+    /// `not a in b` is the same test as `a not in b`, and likewise for `is`.
+    fn fold_not_comparison(&self, expr: &mut Expression) {
+        let Expression::UnaryOperation(u) = expr else { return };
+        if u.operator != UnaryOp::Not {
+            return;
+        }
+        let Expression::Comparison(c) = u.expression.as_mut() else { return };
+        if c.comparisons.len() != 1 {
+            return;
+        }
+        let inverted = match c.comparisons[0].operator {
+            CompOp::In => CompOp::NotIn,
+            CompOp::NotIn => CompOp::In,
+            CompOp::Is => CompOp::IsNot,
+            CompOp::IsNot => CompOp::Is,
+            _ => return,
+        };
+        c.comparisons[0].operator = inverted;
+        let comparison = std::mem::replace(u.expression.as_mut(), Expression::Ellipsis(Boxed::default()));
+        *expr = comparison;
+    }
+
+    fn fold_iter(&self, iter: &mut Expression) {
+        if self.options.fold_membership {
+            fold_container(iter);
+        }
+    }
+}
+
+impl<'a, 'o> VisitorMut<'a> for Optimizer<'o> {
+    fn visit_module(&mut self, node: &mut Module<'a>) {
+        visitor::walk_module_mut(self, node);
+        self.prune(&mut node.body, false);
+    }
+
+    fn visit_suite(&mut self, node: &mut Suite<'a>) {
+        visitor::walk_suite_mut(self, node);
+        if let Suite::IndentedBlock(block) = node {
+            self.prune(&mut block.body, true);
+        }
+    }
+
+    fn visit_compound_statement(&mut self, node: &mut CompoundStatement<'a>) {
+        visitor::walk_compound_statement_mut(self, node);
+        if let CompoundStatement::For(f) = node {
+            self.fold_iter(&mut f.iter);
+        }
+    }
+
+    fn visit_comp_for(&mut self, node: &mut CompFor<'a>) {
+        visitor::walk_comp_for_mut(self, node);
+        self.fold_iter(&mut node.iter);
+    }
+
+    fn visit_expression(&mut self, node: &mut Expression<'a>) {
+        visitor::walk_expression_mut(self, node);
+        if self.options.fold_constants {
+            self.fold_boolean(node);
+            self.fold_not_comparison(node);
+        }
+        if let Some(value) = self.fold(node) {
+            let span = node.span();
+            *node = Expression::Constant(Boxed::new(Constant { value, span }));
+        }
+    }
+}
+
+fn suite_statements<'a>(suite: Suite<'a>, out: &mut Vec<Statement<'a>>) {
+    match suite {
+        Suite::IndentedBlock(block) => out.extend(block.body),
+        Suite::SimpleStatementSuite(suite) => out.push(Statement::Simple(SimpleStatementLine {
+            body: suite.body,
+            span: suite.span,
+        })),
+    }
+}
+
+fn pass_statement<'a>(span: Span) -> Statement<'a> {
+    Statement::Simple(SimpleStatementLine {
+        body: vec![SmallStatement::Pass],
+        span,
+    })
+}
+
+/// A constant list display becomes a tuple and a constant set display a frozenset, which
+/// the compiler can load in one instruction.
+fn fold_container(expr: &mut Expression) {
+    let value = match expr {
+        Expression::List(l) => element_constants(&l.elements).map(ConstantValue::Tuple),
+        Expression::Set(s) => element_constants(&s.elements).and_then(|items| {
+            let mut unique: Vec<ConstantValue> = vec![];
+            for item in items {
+                let mut seen = false;
+                for existing in &unique {
+                    seen |= py_eq(existing, &item)?;
+                }
+                if !seen {
+                    unique.push(item);
+                }
+            }
+            Some(ConstantValue::FrozenSet(unique))
+        }),
+        _ => None,
+    };
+    if let Some(value) = value {
+        let span = expr.span();
+        *expr = Expression::Constant(Boxed::new(Constant { value, span }));
+    }
+}
+
+fn element_constants(elements: &[Element]) -> Option<Vec<ConstantValue>> {
+    elements
+        .iter()
+        .map(|element| match element {
+            Element::Simple { value } => constant_of(value),
+            Element::Starred(_) => None,
+        })
+        .collect()
+}
+
+/// The value of `expr` if it is a literal or an already folded constant.
+pub fn constant_of(expr: &Expression) -> Option<ConstantValue> {
+    match expr {
+        Expression::Constant(c) => Some(c.value.clone()),
+        Expression::Integer(i) => i.parse_value().ok().map(ConstantValue::Int),
+        Expression::Hexidecimal(h) => h.parse_value().ok().map(ConstantValue::Int),
+        Expression::Binary(b) => b.parse_value().ok().map(ConstantValue::Int),
+        Expression::Float(f) => f.parse_value().ok().map(ConstantValue::Float),
+        Expression::Imaginary(i) => i.parse_value().ok().map(|imag| ConstantValue::Complex { real: 0.0, imag }),
+        Expression::SimpleString(s) => match s.parse_value().ok()? {
+            StringValue::Str(s) => Some(ConstantValue::Str(s)),
+            StringValue::Bytes(b) => Some(ConstantValue::Bytes(b)),
+        },
+        Expression::Ellipsis(_) => Some(ConstantValue::Ellipsis),
+        Expression::Name(n) => match n.value {
+            "True" => Some(ConstantValue::Bool(true)),
+            "False" => Some(ConstantValue::Bool(false)),
+            "None" => Some(ConstantValue::None),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn as_int(value: &ConstantValue) -> Option<i64> {
+    match value {
+        ConstantValue::Bool(b) => Some(*b as i64),
+        ConstantValue::Int(i) => i.to_i64(),
+        _ => None,
+    }
+}
+
+fn as_float(value: &ConstantValue) -> Option<f64> {
+    match value {
+        ConstantValue::Float(f) => Some(*f),
+        ConstantValue::Bool(_) | ConstantValue::Int(_) => as_int(value).map(|i| i as f64),
+        _ => None,
+    }
+}
+
+fn as_complex(value: &ConstantValue) -> Option<(f64, f64)> {
+    match value {
+        ConstantValue::Complex { real, imag } => Some((*real, *imag)),
+        _ => as_float(value).map(|f| (f, 0.0)),
+    }
+}
+
+fn is_int(value: &ConstantValue) -> bool {
+    matches!(value, ConstantValue::Int(_) | ConstantValue::Bool(_))
+}
+
+fn int(value: i64) -> ConstantValue {
+    ConstantValue::Int(BigInt::from_i64(value))
+}
+
+fn bit_length(value: i64) -> u64 {
+    64 - value.unsigned_abs().leading_zeros() as u64
+}
+
+fn fold_unary(op: &UnaryOp, value: &ConstantValue) -> Option<ConstantValue> {
+    match (op, value) {
+        (UnaryOp::Not, value) => Some(ConstantValue::Bool(!value.is_truthy())),
+        (UnaryOp::Minus, ConstantValue::Float(f)) => Some(ConstantValue::Float(-f)),
+        (UnaryOp::Plus, ConstantValue::Float(f)) => Some(ConstantValue::Float(*f)),
+        (UnaryOp::Minus, ConstantValue::Complex { real, imag }) => {
+            Some(ConstantValue::Complex { real: -real, imag: -imag })
+        }
+        (UnaryOp::Plus, ConstantValue::Complex { .. }) => Some(value.clone()),
+        (UnaryOp::Minus, value) if is_int(value) => as_int(value)?.checked_neg().map(int),
+        (UnaryOp::Plus, value) if is_int(value) => as_int(value).map(int),
+        (UnaryOp::BitInvert, value) if is_int(value) => as_int(value).map(|i| int(!i)),
+        _ => None,
+    }
+}
+
+fn fold_binary(op: &BinaryOp, left: &ConstantValue, right: &ConstantValue) -> Option<ConstantValue> {
+    use ConstantValue::{Bytes, Complex, Str, Tuple};
+
+    if is_int(left) && is_int(right) {
+        return fold_int(op, left, right);
+    }
+    match (op, left, right) {
+        (BinaryOp::Add, Str(l), Str(r)) => Some(Str(format!("{}{}", l, r))),
+        (BinaryOp::Add, Bytes(l), Bytes(r)) => Some(Bytes([l.as_slice(), r.as_slice()].concat())),
+        (BinaryOp::Add, Tuple(l), Tuple(r)) => {
+            let items: Vec<ConstantValue> = l.iter().chain(r.iter()).cloned().collect();
+            let folded = Tuple(items);
+            (folded.total_items() <= MAX_TOTAL_ITEMS).then_some(folded)
+        }
+        (BinaryOp::Multiply, Str(_) | Bytes(_) | Tuple(_), count) if is_int(count) => repeat(left, as_int(count)?),
+        (BinaryOp::Multiply, count, Str(_) | Bytes(_) | Tuple(_)) if is_int(count) => repeat(right, as_int(count)?),
+        (_, Complex { .. }, _) | (_, _, Complex { .. }) => fold_complex(op, as_complex(left)?, as_complex(right)?),
+        _ => fold_float(op, as_float(left)?, as_float(right)?),
+    }
+}
+
+fn fold_int(op: &BinaryOp, left: &ConstantValue, right: &ConstantValue) -> Option<ConstantValue> {
+    let (l, r) = (as_int(left)?, as_int(right)?);
+    let both_bool = matches!((left, right), (ConstantValue::Bool(_), ConstantValue::Bool(_)));
+    let value = match op {
+        BinaryOp::Add => l.checked_add(r)?,
+        BinaryOp::Subtract => l.checked_sub(r)?,
+        BinaryOp::Multiply => {
+            if bit_length(l) + bit_length(r) > MAX_INT_SIZE {
+                return None;
+            }
+            l.checked_mul(r)?
+        }
+        BinaryOp::Divide => {
+            // Only exact when both operands are exactly representable as floats.
+            if r == 0 || l.abs() > EXACT_FLOAT_INT || r.abs() > EXACT_FLOAT_INT {
+                return None;
+            }
+            return Some(ConstantValue::Float(l as f64 / r as f64));
+        }
+        BinaryOp::FloorDivide => {
+            if r == 0 {
+                return None;
+            }
+            let (div, rem) = (l.checked_div(r)?, l.checked_rem(r)?);
+            if rem != 0 && (rem < 0) != (r < 0) {
+                div - 1
+            } else {
+                div
+            }
+        }
+        BinaryOp::Modulo => {
+            if r == 0 {
+                return None;
+            }
+            let rem = l.checked_rem(r)?;
+            if rem != 0 && (rem < 0) != (r < 0) {
+                rem + r
+            } else {
+                rem
+            }
+        }
+        BinaryOp::Power => {
+            if r < 0 {
+                if l == 0 {
+                    return None;
+                }
+                return fold_float(op, l as f64, r as f64);
+            }
+            if r > 0 && bit_length(l) > MAX_INT_SIZE / r as u64 {
+                return None;
+            }
+            l.checked_pow(u32::try_from(r).ok()?)?
+        }
+        BinaryOp::LeftShift => {
+            if r < 0 || r as u64 > MAX_INT_SIZE || bit_length(l) > MAX_INT_SIZE - r as u64 {
+                return None;
+            }
+            if r >= 64 {
+                return if l == 0 { Some(int(0)) } else { None };
+            }
+            i64::try_from((l as i128) << r).ok()?
+        }
+        BinaryOp::RightShift => {
+            if r < 0 {
+                return None;
+            }
+            l >> r.min(63)
+        }
+        BinaryOp::BitAnd if both_bool => return Some(ConstantValue::Bool(l & r != 0)),
+        BinaryOp::BitOr if both_bool => return Some(ConstantValue::Bool(l | r != 0)),
+        BinaryOp::BitXor if both_bool => return Some(ConstantValue::Bool(l ^ r != 0)),
+        BinaryOp::BitAnd => l & r,
+        BinaryOp::BitOr => l | r,
+        BinaryOp::BitXor => l ^ r,
+        BinaryOp::MatrixMultiply => return None,
+    };
+    Some(int(value))
+}
+
+fn fold_float(op: &BinaryOp, l: f64, r: f64) -> Option<ConstantValue> {
+    let value = match op {
+        BinaryOp::Add => l + r,
+        BinaryOp::Subtract => l - r,
+        BinaryOp::Multiply => l * r,
+        BinaryOp::Divide if r != 0.0 => l / r,
+        BinaryOp::FloorDivide if r != 0.0 => float_div_mod(l, r).0,
+        BinaryOp::Modulo if r != 0.0 => float_div_mod(l, r).1,
+        BinaryOp::Power => {
+            if l == 0.0 && r < 0.0 {
+                return None;
+            }
+            if l < 0.0 && r.fract() != 0.0 && r.is_finite() {
+                // A negative base with a fractional exponent is complex.
+                return None;
+            }
+            let value = l.powf(r);
+            if value.is_infinite() && l.is_finite() && r.is_finite() {
+                // OverflowError at runtime.
+                return None;
+            }
+            value
+        }
+        _ => return None,
+    };
+    Some(ConstantValue::Float(value))
+}
+
+/// Python's float `divmod`: the remainder takes the sign of the divisor.
+fn float_div_mod(l: f64, r: f64) -> (f64, f64) {
+    let mut rem = l % r;
+    let mut div = (l - rem) / r;
+    if rem != 0.0 {
+        if (r < 0.0) != (rem < 0.0) {
+            rem += r;
+            div -= 1.0;
+        }
+    } else {
+        rem = 0f64.copysign(r);
+    }
+    let floordiv = if div != 0.0 {
+        let mut floordiv = div.floor();
+        if div - floordiv > 0.5 {
+            floordiv += 1.0;
+        }
+        floordiv
+    } else {
+        0f64.copysign(l / r)
+    };
+    (floordiv, rem)
+}
+
+fn fold_complex(op: &BinaryOp, (lr, li): (f64, f64), (rr, ri): (f64, f64)) -> Option<ConstantValue> {
+    let (real, imag) = match op {
+        BinaryOp::Add => (lr + rr, li + ri),
+        BinaryOp::Subtract => (lr - rr, li - ri),
+        BinaryOp::Multiply => (lr * rr - li * ri, lr * ri + li * rr),
+        _ => return None,
+    };
+    Some(ConstantValue::Complex { real, imag })
+}
+
+/// `seq * count`, within CPython's size limits.
+fn repeat(seq: &ConstantValue, count: i64) -> Option<ConstantValue> {
+    let count = usize::try_from(count).ok()?;
+    match seq {
+        ConstantValue::Str(s) => {
+            (s.is_empty() || count <= MAX_STR_SIZE / s.len()).then(|| ConstantValue::Str(s.repeat(count)))
+        }
+        ConstantValue::Bytes(b) => {
+            (b.is_empty() || count <= MAX_STR_SIZE / b.len()).then(|| ConstantValue::Bytes(b.repeat(count)))
+        }
+        ConstantValue::Tuple(items) => {
+            if !items.is_empty() && count > MAX_COLLECTION_SIZE / items.len() {
+                return None;
+            }
+            let folded = ConstantValue::Tuple(items.iter().cycle().take(items.len() * count).cloned().collect());
+            (folded.total_items() <= MAX_TOTAL_ITEMS).then_some(folded)
+        }
+        _ => None,
+    }
+}
+
+/// Compares an int and a float exactly, the way CPython does, instead of rounding the int.
+fn int_float_cmp(i: i64, f: f64) -> Option<Ordering> {
+    if f.is_nan() {
+        return None;
+    }
+    if i.abs() <= EXACT_FLOAT_INT {
+        return (i as f64).partial_cmp(&f);
+    }
+    if f >= 9.223_372_036_854_776e18 {
+        return Some(Ordering::Less);
+    }
+    if f < -9.223_372_036_854_776e18 {
+        return Some(Ordering::Greater);
+    }
+    // |f| >= 2**53 here, so it has no fractional part and fits in an i64.
+    Some(i.cmp(&(f as i64)))
+}
+
+fn numeric_cmp(left: &ConstantValue, right: &ConstantValue) -> Option<Option<Ordering>> {
+    use ConstantValue::{Bool, Float, Int};
+    let ordering = match (left, right) {
+        (Int(_) | Bool(_), Int(_) | Bool(_)) => Some(as_int(left)?.cmp(&as_int(right)?)),
+        (Int(_) | Bool(_), Float(f)) => int_float_cmp(as_int(left)?, *f),
+        (Float(f), Int(_) | Bool(_)) => int_float_cmp(as_int(right)?, *f).map(Ordering::reverse),
+        (Float(l), Float(r)) => l.partial_cmp(r),
+        _ => return None,
+    };
+    // The outer option says whether these are numbers, the inner whether they are ordered
+    // (NaN is not).
+    Some(ordering)
+}
+
+/// Python's `==` between constants, or `None` when it can't be decided here.
+fn py_eq(left: &ConstantValue, right: &ConstantValue) -> Option<bool> {
+    use ConstantValue::{Bool, Complex, Float, FrozenSet, Int, Tuple};
+    if let Some(ordering) = numeric_cmp(left, right) {
+        return Some(ordering == Some(Ordering::Equal));
+    }
+    match (left, right) {
+        (Complex { .. }, _) | (_, Complex { .. }) => {
+            let (l, r) = (as_complex(left), as_complex(right));
+            match (l, r) {
+                (Some(l), Some(r)) => Some(l == r),
+                _ => Some(false),
+            }
+        }
+        (Tuple(l), Tuple(r)) => {
+            if l.len() != r.len() {
+                return Some(false);
+            }
+            for (l, r) in l.iter().zip(r.iter()) {
+                if !py_eq(l, r)? {
+                    return Some(false);
+                }
+            }
+            Some(true)
+        }
+        // Set equality ignores order; leave it to runtime.
+        (FrozenSet(_), FrozenSet(_)) => None,
+        (Int(_), _) | (_, Int(_)) => {
+            // A big int the i64 fast path could not compare.
+            if matches!((left, right), (Int(_) | Bool(_) | Float(_), Int(_) | Bool(_) | Float(_))) {
+                None
+            } else {
+                Some(false)
+            }
+        }
+        _ => Some(left == right),
+    }
+}
+
+/// Python's ordering between constants, `None` when comparing them would raise.
+fn py_cmp(left: &ConstantValue, right: &ConstantValue) -> Option<Option<Ordering>> {
+    use ConstantValue::{Bytes, Str, Tuple};
+    if let Some(ordering) = numeric_cmp(left, right) {
+        return Some(ordering);
+    }
+    match (left, right) {
+        (Str(l), Str(r)) => Some(Some(l.cmp(r))),
+        (Bytes(l), Bytes(r)) => Some(Some(l.cmp(r))),
+        (Tuple(l), Tuple(r)) => {
+            for (l, r) in l.iter().zip(r.iter()) {
+                if !py_eq(l, r)? {
+                    return py_cmp(l, r);
+                }
+            }
+            Some(Some(l.len().cmp(&r.len())))
+        }
+        _ => None,
+    }
+}
+
+fn contains(container: &ConstantValue, item: &ConstantValue) -> Option<bool> {
+    use ConstantValue::{Bytes, FrozenSet, Str, Tuple};
+    match (container, item) {
+        (Str(haystack), Str(needle)) => Some(haystack.contains(needle.as_str())),
+        (Bytes(haystack), Bytes(needle)) => {
+            Some(needle.is_empty() || haystack.windows(needle.len()).any(|window| window == needle.as_slice()))
+        }
+        (Tuple(items), _) | (FrozenSet(items), _) => {
+            for candidate in items {
+                if py_eq(candidate, item)? {
+                    return Some(true);
+                }
+            }
+            Some(false)
+        }
+        _ => None,
+    }
+}
+
+fn is_singleton(value: &ConstantValue) -> bool {
+    matches!(value, ConstantValue::None | ConstantValue::Bool(_) | ConstantValue::Ellipsis)
+}
+
+fn compare(op: &CompOp, left: &ConstantValue, right: &ConstantValue) -> Option<bool> {
+    let ordered = |test: fn(Ordering) -> bool| py_cmp(left, right).map(|ordering| ordering.is_some_and(test));
+    match op {
+        CompOp::Equal => py_eq(left, right),
+        CompOp::NotEqual => py_eq(left, right).map(|eq| !eq),
+        CompOp::LessThan => ordered(Ordering::is_lt),
+        CompOp::LessThanEqual => ordered(Ordering::is_le),
+        CompOp::GreaterThan => ordered(Ordering::is_gt),
+        CompOp::GreaterThanEqual => ordered(Ordering::is_ge),
+        CompOp::In => contains(right, left),
+        CompOp::NotIn => contains(right, left).map(|found| !found),
+        // Identity is only known for the singletons.
+        CompOp::Is if is_singleton(left) && is_singleton(right) => Some(left == right),
+        CompOp::IsNot if is_singleton(left) && is_singleton(right) => Some(left != right),
+        CompOp::Is | CompOp::IsNot => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{optimize, OptimizerOptions};
+    use crate::ast::{ConstantValue, Expression, SmallStatement, Statement};
+    use crate::bigint::BigInt;
+    use crate::parser::{parse_module, TokenArena};
+    use crate::tokenizer::Position;
+
+    /// `source` optimized with `options`, written back out as source.
+    fn optimized(source: &str, options: &OptimizerOptions) -> String {
+        let arena = TokenArena::from_source(source).expect("tokenize");
+        let mut module = parse_module(&arena, "__test__").expect("parse");
+        optimize(&mut module, options);
+        module.to_source()
+    }
+
+    fn folded(source: &str) -> String {
+        optimized(source, &OptimizerOptions::default())
+    }
+
+    #[test]
+    fn folds_constants_and_drops_dead_branches() {
+        let source = "x = 2 * 3 + 1\nif False:\n    y = 1\nwhile x:\n    if not __debug__:\n        z = 1\n";
+        let arena = TokenArena::from_source(source).expect("tokenize");
+        let mut module = parse_module(&arena, "__test__").expect("parse");
+        optimize(&mut module, &OptimizerOptions::default());
+        assert_eq!(module.to_source(), "x = 7\nwhile x:\n    pass\n");
+
+        // The folded constant and the `pass` standing in for the dead `if` keep the positions
+        // of what they replaced.
+        let Statement::Simple(assign) = &module.body[0] else { panic!("expected a simple statement") };
+        let SmallStatement::Assign(assign) = &assign.body[0] else { panic!("expected an assignment") };
+        let Expression::Constant(seven) = &assign.value else { panic!("expected a constant") };
+        assert_eq!(seven.value, ConstantValue::Int(BigInt::from_i64(7)));
+        assert_eq!(seven.span.start, Position { line: 1, col: 4 });
+        assert_eq!(seven.span.end, Position { line: 1, col: 13 });
+        assert_eq!(module.body[1].span().start.line, 4);
+    }
+
+    #[test]
+    fn folds_membership_tests_and_strings() {
+        assert_eq!(folded("x in [1, 2]\n"), "x in (1, 2)\n");
+        assert_eq!(folded("x in {1, 1, 2}\n"), "x in frozenset({1, 2})\n");
+        assert_eq!(folded("for x in [a, 1]:\n    pass\n"), "for x in [a, 1]:\n    pass\n");
+        assert_eq!(folded("s = 'a' 'b'\n"), "s = 'ab'\n");
+        assert_eq!(folded("t = (1, 2) + (3,)\n"), "t = 1, 2, 3\n");
+        assert_eq!(folded("b = not x in y\n"), "b = x not in y\n");
+    }
+
+    #[test]
+    fn each_option_can_be_switched_off() {
+        let off = |options: OptimizerOptions| optimized(
+            "x = 1 + 2\nif False:\n    y = 1\nz = x in [1, 2]\ns = 'a' 'b'\n",
+            &options,
+        );
+        assert_eq!(
+            off(OptimizerOptions { fold_constants: false, ..Default::default() }),
+            "x = 1 + 2\nz = x in (1, 2)\ns = 'ab'\n"
+        );
+        assert_eq!(
+            off(OptimizerOptions { remove_dead_branches: false, ..Default::default() }),
+            "x = 3\nif False:\n    y = 1\nz = x in (1, 2)\ns = 'ab'\n"
+        );
+        assert_eq!(
+            off(OptimizerOptions { fold_membership: false, ..Default::default() }),
+            "x = 3\nz = x in [1, 2]\ns = 'ab'\n"
+        );
+        assert_eq!(
+            off(OptimizerOptions { merge_strings: false, ..Default::default() }),
+            "x = 3\nz = x in (1, 2)\ns = 'a' 'b'\n"
+        );
+
+        let debug = "if __debug__:\n    x = 1\n";
+        assert_eq!(folded(debug), "x = 1\n");
+        assert_eq!(optimized(debug, &OptimizerOptions { optimize: 1, ..Default::default() }), "");
+    }
+
+    #[test]
+    fn leaves_what_would_raise_or_grow_too_large() {
+        for source in [
+            "x = 1 / 0\n",
+            "x = 1 // 0\n",
+            "x = 1 % 0\n",
+            "x = 0 ** -1\n",
+            "x = 1 << -1\n",
+            "x = 2 ** 1000\n",
+            "x = 1 << 200\n",
+            "x = 'ab' * 3000\n",
+            "x = b'ab' * 3000\n",
+            "x = (1, 2) * 200\n",
+            "x = 10.0 ** 400\n",
+            "x = (-8) ** 0.5\n",
+        ] {
+            let arena = TokenArena::from_source(source).expect("tokenize");
+            let mut module = parse_module(&arena, "__test__").expect("parse");
+            optimize(&mut module, &OptimizerOptions::default());
+            let Statement::Simple(line) = &module.body[0] else { panic!("expected a simple statement") };
+            let SmallStatement::Assign(assign) = &line.body[0] else { panic!("expected an assignment") };
+            assert!(matches!(assign.value, Expression::BinaryOperation(_)), "{source:?} was folded");
+        }
+        assert_eq!(folded("x = 'ab' * 3\n"), "x = 'ababab'\n");
+        assert_eq!(folded("x = 2 ** 10\n"), "x = 1024\n");
+    }
+}
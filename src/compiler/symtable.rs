@@ -0,0 +1,1156 @@
+//! Scope analysis, modelled on CPython's `symtable` module.
+//!
+//! `symtable()` walks a parsed `Module` once to record how every name is used in every
+//! block, then resolves each name to a `Scope` the way CPython's `analyze_block` does:
+//! class bodies are invisible to the functions nested in them, comprehensions get a block
+//! of their own and `:=` inside a comprehension binds in the enclosing function.
+//!
+//! The type parameters of a generic function, class or type alias are bound in an
+//! annotation scope of their own, a function-like block around the definition, and the
+//! value of a type alias is a block of its own too.  An annotation scope directly inside a
+//! class body can still see the class's names, through its `__classdict__` cell.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::visitor::{self, Visitor};
+use crate::ast::{
+    AssignTargetExpression, Boxed, CompFor, CompoundStatement, DelTargetExpression, Element, Expression,
+    ImportNames, MatchPattern, MatchSequence, Module, NameItem, NameOrAttribute, Parameters, Param,
+    SmallStatement, Span, StarArg, StarrableMatchSequenceElement, Statement, SyntaxError, TypeParam,
+};
+
+const DEF_GLOBAL: u16 = 1;
+const DEF_LOCAL: u16 = 1 << 1;
+const DEF_PARAM: u16 = 1 << 2;
+const DEF_NONLOCAL: u16 = 1 << 3;
+const USE: u16 = 1 << 4;
+const DEF_FREE_CLASS: u16 = 1 << 5;
+const DEF_IMPORT: u16 = 1 << 6;
+const DEF_ANNOT: u16 = 1 << 7;
+const DEF_COMP_ITER: u16 = 1 << 8;
+const DEF_TYPE_PARAM: u16 = 1 << 9;
+
+const DEF_BOUND: u16 = DEF_LOCAL | DEF_PARAM | DEF_IMPORT;
+
+type NameSet<'a> = HashSet<&'a str>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolTableType {
+    Module,
+    Class,
+    Function,
+    /// The annotation scope that binds the type parameters of a generic function, class or
+    /// type alias.
+    TypeParameters,
+    /// The lazily evaluated value of a `type` statement.
+    TypeAlias,
+}
+
+/// Where the compiler has to look a name up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Local,
+    /// Named in a `global` statement in this block.
+    GlobalExplicit,
+    /// Never bound in this block or any enclosing function.
+    GlobalImplicit,
+    /// Bound in an enclosing function.
+    Free,
+    /// Local, but also used by a nested block.
+    Cell,
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol<'a> {
+    name: &'a str,
+    flags: u16,
+    scope: Scope,
+    namespace: bool,
+    /// Where the `global` or `nonlocal` statement naming this symbol was.
+    decl_span: Span,
+}
+
+impl<'a> Symbol<'a> {
+    fn new(name: &'a str) -> Self {
+        Self {
+            name,
+            flags: 0,
+            scope: Scope::Local,
+            namespace: false,
+            decl_span: Span::default(),
+        }
+    }
+
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    pub fn scope(&self) -> Scope {
+        self.scope
+    }
+
+    pub fn is_referenced(&self) -> bool {
+        self.flags & USE != 0
+    }
+
+    pub fn is_imported(&self) -> bool {
+        self.flags & DEF_IMPORT != 0
+    }
+
+    pub fn is_parameter(&self) -> bool {
+        self.flags & DEF_PARAM != 0
+    }
+
+    pub fn is_global(&self) -> bool {
+        matches!(self.scope, Scope::GlobalExplicit | Scope::GlobalImplicit)
+    }
+
+    pub fn is_declared_global(&self) -> bool {
+        self.scope == Scope::GlobalExplicit
+    }
+
+    pub fn is_nonlocal(&self) -> bool {
+        self.flags & DEF_NONLOCAL != 0
+    }
+
+    pub fn is_local(&self) -> bool {
+        matches!(self.scope, Scope::Local | Scope::Cell)
+    }
+
+    pub fn is_annotated(&self) -> bool {
+        self.flags & DEF_ANNOT != 0
+    }
+
+    pub fn is_free(&self) -> bool {
+        self.scope == Scope::Free
+    }
+
+    pub fn is_cell(&self) -> bool {
+        self.scope == Scope::Cell
+    }
+
+    /// A class body binds this name and a method also closes over it, so the method sees the
+    /// enclosing function's binding rather than the class attribute.
+    pub fn is_free_class(&self) -> bool {
+        self.flags & DEF_FREE_CLASS != 0
+    }
+
+    pub fn is_assigned(&self) -> bool {
+        self.flags & DEF_LOCAL != 0
+    }
+
+    /// The name is bound by a `def` or `class` statement in this block.
+    pub fn is_namespace(&self) -> bool {
+        self.namespace
+    }
+}
+
+/// The names of one block (module, class body, function, lambda or comprehension) and the
+/// blocks nested directly inside it.
+#[derive(Debug, Clone)]
+pub struct SymbolTable<'a> {
+    name: &'a str,
+    kind: SymbolTableType,
+    lineno: usize,
+    nested: bool,
+    comprehension: bool,
+    generator: bool,
+    coroutine: bool,
+    needs_class_closure: bool,
+    needs_classdict: bool,
+    can_see_class_scope: bool,
+    symbols: Vec<Symbol<'a>>,
+    index: HashMap<&'a str, usize>,
+    children: Vec<SymbolTable<'a>>,
+}
+
+impl<'a> SymbolTable<'a> {
+    fn new(name: &'a str, kind: SymbolTableType, lineno: usize, nested: bool) -> Self {
+        Self {
+            name,
+            kind,
+            lineno,
+            nested,
+            comprehension: false,
+            generator: false,
+            coroutine: false,
+            needs_class_closure: false,
+            needs_classdict: false,
+            can_see_class_scope: false,
+            symbols: vec![],
+            index: HashMap::new(),
+            children: vec![],
+        }
+    }
+
+    pub fn kind(&self) -> SymbolTableType {
+        self.kind
+    }
+
+    /// `"top"` for the module, the function or class name, `"lambda"`, or one of
+    /// `"listcomp"`, `"setcomp"`, `"dictcomp"` and `"genexpr"`.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    pub fn lineno(&self) -> usize {
+        self.lineno
+    }
+
+    /// Function blocks and annotation scopes can use fast locals.
+    pub fn is_optimized(&self) -> bool {
+        !matches!(self.kind, SymbolTableType::Module | SymbolTableType::Class)
+    }
+
+    pub fn is_nested(&self) -> bool {
+        self.nested
+    }
+
+    pub fn is_comprehension(&self) -> bool {
+        self.comprehension
+    }
+
+    pub fn is_generator(&self) -> bool {
+        self.generator
+    }
+
+    pub fn is_coroutine(&self) -> bool {
+        self.coroutine
+    }
+
+    /// A method of this class uses `__class__` or `super`, so the class body needs a
+    /// `__class__` cell.
+    pub fn needs_class_closure(&self) -> bool {
+        self.needs_class_closure
+    }
+
+    /// An annotation scope in this class body reads class attributes, so the class body
+    /// needs a `__classdict__` cell holding its namespace.
+    pub fn needs_classdict(&self) -> bool {
+        self.needs_classdict
+    }
+
+    /// This annotation scope is directly inside a class body, whose namespace is looked in
+    /// before globals and enclosing functions.
+    pub fn can_see_class_scope(&self) -> bool {
+        self.can_see_class_scope
+    }
+
+    pub fn has_children(&self) -> bool {
+        !self.children.is_empty()
+    }
+
+    pub fn identifiers(&self) -> impl Iterator<Item = &'a str> + '_ {
+        self.symbols.iter().map(|sym| sym.name)
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&Symbol<'a>> {
+        self.index.get(name).map(|idx| &self.symbols[*idx])
+    }
+
+    pub fn symbols(&self) -> &[Symbol<'a>] {
+        &self.symbols
+    }
+
+    /// Nested blocks in the order the compiler reaches them in, which is source order except
+    /// that values come before the targets they are assigned to, decorators before
+    /// defaults and annotations, and a class body before its bases.
+    pub fn children(&self) -> &[SymbolTable<'a>] {
+        &self.children
+    }
+
+    /// The blocks of the functions and classes bound to `name` in this block.
+    pub fn namespaces(&self, name: &str) -> Vec<&SymbolTable<'a>> {
+        self.children.iter().filter(|child| child.name == name).collect()
+    }
+
+    pub fn parameters(&self) -> Vec<&'a str> {
+        self.names_where(|sym| sym.is_parameter())
+    }
+
+    pub fn locals(&self) -> Vec<&'a str> {
+        self.names_where(|sym| sym.is_local())
+    }
+
+    pub fn globals(&self) -> Vec<&'a str> {
+        self.names_where(|sym| sym.is_global())
+    }
+
+    pub fn nonlocals(&self) -> Vec<&'a str> {
+        self.names_where(|sym| sym.is_nonlocal())
+    }
+
+    pub fn frees(&self) -> Vec<&'a str> {
+        self.names_where(|sym| sym.is_free())
+    }
+
+    pub fn cells(&self) -> Vec<&'a str> {
+        self.names_where(|sym| sym.is_cell())
+    }
+
+    /// The functions defined directly in a class body.
+    pub fn methods(&self) -> Vec<&'a str> {
+        self.children
+            .iter()
+            .filter(|child| child.kind == SymbolTableType::Function && !child.comprehension && child.name != "lambda")
+            .map(|child| child.name)
+            .collect()
+    }
+
+    fn names_where(&self, test: impl Fn(&Symbol<'a>) -> bool) -> Vec<&'a str> {
+        self.symbols.iter().filter(|sym| test(sym)).map(|sym| sym.name).collect()
+    }
+
+    fn flags(&self, name: &str) -> u16 {
+        self.lookup(name).map(|sym| sym.flags).unwrap_or(0)
+    }
+
+    fn symbol_mut(&mut self, name: &'a str) -> &mut Symbol<'a> {
+        let idx = match self.index.get(name) {
+            Some(idx) => *idx,
+            None => {
+                self.symbols.push(Symbol::new(name));
+                self.index.insert(name, self.symbols.len() - 1);
+                self.symbols.len() - 1
+            }
+        };
+        &mut self.symbols[idx]
+    }
+
+    /// Resolves the scope of every name in this block and its children.  Returns the names
+    /// that are free in this block, so the caller can turn its own locals into cells.
+    fn analyze(&mut self, mut bound: Option<NameSet<'a>>, mut global: NameSet<'a>) -> Result<NameSet<'a>, SyntaxError> {
+        let is_class = self.kind == SymbolTableType::Class;
+        let mut local = NameSet::new();
+        let mut free = NameSet::new();
+        let mut scopes: HashMap<&'a str, Scope> = HashMap::new();
+        let mut new_global = NameSet::new();
+        let mut new_bound = NameSet::new();
+
+        // A class body has no effect on the names visible to functions nested inside it.
+        if is_class {
+            new_global.extend(global.iter().copied());
+            if let Some(bound) = &bound {
+                new_bound.extend(bound.iter().copied());
+            }
+        }
+
+        for sym in &self.symbols {
+            let scope = analyze_name(sym, &mut bound, &mut local, &mut free, &mut global)?;
+            scopes.insert(sym.name, scope);
+        }
+
+        if is_class {
+            new_bound.insert("__class__");
+            new_bound.insert("__classdict__");
+        } else {
+            if self.is_optimized() {
+                new_bound.extend(local.iter().copied());
+            }
+            if let Some(bound) = &bound {
+                new_bound.extend(bound.iter().copied());
+            }
+            new_global.extend(global.iter().copied());
+        }
+
+        let mut new_free = NameSet::new();
+        for child in self.children.iter_mut() {
+            new_free.extend(child.analyze(Some(new_bound.clone()), new_global.clone())?);
+        }
+
+        match self.kind {
+            SymbolTableType::Function | SymbolTableType::TypeParameters | SymbolTableType::TypeAlias => {
+                for (name, scope) in scopes.iter_mut() {
+                    if *scope == Scope::Local && new_free.remove(name) {
+                        *scope = Scope::Cell;
+                    }
+                }
+            }
+            SymbolTableType::Class => {
+                if new_free.remove("__class__") {
+                    self.needs_class_closure = true;
+                }
+                if new_free.remove("__classdict__") {
+                    self.needs_classdict = true;
+                }
+            }
+            SymbolTableType::Module => {}
+        }
+
+        for sym in self.symbols.iter_mut() {
+            sym.scope = scopes[sym.name];
+        }
+        for name in &new_free {
+            if let Some(idx) = self.index.get(name) {
+                let sym = &mut self.symbols[*idx];
+                if is_class && sym.flags & (DEF_BOUND | DEF_GLOBAL) != 0 {
+                    sym.flags |= DEF_FREE_CLASS;
+                }
+                continue;
+            }
+            if let Some(bound) = &bound {
+                if !bound.contains(name) {
+                    // Global in an enclosing block, nothing to pass through.
+                    continue;
+                }
+            }
+            self.symbol_mut(name).scope = Scope::Free;
+        }
+
+        free.extend(new_free);
+        Ok(free)
+    }
+}
+
+fn analyze_name<'a>(
+    sym: &Symbol<'a>,
+    bound: &mut Option<NameSet<'a>>,
+    local: &mut NameSet<'a>,
+    free: &mut NameSet<'a>,
+    global: &mut NameSet<'a>,
+) -> Result<Scope, SyntaxError> {
+    let name = sym.name;
+    if sym.flags & DEF_GLOBAL != 0 {
+        if sym.flags & DEF_NONLOCAL != 0 {
+            return Err(SyntaxError::new(format!("name '{}' is nonlocal and global", name), sym.decl_span));
+        }
+        global.insert(name);
+        if let Some(bound) = bound {
+            bound.remove(name);
+        }
+        return Ok(Scope::GlobalExplicit);
+    }
+    if sym.flags & DEF_NONLOCAL != 0 {
+        return match bound {
+            None => Err(SyntaxError::new(
+                "nonlocal declaration not allowed at module level",
+                sym.decl_span,
+            )),
+            Some(bound) if !bound.contains(name) => Err(SyntaxError::new(
+                format!("no binding for nonlocal '{}' found", name),
+                sym.decl_span,
+            )),
+            Some(_) => {
+                free.insert(name);
+                Ok(Scope::Free)
+            }
+        };
+    }
+    if sym.flags & DEF_BOUND != 0 {
+        local.insert(name);
+        global.remove(name);
+        return Ok(Scope::Local);
+    }
+    if bound.as_ref().is_some_and(|bound| bound.contains(name)) {
+        free.insert(name);
+        return Ok(Scope::Free);
+    }
+    Ok(Scope::GlobalImplicit)
+}
+
+/// Builds the symbol table for `module`, or returns the first scoping error, such as a
+/// `nonlocal` name with no binding in an enclosing function.
+pub fn symtable<'a>(module: &Module<'a>) -> Result<SymbolTable<'a>, SyntaxError> {
+    let mut builder = SymtableBuilder {
+        stack: vec![SymbolTable::new("top", SymbolTableType::Module, 0, false)],
+        error: None,
+        span: Span::default(),
+        in_comp_iter: false,
+    };
+    builder.visit_module(module);
+    if let Some(error) = builder.error {
+        return Err(error);
+    }
+    let mut top = builder.stack.pop().expect("module block");
+    top.analyze(None, NameSet::new())?;
+    Ok(top)
+}
+
+struct SymtableBuilder<'a> {
+    stack: Vec<SymbolTable<'a>>,
+    error: Option<SyntaxError>,
+    /// The statement currently being visited, used to position errors.
+    span: Span,
+    /// Visiting the iterable of a comprehension's `for` clause.
+    in_comp_iter: bool,
+}
+
+impl<'a> SymtableBuilder<'a> {
+    fn current(&mut self) -> &mut SymbolTable<'a> {
+        self.stack.last_mut().expect("module block")
+    }
+
+    fn set_error(&mut self, message: std::string::String) {
+        if self.error.is_none() {
+            self.error = Some(SyntaxError::new(message, self.span));
+        }
+    }
+
+    fn add_def(&mut self, name: &'a str, flag: u16) {
+        let depth = self.stack.len() - 1;
+        self.add_def_at(depth, name, flag);
+    }
+
+    fn add_def_at(&mut self, depth: usize, name: &'a str, flag: u16) {
+        let span = self.span;
+        let table = &mut self.stack[depth];
+        if flag & DEF_PARAM != 0 && table.flags(name) & DEF_PARAM != 0 {
+            self.set_error(format!("duplicate argument '{}' in function definition", name));
+            return;
+        }
+        let sym = table.symbol_mut(name);
+        // Scoping errors point at the first declaration, as CPython's do.
+        if flag & (DEF_GLOBAL | DEF_NONLOCAL) != 0 && sym.flags & (DEF_GLOBAL | DEF_NONLOCAL) == 0 {
+            sym.decl_span = span;
+        }
+        sym.flags |= flag;
+    }
+
+    fn enter_block(&mut self, name: &'a str, kind: SymbolTableType) {
+        let parent = self.stack.last().expect("module block");
+        let nested = parent.nested || parent.is_optimized();
+        let lineno = self.span.start.line;
+        self.stack.push(SymbolTable::new(name, kind, lineno, nested));
+    }
+
+    /// Enters an annotation scope for the definition of `name`.  One directly inside a class
+    /// body reads the class's names through its `__classdict__` cell.
+    fn enter_annotation_scope(&mut self, name: &'a str, kind: SymbolTableType, in_class: bool) {
+        self.enter_block(name, kind);
+        if in_class {
+            self.current().can_see_class_scope = true;
+            self.add_def("__classdict__", USE);
+        }
+    }
+
+    /// Reports `what`, such as a `yield` expression, used in a block of `kind` if that is an
+    /// annotation scope, where it can't be.
+    fn check_annotation_scope(&mut self, kind: SymbolTableType, what: &str) {
+        let place = match kind {
+            SymbolTableType::TypeParameters => "the definition of a generic",
+            SymbolTableType::TypeAlias => "a type alias",
+            _ => return,
+        };
+        self.set_error(format!("{} cannot be used within {}", what, place));
+    }
+
+    fn leave_block(&mut self) {
+        let table = self.stack.pop().expect("nested block");
+        self.current().children.push(table);
+    }
+
+    fn declare(&mut self, names: &[NameItem<'a>], declaration: &str, flag: u16) {
+        for item in names {
+            let name = item.name.value;
+            let current = self.current().flags(name);
+            let message = if current & DEF_PARAM != 0 {
+                Some(format!("name '{}' is parameter and {}", name, declaration))
+            } else if current & USE != 0 {
+                Some(format!("name '{}' is used prior to {} declaration", name, declaration))
+            } else if current & DEF_ANNOT != 0 {
+                Some(format!("annotated name '{}' can't be {}", name, declaration))
+            } else if current & DEF_LOCAL != 0 {
+                Some(format!("name '{}' is assigned to before {} declaration", name, declaration))
+            } else {
+                None
+            };
+            if let Some(message) = message {
+                self.set_error(message);
+            }
+            self.add_def(name, flag);
+        }
+    }
+
+    fn add_params(&mut self, params: &Parameters<'a>) {
+        for param in all_params(params) {
+            self.add_def(param.name.value, DEF_PARAM);
+        }
+    }
+
+    fn bind_target(&mut self, target: &AssignTargetExpression<'a>, flag: u16) {
+        match target {
+            AssignTargetExpression::Name(n) => self.add_def(n.value, flag),
+            AssignTargetExpression::StarredElement(s) => self.bind_expression(&s.value, flag),
+            AssignTargetExpression::Tuple(t) => self.bind_elements(&t.elements, flag),
+            AssignTargetExpression::List(l) => self.bind_elements(&l.elements, flag),
+            AssignTargetExpression::Attribute(_) | AssignTargetExpression::Subscript(_) => {
+                visitor::walk_assign_target(self, target)
+            }
+        }
+    }
+
+    fn bind_elements(&mut self, elements: &[Element<'a>], flag: u16) {
+        for element in elements {
+            match element {
+                Element::Simple { value } => self.bind_expression(value, flag),
+                Element::Starred(s) => self.bind_expression(&s.value, flag),
+            }
+        }
+    }
+
+    fn bind_expression(&mut self, expr: &Expression<'a>, flag: u16) {
+        match expr {
+            Expression::Name(n) => self.add_def(n.value, flag),
+            Expression::StarredElement(s) => self.bind_expression(&s.value, flag),
+            Expression::Tuple(t) => self.bind_elements(&t.elements, flag),
+            Expression::List(l) => self.bind_elements(&l.elements, flag),
+            other => self.visit_expression(other),
+        }
+    }
+
+    fn bind_import(&mut self, name: &NameOrAttribute<'a>, asname: Option<&AssignTargetExpression<'a>>) {
+        match asname {
+            Some(asname) => self.bind_target(asname, DEF_IMPORT),
+            None => {
+                // `import a.b.c` binds `a`.
+                let mut expr: Expression<'a> = name.clone().into();
+                while let Expression::Attribute(attr) = expr {
+                    expr = Boxed::into_inner(Boxed::into_inner(attr).value);
+                }
+                if let Expression::Name(n) = expr {
+                    self.add_def(n.value, DEF_IMPORT);
+                }
+            }
+        }
+    }
+
+    fn visit_defaults(&mut self, params: &Parameters<'a>) {
+        for param in all_params(params) {
+            if let Some(default) = &param.default {
+                self.visit_expression(default);
+            }
+        }
+    }
+
+    /// The annotations and the return annotation, which the compiler evaluates after the
+    /// defaults.
+    fn visit_annotations(&mut self, params: &Parameters<'a>, returns: Option<&Expression<'a>>) {
+        for param in all_params(params) {
+            if let Some(annotation) = &param.annotation {
+                self.visit_expression(&annotation.annotation);
+            }
+        }
+        if let Some(returns) = returns {
+            self.visit_expression(returns);
+        }
+    }
+
+    fn visit_comprehension(&mut self, name: &'a str, for_in: &CompFor<'a>, elts: &[&Expression<'a>]) {
+        // The outermost iterable is evaluated in the enclosing block and passed in as `.0`.
+        let in_comp_iter = std::mem::replace(&mut self.in_comp_iter, true);
+        self.visit_expression(&for_in.iter);
+        self.in_comp_iter = in_comp_iter;
+
+        self.enter_block(name, SymbolTableType::Function);
+        self.current().comprehension = true;
+        self.current().generator = name == "genexpr";
+        self.add_def(".0", DEF_PARAM);
+        self.bind_target(&for_in.target, DEF_LOCAL | DEF_COMP_ITER);
+        for comp_if in &for_in.ifs {
+            self.visit_expression(&comp_if.test);
+        }
+        if let Some(inner) = &for_in.inner_for_in {
+            self.visit_comp_for(inner);
+        }
+        for elt in elts {
+            self.visit_expression(elt);
+        }
+        self.leave_block();
+    }
+
+    /// `:=` inside a comprehension binds in the nearest enclosing function or module.
+    fn bind_named_expr(&mut self, name: &'a str) {
+        if self.in_comp_iter {
+            self.set_error("assignment expression cannot be used in a comprehension iterable expression".to_string());
+            return;
+        }
+        if !self.current().comprehension {
+            let kind = self.current().kind;
+            self.check_annotation_scope(kind, "named expression");
+            self.add_def(name, DEF_LOCAL);
+            return;
+        }
+        for depth in (0..self.stack.len()).rev() {
+            let table = &self.stack[depth];
+            if table.comprehension {
+                if table.flags(name) & DEF_COMP_ITER != 0 {
+                    self.set_error(format!(
+                        "assignment expression cannot rebind comprehension iteration variable '{}'",
+                        name
+                    ));
+                    return;
+                }
+                continue;
+            }
+            match table.kind {
+                SymbolTableType::Function => {
+                    let flag = if table.flags(name) & DEF_GLOBAL != 0 { DEF_GLOBAL } else { DEF_NONLOCAL };
+                    self.add_def(name, flag);
+                    self.add_def_at(depth, name, DEF_LOCAL);
+                }
+                SymbolTableType::Module => {
+                    self.add_def(name, DEF_GLOBAL);
+                    self.add_def_at(depth, name, DEF_GLOBAL);
+                }
+                SymbolTableType::Class => self.set_error(
+                    "assignment expression within a comprehension cannot be used in a class body".to_string(),
+                ),
+                SymbolTableType::TypeParameters | SymbolTableType::TypeAlias => {
+                    self.check_annotation_scope(table.kind, "named expression")
+                }
+            }
+            return;
+        }
+    }
+}
+
+impl<'r, 'a> Visitor<'r, 'a> for SymtableBuilder<'a> {
+    fn visit_statement(&mut self, node: &'r Statement<'a>) {
+        let span = std::mem::replace(&mut self.span, node.span());
+        visitor::walk_statement(self, node);
+        self.span = span;
+    }
+
+    fn visit_small_statement(&mut self, node: &'r SmallStatement<'a>) {
+        match node {
+            SmallStatement::Global(g) => self.declare(&g.names, "global", DEF_GLOBAL),
+            SmallStatement::Nonlocal(n) => self.declare(&n.names, "nonlocal", DEF_NONLOCAL),
+            SmallStatement::Import(i) => {
+                for alias in &i.names {
+                    self.bind_import(&alias.name, alias.asname.as_ref().map(|a| &a.name));
+                }
+            }
+            SmallStatement::ImportFrom(i) => match &i.names {
+                ImportNames::Star(_) => {
+                    if self.current().kind != SymbolTableType::Module {
+                        self.set_error("import * only allowed at module level".to_string());
+                    }
+                }
+                ImportNames::Aliases(aliases) => {
+                    for alias in aliases {
+                        self.bind_import(&alias.name, alias.asname.as_ref().map(|a| &a.name));
+                    }
+                }
+            },
+            SmallStatement::AnnAssign(a) => {
+                if let AssignTargetExpression::Name(n) = &a.target {
+                    let flags = self.current().flags(n.value);
+                    let is_module = self.stack.len() == 1;
+                    if flags & (DEF_GLOBAL | DEF_NONLOCAL) != 0 && !is_module {
+                        let declaration = if flags & DEF_GLOBAL != 0 { "global" } else { "nonlocal" };
+                        self.set_error(format!("annotated name '{}' can't be {}", n.value, declaration));
+                    }
+                    self.add_def(n.value, DEF_ANNOT | DEF_LOCAL);
+                }
+                if let Some(value) = &a.value {
+                    self.visit_expression(value);
+                }
+                self.visit_assign_target(&a.target);
+                self.visit_expression(&a.annotation.annotation);
+            }
+            SmallStatement::TypeAlias(t) => {
+                let in_class = self.current().kind == SymbolTableType::Class;
+                self.add_def(t.name.value, DEF_LOCAL);
+                if !t.type_params.is_empty() {
+                    self.enter_annotation_scope(t.name.value, SymbolTableType::TypeParameters, in_class);
+                    for param in &t.type_params {
+                        self.visit_type_param(param);
+                    }
+                }
+                self.enter_annotation_scope(t.name.value, SymbolTableType::TypeAlias, in_class);
+                self.visit_expression(&t.value);
+                self.leave_block();
+                if !t.type_params.is_empty() {
+                    self.leave_block();
+                }
+            }
+            // The value is evaluated before the targets.
+            SmallStatement::Assign(a) => {
+                self.visit_expression(&a.value);
+                for target in &a.targets {
+                    self.visit_assign_target(&target.target);
+                }
+            }
+            _ => visitor::walk_small_statement(self, node),
+        }
+    }
+
+    fn visit_compound_statement(&mut self, node: &'r CompoundStatement<'a>) {
+        match node {
+            CompoundStatement::FunctionDef(f) => {
+                let in_class = self.current().kind == SymbolTableType::Class;
+                self.add_def(f.name.value, DEF_LOCAL);
+                self.current().symbol_mut(f.name.value).namespace = true;
+                for decorator in &f.decorators {
+                    self.visit_expression(&decorator.decorator);
+                }
+                self.visit_defaults(&f.params);
+                if !f.type_params.is_empty() {
+                    // The defaults are evaluated outside and passed in.
+                    self.enter_annotation_scope(f.name.value, SymbolTableType::TypeParameters, in_class);
+                    if positional_defaults(&f.params) {
+                        self.add_def(".defaults", DEF_PARAM);
+                    }
+                    if f.params.kwonly_params.iter().any(|p| p.default.is_some()) {
+                        self.add_def(".kwdefaults", DEF_PARAM);
+                    }
+                    for param in &f.type_params {
+                        self.visit_type_param(param);
+                    }
+                }
+                self.visit_annotations(&f.params, f.returns.as_ref().map(|r| &r.annotation));
+                self.enter_block(f.name.value, SymbolTableType::Function);
+                self.current().coroutine = f.asynchronous.is_some();
+                self.add_params(&f.params);
+                self.visit_suite(&f.body);
+                self.leave_block();
+                if !f.type_params.is_empty() {
+                    self.leave_block();
+                }
+            }
+            CompoundStatement::ClassDef(c) => {
+                let in_class = self.current().kind == SymbolTableType::Class;
+                self.add_def(c.name.value, DEF_LOCAL);
+                self.current().symbol_mut(c.name.value).namespace = true;
+                for decorator in &c.decorators {
+                    self.visit_expression(&decorator.decorator);
+                }
+                if !c.type_params.is_empty() {
+                    self.enter_annotation_scope(c.name.value, SymbolTableType::TypeParameters, in_class);
+                    for param in &c.type_params {
+                        self.visit_type_param(param);
+                    }
+                }
+                // The body is compiled before the bases are evaluated.
+                self.enter_block(c.name.value, SymbolTableType::Class);
+                self.visit_suite(&c.body);
+                self.leave_block();
+                for arg in c.bases.iter().chain(c.keywords.iter()) {
+                    self.visit_arg(arg);
+                }
+                if !c.type_params.is_empty() {
+                    self.leave_block();
+                }
+            }
+            CompoundStatement::For(f) => {
+                self.visit_expression(&f.iter);
+                self.visit_assign_target(&f.target);
+                self.visit_suite(&f.body);
+                if let Some(orelse) = &f.orelse {
+                    self.visit_suite(&orelse.body);
+                }
+            }
+            _ => visitor::walk_compound_statement(self, node),
+        }
+    }
+
+    fn visit_expression(&mut self, node: &'r Expression<'a>) {
+        match node {
+            Expression::Name(n) => {
+                self.add_def(n.value, USE);
+                // Zero argument `super()` finds its class through the `__class__` cell.
+                if n.value == "super" && self.current().kind == SymbolTableType::Function {
+                    self.add_def("__class__", USE);
+                }
+            }
+            Expression::NamedExpr(n) => {
+                self.visit_expression(&n.value);
+                if let Expression::Name(target) = &*n.target {
+                    self.bind_named_expr(target.value);
+                }
+            }
+            Expression::Lambda(l) => {
+                self.visit_defaults(&l.params);
+                self.enter_block("lambda", SymbolTableType::Function);
+                self.add_params(&l.params);
+                self.visit_expression(&l.body);
+                self.leave_block();
+            }
+            Expression::Yield(_) | Expression::Await(_) => {
+                let kind = self.current().kind;
+                if matches!(node, Expression::Yield(_)) {
+                    self.check_annotation_scope(kind, "yield expression");
+                    self.current().generator = true;
+                } else {
+                    self.check_annotation_scope(kind, "await expression");
+                }
+                visitor::walk_expression(self, node);
+            }
+            Expression::ListComp(l) => self.visit_comprehension("listcomp", &l.for_in, &[&l.elt]),
+            Expression::SetComp(s) => self.visit_comprehension("setcomp", &s.for_in, &[&s.elt]),
+            Expression::DictComp(d) => self.visit_comprehension("dictcomp", &d.for_in, &[&d.key, &d.value]),
+            Expression::GeneratorExp(g) => self.visit_comprehension("genexpr", &g.for_in, &[&g.elt]),
+            _ => visitor::walk_expression(self, node),
+        }
+    }
+
+    fn visit_assign_target(&mut self, node: &'r AssignTargetExpression<'a>) {
+        self.bind_target(node, DEF_LOCAL);
+    }
+
+    /// Binds a type parameter in the annotation scope being visited.
+    fn visit_type_param(&mut self, node: &'r TypeParam<'a>) {
+        let name = node.name.value;
+        if self.current().flags(name) & DEF_TYPE_PARAM != 0 {
+            self.set_error(format!("duplicate type parameter '{}'", name));
+        }
+        self.add_def(name, DEF_LOCAL | DEF_TYPE_PARAM);
+        visitor::walk_type_param(self, node);
+    }
+
+    fn visit_del_target(&mut self, node: &'r DelTargetExpression<'a>) {
+        match node {
+            DelTargetExpression::Name(n) => self.add_def(n.value, DEF_LOCAL),
+            DelTargetExpression::Tuple(t) => self.bind_elements(&t.elements, DEF_LOCAL),
+            DelTargetExpression::List(l) => self.bind_elements(&l.elements, DEF_LOCAL),
+            _ => visitor::walk_del_target(self, node),
+        }
+    }
+
+    /// Inner `for` clauses of a comprehension, visited inside the comprehension's block.
+    fn visit_comp_for(&mut self, node: &'r CompFor<'a>) {
+        let in_comp_iter = std::mem::replace(&mut self.in_comp_iter, true);
+        self.visit_expression(&node.iter);
+        self.in_comp_iter = in_comp_iter;
+        self.bind_target(&node.target, DEF_LOCAL | DEF_COMP_ITER);
+        for comp_if in &node.ifs {
+            self.visit_expression(&comp_if.test);
+        }
+        if let Some(inner) = &node.inner_for_in {
+            self.visit_comp_for(inner);
+        }
+    }
+
+    fn visit_match_pattern(&mut self, node: &'r MatchPattern<'a>) {
+        match node {
+            MatchPattern::Value(value) => self.visit_expression(&value.value),
+            MatchPattern::Singleton(_) => {}
+            MatchPattern::Sequence(seq) => {
+                let patterns = match seq {
+                    MatchSequence::MatchList(l) => &l.patterns,
+                    MatchSequence::MatchTuple(t) => &t.patterns,
+                };
+                for element in patterns {
+                    match element {
+                        StarrableMatchSequenceElement::Simple(s) => self.visit_match_pattern(&s.value),
+                        StarrableMatchSequenceElement::Starred(s) => {
+                            if let Some(name) = &s.name {
+                                self.add_def(name.value, DEF_LOCAL);
+                            }
+                        }
+                    }
+                }
+            }
+            MatchPattern::Mapping(m) => {
+                for element in &m.elements {
+                    self.visit_expression(&element.key);
+                    self.visit_match_pattern(&element.pattern);
+                }
+                if let Some(rest) = &m.rest {
+                    self.add_def(rest.value, DEF_LOCAL);
+                }
+            }
+            MatchPattern::Class(c) => {
+                self.visit_expression(&c.cls.clone().into());
+                for element in &c.patterns {
+                    self.visit_match_pattern(&element.value);
+                }
+                for kwd in &c.kwds {
+                    self.visit_match_pattern(&kwd.pattern);
+                }
+            }
+            MatchPattern::As(a) => {
+                if let Some(pattern) = &a.pattern {
+                    self.visit_match_pattern(pattern);
+                }
+                if let Some(name) = &a.name {
+                    self.add_def(name.value, DEF_LOCAL);
+                }
+            }
+            MatchPattern::Or(o) => {
+                for element in &o.patterns {
+                    self.visit_match_pattern(&element.pattern);
+                }
+            }
+        }
+    }
+}
+
+fn positional_defaults(params: &Parameters) -> bool {
+    params.posonly_params.iter().chain(params.params.iter()).any(|p| p.default.is_some())
+}
+
+fn all_params<'r, 'a>(params: &'r Parameters<'a>) -> impl Iterator<Item = &'r Param<'a>> {
+    let star_arg = match &params.star_arg {
+        Some(StarArg::Param(param)) => Some(param.as_ref()),
+        _ => None,
+    };
+    params
+        .posonly_params
+        .iter()
+        .chain(params.params.iter())
+        .chain(star_arg)
+        .chain(params.kwonly_params.iter())
+        .chain(params.star_kwarg.iter())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{symtable, Scope, SymbolTable, SymbolTableType};
+    use crate::parser::{parse_module, TokenArena};
+
+    fn analyze<R>(source: &str, check: impl FnOnce(&SymbolTable<'_>) -> R) -> R {
+        let arena = TokenArena::from_source(source).expect("tokenize");
+        let module = parse_module(&arena, "__test__").expect("parse");
+        let top = symtable(&module).expect("symtable");
+        check(&top)
+    }
+
+    fn error(source: &str) -> String {
+        let arena = TokenArena::from_source(source).expect("tokenize");
+        let module = parse_module(&arena, "__test__").expect("parse");
+        match symtable(&module) {
+            Err(err) => err.to_string(),
+            Ok(_) => panic!("{source:?} has no scoping error"),
+        }
+    }
+
+    fn scope(table: &SymbolTable<'_>, name: &str) -> Option<Scope> {
+        table.lookup(name).map(|s| s.scope())
+    }
+
+    #[test]
+    fn resolves_cells_and_free_variables() {
+        let source = "def outer(a):\n    b = a\n    def inner():\n        nonlocal b\n        return a + b + c\n    return inner\n";
+        analyze(source, |top| {
+            assert_eq!(scope(top, "outer"), Some(Scope::Local));
+
+            let outer = &top.children()[0];
+            assert_eq!(outer.parameters(), vec!["a"]);
+            assert_eq!(outer.cells(), vec!["a", "b"]);
+
+            let inner = &outer.children()[0];
+            assert!(inner.is_nested());
+            assert_eq!(inner.frees(), vec!["b", "a"]);
+            assert_eq!(inner.nonlocals(), vec!["b"]);
+            assert_eq!(scope(inner, "c"), Some(Scope::GlobalImplicit));
+        });
+    }
+
+    #[test]
+    fn class_scopes_are_skipped_by_nested_functions() {
+        let source = "def f():\n    x = 1\n    class C:\n        x = 2\n        y = x\n        def m(self):\n            return x\n    return C\n";
+        analyze(source, |top| {
+            let f = &top.children()[0];
+            assert_eq!(f.cells(), vec!["x"]);
+
+            let class = &f.children()[0];
+            assert_eq!(class.name(), "C");
+            assert_eq!(scope(class, "x"), Some(Scope::Local));
+            assert_eq!(class.methods(), vec!["m"]);
+
+            // The method sees the function's `x`, not the class attribute.
+            let method = &class.children()[0];
+            assert_eq!(method.frees(), vec!["x"]);
+        });
+    }
+
+    #[test]
+    fn comprehensions_get_their_own_block() {
+        let source = "def f(xs):\n    return [x * n for x in xs]\n";
+        analyze(source, |top| {
+            let f = &top.children()[0];
+            assert_eq!(scope(f, "x"), None);
+            assert_eq!(scope(f, "xs"), Some(Scope::Local));
+
+            let comp = &f.children()[0];
+            assert!(comp.is_comprehension());
+            assert_eq!(comp.name(), "listcomp");
+            assert_eq!(scope(comp, "x"), Some(Scope::Local));
+            assert_eq!(scope(comp, "n"), Some(Scope::GlobalImplicit));
+            // The outermost iterable is evaluated in the enclosing function.
+            assert_eq!(scope(comp, "xs"), None);
+        });
+    }
+
+    #[test]
+    fn walrus_in_a_comprehension_binds_in_the_enclosing_function() {
+        let source = "def f(xs):\n    if any((last := x) for x in xs):\n        return last\n";
+        analyze(source, |top| {
+            let f = &top.children()[0];
+            assert_eq!(f.cells(), vec!["last"]);
+
+            let genexpr = &f.children()[0];
+            assert!(genexpr.is_generator());
+            assert_eq!(genexpr.frees(), vec!["last"]);
+        });
+        analyze("[y := 1 for _ in range(3)]\n", |top| {
+            assert_eq!(scope(top, "y"), Some(Scope::GlobalExplicit));
+            assert_eq!(scope(&top.children()[0], "y"), Some(Scope::GlobalExplicit));
+        });
+    }
+
+    #[test]
+    fn type_parameters_get_an_annotation_scope() {
+        let source = "class C:\n    def m[T](self, x: T = 0) -> Alias:\n        return T\n    type A[U] = list[U]\n";
+        analyze(source, |top| {
+            let class = &top.children()[0];
+            assert!(class.needs_classdict());
+
+            let params = &class.children()[0];
+            assert_eq!(params.kind(), SymbolTableType::TypeParameters);
+            assert!(params.can_see_class_scope());
+            assert_eq!(params.parameters(), vec![".defaults"]);
+            assert_eq!(params.cells(), vec!["T"]);
+            assert_eq!(scope(params, "Alias"), Some(Scope::GlobalImplicit));
+            assert_eq!(scope(&params.children()[0], "T"), Some(Scope::Free));
+
+            let alias = &class.children()[1].children()[0];
+            assert_eq!(alias.kind(), SymbolTableType::TypeAlias);
+            assert_eq!(alias.frees(), vec!["__classdict__", "U"]);
+        });
+    }
+
+    #[test]
+    fn classifies_global_and_nonlocal_declarations() {
+        let source = "x = 0\ndef f():\n    global x\n    x = 1\n    def g():\n        return x\n    return g\n";
+        analyze(source, |top| {
+            let f = &top.children()[0];
+            assert_eq!(scope(f, "x"), Some(Scope::GlobalExplicit));
+            assert_eq!(f.globals(), vec!["x"]);
+            assert!(f.cells().is_empty());
+            assert_eq!(scope(&f.children()[0], "x"), Some(Scope::GlobalImplicit));
+        });
+    }
+
+    #[test]
+    fn reports_scoping_errors() {
+        assert_eq!(
+            error("def f():\n    x = 1\n    def g():\n        global x\n        nonlocal x\n"),
+            "name 'x' is nonlocal and global (line 4)"
+        );
+        assert_eq!(error("def f():\n    nonlocal y\n"), "no binding for nonlocal 'y' found (line 2)");
+        assert_eq!(error("def f():\n    print(x)\n    global x\n"), "name 'x' is used prior to global declaration (line 3)");
+        assert_eq!(error("def f():\n    x = 1\n    global x\n"), "name 'x' is assigned to before global declaration (line 3)");
+        assert_eq!(error("def f():\n    x: int\n    global x\n"), "annotated name 'x' can't be global (line 3)");
+        assert_eq!(
+            error("def f(xs):\n    return [x for x in (y := xs)]\n"),
+            "assignment expression cannot be used in a comprehension iterable expression (line 2)"
+        );
+        assert_eq!(
+            error("def f(xs):\n    return [(x := 0) for x in xs]\n"),
+            "assignment expression cannot rebind comprehension iteration variable 'x' (line 2)"
+        );
+        assert_eq!(
+            error("class C:\n    ys = [(y := x) for x in range(3)]\n"),
+            "assignment expression within a comprehension cannot be used in a class body (line 2)"
+        );
+        assert_eq!(error("def f():\n    from os import *\n"), "import * only allowed at module level (line 2)");
+        assert_eq!(error("def f[T, T](): pass\n"), "duplicate type parameter 'T' (line 1)");
+        assert_eq!(error("type A = (yield)\n"), "yield expression cannot be used within a type alias (line 1)");
+        assert_eq!(
+            error("class C[T: (x := int)]: pass\n"),
+            "named expression cannot be used within the definition of a generic (line 1)"
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use super::{ImportError, ModuleInfo, ModuleKind};
+
+/// Modules of a source tree and the imports between them.
+#[derive(Debug, Default, Clone)]
+pub struct ImportGraph {
+    pub modules: BTreeMap<String, ModuleInfo>,
+    /// For each module that could be parsed, the modules in the tree it imports.
+    pub edges: BTreeMap<String, BTreeSet<String>>,
+    /// For each module, the top-level names of the imports that are not in the tree.
+    pub external: BTreeMap<String, BTreeSet<String>>,
+    pub errors: Vec<ImportError>,
+}
+
+impl ImportGraph {
+    /// Import cycles: the strongly connected components with more than one module, each
+    /// sorted by name.
+    pub fn cycles(&self) -> Vec<Vec<String>> {
+        let mut tarjan = Tarjan { graph: self, index: 0, indices: BTreeMap::new(), stack: vec![], on_stack: vec![], components: vec![] };
+        for module in self.edges.keys() {
+            if !tarjan.indices.contains_key(module.as_str()) {
+                tarjan.connect(module);
+            }
+        }
+        let mut cycles: Vec<Vec<String>> = tarjan
+            .components
+            .into_iter()
+            .filter(|component| component.len() > 1)
+            .map(|component| {
+                let mut names: Vec<String> = component.into_iter().map(str::to_string).collect();
+                names.sort();
+                names
+            })
+            .collect();
+        cycles.sort();
+        cycles
+    }
+
+    /// The graph in Graphviz DOT format.  Imports that are part of a cycle are drawn red and
+    /// namespace packages dashed.
+    pub fn to_dot(&self) -> String {
+        let cycles = self.cycles();
+        let in_cycle: BTreeMap<&str, usize> = cycles
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, cycle)| cycle.iter().map(move |name| (name.as_str(), idx)))
+            .collect();
+        let mut out = String::from("digraph imports {\n");
+        for (name, info) in &self.modules {
+            let style = if info.kind == ModuleKind::Namespace { " [style=dashed]" } else { "" };
+            let _ = writeln!(out, "    {}{};", dot_id(name), style);
+        }
+        for (from, targets) in &self.edges {
+            for to in targets {
+                let cyclic = in_cycle.get(from.as_str()).is_some_and(|idx| in_cycle.get(to.as_str()) == Some(idx));
+                let style = if cyclic { " [color=red]" } else { "" };
+                let _ = writeln!(out, "    {} -> {}{};", dot_id(from), dot_id(to), style);
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// The graph as JSON: `modules`, `edges`, `external`, `cycles` and `errors`.
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("{\n  \"modules\": [");
+        for (idx, info) in self.modules.values().enumerate() {
+            let kind = match info.kind {
+                ModuleKind::Module => "module",
+                ModuleKind::Package => "package",
+                ModuleKind::Namespace => "namespace",
+            };
+            let _ = write!(
+                out,
+                "{}\n    {{\"name\": {}, \"path\": {}, \"kind\": \"{}\"}}",
+                if idx == 0 { "" } else { "," },
+                json_string(&info.name),
+                json_string(&info.path.display().to_string()),
+                kind
+            );
+        }
+        out.push_str("\n  ],\n  \"edges\": [");
+        let edges = self.edges.iter().flat_map(|(from, targets)| targets.iter().map(move |to| (from, to)));
+        for (idx, (from, to)) in edges.enumerate() {
+            let _ = write!(
+                out,
+                "{}\n    {{\"from\": {}, \"to\": {}}}",
+                if idx == 0 { "" } else { "," },
+                json_string(from),
+                json_string(to)
+            );
+        }
+        out.push_str("\n  ],\n  \"external\": {");
+        for (idx, (module, names)) in self.external.iter().enumerate() {
+            let names: Vec<String> = names.iter().map(|name| json_string(name)).collect();
+            let _ = write!(
+                out,
+                "{}\n    {}: [{}]",
+                if idx == 0 { "" } else { "," },
+                json_string(module),
+                names.join(", ")
+            );
+        }
+        out.push_str("\n  },\n  \"cycles\": [");
+        for (idx, cycle) in self.cycles().iter().enumerate() {
+            let names: Vec<String> = cycle.iter().map(|name| json_string(name)).collect();
+            let _ = write!(out, "{}\n    [{}]", if idx == 0 { "" } else { "," }, names.join(", "));
+        }
+        out.push_str("\n  ],\n  \"errors\": [");
+        for (idx, error) in self.errors.iter().enumerate() {
+            let _ = write!(out, "{}\n    {}", if idx == 0 { "" } else { "," }, json_string(&error.to_string()));
+        }
+        out.push_str("\n  ]\n}\n");
+        out
+    }
+}
+
+/// Tarjan's strongly connected components over the import edges.  The depth-first search
+/// keeps its own work stack rather than recursing, so long import chains can't overflow the
+/// call stack.
+struct Tarjan<'g> {
+    graph: &'g ImportGraph,
+    index: usize,
+    /// Visit order and low-link of each module seen so far.
+    indices: BTreeMap<&'g str, (usize, usize)>,
+    stack: Vec<&'g str>,
+    /// Whether the module with a given visit order is on `stack`.
+    on_stack: Vec<bool>,
+    components: Vec<Vec<&'g str>>,
+}
+
+/// A module being searched from, with the imports still to follow.
+struct Frame<'g> {
+    module: &'g str,
+    targets: std::collections::btree_set::Iter<'g, String>,
+}
+
+impl<'g> Tarjan<'g> {
+    fn connect(&mut self, root: &'g str) {
+        let mut work = vec![self.enter(root)];
+        while let Some(frame) = work.last_mut() {
+            let module = frame.module;
+            if let Some(target) = frame.targets.next() {
+                let target = target.as_str();
+                match self.indices.get(target) {
+                    None => work.push(self.enter(target)),
+                    Some(&(target_index, _)) if self.on_stack[target_index] => self.lower(module, target_index),
+                    Some(_) => {}
+                }
+                continue;
+            }
+
+            work.pop();
+            let (index, low) = self.indices[module];
+            if let Some(parent) = work.last() {
+                self.lower(parent.module, low);
+            }
+            if low == index {
+                let mut component = vec![];
+                while let Some(member) = self.stack.pop() {
+                    self.on_stack[self.indices[member].0] = false;
+                    component.push(member);
+                    if member == module {
+                        break;
+                    }
+                }
+                self.components.push(component);
+            }
+        }
+    }
+
+    fn enter(&mut self, module: &'g str) -> Frame<'g> {
+        let index = self.index;
+        self.index += 1;
+        self.indices.insert(module, (index, index));
+        self.stack.push(module);
+        self.on_stack.push(true);
+        let targets = match self.graph.edges.get(module) {
+            Some(targets) => targets.iter(),
+            None => EMPTY.iter(),
+        };
+        Frame { module, targets }
+    }
+
+    fn lower(&mut self, module: &'g str, low: usize) {
+        let entry = self.indices.get_mut(module).expect("visited");
+        entry.1 = entry.1.min(low);
+    }
+}
+
+static EMPTY: BTreeSet<String> = BTreeSet::new();
+
+fn dot_id(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn json_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ImportGraph;
+
+    #[test]
+    fn finds_cycles_along_long_chains() {
+        let name = |idx: usize| format!("m{:06}", idx);
+        let mut graph = ImportGraph::default();
+        for idx in 0..100_000 {
+            graph.edges.entry(name(idx)).or_default().insert(name(idx + 1));
+        }
+        assert!(graph.cycles().is_empty());
+
+        // Close the last thousand modules into a loop and hang a second, separate one off it.
+        graph.edges.entry(name(100_000)).or_default().insert(name(99_000));
+        graph.edges.entry(name(99_500)).or_default().insert("x".to_string());
+        graph.edges.entry("x".to_string()).or_default().insert("y".to_string());
+        graph.edges.entry("y".to_string()).or_default().insert("x".to_string());
+        let cycles = graph.cycles();
+        assert_eq!(cycles.len(), 2);
+        assert_eq!(cycles[0].len(), 1001);
+        assert_eq!((cycles[0][0].as_str(), cycles[0][1000].as_str()), ("m099000", "m100000"));
+        assert_eq!(cycles[1], ["x", "y"]);
+    }
+}
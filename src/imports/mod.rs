@@ -0,0 +1,338 @@
+//! The module dependency graph of a source tree, for checking layering rules.
+//!
+//! Every `.py` file below the root is parsed and each `import` and `from ... import` in it,
+//! including ones inside functions, is resolved the way the import system would resolve it
+//! with the root on `sys.path`: regular packages have an `__init__.py`, and a directory of
+//! modules without one is a namespace package.  Imports that resolve outside the tree, such as
+//! `os`, are kept apart as external dependencies.
+
+mod graph;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::ast::visitor::{self, Visitor};
+use crate::ast::{ImportNames, SmallStatement, Statement};
+use crate::parser::{parse_module, TokenArena};
+
+pub use graph::ImportGraph;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleKind {
+    Module,
+    /// A directory with an `__init__.py`.
+    Package,
+    /// A directory of modules without an `__init__.py` (PEP 420).
+    Namespace,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleInfo {
+    /// The dotted module name, e.g. `"pkg.sub.mod"`.
+    pub name: String,
+    /// The source file, or the directory of a namespace package.
+    pub path: PathBuf,
+    pub kind: ModuleKind,
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ImportError {
+    #[error("{}: {message}", .path.display())]
+    Unreadable { path: PathBuf, message: String },
+    /// The module could not be tokenized or parsed.
+    #[error("{}: {error}", .path.display())]
+    Parse { path: PathBuf, error: String },
+    #[error("{module}, line {line}: attempted relative import beyond top-level package")]
+    BeyondTopLevel { module: String, line: usize },
+    #[error("{module}, line {line}: no module named {target:?}")]
+    NotFound { module: String, line: usize, target: String },
+}
+
+impl ImportGraph {
+    /// Builds the graph of every module below `root`.  If `root` is itself a package, its
+    /// parent directory is taken as the import root so module names start with the package.
+    pub fn from_directory(root: &Path) -> ImportGraph {
+        let mut graph = ImportGraph::default();
+        let mut prefix = vec![];
+        if root.join("__init__.py").is_file() {
+            if let Some(name) = root.file_name().and_then(|name| name.to_str()) {
+                prefix.push(name.to_string());
+            }
+        }
+        if let Err(err) = discover(root, &prefix, &mut graph.modules) {
+            graph.errors.push(ImportError::Unreadable { path: root.to_path_buf(), message: err.to_string() });
+        }
+
+        let modules: Vec<ModuleInfo> = graph.modules.values().cloned().collect();
+        for info in modules {
+            if info.kind != ModuleKind::Namespace {
+                graph.add_imports_of(&info);
+            }
+        }
+        graph
+    }
+
+    fn add_imports_of(&mut self, info: &ModuleInfo) {
+        let source = match fs::read_to_string(&info.path) {
+            Ok(source) => source,
+            Err(err) => {
+                let error = ImportError::Unreadable { path: info.path.clone(), message: err.to_string() };
+                return self.errors.push(error);
+            }
+        };
+        let parse_error = |error: String| ImportError::Parse { path: info.path.clone(), error };
+        let arena = match TokenArena::from_source(&source) {
+            Ok(arena) => arena,
+            Err(err) => return self.errors.push(parse_error(format!("tokenizer error: {err}"))),
+        };
+        let module = match parse_module(&arena, &info.name) {
+            Ok(module) => module,
+            Err(err) => return self.errors.push(parse_error(err.to_string())),
+        };
+
+        let mut collector = ImportCollector { line: 0, imports: vec![] };
+        collector.visit_module(&module);
+        self.edges.entry(info.name.clone()).or_default();
+        for import in collector.imports {
+            self.resolve(info, import);
+        }
+    }
+
+    fn resolve(&mut self, info: &ModuleInfo, import: RawImport) {
+        let package: Vec<&str> = if import.level == 0 {
+            vec![]
+        } else {
+            let mut parts: Vec<&str> = info.name.split('.').collect();
+            if info.kind == ModuleKind::Module {
+                parts.pop();
+            }
+            if import.level > parts.len() {
+                self.errors.push(ImportError::BeyondTopLevel { module: info.name.clone(), line: import.line });
+                return;
+            }
+            parts.truncate(parts.len() + 1 - import.level);
+            parts
+        };
+        let base = package
+            .into_iter()
+            .chain(import.module.split('.').filter(|part| !part.is_empty()))
+            .collect::<Vec<_>>()
+            .join(".");
+
+        let mut targets = vec![];
+        if import.names.is_empty() {
+            targets.push(self.deepest_module(&base));
+        } else {
+            for name in &import.names {
+                let submodule = format!("{base}.{name}");
+                if self.modules.contains_key(&submodule) {
+                    targets.push(Some(submodule));
+                } else {
+                    targets.push(self.deepest_module(&base));
+                }
+            }
+        }
+
+        for target in targets {
+            match target {
+                // `from . import name` in a package's `__init__` often just reads a name the
+                // package itself defines.
+                Some(target) if target == info.name => {}
+                Some(target) => {
+                    self.edges.entry(info.name.clone()).or_default().insert(target);
+                }
+                None if import.level > 0 => self.errors.push(ImportError::NotFound {
+                    module: info.name.clone(),
+                    line: import.line,
+                    target: base.clone(),
+                }),
+                None => {
+                    let top = base.split('.').next().unwrap_or(&base).to_string();
+                    self.external.entry(info.name.clone()).or_default().insert(top);
+                }
+            }
+        }
+    }
+
+    /// The longest prefix of `name` that is a module in the tree.  `import a.b.c` runs
+    /// `a` and `a.b` too, but the dependency worth recording is on the most specific one.
+    fn deepest_module(&self, name: &str) -> Option<String> {
+        let mut candidate = name;
+        loop {
+            if self.modules.contains_key(candidate) {
+                return Some(candidate.to_string());
+            }
+            candidate = &candidate[..candidate.rfind('.')?];
+        }
+    }
+}
+
+/// Adds the modules in `dir`, whose dotted package name is `package`, to `out`.  Returns
+/// whether any were found, so empty directories do not become namespace packages.
+fn discover(dir: &Path, package: &[String], out: &mut BTreeMap<String, ModuleInfo>) -> std::io::Result<bool> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)?.map(|entry| entry.map(|e| e.path())).collect::<Result<_, _>>()?;
+    entries.sort();
+    let mut found = false;
+    let mut has_init = false;
+    for entry in entries {
+        let Some(file_name) = entry.file_name().and_then(|name| name.to_str()) else { continue };
+        if entry.is_dir() {
+            if is_identifier(file_name) {
+                let sub_package: Vec<String> = package.iter().cloned().chain([file_name.to_string()]).collect();
+                found |= discover(&entry, &sub_package, out)?;
+            }
+            continue;
+        }
+        let Some(stem) = file_name.strip_suffix(".py") else { continue };
+        if stem == "__init__" {
+            has_init = true;
+            if !package.is_empty() {
+                let name = package.join(".");
+                out.insert(name.clone(), ModuleInfo { name, path: entry, kind: ModuleKind::Package });
+            }
+        } else if is_identifier(stem) {
+            let name = package.iter().map(String::as_str).chain([stem]).collect::<Vec<_>>().join(".");
+            out.insert(name.clone(), ModuleInfo { name, path: entry, kind: ModuleKind::Module });
+        } else {
+            continue;
+        }
+        found = true;
+    }
+    if found && !has_init && !package.is_empty() {
+        let name = package.join(".");
+        out.insert(name.clone(), ModuleInfo { name, path: dir.to_path_buf(), kind: ModuleKind::Namespace });
+    }
+    Ok(found)
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c == '_' || c.is_alphabetic()) && chars.all(|c| c == '_' || c.is_alphanumeric())
+}
+
+/// One import statement's target before resolution.
+struct RawImport {
+    /// The leading dots of a relative import.
+    level: usize,
+    module: String,
+    /// The names after `from ... import`; empty for `import x` and `import *`.
+    names: Vec<String>,
+    line: usize,
+}
+
+struct ImportCollector {
+    line: usize,
+    imports: Vec<RawImport>,
+}
+
+impl<'r, 'a> Visitor<'r, 'a> for ImportCollector {
+    fn visit_statement(&mut self, node: &'r Statement<'a>) {
+        self.line = node.span().start.line;
+        visitor::walk_statement(self, node);
+    }
+
+    fn visit_small_statement(&mut self, node: &'r SmallStatement<'a>) {
+        match node {
+            SmallStatement::Import(import) => {
+                for alias in &import.names {
+                    let module = alias.name.dotted_name();
+                    self.imports.push(RawImport { level: 0, module, names: vec![], line: self.line });
+                }
+            }
+            SmallStatement::ImportFrom(import) => {
+                let names = match &import.names {
+                    ImportNames::Star(_) => vec![],
+                    ImportNames::Aliases(aliases) => aliases.iter().map(|alias| alias.name.dotted_name()).collect(),
+                };
+                self.imports.push(RawImport {
+                    level: import.relative.len(),
+                    module: import.module.as_ref().map(|m| m.dotted_name()).unwrap_or_default(),
+                    names,
+                    line: self.line,
+                });
+            }
+            _ => visitor::walk_small_statement(self, node),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::fs;
+
+    use std::path::{Path, PathBuf};
+
+    use super::{ImportError, ImportGraph};
+
+    /// A directory of source files under the system temporary directory, removed on drop so
+    /// a failing test doesn't leave it behind.
+    struct TempTree(PathBuf);
+
+    impl TempTree {
+        fn new(name: &str, files: &[(&str, &str)]) -> TempTree {
+            let tree = TempTree(std::env::temp_dir().join(format!("rython3-{name}-{}", std::process::id())));
+            for (path, source) in files {
+                let path = tree.0.join(path);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, source).unwrap();
+            }
+            tree
+        }
+
+        fn path(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempTree {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn resolves_imports_and_finds_cycles() {
+        let tree = TempTree::new("imports", &[
+            ("pkg/__init__.py", "from .core import run\n"),
+            ("pkg/core.py", "import os.path\nfrom . import util\n\ndef run():\n    from .util import helper\n"),
+            ("pkg/util.py", "from pkg.core import run\n"),
+            ("ns/tool.py", "import pkg.util\nfrom ... import nothing\n"),
+        ]);
+
+        let graph = ImportGraph::from_directory(tree.path());
+
+        let names: Vec<&str> = graph.modules.keys().map(String::as_str).collect();
+        assert_eq!(names, ["ns", "ns.tool", "pkg", "pkg.core", "pkg.util"]);
+        let imports = |module: &str| graph.edges[module].iter().map(String::as_str).collect::<Vec<_>>();
+        assert_eq!(imports("pkg"), ["pkg.core"]);
+        assert_eq!(imports("pkg.core"), ["pkg.util"]);
+        assert_eq!(imports("ns.tool"), ["pkg.util"]);
+        assert_eq!(graph.external["pkg.core"], BTreeSet::from(["os".to_string()]));
+        assert_eq!(graph.errors, [ImportError::BeyondTopLevel { module: "ns.tool".to_string(), line: 2 }]);
+        assert_eq!(graph.cycles(), [vec!["pkg.core".to_string(), "pkg.util".to_string()]]);
+        assert!(graph.to_dot().contains("    \"pkg.core\" -> \"pkg.util\" [color=red];\n"));
+        assert!(graph.to_json().contains("{\"from\": \"ns.tool\", \"to\": \"pkg.util\"}"));
+    }
+
+    #[test]
+    fn tells_syntax_errors_from_io_errors() {
+        let tree = TempTree::new("import-errors", &[("ok.py", "import bad\n"), ("bad.py", "def f(:\n    pass\n")]);
+
+        let graph = ImportGraph::from_directory(tree.path());
+        assert_eq!(graph.edges["ok"].iter().collect::<Vec<_>>(), ["bad"]);
+        match &graph.errors[..] {
+            [ImportError::Parse { path, error }] => {
+                assert!(path.ends_with("bad.py"));
+                assert!(!error.is_empty());
+            }
+            errors => panic!("expected one parse error, got {errors:?}"),
+        }
+
+        let missing = ImportGraph::from_directory(&tree.path().join("missing"));
+        assert!(matches!(&missing.errors[..], [ImportError::Unreadable { .. }]));
+    }
+}
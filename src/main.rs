@@ -8,6 +8,11 @@ mod tokenizer;
 mod ast;
 mod lexer;
 mod parser;
+mod bigint;
+mod compiler;
+mod codemod;
+mod imports;
+mod vm;
 
 use std::{env};
 
@@ -23,7 +28,10 @@ fn main() {
     pretty_env_logger::init();
 
 
-    let mut args = env::args();
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Some(path) = args.first() {
+        std::process::exit(vm::run_file(path, args.clone()));
+    }
     // if args.len() == 2 {
     //     let fname = args.nth(1).expect("Expected a filename");
     //
@@ -0,0 +1,95 @@
+use crate::ast::{NodeArena, Span, SyntaxError};
+use crate::tokenizer::{tokenize, tokenize_recovering, TokError, Token};
+
+use super::grammar::TokVec;
+
+/// Owns every token produced for a single parse, and the memory of the AST nodes parsed
+/// from them.
+///
+/// The grammar and the AST only ever hold `&'arena Token` references into this buffer, so a
+/// parse allocates the tokens once up front instead of once per node, and nothing has to be
+/// reference counted or cloned while the `make_*` adapters shuffle tokens around.  The nodes
+/// are allocated in `nodes` rather than boxed one by one.  Dropping the arena frees all of
+/// them at once.
+#[derive(Debug, Default)]
+pub struct TokenArena<'a> {
+    tokens: Vec<Token<'a>>,
+    nodes: NodeArena,
+    /// What `from_source_recovering` could not tokenize, for `parse_module_recovering` to
+    /// report along with the statements it skips.
+    errors: Vec<SyntaxError>,
+}
+
+impl<'a> TokenArena<'a> {
+    pub fn new(tokens: Vec<Token<'a>>) -> Self {
+        Self { tokens, nodes: NodeArena::new(), errors: vec![] }
+    }
+
+    /// Tokenizes `source` into a new arena.  The tokens borrow their text from `source`.
+    pub fn from_source(source: &'a str) -> Result<Self, TokError> {
+        tokenize(source).map(Self::new)
+    }
+
+    /// Tokenizes `source` into a new arena, turning whatever does not tokenize into error
+    /// tokens instead of failing.  See `tokenize_recovering`.
+    pub fn from_source_recovering(source: &'a str) -> Self {
+        let (tokens, errors) = tokenize_recovering(source);
+        let errors = errors
+            .into_iter()
+            .map(|(err, position)| SyntaxError::new(err.to_string(), Span::new(position, position)))
+            .collect();
+        Self { tokens, nodes: NodeArena::new(), errors }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            tokens: Vec::with_capacity(capacity),
+            nodes: NodeArena::new(),
+            errors: vec![],
+        }
+    }
+
+    /// Appends a token and returns its index in the arena.
+    pub fn push(&mut self, token: Token<'a>) -> usize {
+        self.tokens.push(token);
+        self.tokens.len() - 1
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&Token<'a>> {
+        self.tokens.get(idx)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub fn tokens(&self) -> &[Token<'a>] {
+        &self.tokens
+    }
+
+    /// Where the nodes of a parse of these tokens are allocated.
+    pub fn nodes(&self) -> &NodeArena {
+        &self.nodes
+    }
+
+    /// The tokenizer errors `from_source_recovering` skipped over, in source order.
+    pub fn errors(&self) -> &[SyntaxError] {
+        &self.errors
+    }
+
+    /// Builds the parser input.  The returned `TokVec` and every AST node parsed from it
+    /// borrow from `self`, so the arena has to outlive the resulting `Module`.
+    pub fn tok_vec(&self) -> TokVec<'_> {
+        TokVec::from(self.tokens())
+    }
+}
+
+impl<'a> std::convert::From<Vec<Token<'a>>> for TokenArena<'a> {
+    fn from(tokens: Vec<Token<'a>>) -> Self {
+        Self::new(tokens)
+    }
+}
@@ -0,0 +1,357 @@
+//! State for the grammar's second pass, which turns a failed parse into a CPython-style
+//! syntax error.
+//!
+//! The first pass runs with the `invalid_*` rules switched off.  Only when it fails is the
+//! module parsed again with them on: they match common mistakes such as a missing comma or a
+//! call on the left of `=`, and the first one to match records its message here.  The
+//! invalid rules never succeed, so the second pass accepts exactly what the first one does.
+//!
+//! The recovering parse is a third mode, with the invalid rules off and a statement that
+//! fails skipped instead of failing the module.
+
+use std::cell::{Cell, RefCell};
+
+use crate::ast::{CompOp, Element, ErrorStatement, Expression, Span, SyntaxError};
+use crate::tokenizer::TType::{Indent, Op, NL};
+use crate::tokenizer::Token;
+
+use super::memo::Memos;
+
+/// Where an invalid target was found, which decides the wording of the message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TargetKind {
+    Star,
+    Del,
+    For,
+}
+
+pub struct Diagnostics<'a> {
+    tokens: &'a [Token<'a>],
+    enabled: bool,
+    recovering: bool,
+    /// Brackets open after each token, counting the token itself.
+    levels: Vec<usize>,
+    /// Set while the grammar checks a lookahead whose failure is not itself an error.
+    suppressed: Cell<usize>,
+    error: RefCell<Option<SyntaxError>>,
+    memos: Memos<'a>,
+}
+
+impl<'a> Diagnostics<'a> {
+    /// First pass: the invalid rules never match.
+    pub(crate) fn disabled() -> Self {
+        Self { tokens: &[], enabled: false, recovering: false, levels: vec![], suppressed: Cell::new(0), error: RefCell::new(None), memos: Memos::default() }
+    }
+
+    /// Recovering parse of `tokens`: the invalid rules are off and failed statements skipped.
+    pub(crate) fn for_recovery(tokens: &'a [Token<'a>]) -> Self {
+        Self { tokens, recovering: true, ..Self::disabled() }
+    }
+
+    /// Second pass over `tokens`.
+    pub(crate) fn enabled(tokens: &'a [Token<'a>]) -> Self {
+        let mut level = 0usize;
+        let levels = tokens
+            .iter()
+            .map(|tok| {
+                if tok.r#type == Op {
+                    match tok.text {
+                        "(" | "[" | "{" => level += 1,
+                        ")" | "]" | "}" => level = level.saturating_sub(1),
+                        _ => {}
+                    }
+                }
+                level
+            })
+            .collect();
+        Self { tokens, enabled: true, levels, ..Self::disabled() }
+    }
+
+    pub(crate) fn active(&self) -> bool {
+        self.enabled && self.suppressed.get() == 0
+    }
+
+    pub(crate) fn recovering(&self) -> bool {
+        self.recovering
+    }
+
+    /// The memo tables of the parse this state belongs to.
+    pub(crate) fn memos(&self) -> &Memos<'a> {
+        &self.memos
+    }
+
+    /// The error statement for the tokens `start..end`, which the grammar skipped.  Its span
+    /// leaves out a leading indent and ends where the last skipped line does.
+    pub(crate) fn skipped(&self, start: usize, end: usize) -> ErrorStatement<'a> {
+        let tokens = &self.tokens[start..end];
+        let first = tokens.iter().find(|tok| tok.r#type != Indent).unwrap_or(&tokens[0]);
+        let last = tokens.iter().rev().find(|tok| tok.r#type == NL).map_or(first.end, |nl| nl.start);
+        ErrorStatement { span: Span::new(first.start, last), tokens }
+    }
+
+    pub(crate) fn suppress(&self) {
+        self.suppressed.set(self.suppressed.get() + 1);
+    }
+
+    pub(crate) fn restore(&self) {
+        self.suppressed.set(self.suppressed.get() - 1);
+    }
+
+    /// Whether the token before `pos` is inside brackets.
+    pub(crate) fn in_brackets(&self, pos: usize) -> bool {
+        pos > 0 && self.levels.get(pos - 1).is_some_and(|&level| level > 0)
+    }
+
+    /// The span from the start of the token at `start` to the end of the one before `end`.
+    pub(crate) fn span(&self, start: usize, end: usize) -> Span {
+        let Some(first) = self.tokens.get(start).or(self.tokens.last()) else { return Span::default() };
+        let last = self.tokens.get(end.max(start + 1) - 1).unwrap_or(first);
+        Span::new(first.start, last.end)
+    }
+
+    /// Records `message` unless an earlier invalid rule already did, and returns the error
+    /// for the grammar to fail with.
+    pub(crate) fn raise(&self, message: impl Into<String>, span: Span) -> &'static str {
+        self.error.borrow_mut().get_or_insert_with(|| SyntaxError::new(message, span));
+        "invalid syntax"
+    }
+
+    /// Records the error for an assignment, `del` or `for` whose `target` cannot be assigned
+    /// to, pointing at the offending part of it.
+    pub(crate) fn raise_invalid_target(&self, kind: TargetKind, target: &Expression, span: Span) -> &'static str {
+        match invalid_target(target, kind) {
+            Some(name) => {
+                let verb = if kind == TargetKind::Del { "delete" } else { "assign to" };
+                self.raise(format!("cannot {verb} {name}"), span)
+            }
+            None => self.raise("invalid syntax", span),
+        }
+    }
+
+    pub(crate) fn take(self) -> Option<SyntaxError> {
+        self.error.into_inner()
+    }
+}
+
+/// How CPython names an expression in error messages.
+pub(crate) fn expr_name(expr: &Expression) -> &'static str {
+    match expr {
+        Expression::Name(name) => match name.value {
+            "True" => "True",
+            "False" => "False",
+            "None" => "None",
+            _ => "name",
+        },
+        Expression::Attribute(_) => "attribute",
+        Expression::Subscript(_) => "subscript",
+        Expression::StarredElement(_) => "starred",
+        Expression::List(_) => "list",
+        Expression::Tuple(_) => "tuple",
+        Expression::Lambda(_) => "lambda",
+        Expression::Call(_) => "function call",
+        Expression::BooleanOperation(_) | Expression::BinaryOperation(_) | Expression::UnaryOperation(_) => "expression",
+        Expression::GeneratorExp(_) => "generator expression",
+        Expression::Yield(_) => "yield expression",
+        Expression::Await(_) => "await expression",
+        Expression::ListComp(_) => "list comprehension",
+        Expression::SetComp(_) => "set comprehension",
+        Expression::DictComp(_) => "dict comprehension",
+        Expression::Dict(_) => "dict literal",
+        Expression::Set(_) => "set display",
+        Expression::FormattedString(_) => "f-string expression",
+        Expression::Ellipsis(_) => "ellipsis",
+        Expression::Comparison(_) => "comparison",
+        Expression::IfExp(_) => "conditional expression",
+        Expression::NamedExpr(_) => "named expression",
+        _ => "literal",
+    }
+}
+
+/// The name of the first part of `expr` that cannot be a target of `kind`, if any.
+fn invalid_target(expr: &Expression, kind: TargetKind) -> Option<&'static str> {
+    let first_invalid = |elements: &[Element]| {
+        elements.iter().find_map(|element| match element {
+            Element::Simple { value } => invalid_target(value, kind),
+            Element::Starred(_) if kind == TargetKind::Del => Some("starred"),
+            Element::Starred(starred) => invalid_target(&starred.value, kind),
+        })
+    };
+    match expr {
+        Expression::Tuple(tuple) => first_invalid(&tuple.elements),
+        Expression::List(list) => first_invalid(&list.elements),
+        Expression::StarredElement(_) if kind == TargetKind::Del => Some("starred"),
+        Expression::StarredElement(starred) => invalid_target(&starred.value, kind),
+        // `for a in b` without its colon parses as a comparison; the target is on the left.
+        Expression::Comparison(comparison) if kind == TargetKind::For => match comparison.comparisons.first() {
+            Some(target) if target.operator == CompOp::In => invalid_target(&comparison.left, kind),
+            _ => None,
+        },
+        Expression::Name(name) if matches!(name.value, "True" | "False" | "None") => Some(expr_name(expr)),
+        Expression::Name(_) | Expression::Attribute(_) | Expression::Subscript(_) => None,
+        _ => Some(expr_name(expr)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{CompoundStatement, ParserError, Statement, Suite};
+    use crate::parser::{parse_module, parse_module_recovering, unclosed_bracket_error, TokenArena};
+
+    fn error(source: &str) -> String {
+        let arena = TokenArena::from_source(source).expect("tokenize");
+        let result = parse_module(&arena, "<test>");
+        match result {
+            Err(ParserError::SyntaxError(err)) => err.to_string(),
+            Err(other) => panic!("expected a syntax error for {source:?}, got {other}"),
+            Ok(_) => panic!("{source:?} parsed"),
+        }
+    }
+
+    #[test]
+    fn reports_cpython_messages() {
+        assert_eq!(error("x = [a b]\n"), "invalid syntax. Perhaps you forgot a comma? (line 1)");
+        assert_eq!(error("print 'hi'\n"), "Missing parentheses in call to 'print'. Did you mean print(...)? (line 1)");
+        assert_eq!(error("f(a=1)\nif x = 1:\n    pass\n"), "invalid syntax. Maybe you meant '==' or ':=' instead of '='? (line 2)");
+        assert_eq!(error("f() = 1\n"), "cannot assign to function call here. Maybe you meant '==' instead of '='? (line 1)");
+        assert_eq!(error("(a, f()) = 1, 2\n"), "cannot assign to function call (line 1)");
+        assert_eq!(error("x + 1 += 1\n"), "'expression' is an illegal expression for augmented assignment (line 1)");
+        assert_eq!(error("del f()\n"), "cannot delete function call (line 1)");
+        assert_eq!(error("for 1 in x:\n    pass\n"), "cannot assign to literal (line 1)");
+        assert_eq!(error("f(a=1, b)\n"), "positional argument follows keyword argument (line 1)");
+        assert_eq!(error("f(x.y=1)\n"), "expression cannot contain assignment, perhaps you meant \"==\"? (line 1)");
+        assert_eq!(error("while x\n    pass\n"), "expected ':' (line 1)");
+        assert_eq!(error("\ndef f():\nreturn 1\n"), "expected an indented block after function definition on line 2 (line 3)");
+        assert_eq!(error("if x:\n    a\n        b\n"), "unexpected indent (line 3)");
+    }
+
+    /// The message and the 1-based column CPython reports for `source`.
+    fn error_at(source: &str) -> (String, usize) {
+        let arena = TokenArena::from_source(source).expect("tokenize");
+        let result = parse_module(&arena, "<test>");
+        match result {
+            Err(ParserError::SyntaxError(err)) => (err.message, err.span.start.col + 1),
+            Err(other) => panic!("expected a syntax error for {source:?}, got {other}"),
+            Ok(_) => panic!("{source:?} parsed"),
+        }
+    }
+
+    #[test]
+    fn reports_errors_in_calls_and_displays() {
+        let generator = "Generator expression must be parenthesized".to_string();
+        assert_eq!(error_at("f(x for x in y, 1)\n"), (generator.clone(), 3));
+        assert_eq!(error_at("f(1, x for x in y)\n"), (generator.clone(), 6));
+        assert_eq!(error_at("f(a, b for b in c)\n"), (generator, 6));
+        assert_eq!(error_at("{1: 2, 3}\n"), ("':' expected after dictionary key".to_string(), 8));
+        assert_eq!(error_at("{**x, 1 2}\n"), ("':' expected after dictionary key".to_string(), 7));
+        assert_eq!(error_at("{1: 2, 3:}\n"), ("expression expected after dictionary key and ':'".to_string(), 9));
+        assert_eq!(error_at("{1: *a}\n"), ("cannot use a starred expression in a dictionary value".to_string(), 5));
+        // Without a ':' it is a set, as in CPython.
+        assert_eq!(error_at("{1 2}\n"), ("invalid syntax. Perhaps you forgot a comma?".to_string(), 2));
+        assert_eq!(error_at("x = 5 +\n"), ("invalid syntax".to_string(), 8));
+        assert_eq!(error_at("try:\n    pass\nx = 1\n"), ("expected 'except' or 'finally' block".to_string(), 1));
+        assert_eq!(error("try:\n    pass\nx = 1\n"), "expected 'except' or 'finally' block (line 3)");
+    }
+
+    #[test]
+    fn reports_errors_in_parameters() {
+        let non_default = "non-default argument follows default argument".to_string();
+        assert_eq!(error_at("def f(x=1, y): pass\n"), (non_default.clone(), 12));
+        assert_eq!(error_at("def f(x=1, /, y): pass\n"), (non_default.clone(), 15));
+        assert_eq!(error_at("lambda x=1, y: 0\n"), (non_default, 13));
+        assert_eq!(error_at("def f(/, a): pass\n"), ("at least one argument must precede /".to_string(), 7));
+        assert_eq!(error_at("def f(a, /, b, /): pass\n"), ("/ may appear only once".to_string(), 16));
+        assert_eq!(error_at("def f(a, *, b, /): pass\n"), ("/ must be ahead of *".to_string(), 16));
+        assert_eq!(error_at("def f(*): pass\n"), ("named arguments must follow bare *".to_string(), 7));
+        assert_eq!(error_at("def f(*a=1): pass\n"), ("var-positional argument cannot have default value".to_string(), 9));
+        assert_eq!(error_at("def f(*a, *b): pass\n"), ("* argument may appear only once".to_string(), 11));
+        assert_eq!(error_at("def f(**k=1): pass\n"), ("var-keyword argument cannot have default value".to_string(), 10));
+        assert_eq!(error_at("def f(**k, a): pass\n"), ("arguments cannot follow var-keyword argument".to_string(), 12));
+        assert_eq!(error_at("lambda *, **k: 0\n"), ("named arguments must follow bare *".to_string(), 11));
+    }
+
+    #[test]
+    fn reports_errors_before_an_unclosed_bracket() {
+        let err = unclosed_bracket_error("def f(:\n").expect("an error before the end");
+        assert_eq!((err.message.as_str(), err.span.start.col), ("invalid syntax", 6));
+        let err = unclosed_bracket_error("(1 2 3\n").expect("an error before the end");
+        assert_eq!(err.message, "invalid syntax. Perhaps you forgot a comma?");
+        // The parser gets to the end, where the bracket is the error.
+        assert_eq!(unclosed_bracket_error("f(1,\n"), None);
+        assert_eq!(unclosed_bracket_error("x = (1 2\n"), None);
+    }
+
+    #[test]
+    fn reports_malformed_number_literals() {
+        assert_eq!(
+            error("x = 0777\n"),
+            "leading zeros in decimal integer literals are not permitted; use an 0o prefix for octal integers (line 1)"
+        );
+        assert_eq!(error("x = 0x\n"), "invalid hexadecimal literal (line 1)");
+        assert_eq!(error("x = 1_\n"), "invalid decimal literal (line 1)");
+        assert_eq!(error("x = 0o8\n"), "invalid digit '8' in octal literal (line 1)");
+        assert_eq!(error("x = 0b102\n"), "invalid digit '2' in binary literal (line 1)");
+        assert_eq!(error("y = 1\nx = -1e\n"), "invalid decimal literal (line 2)");
+    }
+
+    #[test]
+    fn recovers_after_bad_statements() {
+        let source = "import os\ndef f(a b):\n    return 1\nclass A:\n    x = [1 2]\n    def g(self):\n        pass\ny =\nz = 3\n";
+        let arena = TokenArena::from_source(source).expect("tokenize");
+        let (module, errors) = parse_module_recovering(&arena, "<test>").expect("recovering parse");
+
+        let kinds: Vec<&str> = module
+            .body
+            .iter()
+            .map(|stmt| match stmt {
+                Statement::Simple(_) => "simple",
+                Statement::Compound(_) => "compound",
+                Statement::Error(_) => "error",
+            })
+            .collect();
+        assert_eq!(kinds, ["simple", "error", "compound", "error", "simple"]);
+        assert_eq!(module.body[1].span().start.line, 2);
+        let Statement::Compound(CompoundStatement::ClassDef(class)) = &module.body[2] else { panic!("expected a class") };
+        let Suite::IndentedBlock(block) = &class.body else { panic!("expected a block") };
+        assert!(matches!(block.body[..], [Statement::Error(_), Statement::Compound(CompoundStatement::FunctionDef(_))]));
+
+        let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            ["invalid syntax (line 2)", "invalid syntax. Perhaps you forgot a comma? (line 5)", "invalid syntax (line 8)"]
+        );
+    }
+
+    #[test]
+    fn recovers_after_tokenizer_errors() {
+        let source = "import os\nx = foo(1,\n        2\ny = $\ndef f():\n    s = 'abc\n    return [\n        3]\n  z = 1\nw = {\n";
+        let arena = TokenArena::from_source_recovering(source);
+        let (module, errors) = parse_module_recovering(&arena, "<test>").expect("recovering parse");
+
+        let kinds: Vec<&str> = module
+            .body
+            .iter()
+            .map(|stmt| match stmt {
+                Statement::Simple(_) => "simple",
+                Statement::Compound(_) => "compound",
+                Statement::Error(_) => "error",
+            })
+            .collect();
+        assert_eq!(kinds, ["simple", "error", "error", "compound", "error", "error"]);
+        assert_eq!((module.body[1].span().start.line, module.body[1].span().end.line), (2, 3));
+        let Statement::Compound(CompoundStatement::FunctionDef(f)) = &module.body[3] else { panic!("expected a def") };
+        let Suite::IndentedBlock(block) = &f.body else { panic!("expected a block") };
+        assert!(matches!(block.body[..], [Statement::Error(_), Statement::Simple(_)]));
+
+        let errors: Vec<(String, usize)> = errors.iter().map(|err| (err.to_string(), err.span.start.col)).collect();
+        assert_eq!(
+            errors,
+            [
+                ("'(' was never closed (line 2)".to_string(), 7),
+                ("'$' is not a valid character in this position (line 4)".to_string(), 4),
+                ("unterminated string literal (line 6)".to_string(), 8),
+                ("unindent does not match any outer indentation level (line 9)".to_string(), 2),
+                ("'{' was never closed (line 10)".to_string(), 4),
+            ]
+        );
+    }
+}
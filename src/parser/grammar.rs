@@ -1,12 +1,15 @@
+// The functions peg generates for rules take the grammar's arguments on top of their own.
+#![allow(clippy::too_many_arguments)]
 // use std::fmt::{Debug, Formatter, self};
-use std::rc::Rc;
 
 
-use crate::tokenizer::{Token};
+use crate::tokenizer::{Position, Token};
 use crate::tokenizer::TType::{
     self, Async, Number, Name as NameType, Name as NameTok, Op as Operator, NL, EndMarker, Newline, Indent, Dedent, Await as AWAIT, String as STRING, FStringStart,
 FStringEnd, FStringString };
 use crate::ast::*;
+use super::diagnostics::{expr_name, Diagnostics, TargetKind};
+use super::memo::Memo;
 
 use peg::str::LineCol;
 use peg::{parser, Parse, ParseElem, RuleResult};
@@ -14,13 +17,103 @@ use peg::{parser, Parse, ParseElem, RuleResult};
 pub type Result<'a, T> = std::result::Result<T, ParserError<'a>>;
 type GrammarResult<T> = std::result::Result<T, &'static str>;
 
+/// The parser input.  Borrows the tokens of a token buffer (usually a `TokenArena`) so that
+/// the grammar and the AST can pass tokens around without allocating or ref counting.
 #[derive(Debug)]
-pub struct TokVec<'a> (Vec<Rc<Token<'a>>>);
+pub struct TokVec<'a> {
+    tokens: Tokens<'a>,
+    /// For each opening bracket, the position of the bracket that closes it, if any.
+    closers: Vec<Option<usize>>,
+}
 
+/// The tokens a `TokVec` reads: a token buffer's own slice, or references collected from
+/// wherever the tokens are, as for the statements `parse_module_recovering` parses again.
+#[derive(Debug)]
+enum Tokens<'a> {
+    Borrowed(&'a [Token<'a>]),
+    Collected(Vec<TokenRef<'a>>),
+}
 
-impl <'a> std::convert::From<Vec<Token<'a>>> for TokVec<'a>{
-    fn from(vec: Vec<Token<'a>>) -> Self {
-        TokVec(vec.into_iter().map(Rc::new).collect())
+impl<'a> Tokens<'a> {
+    fn len(&self) -> usize {
+        match self {
+            Tokens::Borrowed(tokens) => tokens.len(),
+            Tokens::Collected(tokens) => tokens.len(),
+        }
+    }
+
+    fn get(&self, idx: usize) -> Option<TokenRef<'a>> {
+        match self {
+            Tokens::Borrowed(tokens) => tokens.get(idx),
+            Tokens::Collected(tokens) => tokens.get(idx).copied(),
+        }
+    }
+}
+
+impl<'a> TokVec<'a> {
+    fn new(tokens: Tokens<'a>) -> Self {
+        let mut closers = vec![None; tokens.len()];
+        let mut open = vec![];
+        for idx in 0..tokens.len() {
+            let tok = tokens.get(idx).expect("token in range");
+            if tok.r#type != Operator {
+                continue;
+            }
+            match tok.text {
+                "(" | "[" | "{" => open.push(idx),
+                ")" | "]" | "}" => {
+                    if let Some(opener) = open.pop() {
+                        closers[opener] = Some(idx);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Self { tokens, closers }
+    }
+
+    /// Skips from an opening bracket at `pos` to just past the bracket that closes it, without
+    /// parsing what is in between.  A bracketed atom that parses ends there, so the grammar
+    /// can look at what follows one before paying for the parse.
+    fn skip_brackets(&self, pos: usize) -> RuleResult<()> {
+        match self.closers.get(pos) {
+            Some(&Some(closer)) => RuleResult::Matched(closer + 1, ()),
+            _ => RuleResult::Failed,
+        }
+    }
+
+    /// Matches, without moving, unless the brackets opening at `pos` are closed and hold
+    /// none of the operators `ops` outside the brackets nested in them.  Tells a tuple from a
+    /// parenthesized expression, or a dict from a set, before what is inside is parsed.
+    fn holds(&self, pos: usize, ops: &[&str]) -> RuleResult<()> {
+        let Some(&Some(closer)) = self.closers.get(pos) else { return RuleResult::Matched(pos, ()) };
+        let mut idx = pos + 1;
+        while idx < closer {
+            let tok = self.tokens.get(idx).expect("token before its closer");
+            if tok.r#type == Operator && ops.contains(&tok.text) {
+                return RuleResult::Matched(pos, ());
+            }
+            idx = self.closers[idx].map_or(idx + 1, |inner| inner + 1);
+        }
+        RuleResult::Failed
+    }
+}
+
+impl <'a> std::convert::From<&'a [Token<'a>]> for TokVec<'a>{
+    fn from(tokens: &'a [Token<'a>]) -> Self {
+        TokVec::new(Tokens::Borrowed(tokens))
+    }
+}
+
+impl <'a> std::convert::From<&'a Vec<Token<'a>>> for TokVec<'a>{
+    fn from(tokens: &'a Vec<Token<'a>>) -> Self {
+        TokVec::new(Tokens::Borrowed(tokens))
+    }
+}
+
+impl <'a> std::iter::FromIterator<TokenRef<'a>> for TokVec<'a> {
+    fn from_iter<I: IntoIterator<Item = TokenRef<'a>>>(tokens: I) -> Self {
+        TokVec::new(Tokens::Collected(tokens.into_iter().collect()))
     }
 }
 
@@ -28,6 +121,9 @@ impl <'a> std::convert::From<Vec<Token<'a>>> for TokVec<'a>{
 pub struct ParseLoc {
     pub start_pos: LineCol,
     pub end_pos: LineCol,
+    /// The index of the token in the parser input, which is the number of tokens when the
+    /// parser ran off the end.
+    pub index: usize,
 }
 
 impl std::fmt::Display for ParseLoc {
@@ -46,11 +142,11 @@ impl <'a>Parse for TokVec<'a> {
     }
 
     fn is_eof<'input>(&'input self, p: usize) -> bool {
-        p >= self.0.len()
+        p >= self.tokens.len()
     }
 
     fn position_repr<'input>(&'input self, p: usize) -> Self::PositionRepr {
-        let tok = self.0.get(p).unwrap_or_else(||self.0.last().unwrap());
+        let tok = self.tokens.get(p).unwrap_or_else(|| self.tokens.get(self.tokens.len() - 1).unwrap());
         ParseLoc {
             start_pos: LineCol {
                 line: tok.start.line,
@@ -62,18 +158,19 @@ impl <'a>Parse for TokVec<'a> {
                 column: tok.end.col,
                 offset: tok.end.col,
             },
+            index: p,
         }
     }
 }
 
-type TokenRef<'a> = Rc<Token<'a>>;
+pub(crate) type TokenRef<'a> = &'a Token<'a>;
 
 impl <'a> ParseElem for TokVec<'a> {
     type Element = TokenRef<'a>;
 
     fn parse_elem(&self, pos: usize) -> RuleResult<Self::Element> {
-        match self.0.get(pos) {
-            Some(tok) => RuleResult::Matched(pos+1, tok.clone()),
+        match self.tokens.get(pos) {
+            Some(tok) => RuleResult::Matched(pos+1, tok),
             None => RuleResult::Failed,
         }
     }
@@ -93,7 +190,7 @@ pub struct ValueNode {
 // }
 
 parser! {
-    pub grammar python<'a>() for TokVec<'a> {
+    pub grammar python<'a>(diagnostics: &Diagnostics<'a>, arena: &'a NodeArena) for TokVec<'a> {
 
 
         //Starting rules
@@ -107,6 +204,38 @@ parser! {
         pub rule statement_input() -> Statement<'a>
         = traced(<s:statement() tok(EndMarker, "EOF") { s }>)
 
+        // The other entry points of `compile()`: `eval`, `single` and `func_type` modes.
+
+        pub rule eval() -> Expression<'a>
+            = e:star_expressions() tok(NL, "NEWLINE")* tok(EndMarker, "EOF") { e }
+
+        /// One statement typed at the interactive prompt, or none for a blank line.
+        pub rule interactive() -> Vec<Statement<'a>>
+            = s:statement_newline() tok(EndMarker, "EOF") { s }
+
+        rule statement_newline() -> Vec<Statement<'a>>
+            = c:compound_stmt() { vec![Statement::Compound(c)] }
+            / s:simple_stmts() { vec![Statement::Simple(make_simple_statement_lines(s))] }
+            / tok(NL, "NEWLINE") { vec![] }
+            / &tok(EndMarker, "EOF") { vec![] }
+
+        pub rule func_type() -> FunctionType<'a>
+            = lit("(") a:type_expressions()? lit(")") lit("->") r:expression() tok(NL, "NEWLINE")* tok(EndMarker, "EOF") {
+                FunctionType { argtypes: a.unwrap_or_default(), returns: r }
+            }
+
+        // CPython drops the `*` and `**` of variadic argument types.
+        rule type_expressions() -> Vec<Expression<'a>>
+            = a:separated(<expression()>, <comma()>) b:(comma() lit("*") e:expression() { e })?
+                c:(comma() lit("**") e:expression() { e })? {
+                    let (first, rest) = a;
+                    std::iter::once(first).chain(rest.into_iter().map(|(_, e)| e)).chain(b).chain(c).collect()
+            }
+            / lit("*") b:expression() c:(comma() lit("**") e:expression() { e })? {
+                std::iter::once(b).chain(c).collect()
+            }
+            / lit("**") c:expression() { vec![c] }
+
         pub rule _file(name: &str) -> Module<'a>
         = s:statements()? eof:tok(EndMarker, "EOF") {
                 make_module(name, s.unwrap_or_default(), eof)
@@ -126,6 +255,7 @@ parser! {
         / s:simple_stmts() {
             Statement::Simple(make_simple_statement_lines(s))
         }
+        / error_statement()
 
         rule simple_stmts() -> SimpleStatementParts<'a>
         = first_tok:&_ stmts:separated_trailer(<simple_stmt()>, <lit(";")>) nl:tok(NL, "NEWLINE") {
@@ -140,7 +270,8 @@ parser! {
 
         #[cache]
         rule simple_stmt() -> SmallStatement<'a>
-        = assignment()
+        = &lit("type") t:type_alias() { SmallStatement::TypeAlias(t) }
+        / assignment()
         / e:star_expressions() { SmallStatement::Expr(Expr { value: e,  }) }
             / &lit("return") s:return_stmt() { SmallStatement::Return(s) }
             // this is expanded from the original grammar's import_stmt rule
@@ -151,8 +282,8 @@ parser! {
             / &lit("del") s:del_stmt() { SmallStatement::Del(s) }
             / &lit("yield") s:yield_stmt() { SmallStatement::Expr(Expr { value: s, }) }
             / &lit("assert") s:assert_stmt() {SmallStatement::Assert(s)}
-            / lit("break") { SmallStatement::Break }
-            / lit("continue") { SmallStatement::Continue }
+            / kw:lit("break") { SmallStatement::Break(Break { span: Span::from_token(kw) }) }
+            / kw:lit("continue") { SmallStatement::Continue(Continue { span: Span::from_token(kw) }) }
             / &lit("global") s:global_stmt() {SmallStatement::Global(s)}
             / &lit("nonlocal") s:nonlocal_stmt() {SmallStatement::Nonlocal(s)}
 
@@ -184,7 +315,7 @@ parser! {
             = a:name() col:lit(":") ann:expression()
                 rhs:(eq:lit("=") d:annotated_rhs() {(eq, d)})? {
                     SmallStatement::AnnAssign(make_ann_assignment(
-                        AssignTargetExpression::Name(Box::new(a)), col, ann, rhs))
+                        AssignTargetExpression::Name(arena.alloc(a)), col, ann, rhs))
             }
             / a:single_subscript_attribute_target() col:lit(":") ann:expression()
                 rhs:(eq:lit("=") d:annotated_rhs() {(eq, d)})? {
@@ -196,6 +327,33 @@ parser! {
             / t:single_target() op:augassign() rhs:(yield_expr() / star_expressions()) {
                 SmallStatement::AugAssign(make_aug_assign(t, op, rhs))
             }
+            / invalid_assignment()
+
+        // Type aliases and type parameters (PEP 695).  `type` is a soft keyword, so `type = 1`
+        // and `type(x)` fall through to the other statements.
+
+        rule type_alias() -> TypeAlias<'a>
+            = lit("type") n:name() t:type_params()? lit("=") e:expression() {
+                TypeAlias { name: n, type_params: t.unwrap_or_default(), value: e }
+            }
+
+        rule type_params() -> Vec<TypeParam<'a>>
+            = lit("[") t:separated_trailer(<type_param()>, <comma()>) lit("]") { make_type_params(t) }
+
+        rule type_param() -> TypeParam<'a>
+            = n:name() b:(lit(":") e:expression() {e})? d:type_param_default()? {
+                TypeParam { kind: TypeParamKind::TypeVar { bound: b }, name: n, default: d }
+            }
+            / invalid_type_param()
+            / lit("*") n:name() d:(lit("=") e:star_expression() {e})? {
+                TypeParam { kind: TypeParamKind::TypeVarTuple, name: n, default: d }
+            }
+            / lit("**") n:name() d:type_param_default()? {
+                TypeParam { kind: TypeParamKind::ParamSpec, name: n, default: d }
+            }
+
+        rule type_param_default() -> Expression<'a>
+            = lit("=") e:expression() { e }
 
         rule annotated_rhs() -> Expression<'a>
             = yield_expr() / star_expressions()
@@ -229,7 +387,7 @@ parser! {
             = kw:lit("raise") exc:expression()
                 rest:(f:lit("from") cause:expression() {(f, cause)})?
         {   make_raise(kw, Some(exc), rest)  }
-        / kw:lit("Raise") { make_raise(kw, None, None ) }
+        / kw:lit("raise") { make_raise(kw, None, None ) }
 
         //TODO play around with this, the greedy star seems like a weird spot to me
         rule global_stmt() -> Global<'a>
@@ -247,8 +405,9 @@ parser! {
                 make_del(kw, t)
             }
             / kw:lit("del") t:del_targets() &(lit(";") / tok(NL, "NEWLINE")) {
-                make_del(kw, make_del_tuple(None, t, None))
+                make_del(kw, make_del_tuple(arena, None, t, None))
             }
+            / invalid_del_stmt()
 
         rule yield_stmt() -> Expression<'a>
             = yield_expr()
@@ -291,7 +450,7 @@ parser! {
 
         rule import_from_as_name() -> ImportAlias<'a>
             = n:name() asname:(kw:lit("as") z:name() {(kw, z)})? {
-                make_import_alias(NameOrAttribute::N(Box::new(n)), asname)
+                make_import_alias(arena, NameOrAttribute::N(arena.alloc(n)), asname)
             }
 
         rule dotted_as_names() -> Vec<ImportAlias<'a>>
@@ -302,13 +461,13 @@ parser! {
 
         rule dotted_as_name() -> ImportAlias<'a>
             = n:dotted_name() asname:(kw:lit("as") z:name() {(kw, z)})? {
-                make_import_alias(n, asname)
+                make_import_alias(arena, n, asname)
             }
 
         // TODO: libcst asks why does this diverge from CPython?
         rule dotted_name() -> NameOrAttribute<'a>
             = first:name() tail:(dot:lit(".") n:name() {(dot, n)})* {
-                make_name_or_attr(first, tail)
+                make_name_or_attr(arena, first, tail)
             }
 
         //1. Compound statements
@@ -338,9 +497,10 @@ parser! {
             / class_def_raw()
 
         rule class_def_raw() -> ClassDef<'a>
-            = kw:lit("class") n:name() arg:(l:lpar() a:arguments()? r:rpar() {(l, a, r)})?
+            = invalid_class_def_raw()
+            / kw:lit("class") n:name() t:type_params()? arg:(l:lpar() a:arguments()? r:rpar() {(l, a, r)})?
                 col:lit(":") b:block() {?
-                    make_class_def(kw, n, arg, col, b)
+                    make_class_def(kw, n, t.unwrap_or_default(), arg, col, b)
             }
 
         // Function definitions
@@ -355,19 +515,21 @@ parser! {
             }
 
         rule function_def_raw() -> FunctionDef<'a>
-            = def:lit("def") n:name() op:lit("(") params:params()?
+            = invalid_def_raw()
+            / def:lit("def") n:name() t:type_params()? op:lit("(") params:params()?
                 cp:lit(")") ty:_returns()? c:lit(":") b:block() {
-                    make_function_def(None, def, n, op, params, cp, ty, c, b)
+                    make_function_def(None, def, n, t.unwrap_or_default(), op, params, cp, ty, c, b)
             }
-            / asy:tok(Async, "ASYNC") def:lit("def") n:name() op:lit("(") params:params()?
+            / asy:tok(Async, "ASYNC") def:lit("def") n:name() t:type_params()? op:lit("(") params:params()?
                 cp:lit(")") ty:_returns()? c:lit(":") b:block() {
-                    make_function_def(Some(asy), def, n, op, params, cp, ty, c, b)
+                    make_function_def(Some(asy), def, n, t.unwrap_or_default(), op, params, cp, ty, c, b)
             }
 
         // Function parameters
 
         rule params() -> Parameters<'a>
-            = parameters()
+            = invalid_parameters()
+            / parameters()
 
         rule parameters() -> Parameters<'a>
             = a:slash_no_default() b:param_no_default()* c:param_with_default()*  d:star_etc()? {
@@ -403,17 +565,19 @@ parser! {
             }
 
         rule star_etc() -> StarEtc<'a>
-            = star:lit("*") a:param_no_default() b:param_maybe_default()* kw:kwds()? {
-                StarEtc(Some(StarArg::Param(Box::new(
+            = invalid_star_etc()
+            / star:lit("*") a:param_no_default() b:param_maybe_default()* kw:kwds()? {
+                StarEtc(Some(StarArg::Param(arena.alloc(
                     add_param_star(a, star)))), b, kw)
             }
             / lit("*") c:comma() b:param_maybe_default()+ kw:kwds()? {
-                StarEtc(Some(StarArg::Star(Box::new(ParamStar { }))), b, kw)
+                StarEtc(Some(StarArg::Star(arena.alloc(ParamStar { }))), b, kw)
             }
             / kw:kwds() { StarEtc(None, vec![], Some(kw)) }
 
         rule kwds() -> Param<'a>
-            = star:lit("**") a:param_no_default() {
+            = invalid_kwds()
+            / star:lit("**") a:param_no_default() {
                 add_param_star(a, star)
             }
 
@@ -455,37 +619,42 @@ parser! {
         // If statement
 
         rule if_stmt() -> If<'a>
-            = i:lit("if") a:named_expression() col:lit(":") b:block() elif:elif_stmt() {
-                make_if(i, a, col, b, Some(OrElse::Elif(elif)), false)
+            = invalid_if_stmt()
+            / i:lit("if") a:named_expression() col:lit(":") b:block() elif:elif_stmt() {
+                make_if(arena, i, a, col, b, Some(OrElse::Elif(elif)), false)
             }
             / i:lit("if") a:named_expression() col:lit(":") b:block() el:else_block()? {
-                make_if(i, a, col, b, el.map(OrElse::Else), false)
+                make_if(arena, i, a, col, b, el.map(OrElse::Else), false)
             }
 
         rule elif_stmt() -> If<'a>
-            = i:lit("elif") a:named_expression() col:lit(":") b:block() elif:elif_stmt() {
-                make_if(i, a, col, b, Some(OrElse::Elif(elif)), true)
+            = invalid_elif_stmt()
+            / i:lit("elif") a:named_expression() col:lit(":") b:block() elif:elif_stmt() {
+                make_if(arena, i, a, col, b, Some(OrElse::Elif(elif)), true)
             }
             / i:lit("elif") a:named_expression() col:lit(":") b:block() el:else_block()? {
-                make_if(i, a, col, b, el.map(OrElse::Else), true)
+                make_if(arena, i, a, col, b, el.map(OrElse::Else), true)
             }
 
         rule else_block() -> Else<'a>
-            = el:lit("else") col:lit(":") b:block() {
+            = invalid_else_stmt()
+            / el:lit("else") col:lit(":") b:block() {
                 make_else(el, col, b)
             }
 
         // While statement
 
         rule while_stmt() -> While<'a>
-            = kw:lit("while") test:named_expression() col:lit(":") b:block() el:else_block()? {
+            = invalid_while_stmt()
+            / kw:lit("while") test:named_expression() col:lit(":") b:block() el:else_block()? {
                 make_while(kw, test, col, b, el)
             }
 
         // For statement
 
         rule for_stmt() -> For<'a>
-            = f:lit("for") t:star_targets() i:lit("in") it:star_expressions() c:lit(":")
+            = invalid_for_stmt()
+            / f:lit("for") t:star_targets() i:lit("in") it:star_expressions() c:lit(":")
                 b:block() el:else_block()? {
                     make_for(None, f, t, i, it, c, b, el)
             }
@@ -494,11 +663,13 @@ parser! {
                 c:lit(":") b:block() el:else_block()? {
                     make_for(Some(asy), f, t, i, it, c, b, el)
             }
+            / invalid_for_target()
 
         // With statement
 
         rule with_stmt() -> With<'a>
-            = kw:lit("with") l:lpar() items:separated_trailer(<with_item()>, <comma()>) r:rpar()
+            = invalid_with_stmt()
+            / kw:lit("with") l:lpar() items:separated_trailer(<with_item()>, <comma()>) r:rpar()
                 col:lit(":") b:block() {
                     make_with(None, kw, Some(l), comma_separate(items.0, items.1, items.2), Some(r), col, b)
             }
@@ -519,6 +690,7 @@ parser! {
             = e:expression() a:lit("as") t:star_target() &(lit(",") / lit(":")) {
                 make_with_item(e, Some(a), Some(t))
             }
+            / invalid_with_item()
             / e:expression() {
                 make_with_item(e, None, None)
             }
@@ -526,7 +698,8 @@ parser! {
         // Try statement
 
         rule try_stmt() -> Try<'a>
-            = kw:lit("try") lit(":") b:block() f:finally_block() {
+            = invalid_try_stmt()
+            / kw:lit("try") lit(":") b:block() f:finally_block() {
                 make_try(kw, b, vec![], None, Some(f))
             }
             / kw:lit("try") lit(":") b:block() ex:except_block()+ el:else_block()?
@@ -544,22 +717,24 @@ parser! {
         // Except statement
 
         rule except_block() -> ExceptHandler<'a>
-            = kw:lit("except") e:expression() a:(k:lit("as") n:name() {(k, n)})?
+            = invalid_except_stmt()
+            / kw:lit("except") e:expression() a:(k:lit("as") n:name() {(k, n)})?
                 col:lit(":") b:block() {
-                    make_except(kw, Some(e), a, col, b)
+                    make_except(arena, kw, Some(e), a, col, b)
             }
             / kw:lit("except") col:lit(":") b:block() {
-                make_except(kw, None, None, col, b)
+                make_except(arena, kw, None, None, col, b)
             }
 
         rule except_star_block() -> ExceptStarHandler<'a>
             = kw:lit("except") star:lit("*") e:expression()
                 a:(k:lit("as") n:name() {(k, n)})? col:lit(":") b:block() {
-                    make_except_star(kw, star, e, a, col, b)
+                    make_except_star(arena, kw, star, e, a, col, b)
             }
 
         rule finally_block() -> Finally<'a>
-            = kw:lit("finally") col:lit(":") b:block() {
+            = invalid_finally_stmt()
+            / kw:lit("finally") col:lit(":") b:block() {
                 make_finally(kw, col, b)
             }
 
@@ -574,7 +749,7 @@ parser! {
 
         rule subject_expr() -> Expression<'a>
             = first:star_named_expression() c:comma() rest:star_named_expressions()? {
-                Expression::Tuple(Box::new(
+                Expression::Tuple(arena.alloc(
                     make_tuple_from_elements(first.with_comma(c), rest.unwrap_or_default()))
                 )
             }
@@ -600,12 +775,12 @@ parser! {
 
         rule as_pattern() -> MatchPattern<'a>
             = pat:or_pattern() kw:lit("as") target:pattern_capture_target() {
-                make_as_pattern(Some(pat), Some(kw), Some(target))
+                make_as_pattern(arena, Some(pat), Some(kw), Some(target))
             }
 
         rule or_pattern() -> MatchPattern<'a>
             = pats:separated(<closed_pattern()>, <lit("|")>) {
-                make_or_pattern(pats.0, pats.1)
+                make_or_pattern(arena, pats.0, pats.1)
             }
 
         rule closed_pattern() -> MatchPattern<'a>
@@ -621,7 +796,7 @@ parser! {
         rule literal_pattern() -> MatchPattern<'a>
             = val:signed_number() !(lit("+") / lit("-")) { make_match_value(val) }
             / val:complex_number() { make_match_value(val) }
-            / val:strings() { make_match_value(val.into()) }
+            / val:strings() { make_match_value(make_string_expression(arena, val)) }
             / n:lit("None") { make_match_singleton(make_name(n)) }
             / n:lit("True") { make_match_singleton(make_name(n)) }
             / n:lit("False") { make_match_singleton(make_name(n)) }
@@ -629,42 +804,42 @@ parser! {
         rule literal_expr() -> Expression<'a>
             = val:signed_number() !(lit("+") / lit("-")) { val }
             / val:complex_number() { val }
-            / val:strings() { val.into() }
-            / n:lit("None") { Expression::Name(Box::new(make_name(n))) }
-            / n:lit("True") { Expression::Name(Box::new(make_name(n))) }
-            / n:lit("False") { Expression::Name(Box::new(make_name(n))) }
+            / val:strings() { make_string_expression(arena, val) }
+            / n:lit("None") { Expression::Name(arena.alloc(make_name(n))) }
+            / n:lit("True") { Expression::Name(arena.alloc(make_name(n))) }
+            / n:lit("False") { Expression::Name(arena.alloc(make_name(n))) }
 
         rule complex_number() -> Expression<'a>
             = re:signed_real_number() op:(lit("+")/lit("-")) im:imaginary_number() {?
-                make_binary_op(re, op, im).map_err(|_| "complex number")
+                make_binary_op(arena, re, op, im).map_err(|_| "complex number")
             }
 
         rule signed_number() -> Expression<'a>
-            = n:tok(Number, "number") { make_number(n) }
+            = n:tok(Number, "number") {? make_number(diagnostics, arena, n) }
             / op:lit("-") n:tok(Number, "number") {?
-                make_unary_op(op, make_number(n)).map_err(|_| "signed number")
+                make_unary_op(arena, op, make_number(diagnostics, arena, n)?).map_err(|_| "signed number")
             }
 
         rule signed_real_number() -> Expression<'a>
             = real_number()
             / op:lit("-") n:real_number() {?
-                make_unary_op(op, n).map_err(|_| "signed real number")
+                make_unary_op(arena, op, n).map_err(|_| "signed real number")
             }
 
         rule real_number() -> Expression<'a>
-            = n:tok(Number, "number") {? ensure_real_number(n) }
+            = n:tok(Number, "number") {? ensure_real_number(diagnostics, arena, n) }
 
         rule imaginary_number() -> Expression<'a>
-            = n:tok(Number, "number") {? ensure_imaginary_number(n) }
+            = n:tok(Number, "number") {? ensure_imaginary_number(diagnostics, arena, n) }
 
         rule capture_pattern() -> MatchPattern<'a>
-            = t:pattern_capture_target() { make_as_pattern(None, None, Some(t)) }
+            = t:pattern_capture_target() { make_as_pattern(arena, None, None, Some(t)) }
 
         rule pattern_capture_target() -> Name<'a>
             = !lit("_") n:name() !(lit(".") / lit("(") / lit("=")) { n }
 
         rule wildcard_pattern() -> MatchPattern<'a>
-            = lit("_") { make_as_pattern(None, None, None) }
+            = lit("_") { make_as_pattern(arena, None, None, None) }
 
         rule value_pattern() -> MatchPattern<'a>
             = v:attr() !(lit(".") / lit("(") / lit("=")) {
@@ -676,12 +851,11 @@ parser! {
         rule attr() -> NameOrAttribute<'a>
             = &(name() lit(".")) v:name_or_attr() { v }
 
-        #[cache_left_rec]
+        #[cache]
         rule name_or_attr() -> NameOrAttribute<'a>
-            = val:name_or_attr() d:lit(".") attr:name() {
-                NameOrAttribute::A(Box::new(make_attribute(val.into(), d, attr)))
+            = first:name() tail:(d:lit(".") attr:name() {(d, attr)})* {
+                make_name_or_attr(arena, first, tail)
             }
-            / n:name() { NameOrAttribute::N(Box::new(n)) }
 
         rule group_pattern() -> MatchPattern<'a>
             = l:lpar() pat:pattern() r:rpar() { pat }
@@ -778,40 +952,51 @@ parser! {
 
         // Expressions
 
-        #[cache]
         rule expression() -> Expression<'a>
-            = _conditional_expression()
+            = memo(<_expression()>, &diagnostics.memos().expression)
+
+        rule _expression() -> Expression<'a>
+            = invalid_expression()
+            / invalid_legacy_expression()
+            / _conditional_expression()
             / lambdef()
 
+        // The body is parsed once and the `if` looked for after it, rather than trying the
+        // conditional and falling back to a plain `disjunction()`, whose cache hit would clone
+        // the whole body again.
         rule _conditional_expression() -> Expression<'a>
-            = body:disjunction() i:lit("if") test:disjunction() e:lit("else") oe:expression() {
-                Expression::IfExp(Box::new(make_ifexp(body, i, test, e, oe)))
+            = body:disjunction() rest:(i:lit("if") test:disjunction() e:lit("else") oe:expression() { (i, test, e, oe) })? {
+                match rest {
+                    Some((i, test, e, oe)) => Expression::IfExp(arena.alloc(make_ifexp(arena, body, i, test, e, oe))),
+                    None => body,
+                }
             }
-            / disjunction()
 
         rule yield_expr() -> Expression<'a>
             = y:lit("yield") f:lit("from") a:expression() {
-                Expression::Yield(Box::new(make_yield(y, Some(f), Some(a))))
+                Expression::Yield(arena.alloc(make_yield(arena, y, Some(f), Some(a))))
             }
             / y:lit("yield") a:star_expressions()? {
-                Expression::Yield(Box::new(make_yield(y, None, a)))
+                Expression::Yield(arena.alloc(make_yield(arena, y, None, a)))
             }
 
         rule star_expressions() -> Expression<'a>
             = first:star_expression()
-                rest:(comma:comma() e:star_expression() { (comma, expr_to_element(e)) })+
+                rest:(comma:comma() e:star_expression() { (comma, expr_to_element(e)) })*
                 comma:comma()? {
-                    Expression::Tuple(Box::new(make_tuple(expr_to_element(first), rest, comma, None, None)))
-            }
-            / e:star_expression() comma:comma() {
-                Expression::Tuple(Box::new(make_tuple(expr_to_element(e), vec![], Some(comma), None, None)))
+                    if rest.is_empty() && comma.is_none() {
+                        first
+                    } else {
+                        Expression::Tuple(arena.alloc(make_tuple(expr_to_element(first), rest, comma, None, None)))
+                    }
             }
-            / star_expression()
 
-        #[cache]
         rule star_expression() -> Expression<'a>
+            = memo(<_star_expression()>, &diagnostics.memos().star_expression)
+
+        rule _star_expression() -> Expression<'a>
             = star:lit("*") e:bitwise_or() {
-                Expression::StarredElement(Box::new(make_starred_element(star, expr_to_element(e))))
+                Expression::StarredElement(arena.alloc(make_starred_element(arena, star, expr_to_element(e))))
             }
             / expression()
 
@@ -822,47 +1007,56 @@ parser! {
 
         rule star_named_expression() -> Element<'a>
             = star:lit("*") e:bitwise_or() {
-                Element::Starred(Box::new(make_starred_element(star, expr_to_element(e))))
+                Element::Starred(arena.alloc(make_starred_element(arena, star, expr_to_element(e))))
             }
             / e:named_expression() { expr_to_element(e) }
 
         rule named_expression() -> Expression<'a>
+            = assignment_expression()
+            / invalid_named_expression()
+            / e:expression() !lit(":=") { e }
+
+        rule assignment_expression() -> Expression<'a>
             = a:name() op:lit(":=") b:expression() {
-                Expression::NamedExpr(Box::new(make_named_expr(a, op, b)))
+                Expression::NamedExpr(arena.alloc(make_named_expr(arena, a, op, b)))
             }
-            / e:expression() !lit(":=") { e }
 
-        #[cache]
+        // Operator chains are parsed as an operand followed by any number of `op operand` pairs
+        // and folded to the left, which builds the same tree as the left-recursive rules of
+        // CPython's grammar.  With `#[cache_left_rec]` every step of a chain clones the tree
+        // parsed so far out of the memo, making a long chain quadratic.  Only the rules that
+        // are entered more than once at the same token are memoized, and through `memo`
+        // rather than `#[cache]`, so that nested brackets don't clone their contents at every
+        // level either.
+
         rule disjunction() -> Expression<'a>
-            = a:conjunction() b:(or:lit("or") inner:conjunction() { (or, inner) })+ {?
-                make_boolean_op(a, b).map_err(|e| "expected disjunction")
+            = memo(<_disjunction()>, &diagnostics.memos().disjunction)
+
+        rule _disjunction() -> Expression<'a>
+            = a:conjunction() b:(or:lit("or") inner:conjunction() { (or, inner) })* {?
+                make_boolean_op(arena, a, b).map_err(|e| "expected disjunction")
             }
-            / conjunction()
 
-        #[cache]
         rule conjunction() -> Expression<'a>
-            = a:inversion() b:(and:lit("and") inner:inversion() { (and, inner) })+ {?
-                make_boolean_op(a, b).map_err(|e| "expected conjunction")
+            = a:inversion() b:(and:lit("and") inner:inversion() { (and, inner) })* {?
+                make_boolean_op(arena, a, b).map_err(|e| "expected conjunction")
             }
-            / inversion()
 
-        #[cache]
         rule inversion() -> Expression<'a>
             = not:lit("not") a:inversion() {?
-                make_unary_op(not, a).map_err(|e| "expected inversion")
+                make_unary_op(arena, not, a).map_err(|e| "expected inversion")
             }
             / comparison()
 
         // Comparison operators
 
-        #[cache]
         rule comparison() -> Expression<'a>
-            = a:bitwise_or() b:compare_op_bitwise_or_pair()+ { make_comparison(a, b) }
-            / bitwise_or()
+            = a:bitwise_or() b:compare_op_bitwise_or_pair()* {
+                if b.is_empty() { a } else { make_comparison(arena, a, b) }
+            }
 
         // This implementation diverges slightly from CPython (3.9) to avoid bloating
         // the parser cache and increase readability.
-        #[cache]
         rule compare_op_bitwise_or_pair() -> (CompOp, Expression<'a>)
             = _op_bitwise_or("==")
             / _op_bitwise_or("!=") // TODO: support barry_as_flufl
@@ -889,133 +1083,106 @@ parser! {
                     .map_err(|_| "comparison")
             }
 
-        #[cache_left_rec]
         rule bitwise_or() -> Expression<'a>
-            = a:bitwise_or() op:lit("|") b:bitwise_xor() {?
-                make_binary_op(a, op, b).map_err(|e| "expected bitwise_or")
+            = memo(<_bitwise_or()>, &diagnostics.memos().bitwise_or)
+
+        rule _bitwise_or() -> Expression<'a>
+            = a:bitwise_xor() b:(op:lit("|") e:bitwise_xor() {(op, e)})* {?
+                make_binary_ops(arena, a, b).map_err(|e| "expected bitwise_or")
             }
-            / bitwise_xor()
 
-        #[cache_left_rec]
         rule bitwise_xor() -> Expression<'a>
-            = a:bitwise_xor() op:lit("^") b:bitwise_and() {?
-                make_binary_op(a, op, b).map_err(|e| "expected bitwise_xor")
+            = a:bitwise_and() b:(op:lit("^") e:bitwise_and() {(op, e)})* {?
+                make_binary_ops(arena, a, b).map_err(|e| "expected bitwise_xor")
             }
-            / bitwise_and()
 
-        #[cache_left_rec]
         rule bitwise_and() -> Expression<'a>
-            = a:bitwise_and() op:lit("&") b:shift_expr() {?
-                make_binary_op(a, op, b).map_err(|e| "expected bitwise_and")
+            = a:shift_expr() b:(op:lit("&") e:shift_expr() {(op, e)})* {?
+                make_binary_ops(arena, a, b).map_err(|e| "expected bitwise_and")
             }
-            / shift_expr()
 
-        #[cache_left_rec]
         rule shift_expr() -> Expression<'a>
-            = a:shift_expr() op:lit("<<") b:sum() {?
-                make_binary_op(a, op, b).map_err(|e| "expected shift_expr")
-            }
-            / a:shift_expr() op:lit(">>") b:sum() {?
-                make_binary_op(a, op, b).map_err(|e| "expected shift_expr")
+            = a:sum() b:(op:(lit("<<") / lit(">>")) e:sum() {(op, e)})* {?
+                make_binary_ops(arena, a, b).map_err(|e| "expected shift_expr")
             }
-            / sum()
 
-        #[cache_left_rec]
         rule sum() -> Expression<'a>
-            = a:sum() op:lit("+") b:term() {?
-                make_binary_op(a, op, b).map_err(|e| "expected sum")
+            = a:term() b:(op:(lit("+") / lit("-")) e:term() {(op, e)})* {?
+                make_binary_ops(arena, a, b).map_err(|e| "expected sum")
             }
-            / a:sum() op:lit("-") b:term() {?
-                make_binary_op(a, op, b).map_err(|e| "expected sum")
-            }
-            / term()
 
-        #[cache_left_rec]
         rule term() -> Expression<'a>
-            = a:term() op:lit("*") b:factor() {?
-                make_binary_op(a, op, b).map_err(|e| "expected term")
-            }
-            / a:term() op:lit("/") b:factor() {?
-                make_binary_op(a, op, b).map_err(|e| "expected term")
+            = a:factor() b:(op:(lit("*") / lit("/") / lit("//") / lit("%") / lit("@")) e:factor() {(op, e)})* {?
+                make_binary_ops(arena, a, b).map_err(|e| "expected term")
             }
-            / a:term() op:lit("//") b:factor() {?
-                make_binary_op(a, op, b).map_err(|e| "expected term")
-            }
-            / a:term() op:lit("%") b:factor() {?
-                make_binary_op(a, op, b).map_err(|e| "expected term")
-            }
-            / a:term() op:lit("@") b:factor() {?
-                make_binary_op(a, op, b).map_err(|e| "expected term")
-            }
-            / factor()
 
-        #[cache]
         rule factor() -> Expression<'a>
+            = memo(<_factor()>, &diagnostics.memos().factor)
+
+        rule _factor() -> Expression<'a>
             = op:lit("+") a:factor() {?
-                make_unary_op(op, a).map_err(|e| "expected factor")
+                make_unary_op(arena, op, a).map_err(|e| "expected factor")
             }
             / op:lit("-") a:factor() {?
-                make_unary_op(op, a).map_err(|e| "expected factor")
+                make_unary_op(arena, op, a).map_err(|e| "expected factor")
             }
             / op:lit("~") a:factor() {?
-                make_unary_op(op, a).map_err(|e| "expected factor")
+                make_unary_op(arena, op, a).map_err(|e| "expected factor")
             }
             / power()
 
         rule power() -> Expression<'a>
-            = a:await_primary() op:lit("**") b:factor() {?
-                make_binary_op(a, op, b).map_err(|e| "expected power")
+            = a:await_primary() b:(op:lit("**") e:factor() {(op, e)})? {?
+                make_binary_ops(arena, a, b.into_iter().collect()).map_err(|e| "expected power")
             }
-            / await_primary()
 
         // Primary elements
 
         rule await_primary() -> Expression<'a>
             = aw:tok(AWAIT, "AWAIT") e:primary() {
-                Expression::Await(Box::new(make_await(aw, e)))
+                Expression::Await(arena.alloc(make_await(arena, aw, e)))
             }
             / primary()
 
-        #[cache_left_rec]
         rule primary() -> Expression<'a>
-            = v:primary() dot:lit(".") attr:name() {
-                Expression::Attribute(Box::new(make_attribute(v, dot, attr)))
-            }
-            / a:primary() b:genexp() {
-                Expression::Call(Box::new(make_genexp_call(a, b)))
-            }
-            / f:primary() lpar:lit("(") arg:arguments()? rpar:lit(")") {
-                Expression::Call(Box::new(make_call(f, lpar, arg.unwrap_or_default(), rpar)))
-            }
-            / v:primary() lbrak:lbrak() s:slices() rbrak:rbrak() {
-                Expression::Subscript(Box::new(make_subscript(v, lbrak, s, rbrak)))
-            }
-            / atom()
+            = memo(<_primary()>, &diagnostics.memos().primary)
+
+        rule _primary() -> Expression<'a>
+            = a:atom() t:trailer()* { make_trailers(arena, a, t) }
+
+        // Plain arguments are tried before a generator expression, which only fails after its
+        // first expression has been parsed.
+        rule trailer() -> Trailer<'a>
+            = dot:lit(".") attr:name() { Trailer::Attribute(dot, attr) }
+            / lpar:lit("(") arg:arguments()? rpar:lit(")") { Trailer::Call(lpar, arg.unwrap_or_default(), rpar) }
+            / g:genexp() { Trailer::GenexpCall(g) }
+            / lbrak:lbrak() s:slices() rbrak:rbrak() { Trailer::Subscript(lbrak, s, rbrak) }
 
         rule slices() -> Vec<SubscriptElement<'a>>
-            = s:slice() !lit(",") { vec![SubscriptElement { slice: s, }] }
-            / slices:separated_trailer(<slice()>, <comma()>) {
+            = slices:separated_trailer(<slice()>, <comma()>) {
                 make_slices(slices.0, slices.1, slices.2)
             }
 
+        // An index is tried first, so that it is only parsed again when it turns out to be the
+        // lower bound of a slice.
         rule slice() -> BaseSlice<'a>
-            = l:expression()? col:lit(":") u:expression()?
+            = v:expression() !lit(":") { make_index(arena, v) }
+            / l:expression()? col:lit(":") u:expression()?
                 rest:(c:lit(":") s:expression()? {(c, s)})? {
-                    make_slice(l, col, u, rest)
+                    make_slice(arena, l, col, u, rest)
             }
-            / v:expression() { make_index(v) }
 
         rule atom() -> Expression<'a>
-            = n:name() { Expression::Name(Box::new(n)) }
-            / n:lit("True") { Expression::Name(Box::new(make_name(n))) }
-            / n:lit("False") { Expression::Name(Box::new(make_name(n))) }
-            / n:lit("None") { Expression::Name(Box::new(make_name(n))) }
-            / &(tok(STRING, "") / tok(FStringStart, "")) s:strings() {s.into()}
-            / n:tok(Number, "NUMBER") { make_number(n) }
-            / &lit("(") e:(tuple() / group() / (g:genexp() {Expression::GeneratorExp(Box::new(g))})) {e}
+            = n:name() { Expression::Name(arena.alloc(n)) }
+            / n:lit("True") { Expression::Name(arena.alloc(make_name(n))) }
+            / n:lit("False") { Expression::Name(arena.alloc(make_name(n))) }
+            / n:lit("None") { Expression::Name(arena.alloc(make_name(n))) }
+            / &(tok(STRING, "") / tok(FStringStart, "")) s:strings() {make_string_expression(arena, s)}
+            / n:tok(Number, "NUMBER") {? make_number(diagnostics, arena, n) }
+            / &lit("(") e:(tuple() / group() / (g:genexp() {Expression::GeneratorExp(arena.alloc(g))})) {e}
             / &lit("[") e:(list() / listcomp()) {e}
             / &lit("{") e:(dict() / set() / dictcomp() / setcomp()) {e}
-            / lit("...") { Expression::Ellipsis }
+            / e:lit("...") { Expression::Ellipsis(arena.alloc(Ellipsis { span: Span::from_token(e) })) }
 
         rule group() -> Expression<'a>
             = lpar:lpar() e:(yield_expr() / named_expression()) rpar:rpar() { e }
@@ -1024,11 +1191,12 @@ parser! {
 
         rule lambdef() -> Expression<'a>
             = kw:lit("lambda") p:lambda_params()? c:lit(":") b:expression() {
-                Expression::Lambda(Box::new(make_lambda(kw, p.unwrap_or_default(), c, b)))
+                Expression::Lambda(arena.alloc(make_lambda(arena, kw, p.unwrap_or_default(), c, b)))
             }
 
         rule lambda_params() -> Parameters<'a>
-            = lambda_parameters()
+            = invalid_lambda_parameters()
+            / lambda_parameters()
 
         // lambda_parameters etc. duplicates parameters but without annotations or type
         // comments, and if there's no comma after a parameter, we expect a colon, not a
@@ -1071,19 +1239,21 @@ parser! {
             }
 
         rule lambda_star_etc() -> StarEtc<'a>
-            = star:lit("*") a:lambda_param_no_default()
+            = invalid_lambda_star_etc()
+            / star:lit("*") a:lambda_param_no_default()
                 b:lambda_param_maybe_default()* kw:lambda_kwds()? {
                     StarEtc(Some(StarArg::Param(
-                        Box::new(add_param_star(a, star))
+                        arena.alloc(add_param_star(a, star))
                     )), b, kw)
             }
             / lit("*") c:comma() b:lambda_param_maybe_default()+ kw:lambda_kwds()? {
-                StarEtc(Some(StarArg::Star(Box::new(ParamStar { }))), b, kw)
+                StarEtc(Some(StarArg::Star(arena.alloc(ParamStar { }))), b, kw)
             }
             / kw:lambda_kwds() { StarEtc(None, vec![], Some(kw)) }
 
         rule lambda_kwds() -> Param<'a>
-            = star:lit("**") a:lambda_param_no_default() {
+            = invalid_lambda_kwds()
+            / star:lit("**") a:lambda_param_no_default() {
                 add_param_star(a, star)
             }
 
@@ -1118,37 +1288,39 @@ parser! {
         rule strings() -> String<'a>
             = s:(str:tok(STRING, "STRING") t:&_ {( make_string(str), t) }
                 / str:fstring() t:&_ {(String::Formatted(str), t)})+ {
-                make_strings(s)
+                make_strings(arena, s)
             }
 
         rule list() -> Expression<'a>
             = lbrak:lbrak() e:star_named_expressions()? rbrak:rbrak() {
-                Expression::List(Box::new(
+                Expression::List(arena.alloc(
                     make_list(lbrak, e.unwrap_or_default(), rbrak))
                 )
             }
 
         rule tuple() -> Expression<'a>
-            = lpar:lpar() first:star_named_expression() &lit(",")
+            = &##holds(&[","]) lpar:lpar() first:star_named_expression() &lit(",")
                 rest:(c:comma() e:star_named_expression() {(c, e)})*
                 trailing_comma:comma()? rpar:rpar() {
-                    Expression::Tuple(Box::new(
+                    Expression::Tuple(arena.alloc(
                         make_tuple(first, rest, trailing_comma, Some(lpar), Some(rpar))
                     ))
             }
             / lpar:lpar() rpar:lit(")") {
-                Expression::Tuple(Box::new(Tuple::default()))}
+                Expression::Tuple(arena.alloc(Tuple { span: Span::between(lpar.tok, rpar), ..Default::default() }))}
 
         rule set() -> Expression<'a>
             = lbrace:lbrace() e:star_named_expressions()? rbrace:rbrace() {
-                Expression::Set(Box::new(make_set(lbrace, e.unwrap_or_default(), rbrace)))
+                Expression::Set(arena.alloc(make_set(lbrace, e.unwrap_or_default(), rbrace)))
             }
 
         // Dicts
 
         rule dict() -> Expression<'a>
-            = lbrace:lbrace() els:double_starred_keypairs()? rbrace:rbrace() {
-                Expression::Dict(Box::new(make_dict(lbrace, els.unwrap_or_default(), rbrace)))
+            = &##holds(&[":", "**"]) lbrace() r:invalid_double_starred_kvpairs() { r }
+            / &(##holds(&[":", "**"]) / lit("{") lit("}"))
+                lbrace:lbrace() els:double_starred_keypairs()? rbrace:rbrace() {
+                Expression::Dict(arena.alloc(make_dict(lbrace, els.unwrap_or_default(), rbrace)))
             }
 
 
@@ -1169,7 +1341,7 @@ parser! {
         // Comprehensions & generators
 
         rule for_if_clauses() -> CompFor<'a>
-            = c:for_if_clause()+ { merge_comp_fors(c) }
+            = c:for_if_clause()+ { merge_comp_fors(arena, c) }
 
         rule for_if_clause() -> CompFor<'a>
             = asy:_async() f:lit("for") tgt:star_targets() i:lit("in")
@@ -1180,6 +1352,7 @@ parser! {
             iter:disjunction() ifs:_comp_if()* {
                 make_for_if(None, f, tgt, i, iter, ifs)
             }
+            / invalid_for_target()
 
         rule _comp_if() -> CompIf<'a>
             = kw:lit("if") cond:disjunction() {
@@ -1188,33 +1361,34 @@ parser! {
 
         rule listcomp() -> Expression<'a>
             = lbrak:lbrak() elt:named_expression() comp:for_if_clauses() rbrak:rbrak() {
-                Expression::ListComp(Box::new(make_list_comp(lbrak, elt, comp, rbrak)))
+                Expression::ListComp(arena.alloc(make_list_comp(arena, lbrak, elt, comp, rbrak)))
             }
 
         rule setcomp() -> Expression<'a>
             = l:lbrace() elt:named_expression() comp:for_if_clauses() r:rbrace() {
-                Expression::SetComp(Box::new(make_set_comp(l, elt, comp, r)))
+                Expression::SetComp(arena.alloc(make_set_comp(arena, l, elt, comp, r)))
             }
 
         rule genexp() -> GeneratorExp<'a>
             = lpar:lpar() g:_bare_genexp() rpar:rpar() {
-                g
+                GeneratorExp { span: Span::between(lpar.tok, rpar.tok), ..g }
             }
 
         rule _bare_genexp() -> GeneratorExp<'a>
-            = elt:named_expression() comp:for_if_clauses() {
-                make_bare_genexp(elt, comp)
+            = elt:(assignment_expression() / e:expression() !lit(":=") { e }) comp:for_if_clauses() {
+                make_bare_genexp(arena, elt, comp)
             }
 
         rule dictcomp() -> Expression<'a>
-            = lbrace:lbrace() elt:kvpair() comp:for_if_clauses() rbrace:rbrace() {
-                Expression::DictComp(Box::new(make_dict_comp(lbrace, elt, comp, rbrace)))
+            = &##holds(&[":"]) lbrace:lbrace() elt:kvpair() comp:for_if_clauses() rbrace:rbrace() {
+                Expression::DictComp(arena.alloc(make_dict_comp(arena, lbrace, elt, comp, rbrace)))
             }
 
         // Function call arguments
 
         rule arguments() -> Vec<Arg<'a>>
             = a:args() trail:comma()? &lit(")") {add_arguments_trailing_comma(a, trail)}
+            / invalid_arguments()
 
         rule args() -> Vec<Arg<'a>>
             = first:_posarg()
@@ -1229,20 +1403,19 @@ parser! {
             / kwargs()
 
         rule _posarg() -> Arg<'a>
-            = a:(starred_expression() / e:named_expression() { make_arg(e) })
+            = a:(starred_expression() / e:(assignment_expression() / e:expression() !lit(":=") { e }) { make_arg(e) })
                 !lit("=") { a }
 
         rule kwargs() -> Vec<Arg<'a>>
             = sitems:separated(<kwarg_or_starred()>, <comma()>)
-                scomma:comma()
-                ditems:separated(<kwarg_or_double_starred()>, <comma()>) {
-                    concat(
-                        comma_separate(sitems.0, sitems.1, Some(scomma)),
-                        comma_separate(ditems.0, ditems.1, None),
-                    )
-            }
-            / items:separated(<kwarg_or_starred()>, <comma()>) {
-                    comma_separate(items.0, items.1, None)
+                ditems:(c:comma() d:separated(<kwarg_or_double_starred()>, <comma()>) { (c, d) })? {
+                    match ditems {
+                        Some((scomma, ditems)) => concat(
+                            comma_separate(sitems.0, sitems.1, Some(scomma)),
+                            comma_separate(ditems.0, ditems.1, None),
+                        ),
+                        None => comma_separate(sitems.0, sitems.1, None),
+                    }
             }
             / items:separated(<kwarg_or_double_starred()>, <comma()>) {
                     comma_separate(items.0, items.1, None)
@@ -1252,11 +1425,13 @@ parser! {
             = star:lit("*") e:expression() { make_star_arg(star, e) }
 
         rule kwarg_or_starred() -> Arg<'a>
-            = _kwarg()
+            = invalid_kwarg()
+            / _kwarg()
             / starred_expression()
 
         rule kwarg_or_double_starred() -> Arg<'a>
-            = _kwarg()
+            = invalid_kwarg()
+            / _kwarg()
             / star:lit("**") e:expression() { make_star_arg(star, e) }
 
         rule _kwarg() -> Arg<'a>
@@ -1270,7 +1445,7 @@ parser! {
         rule star_targets() -> AssignTargetExpression<'a>
             = a:star_target() !lit(",") {a}
             / targets:separated_trailer(<t:star_target() {assign_target_to_element(t)}>, <comma()>) {
-                AssignTargetExpression::Tuple(Box::new(
+                AssignTargetExpression::Tuple(arena.alloc(
                     make_tuple(targets.0, targets.1, targets.2, None, None)
                 ))
             }
@@ -1292,72 +1467,71 @@ parser! {
                 make_tuple(assign_target_to_element(t), vec![], trail, None, None)
             }
 
-        #[cache]
         rule star_target() -> AssignTargetExpression<'a>
+            = memo(<_star_target()>, &diagnostics.memos().star_target)
+
+        rule _star_target() -> AssignTargetExpression<'a>
             = star:lit("*") !lit("*") t:star_target() {
-                AssignTargetExpression::StarredElement(Box::new(
-                    make_starred_element(star, assign_target_to_element(t))
+                AssignTargetExpression::StarredElement(arena.alloc(
+                    make_starred_element(arena, star, assign_target_to_element(t))
                 ))
             }
             / target_with_star_atom()
 
-        #[cache]
         rule target_with_star_atom() -> AssignTargetExpression<'a>
+            = memo(<_target_with_star_atom()>, &diagnostics.memos().target_with_star_atom)
+
+        rule _target_with_star_atom() -> AssignTargetExpression<'a>
             = a:t_primary() dot:lit(".") n:name() !t_lookahead() {
-                AssignTargetExpression::Attribute(Box::new(make_attribute(a, dot, n)))
+                AssignTargetExpression::Attribute(arena.alloc(make_attribute(arena, a, dot, n)))
             }
             / a:t_primary() lbrak:lbrak() s:slices() rbrak:rbrak() !t_lookahead() {
-                AssignTargetExpression::Subscript(Box::new(
-                    make_subscript(a, lbrak, s, rbrak)
+                AssignTargetExpression::Subscript(arena.alloc(
+                    make_subscript(arena, a, lbrak, s, rbrak)
                 ))
             }
             / a:star_atom() {a}
 
         rule star_atom() -> AssignTargetExpression<'a>
-            = a:name() { AssignTargetExpression::Name(Box::new(a)) }
+            = a:name() { AssignTargetExpression::Name(arena.alloc(a)) }
             / lpar:lpar() a:target_with_star_atom() rpar:rpar() { a}
             / lpar:lpar() a:star_targets_tuple_seq()? rpar:rpar() {
-               AssignTargetExpression::Tuple(Box::new(
-                   a.unwrap_or_default()
+               AssignTargetExpression::Tuple(arena.alloc(
+                   Tuple { span: Span::between(lpar.tok, rpar.tok), ..a.unwrap_or_default() }
                ))
             }
             / lbrak:lbrak() a:star_targets_list_seq()? rbrak:rbrak() {
-                AssignTargetExpression::List(Box::new(
+                AssignTargetExpression::List(arena.alloc(
                     make_list(lbrak, a.unwrap_or_default(), rbrak)
                 ))
             }
 
         rule single_target() -> AssignTargetExpression<'a>
             = single_subscript_attribute_target()
-            / n:name() { AssignTargetExpression::Name(Box::new(n)) }
+            / n:name() { AssignTargetExpression::Name(arena.alloc(n)) }
             / lpar:lpar() t:single_target() rpar:rpar() { t }
 
         rule single_subscript_attribute_target() -> AssignTargetExpression<'a>
             = a:t_primary() dot:lit(".") n:name() !t_lookahead() {
-                AssignTargetExpression::Attribute(Box::new(make_attribute(a, dot, n)))
+                AssignTargetExpression::Attribute(arena.alloc(make_attribute(arena, a, dot, n)))
             }
             / a:t_primary() lbrak:lbrak() s:slices() rbrak:rbrak() !t_lookahead() {
-                AssignTargetExpression::Subscript(Box::new(
-                    make_subscript(a, lbrak, s, rbrak)
+                AssignTargetExpression::Subscript(arena.alloc(
+                    make_subscript(arena, a, lbrak, s, rbrak)
                 ))
             }
 
 
-        #[cache_left_rec]
+        /// A primary that is followed by `(`, `[` or `.`: everything but the last trailer of a
+        /// target.  A bracketed atom with no trailer after it is turned down before it is
+        /// parsed: `star_atom` tries a `t_primary` at every level of a nested list or tuple, and
+        /// parsing each level again would make nesting quadratic.
         rule t_primary() -> Expression<'a>
-            = value:t_primary() dot:lit(".") attr:name() &t_lookahead() {
-                Expression::Attribute(Box::new(make_attribute(value, dot, attr)))
-            }
-            / v:t_primary() l:lbrak() s:slices() r:rbrak() &t_lookahead() {
-                Expression::Subscript(Box::new(make_subscript(v, l, s, r)))
-            }
-            / f:t_primary() gen:genexp() &t_lookahead() {
-                Expression::Call(Box::new(make_genexp_call(f, gen)))
-            }
-            / f:t_primary() lpar:lit("(") arg:arguments()? rpar:lit(")") &t_lookahead() {
-                Expression::Call(Box::new(make_call(f, lpar, arg.unwrap_or_default(), rpar)))
-            }
-            / a:atom() &t_lookahead() {a}
+            = memo(<_t_primary()>, &diagnostics.memos().t_primary)
+
+        rule _t_primary() -> Expression<'a>
+            = !(##skip_brackets() !t_lookahead())
+                a:atom() &t_lookahead() t:(t:trailer() &t_lookahead() { t })* { make_trailers(arena, a, t) }
 
         rule t_lookahead() -> ()
             = (lit("(") / lit("[") / lit(".")) {}
@@ -1371,23 +1545,23 @@ parser! {
 
         rule del_target() -> DelTargetExpression<'a>
             = a:t_primary() d:lit(".") n:name() !t_lookahead() {
-                DelTargetExpression::Attribute(Box::new(make_attribute(a, d, n)))
+                DelTargetExpression::Attribute(arena.alloc(make_attribute(arena, a, d, n)))
             }
             / a:t_primary() lbrak:lbrak() s:slices() rbrak:rbrak() !t_lookahead() {
-                DelTargetExpression::Subscript(Box::new(
-                    make_subscript(a, lbrak, s, rbrak)
+                DelTargetExpression::Subscript(arena.alloc(
+                    make_subscript(arena, a, lbrak, s, rbrak)
                 ))
             }
             / del_t_atom()
 
         rule del_t_atom() -> DelTargetExpression<'a>
-            = n:name() { DelTargetExpression::Name(Box::new(n)) }
+            = n:name() { DelTargetExpression::Name(arena.alloc(n)) }
             / l:lpar() d:del_target() r:rpar() { d }
             / l:lpar() d:del_targets()? r:rpar() {
-                make_del_tuple(Some(l), d.unwrap_or_default(), Some(r))
+                make_del_tuple(arena, Some(l), d.unwrap_or_default(), Some(r))
             }
             / l:lbrak() d:del_targets()? r:rbrak() {
-                DelTargetExpression::List(Box::new(
+                DelTargetExpression::List(arena.alloc(
                     make_list(l, d.unwrap_or_default(), r)
                 ))
             }
@@ -1398,7 +1572,7 @@ parser! {
             = start:tok(FStringStart, "f\"")
                 parts:(_f_string() / _f_replacement())*
                 end:tok(FStringEnd, "\"") {
-                    make_fstring(start.text, parts, end.text)
+                    make_fstring(start, parts, end)
             }
 
         rule _f_string() -> FormattedStringContent<'a>
@@ -1411,13 +1585,13 @@ parser! {
                 conv:(t:lit("!") c:_f_conversion() {(t,c)})?
                 spec:(t:lit(":") s:_f_spec() {(t,s)})?
                 rb:lit("}") {
-                    FormattedStringContent::Expression(Box::new(
+                    FormattedStringContent::Expression(arena.alloc(
                         make_fstring_expression(lb, e, eq, conv, spec, rb)
                     ))
             }
 
         rule _f_expr() -> Expression<'a>
-            = (g:_bare_genexp() {Expression::GeneratorExp(Box::new(g))})
+            = (g:_bare_genexp() {Expression::GeneratorExp(arena.alloc(g))})
             / star_expressions()
             / yield_expr()
 
@@ -1427,6 +1601,346 @@ parser! {
         rule _f_spec() -> Vec<FormattedStringContent<'a>>
             = (_f_string() / _f_replacement())*
 
+        // Invalid rules
+        //
+        // Alternatives for common mistakes, after CPython's `invalid_*` rules.  They only run
+        // on the second pass over a module that failed to parse (see `parse_module`), record
+        // a message in `diagnostics` and always fail, so they never change what parses.
+
+        rule second_pass()
+            = quiet!{ {? if diagnostics.active() { Ok(()) } else { Err("second pass") } } }
+
+        rule suppress() = { diagnostics.suppress() }
+
+        /// `e` with the invalid rules off, for lookaheads that may fail on valid code.
+        rule without_invalid<T>(e: rule<T>) -> T
+            = suppress() r:e()? {? diagnostics.restore(); r.ok_or("") }
+
+        rule invalid_expression<T>() -> T
+            // `print "x"` and soft keywords are left to the other rules.
+            = second_pass()
+                !(tok(NameTok, "NAME") (tok(STRING, "STRING") / tok(FStringStart, "FSTRING_START"))
+                    / lit("match") / lit("case") / lit("_"))
+                s:position!() a:disjunction() without_invalid(<expression()>) e:position!() {?
+                    if is_legacy_name(&a) || !diagnostics.in_brackets(e) {
+                        Err("")
+                    } else {
+                        Err(diagnostics.raise("invalid syntax. Perhaps you forgot a comma?", diagnostics.span(s, e)))
+                    }
+            }
+            / second_pass() s:position!() disjunction() lit("if") disjunction() e:position!()
+                !(lit("else") / lit(":")) {?
+                    Err(diagnostics.raise("expected 'else' after 'if' expression", diagnostics.span(s, e)))
+            }
+
+        rule invalid_legacy_expression<T>() -> T
+            = second_pass() s:position!() a:name() !lit("(") star_expressions() e:position!() {?
+                if a.value == "print" || a.value == "exec" {
+                    let message = format!("Missing parentheses in call to '{0}'. Did you mean {0}(...)?", a.value);
+                    Err(diagnostics.raise(message, diagnostics.span(s, e)))
+                } else {
+                    Err("")
+                }
+            }
+
+        rule invalid_named_expression<T>() -> T
+            = second_pass() s:position!() a:expression() e:position!() lit(":=") expression() {?
+                let message = format!("cannot use assignment expressions with {}", expr_name(&a));
+                Err(diagnostics.raise(message, diagnostics.span(s, e)))
+            }
+            / second_pass() s:position!() name() lit("=") bitwise_or() e:position!() !(lit("=") / lit(":=")) {?
+                let message = "invalid syntax. Maybe you meant '==' or ':=' instead of '='?";
+                Err(diagnostics.raise(message, diagnostics.span(s, e)))
+            }
+            / second_pass() !(list() / tuple() / genexp() / lit("True") / lit("None") / lit("False"))
+                s:position!() a:bitwise_or() e:position!() lit("=") bitwise_or() !(lit("=") / lit(":=")) {?
+                    let message = format!("cannot assign to {} here. Maybe you meant '==' instead of '='?", expr_name(&a));
+                    Err(diagnostics.raise(message, diagnostics.span(s, e)))
+            }
+
+        rule invalid_assignment<T>() -> T
+            = second_pass() s:position!() a:invalid_ann_assign_target() e:position!() lit(":") expression() {?
+                let message = format!("only single target (not {}) can be annotated", expr_name(&a));
+                Err(diagnostics.raise(message, diagnostics.span(s, e)))
+            }
+            / second_pass() s:position!() star_named_expression() e:position!() lit(",") star_named_expressions()*
+                lit(":") expression() {?
+                    Err(diagnostics.raise("only single target (not tuple) can be annotated", diagnostics.span(s, e)))
+            }
+            / second_pass() s:position!() expression() e:position!() lit(":") expression() {?
+                Err(diagnostics.raise("illegal target for annotation", diagnostics.span(s, e)))
+            }
+            / second_pass() (star_targets() lit("="))* s:position!() a:star_expressions() e:position!() lit("=") {?
+                Err(diagnostics.raise_invalid_target(TargetKind::Star, &a, diagnostics.span(s, e)))
+            }
+            / second_pass() (star_targets() lit("="))* s:position!() yield_expr() e:position!() lit("=") {?
+                Err(diagnostics.raise("assignment to yield expression not possible", diagnostics.span(s, e)))
+            }
+            / second_pass() s:position!() a:star_expressions() e:position!() augassign()
+                (yield_expr() / star_expressions()) {?
+                    let message = format!("'{}' is an illegal expression for augmented assignment", expr_name(&a));
+                    Err(diagnostics.raise(message, diagnostics.span(s, e)))
+            }
+
+        rule invalid_ann_assign_target() -> Expression<'a>
+            = list()
+            / tuple()
+            / lpar() a:invalid_ann_assign_target() rpar() { a }
+
+        rule invalid_del_stmt<T>() -> T
+            = second_pass() lit("del") s:position!() a:star_expressions() e:position!() {?
+                Err(diagnostics.raise_invalid_target(TargetKind::Del, &a, diagnostics.span(s, e)))
+            }
+
+        rule invalid_for_target<T>() -> T
+            = second_pass() _async()? lit("for") s:position!() a:star_expressions() e:position!() {?
+                Err(diagnostics.raise_invalid_target(TargetKind::For, &a, diagnostics.span(s, e)))
+            }
+
+        rule invalid_with_item<T>() -> T
+            = second_pass() expression() lit("as") s:position!() a:expression() e:position!()
+                &(lit(",") / lit(")") / lit(":")) {?
+                    Err(diagnostics.raise_invalid_target(TargetKind::Star, &a, diagnostics.span(s, e)))
+            }
+
+        rule invalid_arguments<T>() -> T
+            = second_pass() a:args() comma() s:position!() lit("*") e:position!() {?
+                let message = "iterable argument unpacking follows keyword argument unpacking";
+                Err(diagnostics.raise(message, diagnostics.span(s, e)))
+            }
+            / second_pass() s:position!() expression() for_if_clauses() e:position!() comma()
+                (args() / expression() for_if_clauses())? {?
+                    Err(diagnostics.raise("Generator expression must be parenthesized", diagnostics.span(s, e)))
+            }
+            // A lone generator expression is a valid call.
+            / second_pass() a:args() for_if_clauses() e:position!() {?
+                match &a[..] {
+                    [_, .., last] => {
+                        let span = Span::new(last.value.span().start, diagnostics.span(e - 1, e).end);
+                        Err(diagnostics.raise("Generator expression must be parenthesized", span))
+                    }
+                    _ => Err(""),
+                }
+            }
+            / second_pass() args() comma() s:position!() expression() for_if_clauses() e:position!() {?
+                Err(diagnostics.raise("Generator expression must be parenthesized", diagnostics.span(s, e)))
+            }
+            / second_pass() a:args() comma() s:position!() args() e:position!() {?
+                let message = if a.iter().any(|arg| arg.star == "**") {
+                    "positional argument follows keyword argument unpacking"
+                } else {
+                    "positional argument follows keyword argument"
+                };
+                Err(diagnostics.raise(message, diagnostics.span(s, e)))
+            }
+
+        rule invalid_kwarg<T>() -> T
+            = second_pass() s:position!() a:(lit("True") / lit("False") / lit("None")) lit("=") e:position!() {?
+                Err(diagnostics.raise(format!("cannot assign to {}", a.text), diagnostics.span(s, e)))
+            }
+            / second_pass() s:position!() name() lit("=") expression() for_if_clauses() e:position!() {?
+                let message = "invalid syntax. Maybe you meant '==' or ':=' instead of '='?";
+                Err(diagnostics.raise(message, diagnostics.span(s, e)))
+            }
+            / second_pass() !(name() lit("=")) s:position!() expression() lit("=") e:position!() {?
+                let message = "expression cannot contain assignment, perhaps you meant \"==\"?";
+                Err(diagnostics.raise(message, diagnostics.span(s, e)))
+            }
+
+        rule invalid_double_starred_kvpairs<T>() -> T
+            = second_pass() without_invalid(<separated(<double_starred_kvpair()>, <comma()>)>) lit(",")
+                r:invalid_kvpair() { r }
+            / second_pass() r:invalid_kvpair_value() { r }
+
+        rule invalid_kvpair<T>() -> T
+            = second_pass() s:position!() without_invalid(<expression()>) e:position!() !lit(":") {?
+                // CPython points at the last character of the key.
+                let end = diagnostics.span(s, e).end;
+                let start = Position { col: end.col.saturating_sub(1), ..end };
+                Err(diagnostics.raise("':' expected after dictionary key", Span::new(start, end)))
+            }
+            / second_pass() r:invalid_kvpair_value() { r }
+
+        rule invalid_kvpair_value<T>() -> T
+            = expression() lit(":") s:position!() lit("*") bitwise_or() e:position!() {?
+                Err(diagnostics.raise("cannot use a starred expression in a dictionary value", diagnostics.span(s, e)))
+            }
+            / expression() s:position!() lit(":") &(lit("}") / lit(",")) {?
+                Err(diagnostics.raise("expression expected after dictionary key and ':'", diagnostics.span(s, s)))
+            }
+
+        rule invalid_parameters<T>() -> T
+            = second_pass() param_no_default()* invalid_parameters_helper() s:position!() param() e:position!()
+                (lit(",") / &lit(")")) {?
+                Err(diagnostics.raise("non-default argument follows default argument", diagnostics.span(s, e)))
+            }
+            / second_pass() s:position!() lit("/") lit(",") {?
+                Err(diagnostics.raise("at least one argument must precede /", diagnostics.span(s, s)))
+            }
+            / second_pass() (slash_no_default() / slash_with_default()) param_maybe_default()* s:position!() lit("/") {?
+                Err(diagnostics.raise("/ may appear only once", diagnostics.span(s, s)))
+            }
+            / second_pass() (slash_no_default() / slash_with_default())? param_maybe_default()* lit("*")
+                (lit(",") / param_no_default()) param_maybe_default()* s:position!() lit("/") {?
+                    Err(diagnostics.raise("/ must be ahead of *", diagnostics.span(s, s)))
+            }
+            / second_pass() param_maybe_default()+ lit("/") s:position!() lit("*") {?
+                Err(diagnostics.raise("expected comma between / and *", diagnostics.span(s, s)))
+            }
+
+        rule invalid_parameters_helper()
+            = slash_with_default() {}
+            / param_with_default()+ {}
+
+        rule invalid_star_etc<T>() -> T
+            = second_pass() s:position!() lit("*") (lit(")") / lit(",") (lit(")") / lit("**"))) {?
+                Err(diagnostics.raise("named arguments must follow bare *", diagnostics.span(s, s)))
+            }
+            / second_pass() lit("*") param() s:position!() lit("=") {?
+                Err(diagnostics.raise("var-positional argument cannot have default value", diagnostics.span(s, s)))
+            }
+            / second_pass() lit("*") (param_no_default() / lit(",")) param_maybe_default()* s:position!() lit("*")
+                (param_no_default() / lit(",")) {?
+                    Err(diagnostics.raise("* argument may appear only once", diagnostics.span(s, s)))
+            }
+
+        rule invalid_kwds<T>() -> T
+            = second_pass() lit("**") param() s:position!() lit("=") {?
+                Err(diagnostics.raise("var-keyword argument cannot have default value", diagnostics.span(s, s)))
+            }
+            / second_pass() lit("**") param() lit(",") s:position!() (param() {} / lit("*") {} / lit("**") {} / lit("/") {})
+                e:position!() {?
+                    Err(diagnostics.raise("arguments cannot follow var-keyword argument", diagnostics.span(s, e)))
+            }
+
+        rule invalid_lambda_parameters<T>() -> T
+            = second_pass() lambda_param_no_default()* invalid_lambda_parameters_helper() s:position!() lambda_param()
+                e:position!() (lit(",") / &lit(":")) {?
+                    Err(diagnostics.raise("non-default argument follows default argument", diagnostics.span(s, e)))
+            }
+            / second_pass() s:position!() lit("/") lit(",") {?
+                Err(diagnostics.raise("at least one argument must precede /", diagnostics.span(s, s)))
+            }
+            / second_pass() (lambda_slash_no_default() / lambda_slash_with_default()) lambda_param_maybe_default()*
+                s:position!() lit("/") {?
+                    Err(diagnostics.raise("/ may appear only once", diagnostics.span(s, s)))
+            }
+            / second_pass() (lambda_slash_no_default() / lambda_slash_with_default())? lambda_param_maybe_default()*
+                lit("*") (lit(",") / lambda_param_no_default()) lambda_param_maybe_default()* s:position!() lit("/") {?
+                    Err(diagnostics.raise("/ must be ahead of *", diagnostics.span(s, s)))
+            }
+            / second_pass() lambda_param_maybe_default()+ lit("/") s:position!() lit("*") {?
+                Err(diagnostics.raise("expected comma between / and *", diagnostics.span(s, s)))
+            }
+
+        rule invalid_lambda_parameters_helper()
+            = lambda_slash_with_default() {}
+            / lambda_param_with_default()+ {}
+
+        rule invalid_lambda_star_etc<T>() -> T
+            // CPython reports this one at the last token it read.
+            = second_pass() lit("*") (lit(":") / lit(",") (lit(":") / lit("**"))) e:position!() {?
+                Err(diagnostics.raise("named arguments must follow bare *", diagnostics.span(e - 1, e)))
+            }
+            / second_pass() lit("*") lambda_param() s:position!() lit("=") {?
+                Err(diagnostics.raise("var-positional argument cannot have default value", diagnostics.span(s, s)))
+            }
+            / second_pass() lit("*") (lambda_param_no_default() / lit(",")) lambda_param_maybe_default()*
+                s:position!() lit("*") (lambda_param_no_default() / lit(",")) {?
+                    Err(diagnostics.raise("* argument may appear only once", diagnostics.span(s, s)))
+            }
+
+        rule invalid_lambda_kwds<T>() -> T
+            = second_pass() lit("**") lambda_param() s:position!() lit("=") {?
+                Err(diagnostics.raise("var-keyword argument cannot have default value", diagnostics.span(s, s)))
+            }
+            / second_pass() lit("**") lambda_param() lit(",") s:position!()
+                (lambda_param() {} / lit("*") {} / lit("**") {} / lit("/") {}) e:position!() {?
+                    Err(diagnostics.raise("arguments cannot follow var-keyword argument", diagnostics.span(s, e)))
+            }
+
+        /// What may follow the header of the compound statement started by `kw`, given that a
+        /// valid block does not.
+        rule invalid_block_start<T>(kw: TokenRef<'a>, what: &'static str) -> T
+            = nl:tok(NL, "NEWLINE") {?
+                Err(diagnostics.raise("expected ':'", Span::from_token(nl)))
+            }
+            / lit(":") tok(NL, "NEWLINE") !tok(Indent, "INDENT") t:_ {?
+                let message = format!("expected an indented block after {} on line {}", what, kw.start.line);
+                Err(diagnostics.raise(message, Span::from_token(t)))
+            }
+
+        rule invalid_if_stmt<T>() -> T
+            = second_pass() kw:lit("if") named_expression() r:invalid_block_start(kw, "'if' statement") { r }
+
+        rule invalid_elif_stmt<T>() -> T
+            = second_pass() kw:lit("elif") named_expression() r:invalid_block_start(kw, "'elif' statement") { r }
+
+        rule invalid_else_stmt<T>() -> T
+            = second_pass() kw:lit("else") r:invalid_block_start(kw, "'else' statement") { r }
+
+        rule invalid_while_stmt<T>() -> T
+            = second_pass() kw:lit("while") named_expression() r:invalid_block_start(kw, "'while' statement") { r }
+
+        rule invalid_for_stmt<T>() -> T
+            = second_pass() _async()? kw:lit("for") star_targets() lit("in") star_expressions()
+                r:invalid_block_start(kw, "'for' statement") { r }
+
+        rule invalid_with_stmt<T>() -> T
+            = second_pass() _async()? kw:lit("with") lpar() separated_trailer(<with_item()>, <comma()>) rpar()
+                r:invalid_block_start(kw, "'with' statement") { r }
+            / second_pass() _async()? kw:lit("with") separated(<with_item()>, <comma()>)
+                r:invalid_block_start(kw, "'with' statement") { r }
+
+        rule invalid_try_stmt<T>() -> T
+            = second_pass() kw:lit("try") r:invalid_block_start(kw, "'try' statement") { r }
+            / second_pass() lit("try") lit(":") block() s:position!() !(lit("except") / lit("finally")) {?
+                Err(diagnostics.raise("expected 'except' or 'finally' block", diagnostics.span(s, s)))
+            }
+
+        rule invalid_except_stmt<T>() -> T
+            = second_pass() kw:lit("except") (expression() (lit("as") name())?)?
+                r:invalid_block_start(kw, "'except' statement") { r }
+
+        rule invalid_finally_stmt<T>() -> T
+            = second_pass() kw:lit("finally") r:invalid_block_start(kw, "'finally' statement") { r }
+
+        rule invalid_def_raw<T>() -> T
+            = second_pass() _async()? kw:lit("def") name() type_params()? lit("(") params()? lit(")") _returns()?
+                r:invalid_block_start(kw, "function definition") { r }
+
+        rule invalid_class_def_raw<T>() -> T
+            = second_pass() kw:lit("class") name() type_params()? (lpar() arguments()? rpar())?
+                r:invalid_block_start(kw, "class definition") { r }
+
+        rule invalid_type_param<T>() -> T
+            = second_pass() star:(lit("**") / lit("*")) name() s:position!() lit(":") b:expression() e:position!() {?
+                let kind = if star.text == "*" { "TypeVarTuple" } else { "ParamSpec" };
+                let what = if matches!(b, Expression::Tuple(_)) { "constraints" } else { "bound" };
+                Err(diagnostics.raise(format!("cannot use {what} with {kind}"), diagnostics.span(s, e)))
+            }
+
+        // Error recovery
+        //
+        // With `parse_module_recovering`, a statement that fails to parse is skipped up to the
+        // end of its logical line, together with any block indented under it, and the parse
+        // carries on with the next one.
+
+        rule recovering()
+            = quiet!{ {? if diagnostics.recovering() { Ok(()) } else { Err("recovery") } } }
+
+        rule error_statement() -> Statement<'a>
+            = recovering() s:position!() (skipped_block() / skipped_line() skipped_block()?) e:position!() {
+                Statement::Error(diagnostics.skipped(s, e))
+            }
+
+        rule skipped_line()
+            = !(tok(Indent, "INDENT") / tok(Dedent, "DEDENT") / tok(EndMarker, "EOF"))
+                (!(tok(NL, "NEWLINE") / tok(EndMarker, "EOF")) _)+ tok(NL, "NEWLINE")
+
+        rule skipped_block()
+            = tok(Indent, "INDENT") (skipped_block() / !tok(Dedent, "DEDENT") _)* tok(Dedent, "DEDENT")
+
         // CST helpers
         // TODO, do away with these?
 
@@ -1436,7 +1950,7 @@ parser! {
         rule dots() -> Vec<Dot>
             = ds:((dot:lit(".") { make_dot(dot) })+
                 / tok:lit("...") {
-                    vec![make_dot(tok.clone()), make_dot(tok.clone()), make_dot(tok.clone())]}
+                    vec![make_dot(tok), make_dot(tok), make_dot(tok)]}
             )+ { ds.into_iter().flatten().collect() }
 
         rule lpar() -> LeftParen<'a>
@@ -1490,6 +2004,13 @@ parser! {
 
 
 
+        /// `e`, memoized in `memo` (see `Memos`).  The rules an expression is parsed through use
+        /// this rather than `#[cache]`, which clones every result it stores.
+        rule memo<T: Clone>(e: rule<T>, memo: &Memo<T>) -> T
+            = #{|_, pos| diagnostics.memos().recall(memo, pos)}
+            / start:position!() outer:#{|_, pos| diagnostics.memos().start(memo, pos)} result:e()? end:position!()
+                r:quiet!{ {? diagnostics.memos().record(memo, start, end, outer, result) } } { r }
+
         rule traced<T>(e: rule<T>) -> T =
             &(_* {
                 #[cfg(feature = "trace")]
@@ -1514,6 +2035,12 @@ parser! {
 //Beginning of adapters
 //##################################################################################################
 
+/// `print` and `exec` were statements in Python 2; the missing-comma message would be wrong
+/// for them.
+fn is_legacy_name(expr: &Expression) -> bool {
+    matches!(expr, Expression::Name(name) if name.value == "print" || name.value == "exec")
+}
+
 fn make_module<'a>(name: &str, body: Vec<Statement<'a>>, tok: TokenRef<'a>) -> Module<'a>{
     Module {
         body,
@@ -1525,7 +2052,9 @@ fn make_module<'a>(name: &str, body: Vec<Statement<'a>>, tok: TokenRef<'a>) -> M
     }
 }
 
-fn _make_simple_statement(parts: SimpleStatementParts) -> (TokenRef, Vec<SmallStatement>, TokenRef) {
+fn _make_simple_statement(parts: SimpleStatementParts) -> (Span, Vec<SmallStatement>, TokenRef) {
+    // The newline token starts right where the last statement on the line ends.
+    let span = Span::new(parts.first_tok.start, parts.nl.start);
     let mut body = vec![];
 
     let mut current = parts.first_statement;
@@ -1535,14 +2064,15 @@ fn _make_simple_statement(parts: SimpleStatementParts) -> (TokenRef, Vec<SmallSt
     }
     body.push(current);
 
-    (parts.first_tok, body, parts.nl)
+    (span, body, parts.nl)
 }
 
 
 fn make_simple_statement_lines(parts: SimpleStatementParts) -> SimpleStatementLine {
-    let (first_tok, body, newline_tok) = _make_simple_statement(parts);
+    let (span, body, newline_tok) = _make_simple_statement(parts);
     SimpleStatementLine {
         body,
+        span,
     }
 }
 
@@ -1568,6 +2098,7 @@ fn make_function_def<'a>(
     async_tok: Option<TokenRef<'a>>,
     def_tok: TokenRef<'a>,
     name: Name<'a>,
+    type_params: Vec<TypeParam<'a>>,
     open_paren_tok: TokenRef<'a>,
     params: Option<Parameters<'a>>,
     close_paren_tok: TokenRef<'a>,
@@ -1576,16 +2107,25 @@ fn make_function_def<'a>(
     body: Suite<'a>,
 ) -> FunctionDef<'a> {
     let asynchronous = async_tok.as_ref().map(|_| Asynchronous {});
+    let span = Span::from_token(async_tok.unwrap_or(def_tok)).to(body.span());
     FunctionDef {
         name,
+        type_params,
         params: params.unwrap_or_default(),
         body,
         decorators: Default::default(),
         returns,
         asynchronous,
+        span,
     }
 }
 
+fn make_type_params<'a>(
+    (first, rest, _trailing_comma): (TypeParam<'a>, Vec<(Comma, TypeParam<'a>)>, Option<Comma>),
+) -> Vec<TypeParam<'a>> {
+    std::iter::once(first).chain(rest.into_iter().map(|(_, param)| param)).collect()
+}
+
 fn make_decorator<'a>(
     at_tok: TokenRef<'a>,
     name: Expression<'a>,
@@ -1593,11 +2133,12 @@ fn make_decorator<'a>(
 ) -> Decorator<'a> {
     Decorator {
         decorator: name,
-
+        span: Span::new(at_tok.start, newline_tok.start),
     }
 }
 
 fn make_comparison<'a>(
+    arena: &'a NodeArena,
     head: Expression<'a>,
     tail: Vec<(CompOp, Expression<'a>)>,
 ) -> Expression<'a> {
@@ -1608,10 +2149,11 @@ fn make_comparison<'a>(
             comparator: e,
         });
     }
-    Expression::Comparison(Box::new(Comparison {
-        left: Box::new(head),
+    let span = comparisons.last().map_or(head.span(), |last| head.span().to(last.comparator.span()));
+    Expression::Comparison(arena.alloc(Comparison {
+        left: arena.alloc(head),
         comparisons,
-
+        span,
     }))
 }
 
@@ -1648,6 +2190,7 @@ fn make_comparison_operator_2<'a>(
 }
 
 fn make_boolean_op<'a>(
+    arena: &'a NodeArena,
     head: Expression<'a>,
     tail: Vec<(TokenRef<'a>, Expression<'a>)>,
 ) -> Result<'a, Expression<'a>> {
@@ -1657,12 +2200,14 @@ fn make_boolean_op<'a>(
 
     let mut expr = head;
     for (tok, right) in tail {
-        expr = Expression::BooleanOperation(Box::new(BooleanOperation {
-            left: Box::new(expr),
+        let span = expr.span().to(right.span());
+        expr = Expression::BooleanOperation(arena.alloc(BooleanOperation {
+            left: arena.alloc(expr),
             operator: make_boolean_operator(tok)?,
-            right: Box::new(right),
+            right: arena.alloc(right),
             lpar: vec![],
             rpar: vec![],
+            span,
         }))
     }
     Ok(expr)
@@ -1682,20 +2227,32 @@ fn make_boolean_operator(tok: TokenRef) -> Result<BooleanOp> {
 }
 
 fn make_binary_op<'a>(
+    arena: &'a NodeArena,
     left: Expression<'a>,
     op: TokenRef<'a>,
     right: Expression<'a>,
 ) -> Result<'a, Expression<'a>> {
     let operator = make_binary_operator(op)?;
-    Ok(Expression::BinaryOperation(Box::new(BinaryOperation {
-        left: Box::new(left),
+    let span = left.span().to(right.span());
+    Ok(Expression::BinaryOperation(arena.alloc(BinaryOperation {
+        left: arena.alloc(left),
         operator,
-        right: Box::new(right),
+        right: arena.alloc(right),
         lpar: vec![],
         rpar: vec![],
+        span,
     })))
 }
 
+/// Folds `head op operand op operand ...` to the left.
+fn make_binary_ops<'a>(
+    arena: &'a NodeArena,
+    head: Expression<'a>,
+    tail: Vec<(TokenRef<'a>, Expression<'a>)>,
+) -> Result<'a, Expression<'a>> {
+    tail.into_iter().try_fold(head, |left, (op, right)| make_binary_op(arena, left, op, right))
+}
+
 fn make_binary_operator(tok: TokenRef) -> Result<BinaryOp> {
 
     match tok.text {
@@ -1742,12 +2299,13 @@ fn make_binary_operator(tok: TokenRef) -> Result<BinaryOp> {
     }
 }
 
-fn make_unary_op<'a>(op: TokenRef<'a>, tail: Expression<'a>) -> Result<'a, Expression<'a>> {
+fn make_unary_op<'a>(arena: &'a NodeArena, op: TokenRef<'a>, tail: Expression<'a>) -> Result<'a, Expression<'a>> {
     let operator = make_unary_operator(op)?;
-    Ok(Expression::UnaryOperation(Box::new(UnaryOperation {
+    let span = Span::from_token(op).to(tail.span());
+    Ok(Expression::UnaryOperation(arena.alloc(UnaryOperation {
         operator,
-        expression: Box::new(tail),
-
+        expression: arena.alloc(tail),
+        span,
     })))
 }
 
@@ -1762,9 +2320,11 @@ fn make_unary_operator(tok: TokenRef) -> Result<UnaryOp> {
     }
 }
 
-fn make_number(num: TokenRef) -> Expression {
-    crate::ast::numbers::parse_number(num.text)
-
+// peg only carries static strings for failures, so a malformed literal records its message,
+// with the literal's span, for `explain` to report.
+fn make_number<'a>(diagnostics: &Diagnostics<'a>, arena: &'a NodeArena, num: TokenRef<'a>) -> GrammarResult<Expression<'a>> {
+    crate::ast::numbers::parse_number(num.text, Span::from_token(num), arena)
+        .map_err(|err| diagnostics.raise(err.to_string(), Span::from_token(num)))
 }
 
 fn make_indented_block<'a>(
@@ -1798,23 +2358,24 @@ fn make_semicolon(tok: TokenRef) -> Semicolon {
 }
 
 fn make_simple_statement_suite(parts: SimpleStatementParts) -> Suite {
-    let (first_tok, body, newline_tok) = _make_simple_statement(parts);
+    let (span, body, newline_tok) = _make_simple_statement(parts);
 
     Suite::SimpleStatementSuite(SimpleStatementSuite {
         body,
-
+        span,
     })
 }
 
 fn make_simple_statement_line(parts: SimpleStatementParts) -> SimpleStatementLine {
-    let (first_tok, body, newline_tok) = _make_simple_statement(parts);
+    let (span, body, newline_tok) = _make_simple_statement(parts);
     SimpleStatementLine {
         body,
-
+        span,
     }
 }
 
 fn make_if<'a>(
+    arena: &'a NodeArena,
     if_tok: TokenRef<'a>,
     cond: Expression<'a>,
     colon_tok: TokenRef<'a>,
@@ -1822,12 +2383,18 @@ fn make_if<'a>(
     orelse: Option<OrElse<'a>>,
     is_elif: bool,
 ) -> If<'a> {
+    let end = match &orelse {
+        Some(OrElse::Elif(elif)) => elif.span,
+        Some(OrElse::Else(el)) => el.body.span(),
+        None => block.span(),
+    };
     If {
 
         test: cond,
         body: block,
-        orelse: orelse.map(Box::new),
+        orelse: orelse.map(|node| arena.alloc(node)),
         is_elif,
+        span: Span::from_token(if_tok).to(end),
     }
 }
 
@@ -1903,23 +2470,19 @@ fn concat<T>(a: Vec<T>, b: Vec<T>) -> Vec<T> {
 }
 
 fn make_name_or_attr<'a>(
+    arena: &'a NodeArena,
     first_tok: Name<'a>,
-    mut tail: Vec<(TokenRef<'a>, Name<'a>)>,
+    tail: Vec<(TokenRef<'a>, Name<'a>)>,
 ) -> NameOrAttribute<'a> {
-    if let Some((dot, name)) = tail.pop() {
-        let dot = make_dot(dot);
-        return NameOrAttribute::A(Box::new(Attribute {
-            attr: name,
-            value: Box::new(make_name_or_attr(first_tok, tail).into()),
-        }));
-    } else {
-        NameOrAttribute::N(Box::new(first_tok))
-    }
+    tail.into_iter().fold(NameOrAttribute::N(arena.alloc(first_tok)), |value, (dot, name)| {
+        NameOrAttribute::A(arena.alloc(make_attribute(arena, value.into(), dot, name)))
+    })
 }
 
 fn make_name(tok: TokenRef) -> Name {
     Name {
         value: tok.text,
+        span: Span::from_token(tok),
     }
 }
 
@@ -1930,12 +2493,13 @@ fn make_dot(tok: TokenRef) -> Dot {
 }
 
 fn make_import_alias<'a>(
+    arena: &'a NodeArena,
     name: NameOrAttribute<'a>,
     asname: Option<(TokenRef<'a>, Name<'a>)>,
 ) -> ImportAlias<'a> {
     ImportAlias {
         name,
-        asname: asname.map(|(x, y)| make_as_name(x, AssignTargetExpression::Name(Box::new(y)))),
+        asname: asname.map(|(x, y)| make_as_name(x, AssignTargetExpression::Name(arena.alloc(y)))),
     }
 }
 
@@ -1995,22 +2559,24 @@ fn make_rpar(tok: TokenRef) -> RightParen {
     RightParen { tok }
 }
 
-fn make_attribute<'a>(value: Expression<'a>, dot: TokenRef<'a>, attr: Name<'a>) -> Attribute<'a> {
-
+fn make_attribute<'a>(arena: &'a NodeArena, value: Expression<'a>, dot: TokenRef<'a>, attr: Name<'a>) -> Attribute<'a> {
+    let span = value.span().to(attr.span);
     Attribute {
         attr,
-        value: Box::new(value),
+        value: arena.alloc(value),
+        span,
     }
 }
 
-fn make_starred_element<'a>(star_tok: TokenRef<'a>, rest: Element<'a>) -> StarredElement<'a> {
+fn make_starred_element<'a>(arena: &'a NodeArena, star_tok: TokenRef<'a>, rest: Element<'a>) -> StarredElement<'a> {
     let value = match rest {
         Element::Simple { value, .. } => value,
         _ => panic!("Internal error while making starred element"),
     };
+    let span = Span::from_token(star_tok).to(value.span());
     StarredElement {
-        value: Box::new(value),
-
+        value: arena.alloc(value),
+        span,
     }
 }
 
@@ -2068,19 +2634,36 @@ fn make_tuple<'a>(
     rpar: Option<RightParen<'a>>,
 ) -> Tuple<'a> {
     let elements = comma_separate(first, rest, trailing_comma);
-
+    let span = match (lpar, rpar) {
+        (Some(lpar), Some(rpar)) => Span::between(lpar.tok, rpar.tok),
+        _ => elements_span(&elements),
+    };
     Tuple {
         elements,
+        span,
     }
 }
 
 fn make_tuple_from_elements<'a>(first: Element<'a>, mut rest: Vec<Element<'a>>) -> Tuple<'a> {
     rest.insert(0, first);
     Tuple {
+        span: elements_span(&rest),
         elements: rest,
     }
 }
 
+/// From the first of `elements` to the last, for a tuple written without parentheses.
+fn elements_span(elements: &[Element]) -> Span {
+    let span = |element: &Element| match element {
+        Element::Simple { value } => value.span(),
+        Element::Starred(s) => s.span,
+    };
+    match (elements.first(), elements.last()) {
+        (Some(first), Some(last)) => span(first).to(span(last)),
+        _ => Span::default(),
+    }
+}
+
 fn make_kwarg<'a>(name: Name<'a>, eq: TokenRef<'a>, value: Expression<'a>) -> Arg<'a> {
     let equal = Some(make_assign_equal(eq));
     let keyword = Some(name);
@@ -2104,22 +2687,45 @@ fn make_star_arg<'a>(star: TokenRef<'a>, expr: Expression<'a>) -> Arg<'a> {
     }
 }
 
+/// What can follow a primary expression.
+enum Trailer<'a> {
+    Attribute(TokenRef<'a>, Name<'a>),
+    GenexpCall(GeneratorExp<'a>),
+    Call(TokenRef<'a>, Vec<Arg<'a>>, TokenRef<'a>),
+    Subscript(LeftSquareBracket<'a>, Vec<SubscriptElement<'a>>, RightSquareBracket<'a>),
+}
+
+/// Applies `trailers` to `head` from left to right.
+fn make_trailers<'a>(arena: &'a NodeArena, head: Expression<'a>, trailers: Vec<Trailer<'a>>) -> Expression<'a> {
+    trailers.into_iter().fold(head, |value, trailer| match trailer {
+        Trailer::Attribute(dot, attr) => Expression::Attribute(arena.alloc(make_attribute(arena, value, dot, attr))),
+        Trailer::GenexpCall(genexp) => Expression::Call(arena.alloc(make_genexp_call(arena, value, genexp))),
+        Trailer::Call(lpar, args, rpar) => Expression::Call(arena.alloc(make_call(arena, value, lpar, args, rpar))),
+        Trailer::Subscript(lbrak, slices, rbrak) => {
+            Expression::Subscript(arena.alloc(make_subscript(arena, value, lbrak, slices, rbrak)))
+        }
+    })
+}
+
 fn make_call<'a>(
+    arena: &'a NodeArena,
     func: Expression<'a>,
     lpar_tok: TokenRef<'a>,
     args: Vec<Arg<'a>>,
     rpar_tok: TokenRef<'a>,
 ) -> Call<'a> {
 
-    let func = Box::new(func);
+    let span = func.span().to(Span::from_token(rpar_tok));
+    let func = arena.alloc(func);
 
     Call {
         func,
         args,
+        span,
     }
 }
 
-fn make_genexp_call<'a>(func: Expression<'a>, mut genexp: GeneratorExp<'a>) -> Call<'a> {
+fn make_genexp_call<'a>(arena: &'a NodeArena, func: Expression<'a>, mut genexp: GeneratorExp<'a>) -> Call<'a> {
     // func ( (genexp) )
     //      ^
     //   lpar_tok
@@ -2132,9 +2738,10 @@ fn make_genexp_call<'a>(func: Expression<'a>, mut genexp: GeneratorExp<'a>) -> C
 
 
     Call {
-        func: Box::new(func),
+        span: func.span().to(genexp.span),
+        func: arena.alloc(func),
         args: vec![Arg {
-            value: Expression::GeneratorExp(Box::new(genexp)),
+            value: Expression::GeneratorExp(arena.alloc(genexp)),
             keyword: None,
             equal: None,
             comma: None,
@@ -2182,20 +2789,29 @@ fn make_for_if<'a>(
     }
 }
 
-fn make_bare_genexp<'a>(elt: Expression<'a>, for_in: CompFor<'a>) -> GeneratorExp<'a> {
+fn make_bare_genexp<'a>(arena: &'a NodeArena, elt: Expression<'a>, for_in: CompFor<'a>) -> GeneratorExp<'a> {
     GeneratorExp {
-        elt: Box::new(elt),
-        for_in: Box::new(for_in),
+        span: elt.span().to(comp_for_end(&for_in)),
+        elt: arena.alloc(elt),
+        for_in: arena.alloc(for_in),
+    }
+}
 
+/// The span of the last expression in a run of `for` and `if` clauses.
+fn comp_for_end(for_in: &CompFor) -> Span {
+    match (&for_in.inner_for_in, for_in.ifs.last()) {
+        (Some(inner), _) => comp_for_end(inner),
+        (None, Some(last)) => last.test.span(),
+        (None, None) => for_in.iter.span(),
     }
 }
 
-fn merge_comp_fors(comp_fors: Vec<CompFor>) -> CompFor {
+fn merge_comp_fors<'a>(arena: &'a NodeArena, comp_fors: Vec<CompFor<'a>>) -> CompFor<'a> {
     let mut it = comp_fors.into_iter().rev();
     let first = it.next().expect("cant merge empty comp_fors");
 
     it.fold(first, |acc, curr| CompFor {
-        inner_for_in: Some(Box::new(acc)),
+        inner_for_in: Some(arena.alloc(acc)),
         ..curr
     })
 }
@@ -2225,32 +2841,35 @@ fn make_right_brace(tok: TokenRef) -> RightCurlyBrace {
 }
 
 fn make_list_comp<'a>(
+    arena: &'a NodeArena,
     lbracket: LeftSquareBracket<'a>,
     elt: Expression<'a>,
     for_in: CompFor<'a>,
     rbracket: RightSquareBracket<'a>,
 ) -> ListComp<'a> {
     ListComp {
-        elt: Box::new(elt),
-        for_in: Box::new(for_in),
-
+        elt: arena.alloc(elt),
+        for_in: arena.alloc(for_in),
+        span: Span::between(lbracket.tok, rbracket.tok),
     }
 }
 
 fn make_set_comp<'a>(
+    arena: &'a NodeArena,
     lbrace: LeftCurlyBrace<'a>,
     elt: Expression<'a>,
     for_in: CompFor<'a>,
     rbrace: RightCurlyBrace<'a>,
 ) -> SetComp<'a> {
     SetComp {
-        elt: Box::new(elt),
-        for_in: Box::new(for_in),
-
+        elt: arena.alloc(elt),
+        for_in: arena.alloc(for_in),
+        span: Span::between(lbrace.tok, rbrace.tok),
     }
 }
 
 fn make_dict_comp<'a>(
+    arena: &'a NodeArena,
     lbrace: LeftCurlyBrace<'a>,
     kvpair: (Expression<'a>, TokenRef<'a>, Expression<'a>),
     for_in: CompFor<'a>,
@@ -2259,11 +2878,10 @@ fn make_dict_comp<'a>(
     let (key, colon_tok, value) = kvpair;
 
     DictComp {
-        key: Box::new(key),
-        value: Box::new(value),
-        for_in: Box::new(for_in),
-
-
+        key: arena.alloc(key),
+        value: arena.alloc(value),
+        for_in: arena.alloc(for_in),
+        span: Span::between(lbrace.tok, rbrace.tok),
     }
 }
 
@@ -2274,7 +2892,7 @@ fn make_list<'a>(
 ) -> List<'a> {
     List {
         elements,
-
+        span: Span::between(lbracket.tok, rbracket.tok),
     }
 }
 
@@ -2285,6 +2903,7 @@ fn make_set<'a>(
 ) -> Set<'a> {
     Set {
         elements,
+        span: Span::between(lbrace.tok, rbrace.tok),
     }
 }
 
@@ -2316,6 +2935,7 @@ fn make_dict<'a>(
 ) -> Dict<'a> {
     Dict {
         elements,
+        span: Span::between(lbrace.tok, rbrace.tok),
     }
 }
 
@@ -2356,8 +2976,8 @@ fn make_double_starred_element<'a>(
     }
 }
 
-fn make_index(value: Expression) -> BaseSlice {
-    BaseSlice::Index(Box::new(Index { value }))
+fn make_index<'a>(arena: &'a NodeArena, value: Expression<'a>) -> BaseSlice<'a> {
+    BaseSlice::Index(arena.alloc(Index { value }))
 }
 
 fn make_colon(tok: TokenRef) -> Colon {
@@ -2366,6 +2986,7 @@ fn make_colon(tok: TokenRef) -> Colon {
 }
 
 fn make_slice<'a>(
+    arena: &'a NodeArena,
     lower: Option<Expression<'a>>,
     first_colon: TokenRef<'a>,
     upper: Option<Expression<'a>>,
@@ -2377,7 +2998,7 @@ fn make_slice<'a>(
     } else {
         (None, None)
     };
-    BaseSlice::Slice(Box::new(Slice {
+    BaseSlice::Slice(arena.alloc(Slice {
         lower,
         upper,
         step,
@@ -2404,20 +3025,22 @@ fn make_slices<'a>(
 }
 
 fn make_subscript<'a>(
+    arena: &'a NodeArena,
     value: Expression<'a>,
     lbracket: LeftSquareBracket<'a>,
     slice: Vec<SubscriptElement<'a>>,
     rbracket: RightSquareBracket<'a>,
 ) -> Subscript<'a> {
-    let lbracket_tok = lbracket.tok.clone();
+    let span = value.span().to(Span::from_token(rbracket.tok));
     Subscript {
-        value: Box::new(value),
+        value: arena.alloc(value),
         slice,
-
+        span,
     }
 }
 
 fn make_ifexp<'a>(
+    arena: &'a NodeArena,
     body: Expression<'a>,
     if_tok: TokenRef<'a>,
     test: Expression<'a>,
@@ -2425,10 +3048,10 @@ fn make_ifexp<'a>(
     orelse: Expression<'a>,
 ) -> IfExp<'a> {
     IfExp {
-        test: Box::new(test),
-        body: Box::new(body),
-        orelse: Box::new(orelse),
-
+        span: body.span().to(orelse.span()),
+        test: arena.alloc(test),
+        body: arena.alloc(body),
+        orelse: arena.alloc(orelse),
     }
 }
 
@@ -2441,6 +3064,7 @@ fn add_arguments_trailing_comma<'a>(
 }
 
 fn make_lambda<'a>(
+    arena: &'a NodeArena,
     lambda_tok: TokenRef<'a>,
     params: Parameters<'a>,
     colon_tok: TokenRef<'a>,
@@ -2448,9 +3072,9 @@ fn make_lambda<'a>(
 ) -> Lambda<'a> {
     let colon = make_colon(colon_tok);
     Lambda {
-        params: Box::new(params),
-        body: Box::new(expr),
-
+        span: Span::from_token(lambda_tok).to(expr.span()),
+        params: arena.alloc(params),
+        body: arena.alloc(expr),
     }
 }
 
@@ -2463,19 +3087,21 @@ fn make_annotation<'a>(tok: TokenRef<'a>, ann: Expression<'a>) -> Annotation<'a>
 
 
 fn make_yield<'a>(
+    arena: &'a NodeArena,
     yield_tok: TokenRef<'a>,
     f: Option<TokenRef<'a>>,
     e: Option<Expression<'a>>,
 ) -> Yield<'a> {
+    let span = e.as_ref().map_or(Span::from_token(yield_tok), |e| Span::from_token(yield_tok).to(e.span()));
     let value = match (f, e) {
         (None, None) => None,
-        (Some(f), Some(e)) => Some(YieldValue::From(Box::new(make_from(f, e)))),
-        (None, Some(e)) => Some(YieldValue::Expression(Box::new(e))),
+        (Some(f), Some(e)) => Some(YieldValue::From(arena.alloc(make_from(f, e)))),
+        (None, Some(e)) => Some(YieldValue::Expression(arena.alloc(e))),
         _ => panic!("yield from without expression"),
     };
     Yield {
-        value: value.map(Box::new),
-
+        value: value.map(|node| arena.alloc(node)),
+        span,
     }
 }
 
@@ -2487,9 +3113,10 @@ fn make_from<'a>(tok: TokenRef<'a>, e: Expression<'a>) -> From<'a> {
 }
 
 fn make_return<'a>(return_tok: TokenRef<'a>, value: Option<Expression<'a>>) -> Return<'a> {
+    let end = value.as_ref().map_or(return_tok.end, |value| value.span().end);
     Return {
         value,
-
+        span: Span::new(return_tok.start, end),
     }
 }
 
@@ -2556,12 +3183,13 @@ fn make_nonlocal<'a>(
             name,
         })
         .collect();
+    let span = Span::new(tok.start, last.span.end);
     names.push(NameItem {
         name: last,
     });
     Nonlocal {
         names,
-
+        span,
     }
 }
 
@@ -2578,6 +3206,7 @@ fn make_for<'a>(
 ) -> For<'a> {
     let asynchronous = async_tok.as_ref().map(|_| Asynchronous {
     });
+    let end = orelse.as_ref().map(|e| e.body.span()).unwrap_or_else(|| body.span());
 
     For {
         target,
@@ -2585,6 +3214,7 @@ fn make_for<'a>(
         body,
         orelse,
         asynchronous,
+        span: Span::from_token(async_tok.unwrap_or(for_tok)).to(end),
     }
 }
 
@@ -2595,24 +3225,26 @@ fn make_while<'a>(
     body: Suite<'a>,
     orelse: Option<Else<'a>>,
 ) -> While<'a> {
+    let end = orelse.as_ref().map(|e| e.body.span()).unwrap_or_else(|| body.span());
     While {
         test,
         body,
         orelse,
-
+        span: Span::from_token(while_tok).to(end),
     }
 }
 
-fn make_await<'a>(await_tok: TokenRef<'a>, expression: Expression<'a>) -> Await<'a> {
+fn make_await<'a>(arena: &'a NodeArena, await_tok: TokenRef<'a>, expression: Expression<'a>) -> Await<'a> {
     Await {
-        expression: Box::new(expression),
-
+        span: Span::from_token(await_tok).to(expression.span()),
+        expression: arena.alloc(expression),
     }
 }
 
 fn make_class_def<'a>(
     class_tok: TokenRef<'a>,
     name: Name<'a>,
+    type_params: Vec<TypeParam<'a>>,
     args: Option<(LeftParen<'a>, Option<Vec<Arg<'a>>>, RightParen<'a>)>,
     colon_tok: TokenRef<'a>,
     body: Suite<'a>,
@@ -2642,31 +3274,41 @@ fn make_class_def<'a>(
             }
         }
     }
+    let span = Span::from_token(class_tok).to(body.span());
     Ok(ClassDef {
         name,
+        type_params,
         body,
         bases,
         keywords,
         decorators: vec![],
-
+        span,
     })
 }
 
 fn make_string(tok: TokenRef) -> String {
     String::Simple(SimpleString {
         value: tok.text,
-
+        span: Span::from_token(tok),
     })
 }
 
-fn make_strings<'a>(s: Vec<(String<'a>, TokenRef<'a>)>) -> String<'a> {
+fn make_string_expression<'a>(arena: &'a NodeArena, s: String<'a>) -> Expression<'a> {
+    match s {
+        String::Simple(s) => Expression::SimpleString(arena.alloc(s)),
+        String::Concatenated(s) => Expression::ConcatenatedString(arena.alloc(s)),
+        String::Formatted(s) => Expression::FormattedString(arena.alloc(s)),
+    }
+}
+
+fn make_strings<'a>(arena: &'a NodeArena, s: Vec<(String<'a>, TokenRef<'a>)>) -> String<'a> {
     let mut strings = s.into_iter().rev();
     let (first, _) = strings.next().expect("no strings to make a string of");
     strings.fold(first, |acc, (str, tok)| {
         let ret: String<'a> = String::Concatenated(ConcatenatedString {
-            left: Box::new(str),
-            right: Box::new(acc),
-
+            span: str.span().to(acc.span()),
+            left: arena.alloc(str),
+            right: arena.alloc(acc),
         });
         ret
     })
@@ -2712,15 +3354,15 @@ fn make_fstring_expression<'a>(
 }
 
 fn make_fstring<'a>(
-    start: &'a str,
+    start: TokenRef<'a>,
     parts: Vec<FormattedStringContent<'a>>,
-    end: &'a str,
+    end: TokenRef<'a>,
 ) -> FormattedString<'a> {
     FormattedString {
-        start,
+        start: start.text,
         parts,
-        end,
-
+        end: end.text,
+        span: Span::between(start, end),
     }
 }
 
@@ -2736,6 +3378,7 @@ fn make_finally<'a>(
 }
 
 fn make_except<'a>(
+    arena: &'a NodeArena,
     except_tok: TokenRef<'a>,
     exp: Option<Expression<'a>>,
     as_: Option<(TokenRef<'a>, Name<'a>)>,
@@ -2743,7 +3386,7 @@ fn make_except<'a>(
     body: Suite<'a>,
 ) -> ExceptHandler<'a> {
     // TODO: AsName should come from outside
-    let name = as_.map(|(x, y)| make_as_name(x, AssignTargetExpression::Name(Box::new(y))));
+    let name = as_.map(|(x, y)| make_as_name(x, AssignTargetExpression::Name(arena.alloc(y))));
     ExceptHandler {
         body,
         r#type: exp,
@@ -2753,6 +3396,7 @@ fn make_except<'a>(
 }
 
 fn make_except_star<'a>(
+    arena: &'a NodeArena,
     except_tok: TokenRef<'a>,
     star_tok: TokenRef<'a>,
     exp: Expression<'a>,
@@ -2761,7 +3405,7 @@ fn make_except_star<'a>(
     body: Suite<'a>,
 ) -> ExceptStarHandler<'a> {
     // TODO: AsName should come from outside
-    let name = as_.map(|(x, y)| make_as_name(x, AssignTargetExpression::Name(Box::new(y))));
+    let name = as_.map(|(x, y)| make_as_name(x, AssignTargetExpression::Name(arena.alloc(y))));
     ExceptStarHandler {
         body,
         r#type: exp,
@@ -2777,12 +3421,13 @@ fn make_try<'a>(
     orelse: Option<Else<'a>>,
     finalbody: Option<Finally<'a>>,
 ) -> Try<'a> {
+    let end = try_end(&body, handlers.last().map(|h| &h.body), &orelse, &finalbody);
     Try {
         body,
         handlers,
         orelse,
         finalbody,
-
+        span: Span::from_token(try_tok).to(end),
     }
 }
 
@@ -2793,12 +3438,30 @@ fn make_try_star<'a>(
     orelse: Option<Else<'a>>,
     finalbody: Option<Finally<'a>>,
 ) -> TryStar<'a> {
+    let end = try_end(&body, handlers.last().map(|h| &h.body), &orelse, &finalbody);
     TryStar {
         body,
         handlers,
         orelse,
         finalbody,
-
+        span: Span::from_token(try_tok).to(end),
+    }
+}
+
+fn try_end<'a>(
+    body: &Suite<'a>,
+    last_handler: Option<&Suite<'a>>,
+    orelse: &Option<Else<'a>>,
+    finalbody: &Option<Finally<'a>>,
+) -> Span {
+    if let Some(f) = finalbody {
+        f.body.span()
+    } else if let Some(e) = orelse {
+        e.body.span()
+    } else if let Some(h) = last_handler {
+        h.span()
+    } else {
+        body.span()
     }
 }
 
@@ -2861,11 +3524,12 @@ fn make_with<'a>(
     body: Suite<'a>,
 ) -> With<'a> {
     let asynchronous = async_tok.as_ref().map(|_| Asynchronous {});
+    let span = Span::from_token(async_tok.unwrap_or(with_tok)).to(body.span());
     With {
         items,
         body,
         asynchronous,
-
+        span,
     }
 }
 
@@ -2877,21 +3541,26 @@ fn make_del<'a>(tok: TokenRef<'a>, target: DelTargetExpression<'a>) -> Del<'a> {
 }
 
 fn make_del_tuple<'a>(
+    arena: &'a NodeArena,
     lpar: Option<LeftParen<'a>>,
     elements: Vec<Element<'a>>,
     rpar: Option<RightParen<'a>>,
 ) -> DelTargetExpression<'a> {
-    DelTargetExpression::Tuple(Box::new(Tuple {
+    let span = match (lpar, rpar) {
+        (Some(lpar), Some(rpar)) => Span::between(lpar.tok, rpar.tok),
+        _ => elements_span(&elements),
+    };
+    DelTargetExpression::Tuple(arena.alloc(Tuple {
         elements,
-
+        span,
     }))
 }
 
-fn make_named_expr<'a>(name: Name<'a>, tok: TokenRef<'a>, expr: Expression<'a>) -> NamedExpr<'a> {
+fn make_named_expr<'a>(arena: &'a NodeArena, name: Name<'a>, tok: TokenRef<'a>, expr: Expression<'a>) -> NamedExpr<'a> {
     NamedExpr {
-        target: Box::new(Expression::Name(Box::new(name))),
-        value: Box::new(expr),
-
+        span: name.span.to(expr.span()),
+        target: arena.alloc(Expression::Name(arena.alloc(name))),
+        value: arena.alloc(expr),
     }
 }
 
@@ -2903,10 +3572,11 @@ fn make_match<'a>(
     cases: Vec<MatchCase<'a>>,
     dedent_tok: TokenRef<'a>,
 ) -> Match<'a> {
+    let end = cases.last().map(|c| c.body.span()).unwrap_or_else(|| Span::from_token(dedent_tok));
     Match {
         subject,
         cases,
-
+        span: Span::from_token(match_tok).to(end),
     }
 }
 
@@ -2949,11 +3619,12 @@ fn make_list_pattern<'a>(
 }
 
 fn make_as_pattern<'a>(
+    arena: &'a NodeArena,
     pattern: Option<MatchPattern<'a>>,
     as_tok: Option<TokenRef<'a>>,
     name: Option<Name<'a>>,
 ) -> MatchPattern<'a> {
-    MatchPattern::As(Box::new(MatchAs {
+    MatchPattern::As(arena.alloc(MatchAs {
         pattern,
         name,
 
@@ -2965,6 +3636,7 @@ fn make_bit_or(tok: TokenRef) -> BitOr {
 }
 
 fn make_or_pattern<'a>(
+    arena: &'a NodeArena,
     first: MatchPattern<'a>,
     rest: Vec<(TokenRef<'a>, MatchPattern<'a>)>,
 ) -> MatchPattern<'a> {
@@ -2986,21 +3658,21 @@ fn make_or_pattern<'a>(
         pattern: current,
         separator: None,
     });
-    MatchPattern::Or(Box::new(MatchOr {
+    MatchPattern::Or(arena.alloc(MatchOr {
         patterns,
 
     }))
 }
 
-fn ensure_real_number(tok: TokenRef) -> GrammarResult<Expression> {
-    match make_number(tok) {
+fn ensure_real_number<'a>(diagnostics: &Diagnostics<'a>, arena: &'a NodeArena, tok: TokenRef<'a>) -> GrammarResult<Expression<'a>> {
+    match make_number(diagnostics, arena, tok)? {
         e @ (Expression::Integer(_) | Expression::Float(_)) => Ok(e),
         _ => Err("real number"),
     }
 }
 
-fn ensure_imaginary_number(tok: TokenRef) -> GrammarResult<Expression> {
-    match make_number(tok) {
+fn ensure_imaginary_number<'a>(diagnostics: &Diagnostics<'a>, arena: &'a NodeArena, tok: TokenRef<'a>) -> GrammarResult<Expression<'a>> {
+    match make_number(diagnostics, arena, tok)? {
         e @ Expression::Imaginary(_) => Ok(e),
         _ => Err("imaginary number"),
     }
@@ -3122,11 +3794,259 @@ fn make_match_keyword_element<'a>(
 
 #[cfg(test)]
 mod tests {
-    use crate::parser::grammar::{python, TokenRef, TokVec};
+    use crate::ast::{CompoundStatement, NodeArena, SmallStatement, Statement};
+    use crate::parser::{parse_module, python, Diagnostics, TokenArena};
     use crate::tokenizer::Token;
-    use crate::tokenizer::TType::{Op, Number};
-    use std::rc::Rc;
+    use crate::tokenizer::TType::{self, Op, Number, Name, NL, Indent, Dedent, EndMarker};
+
+    use std::time::{Duration, Instant};
+
+    /// A name for a kind of source, and a generator for it at a given depth or length.
+    type Shape = (&'static str, fn(usize) -> String);
+
+    /// Appends the tokens of one line, then its NL token.
+    fn push_line<'a>(arena: &mut TokenArena<'a>, line: &mut usize, parts: &[(TType, &'a str)]) {
+        for (ttype, text) in parts {
+            arena.push(Token::quick(*ttype, *line, 0, 0, text));
+        }
+        arena.push(Token::quick(NL, *line, 0, 0, "\n"));
+        *line += 1;
+    }
+
+    /// Generates the tokens for a module made of `count` small functions, each with a handful
+    /// of assignments, calls, attribute lookups and subscripts.
+    fn generate_module<'a>(count: usize, numbers: &'a [String]) -> TokenArena<'a> {
+        let mut arena = TokenArena::with_capacity(count * 64);
+        let mut line = 1;
+
+        for idx in 0..count {
+            let num = numbers[idx].as_str();
+            push_line(&mut arena, &mut line, &[
+                (Name, "def"), (Name, "func"), (Op, "("), (Name, "a"), (Op, ","), (Name, "b"),
+                (Op, "="), (Number, num), (Op, ")"), (Op, ":"),
+            ]);
+            arena.push(Token::quick(Indent, line, 0, 4, "    "));
+            push_line(&mut arena, &mut line, &[
+                (Name, "x"), (Op, "="), (Op, "("), (Name, "a"), (Op, "+"), (Number, num), (Op, ")"),
+                (Op, "*"), (Name, "b"), (Op, "."), (Name, "c"), (Op, "["), (Number, num), (Op, "]"),
+            ]);
+            push_line(&mut arena, &mut line, &[
+                (Name, "y"), (Op, "="), (Name, "call"), (Op, "("), (Name, "x"), (Op, ","),
+                (Name, "key"), (Op, "="), (Op, "["), (Number, num), (Op, ","), (Name, "a"), (Op, "]"), (Op, ")"),
+            ]);
+            push_line(&mut arena, &mut line, &[
+                (Name, "if"), (Name, "x"), (Op, "<"), (Name, "y"), (Name, "and"), (Name, "not"), (Name, "b"),
+                (Op, ":"), (Name, "return"), (Name, "x"),
+            ]);
+            push_line(&mut arena, &mut line, &[(Name, "return"), (Name, "y"), (Op, "-"), (Number, num)]);
+            arena.push(Token::quick(Dedent, line, 0, 0, ""));
+        }
+        arena.push(Token::quick(EndMarker, line, 0, 0, ""));
+        arena
+    }
+
+    #[test]
+    fn parses_from_token_arena() {
+        let numbers: Vec<String> = (0..3).map(|n| n.to_string()).collect();
+        let arena = generate_module(3, &numbers);
+        let module = parse_module(&arena, "__test__").expect("parse");
+        assert_eq!(module.body.len(), 3);
+        assert!(module.validate().is_empty());
+    }
+
+    /// The fastest of a few parses of `tokens`, each with its nodes in a fresh `nodes()` and
+    /// timed until the module and the nodes are freed.
+    fn time_parse(tokens: &TokenArena, nodes: fn() -> NodeArena) -> Duration {
+        let input = tokens.tok_vec();
+        (0..5)
+            .map(|_| {
+                let started = Instant::now();
+                {
+                    let nodes = nodes();
+                    let diagnostics = Diagnostics::disabled();
+                    let module = python::file(&input, &diagnostics, &nodes, "__bench__").expect("parse");
+                    assert!(!module.body.is_empty());
+                }
+                started.elapsed()
+            })
+            .min()
+            .expect("parsed")
+    }
+
+    /// Compares allocating the nodes in the arena with boxing each one, as parses did before.
+    /// Run with `cargo test --release -- --ignored --nocapture bench_large_generated_module`
+    #[test]
+    #[ignore]
+    fn bench_large_generated_module() {
+        let count = 20_000;
+        let numbers: Vec<String> = (0..count).map(|n| n.to_string()).collect();
+
+        let started = Instant::now();
+        let arena = generate_module(count, &numbers);
+        let generated = started.elapsed();
+
+        let module = parse_module(&arena, "__bench__").expect("parse");
+        assert_eq!(module.body.len(), count);
+        let in_arena = time_parse(&arena, NodeArena::new);
+        let boxed = time_parse(&arena, NodeArena::boxing);
+        println!(
+            "{} tokens ({} KiB of token storage) generated in {:?}, parsed in {:?} with the nodes in the arena \
+             and in {:?} with each node boxed ({:.2}x)",
+            arena.len(),
+            arena.len() * std::mem::size_of::<Token>() / 1024,
+            generated,
+            in_arena,
+            boxed,
+            boxed.as_secs_f64() / in_arena.as_secs_f64()
+        );
+    }
 
+    #[test]
+    fn folds_operator_and_trailer_chains_left() {
+        let source = "(a - b) - c - d\na - (b - c)\n2 ** 3 ** 4\nx.y(z)[0].w = f(a)(b)\n";
+        let arena = TokenArena::from_source(source).expect("tokenize");
+        let module = parse_module(&arena, "__test__").expect("parse");
+        assert_eq!(module.to_source(), "a - b - c - d\na - (b - c)\n2 ** 3 ** 4\nx.y(z)[0].w = f(a)(b)\n");
+    }
+
+    #[test]
+    fn records_expression_spans() {
+        // Each expression is the value of `x = ...`, and its span should cover exactly the
+        // text given, which leaves out surrounding parentheses as CPython's `ast` does.
+        let cases = [
+            ("a.b(c)[d]", "a.b(c)[d]"),
+            ("(a + b)", "a + b"),
+            ("1, 2", "1, 2"),
+            ("(1, 2)", "(1, 2)"),
+            ("-x ** 2", "-x ** 2"),
+            ("not a < b", "not a < b"),
+            ("a and b or c", "a and b or c"),
+            ("b if c else d", "b if c else d"),
+            ("lambda a: a + 1", "lambda a: a + 1"),
+            ("f(y for y in z if y)", "f(y for y in z if y)"),
+            ("[y for y in z]", "[y for y in z]"),
+            ("{**a, 'k': 1}", "{**a, 'k': 1}"),
+            ("'a' 'b'", "'a' 'b'"),
+            ("f'{y}!'", "f'{y}!'"),
+            ("(y := 5)", "y := 5"),
+            ("...", "..."),
+        ];
+        for (expr, expected) in cases {
+            let source = format!("x = {expr}\n");
+            let arena = TokenArena::from_source(&source).expect("tokenize");
+            let module = parse_module(&arena, "__test__").expect("parse");
+            let Statement::Simple(line) = &module.body[0] else { panic!("expected a simple statement") };
+            let SmallStatement::Assign(assign) = &line.body[0] else { panic!("expected an assignment") };
+            let span = assign.value.span();
+            assert_eq!((span.start.line, span.end.line), (1, 1));
+            assert_eq!(&source[span.start.col..span.end.col], expected);
+        }
+    }
+
+    /// Cases from CPython's `test_type_params`.
+    #[test]
+    fn parses_type_params() {
+        let source = "\
+type TA1 = int
+type TA2[A, B: int, *C, **D] = dict[A, B]
+type TA3[T = int, *Ts = *tuple[int], **P = [int, str]] = T
+class ClassA[T: (int, str), U: str](Base, metaclass=Meta):
+    def method[V](self, x: T) -> V:
+        pass
+async def func1[**A](*args: A.args) -> None:
+    pass
+type = 1
+type(x)
+";
+        let arena = TokenArena::from_source(source).expect("tokenize");
+        let module = parse_module(&arena, "__test__").expect("parse");
+        assert_eq!(module.to_source(), source);
+        assert!(module.validate().is_empty());
+
+        let Statement::Compound(CompoundStatement::ClassDef(class)) = &module.body[3] else { panic!("expected a class") };
+        let names: Vec<&str> = class.type_params.iter().map(|param| param.name.value).collect();
+        assert_eq!(names, ["T", "U"]);
+        assert_eq!(class.type_params[0].constraints().map(|c| c.len()), Some(2));
+        assert_eq!(class.type_params[1].constraints(), None);
+
+        let error = |source: &str| {
+            let arena = TokenArena::from_source(source).expect("tokenize");
+            let result = parse_module(&arena, "__test__");
+            match result {
+                Ok(module) => module.validate().iter().map(ToString::to_string).collect::<Vec<_>>().join("; "),
+                Err(err) => err.to_string(),
+            }
+        };
+        assert_eq!(error("type TA1[*Ts: int] = int\n"), "cannot use bound with TypeVarTuple (line 1)");
+        assert_eq!(error("def f[**P: (int, str)](): pass\n"), "cannot use constraints with ParamSpec (line 1)");
+        assert_eq!(error("class C[T, T]: pass\n"), "duplicate type parameter 'T' (line 1)");
+        assert_eq!(
+            error("def f[T = int, U](): pass\n"),
+            "non-default type parameter 'U' follows default type parameter (line 1)"
+        );
+    }
+
+    /// Parse time of deeply nested brackets and long chains, at `n` and `2n`.  Each should
+    /// roughly double, not quadruple.
+    ///
+    /// Run with `cargo test --release -- --ignored --nocapture bench_nesting_and_chains`
+    #[test]
+    #[ignore]
+    fn bench_nesting_and_chains() {
+        let shapes: [Shape; 8] = [
+            ("nested parentheses", |n| format!("x = {}1{}\n", "(".repeat(n), ")".repeat(n))),
+            ("nested lists", |n| format!("x = {}1{}\n", "[".repeat(n), "]".repeat(n))),
+            ("nested calls", |n| format!("x = {}1{}\n", "f(".repeat(n), ")".repeat(n))),
+            ("attribute chain", |n| format!("x = a{}\n", ".b".repeat(n))),
+            ("attribute target", |n| format!("a{} = 1\n", ".b".repeat(n))),
+            ("subscript chain", |n| format!("x = a{}\n", "[0]".repeat(n))),
+            ("call chain", |n| format!("x = f{}\n", "(a)".repeat(n))),
+            ("long sum", |n| format!("x = 1{}\n", " + 1".repeat(n))),
+        ];
+        for (shape, generate) in shapes {
+            let mut timings = vec![];
+            for n in [90, 180] {
+                let source = generate(n);
+                let arena = TokenArena::from_source(&source).expect("tokenize");
+                let started = Instant::now();
+                parse_module(&arena, "__bench__").expect("parse");
+                timings.push(started.elapsed());
+            }
+            println!("{shape:>20}: {:?} at n = 90, {:?} at n = 180", timings[0], timings[1]);
+        }
+    }
+
+    /// The memoized rules parsed and the tokens their copied results cover, for a parse of
+    /// `generate(n)`.
+    fn parse_work(generate: fn(usize) -> String, n: usize) -> (usize, usize) {
+        let source = generate(n);
+        let arena = TokenArena::from_source(&source).expect("tokenize");
+        let input = arena.tok_vec();
+        let diagnostics = Diagnostics::disabled();
+        let result = python::file(&input, &diagnostics, arena.nodes(), "__test__");
+        result.expect("parse");
+        (diagnostics.memos().parses(), diagnostics.memos().copied())
+    }
+
+    #[test]
+    fn nesting_parses_in_linear_time() {
+        let shapes: [Shape; 4] = [
+            ("nested lists", |n| format!("x = {}1{}\n", "[".repeat(n), "]".repeat(n))),
+            ("nested calls", |n| format!("x = {}1{}\n", "f(".repeat(n), ")".repeat(n))),
+            ("nested subscripts", |n| format!("x = {}1{}\n", "a[".repeat(n), "]".repeat(n))),
+            ("nested comparisons", |n| format!("x = {}1{}\n", "(1 < ".repeat(n), ")".repeat(n))),
+        ];
+        // Deep nesting recurses deeply, more so in a debug build.
+        let check = std::thread::Builder::new().stack_size(256 << 20).spawn(move || {
+            for (shape, generate) in shapes {
+                let (short, long) = (parse_work(generate, 40), parse_work(generate, 160));
+                // Four times the depth is about four times the work; quadratic would be 16.
+                assert!(long.0 <= 5 * short.0, "{shape}: {} rules parsed at depth 40, {} at 160", short.0, long.0);
+                assert!(long.1 <= 5 * short.1, "{shape}: {} tokens copied at depth 40, {} at 160", short.1, long.1);
+            }
+        }).expect("spawn");
+        check.join().unwrap();
+    }
 
     #[test]
     fn basic() {
@@ -0,0 +1,124 @@
+//! Memo tables for the rules an expression is parsed through.
+//!
+//! peg's `#[cache]` stores a clone of every result it memoizes.  Each level of a nested list
+//! or call goes through several cached rules, so every level cloned the whole subtree below
+//! it and deep nesting parsed in quadratic time.
+//!
+//! A `Memo` hands the first result for a token to the caller without keeping it, and only
+//! notes that the rule matched there.  When the grammar backtracks and asks for that result
+//! again, the rule is parsed a second time and a copy is kept for any later requests.
+//!
+//! The rules below it are parsed again as part of that, and must not be copied just for
+//! being asked for a second time, or each level would clone its subtree again.  So every
+//! parse that keeps its result starts a new generation, and a result is only kept when it is
+//! asked for twice within the same one.  Anything older is parsed again without a copy.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+use peg::RuleResult;
+
+use crate::ast::{AssignTargetExpression, Expression};
+
+enum Entry<T> {
+    /// Matched in the given generation and handed to the caller without keeping a copy.
+    Parsed(usize),
+    /// The end position and result, kept once the result was asked for again.
+    Matched(usize, T),
+    Failed,
+}
+
+pub(crate) struct Memo<T> {
+    entries: RefCell<HashMap<usize, Entry<T>>>,
+}
+
+impl<T> Default for Memo<T> {
+    fn default() -> Self {
+        Self { entries: RefCell::new(HashMap::new()) }
+    }
+}
+
+/// The memo tables of one parse, one per memoized rule.
+#[derive(Default)]
+pub(crate) struct Memos<'a> {
+    generation: Cell<usize>,
+    generations: Cell<usize>,
+    /// How many times a memoized rule was parsed, and how many tokens the results copied in
+    /// and out of the tables cover: the work memoizing does, counted rather than timed.
+    parses: Cell<usize>,
+    copied: Cell<usize>,
+    pub(crate) expression: Memo<Expression<'a>>,
+    pub(crate) star_expression: Memo<Expression<'a>>,
+    pub(crate) disjunction: Memo<Expression<'a>>,
+    pub(crate) bitwise_or: Memo<Expression<'a>>,
+    pub(crate) factor: Memo<Expression<'a>>,
+    pub(crate) primary: Memo<Expression<'a>>,
+    pub(crate) t_primary: Memo<Expression<'a>>,
+    pub(crate) star_target: Memo<AssignTargetExpression<'a>>,
+    pub(crate) target_with_star_atom: Memo<AssignTargetExpression<'a>>,
+}
+
+impl<'a> Memos<'a> {
+    /// The result kept in `memo` for `pos`, if there is one.
+    pub(crate) fn recall<T: Clone>(&self, memo: &Memo<T>, pos: usize) -> RuleResult<T> {
+        match memo.entries.borrow().get(&pos) {
+            Some(Entry::Matched(end, result)) => {
+                self.copied.set(self.copied.get() + (end - pos));
+                RuleResult::Matched(*end, result.clone())
+            }
+            _ => RuleResult::Failed,
+        }
+    }
+
+    /// Starts parsing the rule of `memo` at `pos`, unless it is known to fail there.  If the
+    /// result is to be kept, a new generation starts and the current one is matched, to be
+    /// passed on to `record`.
+    pub(crate) fn start<T>(&self, memo: &Memo<T>, pos: usize) -> RuleResult<Option<usize>> {
+        let entry = memo.entries.borrow();
+        let entry = entry.get(&pos);
+        if !matches!(entry, Some(Entry::Failed)) {
+            self.parses.set(self.parses.get() + 1);
+        }
+        match entry {
+            Some(Entry::Failed) => RuleResult::Failed,
+            Some(Entry::Parsed(generation)) if *generation == self.generation.get() => {
+                self.generations.set(self.generations.get() + 1);
+                RuleResult::Matched(pos, Some(self.generation.replace(self.generations.get())))
+            }
+            _ => RuleResult::Matched(pos, None),
+        }
+    }
+
+    /// Notes the `result` of parsing the rule of `memo` from `start` to `end` and passes it on.
+    /// `outer` is what `start` matched.
+    pub(crate) fn record<T: Clone>(
+        &self,
+        memo: &Memo<T>,
+        start: usize,
+        end: usize,
+        outer: Option<usize>,
+        result: Option<T>,
+    ) -> Result<T, &'static str> {
+        let entry = match (&result, outer) {
+            (None, _) => Entry::Failed,
+            (Some(result), Some(_)) => {
+                self.copied.set(self.copied.get() + (end - start));
+                Entry::Matched(end, result.clone())
+            }
+            (Some(_), None) => Entry::Parsed(self.generation.get()),
+        };
+        if let Some(outer) = outer {
+            self.generation.set(outer);
+        }
+        memo.entries.borrow_mut().insert(start, entry);
+        result.ok_or("")
+    }
+
+    pub(crate) fn parses(&self) -> usize {
+        self.parses.get()
+    }
+
+    pub(crate) fn copied(&self) -> usize {
+        self.copied.get()
+    }
+}
@@ -1,5 +1,154 @@
 mod grammar;
+mod arena;
+mod diagnostics;
+mod memo;
+mod modes;
 
 pub use grammar::{python};
 
-pub use grammar::TokVec;
\ No newline at end of file
+pub use grammar::TokVec;
+pub use arena::TokenArena;
+pub use diagnostics::Diagnostics;
+pub use modes::{input_status, parse, InputStatus, Mode, Parsed};
+
+use peg::error::ParseError;
+
+use crate::ast::visitor::{self, Visitor};
+use crate::ast::{ErrorStatement, Module, NodeArena, ParserError, Span, Statement, SyntaxError};
+use crate::tokenizer::{tokenize_until_error, TType, Token};
+
+/// Parses the tokens in `arena` as a module.  `name` is only used in error messages.
+///
+/// If the tokens do not parse, they are parsed a second time with the grammar's `invalid_*`
+/// rules on, and the error is the `SyntaxError` CPython would report where one of them
+/// matches.  Otherwise it is CPython's "invalid syntax" at the token the parser failed on.
+pub fn parse_module<'a>(arena: &'a TokenArena<'a>, name: &'a str) -> Result<Module<'a>, ParserError<'a>> {
+    let input = arena.tok_vec();
+    match python::file(&input, &Diagnostics::disabled(), arena.nodes(), name) {
+        Ok(module) => Ok(module),
+        Err(err) => match explain(&input, arena.tokens(), &err) {
+            Some(syntax_error) => Err(ParserError::SyntaxError(syntax_error)),
+            None => Err(ParserError::ParserError(err, name)),
+        },
+    }
+}
+
+/// Parses the tokens in `arena` as a module, carrying on past statements that do not parse,
+/// for editors working on half-written code.  Each of those becomes a `Statement::Error` in
+/// the module and an error in the returned list, in source order.
+///
+/// Fill `arena` with `TokenArena::from_source_recovering` to get past source that does not
+/// tokenize as well: the statements it is in are skipped and the errors reported are the
+/// tokenizer's.  The parse itself only fails if `arena` was not filled by the tokenizer and
+/// its indents do not balance.
+pub fn parse_module_recovering<'a>(
+    arena: &'a TokenArena<'a>,
+    name: &'a str,
+) -> Result<(Module<'a>, Vec<SyntaxError>), ParserError<'a>> {
+    let input = arena.tok_vec();
+    let module = python::file(&input, &Diagnostics::for_recovery(arena.tokens()), arena.nodes(), name)
+        .map_err(|err| ParserError::ParserError(err, name))?;
+    let mut collector = ErrorCollector { errors: vec![] };
+    collector.visit_module(&module);
+    let mut errors = arena.errors().to_vec();
+    let tokenizer_error_in = |stmt: &ErrorStatement| {
+        let (start, end) = (stmt.span.start, stmt.span.end);
+        arena.errors().iter().any(|err| {
+            let at = (err.span.start.line, err.span.start.col);
+            (start.line, start.col) <= at && at <= (end.line, end.col)
+        })
+    };
+    for stmt in &collector.errors {
+        if !tokenizer_error_in(stmt) {
+            errors.push(statement_error(stmt, module.eof_tok));
+        }
+    }
+    errors.sort_by_key(|err| (err.span.start.line, err.span.start.col));
+    Ok((module, errors))
+}
+
+/// The `SyntaxError` for a failed parse of `input`, whose tokens are `tokens`: the one an
+/// `invalid_*` rule reports, or else "invalid syntax" at the token the parser failed on.
+/// `None` if that token is not among `tokens`.
+fn explain<'a>(
+    input: &TokVec<'a>,
+    tokens: &'a [Token<'a>],
+    err: &ParseError<<TokVec<'a> as peg::Parse>::PositionRepr>,
+) -> Option<SyntaxError> {
+    diagnose(input, tokens).or_else(|| invalid_syntax(tokens, err))
+}
+
+fn invalid_syntax<'a>(
+    tokens: &'a [Token<'a>],
+    err: &ParseError<<TokVec<'a> as peg::Parse>::PositionRepr>,
+) -> Option<SyntaxError> {
+    let tok = unexpected_token(tokens, err)?;
+    let message = if tok.r#type == TType::Indent { "unexpected indent" } else { "invalid syntax" };
+    Some(SyntaxError::new(message, Span::from_token(tok)))
+}
+
+/// The error the grammar's `invalid_*` rules report for `input`, if one of them matches.
+fn diagnose<'a>(input: &TokVec<'a>, tokens: &'a [Token<'a>]) -> Option<SyntaxError> {
+    // The nodes are thrown away: only the error is wanted.
+    let nodes = NodeArena::new();
+    let diagnostics = Diagnostics::enabled(tokens);
+    let _ = python::file(input, &diagnostics, &nodes, "");
+    diagnostics.take()
+}
+
+/// The token the parser failed on.  `lit` and `tok` reject a token after consuming it, so the
+/// parser reports the position of the token after it.
+fn unexpected_token<'a>(
+    tokens: &'a [Token<'a>],
+    err: &ParseError<<TokVec<'a> as peg::Parse>::PositionRepr>,
+) -> Option<&'a Token<'a>> {
+    tokens.get(err.location.index.checked_sub(1)?)
+}
+
+/// The error CPython reports for `source`, which `tokenize` fails on because a bracket is
+/// never closed, if it is not that one.  CPython's parser reads tokens as it goes and only
+/// runs into the unclosed bracket at the end of the source, so a syntax error before the end
+/// is reported instead, as it is for `def f(:`.
+pub fn unclosed_bracket_error(source: &str) -> Option<SyntaxError> {
+    let arena = TokenArena::new(tokenize_until_error(source));
+    let tokens = arena.tokens();
+    let input = arena.tok_vec();
+    let err = python::file(&input, &Diagnostics::disabled(), arena.nodes(), "").err()?;
+    // Reading the end marker is where CPython finds the bracket unclosed, and so is an error
+    // that spans the last token, which an `invalid_*` rule only raises after looking past it.
+    let last = tokens.len().checked_sub(2).and_then(|last| tokens.get(last))?;
+    if err.location.index >= tokens.len() {
+        return None;
+    }
+    match diagnose(&input, tokens) {
+        Some(error) if (error.span.end.line, error.span.end.col) >= (last.end.line, last.end.col) => None,
+        Some(error) => Some(error),
+        None => invalid_syntax(tokens, &err),
+    }
+}
+
+/// The error for a statement the recovering parser skipped, found by parsing its tokens as a
+/// module of their own.
+fn statement_error<'a>(stmt: &ErrorStatement<'a>, eof: &'a Token<'a>) -> SyntaxError {
+    let input: TokVec<'a> = stmt.tokens.iter().chain([eof]).collect();
+    let nodes = NodeArena::new();
+    let diagnostics = Diagnostics::disabled();
+    let err = match python::file(&input, &diagnostics, &nodes, "") {
+        Ok(_) => return SyntaxError::new("invalid syntax", stmt.span),
+        Err(err) => err,
+    };
+    explain(&input, stmt.tokens, &err).unwrap_or_else(|| SyntaxError::new("invalid syntax", stmt.span))
+}
+
+struct ErrorCollector<'a> {
+    errors: Vec<ErrorStatement<'a>>,
+}
+
+impl<'r, 'a> Visitor<'r, 'a> for ErrorCollector<'a> {
+    fn visit_statement(&mut self, node: &'r Statement<'a>) {
+        match node {
+            Statement::Error(stmt) => self.errors.push(stmt.clone()),
+            _ => visitor::walk_statement(self, node),
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! The entry points of CPython's `compile()` other than whole modules, and the check a REPL
+//! makes before compiling what has been typed so far.
+
+use crate::ast::{Expression, FunctionType, Module, ParserError, Statement, SyntaxError};
+use crate::tokenizer::{TType, TokError, Token};
+
+use super::{explain, parse_module, python, Diagnostics, TokenArena};
+
+/// The `mode` argument of `compile()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// A module.
+    Exec,
+    /// A single expression, with any number of blank lines after it.
+    Eval,
+    /// One statement typed at the interactive prompt.
+    Single,
+    /// The signature in a function's type comment, e.g. `(int, str) -> bool`.
+    FuncType,
+}
+
+/// What `parse` returns for each `Mode`.
+pub enum Parsed<'a> {
+    Module(Module<'a>),
+    Expression(Expression<'a>),
+    /// The statement typed at the prompt, or nothing for a blank line.
+    Interactive(Vec<Statement<'a>>),
+    FunctionType(FunctionType<'a>),
+}
+
+/// Whether the source typed at an interactive prompt can run yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputStatus {
+    Complete,
+    /// Nothing is wrong so far but the statement is not finished: the REPL should ask for
+    /// another line.
+    Incomplete,
+    /// The source cannot become valid by adding lines.  Holds the error message.
+    Invalid(String),
+}
+
+/// Parses the tokens in `arena` the way `compile()` does in `mode`.  `name` is only used in
+/// error messages.
+pub fn parse<'a>(arena: &'a TokenArena<'a>, mode: Mode, name: &'a str) -> Result<Parsed<'a>, ParserError<'a>> {
+    let input = arena.tok_vec();
+    let diagnostics = Diagnostics::disabled();
+    let result = match mode {
+        Mode::Exec => return parse_module(arena, name).map(Parsed::Module),
+        Mode::Single => return parse_interactive(arena, name).map(Parsed::Interactive),
+        Mode::Eval => python::eval(&input, &diagnostics, arena.nodes()).map(Parsed::Expression),
+        Mode::FuncType => python::func_type(&input, &diagnostics, arena.nodes()).map(Parsed::FunctionType),
+    };
+    result.map_err(|err| match explain(&input, arena.tokens(), &err) {
+        Some(syntax_error) => ParserError::SyntaxError(syntax_error),
+        None => ParserError::ParserError(err, name),
+    })
+}
+
+fn parse_interactive<'a>(arena: &'a TokenArena<'a>, name: &'a str) -> Result<Vec<Statement<'a>>, ParserError<'a>> {
+    let input = arena.tok_vec();
+    let diagnostics = Diagnostics::disabled();
+    let err = match python::interactive(&input, &diagnostics, arena.nodes()) {
+        Ok(body) => return Ok(body),
+        Err(err) => err,
+    };
+    if let Ok(module) = python::file(&input, &diagnostics, arena.nodes(), name) {
+        if let Some(second) = module.body.get(1) {
+            let message = "multiple statements found while compiling a single statement";
+            return Err(ParserError::SyntaxError(SyntaxError::new(message, second.span())));
+        }
+    }
+    match explain(&input, arena.tokens(), &err) {
+        Some(syntax_error) => Err(ParserError::SyntaxError(syntax_error)),
+        None => Err(ParserError::ParserError(err, name)),
+    }
+}
+
+/// Decides, like the standard REPL, whether `source` is a complete statement in `single`
+/// mode.  Brackets, triple-quoted strings and backslash continuations that are still open
+/// make it incomplete, and so does a block that is empty so far.  A compound statement is
+/// complete once a blank line follows it.
+pub fn input_status(source: &str) -> InputStatus {
+    let arena = match TokenArena::from_source(source) {
+        Ok(arena) => arena,
+        Err(TokError::UnclosedParen(_) | TokError::UnterminatedTripleQuotedString | TokError::LineContinuationEof) => {
+            return InputStatus::Incomplete
+        }
+        Err(err) => return InputStatus::Invalid(err.to_string()),
+    };
+    let tokens = arena.tokens();
+    let Some(eof) = tokens.last() else { return InputStatus::Complete };
+    let last_line = source.strip_suffix('\n').unwrap_or(source).trim_end_matches('\r');
+    if last_line.ends_with('\\') {
+        return InputStatus::Incomplete;
+    }
+    let result = parse_interactive(&arena, "<stdin>");
+    match result {
+        Ok(body) if matches!(body.first(), Some(Statement::Compound(_))) && !ends_with_blank_line(source) => {
+            InputStatus::Incomplete
+        }
+        Ok(_) => InputStatus::Complete,
+        Err(ParserError::SyntaxError(err))
+            if err.message.starts_with("expected an indented block") && err.span.start.line >= eof.start.line =>
+        {
+            InputStatus::Incomplete
+        }
+        // A decorator waits for the definition it decorates.
+        Err(_) if last_line_start(tokens).is_some_and(|tok| tok.text == "@") => InputStatus::Incomplete,
+        Err(err) => InputStatus::Invalid(err.to_string()),
+    }
+}
+
+/// The first token of the last logical line in `tokens`.
+fn last_line_start<'a>(tokens: &'a [Token<'a>]) -> Option<&'a Token<'a>> {
+    let significant = |tok: &&Token| !matches!(tok.r#type, TType::Indent | TType::Dedent | TType::EndMarker);
+    tokens.split(|tok| tok.r#type == TType::NL).rev().find_map(|line| line.iter().find(significant))
+}
+
+fn ends_with_blank_line(source: &str) -> bool {
+    let Some(body) = source.strip_suffix('\n') else { return false };
+    body.rsplit('\n').next().is_some_and(|line| line.trim().is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{input_status, parse, InputStatus, Mode, Parsed};
+    use crate::ast::ParserError;
+    use crate::parser::TokenArena;
+
+    #[test]
+    fn parses_each_mode() {
+        let arena = TokenArena::from_source("x + 1\n\n").expect("tokenize");
+        assert!(matches!(parse(&arena, Mode::Eval, "<test>"), Ok(Parsed::Expression(_))));
+
+        let arena = TokenArena::from_source("(int, *str, **bool) -> List[int]\n").expect("tokenize");
+        let Ok(Parsed::FunctionType(func_type)) = parse(&arena, Mode::FuncType, "<test>") else { panic!("func_type") };
+        let argtypes: Vec<String> = func_type.argtypes.iter().map(|e| e.to_source()).collect();
+        assert_eq!(argtypes, ["int", "str", "bool"]);
+        assert_eq!(func_type.returns.to_source(), "List[int]");
+
+        let arena = TokenArena::from_source("if x:\n    y\n\n").expect("tokenize");
+        let Ok(Parsed::Interactive(body)) = parse(&arena, Mode::Single, "<test>") else { panic!("single") };
+        assert_eq!(body.len(), 1);
+        let arena = TokenArena::from_source("x = 1\ny = 2\n").expect("tokenize");
+        let result = parse(&arena, Mode::Single, "<test>");
+        match result {
+            Err(ParserError::SyntaxError(err)) => {
+                assert_eq!(err.to_string(), "multiple statements found while compiling a single statement (line 2)")
+            }
+            _ => panic!("two statements in single mode"),
+        }
+    }
+
+    #[test]
+    fn tells_incomplete_input() {
+        for source in ["x = 1\n", "x = 1", "\n", "if x:\n    pass\n\n", "@dec\ndef f(): pass\n\n", "x = (1,\n  2)\n"] {
+            assert_eq!(input_status(source), InputStatus::Complete, "{source:?}");
+        }
+        for source in [
+            "if x:\n",
+            "if x:\n    pass\n",
+            "if x:\n    pass\nelse:\n",
+            "x = (1,\n",
+            "s = '''abc\n",
+            "x = 1 + \\\n",
+            "@dec\n",
+            "class A:\n    def f(self):\n",
+        ] {
+            assert_eq!(input_status(source), InputStatus::Incomplete, "{source:?}");
+        }
+        for source in ["x = = 1\n", "if x:\nfoo()\n", "x = 1)\n"] {
+            assert!(matches!(input_status(source), InputStatus::Invalid(_)), "{source:?}");
+        }
+    }
+}
@@ -6,7 +6,7 @@ pub enum TokError {
     TabSpace,
     #[error("too many indentation levels")]
     TooDeep,
-    #[error("no matching outer block for dedent")]
+    #[error("unindent does not match any outer indentation level")]
     Dedent,
     #[error("unexpected characters after a line continuation")]
     LineContinuation,
@@ -36,12 +36,24 @@ pub enum TokError {
     UnterminatedString,
     #[error("unterminated triple-quoted string literal")]
     UnterminatedTripleQuotedString,
+    #[error("(unicode error) 'unicodeescape' codec can't decode bytes in position {0}-{1}: truncated {2} escape")]
+    TruncatedEscape(usize, usize, &'static str),
+    #[error("(unicode error) 'unicodeescape' codec can't decode bytes in position {0}-{1}: illegal Unicode character")]
+    IllegalUnicodeCharacter(usize, usize),
+    #[error("(unicode error) 'unicodeescape' codec can't decode bytes in position {0}-{1}: unknown Unicode character name")]
+    UnknownUnicodeName(usize, usize),
+    #[error("(value error) invalid \\x escape at position {0}")]
+    BadBytesEscape(usize),
+    #[error("bytes can only contain ASCII literal characters")]
+    NonAsciiBytes,
     #[error("unmatched {0:?}")]
     UnmatchedClosingParen(char),
     #[error("Closing parenthesis {1:?} does not match opening parenthesis {0:?}")]
     MismatchedClosingParen(char, char),
     #[error("Closing parenthesis {1:?} does not match opening parenthesis {0:?} on line {2:}")]
     MismatchedClosingParenOnLine(char, char, usize),
+    #[error("'{0}' was never closed")]
+    UnclosedParen(char),
     #[error("{0:?} is not a valid character in this position")]
     BadCharacter(char),
     #[error("non specific issue")]
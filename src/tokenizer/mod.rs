@@ -7,10 +7,12 @@ mod managed_line;
 mod module_lines;
 pub mod position;
 pub mod patterns;
+pub mod tokenize;
 
 pub use token::Token;
 pub use processor::Processor;
 pub use ttype::TType;
 pub use error::TokError;
 pub use position::Position;
+pub use tokenize::{tokenize, tokenize_located, tokenize_recovering, tokenize_until_error};
 
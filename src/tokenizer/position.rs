@@ -1,6 +1,6 @@
 use std::fmt::{Debug, Formatter};
 
-#[derive(Default, Eq, Clone)]
+#[derive(Default, Eq, Clone, Copy)]
 pub struct Position {
     pub col: usize,
     pub line: usize,
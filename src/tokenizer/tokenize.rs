@@ -0,0 +1,816 @@
+//! Turns source text into the token stream the grammar expects.
+//!
+//! Columns are byte offsets into the line and lines are 1-based.  Comments and blank lines
+//! produce no tokens, and a logical line ends in an `NL` token that starts where the line's
+//! last token ends, so statement spans never cover trailing comments or whitespace.
+//!
+//! `tokenize_recovering` never fails.  Whatever does not tokenize becomes an `ErrorToken`
+//! up to the end of its line and tokenizing resumes on the next one, so the recovering
+//! parser can skip the statement it is in.
+
+use super::operators::OPERATOR_RE;
+use super::{Position, TokError, Token, TType};
+
+/// CPython's `MAXINDENT`.
+const MAX_INDENT: usize = 100;
+const TAB_SIZE: usize = 8;
+
+/// Tokenizes a whole module, ending with the dedents for any open blocks and an `EndMarker`.
+pub fn tokenize(source: &str) -> Result<Vec<Token<'_>>, TokError> {
+    tokenize_located(source).map_err(|(err, _)| err)
+}
+
+/// Like `tokenize`, with the position the tokenizer had reached when it failed, for error
+/// messages that quote the offending line.
+pub fn tokenize_located(source: &str) -> Result<Vec<Token<'_>>, (TokError, Position)> {
+    let mut tokenizer = Tokenizer::new(source);
+    tokenizer.run().map_err(|err| (err, tokenizer.position()))
+}
+
+/// The tokens before the point `tokenize` fails at, or all of them if it does not, ending
+/// with an `EndMarker`, for the parser to look for an error that comes first.
+pub fn tokenize_until_error(source: &str) -> Vec<Token<'_>> {
+    let mut tokenizer = Tokenizer::new(source);
+    match tokenizer.run() {
+        Ok(tokens) => tokens,
+        Err(_) => {
+            let end = tokenizer.position();
+            let mut tokens = std::mem::take(&mut tokenizer.tokens);
+            tokens.push(Token::Make(TType::EndMarker, end, end, ""));
+            tokens
+        }
+    }
+}
+
+/// Tokenizes a whole module, turning each error into an `ErrorToken` that runs to the end of
+/// the line.  The errors come back in source order, each with where it was found: the start
+/// of an unterminated string, the opening bracket of one that is never closed, and otherwise
+/// the position the tokenizer had reached.
+///
+/// A bracket or f-string that is never closed would swallow the rest of the module, so it is
+/// cut off at the first line after it that is indented no deeper than the statement it is in.
+/// Which ones are never closed is only known at the end, so each one found means tokenizing
+/// again from the start.
+pub fn tokenize_recovering(source: &str) -> (Vec<Token<'_>>, Vec<(TokError, Position)>) {
+    let mut unclosed = vec![];
+    loop {
+        let mut tokenizer = Tokenizer::new(source);
+        tokenizer.recovery = Some(Recovery { unclosed, retry: None, errors: vec![] });
+        let tokens = tokenizer.run().expect("a recovering tokenizer does not fail");
+        let mut recovery = tokenizer.recovery.take().expect("still recovering");
+        match recovery.retry {
+            Some(offset) => {
+                recovery.unclosed.push(offset);
+                unclosed = recovery.unclosed;
+            }
+            None => {
+                recovery.errors.sort_by_key(|(_, position)| (position.line, position.col));
+                return (tokens, recovery.errors);
+            }
+        }
+    }
+}
+
+/// An open bracket, or the start of an f-string, which has to be closed again.
+#[derive(Clone, Copy)]
+struct Opener {
+    offset: usize,
+    start: Position,
+}
+
+struct Bracket {
+    open: char,
+    at: Opener,
+}
+
+/// State for `tokenize_recovering`.
+struct Recovery {
+    /// Byte offsets of the brackets and f-strings that an earlier run found are never closed.
+    unclosed: Vec<usize>,
+    /// An opener still open at the end that is not in `unclosed` yet.
+    retry: Option<usize>,
+    errors: Vec<(TokError, Position)>,
+}
+
+struct FStringField {
+    /// How many brackets were open when the field's `{` was read.
+    depth: usize,
+    in_spec: bool,
+}
+
+struct FStringState<'a> {
+    at: Opener,
+    quote: &'a str,
+    raw: bool,
+    fields: Vec<FStringField>,
+}
+
+struct Tokenizer<'a> {
+    source: &'a str,
+    pos: usize,
+    line: usize,
+    line_start: usize,
+    at_line_start: bool,
+    /// Indentation columns with tabs expanded to 8 and to 1, to catch ambiguous mixes.
+    indents: Vec<(usize, usize)>,
+    parens: Vec<Bracket>,
+    fstrings: Vec<FStringState<'a>>,
+    tokens: Vec<Token<'a>>,
+    recovery: Option<Recovery>,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn new(source: &'a str) -> Self {
+        let pos = if source.starts_with('\u{feff}') { '\u{feff}'.len_utf8() } else { 0 };
+        Self {
+            source,
+            pos,
+            line: 1,
+            line_start: pos,
+            at_line_start: true,
+            indents: vec![(0, 0)],
+            parens: vec![],
+            fstrings: vec![],
+            tokens: vec![],
+            recovery: None,
+        }
+    }
+
+    fn run(&mut self) -> Result<Vec<Token<'a>>, TokError> {
+        loop {
+            let resume = (self.pos, self.line, self.line_start);
+            match self.step() {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(err) if self.recovery.is_some() => self.skip_error(err, resume),
+                Err(err) => return Err(err),
+            }
+        }
+        if self.recovery.is_some() {
+            self.close_unclosed();
+        }
+        if !self.fstrings.is_empty() {
+            return Err(TokError::UnterminatedString);
+        }
+        if let Some(bracket) = self.parens.last() {
+            return Err(TokError::UnclosedParen(bracket.open));
+        }
+        if self.tokens.last().is_some_and(|tok| tok.r#type != TType::NL) {
+            let start = self.tokens.last().map(|tok| tok.end).unwrap_or_default();
+            self.tokens.push(Token::Make(TType::NL, start, self.position(), ""));
+        }
+        let end = self.position();
+        for _ in 1..self.indents.len() {
+            self.tokens.push(Token::Make(TType::Dedent, end, end, ""));
+        }
+        self.tokens.push(Token::Make(TType::EndMarker, end, end, ""));
+        Ok(std::mem::take(&mut self.tokens))
+    }
+
+    /// Tokenizes the next piece of the source.  Returns false at the end.
+    fn step(&mut self) -> Result<bool, TokError> {
+        if self.at_line_start && !self.indentation()? {
+            return Ok(true);
+        }
+        if self.in_fstring_literal() {
+            self.fstring_literal()?;
+            return Ok(true);
+        }
+        self.skip_whitespace();
+        let Some(c) = self.peek() else { return Ok(false) };
+        match c {
+            '#' => self.skip_comment(),
+            '\\' => self.line_continuation()?,
+            '\n' | '\r' => self.newline(),
+            _ => self.token(c)?,
+        }
+        Ok(true)
+    }
+
+    /// Records `err` and turns the rest of the line, from where the failed step started at
+    /// `resume`, into an `ErrorToken`.  Brackets and f-strings opened on the line are dropped
+    /// with it; those opened on earlier lines stay open.
+    fn skip_error(&mut self, err: TokError, resume: (usize, usize, usize)) {
+        let found = self.position();
+        (self.pos, self.line, self.line_start) = resume;
+        self.skip_whitespace();
+        let start = self.pos;
+        let start_pos = self.position();
+        let position = match err {
+            TokError::UnterminatedString | TokError::UnterminatedTripleQuotedString => start_pos,
+            _ => found,
+        };
+        self.skip_comment();
+        self.emit(TType::ErrorToken, start, start_pos);
+        self.parens.retain(|bracket| bracket.at.start.line < start_pos.line);
+        self.fstrings.retain(|fs| fs.at.start.line < start_pos.line);
+        self.at_line_start = false;
+        self.recovery.as_mut().expect("recovering").errors.push((err, position));
+    }
+
+    /// The outermost bracket or f-string still open, with the error for never closing it.
+    fn outermost_opener(&self) -> Option<(Opener, TokError)> {
+        let bracket = self.parens.first().map(|bracket| (bracket.at, TokError::UnclosedParen(bracket.open)));
+        let fstring = self.fstrings.first().map(|fs| (fs.at, TokError::UnterminatedString));
+        [bracket, fstring].into_iter().flatten().min_by_key(|(at, _)| at.offset)
+    }
+
+    /// At the end of a recovering run, records the bracket or f-string left open, or asks for
+    /// another run that cuts it off if this one did not know it is never closed.
+    fn close_unclosed(&mut self) {
+        let Some((at, err)) = self.outermost_opener() else { return };
+        let recovery = self.recovery.as_mut().expect("recovering");
+        if recovery.unclosed.contains(&at.offset) {
+            recovery.errors.push((err, at.start));
+        } else {
+            recovery.retry = Some(at.offset);
+        }
+        self.parens.clear();
+        self.fstrings.clear();
+    }
+
+    /// In a recovering run, whether the newline at the current position ends a bracket or
+    /// f-string that is never closed: the next line with code on it is indented no deeper
+    /// than the current logical line.
+    fn cuts_unclosed(&mut self) -> bool {
+        let Some((at, err)) = self.outermost_opener() else { return false };
+        let Some(recovery) = &self.recovery else { return false };
+        if !recovery.unclosed.contains(&at.offset) {
+            return false;
+        }
+        let indent = self.indents.last().map_or(0, |&(col, _)| col);
+        let mut lines = self.rest().lines().skip(1);
+        let next = lines.find(|line| !matches!(line.trim_start().chars().next(), None | Some('#')));
+        if next.is_none_or(|line| indent_width(line) > indent) {
+            return false;
+        }
+        self.recovery.as_mut().expect("recovering").errors.push((err, at.start));
+        self.parens.clear();
+        self.fstrings.clear();
+        true
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.rest().chars().nth(n)
+    }
+
+    fn position(&self) -> Position {
+        Position::m(self.pos - self.line_start, self.line)
+    }
+
+    fn emit(&mut self, ttype: TType, start: usize, start_pos: Position) {
+        let text = &self.source[start..self.pos];
+        let end = self.position();
+        self.tokens.push(Token::Make(ttype, start_pos, end, text));
+    }
+
+    /// Consumes a `\n`, `\r\n` or `\r` and starts the next line.
+    fn consume_newline(&mut self) {
+        if self.rest().starts_with("\r\n") {
+            self.pos += 2;
+        } else {
+            self.pos += 1;
+        }
+        self.line += 1;
+        self.line_start = self.pos;
+    }
+
+    /// Measures the indentation of a new line and emits `Indent`/`Dedent` tokens for it.
+    /// Returns false if the line was blank or only a comment and has been skipped.
+    fn indentation(&mut self) -> Result<bool, TokError> {
+        let (mut col, mut alt_col) = (0, 0);
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' => {
+                    col += 1;
+                    alt_col += 1;
+                }
+                '\t' => {
+                    col = (col / TAB_SIZE + 1) * TAB_SIZE;
+                    alt_col += 1;
+                }
+                '\x0c' => {
+                    col = 0;
+                    alt_col = 0;
+                }
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        match self.peek() {
+            None => return Ok(true),
+            Some('#') => {
+                self.skip_comment();
+                if self.peek().is_some() {
+                    self.consume_newline();
+                }
+                return Ok(false);
+            }
+            Some('\n' | '\r') => {
+                self.consume_newline();
+                return Ok(false);
+            }
+            _ => {}
+        }
+        self.at_line_start = false;
+
+        let &(current, current_alt) = self.indents.last().expect("the indent stack starts at 0");
+        if col > current {
+            if alt_col <= current_alt {
+                return Err(TokError::TabSpace);
+            }
+            if self.indents.len() >= MAX_INDENT {
+                return Err(TokError::TooDeep);
+            }
+            self.indents.push((col, alt_col));
+            let start = Position::m(0, self.line);
+            self.emit(TType::Indent, self.line_start, start);
+        } else if col < current {
+            while self.indents.last().is_some_and(|&(indent, _)| col < indent) {
+                self.indents.pop();
+                let here = self.position();
+                self.tokens.push(Token::Make(TType::Dedent, here, here, ""));
+            }
+            let &(indent, indent_alt) = self.indents.last().expect("the indent stack starts at 0");
+            if col != indent {
+                return Err(TokError::Dedent);
+            }
+            if alt_col != indent_alt {
+                return Err(TokError::TabSpace);
+            }
+        } else if alt_col != current_alt {
+            return Err(TokError::TabSpace);
+        }
+        Ok(true)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ' | '\t' | '\x0c') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        let len = self.rest().find(['\n', '\r']).unwrap_or(self.rest().len());
+        self.pos += len;
+    }
+
+    fn line_continuation(&mut self) -> Result<(), TokError> {
+        self.pos += 1;
+        match self.peek() {
+            Some('\n' | '\r') => {
+                self.consume_newline();
+                Ok(())
+            }
+            None => Err(TokError::LineContinuationEof),
+            Some(_) => Err(TokError::LineContinuation),
+        }
+    }
+
+    /// Ends the logical line unless a bracket or an f-string replacement field is still open.
+    fn newline(&mut self) {
+        let start = self.pos;
+        let logical_end = (self.parens.is_empty() && self.fstrings.is_empty()) || self.cuts_unclosed();
+        let needs_nl = logical_end && self.tokens.last().is_some_and(|tok| tok.r#type != TType::NL);
+        let start_pos = self.tokens.last().map(|tok| tok.end).unwrap_or_default();
+        let end_pos = self.position();
+        self.consume_newline();
+        if needs_nl {
+            let text = &self.source[start..self.pos];
+            self.tokens.push(Token::Make(TType::NL, start_pos, end_pos, text));
+        }
+        self.at_line_start = logical_end;
+    }
+
+    fn token(&mut self, c: char) -> Result<(), TokError> {
+        if c.is_ascii_digit() || (c == '.' && self.peek_nth(1).is_some_and(|next| next.is_ascii_digit())) {
+            return self.number();
+        }
+        if c == '"' || c == '\'' {
+            return self.string(self.pos, self.position(), "");
+        }
+        if c == '_' || c.is_alphabetic() {
+            return self.name();
+        }
+        if let Some(field) = self.fstrings.last().and_then(|fs| fs.fields.last()) {
+            if field.depth == self.parens.len() && (c == '}' || c == ':') {
+                return self.fstring_field_op(c);
+            }
+        }
+        self.operator(c)
+    }
+
+    fn name(&mut self) -> Result<(), TokError> {
+        let start = self.pos;
+        let start_pos = self.position();
+        let len = self
+            .rest()
+            .find(|c: char| !(c == '_' || c.is_alphanumeric()))
+            .unwrap_or(self.rest().len());
+        self.pos += len;
+        let text = &self.source[start..self.pos];
+        if matches!(self.peek(), Some('"' | '\'')) && is_string_prefix(text) {
+            return self.string(start, start_pos, text);
+        }
+        let ttype = match text {
+            "async" => TType::Async,
+            "await" => TType::Await,
+            _ => TType::Name,
+        };
+        self.emit(ttype, start, start_pos);
+        Ok(())
+    }
+
+    fn number(&mut self) -> Result<(), TokError> {
+        let start = self.pos;
+        let start_pos = self.position();
+        let digits = |tok: &mut Self, valid: fn(char) -> bool| {
+            while tok.peek().is_some_and(|c| c == '_' || valid(c)) {
+                tok.pos += 1;
+            }
+        };
+        let radix = self.rest().get(..2).map(|p| p.to_ascii_lowercase());
+        match radix.as_deref() {
+            Some("0x") => {
+                self.pos += 2;
+                digits(self, |c| c.is_ascii_hexdigit());
+            }
+            Some("0o" | "0b") => {
+                self.pos += 2;
+                digits(self, |c| c.is_ascii_digit());
+            }
+            _ => {
+                digits(self, |c| c.is_ascii_digit());
+                if self.peek() == Some('.') {
+                    self.pos += 1;
+                    digits(self, |c| c.is_ascii_digit());
+                }
+                if let Some('e' | 'E') = self.peek() {
+                    let exponent = match self.peek_nth(1) {
+                        Some('+' | '-') => self.peek_nth(2),
+                        next => next,
+                    };
+                    if exponent.is_some_and(|c| c.is_ascii_digit()) {
+                        self.pos += if matches!(self.peek_nth(1), Some('+' | '-')) { 2 } else { 1 };
+                        digits(self, |c| c.is_ascii_digit());
+                    } else if !self.peek_nth(1).is_some_and(|c| c.is_alphanumeric() || c == '_') {
+                        // `1e` with no exponent is a malformed literal, not `1` and a name;
+                        // `1else` is still `1 else`.
+                        self.pos += 1;
+                    }
+                }
+                if let Some('j' | 'J') = self.peek() {
+                    self.pos += 1;
+                }
+            }
+        }
+        self.emit(TType::Number, start, start_pos);
+        Ok(())
+    }
+
+    /// Reads a string literal whose prefix (possibly empty) started at `start`.
+    fn string(&mut self, start: usize, start_pos: Position, prefix: &str) -> Result<(), TokError> {
+        let quote_char = self.peek().expect("called at a quote");
+        let triple: String = std::iter::repeat_n(quote_char, 3).collect();
+        let quote_len = if self.rest().starts_with(&triple) { 3 } else { 1 };
+        let quote = &self.rest()[..quote_len];
+        self.pos += quote_len;
+
+        let lower = prefix.to_ascii_lowercase();
+        if lower.contains('f') {
+            self.emit(TType::FStringStart, start, start_pos);
+            let at = Opener { offset: start, start: start_pos };
+            self.fstrings.push(FStringState { at, quote, raw: lower.contains('r'), fields: vec![] });
+            return Ok(());
+        }
+
+        let unterminated =
+            if quote_len == 3 { TokError::UnterminatedTripleQuotedString } else { TokError::UnterminatedString };
+        loop {
+            if self.rest().starts_with(quote) {
+                self.pos += quote_len;
+                break;
+            }
+            match self.peek() {
+                None => return Err(unterminated),
+                Some('\\') => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some('\n' | '\r') => self.consume_newline(),
+                        Some(c) => self.pos += c.len_utf8(),
+                        None => return Err(unterminated),
+                    }
+                }
+                Some('\n' | '\r') if quote_len == 1 => return Err(unterminated),
+                Some('\n' | '\r') => self.consume_newline(),
+                Some(c) => self.pos += c.len_utf8(),
+            }
+        }
+        self.emit(TType::String, start, start_pos);
+        Ok(())
+    }
+
+    /// True when the innermost f-string is reading literal text rather than a replacement
+    /// field's expression.
+    fn in_fstring_literal(&self) -> bool {
+        self.fstrings
+            .last()
+            .is_some_and(|fs| fs.fields.last().is_none_or(|field| field.in_spec))
+    }
+
+    /// Reads f-string text up to the next replacement field or the end of the string.  Doubled
+    /// braces stay in the text as written; the parser decodes them.
+    fn fstring_literal(&mut self) -> Result<(), TokError> {
+        let state = self.fstrings.last().expect("in an f-string");
+        let (quote, raw) = (state.quote, state.raw);
+        let in_spec = state.fields.last().is_some_and(|field| field.in_spec);
+        let triple = quote.len() == 3;
+        let unterminated =
+            if triple { TokError::UnterminatedTripleQuotedString } else { TokError::UnterminatedString };
+
+        let start = self.pos;
+        let start_pos = self.position();
+        loop {
+            if !in_spec && self.rest().starts_with(quote) {
+                self.flush_fstring_text(start, start_pos);
+                let end_pos = self.position();
+                let end = self.pos;
+                self.pos += quote.len();
+                self.emit(TType::FStringEnd, end, end_pos);
+                self.fstrings.pop();
+                return Ok(());
+            }
+            match self.peek() {
+                None => return Err(unterminated),
+                Some('\\') if !raw => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some('\n' | '\r') => self.consume_newline(),
+                        Some(c) => self.pos += c.len_utf8(),
+                        None => return Err(unterminated),
+                    }
+                }
+                Some('{') if !in_spec && self.peek_nth(1) == Some('{') => self.pos += 2,
+                Some('}') if !in_spec && self.peek_nth(1) == Some('}') => self.pos += 2,
+                Some('{') => {
+                    self.flush_fstring_text(start, start_pos);
+                    let brace_pos = self.position();
+                    let brace = self.pos;
+                    self.pos += 1;
+                    self.emit(TType::Op, brace, brace_pos);
+                    let depth = self.parens.len();
+                    let state = self.fstrings.last_mut().expect("in an f-string");
+                    state.fields.push(FStringField { depth, in_spec: false });
+                    return Ok(());
+                }
+                Some('}') if in_spec => {
+                    self.flush_fstring_text(start, start_pos);
+                    return self.fstring_field_op('}');
+                }
+                Some('}') => return Err(TokError::BadCharacter('}')),
+                Some('\n' | '\r') if !triple => return Err(unterminated),
+                Some('\n' | '\r') => self.consume_newline(),
+                Some(c) => self.pos += c.len_utf8(),
+            }
+        }
+    }
+
+    fn flush_fstring_text(&mut self, start: usize, start_pos: Position) {
+        if self.pos > start {
+            self.emit(TType::FStringString, start, start_pos);
+        }
+    }
+
+    /// A `}` closing a replacement field or a `:` starting its format spec.
+    fn fstring_field_op(&mut self, c: char) -> Result<(), TokError> {
+        let start = self.pos;
+        let start_pos = self.position();
+        self.pos += 1;
+        self.emit(TType::Op, start, start_pos);
+        let fields = &mut self.fstrings.last_mut().expect("in an f-string").fields;
+        if c == '}' {
+            fields.pop();
+        } else if let Some(field) = fields.last_mut() {
+            field.in_spec = true;
+        }
+        Ok(())
+    }
+
+    fn operator(&mut self, c: char) -> Result<(), TokError> {
+        let Some(found) = OPERATOR_RE.find(self.rest()) else {
+            return Err(TokError::BadCharacter(c));
+        };
+        match c {
+            '(' | '[' | '{' => self.parens.push(Bracket { open: c, at: Opener { offset: self.pos, start: self.position() } }),
+            ')' | ']' | '}' => {
+                let Some(Bracket { open, at }) = self.parens.pop() else {
+                    return Err(TokError::UnmatchedClosingParen(c));
+                };
+                let open_line = at.start.line;
+                if closing_paren(open) != c {
+                    return Err(if open_line == self.line {
+                        TokError::MismatchedClosingParen(open, c)
+                    } else {
+                        TokError::MismatchedClosingParenOnLine(open, c, open_line)
+                    });
+                }
+            }
+            _ => {}
+        }
+        let start = self.pos;
+        let start_pos = self.position();
+        self.pos += found.end();
+        self.emit(TType::Op, start, start_pos);
+        Ok(())
+    }
+}
+
+fn closing_paren(open: char) -> char {
+    match open {
+        '(' => ')',
+        '[' => ']',
+        _ => '}',
+    }
+}
+
+/// The indentation of `line` in columns, with tabs expanded to 8.
+fn indent_width(line: &str) -> usize {
+    let mut col = 0;
+    for c in line.chars() {
+        match c {
+            ' ' => col += 1,
+            '\t' => col = (col / TAB_SIZE + 1) * TAB_SIZE,
+            '\x0c' => col = 0,
+            _ => break,
+        }
+    }
+    col
+}
+
+fn is_string_prefix(text: &str) -> bool {
+    matches!(
+        text.to_ascii_lowercase().as_str(),
+        "r" | "u" | "b" | "br" | "rb" | "f" | "fr" | "rf"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tokenize, tokenize_located, tokenize_recovering};
+    use crate::tokenizer::TType::{self, Dedent, EndMarker, ErrorToken, FStringEnd, FStringStart, FStringString, Indent, Name, Number, Op, String, NL};
+    use crate::tokenizer::{Position, TokError};
+
+    fn kinds(source: &str) -> Vec<(TType, &str)> {
+        tokenize(source).expect("tokenize").iter().map(|tok| (tok.r#type, tok.text)).collect()
+    }
+
+    #[test]
+    fn tokenizes_blocks_strings_and_fstrings() {
+        let source = "def f(a,\n      b):  # comment\n\n    return f'{a!r:>{b}}{{' + rb\"x\"\n";
+        assert_eq!(
+            kinds(source),
+            vec![
+                (Name, "def"), (Name, "f"), (Op, "("), (Name, "a"), (Op, ","), (Name, "b"), (Op, ")"), (Op, ":"),
+                (NL, "\n"),
+                (Indent, "    "), (Name, "return"), (FStringStart, "f'"), (Op, "{"), (Name, "a"), (Op, "!"),
+                (Name, "r"), (Op, ":"), (FStringString, ">"), (Op, "{"), (Name, "b"), (Op, "}"), (Op, "}"),
+                (FStringString, "{{"), (FStringEnd, "'"), (Op, "+"), (String, "rb\"x\""), (NL, "\n"),
+                (Dedent, ""), (EndMarker, ""),
+            ]
+        );
+        let tokens = tokenize("x = 1.5e3  # trailing\n").expect("tokenize");
+        assert_eq!((tokens[2].r#type, tokens[2].text), (Number, "1.5e3"));
+        assert_eq!(tokens[3].start, tokens[2].end);
+
+        assert_eq!(tokenize("if x:\n    a\n  b\n").unwrap_err(), TokError::Dedent);
+        assert_eq!(tokenize("f(x]\n").unwrap_err(), TokError::MismatchedClosingParen('(', ']'));
+        assert_eq!(tokenize("(x\n").unwrap_err(), TokError::UnclosedParen('('));
+    }
+
+    /// Each token's type with its start as (line, column).
+    fn positions(source: &str) -> Vec<(TType, usize, usize)> {
+        tokenize(source).expect("tokenize").iter().map(|tok| (tok.r#type, tok.start.line, tok.start.col)).collect()
+    }
+
+    fn error_at(source: &str) -> (TokError, usize, usize) {
+        let (err, Position { line, col, .. }) = tokenize_located(source).unwrap_err();
+        (err, line, col)
+    }
+
+    #[test]
+    fn tracks_indentation() {
+        let source = "if a:\n    if b:\n\n  # comment at another depth\n        c\nd\n";
+        assert_eq!(
+            positions(source),
+            vec![
+                (Name, 1, 0), (Name, 1, 3), (Op, 1, 4), (NL, 1, 5),
+                (Indent, 2, 0), (Name, 2, 4), (Name, 2, 7), (Op, 2, 8), (NL, 2, 9),
+                (Indent, 5, 0), (Name, 5, 8), (NL, 5, 9),
+                (Dedent, 6, 0), (Dedent, 6, 0), (Name, 6, 0), (NL, 6, 1),
+                (EndMarker, 7, 0),
+            ]
+        );
+        // Blocks still open at the end of the file are closed there.
+        let kinds: Vec<TType> = tokenize("while x:\n\tfor y in z:\n\t\tpass").unwrap().iter().map(|tok| tok.r#type).collect();
+        assert_eq!(kinds[kinds.len() - 4..], [NL, Dedent, Dedent, EndMarker]);
+
+        // A tab counts as up to 8 columns, so these are only consistent if tabs are 8 wide.
+        assert_eq!(tokenize("if x:\n\tpass\n        pass\n").unwrap_err(), TokError::TabSpace);
+        assert_eq!(tokenize("if x:\n        a\n\tb\n").unwrap_err(), TokError::TabSpace);
+        assert_eq!(tokenize("  x\n").map(|tokens| tokens[0].r#type), Ok(Indent));
+        // As in CPython, 99 nested blocks are fine and the 100th is too deep.
+        let nested = |depth: usize| (0..depth).map(|level| format!("{}if x:\n", " ".repeat(level))).collect::<Vec<_>>().concat() + &" ".repeat(depth) + "pass\n";
+        assert!(tokenize(&nested(99)).is_ok());
+        assert_eq!(tokenize(&nested(100)).unwrap_err(), TokError::TooDeep);
+    }
+
+    #[test]
+    fn joins_continuation_lines() {
+        let source = "x = 1 + \\\n    2\ny = [1,\n  2]  # c\nz = '''a\nb'''\n";
+        assert_eq!(
+            positions(source),
+            vec![
+                (Name, 1, 0), (Op, 1, 2), (Number, 1, 4), (Op, 1, 6), (Number, 2, 4), (NL, 2, 5),
+                (Name, 3, 0), (Op, 3, 2), (Op, 3, 4), (Number, 3, 5), (Op, 3, 6), (Number, 4, 2), (Op, 4, 3), (NL, 4, 4),
+                (Name, 5, 0), (Op, 5, 2), (String, 5, 4), (NL, 6, 4),
+                (EndMarker, 7, 0),
+            ]
+        );
+        let tokens = tokenize("s = 'a\\\nb'\n").expect("tokenize");
+        assert_eq!((tokens[2].text, tokens[2].end.line, tokens[2].end.col), ("'a\\\nb'", 2, 2));
+
+        // Inside brackets an indented continuation line is not an indent.
+        assert!(!positions("f(a,\n        b)\n").iter().any(|&(kind, ..)| kind == Indent));
+        assert_eq!(tokenize("x = 1 \\ 2\n").unwrap_err(), TokError::LineContinuation);
+        assert_eq!(tokenize("x = 1 \\").unwrap_err(), TokError::LineContinuationEof);
+    }
+
+    #[test]
+    fn splits_nested_and_multiline_fstrings() {
+        assert_eq!(
+            kinds("f\"{f'{x}'} {y=}\"\n"),
+            vec![
+                (FStringStart, "f\""), (Op, "{"), (FStringStart, "f'"), (Op, "{"), (Name, "x"), (Op, "}"), (FStringEnd, "'"),
+                (Op, "}"), (FStringString, " "), (Op, "{"), (Name, "y"), (Op, "="), (Op, "}"), (FStringEnd, "\""),
+                (NL, "\n"), (EndMarker, ""),
+            ]
+        );
+        // A dict display inside a field, a newline in a triple-quoted f-string and a `:` that
+        // is a slice rather than a format spec.
+        assert_eq!(
+            kinds("rf'''{ {'a': 1}['a'] }\n{s[1:]:}}}'''"),
+            vec![
+                (FStringStart, "rf'''"), (Op, "{"), (Op, "{"), (String, "'a'"), (Op, ":"), (Number, "1"), (Op, "}"),
+                (Op, "["), (String, "'a'"), (Op, "]"), (Op, "}"), (FStringString, "\n"), (Op, "{"), (Name, "s"),
+                (Op, "["), (Number, "1"), (Op, ":"), (Op, "]"), (Op, ":"), (Op, "}"), (FStringString, "}}"),
+                (FStringEnd, "'''"), (NL, ""), (EndMarker, ""),
+            ]
+        );
+        let tokens = tokenize("f'''a\nb{c}'''").expect("tokenize");
+        assert_eq!((tokens[3].text, tokens[3].start.line, tokens[3].start.col), ("c", 2, 2));
+
+        assert_eq!(tokenize("f'{x'\n").unwrap_err(), TokError::UnterminatedString);
+        assert_eq!(tokenize("f'a}'\n").unwrap_err(), TokError::BadCharacter('}'));
+        assert_eq!(tokenize("f'''{x}\n").unwrap_err(), TokError::UnterminatedTripleQuotedString);
+    }
+
+    #[test]
+    fn reports_where_tokenizing_failed() {
+        assert_eq!(error_at("x = 1\ny = $\n"), (TokError::BadCharacter('$'), 2, 4));
+        assert_eq!(error_at("x = (1,\n  2))\n"), (TokError::UnmatchedClosingParen(')'), 2, 4));
+        assert_eq!(error_at("x = [1,\n  2)\n"), (TokError::MismatchedClosingParenOnLine('[', ')', 1), 2, 3));
+        assert_eq!(error_at("if x:\n    a\n  b\n"), (TokError::Dedent, 3, 2));
+        assert_eq!(error_at("s = 'abc\nt = 1\n"), (TokError::UnterminatedString, 1, 8));
+        assert_eq!(error_at("s = \"\"\"abc\nt = 1\n"), (TokError::UnterminatedTripleQuotedString, 3, 0));
+    }
+
+    #[test]
+    fn recovers_at_the_next_line() {
+        let (tokens, errors) = tokenize_recovering("f($)\nx = [1,\n  2\ny = '''\n");
+        let tokens: Vec<(TType, &str)> = tokens.iter().map(|tok| (tok.r#type, tok.text)).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (Name, "f"), (Op, "("), (ErrorToken, "$)"), (NL, "\n"),
+                (Name, "x"), (Op, "="), (Op, "["), (Number, "1"), (Op, ","), (Number, "2"), (NL, "\n"),
+                (Name, "y"), (Op, "="), (ErrorToken, "'''"), (NL, "\n"), (EndMarker, ""),
+            ]
+        );
+        assert_eq!(
+            errors,
+            [
+                (TokError::BadCharacter('$'), Position::m(2, 1)),
+                (TokError::UnclosedParen('['), Position::m(4, 2)),
+                (TokError::UnterminatedTripleQuotedString, Position::m(4, 4)),
+            ]
+        );
+    }
+}
@@ -20,4 +20,7 @@ pub enum TType {
     #[allow(dead_code)]
     FStringEnd,
     EndMarker,
+    /// Source that did not tokenize, up to the end of its line.  Only `tokenize_recovering`
+    /// produces these, and the grammar never accepts one.
+    ErrorToken,
 }
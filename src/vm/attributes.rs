@@ -0,0 +1,564 @@
+//! Attribute lookup, assignment and deletion.
+//!
+//! Special methods are looked up on the class only, and only in classes defined in Python:
+//! the behaviour of the builtin types is implemented directly on their payloads.  Their
+//! own special methods (see `methods`) are there for explicit calls like `"a".__len__()`.
+
+use super::object::{ExceptionState, FuncArgs, Payload, PyObjectRef, PyProperty, PyResult};
+use super::VirtualMachine;
+
+impl VirtualMachine {
+    /// The special method `name` of the class of `obj`, if a class statement defined it.
+    pub fn special_method(&self, obj: &PyObjectRef, name: &str) -> Option<PyObjectRef> {
+        let class = obj.class();
+        let mro = class.as_type()?.mro.borrow().clone();
+        mro.iter()
+            .take_while(|base| base.as_type().is_some_and(|t| t.heap))
+            .find_map(|base| self.dict_get_str(base.dict.as_ref()?, name))
+    }
+
+    pub fn has_special(&self, obj: &PyObjectRef, name: &str) -> bool {
+        self.special_method(obj, name).is_some()
+    }
+
+    /// Calls the special method `name` of `obj` if it has one.
+    pub fn call_special(&self, obj: &PyObjectRef, name: &str, args: Vec<PyObjectRef>) -> PyResult<Option<PyObjectRef>> {
+        match self.special_method(obj, name) {
+            Some(method) => self.call_bound(&method, obj, args).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Calls a binary special method, with None if it returns `NotImplemented`.
+    pub fn try_special(&self, obj: &PyObjectRef, name: &str, other: &PyObjectRef) -> PyResult<Option<PyObjectRef>> {
+        match self.call_special(obj, name, vec![other.clone()])? {
+            Some(result) if result.is(&self.not_implemented) => Ok(None),
+            result => Ok(result),
+        }
+    }
+
+    /// Calls `method`, found on the class of `obj`, as a method of `obj`.
+    pub fn call_bound(&self, method: &PyObjectRef, obj: &PyObjectRef, args: Vec<PyObjectRef>) -> PyResult {
+        match &method.payload {
+            Payload::Function(_) | Payload::BuiltinFunction(_) => {
+                let mut all = Vec::with_capacity(args.len() + 1);
+                all.push(obj.clone());
+                all.extend(args);
+                self.call(method, FuncArgs::new(all))
+            }
+            _ => {
+                let bound = self.bind(method, Some(obj), &obj.class())?;
+                self.call(&bound, FuncArgs::new(args))
+            }
+        }
+    }
+
+    /// The value of a class attribute looked up through an instance (or through the class
+    /// itself when `instance` is None): functions become bound methods.
+    pub fn bind(&self, attr: &PyObjectRef, instance: Option<&PyObjectRef>, owner: &PyObjectRef) -> PyResult {
+        Ok(match &attr.payload {
+            Payload::Function(_) | Payload::BuiltinFunction(_) => match instance {
+                Some(instance) => self.new_bound_method(attr, instance),
+                None => attr.clone(),
+            },
+            Payload::StaticMethod(function) => function.clone(),
+            Payload::ClassMethod(function) => self.new_bound_method(function, owner),
+            Payload::Property(property) => match (instance, &property.getter) {
+                (None, _) => attr.clone(),
+                (Some(instance), Some(getter)) => self.call(getter, FuncArgs::new(vec![instance.clone()]))?,
+                (Some(instance), None) => return Err(self.property_error(property, instance, "getter")),
+            },
+            Payload::Member { name, owner, index } => match instance {
+                Some(instance) => {
+                    self.check_member(attr, owner, instance)?;
+                    match instance.slots.get(*index).and_then(|slot| slot.borrow().clone()) {
+                        Some(value) => value,
+                        None => return Err(self.new_attribute_error(instance, name)),
+                    }
+                }
+                None => attr.clone(),
+            },
+            _ => match self.special_method(attr, "__get__") {
+                Some(get) => {
+                    let instance = instance.cloned().unwrap_or_else(|| self.none());
+                    self.call_bound(&get, attr, vec![instance, owner.clone()])?
+                }
+                None => attr.clone(),
+            },
+        })
+    }
+
+    /// Whether `attr`, found on a class, takes precedence over the instance dict: a
+    /// property or an object whose class defines `__set__` or `__delete__`.
+    pub fn is_data_descriptor(&self, attr: &PyObjectRef) -> bool {
+        matches!(attr.payload, Payload::Property(_) | Payload::Member { .. })
+            || self.has_special(attr, "__set__")
+            || self.has_special(attr, "__delete__")
+    }
+
+    fn property_error(&self, property: &PyProperty, instance: &PyObjectRef, missing: &str) -> PyObjectRef {
+        let msg = match &*property.name.borrow() {
+            Some(name) => format!("property '{}' of '{}' object has no {}", name, self.class_name(instance), missing),
+            None => format!("property of '{}' object has no {}", self.class_name(instance), missing),
+        };
+        self.new_exception_msg(&self.exceptions.attribute_error, msg)
+    }
+
+    /// A `__slots__` member applies only to instances of the class that defined it.
+    fn check_member(&self, member: &PyObjectRef, owner: &PyObjectRef, instance: &PyObjectRef) -> PyResult<()> {
+        if self.isinstance(instance, owner) {
+            return Ok(());
+        }
+        let Payload::Member { name, .. } = &member.payload else { unreachable!("not a member") };
+        Err(self.new_type_error(format!(
+            "descriptor '{}' for '{}' objects doesn't apply to a '{}' object",
+            name,
+            owner.as_type().expect("type").name(),
+            self.class_name(instance)
+        )))
+    }
+
+    pub fn new_bound_method(&self, function: &PyObjectRef, receiver: &PyObjectRef) -> PyObjectRef {
+        self.new_object(
+            Payload::BoundMethod { function: function.clone(), receiver: receiver.clone() },
+            &self.types.method_type,
+        )
+    }
+
+    /// `getattr(obj, name)`.
+    pub fn get_attribute(&self, obj: &PyObjectRef, name: &str) -> PyResult {
+        let result = match self.special_method(obj, "__getattribute__") {
+            Some(getattribute) => self.call_bound(&getattribute, obj, vec![self.new_str(name.to_string())]),
+            None => self.generic_getattr(obj, name),
+        };
+        match result {
+            Err(exc) if self.isinstance(&exc, &self.exceptions.attribute_error) => {
+                match self.special_method(obj, "__getattr__") {
+                    Some(getattr) => self.call_bound(&getattr, obj, vec![self.new_str(name.to_string())]),
+                    None => Err(exc),
+                }
+            }
+            result => result,
+        }
+    }
+
+    /// `object.__getattribute__`, or `type.__getattribute__` for a class.
+    pub fn generic_getattr(&self, obj: &PyObjectRef, name: &str) -> PyResult {
+        if let Payload::Super { this_class, obj: Some(instance), obj_type: Some(obj_type) } = &obj.payload {
+            if let Some(value) = self.super_getattr(this_class, instance, obj_type, name)? {
+                return Ok(value);
+            }
+        }
+        match obj.as_type() {
+            Some(_) => self.type_getattr(obj, name),
+            None => self.object_getattr(obj, name),
+        }
+    }
+
+    /// `super(this_class, instance).name`: the first class after `this_class` in the method
+    /// resolution order of `obj_type` that has the attribute, bound to `instance`.
+    fn super_getattr(
+        &self,
+        this_class: &PyObjectRef,
+        instance: &PyObjectRef,
+        obj_type: &PyObjectRef,
+        name: &str,
+    ) -> PyResult<Option<PyObjectRef>> {
+        if name == "__class__" {
+            return Ok(None);
+        }
+        let mro = obj_type.as_type().expect("type").mro.borrow().clone();
+        let Some(start) = mro.iter().position(|base| base.is(this_class)) else { return Ok(None) };
+        let Some(attr) = mro[start + 1..].iter().find_map(|base| self.dict_get_str(base.dict.as_ref()?, name)) else {
+            return Ok(None);
+        };
+        // `super(C, cls)` binds like an attribute looked up on the class.
+        let instance = if instance.is(obj_type) { None } else { Some(instance) };
+        self.bind(&attr, instance, obj_type).map(Some)
+    }
+
+    /// `getattr(obj, name, None)` that tells a missing attribute from a None one.
+    pub fn get_attribute_opt(&self, obj: &PyObjectRef, name: &str) -> PyResult<Option<PyObjectRef>> {
+        match self.get_attribute(obj, name) {
+            Ok(value) => Ok(Some(value)),
+            Err(exc) if self.isinstance(&exc, &self.exceptions.attribute_error) => Ok(None),
+            Err(exc) => Err(exc),
+        }
+    }
+
+    pub fn has_attr(&self, obj: &PyObjectRef, name: &str) -> PyResult<bool> {
+        Ok(self.get_attribute_opt(obj, name)?.is_some())
+    }
+
+    /// `obj.name(*args)`.
+    pub fn call_method(&self, obj: &PyObjectRef, name: &str, args: Vec<PyObjectRef>) -> PyResult {
+        let method = self.get_attribute(obj, name)?;
+        self.call(&method, FuncArgs::new(args))
+    }
+
+    /// `object.__getattribute__`: data descriptors on the class, then the instance dict,
+    /// then other class attributes.
+    fn object_getattr(&self, obj: &PyObjectRef, name: &str) -> PyResult {
+        let class = obj.class();
+        if let Some(value) = self.builtin_attribute(obj, name)? {
+            return Ok(value);
+        }
+        let attr = self.type_lookup(&class, name);
+        if let Some(attr) = attr.as_ref().filter(|attr| self.is_data_descriptor(attr)) {
+            return self.bind(attr, Some(obj), &class);
+        }
+        if let Some(dict) = &obj.dict {
+            if let Some(value) = self.instance_dict_get(dict, name)? {
+                return Ok(value);
+            }
+        }
+        match attr {
+            Some(attr) => self.bind(&attr, Some(obj), &class),
+            None => Err(self.new_attribute_error(obj, name)),
+        }
+    }
+
+    /// Looks `name` up in an instance dict, which a module's `__dict__` can have replaced
+    /// with any dict.
+    fn instance_dict_get(&self, dict: &PyObjectRef, name: &str) -> PyResult<Option<PyObjectRef>> {
+        if dict.class().is(&self.types.dict_type) {
+            return Ok(self.dict_get_str(dict, name));
+        }
+        self.dict_get_item(dict, &self.new_str(name.to_string()))
+    }
+
+    fn type_getattr(&self, class: &PyObjectRef, name: &str) -> PyResult {
+        let ty = class.as_type().expect("type");
+        let special = match name {
+            "__name__" => Some(self.new_str(ty.name())),
+            "__qualname__" => Some(self.new_str(ty.qualname.borrow().clone())),
+            "__mro__" => Some(self.new_tuple(ty.mro.borrow().clone())),
+            "__bases__" => Some(self.new_tuple(ty.bases.borrow().clone())),
+            "__base__" => Some(ty.bases.borrow().first().cloned().unwrap_or_else(|| self.none())),
+            "__dict__" => class.dict.clone(),
+            "__class__" => Some(class.class()),
+            "__doc__" => Some(self.type_lookup_dict(class, "__doc__").unwrap_or_else(|| self.none())),
+            "__module__" => Some(
+                self.type_lookup_dict(class, "__module__").unwrap_or_else(|| self.new_str("builtins".to_string())),
+            ),
+            _ => None,
+        };
+        if let Some(value) = special {
+            return Ok(value);
+        }
+        if let Some(attr) = self.type_lookup(class, name) {
+            return self.bind(&attr, None, class);
+        }
+        let metatype = class.class();
+        match self.type_lookup(&metatype, name) {
+            Some(attr) => self.bind(&attr, Some(class), &metatype),
+            None => Err(self.new_attribute_error(class, name)),
+        }
+    }
+
+    /// `name` from the class's own dict.
+    fn type_lookup_dict(&self, class: &PyObjectRef, name: &str) -> Option<PyObjectRef> {
+        self.dict_get_str(class.dict.as_ref()?, name)
+    }
+
+    /// Attributes that builtin objects keep in their payloads.
+    fn builtin_attribute(&self, obj: &PyObjectRef, name: &str) -> PyResult<Option<PyObjectRef>> {
+        if name == "__class__" {
+            return Ok(Some(obj.class()));
+        }
+        if name == "__dict__" {
+            return Ok(obj.dict.clone());
+        }
+        if name == "__value__" && obj.class().is(&self.types.type_alias_type) {
+            return self.type_alias_value(obj).map(Some);
+        }
+        let value = match (&obj.payload, name) {
+            (Payload::Function(function), _) => match name {
+                "__name__" => self.new_str(function.name.borrow().clone()),
+                "__qualname__" => self.new_str(function.qualname.borrow().clone()),
+                "__doc__" => function.doc.borrow().clone(),
+                "__module__" => function.module.borrow().clone(),
+                "__defaults__" => function.defaults.borrow().clone().unwrap_or_else(|| self.none()),
+                "__kwdefaults__" => function.kwdefaults.borrow().clone().unwrap_or_else(|| self.none()),
+                "__annotations__" => {
+                    let mut annotations = function.annotations.borrow_mut();
+                    annotations.get_or_insert_with(|| self.new_dict()).clone()
+                }
+                "__code__" => self.new_object(Payload::Code(function.code.clone()), &self.types.code_type),
+                "__globals__" => function.globals.clone(),
+                "__closure__" => function.closure.clone().unwrap_or_else(|| self.none()),
+                _ => return Ok(None),
+            },
+            (Payload::BuiltinFunction(function), "__name__" | "__qualname__") => self.new_str(function.name.to_string()),
+            (Payload::BuiltinFunction(_), "__doc__") => self.none(),
+            (Payload::BoundMethod { receiver, .. }, "__self__") => receiver.clone(),
+            (Payload::BoundMethod { function, .. }, "__func__") => function.clone(),
+            (Payload::BoundMethod { function, .. }, "__name__" | "__qualname__" | "__doc__" | "__module__") => {
+                return self.get_attribute(function, name).map(Some)
+            }
+            (Payload::StaticMethod(function) | Payload::ClassMethod(function), "__func__") => function.clone(),
+            (Payload::Property(property), "fget") => property.getter.clone().unwrap_or_else(|| self.none()),
+            (Payload::Property(property), "fset") => property.setter.clone().unwrap_or_else(|| self.none()),
+            (Payload::Property(property), "fdel") => property.deleter.clone().unwrap_or_else(|| self.none()),
+            (Payload::Property(property), "__doc__") => property.doc.clone(),
+            (Payload::Member { name, .. }, "__name__") => self.new_str(name.clone()),
+            (Payload::Member { owner, .. }, "__objclass__") => owner.clone(),
+            (Payload::Super { this_class, .. }, "__thisclass__") => this_class.clone(),
+            (Payload::Super { obj, .. }, "__self__") => obj.clone().unwrap_or_else(|| self.none()),
+            (Payload::Super { obj_type, .. }, "__self_class__") => obj_type.clone().unwrap_or_else(|| self.none()),
+            (Payload::DictView { dict, .. }, "mapping") => dict.clone(),
+            (Payload::Cell(contents), "cell_contents") => match &*contents.borrow() {
+                Some(value) => value.clone(),
+                None => return Err(self.new_value_error("Cell is empty".to_string())),
+            },
+            (Payload::Generator(generator), "__name__") => self.new_str(generator.name.clone()),
+            (Payload::Generator(generator), "__qualname__") => self.new_str(generator.qualname.clone()),
+            (Payload::Exception(state), "args") => state.args.borrow().clone(),
+            (Payload::Exception(state), "__cause__") => state.cause.borrow().clone().unwrap_or_else(|| self.none()),
+            (Payload::Exception(state), "__context__") => state.context.borrow().clone().unwrap_or_else(|| self.none()),
+            (Payload::Exception(state), "__suppress_context__") => self.new_bool(state.suppress_context.get()),
+            (Payload::Exception(ExceptionState { group: Some((message, _)), .. }), "message") => message.clone(),
+            (Payload::Exception(ExceptionState { group: Some((_, exceptions)), .. }), "exceptions") => exceptions.clone(),
+            (Payload::Exception(state), "__traceback__") => {
+                let entries = state.traceback.borrow().clone();
+                self.new_traceback(entries)
+            }
+            (Payload::Exception(state), "code") if self.isinstance(obj, &self.exceptions.system_exit) => {
+                let args = state.args.borrow().clone();
+                match args.as_tuple().expect("args tuple") {
+                    [] => self.none(),
+                    [code] => code.clone(),
+                    _ => args.clone(),
+                }
+            }
+            (Payload::Traceback(entries), "tb_lineno") => self.new_int(entries.last().map_or(0, |e| e.line) as i64),
+            (Payload::Traceback(entries), "tb_next") => self.new_traceback(entries[..entries.len() - 1].to_vec()),
+            (Payload::Exception(_), "value") if self.isinstance(obj, &self.exceptions.stop_iteration) => {
+                self.stop_iteration_value(obj)
+            }
+            (Payload::Int(_) | Payload::Bool(_), "real" | "numerator") => self.new_bigint(obj.as_int().expect("int")),
+            (Payload::Int(_) | Payload::Bool(_), "imag") => self.new_int(0),
+            (Payload::Int(_) | Payload::Bool(_), "denominator") => self.new_int(1),
+            (Payload::Float(f), "real") => self.new_float(*f),
+            (Payload::Float(_), "imag") => self.new_float(0.0),
+            (Payload::Complex { real, .. }, "real") => self.new_float(*real),
+            (Payload::Complex { imag, .. }, "imag") => self.new_float(*imag),
+            (Payload::Slice { start, .. }, "start") => start.clone(),
+            (Payload::Slice { stop, .. }, "stop") => stop.clone(),
+            (Payload::Slice { step, .. }, "step") => step.clone(),
+            (Payload::Range { start, .. }, "start") => self.new_bigint(start.clone()),
+            (Payload::Range { stop, .. }, "stop") => self.new_bigint(stop.clone()),
+            (Payload::Range { step, .. }, "step") => self.new_bigint(step.clone()),
+            (Payload::Code(code), "co_name") => self.new_str(code.name.clone()),
+            (Payload::Code(code), "co_qualname") => self.new_str(code.qualname.clone()),
+            (Payload::Code(code), "co_filename") => self.new_str(code.filename.clone()),
+            (Payload::Code(code), "co_firstlineno") => self.new_int(code.first_lineno as i64),
+            (Payload::Code(code), "co_argcount") => self.new_int(code.argcount as i64),
+            (Payload::Code(code), "co_flags") => self.new_int(code.flags as i64),
+            (Payload::Code(code), "co_varnames") => {
+                self.new_tuple(code.varnames.iter().map(|name| self.new_str(name.clone())).collect())
+            }
+            (Payload::Code(code), "co_cellvars") => {
+                self.new_tuple(code.cellvars.iter().map(|name| self.new_str(name.clone())).collect())
+            }
+            (Payload::Code(code), "co_freevars") => {
+                self.new_tuple(code.freevars.iter().map(|name| self.new_str(name.clone())).collect())
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(value))
+    }
+
+    /// The value of a type alias, evaluated the first time it is read and kept.
+    fn type_alias_value(&self, alias: &PyObjectRef) -> PyResult {
+        let dict = alias.dict.as_ref().expect("type alias dict");
+        if let Some(value) = self.dict_get_str(dict, "__value__") {
+            return Ok(value);
+        }
+        let evaluate = self.dict_get_str(dict, "__evaluate_value__").expect("type alias value function");
+        let value = self.call(&evaluate, FuncArgs::new(vec![]))?;
+        self.dict_set_str(dict, "__value__", value.clone());
+        Ok(value)
+    }
+
+    /// `setattr(obj, name, value)`.
+    pub fn set_attribute(&self, obj: &PyObjectRef, name: &str, value: PyObjectRef) -> PyResult<()> {
+        if let Some(setattr) = self.special_method(obj, "__setattr__") {
+            self.call_bound(&setattr, obj, vec![self.new_str(name.to_string()), value])?;
+            return Ok(());
+        }
+        self.generic_setattr(obj, name, Some(value))
+    }
+
+    /// `delattr(obj, name)`.
+    pub fn del_attribute(&self, obj: &PyObjectRef, name: &str) -> PyResult<()> {
+        if let Some(delattr) = self.special_method(obj, "__delattr__") {
+            self.call_bound(&delattr, obj, vec![self.new_str(name.to_string())])?;
+            return Ok(());
+        }
+        self.generic_setattr(obj, name, None)
+    }
+
+    /// `object.__setattr__`, or `object.__delattr__` when `value` is None.
+    pub fn generic_setattr(&self, obj: &PyObjectRef, name: &str, value: Option<PyObjectRef>) -> PyResult<()> {
+        let class = obj.class();
+        if let Some(attr) = self.type_lookup(&class, name) {
+            if let Payload::Property(property) = &attr.payload {
+                let (function, missing) = match &value {
+                    Some(_) => (&property.setter, "setter"),
+                    None => (&property.deleter, "deleter"),
+                };
+                let Some(function) = function else {
+                    return Err(self.property_error(property, obj, missing));
+                };
+                let mut args = vec![obj.clone()];
+                args.extend(value);
+                self.call(function, FuncArgs::new(args))?;
+                return Ok(());
+            }
+            if let Payload::Member { name, owner, index } = &attr.payload {
+                self.check_member(&attr, owner, obj)?;
+                let Some(slot) = obj.slots.get(*index) else {
+                    return Err(self.new_attribute_error(obj, name));
+                };
+                if value.is_none() && slot.borrow().is_none() {
+                    return Err(self.new_exception_msg(&self.exceptions.attribute_error, name.clone()));
+                }
+                *slot.borrow_mut() = value;
+                return Ok(());
+            }
+            let method = if value.is_some() { "__set__" } else { "__delete__" };
+            if let Some(descriptor) = self.special_method(&attr, method) {
+                let mut args = vec![obj.clone()];
+                args.extend(value);
+                self.call_bound(&descriptor, &attr, args)?;
+                return Ok(());
+            }
+        }
+        if let Some(ty) = obj.as_type() {
+            if !ty.heap {
+                return Err(self.new_type_error(format!(
+                    "cannot set '{}' attribute of immutable type '{}'",
+                    name,
+                    ty.name()
+                )));
+            }
+            if let ("__name__" | "__qualname__", Some(value)) = (name, &value) {
+                let Some(text) = value.as_str() else {
+                    return Err(self.new_type_error(format!(
+                        "can only assign string to {}.{}, not '{}'",
+                        ty.name(),
+                        name,
+                        self.class_name(value)
+                    )));
+                };
+                let field = if name == "__name__" { &ty.name } else { &ty.qualname };
+                *field.borrow_mut() = text.to_string();
+                return Ok(());
+            }
+        }
+        if let Payload::Function(function) = &obj.payload {
+            let done = match (name, &value) {
+                ("__name__" | "__qualname__", Some(value)) => {
+                    let Some(text) = value.as_str() else {
+                        return Err(self.new_type_error(format!("{} must be set to a string object", name)));
+                    };
+                    let field = if name == "__name__" { &function.name } else { &function.qualname };
+                    *field.borrow_mut() = text.to_string();
+                    true
+                }
+                ("__doc__", _) => {
+                    *function.doc.borrow_mut() = value.clone().unwrap_or_else(|| self.none());
+                    true
+                }
+                ("__module__", Some(value)) => {
+                    *function.module.borrow_mut() = value.clone();
+                    true
+                }
+                ("__defaults__", _) => {
+                    *function.defaults.borrow_mut() = value.clone().filter(|v| !self.is_none(v));
+                    true
+                }
+                ("__kwdefaults__", _) => {
+                    *function.kwdefaults.borrow_mut() = value.clone().filter(|v| !self.is_none(v));
+                    true
+                }
+                ("__annotations__", _) => {
+                    *function.annotations.borrow_mut() = value.clone();
+                    true
+                }
+                ("__closure__" | "__globals__", _) => {
+                    return Err(self.new_exception_msg(&self.exceptions.attribute_error, "readonly attribute".to_string()))
+                }
+                _ => false,
+            };
+            if done {
+                return Ok(());
+            }
+        }
+        if let Payload::Exception(state) = &obj.payload {
+            if let Some(value) = &value {
+                match name {
+                    "args" => {
+                        *state.args.borrow_mut() = self.new_tuple(self.collect(value)?);
+                        return Ok(());
+                    }
+                    "__cause__" | "__context__" => {
+                        let field = if name == "__cause__" { &state.cause } else { &state.context };
+                        if self.is_none(value) {
+                            *field.borrow_mut() = None;
+                        } else if self.isinstance(value, &self.exceptions.base_exception) {
+                            *field.borrow_mut() = Some(value.clone());
+                        } else {
+                            let which = if name == "__cause__" { "cause" } else { "context" };
+                            return Err(self.new_type_error(format!(
+                                "exception {} must be None or derive from BaseException",
+                                which
+                            )));
+                        }
+                        if name == "__cause__" {
+                            state.suppress_context.set(true);
+                        }
+                        return Ok(());
+                    }
+                    "__suppress_context__" => {
+                        state.suppress_context.set(self.is_true(value)?);
+                        return Ok(());
+                    }
+                    "__traceback__" => return self.set_traceback(obj, value),
+                    "message" | "exceptions" if state.group.is_some() => {
+                        return Err(self.new_exception_msg(
+                            &self.exceptions.attribute_error,
+                            "readonly attribute".to_string(),
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        if let (Payload::Cell(contents), "cell_contents") = (&obj.payload, name) {
+            *contents.borrow_mut() = value;
+            return Ok(());
+        }
+        let Some(dict) = &obj.dict else {
+            return Err(match self.type_lookup(&class, name) {
+                Some(_) => self.new_exception_msg(
+                    &self.exceptions.attribute_error,
+                    format!("'{}' object attribute '{}' is read-only", self.class_name(obj), name),
+                ),
+                None => self.new_attribute_error(obj, name),
+            });
+        };
+        let key = self.new_str(name.to_string());
+        match value {
+            Some(value) => self.dict_set_item(dict, &key, &value),
+            None => {
+                if self.dict_del_item(dict, &key)? {
+                    Ok(())
+                } else {
+                    Err(self.new_attribute_error(obj, name))
+                }
+            }
+        }
+    }
+}
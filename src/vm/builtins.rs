@@ -0,0 +1,757 @@
+//! The `builtins` module: the builtin functions, types, exceptions and constants.
+
+use std::cell::RefCell;
+use std::cmp::Ordering;
+
+use crate::bigint::BigInt;
+use crate::compiler::bytecode::ComparisonOperator;
+
+use super::float::round_float;
+use super::frame::Frame;
+use super::object::{DictViewKind, FuncArgs, IteratorState, Payload, PyObjectRef, PyResult};
+use super::VirtualMachine;
+
+/// Checks the number of positional arguments of builtin function `name` and that it got no
+/// keyword arguments.
+pub(crate) fn check_args(vm: &VirtualMachine, name: &str, args: &FuncArgs, min: usize, max: usize) -> PyResult<()> {
+    if !args.kwargs.is_empty() {
+        return Err(vm.new_type_error(format!("{}() takes no keyword arguments", name)));
+    }
+    let count = args.args.len();
+    if count >= min && count <= max {
+        return Ok(());
+    }
+    let msg = if max == 0 {
+        format!("{}() takes no arguments ({} given)", name, count)
+    } else if min == max && min == 1 {
+        format!("{}() takes exactly one argument ({} given)", name, count)
+    } else if min == max {
+        format!("{} expected {} arguments, got {}", name, min, count)
+    } else if count < min {
+        format!("{} expected at least {} argument{}, got {}", name, min, if min == 1 { "" } else { "s" }, count)
+    } else {
+        format!("{} expected at most {} argument{}, got {}", name, max, if max == 1 { "" } else { "s" }, count)
+    };
+    Err(vm.new_type_error(msg))
+}
+
+fn builtin_print(vm: &VirtualMachine, mut args: FuncArgs) -> PyResult {
+    let mut text_arg = |name: &str| -> PyResult<Option<String>> {
+        match args.take_kwarg(name) {
+            Some(value) if vm.is_none(&value) => Ok(None),
+            Some(value) => match value.as_str() {
+                Some(s) => Ok(Some(s.to_string())),
+                None => Err(vm.new_type_error(format!(
+                    "{} must be None or a string, not {}",
+                    name,
+                    vm.class_name(&value)
+                ))),
+            },
+            None => Ok(None),
+        }
+    };
+    let sep = text_arg("sep")?.unwrap_or_else(|| " ".to_string());
+    let end = text_arg("end")?.unwrap_or_else(|| "\n".to_string());
+    let file = args.take_kwarg("file").filter(|file| !vm.is_none(file));
+    let flush = match args.take_kwarg("flush") {
+        Some(flush) => vm.is_true(&flush)?,
+        None => false,
+    };
+    if let Some((name, _)) = args.kwargs.first() {
+        return Err(vm.new_type_error(format!("'{}' is an invalid keyword argument for print()", name)));
+    }
+    let mut text = String::new();
+    for (i, arg) in args.args.iter().enumerate() {
+        if i > 0 {
+            text.push_str(&sep);
+        }
+        text.push_str(&vm.to_str(arg)?);
+    }
+    text.push_str(&end);
+    let file = match file {
+        Some(file) => file,
+        None => vm.sys_attribute("stdout")?,
+    };
+    vm.call_method(&file, "write", vec![vm.new_str(text)])?;
+    if flush {
+        vm.call_method(&file, "flush", vec![])?;
+    }
+    Ok(vm.none())
+}
+
+fn builtin_len(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "len", &args, 1, 1)?;
+    Ok(vm.new_int(vm.len(&args.args[0])? as i64))
+}
+
+fn builtin_repr(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "repr", &args, 1, 1)?;
+    Ok(vm.new_str(vm.repr(&args.args[0])?))
+}
+
+fn builtin_ascii(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "ascii", &args, 1, 1)?;
+    Ok(vm.new_str(vm.ascii(&args.args[0])?))
+}
+
+fn builtin_format(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "format", &args, 1, 2)?;
+    let spec = match args.args.get(1) {
+        Some(spec) => vm.expect_str(spec, "format() argument 2")?,
+        None => String::new(),
+    };
+    Ok(vm.new_str(vm.format(&args.args[0], &spec)?))
+}
+
+fn builtin_id(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "id", &args, 1, 1)?;
+    Ok(vm.new_int(std::rc::Rc::as_ptr(&args.args[0]) as i64))
+}
+
+fn builtin_hash(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "hash", &args, 1, 1)?;
+    Ok(vm.new_int(vm.hash(&args.args[0])?))
+}
+
+fn builtin_callable(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "callable", &args, 1, 1)?;
+    Ok(vm.new_bool(vm.is_callable(&args.args[0])))
+}
+
+fn builtin_abs(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "abs", &args, 1, 1)?;
+    let value = &args.args[0];
+    if let Some(result) = vm.call_special(value, "__abs__", vec![])? {
+        return Ok(result);
+    }
+    vm.native_abs(value)
+}
+
+/// `min()` and `max()`, which keep the first of equal items.
+fn min_max(vm: &VirtualMachine, mut args: FuncArgs, name: &str, op: ComparisonOperator) -> PyResult {
+    let key = args.take_kwarg("key").filter(|key| !vm.is_none(key));
+    let default = args.take_kwarg("default");
+    if let Some((kwarg, _)) = args.kwargs.first() {
+        return Err(vm.new_type_error(format!("'{}' is an invalid keyword argument for {}()", kwarg, name)));
+    }
+    let items = match args.args.len() {
+        0 => return Err(vm.new_type_error(format!("{} expected at least 1 argument, got 0", name))),
+        1 => vm.collect(&args.args[0])?,
+        _ if default.is_some() => {
+            return Err(vm.new_type_error(format!(
+                "Cannot specify a default for {}() with multiple positional arguments",
+                name
+            )))
+        }
+        _ => args.args,
+    };
+    let mut best: Option<(PyObjectRef, PyObjectRef)> = None;
+    for item in items {
+        let value = match &key {
+            Some(key) => vm.call(key, FuncArgs::new(vec![item.clone()]))?,
+            None => item.clone(),
+        };
+        best = match best {
+            Some((best_item, best_value)) if !vm.compare_bool(&value, &best_value, op)? => Some((best_item, best_value)),
+            _ => Some((item, value)),
+        };
+    }
+    match (best, default) {
+        (Some((item, _)), _) => Ok(item),
+        (None, Some(default)) => Ok(default),
+        (None, None) => Err(vm.new_value_error(format!("{}() arg is an empty sequence", name))),
+    }
+}
+
+fn builtin_min(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    min_max(vm, args, "min", ComparisonOperator::Less)
+}
+
+fn builtin_max(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    min_max(vm, args, "max", ComparisonOperator::Greater)
+}
+
+fn builtin_sum(vm: &VirtualMachine, mut args: FuncArgs) -> PyResult {
+    let start = args.take_kwarg("start");
+    check_args(vm, "sum", &args, 1, 2)?;
+    let mut total = start.or_else(|| args.args.get(1).cloned()).unwrap_or_else(|| vm.new_int(0));
+    match &total.payload {
+        Payload::Str(_) => {
+            return Err(vm.new_type_error("sum() can't sum strings [use ''.join(seq) instead]".to_string()))
+        }
+        Payload::Bytes(_) => {
+            return Err(vm.new_type_error("sum() can't sum bytes [use b''.join(seq) instead]".to_string()))
+        }
+        _ => {}
+    }
+    let iter = vm.get_iter(&args.args[0])?;
+    while let Some(item) = vm.next(&iter)? {
+        total = vm.binary_op(&total, &item, crate::compiler::bytecode::BinaryOperator::Add)?;
+    }
+    Ok(total)
+}
+
+fn builtin_isinstance(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "isinstance", &args, 2, 2)?;
+    Ok(vm.new_bool(vm.check_class_spec(&args.args[0], &args.args[1], "isinstance", false)?))
+}
+
+fn builtin_issubclass(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "issubclass", &args, 2, 2)?;
+    if args.args[0].as_type().is_none() {
+        return Err(vm.new_type_error("issubclass() arg 1 must be a class".to_string()));
+    }
+    Ok(vm.new_bool(vm.check_class_spec(&args.args[0], &args.args[1], "issubclass", true)?))
+}
+
+fn builtin_iter(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "iter", &args, 1, 2)?;
+    match args.args.as_slice() {
+        [obj] => vm.get_iter(obj),
+        [function, sentinel] => {
+            if !vm.is_callable(function) {
+                return Err(vm.new_type_error("iter(v, w): v must be callable".to_string()));
+            }
+            Ok(vm.new_iterator(IteratorState::Callable { function: function.clone(), sentinel: sentinel.clone() }))
+        }
+        _ => unreachable!("checked above"),
+    }
+}
+
+fn builtin_next(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "next", &args, 1, 2)?;
+    let iter = &args.args[0];
+    let is_iterator = matches!(iter.payload, Payload::Iterator(_) | Payload::Generator(_))
+        || vm.has_special(iter, "__next__");
+    if !is_iterator {
+        return Err(vm.new_type_error(format!("'{}' object is not an iterator", vm.class_name(iter))));
+    }
+    if let Payload::Generator(_) = iter.payload {
+        if args.args.len() == 1 {
+            return match vm.generator_next(iter)? {
+                Some(value) => Ok(value),
+                None => Err(vm.new_stop_iteration(None)),
+            };
+        }
+    }
+    match (vm.next(iter)?, args.args.get(1)) {
+        (Some(value), _) => Ok(value),
+        (None, Some(default)) => Ok(default.clone()),
+        (None, None) => Err(vm.new_stop_iteration(None)),
+    }
+}
+
+fn builtin_enumerate(vm: &VirtualMachine, mut args: FuncArgs) -> PyResult {
+    let start = args.take_kwarg("start");
+    check_args(vm, "enumerate", &args, 1, 2)?;
+    let count = match start.or_else(|| args.args.get(1).cloned()) {
+        Some(start) => vm.to_index(&start)?,
+        None => BigInt::zero(),
+    };
+    let iter = vm.get_iter(&args.args[0])?;
+    Ok(vm.new_iterator(IteratorState::Enumerate { iter, count }))
+}
+
+fn builtin_zip(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    if let Some((name, _)) = args.kwargs.first() {
+        return Err(vm.new_type_error(format!("zip() got an unexpected keyword argument '{}'", name)));
+    }
+    let iters = args.args.iter().map(|arg| vm.get_iter(arg)).collect::<PyResult<Vec<_>>>()?;
+    Ok(vm.new_iterator(IteratorState::Zip { iters }))
+}
+
+fn builtin_map(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    if args.args.len() < 2 {
+        return Err(vm.new_type_error("map() must have at least two arguments.".to_string()));
+    }
+    let iters = args.args[1..].iter().map(|arg| vm.get_iter(arg)).collect::<PyResult<Vec<_>>>()?;
+    Ok(vm.new_iterator(IteratorState::Map { function: args.args[0].clone(), iters }))
+}
+
+fn builtin_filter(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "filter", &args, 2, 2)?;
+    let iter = vm.get_iter(&args.args[1])?;
+    Ok(vm.new_iterator(IteratorState::Filter { function: args.args[0].clone(), iter }))
+}
+
+fn builtin_reversed(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "reversed", &args, 1, 1)?;
+    let seq = &args.args[0];
+    if let Some(result) = vm.call_special(seq, "__reversed__", vec![])? {
+        return Ok(result);
+    }
+    let view = match &seq.payload {
+        Payload::Dict(_) => Some((seq.clone(), DictViewKind::Keys)),
+        Payload::DictView { dict, kind } => Some((dict.clone(), *kind)),
+        _ => None,
+    };
+    if let Some((dict, kind)) = view {
+        let len = dict.as_dict().expect("dict").borrow().len();
+        return Ok(vm.new_iterator(IteratorState::Dict { dict, index: usize::MAX, len, kind, reversed: true }));
+    }
+    let reversible = matches!(seq.payload, Payload::List(_) | Payload::Tuple(_) | Payload::Str(_) | Payload::Bytes(_) | Payload::Range { .. })
+        || (vm.has_special(seq, "__getitem__") && vm.has_special(seq, "__len__"));
+    if !reversible {
+        return Err(vm.new_type_error(format!("'{}' object is not reversible", vm.class_name(seq))));
+    }
+    let index = vm.len(seq)?;
+    Ok(vm.new_iterator(IteratorState::Reversed { seq: seq.clone(), index }))
+}
+
+fn builtin_sorted(vm: &VirtualMachine, mut args: FuncArgs) -> PyResult {
+    let key = args.take_kwarg("key").filter(|key| !vm.is_none(key));
+    let reverse = match args.take_kwarg("reverse") {
+        Some(reverse) => vm.is_true(&reverse)?,
+        None => false,
+    };
+    check_args(vm, "sorted", &args, 1, 1)?;
+    let items = vm.collect(&args.args[0])?;
+    let items = vm.sort_items(items, key.as_ref(), reverse)?;
+    Ok(vm.new_list(items))
+}
+
+fn builtin_any(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "any", &args, 1, 1)?;
+    let iter = vm.get_iter(&args.args[0])?;
+    while let Some(item) = vm.next(&iter)? {
+        if vm.is_true(&item)? {
+            return Ok(vm.new_bool(true));
+        }
+    }
+    Ok(vm.new_bool(false))
+}
+
+fn builtin_all(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "all", &args, 1, 1)?;
+    let iter = vm.get_iter(&args.args[0])?;
+    while let Some(item) = vm.next(&iter)? {
+        if !vm.is_true(&item)? {
+            return Ok(vm.new_bool(false));
+        }
+    }
+    Ok(vm.new_bool(true))
+}
+
+/// The attribute name argument of `getattr()` and friends.
+fn attribute_name(vm: &VirtualMachine, name: &PyObjectRef) -> PyResult<String> {
+    match name.as_str() {
+        Some(name) => Ok(name.to_string()),
+        None => Err(vm.new_type_error(format!("attribute name must be string, not '{}'", vm.class_name(name)))),
+    }
+}
+
+fn builtin_getattr(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "getattr", &args, 2, 3)?;
+    let name = attribute_name(vm, &args.args[1])?;
+    match args.args.get(2) {
+        Some(default) => Ok(vm.get_attribute_opt(&args.args[0], &name)?.unwrap_or_else(|| default.clone())),
+        None => vm.get_attribute(&args.args[0], &name),
+    }
+}
+
+fn builtin_setattr(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "setattr", &args, 3, 3)?;
+    let name = attribute_name(vm, &args.args[1])?;
+    vm.set_attribute(&args.args[0], &name, args.args[2].clone())?;
+    Ok(vm.none())
+}
+
+fn builtin_delattr(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "delattr", &args, 2, 2)?;
+    let name = attribute_name(vm, &args.args[1])?;
+    vm.del_attribute(&args.args[0], &name)?;
+    Ok(vm.none())
+}
+
+fn builtin_hasattr(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "hasattr", &args, 2, 2)?;
+    let name = attribute_name(vm, &args.args[1])?;
+    Ok(vm.new_bool(vm.has_attr(&args.args[0], &name)?))
+}
+
+fn builtin_vars(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "vars", &args, 1, 1)?;
+    match &args.args[0].dict {
+        Some(dict) => Ok(dict.clone()),
+        None => Err(vm.new_type_error("vars() argument must have __dict__ attribute".to_string())),
+    }
+}
+
+/// `dir(obj)`: the names in the instance dict and in the dicts of the class and its bases,
+/// or of the class and its bases for a class, sorted.
+fn builtin_dir(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "dir", &args, 1, 1)?;
+    let obj = &args.args[0];
+    if let Some(dir) = vm.special_method(obj, "__dir__") {
+        let names = vm.call_bound(&dir, obj, vec![])?;
+        return Ok(vm.new_list(vm.sort_items(vm.collect(&names)?, None, false)?));
+    }
+    let classes = match obj.as_type() {
+        Some(ty) => ty.mro.borrow().clone(),
+        None => obj.class().as_type().expect("type").mro.borrow().clone(),
+    };
+    let mut names = vec![];
+    for dict in obj.dict.iter().chain(classes.iter().filter_map(|class| class.dict.as_ref())) {
+        if let Payload::Dict(table) = &dict.payload {
+            names.extend(table.borrow().keys());
+        }
+    }
+    let names = vm.hash_table(&names)?.keys();
+    Ok(vm.new_list(vm.sort_items(names, None, false)?))
+}
+
+fn builtin_chr(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "chr", &args, 1, 1)?;
+    let code = vm.to_index(&args.args[0])?;
+    match code.to_i64().and_then(|code| u32::try_from(code).ok()).and_then(char::from_u32) {
+        Some(c) => Ok(vm.new_str(c.to_string())),
+        None => Err(vm.new_value_error("chr() arg not in range(0x110000)".to_string())),
+    }
+}
+
+fn builtin_ord(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "ord", &args, 1, 1)?;
+    let arg = &args.args[0];
+    let (code, len) = match &arg.payload {
+        Payload::Str(s) => (s.chars().next().map(|c| c as i64), s.char_len()),
+        Payload::Bytes(b) => (b.first().map(|b| *b as i64), b.len()),
+        _ => {
+            return Err(vm.new_type_error(format!(
+                "ord() expected string of length 1, but {} found",
+                vm.class_name(arg)
+            )))
+        }
+    };
+    match code {
+        Some(code) if len == 1 => Ok(vm.new_int(code)),
+        _ => Err(vm.new_type_error(format!("ord() expected a character, but string of length {} found", len))),
+    }
+}
+
+/// `bin()`, `oct()` and `hex()`.
+fn int_to_base(vm: &VirtualMachine, args: FuncArgs, name: &str, radix: u32, prefix: &str) -> PyResult {
+    check_args(vm, name, &args, 1, 1)?;
+    let value = vm.to_index(&args.args[0])?;
+    let digits = value.to_str_radix(radix);
+    let text = match digits.strip_prefix('-') {
+        Some(digits) => format!("-{}{}", prefix, digits),
+        None => format!("{}{}", prefix, digits),
+    };
+    Ok(vm.new_str(text))
+}
+
+fn builtin_bin(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    int_to_base(vm, args, "bin", 2, "0b")
+}
+
+fn builtin_oct(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    int_to_base(vm, args, "oct", 8, "0o")
+}
+
+fn builtin_hex(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    int_to_base(vm, args, "hex", 16, "0x")
+}
+
+fn builtin_divmod(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    use crate::compiler::bytecode::BinaryOperator;
+    check_args(vm, "divmod", &args, 2, 2)?;
+    let (a, b) = (&args.args[0], &args.args[1]);
+    if let Some(result) = vm.try_special(a, "__divmod__", b)? {
+        return Ok(result);
+    }
+    if let Some(result) = vm.try_special(b, "__rdivmod__", a)? {
+        return Ok(result);
+    }
+    let numeric = |obj: &PyObjectRef| matches!(obj.payload, Payload::Int(_) | Payload::Bool(_) | Payload::Float(_));
+    if !numeric(a) || !numeric(b) {
+        return Err(vm.new_type_error(format!(
+            "unsupported operand type(s) for divmod(): '{}' and '{}'",
+            vm.class_name(a),
+            vm.class_name(b)
+        )));
+    }
+    let quotient = vm.binary_op(a, b, BinaryOperator::FloorDivide)?;
+    let remainder = vm.binary_op(a, b, BinaryOperator::Remainder)?;
+    Ok(vm.new_tuple(vec![quotient, remainder]))
+}
+
+fn builtin_pow(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    use crate::compiler::bytecode::BinaryOperator;
+    check_args(vm, "pow", &args, 2, 3)?;
+    let modulus = match args.args.get(2) {
+        Some(modulus) if !vm.is_none(modulus) => modulus,
+        _ => return vm.binary_op(&args.args[0], &args.args[1], BinaryOperator::Power),
+    };
+    if let Some(result) = vm.call_special(&args.args[0], "__pow__", vec![args.args[1].clone(), modulus.clone()])? {
+        return Ok(result);
+    }
+    let (Some(base), Some(exp), Some(modulus)) = (args.args[0].as_int(), args.args[1].as_int(), modulus.as_int()) else {
+        return Err(vm.new_type_error("pow() 3rd argument not allowed unless all arguments are integers".to_string()));
+    };
+    if modulus.is_zero() {
+        return Err(vm.new_value_error("pow() 3rd argument cannot be 0".to_string()));
+    }
+    let (base, exp) = if exp.is_negative() {
+        match base.mod_inverse(&modulus) {
+            Some(inverse) => (inverse, -exp),
+            None => return Err(vm.new_value_error("base is not invertible for the given modulus".to_string())),
+        }
+    } else {
+        (base, exp)
+    };
+    Ok(vm.new_bigint(base.mod_pow(&exp, &modulus).expect("non-zero modulus")))
+}
+
+fn builtin_round(vm: &VirtualMachine, mut args: FuncArgs) -> PyResult {
+    let ndigits = args.take_kwarg("ndigits");
+    check_args(vm, "round", &args, 1, 2)?;
+    let ndigits = ndigits.or_else(|| args.args.get(1).cloned()).filter(|n| !vm.is_none(n));
+    let value = &args.args[0];
+    if let Some(round) = vm.special_method(value, "__round__") {
+        let round_args = ndigits.into_iter().collect();
+        return vm.call_bound(&round, value, round_args);
+    }
+    match &value.payload {
+        Payload::Int(_) | Payload::Bool(_) => {
+            let int = value.as_int().expect("int");
+            let digits = match &ndigits {
+                Some(ndigits) => vm.to_index(ndigits)?,
+                None => BigInt::zero(),
+            };
+            if !digits.is_negative() {
+                return Ok(vm.new_bigint(int));
+            }
+            // Round to a multiple of 10**-digits, halves to the even multiple.
+            let Some(exp) = digits.to_i64().filter(|exp| *exp > -(1 << 20)) else {
+                return Ok(vm.new_int(0));
+            };
+            let unit = BigInt::from(10).pow(exp.unsigned_abs());
+            let (quotient, rem) = int.div_mod_floor(&unit).expect("non-zero unit");
+            let twice = &rem + &rem;
+            let round_up = match twice.cmp(&unit) {
+                std::cmp::Ordering::Greater => true,
+                std::cmp::Ordering::Equal => quotient.is_odd(),
+                std::cmp::Ordering::Less => false,
+            };
+            let quotient = if round_up { &quotient + &BigInt::from(1) } else { quotient };
+            Ok(vm.new_bigint(&quotient * &unit))
+        }
+        Payload::Float(f) => match ndigits {
+            None => {
+                let rounded = round_float(*f, 0).unwrap_or(*f);
+                vm.float_to_int(rounded).map(|int| vm.new_bigint(int))
+            }
+            Some(ndigits) => {
+                let digits = vm.to_index(&ndigits)?;
+                let digits = digits.to_i64().unwrap_or(if digits.is_negative() { i64::MIN } else { i64::MAX });
+                match round_float(*f, digits) {
+                    Some(rounded) => Ok(vm.new_float(rounded)),
+                    None => Err(vm.new_overflow_error("rounded value too large to represent".to_string())),
+                }
+            }
+        },
+        _ => Err(vm.new_type_error(format!(
+            "type {} doesn't define __round__ method",
+            vm.class_name(value)
+        ))),
+    }
+}
+
+/// `__build_class__(func, name, *bases, metaclass=None, **kwds)`: runs the class body with
+/// a new namespace and creates the class from it.
+fn builtin_build_class(vm: &VirtualMachine, mut args: FuncArgs) -> PyResult {
+    if args.args.len() < 2 {
+        return Err(vm.new_type_error("__build_class__: not enough arguments".to_string()));
+    }
+    let body = args.args.remove(0);
+    let name = args.args.remove(0);
+    let Payload::Function(function) = &body.payload else {
+        return Err(vm.new_type_error("__build_class__: func must be a function".to_string()));
+    };
+    if name.as_str().is_none() {
+        return Err(vm.new_type_error("__build_class__: name is not a string".to_string()));
+    }
+    let bases = args.args;
+    let metaclass = match args.kwargs.iter().position(|(key, _)| key == "metaclass") {
+        Some(idx) => args.kwargs.remove(idx).1,
+        None => vm.most_derived_metaclass(&vm.types.type_type, &bases)?,
+    };
+    let metaclass = match metaclass.as_type() {
+        Some(_) => vm.most_derived_metaclass(&metaclass, &bases)?,
+        None => metaclass,
+    };
+    let bases_tuple = vm.new_tuple(bases);
+    let namespace = match vm.special_method(&metaclass, "__prepare__").or_else(|| vm.type_lookup(&metaclass, "__prepare__")) {
+        Some(prepare) if metaclass.as_type().is_some_and(|ty| ty.heap) => {
+            let prepare = vm.bind(&prepare, None, &metaclass)?;
+            vm.call(&prepare, FuncArgs::with_kwargs(vec![name.clone(), bases_tuple.clone()], args.kwargs.clone()))?
+        }
+        _ => vm.new_dict(),
+    };
+    let mut frame = Frame::new(vm, function.code.clone(), function.globals.clone(), Some(namespace.clone()));
+    frame.init_cells(vm, function.closure.as_ref());
+    let cell = vm.run_frame(&mut frame)?;
+    if let Payload::Cell(_) = cell.payload {
+        vm.set_item(&namespace, &vm.new_str("__classcell__".to_string()), &cell)?;
+    }
+    vm.call(&metaclass, FuncArgs::with_kwargs(vec![name, bases_tuple, namespace], args.kwargs))
+}
+
+impl VirtualMachine {
+    pub(crate) fn init_builtins(&self) {
+        let dict = self.builtins_dict();
+        let functions: &[(&'static str, super::object::NativeFunction)] = &[
+            ("__build_class__", builtin_build_class),
+            ("abs", builtin_abs),
+            ("all", builtin_all),
+            ("any", builtin_any),
+            ("ascii", builtin_ascii),
+            ("bin", builtin_bin),
+            ("callable", builtin_callable),
+            ("chr", builtin_chr),
+            ("delattr", builtin_delattr),
+            ("dir", builtin_dir),
+            ("divmod", builtin_divmod),
+            ("enumerate", builtin_enumerate),
+            ("filter", builtin_filter),
+            ("format", builtin_format),
+            ("getattr", builtin_getattr),
+            ("hasattr", builtin_hasattr),
+            ("hash", builtin_hash),
+            ("hex", builtin_hex),
+            ("id", builtin_id),
+            ("isinstance", builtin_isinstance),
+            ("issubclass", builtin_issubclass),
+            ("iter", builtin_iter),
+            ("len", builtin_len),
+            ("map", builtin_map),
+            ("max", builtin_max),
+            ("min", builtin_min),
+            ("next", builtin_next),
+            ("oct", builtin_oct),
+            ("ord", builtin_ord),
+            ("pow", builtin_pow),
+            ("print", builtin_print),
+            ("repr", builtin_repr),
+            ("reversed", builtin_reversed),
+            ("round", builtin_round),
+            ("setattr", builtin_setattr),
+            ("sorted", builtin_sorted),
+            ("sum", builtin_sum),
+            ("vars", builtin_vars),
+            ("zip", builtin_zip),
+        ];
+        for (name, function) in functions {
+            self.dict_set_str(&dict, name, self.new_builtin_function(name, *function));
+        }
+        for (name, class) in self.types.builtin_names() {
+            self.dict_set_str(&dict, name, class.clone());
+        }
+        for (name, class) in &self.exceptions.all {
+            self.dict_set_str(&dict, name, class.clone());
+        }
+        self.dict_set_str(&dict, "None", self.none());
+        self.dict_set_str(&dict, "True", self.new_bool(true));
+        self.dict_set_str(&dict, "False", self.new_bool(false));
+        self.dict_set_str(&dict, "Ellipsis", self.ellipsis.clone());
+        self.dict_set_str(&dict, "NotImplemented", self.not_implemented.clone());
+        self.dict_set_str(&dict, "__debug__", self.new_bool(true));
+        self.dict_set_str(&dict, "__name__", self.new_str("builtins".to_string()));
+        self.dict_set_str(&dict, "__doc__", self.none());
+        self.dict_set_str(&dict, "__package__", self.new_str(String::new()));
+        self.dict_set_item(&self.sys_modules, &self.new_str("builtins".to_string()), &self.builtins)
+            .expect("str keys hash");
+    }
+
+    /// The metaclass of a class with `bases`, starting from `metaclass`: the one among them
+    /// that is a subclass of all the others.
+    fn most_derived_metaclass(&self, metaclass: &PyObjectRef, bases: &[PyObjectRef]) -> PyResult {
+        let mut winner = metaclass.clone();
+        for base in bases {
+            let base_meta = base.class();
+            if self.is_subclass(&winner, &base_meta) {
+                continue;
+            }
+            if self.is_subclass(&base_meta, &winner) {
+                winner = base_meta;
+                continue;
+            }
+            return Err(self.new_type_error(
+                "metaclass conflict: the metaclass of a derived class must be a (non-strict) subclass of the metaclasses of all its bases"
+                    .to_string(),
+            ));
+        }
+        Ok(winner)
+    }
+
+    /// `isinstance(obj, spec)` or, with `subclass`, `issubclass(obj, spec)` for a class or
+    /// a tuple of classes.
+    fn check_class_spec(&self, obj: &PyObjectRef, spec: &PyObjectRef, name: &str, subclass: bool) -> PyResult<bool> {
+        if let Some(classes) = spec.as_tuple() {
+            for class in classes {
+                if self.check_class_spec(obj, class, name, subclass)? {
+                    return Ok(true);
+                }
+            }
+            return Ok(false);
+        }
+        let hook = if subclass { "__subclasscheck__" } else { "__instancecheck__" };
+        if let Some(result) = self.call_special(spec, hook, vec![obj.clone()])? {
+            return self.is_true(&result);
+        }
+        if spec.as_type().is_none() {
+            return Err(self.new_type_error(format!(
+                "{}() arg 2 must be a type, a tuple of types, or a union",
+                name
+            )));
+        }
+        Ok(if subclass { self.is_subclass(obj, spec) } else { self.isinstance(obj, spec) })
+    }
+
+    /// Sorts `items` stably by `key`, stopping at the first comparison that raises.
+    pub fn sort_items(
+        &self,
+        items: Vec<PyObjectRef>,
+        key: Option<&PyObjectRef>,
+        reverse: bool,
+    ) -> PyResult<Vec<PyObjectRef>> {
+        let mut keyed = Vec::with_capacity(items.len());
+        for item in items {
+            let value = match key {
+                Some(key) => self.call(key, FuncArgs::new(vec![item.clone()]))?,
+                None => item.clone(),
+            };
+            keyed.push((value, item));
+        }
+        if reverse {
+            keyed.reverse();
+        }
+        let error = RefCell::new(None);
+        keyed.sort_by(|(a, _), (b, _)| {
+            if error.borrow().is_some() {
+                return Ordering::Equal;
+            }
+            let less = |x, y| match self.compare_bool(x, y, ComparisonOperator::Less) {
+                Ok(less) => less,
+                Err(exc) => {
+                    *error.borrow_mut() = Some(exc);
+                    false
+                }
+            };
+            if less(a, b) {
+                Ordering::Less
+            } else if less(b, a) {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+        });
+        if let Some(exc) = error.into_inner() {
+            return Err(exc);
+        }
+        if reverse {
+            keyed.reverse();
+        }
+        Ok(keyed.into_iter().map(|(_, item)| item).collect())
+    }
+}
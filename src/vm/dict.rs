@@ -0,0 +1,316 @@
+//! The hash table behind `dict` and `set`.
+//!
+//! Entries are kept in insertion order with tombstones for deleted ones, and an index maps
+//! each hash to the entries that have it.  Comparing keys can run Python code, which could
+//! change the table, so the table never compares keys itself: callers get the candidate
+//! entries for a hash, compare them without holding a borrow and then come back with the
+//! position of the match.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use super::object::{Payload, PyObjectRef, PyResult};
+use super::VirtualMachine;
+
+#[derive(Clone)]
+pub struct DictEntry {
+    pub hash: i64,
+    pub key: PyObjectRef,
+    pub value: PyObjectRef,
+}
+
+#[derive(Clone, Default)]
+pub struct PyDict {
+    entries: Vec<Option<DictEntry>>,
+    index: HashMap<i64, Vec<usize>>,
+    len: usize,
+}
+
+impl PyDict {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Positions and keys of the entries whose hash is `hash`.
+    pub fn candidates(&self, hash: i64) -> Vec<(usize, PyObjectRef)> {
+        match self.index.get(&hash) {
+            Some(positions) => positions
+                .iter()
+                .filter_map(|pos| self.entries[*pos].as_ref().map(|entry| (*pos, entry.key.clone())))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// The entry whose key is the str `key`, which needs no Python-level comparison.
+    pub fn find_str(&self, hash: i64, key: &str) -> Option<usize> {
+        self.index
+            .get(&hash)?
+            .iter()
+            .copied()
+            .find(|pos| matches!(&self.entries[*pos], Some(entry) if entry.key.as_str() == Some(key)))
+    }
+
+    pub fn entry(&self, pos: usize) -> Option<&DictEntry> {
+        self.entries.get(pos).and_then(|entry| entry.as_ref())
+    }
+
+    /// The first live entry at or after `pos`, for iteration.
+    pub fn next_entry(&self, pos: usize) -> Option<(usize, &DictEntry)> {
+        self.entries.iter().enumerate().skip(pos).find_map(|(pos, entry)| entry.as_ref().map(|e| (pos, e)))
+    }
+
+    /// The last live entry before `pos`, for reversed iteration.
+    pub fn prev_entry(&self, pos: usize) -> Option<(usize, &DictEntry)> {
+        let end = pos.min(self.entries.len());
+        self.entries[..end].iter().enumerate().rev().find_map(|(pos, entry)| entry.as_ref().map(|e| (pos, e)))
+    }
+
+    pub fn set_value(&mut self, pos: usize, value: PyObjectRef) {
+        if let Some(entry) = self.entries[pos].as_mut() {
+            entry.value = value;
+        }
+    }
+
+    /// Adds an entry for a key known not to be in the table.
+    pub fn push(&mut self, hash: i64, key: PyObjectRef, value: PyObjectRef) {
+        self.index.entry(hash).or_default().push(self.entries.len());
+        self.entries.push(Some(DictEntry { hash, key, value }));
+        self.len += 1;
+    }
+
+    pub fn remove(&mut self, pos: usize) -> Option<DictEntry> {
+        let entry = self.entries.get_mut(pos)?.take()?;
+        if let Some(positions) = self.index.get_mut(&entry.hash) {
+            positions.retain(|p| *p != pos);
+            if positions.is_empty() {
+                self.index.remove(&entry.hash);
+            }
+        }
+        self.len -= 1;
+        if self.entries.len() > 16 && self.len * 2 < self.entries.len() {
+            self.compact();
+        }
+        Some(entry)
+    }
+
+    /// Removes and returns the last entry, for `dict.popitem()`.
+    pub fn pop_last(&mut self) -> Option<DictEntry> {
+        let pos = self.entries.iter().rposition(|entry| entry.is_some())?;
+        self.remove(pos)
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &DictEntry> {
+        self.entries.iter().flatten()
+    }
+
+    pub fn keys(&self) -> Vec<PyObjectRef> {
+        self.iter().map(|entry| entry.key.clone()).collect()
+    }
+
+    pub fn values(&self) -> Vec<PyObjectRef> {
+        self.iter().map(|entry| entry.value.clone()).collect()
+    }
+
+    pub fn items(&self) -> Vec<(PyObjectRef, PyObjectRef)> {
+        self.iter().map(|entry| (entry.key.clone(), entry.value.clone())).collect()
+    }
+
+    fn compact(&mut self) {
+        let entries: Vec<DictEntry> = self.entries.drain(..).flatten().collect();
+        self.index.clear();
+        self.len = 0;
+        for entry in entries {
+            self.push(entry.hash, entry.key, entry.value);
+        }
+    }
+}
+
+impl VirtualMachine {
+    /// The position of `key` in `table`, comparing the candidates for its hash with `==`.
+    pub fn table_lookup(&self, table: &PyDict, hash: i64, key: &PyObjectRef) -> PyResult<Option<usize>> {
+        if let Some(s) = key.as_str().filter(|_| key.class().is(&self.types.str_type)) {
+            return Ok(table.find_str(hash, s));
+        }
+        for (pos, candidate) in table.candidates(hash) {
+            if self.same_or_eq(&candidate, key)? {
+                return Ok(Some(pos));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Looks `key` up in the table of a dict or set object.  The table is copied for the
+    /// comparisons, which may run Python code that changes it.
+    fn lookup(&self, table: &RefCell<PyDict>, key: &PyObjectRef) -> PyResult<(i64, Option<usize>)> {
+        let hash = self.hash(key)?;
+        if let Some(s) = key.as_str().filter(|_| key.class().is(&self.types.str_type)) {
+            return Ok((hash, table.borrow().find_str(hash, s)));
+        }
+        let candidates = table.borrow().candidates(hash);
+        for (pos, candidate) in candidates {
+            if self.same_or_eq(&candidate, key)? {
+                return Ok((hash, Some(pos)));
+            }
+        }
+        Ok((hash, None))
+    }
+
+    fn dict_table<'a>(&self, dict: &'a PyObjectRef) -> &'a RefCell<PyDict> {
+        dict.as_dict().expect("dict object")
+    }
+
+    pub fn dict_get_item(&self, dict: &PyObjectRef, key: &PyObjectRef) -> PyResult<Option<PyObjectRef>> {
+        let table = self.dict_table(dict);
+        let (_, pos) = self.lookup(table, key)?;
+        Ok(pos.and_then(|pos| table.borrow().entry(pos).map(|entry| entry.value.clone())))
+    }
+
+    pub fn dict_set_item(&self, dict: &PyObjectRef, key: &PyObjectRef, value: &PyObjectRef) -> PyResult<()> {
+        let table = self.dict_table(dict);
+        let (hash, pos) = self.lookup(table, key)?;
+        match pos {
+            Some(pos) => table.borrow_mut().set_value(pos, value.clone()),
+            None => table.borrow_mut().push(hash, key.clone(), value.clone()),
+        }
+        Ok(())
+    }
+
+    /// Removes `key`, returning whether it was there.
+    pub fn dict_del_item(&self, dict: &PyObjectRef, key: &PyObjectRef) -> PyResult<bool> {
+        Ok(self.dict_pop_item(dict, key)?.is_some())
+    }
+
+    pub fn dict_pop_item(&self, dict: &PyObjectRef, key: &PyObjectRef) -> PyResult<Option<PyObjectRef>> {
+        let table = self.dict_table(dict);
+        let (_, pos) = self.lookup(table, key)?;
+        Ok(pos.and_then(|pos| table.borrow_mut().remove(pos)).map(|entry| entry.value))
+    }
+
+    /// Looks up a str key, as namespaces do.
+    pub fn dict_get_str(&self, dict: &PyObjectRef, key: &str) -> Option<PyObjectRef> {
+        let table = self.dict_table(dict).borrow();
+        let pos = table.find_str(str_hash(key), key)?;
+        table.entry(pos).map(|entry| entry.value.clone())
+    }
+
+    pub fn dict_set_str(&self, dict: &PyObjectRef, key: &str, value: PyObjectRef) {
+        let hash = str_hash(key);
+        let mut table = self.dict_table(dict).borrow_mut();
+        match table.find_str(hash, key) {
+            Some(pos) => table.set_value(pos, value),
+            None => table.push(hash, self.new_str(key.to_string()), value),
+        }
+    }
+
+    pub fn dict_del_str(&self, dict: &PyObjectRef, key: &str) -> Option<PyObjectRef> {
+        let mut table = self.dict_table(dict).borrow_mut();
+        let pos = table.find_str(str_hash(key), key)?;
+        table.remove(pos).map(|entry| entry.value)
+    }
+
+    /// `dict.update(source)`: from a mapping with `keys()`, or from an iterable of pairs.
+    pub fn dict_update(&self, dict: &PyObjectRef, source: &PyObjectRef) -> PyResult<()> {
+        if let Payload::Dict(table) = &source.payload {
+            if self.special_method(source, "__iter__").is_none() {
+                let items = table.borrow().items();
+                for (key, value) in items {
+                    self.dict_set_item(dict, &key, &value)?;
+                }
+                return Ok(());
+            }
+        }
+        if self.has_attr(source, "keys")? {
+            let keys = self.call_method(source, "keys", vec![])?;
+            for key in self.collect(&keys)? {
+                let value = self.get_item(source, &key)?;
+                self.dict_set_item(dict, &key, &value)?;
+            }
+            return Ok(());
+        }
+        for (idx, item) in self.collect(source)?.into_iter().enumerate() {
+            let pair = self.collect(&item).map_err(|_| {
+                self.new_type_error(format!(
+                    "cannot convert dictionary update sequence element #{} to a sequence",
+                    idx
+                ))
+            })?;
+            match pair.as_slice() {
+                [key, value] => self.dict_set_item(dict, key, value)?,
+                _ => {
+                    return Err(self.new_value_error(format!(
+                        "dictionary update sequence element #{} has length {}; 2 is required",
+                        idx,
+                        pair.len()
+                    )))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// A table with each of `items` once, as sets hold them.
+    pub fn hash_table(&self, items: &[PyObjectRef]) -> PyResult<PyDict> {
+        let table = RefCell::new(PyDict::new());
+        for item in items {
+            let (hash, pos) = self.lookup(&table, item)?;
+            if pos.is_none() {
+                table.borrow_mut().push(hash, item.clone(), self.none());
+            }
+        }
+        Ok(table.into_inner())
+    }
+
+    /// The table of a set or frozenset, copied.
+    pub fn set_table(&self, set: &PyObjectRef) -> Option<PyDict> {
+        match &set.payload {
+            Payload::Set(table) => Some(table.borrow().clone()),
+            Payload::FrozenSet(table) => Some(table.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn set_add(&self, set: &PyObjectRef, item: &PyObjectRef) -> PyResult<()> {
+        let Payload::Set(table) = &set.payload else { unreachable!("not a set") };
+        let (hash, pos) = self.lookup(table, item)?;
+        if pos.is_none() {
+            table.borrow_mut().push(hash, item.clone(), self.none());
+        }
+        Ok(())
+    }
+
+    /// Removes `item` from a set, returning whether it was there.
+    pub fn set_discard(&self, set: &PyObjectRef, item: &PyObjectRef) -> PyResult<bool> {
+        let Payload::Set(table) = &set.payload else { unreachable!("not a set") };
+        let (_, pos) = self.lookup(table, item)?;
+        Ok(pos.and_then(|pos| table.borrow_mut().remove(pos)).is_some())
+    }
+
+    pub fn set_contains(&self, set: &PyObjectRef, item: &PyObjectRef) -> PyResult<bool> {
+        match &set.payload {
+            Payload::Set(table) => Ok(self.lookup(table, item)?.1.is_some()),
+            Payload::FrozenSet(table) => {
+                let hash = self.hash(item)?;
+                Ok(self.table_lookup(table, hash, item)?.is_some())
+            }
+            _ => unreachable!("not a set"),
+        }
+    }
+}
+
+/// The hash of a str, without a VM; it matches `VirtualMachine::hash` of the str object.
+pub(crate) fn str_hash(key: &str) -> i64 {
+    super::ops::hash_bytes(key.as_bytes())
+}
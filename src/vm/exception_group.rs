@@ -0,0 +1,288 @@
+//! `BaseExceptionGroup` and `ExceptionGroup`, and the `except*` matching built on them.
+
+use super::builtins::check_args;
+use super::methods::receiver;
+use super::object::{FuncArgs, Payload, PyObjectRef, PyResult};
+use super::VirtualMachine;
+
+/// What `split()` and `subgroup()` test each exception of a group with.
+enum Condition {
+    /// An exception class or a tuple of them.
+    Classes(PyObjectRef),
+    /// A function called with each exception.
+    Predicate(PyObjectRef),
+    /// The exceptions themselves: the leaves `except*` clauses re-raised.
+    Leaves(Vec<PyObjectRef>),
+}
+
+/// The matching and the other parts of a group, None where nothing is left.
+struct Split {
+    matched: Option<PyObjectRef>,
+    rest: Option<PyObjectRef>,
+}
+
+fn is_exception_group(payload: &Payload) -> bool {
+    matches!(payload, Payload::Exception(state) if state.group.is_some())
+}
+
+fn group_derive(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (group, args) = receiver(vm, args, "BaseExceptionGroup", "derive", is_exception_group)?;
+    check_args(vm, "derive", &args, 1, 1)?;
+    let (message, _) = vm.group_parts(&group);
+    vm.call(&vm.exceptions.base_exception_group, FuncArgs::new(vec![message, args.args[0].clone()]))
+}
+
+fn group_split(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (group, args) = receiver(vm, args, "BaseExceptionGroup", "split", is_exception_group)?;
+    check_args(vm, "split", &args, 1, 1)?;
+    let condition = vm.split_condition(&args.args[0])?;
+    let split = vm.split_group(&group, &condition, true)?;
+    let part = |part: Option<PyObjectRef>| part.unwrap_or_else(|| vm.none());
+    Ok(vm.new_tuple(vec![part(split.matched), part(split.rest)]))
+}
+
+fn group_subgroup(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (group, args) = receiver(vm, args, "BaseExceptionGroup", "subgroup", is_exception_group)?;
+    check_args(vm, "subgroup", &args, 1, 1)?;
+    let condition = vm.split_condition(&args.args[0])?;
+    let split = vm.split_group(&group, &condition, false)?;
+    Ok(split.matched.unwrap_or_else(|| vm.none()))
+}
+
+impl VirtualMachine {
+    pub(crate) fn add_exception_group_methods(&self) {
+        let group = &self.exceptions.base_exception_group;
+        self.add_method(group, "derive", group_derive);
+        self.add_method(group, "split", group_split);
+        self.add_method(group, "subgroup", group_subgroup);
+    }
+
+    /// Checks the arguments of `BaseExceptionGroup.__new__` and returns the class to create,
+    /// with the message and the tuple of exceptions.  A `BaseExceptionGroup` of nothing but
+    /// `Exception`s is an `ExceptionGroup`.
+    pub(crate) fn exception_group_new(
+        &self,
+        class: &PyObjectRef,
+        args: &FuncArgs,
+    ) -> PyResult<(PyObjectRef, (PyObjectRef, PyObjectRef))> {
+        let name = class.as_type().expect("type").name();
+        if !args.kwargs.is_empty() {
+            return Err(self.new_type_error(format!("{}() takes no keyword arguments", name)));
+        }
+        let [message, exceptions] = args.args.as_slice() else {
+            return Err(self.new_type_error(format!(
+                "BaseExceptionGroup.__new__() takes exactly 2 arguments ({} given)",
+                args.args.len()
+            )));
+        };
+        if message.as_str().is_none() {
+            return Err(self.new_type_error(format!(
+                "BaseExceptionGroup.__new__() argument 1 must be str, not {}",
+                self.class_name(message)
+            )));
+        }
+        let is_sequence = matches!(exceptions.payload, Payload::List(_) | Payload::Tuple(_) | Payload::Str(_))
+            || (!self.is_mapping(exceptions) && self.has_special(exceptions, "__getitem__"));
+        if !is_sequence {
+            return Err(self.new_type_error("second argument (exceptions) must be a sequence".to_string()));
+        }
+        let items = self.collect(exceptions)?;
+        if items.is_empty() {
+            return Err(self.new_value_error("second argument (exceptions) must be a non-empty sequence".to_string()));
+        }
+        if let Some(idx) = items.iter().position(|item| !self.isinstance(item, &self.exceptions.base_exception)) {
+            return Err(self.new_value_error(format!("Item {} of second argument (exceptions) is not an exception", idx)));
+        }
+        let only_exceptions = items.iter().all(|item| self.isinstance(item, &self.exceptions.exception));
+        let class = if class.is(&self.exceptions.base_exception_group) {
+            if only_exceptions { self.exceptions.exception_group.clone() } else { class.clone() }
+        } else if only_exceptions || !self.is_subclass(class, &self.exceptions.exception) {
+            class.clone()
+        } else if class.is(&self.exceptions.exception_group) {
+            return Err(self.new_type_error("Cannot nest BaseExceptions in an ExceptionGroup".to_string()));
+        } else {
+            return Err(self.new_type_error(format!("Cannot nest BaseExceptions in '{}'", name)));
+        };
+        Ok((class, (message.clone(), self.new_tuple(items))))
+    }
+
+    /// The message and the tuple of exceptions of an exception group.
+    pub(crate) fn group_parts(&self, group: &PyObjectRef) -> (PyObjectRef, PyObjectRef) {
+        group.as_exception().and_then(|state| state.group.clone()).expect("exception group")
+    }
+
+    pub(crate) fn is_exception_group(&self, exc: &PyObjectRef) -> bool {
+        is_exception_group(&exc.payload)
+    }
+
+    /// The argument of `split()` and `subgroup()`.
+    fn split_condition(&self, condition: &PyObjectRef) -> PyResult<Condition> {
+        let is_exception_class =
+            |class: &PyObjectRef| class.as_type().is_some() && self.is_subclass(class, &self.exceptions.base_exception);
+        if self.is_callable(condition) && condition.as_type().is_none() {
+            return Ok(Condition::Predicate(condition.clone()));
+        }
+        let classes = match condition.as_tuple() {
+            Some(classes) => classes.iter().all(is_exception_class),
+            None => is_exception_class(condition),
+        };
+        if !classes {
+            return Err(self.new_type_error("expected a function, exception type or tuple of exception types".to_string()));
+        }
+        Ok(Condition::Classes(condition.clone()))
+    }
+
+    fn split_matches(&self, exc: &PyObjectRef, condition: &Condition) -> PyResult<bool> {
+        match condition {
+            Condition::Classes(classes) => self.exception_matches(exc, classes),
+            Condition::Predicate(predicate) => {
+                let result = self.call(predicate, FuncArgs::new(vec![exc.clone()]))?;
+                self.is_true(&result)
+            }
+            Condition::Leaves(leaves) => Ok(leaves.iter().any(|leaf| leaf.is(exc))),
+        }
+    }
+
+    /// `exc.split(condition)`: an exception that matches is matched whole, and a group that
+    /// does not is split into new groups of its matching and other exceptions.
+    fn split_group(&self, exc: &PyObjectRef, condition: &Condition, with_rest: bool) -> PyResult<Split> {
+        if self.split_matches(exc, condition)? {
+            return Ok(Split { matched: Some(exc.clone()), rest: None });
+        }
+        if !self.is_exception_group(exc) {
+            return Ok(Split { matched: None, rest: with_rest.then(|| exc.clone()) });
+        }
+        let (_, exceptions) = self.group_parts(exc);
+        let (mut matched, mut rest) = (vec![], vec![]);
+        for item in exceptions.as_tuple().expect("exceptions tuple") {
+            let split = self.split_group(item, condition, with_rest)?;
+            matched.extend(split.matched);
+            rest.extend(split.rest);
+        }
+        Ok(Split { matched: self.group_subset(exc, matched)?, rest: self.group_subset(exc, rest)? })
+    }
+
+    /// `group.derive(exceptions)` with the traceback, cause, context and notes of `group`,
+    /// or None for no exceptions.
+    fn group_subset(&self, group: &PyObjectRef, exceptions: Vec<PyObjectRef>) -> PyResult<Option<PyObjectRef>> {
+        if exceptions.is_empty() {
+            return Ok(None);
+        }
+        let derived = self.call_method(group, "derive", vec![self.new_list(exceptions)])?;
+        if !self.is_exception_group(&derived) {
+            return Err(self.new_type_error("derive must return an instance of BaseExceptionGroup".to_string()));
+        }
+        let (from, to) = (group.as_exception().expect("exception"), derived.as_exception().expect("exception"));
+        *to.traceback.borrow_mut() = from.traceback.borrow().clone();
+        *to.context.borrow_mut() = from.context.borrow().clone();
+        *to.cause.borrow_mut() = from.cause.borrow().clone();
+        to.suppress_context.set(true);
+        if let Some(notes) = self.get_attribute_opt(group, "__notes__")? {
+            // Each part gets a list of its own.
+            if matches!(notes.payload, Payload::List(_) | Payload::Tuple(_)) {
+                let notes = self.new_list(self.collect(&notes)?);
+                self.set_attribute(&derived, "__notes__", notes)?;
+            }
+        }
+        Ok(Some(derived))
+    }
+
+    /// `except* class`: splits `exc` into what does not match and what does, either None.
+    /// A matching exception that is not a group is wrapped in one.
+    pub(crate) fn exception_group_match(
+        &self,
+        exc: &PyObjectRef,
+        class: &PyObjectRef,
+    ) -> PyResult<(PyObjectRef, PyObjectRef)> {
+        let classes = match class.as_tuple() {
+            Some(classes) => classes.to_vec(),
+            None => vec![class.clone()],
+        };
+        for class in &classes {
+            if class.as_type().is_none() || !self.is_subclass(class, &self.exceptions.base_exception) {
+                return Err(self.new_type_error(
+                    "catching classes that do not inherit from BaseException is not allowed".to_string(),
+                ));
+            }
+            if self.is_subclass(class, &self.exceptions.base_exception_group) {
+                return Err(self.new_type_error(
+                    "catching ExceptionGroup with except* is not allowed. Use except instead.".to_string(),
+                ));
+            }
+        }
+        if self.is_none(exc) {
+            return Ok((self.none(), self.none()));
+        }
+        if self.exception_matches(exc, class)? {
+            if self.is_exception_group(exc) {
+                return Ok((self.none(), exc.clone()));
+            }
+            let args = vec![self.new_str(String::new()), self.new_tuple(vec![exc.clone()])];
+            let wrapped = self.call(&self.exceptions.base_exception_group, FuncArgs::new(args))?;
+            let traceback = exc.as_exception().expect("exception").traceback.borrow().clone();
+            *wrapped.as_exception().expect("exception").traceback.borrow_mut() = traceback;
+            return Ok((self.none(), wrapped));
+        }
+        if !self.is_exception_group(exc) {
+            return Ok((exc.clone(), self.none()));
+        }
+        let split = self.split_group(exc, &Condition::Classes(class.clone()), true)?;
+        let part = |part: Option<PyObjectRef>| part.unwrap_or_else(|| self.none());
+        Ok((part(split.rest), part(split.matched)))
+    }
+
+    /// What a `try`/`except*` statement raises once its clauses have run: the exceptions
+    /// they raised, then the part of `original` they re-raised or left unhandled, all in
+    /// one group if there is more than one.
+    pub(crate) fn prep_reraise_star(&self, original: &PyObjectRef, excs: Vec<PyObjectRef>) -> PyResult {
+        if !self.is_exception_group(original) {
+            // A bare exception goes to one clause at most.
+            return Ok(excs.into_iter().next().unwrap_or_else(|| self.none()));
+        }
+        let (mut reraised, mut raised) = (vec![], vec![]);
+        for exc in excs.into_iter().filter(|exc| !self.is_none(exc)) {
+            match self.same_exception_metadata(&exc, original) {
+                true => reraised.push(exc),
+                false => raised.push(exc),
+            }
+        }
+        let mut leaves = vec![];
+        for exc in &reraised {
+            self.collect_leaves(exc, &mut leaves);
+        }
+        raised.extend(self.split_group(original, &Condition::Leaves(leaves), false)?.matched);
+        match raised.len() {
+            0 => Ok(self.none()),
+            1 => Ok(raised.remove(0)),
+            _ => {
+                let args = vec![self.new_str(String::new()), self.new_list(raised)];
+                self.call(&self.exceptions.base_exception_group, FuncArgs::new(args))
+            }
+        }
+    }
+
+    /// Whether `exc` is `original` or a part `split()` made of it, rather than an exception
+    /// raised anew.
+    fn same_exception_metadata(&self, exc: &PyObjectRef, original: &PyObjectRef) -> bool {
+        let (Some(a), Some(b)) = (exc.as_exception(), original.as_exception()) else { return false };
+        let same = |x: &Option<PyObjectRef>, y: &Option<PyObjectRef>| match (x, y) {
+            (Some(x), Some(y)) => x.is(y),
+            (None, None) => true,
+            _ => false,
+        };
+        *a.traceback.borrow() == *b.traceback.borrow()
+            && same(&a.cause.borrow(), &b.cause.borrow())
+            && same(&a.context.borrow(), &b.context.borrow())
+    }
+
+    fn collect_leaves(&self, exc: &PyObjectRef, leaves: &mut Vec<PyObjectRef>) {
+        if !self.is_exception_group(exc) {
+            leaves.push(exc.clone());
+            return;
+        }
+        let (_, exceptions) = self.group_parts(exc);
+        for item in exceptions.as_tuple().expect("exceptions tuple") {
+            self.collect_leaves(item, leaves);
+        }
+    }
+}
@@ -0,0 +1,935 @@
+//! The builtin exception types and the helpers the VM raises them with.
+
+use std::cell::{Cell, RefCell};
+use std::io::Write;
+
+use super::builtins::check_args;
+use super::dict::PyDict;
+use super::methods::receiver;
+use super::object::{ExceptionState, FuncArgs, Payload, PyObject, PyObjectRef, PyResult, PyType, TracebackEntry};
+use super::types::Types;
+use super::VirtualMachine;
+
+/// Each builtin exception and its base, bases first.
+const HIERARCHY: &[(&str, &str)] = &[
+    ("BaseException", ""),
+    ("SystemExit", "BaseException"),
+    ("KeyboardInterrupt", "BaseException"),
+    ("GeneratorExit", "BaseException"),
+    ("BaseExceptionGroup", "BaseException"),
+    ("Exception", "BaseException"),
+    ("StopIteration", "Exception"),
+    ("StopAsyncIteration", "Exception"),
+    ("ArithmeticError", "Exception"),
+    ("FloatingPointError", "ArithmeticError"),
+    ("OverflowError", "ArithmeticError"),
+    ("ZeroDivisionError", "ArithmeticError"),
+    ("AssertionError", "Exception"),
+    ("AttributeError", "Exception"),
+    ("BufferError", "Exception"),
+    ("EOFError", "Exception"),
+    ("ImportError", "Exception"),
+    ("ModuleNotFoundError", "ImportError"),
+    ("LookupError", "Exception"),
+    ("IndexError", "LookupError"),
+    ("KeyError", "LookupError"),
+    ("MemoryError", "Exception"),
+    ("NameError", "Exception"),
+    ("UnboundLocalError", "NameError"),
+    ("OSError", "Exception"),
+    ("BlockingIOError", "OSError"),
+    ("ChildProcessError", "OSError"),
+    ("ConnectionError", "OSError"),
+    ("BrokenPipeError", "ConnectionError"),
+    ("ConnectionAbortedError", "ConnectionError"),
+    ("ConnectionRefusedError", "ConnectionError"),
+    ("ConnectionResetError", "ConnectionError"),
+    ("FileExistsError", "OSError"),
+    ("FileNotFoundError", "OSError"),
+    ("InterruptedError", "OSError"),
+    ("IsADirectoryError", "OSError"),
+    ("NotADirectoryError", "OSError"),
+    ("PermissionError", "OSError"),
+    ("ProcessLookupError", "OSError"),
+    ("TimeoutError", "OSError"),
+    ("ReferenceError", "Exception"),
+    ("RuntimeError", "Exception"),
+    ("NotImplementedError", "RuntimeError"),
+    ("RecursionError", "RuntimeError"),
+    ("SyntaxError", "Exception"),
+    ("IndentationError", "SyntaxError"),
+    ("TabError", "IndentationError"),
+    ("SystemError", "Exception"),
+    ("TypeError", "Exception"),
+    ("ValueError", "Exception"),
+    ("UnicodeError", "ValueError"),
+    ("UnicodeDecodeError", "UnicodeError"),
+    ("UnicodeEncodeError", "UnicodeError"),
+    ("UnicodeTranslateError", "UnicodeError"),
+    ("Warning", "Exception"),
+    ("BytesWarning", "Warning"),
+    ("DeprecationWarning", "Warning"),
+    ("EncodingWarning", "Warning"),
+    ("FutureWarning", "Warning"),
+    ("ImportWarning", "Warning"),
+    ("PendingDeprecationWarning", "Warning"),
+    ("ResourceWarning", "Warning"),
+    ("RuntimeWarning", "Warning"),
+    ("SyntaxWarning", "Warning"),
+    ("UnicodeWarning", "Warning"),
+    ("UserWarning", "Warning"),
+];
+
+/// How many exceptions of a group, and how many nested groups, a traceback shows.
+const MAX_GROUP_WIDTH: usize = 15;
+const MAX_GROUP_DEPTH: usize = 10;
+
+/// Older names of `OSError`.
+const OS_ERROR_ALIASES: &[&str] = &["EnvironmentError", "IOError"];
+
+/// The subclass `OSError(errno, ...)` creates for each Linux errno.
+const ERRNO_CLASSES: &[(i64, &str)] = &[
+    (1, "PermissionError"),
+    (2, "FileNotFoundError"),
+    (3, "ProcessLookupError"),
+    (4, "InterruptedError"),
+    (10, "ChildProcessError"),
+    (11, "BlockingIOError"),
+    (13, "PermissionError"),
+    (17, "FileExistsError"),
+    (20, "NotADirectoryError"),
+    (21, "IsADirectoryError"),
+    (32, "BrokenPipeError"),
+    (103, "ConnectionAbortedError"),
+    (104, "ConnectionResetError"),
+    (108, "BrokenPipeError"),
+    (110, "TimeoutError"),
+    (111, "ConnectionRefusedError"),
+    (114, "BlockingIOError"),
+    (115, "BlockingIOError"),
+];
+
+pub struct ExceptionTypes {
+    pub base_exception: PyObjectRef,
+    pub exception: PyObjectRef,
+    pub base_exception_group: PyObjectRef,
+    pub exception_group: PyObjectRef,
+    pub system_exit: PyObjectRef,
+    pub generator_exit: PyObjectRef,
+    pub stop_iteration: PyObjectRef,
+    pub stop_async_iteration: PyObjectRef,
+    pub overflow_error: PyObjectRef,
+    pub zero_division_error: PyObjectRef,
+    pub assertion_error: PyObjectRef,
+    pub attribute_error: PyObjectRef,
+    pub import_error: PyObjectRef,
+    pub module_not_found_error: PyObjectRef,
+    pub lookup_error: PyObjectRef,
+    pub index_error: PyObjectRef,
+    pub key_error: PyObjectRef,
+    pub memory_error: PyObjectRef,
+    pub name_error: PyObjectRef,
+    pub unbound_local_error: PyObjectRef,
+    pub os_error: PyObjectRef,
+    pub runtime_error: PyObjectRef,
+    pub recursion_error: PyObjectRef,
+    pub syntax_error: PyObjectRef,
+    pub indentation_error: PyObjectRef,
+    pub tab_error: PyObjectRef,
+    pub system_error: PyObjectRef,
+    pub type_error: PyObjectRef,
+    pub value_error: PyObjectRef,
+    pub unicode_encode_error: PyObjectRef,
+    pub unicode_decode_error: PyObjectRef,
+    pub unicode_translate_error: PyObjectRef,
+    /// Every builtin exception type with its name, for `builtins`.
+    pub all: Vec<(&'static str, PyObjectRef)>,
+}
+
+impl ExceptionTypes {
+    pub(crate) fn new(types: &Types) -> Self {
+        let mut all: Vec<(&'static str, PyObjectRef)> = vec![];
+        for (name, base) in HIERARCHY {
+            let base = match all.iter().find(|(n, _)| n == base) {
+                Some((_, base)) => base.clone(),
+                None => types.object_type.clone(),
+            };
+            all.push((name, exception_type(types, name, &base)));
+        }
+        let os_error = all.iter().find(|(n, _)| *n == "OSError").expect("OSError").1.clone();
+        all.extend(OS_ERROR_ALIASES.iter().map(|alias| (*alias, os_error.clone())));
+        all.push(("ExceptionGroup", exception_group_type(types, &all)));
+        let get = |name: &str| all.iter().find(|(n, _)| *n == name).expect("builtin exception").1.clone();
+        Self {
+            base_exception: get("BaseException"),
+            exception: get("Exception"),
+            base_exception_group: get("BaseExceptionGroup"),
+            exception_group: get("ExceptionGroup"),
+            system_exit: get("SystemExit"),
+            generator_exit: get("GeneratorExit"),
+            stop_iteration: get("StopIteration"),
+            stop_async_iteration: get("StopAsyncIteration"),
+            overflow_error: get("OverflowError"),
+            zero_division_error: get("ZeroDivisionError"),
+            assertion_error: get("AssertionError"),
+            attribute_error: get("AttributeError"),
+            import_error: get("ImportError"),
+            module_not_found_error: get("ModuleNotFoundError"),
+            lookup_error: get("LookupError"),
+            index_error: get("IndexError"),
+            key_error: get("KeyError"),
+            memory_error: get("MemoryError"),
+            name_error: get("NameError"),
+            unbound_local_error: get("UnboundLocalError"),
+            os_error: get("OSError"),
+            runtime_error: get("RuntimeError"),
+            recursion_error: get("RecursionError"),
+            syntax_error: get("SyntaxError"),
+            indentation_error: get("IndentationError"),
+            tab_error: get("TabError"),
+            system_error: get("SystemError"),
+            type_error: get("TypeError"),
+            value_error: get("ValueError"),
+            unicode_encode_error: get("UnicodeEncodeError"),
+            unicode_decode_error: get("UnicodeDecodeError"),
+            unicode_translate_error: get("UnicodeTranslateError"),
+            all,
+        }
+    }
+
+    /// The builtin exception class called `name`.
+    fn named(&self, name: &str) -> &PyObjectRef {
+        &self.all.iter().find(|(n, _)| *n == name).expect("builtin exception").1
+    }
+}
+
+fn exception_type(types: &Types, name: &str, base: &PyObjectRef) -> PyObjectRef {
+    let dict = PyObject::new(Payload::Dict(RefCell::new(PyDict::new())), types.dict_type.clone(), None);
+    let ty = PyType {
+        name: RefCell::new(name.to_string()),
+        qualname: RefCell::new(name.to_string()),
+        bases: RefCell::new(vec![base.clone()]),
+        mro: RefCell::new(vec![]),
+        new: Some(exception_new),
+        heap: false,
+        instance_dict: true,
+        slots: 0,
+    };
+    let class = PyObject::new(Payload::Type(ty), types.type_type.clone(), Some(dict));
+    let mut mro = vec![class.clone()];
+    mro.extend(base.as_type().expect("type").mro.borrow().iter().cloned());
+    *class.as_type().expect("type").mro.borrow_mut() = mro;
+    class
+}
+
+/// `ExceptionGroup`, the one builtin exception with two bases: `BaseExceptionGroup` and
+/// `Exception`.
+fn exception_group_type(types: &Types, all: &[(&str, PyObjectRef)]) -> PyObjectRef {
+    let get = |name: &str| all.iter().find(|(n, _)| *n == name).expect("builtin exception").1.clone();
+    let (group, exception) = (get("BaseExceptionGroup"), get("Exception"));
+    let class = exception_type(types, "ExceptionGroup", &group);
+    let ty = class.as_type().expect("type");
+    *ty.bases.borrow_mut() = vec![group.clone(), exception.clone()];
+    let mut mro = vec![class.clone(), group];
+    mro.extend(exception.as_type().expect("type").mro.borrow().iter().cloned());
+    *ty.mro.borrow_mut() = mro;
+    class
+}
+
+/// `BaseException.__new__`, which keeps the positional arguments as `args`.  `OSError`
+/// creates the subclass for its errno instead, and exception groups check their arguments.
+fn exception_new(vm: &VirtualMachine, mut args: FuncArgs) -> PyResult {
+    let mut class = args.args.remove(0);
+    let mut group = None;
+    if vm.is_subclass(&class, &vm.exceptions.base_exception_group) {
+        let (group_class, parts) = vm.exception_group_new(&class, &args)?;
+        class = group_class;
+        group = Some(parts);
+    }
+    if class.is(&vm.exceptions.os_error) && (2..=5).contains(&args.args.len()) {
+        let errno = args.args[0].as_int().and_then(|errno| errno.to_i64());
+        if let Some((_, name)) = ERRNO_CLASSES.iter().find(|(code, _)| Some(*code) == errno) {
+            class = vm.exceptions.named(name).clone();
+        }
+    }
+    let state = ExceptionState {
+        args: RefCell::new(vm.new_tuple(args.args.clone())),
+        traceback: RefCell::new(vec![]),
+        cause: RefCell::new(None),
+        context: RefCell::new(None),
+        suppress_context: Cell::new(false),
+        group,
+    };
+    let exc = vm.new_object(Payload::Exception(state), &class);
+    // A class's own `__init__` takes the keywords instead.
+    let kwargs = match vm.lookup_user_method(&class, "__init__") {
+        Some(_) => vec![],
+        None => args.kwargs,
+    };
+    vm.init_exception(&exc, args.args, kwargs)?;
+    Ok(exc)
+}
+
+fn is_exception(payload: &Payload) -> bool {
+    matches!(payload, Payload::Exception(_))
+}
+
+fn exception_init(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (exc, args) = receiver(vm, args, "BaseException", "__init__", is_exception)?;
+    vm.init_exception(&exc, args.args, args.kwargs)?;
+    Ok(vm.none())
+}
+
+fn exception_method_str(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (exc, args) = receiver(vm, args, "BaseException", "__str__", is_exception)?;
+    check_args(vm, "BaseException.__str__", &args, 0, 0)?;
+    Ok(vm.new_str(vm.exception_str(&exc)?))
+}
+
+fn exception_method_repr(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (exc, args) = receiver(vm, args, "BaseException", "__repr__", is_exception)?;
+    check_args(vm, "BaseException.__repr__", &args, 0, 0)?;
+    Ok(vm.new_str(vm.exception_repr(&exc)?))
+}
+
+fn exception_with_traceback(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (exc, args) = receiver(vm, args, "BaseException", "with_traceback", is_exception)?;
+    check_args(vm, "BaseException.with_traceback", &args, 1, 1)?;
+    vm.set_traceback(&exc, &args.args[0])?;
+    Ok(exc)
+}
+
+fn exception_add_note(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (exc, args) = receiver(vm, args, "BaseException", "add_note", is_exception)?;
+    check_args(vm, "BaseException.add_note", &args, 1, 1)?;
+    let note = &args.args[0];
+    if note.as_str().is_none() {
+        return Err(vm.new_type_error(format!("note must be a str, not '{}'", vm.class_name(note))));
+    }
+    let notes = match vm.get_attribute_opt(&exc, "__notes__")? {
+        Some(notes) => notes,
+        None => {
+            let notes = vm.new_list(vec![]);
+            vm.set_attribute(&exc, "__notes__", notes.clone())?;
+            notes
+        }
+    };
+    match &notes.payload {
+        Payload::List(items) => items.borrow_mut().push(note.clone()),
+        _ => return Err(vm.new_type_error("Cannot add note: __notes__ is not a list".to_string())),
+    }
+    Ok(vm.none())
+}
+
+/// The argument `idx` of an exception, or None.
+fn arg_or_none(vm: &VirtualMachine, args: &[PyObjectRef], idx: usize) -> PyObjectRef {
+    args.get(idx).cloned().unwrap_or_else(|| vm.none())
+}
+
+impl VirtualMachine {
+    pub(crate) fn add_exception_methods(&self) {
+        let base = &self.exceptions.base_exception;
+        self.add_method(base, "__init__", exception_init);
+        self.add_method(base, "__str__", exception_method_str);
+        self.add_method(base, "__repr__", exception_method_repr);
+        self.add_method(base, "with_traceback", exception_with_traceback);
+        self.add_method(base, "add_note", exception_add_note);
+        self.add_exception_group_methods();
+    }
+
+    /// `BaseException.__init__`: sets `args`, and the attributes that some builtin
+    /// exceptions take from their arguments and keywords.
+    fn init_exception(
+        &self,
+        exc: &PyObjectRef,
+        mut args: Vec<PyObjectRef>,
+        kwargs: Vec<(String, PyObjectRef)>,
+    ) -> PyResult<()> {
+        let is = |class: &PyObjectRef| self.isinstance(exc, class);
+        let exceptions = &self.exceptions;
+        let keywords: &[&str] = if is(&exceptions.attribute_error) {
+            &["name", "obj"]
+        } else if is(&exceptions.name_error) {
+            &["name"]
+        } else if is(&exceptions.import_error) {
+            &["name", "path"]
+        } else {
+            &[]
+        };
+        for (key, _) in &kwargs {
+            if !keywords.contains(&key.as_str()) {
+                let class = self.class_name(exc);
+                return Err(self.new_type_error(match keywords {
+                    [] => format!("{}() takes no keyword arguments", class),
+                    _ => format!("'{}' is an invalid keyword argument for {}()", key, class),
+                }));
+            }
+        }
+        let mut fields: Vec<(&str, PyObjectRef)> = keywords
+            .iter()
+            .map(|keyword| {
+                let value = kwargs.iter().find(|(key, _)| key == keyword).map(|(_, value)| value.clone());
+                (*keyword, value.unwrap_or_else(|| self.none()))
+            })
+            .collect();
+        if is(&exceptions.import_error) {
+            let msg = if args.len() == 1 { args[0].clone() } else { self.none() };
+            fields.push(("msg", msg));
+        }
+        if is(&exceptions.os_error) {
+            let known = (2..=5).contains(&args.len());
+            let field = |idx: usize| if known { arg_or_none(self, &args, idx) } else { self.none() };
+            fields.extend([("errno", field(0)), ("strerror", field(1)), ("filename", field(2)), ("filename2", field(4))]);
+            // The file names are attributes only.
+            if known && args.len() > 2 {
+                args.truncate(2);
+            }
+        }
+        let unicode_fields: &[&str] = if is(&exceptions.unicode_translate_error) {
+            &["object", "start", "end", "reason"]
+        } else if is(&exceptions.unicode_encode_error) || is(&exceptions.unicode_decode_error) {
+            &["encoding", "object", "start", "end", "reason"]
+        } else {
+            &[]
+        };
+        if !unicode_fields.is_empty() && args.len() == unicode_fields.len() {
+            fields.extend(unicode_fields.iter().zip(&args).map(|(name, value)| (*name, value.clone())));
+        }
+        if is(&exceptions.syntax_error) {
+            let details = args.get(1).and_then(|details| details.as_tuple().map(<[_]>::to_vec)).unwrap_or_default();
+            fields.push(("msg", arg_or_none(self, &args, 0)));
+            let names = ["filename", "lineno", "offset", "text", "end_lineno", "end_offset"];
+            for (idx, name) in names.into_iter().enumerate() {
+                fields.push((name, arg_or_none(self, &details, idx)));
+            }
+            fields.push(("print_file_and_line", self.none()));
+        }
+        let state = exc.as_exception().expect("exception");
+        *state.args.borrow_mut() = self.new_tuple(args);
+        if let Some(dict) = &exc.dict {
+            for (name, value) in fields {
+                self.dict_set_str(dict, name, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes the exception being handled the `__context__` of `exc`, raised while handling
+    /// it, unless `exc` already has one.  A chain that would loop back to `exc` is cut.
+    pub(crate) fn chain_exception(&self, exc: &PyObjectRef) {
+        let Some(state) = exc.as_exception() else { return };
+        let Some(handled) = self.handled.borrow().clone() else { return };
+        if handled.is(exc) || state.context.borrow().is_some() {
+            return;
+        }
+        let mut current = handled.clone();
+        while let Some(context) = current.as_exception().and_then(|s| s.context.borrow().clone()) {
+            if context.is(exc) {
+                *current.as_exception().expect("exception").context.borrow_mut() = None;
+                break;
+            }
+            current = context;
+        }
+        *state.context.borrow_mut() = Some(handled);
+    }
+
+    /// `exc.__traceback__`, or None for an exception that was never raised.
+    pub(crate) fn new_traceback(&self, entries: Vec<TracebackEntry>) -> PyObjectRef {
+        if entries.is_empty() {
+            return self.none();
+        }
+        self.new_object(Payload::Traceback(entries), &self.types.traceback_type)
+    }
+
+    /// `exc.__traceback__ = traceback` and `exc.with_traceback(traceback)`.
+    pub(crate) fn set_traceback(&self, exc: &PyObjectRef, traceback: &PyObjectRef) -> PyResult<()> {
+        let entries = match &traceback.payload {
+            Payload::Traceback(entries) => entries.clone(),
+            Payload::None => vec![],
+            _ => return Err(self.new_type_error("__traceback__ must be a traceback or None".to_string())),
+        };
+        *exc.as_exception().expect("exception").traceback.borrow_mut() = entries;
+        Ok(())
+    }
+
+    /// An instance of the exception class `class` with `args`.
+    pub fn new_exception(&self, class: &PyObjectRef, args: Vec<PyObjectRef>) -> PyObjectRef {
+        let mut all = vec![class.clone()];
+        all.extend(args);
+        exception_new(self, FuncArgs::new(all)).expect("exception creation")
+    }
+
+    pub fn new_exception_msg(&self, class: &PyObjectRef, msg: String) -> PyObjectRef {
+        self.new_exception(class, vec![self.new_str(msg)])
+    }
+
+    pub fn new_type_error(&self, msg: String) -> PyObjectRef {
+        self.new_exception_msg(&self.exceptions.type_error, msg)
+    }
+
+    pub fn new_value_error(&self, msg: String) -> PyObjectRef {
+        self.new_exception_msg(&self.exceptions.value_error, msg)
+    }
+
+    pub fn new_index_error(&self, msg: String) -> PyObjectRef {
+        self.new_exception_msg(&self.exceptions.index_error, msg)
+    }
+
+    pub fn new_overflow_error(&self, msg: String) -> PyObjectRef {
+        self.new_exception_msg(&self.exceptions.overflow_error, msg)
+    }
+
+    pub fn new_zero_division_error(&self, msg: String) -> PyObjectRef {
+        self.new_exception_msg(&self.exceptions.zero_division_error, msg)
+    }
+
+    pub fn new_runtime_error(&self, msg: String) -> PyObjectRef {
+        self.new_exception_msg(&self.exceptions.runtime_error, msg)
+    }
+
+    pub fn new_name_error(&self, name: &str) -> PyObjectRef {
+        self.new_exception_msg(&self.exceptions.name_error, format!("name '{}' is not defined", name))
+    }
+
+    pub fn new_key_error(&self, key: PyObjectRef) -> PyObjectRef {
+        self.new_exception(&self.exceptions.key_error, vec![key])
+    }
+
+    pub fn new_attribute_error(&self, obj: &PyObjectRef, name: &str) -> PyObjectRef {
+        let msg = match obj.as_type() {
+            Some(ty) => format!("type object '{}' has no attribute '{}'", ty.name(), name),
+            None => match &obj.payload {
+                Payload::Module { name: module } => format!("module '{}' has no attribute '{}'", module, name),
+                _ => format!("'{}' object has no attribute '{}'", self.class_name(obj), name),
+            },
+        };
+        self.new_exception_msg(&self.exceptions.attribute_error, msg)
+    }
+
+    pub fn new_stop_iteration(&self, value: Option<PyObjectRef>) -> PyObjectRef {
+        let args = value.into_iter().collect();
+        self.new_exception(&self.exceptions.stop_iteration, args)
+    }
+
+    /// `raise value`: an exception instance is raised as it is and an exception class is
+    /// called with no arguments.
+    pub fn make_exception(&self, value: &PyObjectRef) -> PyResult {
+        if value.as_type().is_some() {
+            if !self.is_subclass(value, &self.exceptions.base_exception) {
+                return Err(self.new_type_error("exceptions must derive from BaseException".to_string()));
+            }
+            let exc = self.call(value, FuncArgs::default())?;
+            if !self.isinstance(&exc, &self.exceptions.base_exception) {
+                return Err(self.new_type_error(format!(
+                    "calling {} should have returned an instance of BaseException, not {}",
+                    self.repr(value)?,
+                    self.class_name(&exc)
+                )));
+            }
+            return Ok(exc);
+        }
+        if self.isinstance(value, &self.exceptions.base_exception) {
+            return Ok(value.clone());
+        }
+        Err(self.new_type_error("exceptions must derive from BaseException".to_string()))
+    }
+
+    /// Whether `exc` is matched by an `except` clause naming `class`, a class or tuple of
+    /// classes.
+    pub fn exception_matches(&self, exc: &PyObjectRef, class: &PyObjectRef) -> PyResult<bool> {
+        let classes = match class.as_tuple() {
+            Some(classes) => classes.to_vec(),
+            None => vec![class.clone()],
+        };
+        for class in &classes {
+            if class.as_type().is_none() || !self.is_subclass(class, &self.exceptions.base_exception) {
+                return Err(self.new_type_error(
+                    "catching classes that do not inherit from BaseException is not allowed".to_string(),
+                ));
+            }
+        }
+        Ok(classes.iter().any(|class| self.isinstance(exc, class)))
+    }
+
+    /// The arguments of an exception, as a tuple.
+    pub fn exception_args(&self, exc: &PyObjectRef) -> PyObjectRef {
+        match exc.as_exception() {
+            Some(state) => state.args.borrow().clone(),
+            None => self.new_tuple(vec![]),
+        }
+    }
+
+    /// `str(exc)`: the only argument, or the tuple of them.
+    pub fn exception_str(&self, exc: &PyObjectRef) -> PyResult<String> {
+        if self.isinstance(exc, &self.exceptions.syntax_error) {
+            if let Some(msg) = self.syntax_error_attribute(exc, "msg") {
+                return self.syntax_error_str(exc, &msg);
+            }
+        }
+        if let Some(text) = self.os_error_str(exc)? {
+            return Ok(text);
+        }
+        if let Some(text) = self.unicode_error_str(exc)? {
+            return Ok(text);
+        }
+        if let Some((message, exceptions)) = exc.as_exception().and_then(|state| state.group.clone()) {
+            let count = exceptions.as_tuple().expect("exceptions tuple").len();
+            let plural = if count == 1 { "" } else { "s" };
+            return Ok(format!("{} ({} sub-exception{})", self.to_str(&message)?, count, plural));
+        }
+        let args = self.exception_args(exc);
+        let args = args.as_tuple().expect("args tuple");
+        match args {
+            [] => Ok(String::new()),
+            [arg] if self.isinstance(exc, &self.exceptions.key_error) => self.repr(arg),
+            [arg] => self.to_str(arg),
+            _ => self.repr(&self.new_tuple(args.to_vec())),
+        }
+    }
+
+    /// `str()` of an OSError with an errno: `[Errno 2] No such file or directory: 'name'`.
+    fn os_error_str(&self, exc: &PyObjectRef) -> PyResult<Option<String>> {
+        if !self.isinstance(exc, &self.exceptions.os_error) {
+            return Ok(None);
+        }
+        let field = |name| self.syntax_error_attribute(exc, name);
+        let (Some(errno), Some(strerror)) = (field("errno"), field("strerror")) else { return Ok(None) };
+        let mut text = format!("[Errno {}] {}", self.to_str(&errno)?, self.to_str(&strerror)?);
+        if let Some(filename) = field("filename") {
+            text.push_str(&format!(": {}", self.repr(&filename)?));
+            if let Some(filename2) = field("filename2") {
+                text.push_str(&format!(" -> {}", self.repr(&filename2)?));
+            }
+        }
+        Ok(Some(text))
+    }
+
+    /// `str()` of a UnicodeEncodeError, UnicodeDecodeError or UnicodeTranslateError made
+    /// with all its fields.
+    fn unicode_error_str(&self, exc: &PyObjectRef) -> PyResult<Option<String>> {
+        let field = |name| self.syntax_error_attribute(exc, name);
+        let (Some(object), Some(start), Some(end), Some(reason)) = (field("object"), field("start"), field("end"), field("reason"))
+        else {
+            return Ok(None);
+        };
+        let (Some(start), Some(end)) = (start.as_int().and_then(|s| s.to_i64()), end.as_int().and_then(|e| e.to_i64())) else {
+            return Ok(None);
+        };
+        let reason = self.to_str(&reason)?;
+        let codec = match field("encoding") {
+            Some(encoding) => format!("'{}' codec can't", self.to_str(&encoding)?),
+            None => "can't".to_string(),
+        };
+        let single = end == start + 1;
+        let escape = |c: char| match c as u32 {
+            code @ 0..=0xff => format!("\\x{:02x}", code),
+            code @ 0x100..=0xffff => format!("\\u{:04x}", code),
+            code => format!("\\U{:08x}", code),
+        };
+        let verb = if self.isinstance(exc, &self.exceptions.unicode_encode_error) { "encode" } else { "translate" };
+        let text = match &object.payload {
+            Payload::Bytes(bytes) if single => match bytes.get(start as usize) {
+                Some(byte) => format!("{} decode byte 0x{:02x} in position {}: {}", codec, byte, start, reason),
+                None => return Ok(None),
+            },
+            Payload::Bytes(_) => format!("{} decode bytes in position {}-{}: {}", codec, start, end - 1, reason),
+            Payload::Str(text) if single => match text.as_str().chars().nth(start as usize) {
+                Some(c) => format!("{} {} character '{}' in position {}: {}", codec, verb, escape(c), start, reason),
+                None => return Ok(None),
+            },
+            Payload::Str(_) => format!("{} {} characters in position {}-{}: {}", codec, verb, start, end - 1, reason),
+            _ => return Ok(None),
+        };
+        Ok(Some(text))
+    }
+
+    /// `str()` of a SyntaxError: the message, with the file's name and the line.
+    fn syntax_error_str(&self, exc: &PyObjectRef, msg: &PyObjectRef) -> PyResult<String> {
+        let msg = self.to_str(msg)?;
+        let filename = self.syntax_error_attribute(exc, "filename").and_then(|f| f.as_str().map(str::to_string));
+        let lineno = self.syntax_error_attribute(exc, "lineno").and_then(|l| l.as_int());
+        let filename = filename.map(|f| f.rsplit('/').next().unwrap_or_default().to_string());
+        Ok(match (filename, lineno) {
+            (Some(filename), Some(lineno)) => format!("{} ({}, line {})", msg, filename, lineno),
+            (Some(filename), None) => format!("{} ({})", msg, filename),
+            (None, Some(lineno)) => format!("{} (line {})", msg, lineno),
+            (None, None) => msg,
+        })
+    }
+
+    pub fn exception_repr(&self, exc: &PyObjectRef) -> PyResult<String> {
+        let args = self.exception_args(exc);
+        let args = args.as_tuple().expect("args tuple");
+        let name = self.class_name(exc);
+        match args {
+            [arg] => Ok(format!("{}({})", name, self.repr(arg)?)),
+            _ => Ok(format!("{}{}", name, self.repr(&self.new_tuple(args.to_vec()))?)),
+        }
+    }
+
+    /// A SyntaxError (or subclass) for `source`, at the 0-based byte column `column` of
+    /// line `line` and up to `end`, with the attributes CPython gives it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_syntax_error(
+        &self,
+        class: &PyObjectRef,
+        msg: String,
+        filename: &str,
+        source: &str,
+        line: usize,
+        column: usize,
+        end: Option<usize>,
+    ) -> PyObjectRef {
+        let text = source.lines().nth(line.saturating_sub(1)).unwrap_or("");
+        // CPython counts offsets in characters, from 1.
+        let offset = |col: usize| text.get(..col).map_or(col, |prefix| prefix.chars().count()) + 1;
+        let offset_value = self.new_int(offset(column) as i64);
+        let end_offset = match end {
+            Some(end) => self.new_int(offset(end) as i64),
+            None => self.none(),
+        };
+        let text_value = self.new_str(format!("{}\n", text));
+        let details = self.new_tuple(vec![
+            self.new_str(filename.to_string()),
+            self.new_int(line as i64),
+            offset_value.clone(),
+            text_value.clone(),
+            self.new_int(line as i64),
+            end_offset.clone(),
+        ]);
+        let exc = self.new_exception(class, vec![self.new_str(msg.clone()), details]);
+        let dict = exc.dict.as_ref().expect("exception dict");
+        self.dict_set_str(dict, "msg", self.new_str(msg));
+        self.dict_set_str(dict, "filename", self.new_str(filename.to_string()));
+        self.dict_set_str(dict, "lineno", self.new_int(line as i64));
+        self.dict_set_str(dict, "offset", offset_value);
+        self.dict_set_str(dict, "text", text_value);
+        self.dict_set_str(dict, "end_lineno", self.new_int(line as i64));
+        self.dict_set_str(dict, "end_offset", end_offset);
+        self.dict_set_str(dict, "print_file_and_line", self.none());
+        exc
+    }
+
+    /// An attribute a SyntaxError keeps in its dict.
+    fn syntax_error_attribute(&self, exc: &PyObjectRef, name: &str) -> Option<PyObjectRef> {
+        let value = self.dict_get_str(exc.dict.as_ref()?, name)?;
+        (!self.is_none(&value)).then_some(value)
+    }
+
+    /// The lines CPython prints for a SyntaxError instead of a traceback entry: the file and
+    /// line, the source line and a caret under the error.
+    fn syntax_error_location(&self, exc: &PyObjectRef) -> String {
+        let attribute = |name| self.syntax_error_attribute(exc, name);
+        let int = |name| attribute(name).and_then(|value| value.as_int()).and_then(|value| value.to_i64());
+        let filename = attribute("filename").and_then(|f| f.as_str().map(str::to_string));
+        let filename = filename.unwrap_or_else(|| "<string>".to_string());
+        let mut out = format!("  File \"{}\", line {}\n", filename, int("lineno").unwrap_or(0));
+        let Some(text) = attribute("text").and_then(|t| t.as_str().map(str::to_string)) else {
+            return out;
+        };
+        let line = text.trim_end_matches('\n');
+        let stripped = line.trim_start_matches([' ', '\n', '\x0c']);
+        let spaces = (line.chars().count() - stripped.chars().count()) as i64;
+        out.push_str(&format!("    {}\n", stripped));
+        if let Some(offset) = int("offset") {
+            let end_offset = match int("end_offset") {
+                Some(end) if end != 0 && end != -1 && end != offset => end,
+                _ => offset + 1,
+            };
+            let column = offset - 1 - spaces;
+            if column >= 0 {
+                let padding: String = stripped
+                    .chars()
+                    .take(column as usize)
+                    .map(|c| if c.is_whitespace() { c } else { ' ' })
+                    .collect();
+                let width = (end_offset - offset).max(1) as usize;
+                out.push_str(&format!("    {}{}\n", padding, "^".repeat(width)));
+            }
+        }
+        out
+    }
+
+    /// Prints an exception that nothing caught the way CPython does, to stderr, after the
+    /// exceptions it was chained to.
+    pub fn print_exception(&self, exc: &PyObjectRef) {
+        let mut report = Report { out: String::new(), seen: vec![], depth: 0, need_close: false };
+        self.format_exception_chain(exc, &mut report);
+        let _ = self.flush_stdout();
+        let _ = std::io::stderr().write_all(report.out.as_bytes());
+    }
+
+    /// The report of `exc`, preceded by its cause or context.
+    fn format_exception_chain(&self, exc: &PyObjectRef, report: &mut Report) {
+        report.seen.push(exc.clone());
+        if let Some(state) = exc.as_exception() {
+            let cause = state.cause.borrow().clone();
+            let context = state.context.borrow().clone().filter(|_| !state.suppress_context.get());
+            let chained = match (cause, context) {
+                (Some(cause), _) => Some((cause, "The above exception was the direct cause of the following exception:")),
+                (None, Some(context)) => {
+                    Some((context, "During handling of the above exception, another exception occurred:"))
+                }
+                (None, None) => None,
+            };
+            if let Some((earlier, message)) = chained.filter(|(earlier, _)| !report.seen.iter().any(|s| s.is(earlier))) {
+                let need_close = report.need_close;
+                self.format_exception_chain(&earlier, report);
+                report.need_close = need_close;
+                report.line("");
+                report.line(message);
+                report.line("");
+            }
+        }
+        match self.is_exception_group(exc) {
+            true => self.format_exception_group(exc, report),
+            false => self.format_exception(exc, report),
+        }
+    }
+
+    /// An exception group and, in numbered boxes below it, the exceptions in it.
+    fn format_exception_group(&self, exc: &PyObjectRef, report: &mut Report) {
+        if report.depth > MAX_GROUP_DEPTH {
+            report.line(&format!("... (max_group_depth is {})", MAX_GROUP_DEPTH));
+            return;
+        }
+        if report.depth == 0 {
+            report.depth = 1;
+        }
+        self.format_exception(exc, report);
+        let (_, exceptions) = self.group_parts(exc);
+        let exceptions = exceptions.as_tuple().expect("exceptions tuple");
+        let shown = exceptions.len().min(MAX_GROUP_WIDTH + 1);
+        report.need_close = false;
+        for idx in 0..shown {
+            let last = idx == shown - 1;
+            // The box is closed by the innermost group that ends here.
+            if last {
+                report.need_close = true;
+            }
+            let corner = if idx == 0 { "+-" } else { "  " };
+            let label = if idx < MAX_GROUP_WIDTH { (idx + 1).to_string() } else { "...".to_string() };
+            report.indent();
+            report.out.push_str(&format!("{}+---------------- {} ----------------\n", corner, label));
+            report.depth += 1;
+            if idx < MAX_GROUP_WIDTH {
+                self.format_exception_chain(&exceptions[idx], report);
+            } else {
+                let remaining = exceptions.len() - MAX_GROUP_WIDTH;
+                report.line(&format!("and {} more exception{}", remaining, if remaining > 1 { "s" } else { "" }));
+            }
+            if last && report.need_close {
+                report.indent();
+                report.out.push_str("+------------------------------------\n");
+                report.need_close = false;
+            }
+            report.depth -= 1;
+        }
+        if report.depth == 1 {
+            report.depth = 0;
+        }
+    }
+
+    /// The traceback, summary line and notes of one exception.
+    fn format_exception(&self, exc: &PyObjectRef, report: &mut Report) {
+        if let Some(state) = exc.as_exception() {
+            let traceback = state.traceback.borrow();
+            if !traceback.is_empty() {
+                match self.is_exception_group(exc) {
+                    true if report.depth == 1 => {
+                        report.indent();
+                        report.out.push_str("+ Exception Group Traceback (most recent call last):\n");
+                    }
+                    true => report.line("Exception Group Traceback (most recent call last):"),
+                    false => report.line("Traceback (most recent call last):"),
+                }
+                for entry in traceback.iter().rev() {
+                    report.line(&format!("  File \"{}\", line {}, in {}", entry.filename, entry.line, entry.name));
+                    if let Some(line) = source_line(&entry.filename, entry.line) {
+                        report.line(&format!("    {}", line));
+                    }
+                }
+            }
+        }
+        let is_syntax_error = self.isinstance(exc, &self.exceptions.syntax_error);
+        if is_syntax_error {
+            for line in self.syntax_error_location(exc).lines() {
+                report.line(line);
+            }
+        }
+        report.line(&self.exception_summary(exc, is_syntax_error));
+        if let Ok(Some(notes)) = self.get_attribute_opt(exc, "__notes__") {
+            if let Payload::List(notes) = &notes.payload {
+                for note in notes.borrow().iter() {
+                    let text = self.to_str(note).unwrap_or_else(|_| "<note str() failed>".to_string());
+                    for line in text.split_inclusive('\n') {
+                        report.margin();
+                        report.out.push_str(line);
+                    }
+                    report.out.push('\n');
+                }
+            }
+        }
+    }
+
+    /// The last line of a traceback: the class and the message.
+    fn exception_summary(&self, exc: &PyObjectRef, is_syntax_error: bool) -> String {
+        let class = exc.class();
+        let ty = class.as_type().expect("type");
+        let mut name = ty.qualname.borrow().clone();
+        if let Some(module) = self.type_lookup(&class, "__module__").and_then(|m| m.as_str().map(str::to_string)) {
+            if module != "__main__" && module != "builtins" {
+                name = format!("{}.{}", module, name);
+            }
+        }
+        let msg = match self.syntax_error_attribute(exc, "msg") {
+            Some(msg) if is_syntax_error => self.to_str(&msg),
+            _ => self.exception_str(exc),
+        };
+        match msg {
+            Ok(msg) if msg.is_empty() => name,
+            Ok(msg) => format!("{}: {}", name, msg),
+            Err(_) => format!("{}: <exception str() failed>", name),
+        }
+    }
+}
+
+/// The text of a traceback being written, for an exception and the exceptions chained to
+/// it or grouped in it.
+struct Report {
+    out: String,
+    /// The exceptions written so far, so that a chain that loops ends.
+    seen: Vec<PyObjectRef>,
+    /// How many exception groups the exception being written is nested in.
+    depth: usize,
+    /// Whether the box around the last exception of a group still needs its bottom line.
+    need_close: bool,
+}
+
+impl Report {
+    fn indent(&mut self) {
+        self.out.push_str(&" ".repeat(2 * self.depth));
+    }
+
+    /// The indentation and the left edge of a box, inside a group.
+    fn margin(&mut self) {
+        self.indent();
+        if self.depth > 0 {
+            self.out.push_str("| ");
+        }
+    }
+
+    fn line(&mut self, text: &str) {
+        self.margin();
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+}
+
+/// Line `line` of the file `filename`, without its indentation, as tracebacks show it.
+fn source_line(filename: &str, line: usize) -> Option<String> {
+    let source = std::fs::read(filename).ok()?;
+    let source = String::from_utf8_lossy(&source);
+    let text = source.lines().nth(line.checked_sub(1)?)?.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
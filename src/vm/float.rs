@@ -0,0 +1,281 @@
+//! Float operations that need exact arithmetic: `round()` to decimal places, the
+//! `float.hex()` and `float.fromhex()` conversions and `as_integer_ratio()`.
+//!
+//! A finite float is exactly `mantissa * 2**exponent`, so these work on that integer and
+//! round only once, at the end.
+
+use std::cmp::Ordering;
+
+use crate::bigint::BigInt;
+
+use super::builtins::check_args;
+use super::methods::receiver;
+use super::object::{FuncArgs, Payload, PyObjectRef, PyResult};
+use super::VirtualMachine;
+
+/// Past this many decimal places every float is already exact.
+const MAX_ROUND_DIGITS: i64 = 323;
+/// Rounding to more than this many places before the point gives zero for every float.
+const MIN_ROUND_DIGITS: i64 = -308;
+
+/// The integer mantissa and power of two of a finite, non-negative float.
+fn float_parts(value: f64) -> (u64, i64) {
+    let bits = value.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i64;
+    let fraction = bits & ((1 << 52) - 1);
+    match exponent {
+        0 => (fraction, -1074),
+        _ => (fraction | (1 << 52), exponent - 1075),
+    }
+}
+
+/// `mantissa * 2**exponent` where the product is known to be representable, scaled in two
+/// steps so that neither power of two overflows or underflows on its own.
+fn scale(mantissa: f64, exponent: i64) -> f64 {
+    let half = exponent / 2;
+    mantissa * 2f64.powi(half as i32) * 2f64.powi((exponent - half) as i32)
+}
+
+/// Rounds `quotient + rem / divisor` to an integer, halves to even.
+fn round_half_even(quotient: BigInt, rem: &BigInt, divisor: &BigInt) -> BigInt {
+    let round_up = match (rem + rem).cmp(divisor) {
+        Ordering::Greater => true,
+        Ordering::Equal => quotient.is_odd(),
+        Ordering::Less => false,
+    };
+    if round_up {
+        &quotient + &BigInt::from(1)
+    } else {
+        quotient
+    }
+}
+
+/// `round(value, ndigits)`: the float nearest to the exact value rounded to `ndigits`
+/// decimal places, halves to even.  None when the result overflows.
+pub fn round_float(value: f64, ndigits: i64) -> Option<f64> {
+    if !value.is_finite() || value == 0.0 || ndigits > MAX_ROUND_DIGITS {
+        return Some(value);
+    }
+    if ndigits < MIN_ROUND_DIGITS {
+        return Some(0.0 * value);
+    }
+    let (mantissa, exponent) = float_parts(value.abs());
+    let ten_power = BigInt::from(10).pow(ndigits.unsigned_abs());
+    let mut numerator = BigInt::from_u64(mantissa);
+    let mut denominator = BigInt::from(1);
+    if exponent >= 0 {
+        numerator = &numerator << exponent as u64;
+    } else {
+        denominator = &denominator << (-exponent) as u64;
+    }
+    if ndigits >= 0 {
+        numerator = &numerator * &ten_power;
+    } else {
+        denominator = &denominator * &ten_power;
+    }
+    let (quotient, rem) = numerator.div_mod_floor(&denominator).expect("non-zero denominator");
+    let rounded = round_half_even(quotient, &rem, &denominator);
+    // Reading the decimal digits back rounds correctly to the nearest float.
+    let text = format!("{}e{}", rounded.to_str_radix(10), -ndigits);
+    let magnitude: f64 = text.parse().expect("decimal float");
+    if magnitude.is_infinite() {
+        return None;
+    }
+    Some(if value < 0.0 { -magnitude } else { magnitude })
+}
+
+/// `float.hex()`: `0x1.<13 hex digits>p<exponent>`, or `0x0.` for subnormals.
+pub fn float_hex(value: f64) -> String {
+    if value.is_nan() {
+        return "nan".to_string();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    let sign = if value.is_sign_negative() { "-" } else { "" };
+    if value == 0.0 {
+        return format!("{}0x0.0p+0", sign);
+    }
+    let bits = value.abs().to_bits();
+    let exponent = (bits >> 52) as i64;
+    let fraction = bits & ((1 << 52) - 1);
+    let (lead, exponent) = if exponent == 0 { (0, -1022) } else { (1, exponent - 1023) };
+    format!("{}0x{}.{:013x}p{}{}", sign, lead, fraction, if exponent < 0 { '-' } else { '+' }, exponent.abs())
+}
+
+/// Why `float.fromhex()` failed.
+pub enum FromHexError {
+    Invalid,
+    Overflow,
+}
+
+/// `float.fromhex(text)`, rounding the exact value to the nearest float.
+pub fn float_fromhex(text: &str) -> Result<f64, FromHexError> {
+    let trimmed = text.trim();
+    let (negative, unsigned) = match trimmed.as_bytes().first() {
+        Some(b'-') => (true, &trimmed[1..]),
+        Some(b'+') => (false, &trimmed[1..]),
+        _ => (false, trimmed),
+    };
+    let signed = |magnitude: f64| if negative { -magnitude } else { magnitude };
+    let lower = unsigned.to_ascii_lowercase();
+    if lower == "inf" || lower == "infinity" {
+        return Ok(signed(f64::INFINITY));
+    }
+    if lower == "nan" {
+        return Ok(signed(f64::NAN));
+    }
+    let body = lower.strip_prefix("0x").unwrap_or(&lower);
+    let (digits, power) = match body.split_once('p') {
+        Some((digits, power)) => {
+            let power = power.strip_prefix('+').unwrap_or(power);
+            let valid = !power.is_empty() && power.trim_start_matches('-').bytes().all(|b| b.is_ascii_digit());
+            if !valid || power.matches('-').count() > 1 {
+                return Err(FromHexError::Invalid);
+            }
+            // Exponents too large for an i64 overflow, or underflow to zero, either way.
+            let power = power.parse::<i64>().unwrap_or(if power.starts_with('-') { i64::MIN / 4 } else { i64::MAX / 4 });
+            (digits, power)
+        }
+        None => (body, 0),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    let all_digits = format!("{}{}", whole, fraction);
+    if all_digits.is_empty() || !all_digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(FromHexError::Invalid);
+    }
+    let mantissa = BigInt::from_str_radix(&all_digits, 16).expect("hex digits");
+    if mantissa.is_zero() {
+        return Ok(signed(0.0));
+    }
+    let exponent = power.saturating_sub(4 * fraction.len() as i64);
+    // The value is in [2**top, 2**(top + 1)); subnormals keep fewer than 53 bits.
+    let bits = mantissa.bit_length() as i64;
+    let top = exponent.saturating_add(bits - 1);
+    if top > 1023 {
+        return Err(FromHexError::Overflow);
+    }
+    let precision = if top >= -1022 { 53 } else { 53 - (-1022 - top) };
+    if precision < 0 {
+        return Ok(signed(0.0));
+    }
+    let shift = bits - precision;
+    let (rounded, exponent) = if shift > 0 {
+        let quotient = &mantissa >> shift as u64;
+        let rem = &mantissa - &(&quotient << shift as u64);
+        let divisor = &BigInt::from(1) << shift as u64;
+        (round_half_even(quotient, &rem, &divisor), exponent + shift)
+    } else {
+        (mantissa, exponent)
+    };
+    let magnitude = scale(rounded.to_f64().expect("53-bit mantissa"), exponent);
+    if magnitude.is_infinite() {
+        return Err(FromHexError::Overflow);
+    }
+    Ok(signed(magnitude))
+}
+
+fn is_float(payload: &Payload) -> bool {
+    matches!(payload, Payload::Float(_))
+}
+
+fn as_float(obj: &PyObjectRef) -> f64 {
+    match obj.payload {
+        Payload::Float(value) => value,
+        _ => unreachable!("not a float"),
+    }
+}
+
+fn float_method_hex(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (value, args) = receiver(vm, args, "float", "hex", is_float)?;
+    check_args(vm, "float.hex", &args, 0, 0)?;
+    let value = as_float(&value);
+    Ok(vm.new_str(float_hex(value)))
+}
+
+fn float_method_fromhex(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "float.fromhex", &args, 2, 2)?;
+    let (class, text) = (&args.args[0], &args.args[1]);
+    let text = vm.expect_str(text, "fromhex() argument")?;
+    let value = match float_fromhex(&text) {
+        Ok(value) => vm.new_float(value),
+        Err(FromHexError::Invalid) => {
+            return Err(vm.new_value_error("invalid hexadecimal floating-point string".to_string()))
+        }
+        Err(FromHexError::Overflow) => {
+            return Err(vm.new_overflow_error("hexadecimal value too large to represent as a float".to_string()))
+        }
+    };
+    if class.is(&vm.types.float_type) {
+        return Ok(value);
+    }
+    vm.call(class, FuncArgs::new(vec![value]))
+}
+
+fn float_as_integer_ratio(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (value, args) = receiver(vm, args, "float", "as_integer_ratio", is_float)?;
+    check_args(vm, "float.as_integer_ratio", &args, 0, 0)?;
+    let value = as_float(&value);
+    if value.is_infinite() {
+        return Err(vm.new_overflow_error("cannot convert Infinity to integer ratio".to_string()));
+    }
+    if value.is_nan() {
+        return Err(vm.new_value_error("cannot convert NaN to integer ratio".to_string()));
+    }
+    let (mantissa, exponent) = float_parts(value.abs());
+    let (numerator, denominator) = if exponent >= 0 || mantissa == 0 {
+        (&BigInt::from_u64(mantissa) << exponent.max(0) as u64, BigInt::from(1))
+    } else {
+        // Cancel the factors of two the mantissa shares with the denominator.
+        let common = (mantissa.trailing_zeros() as i64).min(-exponent);
+        (BigInt::from_u64(mantissa >> common), &BigInt::from(1) << (-exponent - common) as u64)
+    };
+    let numerator = if value < 0.0 { -numerator } else { numerator };
+    Ok(vm.new_tuple(vec![vm.new_bigint(numerator), vm.new_bigint(denominator)]))
+}
+
+fn float_is_integer(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (value, args) = receiver(vm, args, "float", "is_integer", is_float)?;
+    check_args(vm, "float.is_integer", &args, 0, 0)?;
+    let value = as_float(&value);
+    Ok(vm.new_bool(value.is_finite() && value.fract() == 0.0))
+}
+
+impl VirtualMachine {
+    pub(crate) fn add_float_methods(&self) {
+        let float = &self.types.float_type;
+        self.add_method(float, "hex", float_method_hex);
+        self.add_method(float, "as_integer_ratio", float_as_integer_ratio);
+        self.add_method(float, "is_integer", float_is_integer);
+        let fromhex = self.new_builtin_function("fromhex", float_method_fromhex);
+        let fromhex = self.new_object(Payload::ClassMethod(fromhex), &self.types.classmethod_type);
+        self.dict_set_str(float.dict.as_ref().expect("type dict"), "fromhex", fromhex);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{float_fromhex, float_hex, round_float};
+
+    #[test]
+    fn rounding_uses_the_exact_binary_value() {
+        assert_eq!(round_float(2.675, 2), Some(2.67));
+        assert_eq!(round_float(0.125, 2), Some(0.12));
+        assert_eq!(round_float(0.375, 2), Some(0.38));
+        assert_eq!(round_float(125.0, -1), Some(120.0));
+        assert_eq!(round_float(135.0, -1), Some(140.0));
+        assert_eq!(round_float(-0.5, 0), Some(-0.0));
+        assert_eq!(round_float(1.7976931348623157e308, -308), None);
+    }
+
+    #[test]
+    fn hex_round_trips() {
+        for value in [1.0, 0.1, -2.5, 5e-324, 1.7976931348623157e308, 2.2250738585072014e-308] {
+            let text = float_hex(value);
+            assert_eq!(float_fromhex(&text).ok(), Some(value), "{}", text);
+        }
+        assert_eq!(float_hex(0.1), "0x1.999999999999ap-4");
+        assert_eq!(float_fromhex("0x1.00000000000008p0").ok(), Some(1.0));
+        assert_eq!(float_fromhex("0x1.00000000000018p0").ok(), Some(1.0000000000000004));
+    }
+}
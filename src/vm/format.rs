@@ -0,0 +1,1127 @@
+//! `repr()`, `str()`, `format()` and `%` formatting.
+
+use crate::bigint::BigInt;
+
+use super::object::{DictViewKind, Payload, PyInt, PyObjectRef, PyResult};
+use super::VirtualMachine;
+
+/// The memory address shown in default reprs.
+pub(crate) fn address(obj: &PyObjectRef) -> String {
+    format!("0x{:x}", std::rc::Rc::as_ptr(obj) as usize)
+}
+
+/// `repr()` of a float: the shortest digits that read back as the same float, in fixed
+/// notation for exponents from -4 up to 16 and in scientific notation otherwise.
+pub fn float_repr(value: f64) -> String {
+    if value.is_nan() {
+        return "nan".to_string();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    // Rust prints the shortest round-tripping digits in scientific notation.
+    let scientific = format!("{:e}", value);
+    let (mantissa, exponent) = scientific.split_once('e').expect("exponent");
+    let exponent: i32 = exponent.parse().expect("exponent digits");
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", mantissa),
+    };
+    let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    if (-4..16).contains(&exponent) {
+        let point = exponent + 1;
+        let body = if point <= 0 {
+            format!("0.{}{}", "0".repeat((-point) as usize), digits)
+        } else if point as usize >= digits.len() {
+            format!("{}{}.0", digits, "0".repeat(point as usize - digits.len()))
+        } else {
+            format!("{}.{}", &digits[..point as usize], &digits[point as usize..])
+        };
+        format!("{}{}", sign, body)
+    } else {
+        let mantissa = if digits.len() == 1 { digits } else { format!("{}.{}", &digits[..1], &digits[1..]) };
+        format!("{}{}e{}{:02}", sign, mantissa, if exponent < 0 { '-' } else { '+' }, exponent.abs())
+    }
+}
+
+/// `repr()` of a complex number, whose parts drop the `.0` of integral values.
+fn complex_repr(real: f64, imag: f64) -> String {
+    let part = |value: f64| {
+        let text = float_repr(value);
+        text.strip_suffix(".0").map(str::to_string).unwrap_or(text)
+    };
+    if real == 0.0 && real.is_sign_positive() {
+        return format!("{}j", part(imag));
+    }
+    let imag_text = part(imag);
+    let sign = if imag_text.starts_with('-') || imag.is_nan() && imag.is_sign_negative() { "" } else { "+" };
+    format!("({}{}{}j)", part(real), sign, imag_text)
+}
+
+/// Whether `str.isprintable()` would count `c`, which decides what `repr()` escapes.
+pub(crate) fn is_printable(c: char) -> bool {
+    if c == ' ' {
+        return true;
+    }
+    if c.is_control() || c.is_whitespace() {
+        return false;
+    }
+    !matches!(
+        c as u32,
+        0xad | 0x600..=0x605 | 0x61c | 0x6dd | 0x70f | 0x180e | 0x200b..=0x200f | 0x202a..=0x202e
+            | 0x2060..=0x2064 | 0x2066..=0x206f | 0xd800..=0xf8ff | 0xfeff | 0xfff9..=0xfffb
+            | 0xe0001 | 0xe0020..=0xe007f | 0xf0000..
+    )
+}
+
+pub fn str_repr(s: &str) -> String {
+    let quote = if s.contains('\'') && !s.contains('"') { '"' } else { '\'' };
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push(quote);
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c == quote => {
+                out.push('\\');
+                out.push(c);
+            }
+            c if is_printable(c) => out.push(c),
+            c if (c as u32) < 0x100 => out.push_str(&format!("\\x{:02x}", c as u32)),
+            c if (c as u32) < 0x10000 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push_str(&format!("\\U{:08x}", c as u32)),
+        }
+    }
+    out.push(quote);
+    out
+}
+
+pub fn bytes_repr(bytes: &[u8]) -> String {
+    let quote = if bytes.contains(&b'\'') && !bytes.contains(&b'"') { b'"' } else { b'\'' };
+    let mut out = String::from("b");
+    out.push(quote as char);
+    for &b in bytes {
+        match b {
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            b if b == quote => {
+                out.push('\\');
+                out.push(b as char);
+            }
+            0x20..=0x7e => out.push(b as char),
+            b => out.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    out.push(quote as char);
+    out
+}
+
+/// The arguments that the fields of `str.format()` refer to.
+struct FormatArgs<'a> {
+    /// The positional arguments, which `format_map()` does not have.
+    args: Option<&'a [PyObjectRef]>,
+    /// The mapping that named fields look up.
+    kwargs: &'a PyObjectRef,
+    /// The index of the next `{}`.
+    next_index: usize,
+    /// Whether a field gave its index, which `{}` cannot follow.
+    manual: bool,
+}
+
+/// A parsed format specification: `[[fill]align][sign][z][#][0][width][,|_][.precision][type]`.
+#[derive(Default)]
+struct FormatSpec {
+    fill: Option<char>,
+    align: Option<char>,
+    sign: Option<char>,
+    alternate: bool,
+    zero: bool,
+    width: Option<usize>,
+    grouping: Option<char>,
+    precision: Option<usize>,
+    kind: Option<char>,
+}
+
+impl FormatSpec {
+    fn parse(spec: &str) -> Result<Self, String> {
+        let chars: Vec<char> = spec.chars().collect();
+        let mut result = Self::default();
+        let mut i = 0;
+        let is_align = |c: char| matches!(c, '<' | '>' | '^' | '=');
+        if chars.len() >= 2 && is_align(chars[1]) {
+            result.fill = Some(chars[0]);
+            result.align = Some(chars[1]);
+            i = 2;
+        } else if !chars.is_empty() && is_align(chars[0]) {
+            result.align = Some(chars[0]);
+            i = 1;
+        }
+        if let Some(&c) = chars.get(i).filter(|c| matches!(c, '+' | '-' | ' ')) {
+            result.sign = Some(c);
+            i += 1;
+        }
+        if chars.get(i) == Some(&'z') {
+            i += 1;
+        }
+        if chars.get(i) == Some(&'#') {
+            result.alternate = true;
+            i += 1;
+        }
+        if chars.get(i) == Some(&'0') {
+            result.zero = true;
+            i += 1;
+        }
+        let digits = |i: &mut usize| {
+            let start = *i;
+            while chars.get(*i).is_some_and(|c| c.is_ascii_digit()) {
+                *i += 1;
+            }
+            (start != *i).then(|| chars[start..*i].iter().collect::<String>().parse::<usize>().unwrap_or(usize::MAX))
+        };
+        result.width = digits(&mut i);
+        if let Some(&c) = chars.get(i).filter(|c| matches!(c, ',' | '_')) {
+            result.grouping = Some(c);
+            i += 1;
+        }
+        if chars.get(i) == Some(&'.') {
+            i += 1;
+            result.precision = Some(digits(&mut i).ok_or("Format specifier missing precision")?);
+        }
+        if i < chars.len() {
+            result.kind = Some(chars[i]);
+            i += 1;
+        }
+        if i < chars.len() {
+            return Err("Invalid format specifier".to_string());
+        }
+        Ok(result)
+    }
+
+    /// Pads `body`, which is preceded by `sign` (a sign and maybe a base prefix), to the
+    /// width.
+    fn pad(&self, sign: &str, body: &str, default_align: char) -> String {
+        let (fill, align) = match (self.fill, self.align, self.zero) {
+            (fill, Some(align), _) => (fill.unwrap_or(' '), align),
+            (None, None, true) => ('0', '='),
+            _ => (' ', default_align),
+        };
+        let len = sign.chars().count() + body.chars().count();
+        let width = self.width.unwrap_or(0);
+        if len >= width {
+            return format!("{}{}", sign, body);
+        }
+        let padding = width - len;
+        let fill_str = |n: usize| fill.to_string().repeat(n);
+        match align {
+            '<' => format!("{}{}{}", sign, body, fill_str(padding)),
+            '^' => format!("{}{}{}{}", fill_str(padding / 2), sign, body, fill_str(padding - padding / 2)),
+            '=' => format!("{}{}{}", sign, fill_str(padding), body),
+            _ => format!("{}{}{}", fill_str(padding), sign, body),
+        }
+    }
+
+    fn sign_for(&self, negative: bool) -> &'static str {
+        match (negative, self.sign) {
+            (true, _) => "-",
+            (false, Some('+')) => "+",
+            (false, Some(' ')) => " ",
+            _ => "",
+        }
+    }
+}
+
+/// Inserts `separator` between groups of `size` digits, counting from the right.
+fn group_digits(digits: &str, separator: char, size: usize) -> String {
+    let chars: Vec<char> = digits.chars().collect();
+    let mut out = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        if i > 0 && (chars.len() - i).is_multiple_of(size) {
+            out.push(separator);
+        }
+        out.push(*c);
+    }
+    out
+}
+
+/// `value` in scientific notation with `precision` digits after the point, as C's `%e`.
+fn format_exponent(value: f64, precision: usize, upper: bool, alternate: bool) -> String {
+    let text = format!("{:.*e}", precision, value);
+    let (mantissa, exponent) = text.split_once('e').expect("exponent");
+    let exponent: i32 = exponent.parse().expect("exponent digits");
+    let mantissa = if alternate && precision == 0 { format!("{}.", mantissa) } else { mantissa.to_string() };
+    let e = if upper { 'E' } else { 'e' };
+    format!("{}{}{}{:02}", mantissa, e, if exponent < 0 { '-' } else { '+' }, exponent.abs())
+}
+
+/// C's `%g`: `precision` significant digits, fixed or scientific by the exponent, with
+/// trailing zeros removed unless `alternate`.
+fn format_general(value: f64, precision: usize, upper: bool, alternate: bool) -> String {
+    let precision = precision.max(1);
+    let text = format!("{:.*e}", precision - 1, value);
+    let exponent: i32 = text.split_once('e').expect("exponent").1.parse().expect("exponent digits");
+    let body = if exponent < -4 || exponent >= precision as i32 {
+        let formatted = format_exponent(value, precision - 1, upper, alternate);
+        if alternate {
+            formatted
+        } else {
+            let (mantissa, exponent) = formatted.split_at(formatted.find(['e', 'E']).expect("exponent"));
+            format!("{}{}", strip_fraction_zeros(mantissa), exponent)
+        }
+    } else {
+        let decimals = (precision as i32 - 1 - exponent).max(0) as usize;
+        let formatted = format!("{:.*}", decimals, value);
+        if alternate {
+            if formatted.contains('.') {
+                formatted
+            } else {
+                format!("{}.", formatted)
+            }
+        } else {
+            strip_fraction_zeros(&formatted)
+        }
+    };
+    body
+}
+
+fn strip_fraction_zeros(text: &str) -> String {
+    if !text.contains('.') {
+        return text.to_string();
+    }
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+fn non_finite(value: f64, upper: bool) -> Option<String> {
+    let text = if value.is_nan() {
+        "nan"
+    } else if value.is_infinite() {
+        "inf"
+    } else {
+        return None;
+    };
+    Some(if upper { text.to_uppercase() } else { text.to_string() })
+}
+
+impl VirtualMachine {
+    pub fn repr(&self, obj: &PyObjectRef) -> PyResult<String> {
+        if let Some(result) = self.call_special(obj, "__repr__", vec![])? {
+            return match result.as_str() {
+                Some(s) => Ok(s.to_string()),
+                None => Err(self.new_type_error(format!(
+                    "__repr__ returned non-string (type {})",
+                    self.class_name(&result)
+                ))),
+            };
+        }
+        self.native_repr(obj)
+    }
+
+    /// The repr of a builtin value, or `<X object at ...>`.
+    pub(super) fn native_repr(&self, obj: &PyObjectRef) -> PyResult<String> {
+        Ok(match &obj.payload {
+            Payload::None => "None".to_string(),
+            Payload::NotImplemented => "NotImplemented".to_string(),
+            Payload::Ellipsis => "Ellipsis".to_string(),
+            Payload::Bool(b) => if *b { "True" } else { "False" }.to_string(),
+            Payload::Int(PyInt::Small(i)) => i.to_string(),
+            Payload::Int(PyInt::Big(i)) => self.int_to_decimal(i)?,
+            Payload::Float(f) => float_repr(*f),
+            Payload::Complex { real, imag } => complex_repr(*real, *imag),
+            Payload::Str(s) => str_repr(s),
+            Payload::Bytes(b) => bytes_repr(b),
+            Payload::Tuple(items) => {
+                if items.len() == 1 {
+                    return Ok(format!("({},)", self.repr(&items[0])?));
+                }
+                return self.repr_items(obj, "(", items, ")");
+            }
+            Payload::List(items) => {
+                let items = items.borrow().clone();
+                return self.repr_items(obj, "[", &items, "]");
+            }
+            Payload::Dict(table) => {
+                let items = table.borrow().items();
+                if items.is_empty() {
+                    return Ok("{}".to_string());
+                }
+                if !self.enter_repr(obj) {
+                    return Ok("{...}".to_string());
+                }
+                let result = items
+                    .iter()
+                    .map(|(key, value)| Ok(format!("{}: {}", self.repr(key)?, self.repr(value)?)))
+                    .collect::<PyResult<Vec<_>>>();
+                self.leave_repr();
+                format!("{{{}}}", result?.join(", "))
+            }
+            Payload::Set(table) => {
+                let keys = table.borrow().keys();
+                let name = self.class_name(obj);
+                if keys.is_empty() {
+                    return Ok(format!("{}()", name));
+                }
+                let inner = self.repr_items(obj, "{", &keys, "}")?;
+                if obj.class().is(&self.types.set_type) {
+                    inner
+                } else {
+                    format!("{}({})", name, inner)
+                }
+            }
+            Payload::FrozenSet(table) => {
+                let name = self.class_name(obj);
+                if table.is_empty() {
+                    return Ok(format!("{}()", name));
+                }
+                format!("{}({})", name, self.repr_items(obj, "{", &table.keys(), "}")?)
+            }
+            Payload::Range { start, stop, step } => {
+                if step == &BigInt::from(1) {
+                    format!("range({}, {})", start, stop)
+                } else {
+                    format!("range({}, {}, {})", start, stop, step)
+                }
+            }
+            Payload::Slice { start, stop, step } => {
+                format!("slice({}, {}, {})", self.repr(start)?, self.repr(stop)?, self.repr(step)?)
+            }
+            Payload::Function(function) => {
+                format!("<function {} at {}>", function.qualname.borrow(), address(obj))
+            }
+            Payload::BuiltinFunction(function) => format!("<built-in function {}>", function.name),
+            Payload::BoundMethod { function, receiver } => match &function.payload {
+                Payload::BuiltinFunction(builtin) => format!(
+                    "<built-in method {} of {} object at {}>",
+                    builtin.name,
+                    self.class_name(receiver),
+                    address(receiver)
+                ),
+                _ => {
+                    let name = self.get_attribute_opt(function, "__qualname__")?;
+                    let name = match name {
+                        Some(name) => self.to_str(&name)?,
+                        None => "?".to_string(),
+                    };
+                    format!("<bound method {} of {}>", name, self.repr(receiver)?)
+                }
+            },
+            Payload::StaticMethod(function) => format!("<staticmethod({})>", self.repr(function)?),
+            Payload::Member { name, owner, .. } => {
+                format!("<member '{}' of '{}' objects>", name, owner.as_type().expect("type").name())
+            }
+            Payload::Super { this_class, obj, .. } => {
+                let name = this_class.as_type().expect("type").name();
+                match obj {
+                    Some(obj) => format!("<super: <class '{}'>, <{} object>>", name, self.class_name(obj)),
+                    None => format!("<super: <class '{}'>, NULL>", name),
+                }
+            }
+            Payload::ClassMethod(function) => format!("<classmethod({})>", self.repr(function)?),
+            Payload::Code(code) => format!(
+                "<code object {} at {}, file \"{}\", line {}>",
+                code.name,
+                address(obj),
+                code.filename,
+                code.first_lineno
+            ),
+            Payload::Cell(contents) => match &*contents.borrow() {
+                Some(value) => format!(
+                    "<cell at {}: {} object at {}>",
+                    address(obj),
+                    self.class_name(value),
+                    address(value)
+                ),
+                None => format!("<cell at {}: empty>", address(obj)),
+            },
+            Payload::Generator(generator) => {
+                format!("<{} object {} at {}>", generator.kind.name(), generator.qualname, address(obj))
+            }
+            Payload::Module { name } => {
+                let file = obj.dict.as_ref().and_then(|dict| self.dict_get_str(dict, "__file__"));
+                match file.as_ref().and_then(|file| file.as_str()) {
+                    Some(file) => format!("<module '{}' from '{}'>", name, file),
+                    None => format!("<module '{}' (built-in)>", name),
+                }
+            }
+            Payload::Type(ty) => {
+                let module = self
+                    .type_lookup_own(obj, "__module__")
+                    .and_then(|module| module.as_str().map(str::to_string))
+                    .filter(|module| module != "builtins");
+                match module {
+                    Some(module) => format!("<class '{}.{}'>", module, ty.qualname.borrow()),
+                    None => format!("<class '{}'>", ty.qualname.borrow()),
+                }
+            }
+            Payload::Exception(_) => return self.exception_repr(obj),
+            Payload::DictView { dict, kind } => {
+                let Payload::Dict(table) = &dict.payload else { unreachable!("view of a dict") };
+                let items: Vec<PyObjectRef> = match kind {
+                    DictViewKind::Keys => table.borrow().keys(),
+                    DictViewKind::Values => table.borrow().values(),
+                    DictViewKind::Items => {
+                        let items = table.borrow().items();
+                        items.into_iter().map(|(key, value)| self.new_tuple(vec![key, value])).collect()
+                    }
+                };
+                return self.repr_items(obj, &format!("{}([", kind.name()), &items, "])");
+            }
+            Payload::Iterator(_)
+            | Payload::AsyncGenAwaitable { .. }
+            | Payload::Property(_)
+            | Payload::Traceback(_)
+            | Payload::Object => {
+                if let Some(repr) = self.type_param_repr(obj) {
+                    return Ok(repr);
+                }
+                self.object_repr(obj)
+            }
+        })
+    }
+
+    /// The decimal digits of an int, refused beyond `sys.get_int_max_str_digits()`.
+    pub fn int_to_decimal(&self, value: &BigInt) -> PyResult<String> {
+        let limit = self.int_max_str_digits.get();
+        let too_long = || {
+            self.new_value_error(format!(
+                "Exceeds the limit ({} digits) for integer string conversion; \
+                 use sys.set_int_max_str_digits() to increase the limit",
+                limit
+            ))
+        };
+        // Each digit takes log2(10) bits, so this many bits surely mean too many digits.
+        if limit > 0 && value.bit_length() > (limit as u64 + 1) * 3322 / 1000 + 4 {
+            return Err(too_long());
+        }
+        let text = value.to_str_radix(10);
+        if limit > 0 && text.trim_start_matches('-').len() > limit {
+            return Err(too_long());
+        }
+        Ok(text)
+    }
+
+    /// `object.__repr__`: the class and the address.
+    pub fn object_repr(&self, obj: &PyObjectRef) -> String {
+        let class = obj.class();
+        let ty = class.as_type().expect("type");
+        let module = self
+            .type_lookup_own(&class, "__module__")
+            .and_then(|module| module.as_str().map(str::to_string))
+            .filter(|module| module != "builtins");
+        match module {
+            Some(module) => format!("<{}.{} object at {}>", module, ty.qualname.borrow(), address(obj)),
+            None => format!("<{} object at {}>", ty.qualname.borrow(), address(obj)),
+        }
+    }
+
+    /// The `__module__` a class itself defines.
+    fn type_lookup_own(&self, class: &PyObjectRef, name: &str) -> Option<PyObjectRef> {
+        self.dict_get_str(class.dict.as_ref()?, name)
+    }
+
+    /// Guards against infinite recursion through containers that contain themselves:
+    /// returns false if `obj` is already being printed.
+    fn enter_repr(&self, obj: &PyObjectRef) -> bool {
+        let mut active = self.repr_active.borrow_mut();
+        if active.iter().any(|seen| std::ptr::eq(*seen, std::rc::Rc::as_ptr(obj))) {
+            return false;
+        }
+        active.push(std::rc::Rc::as_ptr(obj));
+        true
+    }
+
+    fn leave_repr(&self) {
+        self.repr_active.borrow_mut().pop();
+    }
+
+    fn repr_items(&self, container: &PyObjectRef, open: &str, items: &[PyObjectRef], close: &str) -> PyResult<String> {
+        if !self.enter_repr(container) {
+            return Ok(format!("{}...{}", open, close));
+        }
+        let parts = items.iter().map(|item| self.repr(item)).collect::<PyResult<Vec<_>>>();
+        self.leave_repr();
+        Ok(format!("{}{}{}", open, parts?.join(", "), close))
+    }
+
+    /// `ascii()`: the repr with non-ASCII characters escaped.
+    pub fn ascii(&self, obj: &PyObjectRef) -> PyResult<String> {
+        let repr = self.repr(obj)?;
+        let mut out = String::with_capacity(repr.len());
+        for c in repr.chars() {
+            match c as u32 {
+                0..=0x7f => out.push(c),
+                code @ 0x80..=0xff => out.push_str(&format!("\\x{:02x}", code)),
+                code @ 0x100..=0xffff => out.push_str(&format!("\\u{:04x}", code)),
+                code => out.push_str(&format!("\\U{:08x}", code)),
+            }
+        }
+        Ok(out)
+    }
+
+    /// `str(obj)`.
+    pub fn to_str(&self, obj: &PyObjectRef) -> PyResult<String> {
+        if let Some(result) = self.call_special(obj, "__str__", vec![])? {
+            return match result.as_str() {
+                Some(s) => Ok(s.to_string()),
+                None => Err(self.new_type_error(format!(
+                    "__str__ returned non-string (type {})",
+                    self.class_name(&result)
+                ))),
+            };
+        }
+        self.native_str(obj)
+    }
+
+    /// `str(obj)` of a builtin value: the repr of anything but strings and exceptions.
+    pub(super) fn native_str(&self, obj: &PyObjectRef) -> PyResult<String> {
+        match &obj.payload {
+            Payload::Str(s) => Ok(s.to_string()),
+            Payload::Exception(_) => self.exception_str(obj),
+            _ => self.repr(obj),
+        }
+    }
+
+    /// `format(value, spec)`.
+    pub fn format(&self, value: &PyObjectRef, spec: &str) -> PyResult<String> {
+        if let Some(result) = self.call_special(value, "__format__", vec![self.new_str(spec.to_string())])? {
+            return match result.as_str() {
+                Some(s) => Ok(s.to_string()),
+                None => Err(self.new_type_error(format!(
+                    "__format__ must return a str, not {}",
+                    self.class_name(&result)
+                ))),
+            };
+        }
+        if spec.is_empty() {
+            return self.to_str(value);
+        }
+        let parsed = FormatSpec::parse(spec).map_err(|msg| self.new_value_error(msg))?;
+        match &value.payload {
+            Payload::Str(s) => self.format_str(s, &parsed),
+            Payload::Bool(_) | Payload::Int(_) => self.format_int(value, &value.as_int().expect("int"), &parsed),
+            Payload::Float(f) => self.format_float(*f, &parsed, "float"),
+            _ => Err(self.new_type_error(format!(
+                "unsupported format string passed to {}.__format__",
+                self.class_name(value)
+            ))),
+        }
+    }
+
+    fn unknown_format_code(&self, code: char, type_name: &str) -> PyObjectRef {
+        self.new_value_error(format!("Unknown format code '{}' for object of type '{}'", code, type_name))
+    }
+
+    fn format_str(&self, s: &str, spec: &FormatSpec) -> PyResult<String> {
+        if let Some(kind) = spec.kind.filter(|k| *k != 's') {
+            return Err(self.unknown_format_code(kind, "str"));
+        }
+        if spec.sign.is_some() {
+            return Err(self.new_value_error("Sign not allowed in string format specifier".to_string()));
+        }
+        if spec.align == Some('=') {
+            return Err(self.new_value_error("'=' alignment not allowed in string format specifier".to_string()));
+        }
+        let body: String = match spec.precision {
+            Some(precision) => s.chars().take(precision).collect(),
+            None => s.to_string(),
+        };
+        Ok(spec.pad("", &body, '<'))
+    }
+
+    fn format_int(&self, value: &PyObjectRef, int: &BigInt, spec: &FormatSpec) -> PyResult<String> {
+        let type_name = self.class_name(value);
+        let kind = spec.kind.unwrap_or('d');
+        if matches!(kind, 'e' | 'E' | 'f' | 'F' | 'g' | 'G' | '%') {
+            return self.format_float(self.int_to_f64(int)?, spec, &type_name);
+        }
+        if spec.precision.is_some() {
+            return Err(self.new_value_error("Precision not allowed in integer format specifier".to_string()));
+        }
+        let negative = int.is_negative();
+        let magnitude = if negative { -int.clone() } else { int.clone() };
+        let (digits, prefix) = match kind {
+            'd' | 'n' => (self.int_to_decimal(&magnitude)?, ""),
+            'b' => (magnitude.to_str_radix(2), "0b"),
+            'o' => (magnitude.to_str_radix(8), "0o"),
+            'x' => (magnitude.to_str_radix(16), "0x"),
+            'X' => (magnitude.to_str_radix(16).to_uppercase(), "0X"),
+            'c' => {
+                if spec.sign.is_some() {
+                    return Err(self.new_value_error("Sign not allowed with integer format specifier 'c'".to_string()));
+                }
+                let c = int
+                    .to_i64()
+                    .and_then(|code| u32::try_from(code).ok())
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| self.new_overflow_error("%c arg not in range(0x110000)".to_string()))?;
+                return Ok(spec.pad("", &c.to_string(), '>'));
+            }
+            other => return Err(self.unknown_format_code(other, &type_name)),
+        };
+        let digits = match spec.grouping {
+            Some(separator) => {
+                let size = if matches!(kind, 'd' | 'n') { 3 } else { 4 };
+                if separator == ',' && size == 4 {
+                    return Err(self.new_value_error(format!("Cannot specify ',' with '{}'.", kind)));
+                }
+                group_digits(&digits, separator, size)
+            }
+            None => digits,
+        };
+        let mut sign = spec.sign_for(negative).to_string();
+        if spec.alternate {
+            sign.push_str(prefix);
+        }
+        Ok(spec.pad(&sign, &digits, '>'))
+    }
+
+    fn format_float(&self, value: f64, spec: &FormatSpec, type_name: &str) -> PyResult<String> {
+        let negative = value.is_sign_negative() && !value.is_nan();
+        let magnitude = value.abs();
+        let upper = matches!(spec.kind, Some('E' | 'F' | 'G'));
+        let body = match non_finite(magnitude, upper) {
+            Some(text) => {
+                if spec.kind == Some('%') {
+                    format!("{}%", text)
+                } else {
+                    text
+                }
+            }
+            None => match spec.kind {
+                Some('e' | 'E') => format_exponent(magnitude, spec.precision.unwrap_or(6), upper, spec.alternate),
+                Some('f' | 'F') => {
+                    let text = format!("{:.*}", spec.precision.unwrap_or(6), magnitude);
+                    if spec.alternate && !text.contains('.') {
+                        format!("{}.", text)
+                    } else {
+                        text
+                    }
+                }
+                Some('g' | 'G' | 'n') => format_general(magnitude, spec.precision.unwrap_or(6), upper, spec.alternate),
+                Some('%') => format!("{:.*}%", spec.precision.unwrap_or(6), magnitude * 100.0),
+                None => match spec.precision {
+                    None => float_repr(magnitude),
+                    Some(precision) => {
+                        // Like 'g', but an integral result keeps a '.0'.
+                        let text = format_general(magnitude, precision, false, spec.alternate);
+                        if text.contains(['.', 'e', 'n', 'i']) {
+                            text
+                        } else {
+                            format!("{}.0", text)
+                        }
+                    }
+                },
+                Some(other) => return Err(self.unknown_format_code(other, type_name)),
+            },
+        };
+        let body = match spec.grouping {
+            Some(separator) if magnitude.is_finite() => {
+                let split = body.find(|c: char| !c.is_ascii_digit()).unwrap_or(body.len());
+                format!("{}{}", group_digits(&body[..split], separator, 3), &body[split..])
+            }
+            _ => body,
+        };
+        Ok(spec.pad(spec.sign_for(negative), &body, '>'))
+    }
+
+    /// `format % args` for a str.
+    pub fn percent_format(&self, format: &str, args: &PyObjectRef) -> PyResult<String> {
+        let chars: Vec<char> = format.chars().collect();
+        let mapping = !matches!(args.payload, Payload::Tuple(_) | Payload::Str(_))
+            && (matches!(args.payload, Payload::Dict(_)) || self.special_method(args, "__getitem__").is_some());
+        let values: Vec<PyObjectRef> = match &args.payload {
+            Payload::Tuple(items) => items.clone(),
+            _ => vec![args.clone()],
+        };
+        let mut next_value = 0;
+        let mut take = |vm: &VirtualMachine| -> PyResult {
+            let value = values
+                .get(next_value)
+                .cloned()
+                .ok_or_else(|| vm.new_type_error("not enough arguments for format string".to_string()))?;
+            next_value += 1;
+            Ok(value)
+        };
+        let mut out = String::new();
+        let mut i = 0;
+        let mut used_mapping = false;
+        while i < chars.len() {
+            if chars[i] != '%' {
+                out.push(chars[i]);
+                i += 1;
+                continue;
+            }
+            i += 1;
+            let mut key_value = None;
+            if chars.get(i) == Some(&'(') {
+                let mut depth = 1;
+                let start = i + 1;
+                i += 1;
+                while i < chars.len() && depth > 0 {
+                    match chars[i] {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        _ => {}
+                    }
+                    i += 1;
+                }
+                if depth > 0 {
+                    return Err(self.new_value_error("incomplete format key".to_string()));
+                }
+                if !mapping {
+                    return Err(self.new_type_error("format requires a mapping".to_string()));
+                }
+                let key: String = chars[start..i - 1].iter().collect();
+                key_value = Some(self.get_item(args, &self.new_str(key))?);
+                used_mapping = true;
+            }
+            let mut spec = FormatSpec::default();
+            while let Some(&flag) = chars.get(i) {
+                match flag {
+                    '-' => spec.align = Some('<'),
+                    '+' => spec.sign = Some('+'),
+                    ' ' if spec.sign.is_none() => spec.sign = Some(' '),
+                    ' ' => {}
+                    '#' => spec.alternate = true,
+                    '0' => spec.zero = true,
+                    _ => break,
+                }
+                i += 1;
+            }
+            if chars.get(i) == Some(&'*') {
+                let width = self.to_usize_index(&take(self)?)?;
+                if width < 0 {
+                    spec.align = Some('<');
+                }
+                spec.width = Some(width.unsigned_abs() as usize);
+                i += 1;
+            } else {
+                let start = i;
+                while chars.get(i).is_some_and(|c| c.is_ascii_digit()) {
+                    i += 1;
+                }
+                if i > start {
+                    spec.width = chars[start..i].iter().collect::<String>().parse().ok();
+                }
+            }
+            if chars.get(i) == Some(&'.') {
+                i += 1;
+                if chars.get(i) == Some(&'*') {
+                    spec.precision = Some(self.to_usize_index(&take(self)?)?.max(0) as usize);
+                    i += 1;
+                } else {
+                    let start = i;
+                    while chars.get(i).is_some_and(|c| c.is_ascii_digit()) {
+                        i += 1;
+                    }
+                    spec.precision = Some(chars[start..i].iter().collect::<String>().parse().unwrap_or(0));
+                }
+            }
+            while matches!(chars.get(i), Some('h' | 'l' | 'L')) {
+                i += 1;
+            }
+            let Some(&conversion) = chars.get(i) else {
+                return Err(self.new_value_error("incomplete format".to_string()));
+            };
+            i += 1;
+            if conversion == '%' {
+                out.push('%');
+                continue;
+            }
+            let value = match key_value.take() {
+                Some(value) => value,
+                None => take(self)?,
+            };
+            if spec.align == Some('<') {
+                spec.zero = false;
+            }
+            out.push_str(&self.percent_conversion(conversion, &value, &spec, i - 1)?);
+        }
+        if next_value < values.len() && !mapping && !used_mapping {
+            return Err(self.new_type_error("not all arguments converted during string formatting".to_string()));
+        }
+        Ok(out)
+    }
+
+    fn percent_conversion(&self, conversion: char, value: &PyObjectRef, spec: &FormatSpec, index: usize) -> PyResult<String> {
+        match conversion {
+            's' | 'r' | 'a' => {
+                let text = match conversion {
+                    's' => self.to_str(value)?,
+                    'r' => self.repr(value)?,
+                    _ => self.ascii(value)?,
+                };
+                let text: String = match spec.precision {
+                    Some(precision) => text.chars().take(precision).collect(),
+                    None => text,
+                };
+                let spec = FormatSpec { width: spec.width, align: spec.align, ..Default::default() };
+                Ok(spec.pad("", &text, '>'))
+            }
+            'd' | 'i' | 'u' | 'x' | 'X' | 'o' => {
+                let int = match &value.payload {
+                    Payload::Float(f) if matches!(conversion, 'd' | 'i' | 'u') => self.float_to_int(*f)?,
+                    _ if matches!(conversion, 'd' | 'i' | 'u') && self.special_method(value, "__int__").is_some() => {
+                        self.to_int(value)?
+                    }
+                    _ => value.as_int().map(Ok).unwrap_or_else(|| self.to_index(value)).map_err(|_| {
+                        let what = if matches!(conversion, 'd' | 'i' | 'u') {
+                            "a real number is required"
+                        } else {
+                            "an integer is required"
+                        };
+                        self.new_type_error(format!("%{} format: {}, not {}", conversion, what, self.class_name(value)))
+                    })?,
+                };
+                let negative = int.is_negative();
+                let magnitude = if negative { -int } else { int };
+                let (mut digits, prefix) = match conversion {
+                    'x' => (magnitude.to_str_radix(16), "0x"),
+                    'X' => (magnitude.to_str_radix(16).to_uppercase(), "0X"),
+                    'o' => (magnitude.to_str_radix(8), "0o"),
+                    _ => (self.int_to_decimal(&magnitude)?, ""),
+                };
+                if let Some(precision) = spec.precision {
+                    if digits.len() < precision {
+                        digits = format!("{}{}", "0".repeat(precision - digits.len()), digits);
+                    }
+                }
+                let mut sign = spec.sign_for(negative).to_string();
+                if spec.alternate {
+                    sign.push_str(prefix);
+                }
+                Ok(spec.pad(&sign, &digits, '>'))
+            }
+            'e' | 'E' | 'f' | 'F' | 'g' | 'G' => {
+                let number = self.to_float(value).map_err(|_| {
+                    self.new_type_error(format!("must be real number, not {}", self.class_name(value)))
+                })?;
+                let spec = FormatSpec {
+                    precision: Some(spec.precision.unwrap_or(6)),
+                    kind: Some(conversion),
+                    fill: spec.fill,
+                    align: spec.align,
+                    sign: spec.sign,
+                    alternate: spec.alternate,
+                    zero: spec.zero,
+                    width: spec.width,
+                    grouping: None,
+                };
+                self.format_float(number, &spec, "float")
+            }
+            'c' => {
+                let c = match &value.payload {
+                    Payload::Str(s) if s.chars().count() == 1 => s.chars().next().expect("one char"),
+                    Payload::Str(_) => return Err(self.new_type_error("%c requires int or char".to_string())),
+                    _ => {
+                        let code = self.to_index(value)?;
+                        code.to_i64()
+                            .and_then(|code| u32::try_from(code).ok())
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| self.new_overflow_error("%c arg not in range(0x110000)".to_string()))?
+                    }
+                };
+                let spec = FormatSpec { width: spec.width, align: spec.align, ..Default::default() };
+                Ok(spec.pad("", &c.to_string(), '>'))
+            }
+            other => Err(self.new_value_error(format!(
+                "unsupported format character '{}' (0x{:x}) at index {}",
+                other, other as u32, index
+            ))),
+        }
+    }
+
+    /// `format % args` for bytes, where `%s` takes bytes.
+    pub fn percent_format_bytes(&self, format: &[u8], args: &PyObjectRef) -> PyResult<Vec<u8>> {
+        // Bytes objects hold Latin-1 text here; `%s` and `%b` insert bytes unchanged.
+        let text: String = format.iter().map(|b| *b as char).collect();
+        let converted = match &args.payload {
+            Payload::Tuple(items) => {
+                let items = items.iter().map(|item| self.bytes_for_percent(item)).collect::<PyResult<Vec<_>>>()?;
+                self.new_tuple(items)
+            }
+            _ => self.bytes_for_percent(args)?,
+        };
+        let result = self.percent_format(&text.replace("%b", "%s"), &converted)?;
+        Ok(result.chars().map(|c| c as u32 as u8).collect())
+    }
+
+    fn bytes_for_percent(&self, value: &PyObjectRef) -> PyResult {
+        Ok(match &value.payload {
+            Payload::Bytes(b) => self.new_str(b.iter().map(|b| *b as char).collect()),
+            _ => value.clone(),
+        })
+    }
+
+    /// `template.format(*args, **kwargs)`, or `template.format_map(kwargs)` without `args`.
+    pub fn str_format(&self, template: &str, args: Option<&[PyObjectRef]>, kwargs: &PyObjectRef) -> PyResult<String> {
+        let mut state = FormatArgs { args, kwargs, next_index: 0, manual: false };
+        self.expand_fields(template, &mut state, 2)
+    }
+
+    /// Replaces the fields of `template`, and of the specs in them down to `depth` levels.
+    fn expand_fields(&self, template: &str, state: &mut FormatArgs, depth: usize) -> PyResult<String> {
+        if depth == 0 {
+            return Err(self.new_value_error("Max string recursion exceeded".to_string()));
+        }
+        let mut out = String::with_capacity(template.len());
+        let mut chars = template.char_indices().peekable();
+        while let Some((start, c)) = chars.next() {
+            let doubled = chars.peek().is_some_and(|(_, next)| *next == c);
+            match c {
+                '{' | '}' if doubled => {
+                    chars.next();
+                    out.push(c);
+                }
+                '}' => return Err(self.new_value_error("Single '}' encountered in format string".to_string())),
+                '{' => {
+                    // The field ends at the brace that matches, after those of nested fields
+                    // in the spec.  Braces in a `[key]` of the name do not count.
+                    let mut nesting = 1;
+                    let (mut in_name, mut in_key) = (true, false);
+                    let end = chars.by_ref().find_map(|(idx, c)| {
+                        match c {
+                            '[' if in_name => in_key = true,
+                            ']' if in_key => in_key = false,
+                            _ if in_key => {}
+                            '!' | ':' => in_name = false,
+                            '{' => nesting += 1,
+                            '}' => nesting -= 1,
+                            _ => {}
+                        }
+                        (nesting == 0).then_some(idx)
+                    });
+                    let Some(end) = end else {
+                        let msg = match start + 1 == template.len() {
+                            true => "Single '{' encountered in format string",
+                            false => "expected '}' before end of string",
+                        };
+                        return Err(self.new_value_error(msg.to_string()));
+                    };
+                    out.push_str(&self.replace_field(&template[start + 1..end], state, depth)?);
+                }
+                c => out.push(c),
+            }
+        }
+        Ok(out)
+    }
+
+    /// The text of one `{name!conversion:spec}`.
+    fn replace_field(&self, field: &str, state: &mut FormatArgs, depth: usize) -> PyResult<String> {
+        // A key in brackets may contain any of the characters that end the name.
+        let mut name_end = field.len();
+        let mut in_key = false;
+        for (idx, c) in field.char_indices() {
+            match c {
+                '[' => in_key = true,
+                ']' => in_key = false,
+                '{' if !in_key => return Err(self.new_value_error("unexpected '{' in field name".to_string())),
+                '!' | ':' if !in_key => {
+                    name_end = idx;
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let (name, mut rest) = field.split_at(name_end);
+        let mut conversion = None;
+        if let Some(after) = rest.strip_prefix('!') {
+            let mut after_chars = after.chars();
+            let Some(c) = after_chars.next() else {
+                return Err(self.new_value_error("unmatched '{' in format spec".to_string()));
+            };
+            rest = after_chars.as_str();
+            if !rest.is_empty() && !rest.starts_with(':') {
+                return Err(self.new_value_error("expected ':' after conversion specifier".to_string()));
+            }
+            if !matches!(c, 'r' | 's' | 'a') {
+                return Err(self.new_value_error(format!("Unknown conversion specifier {}", c)));
+            }
+            conversion = Some(c);
+        }
+        let value = self.field_value(name, state)?;
+        let spec = match rest.strip_prefix(':') {
+            Some(spec) if spec.contains('{') => self.expand_fields(spec, state, depth - 1)?,
+            Some(spec) => spec.to_string(),
+            None => String::new(),
+        };
+        self.format_field(&value, conversion, &spec)
+    }
+
+    /// The value that a field name such as `0`, `name.attr` or `name[key]` refers to.
+    fn field_value(&self, name: &str, state: &mut FormatArgs) -> PyResult {
+        let first_end = name.find(['.', '[']).unwrap_or(name.len());
+        let (first, mut rest) = name.split_at(first_end);
+        let index = if first.is_empty() {
+            if state.manual {
+                return Err(self.new_value_error(
+                    "cannot switch from manual field specification to automatic field numbering".to_string(),
+                ));
+            }
+            state.next_index += 1;
+            Some(state.next_index - 1)
+        } else if first.bytes().all(|b| b.is_ascii_digit()) {
+            if state.next_index > 0 {
+                return Err(self.new_value_error(
+                    "cannot switch from automatic field numbering to manual field specification".to_string(),
+                ));
+            }
+            state.manual = true;
+            let index = first
+                .parse()
+                .map_err(|_| self.new_value_error("Too many decimal digits in format string".to_string()))?;
+            Some(index)
+        } else {
+            None
+        };
+        let mut value = match (index, state.args) {
+            (Some(index), Some(args)) => args.get(index).cloned().ok_or_else(|| {
+                self.new_index_error(format!("Replacement index {} out of range for positional args tuple", index))
+            })?,
+            (Some(_), None) => return Err(self.new_value_error("Format string contains positional fields".to_string())),
+            (None, _) => self.get_item(state.kwargs, &self.new_str(first.to_string()))?,
+        };
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('.') {
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                if end == 0 {
+                    return Err(self.new_value_error("Empty attribute in format string".to_string()));
+                }
+                value = self.get_attribute(&value, &after[..end])?;
+                rest = &after[end..];
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let Some(end) = after.find(']') else {
+                    return Err(self.new_value_error("Missing ']' in format string".to_string()));
+                };
+                let key = &after[..end];
+                if key.is_empty() {
+                    return Err(self.new_value_error("Empty attribute in format string".to_string()));
+                }
+                let key = match key.parse::<i64>() {
+                    Ok(index) if key.bytes().all(|b| b.is_ascii_digit()) => self.new_int(index),
+                    _ => self.new_str(key.to_string()),
+                };
+                value = self.get_item(&value, &key)?;
+                rest = &after[end + 1..];
+            } else {
+                return Err(self.new_value_error(
+                    "Only '.' or '[' may follow ']' in format field specifier".to_string(),
+                ));
+            }
+        }
+        Ok(value)
+    }
+
+    /// The value of one `{field}` of `str.format()` or an f-string, converted and formatted.
+    pub fn format_field(&self, value: &PyObjectRef, conversion: Option<char>, spec: &str) -> PyResult<String> {
+        let value = match conversion {
+            Some('s') => self.new_str(self.to_str(value)?),
+            Some('r') => self.new_str(self.repr(value)?),
+            Some('a') => self.new_str(self.ascii(value)?),
+            _ => value.clone(),
+        };
+        self.format(&value, spec)
+    }
+}
@@ -0,0 +1,1283 @@
+//! Frames and the evaluation loop.
+//!
+//! A frame runs the instructions of one code object.  It returns when the code returns, or
+//! when a generator yields, keeping its stacks so that it can be resumed.  Exceptions are
+//! `Err` values: the loop records the frame in the traceback and jumps to the innermost
+//! handler on the block stack, or passes the exception to the caller if there is none.
+
+use std::rc::Rc;
+
+use crate::compiler::bytecode::{
+    Instruction, TypeParamFlavor, CO_COROUTINE, MAKE_ANNOTATIONS, MAKE_CLOSURE, MAKE_DEFAULTS, MAKE_KWDEFAULTS,
+};
+use crate::compiler::CodeObject;
+
+use super::object::{FuncArgs, GeneratorKind, Payload, PyObjectRef, PyResult, TracebackEntry};
+use super::VirtualMachine;
+
+/// How a frame ends or pauses.
+pub enum ExecutionResult {
+    Return(PyObjectRef),
+    /// A `yield`.
+    Yield(PyObjectRef),
+    /// A value an `await` or `yield from` passes on from the iterator it delegates to.
+    Await(PyObjectRef),
+}
+
+/// How a frame is started or resumed.
+pub enum Resume {
+    Start,
+    /// Resumes a paused generator with the value of its `yield`.
+    Send(PyObjectRef),
+    /// Resumes a paused generator by raising an exception at its `yield`.
+    Throw(PyObjectRef),
+}
+
+/// An exception handler pushed by `SetupFinally` or `SetupWith`.
+#[derive(Clone, Copy)]
+struct Block {
+    handler: usize,
+    /// The value stack is cut back to this depth before the handler runs.
+    level: usize,
+}
+
+/// What `super()` without arguments and `type(name, bases, namespace)` take from the frame
+/// that calls them.
+pub(crate) struct FrameInfo {
+    pub globals: PyObjectRef,
+    pub first_arg: Option<PyObjectRef>,
+    pub class_cell: Option<PyObjectRef>,
+}
+
+pub struct Frame {
+    pub code: Rc<CodeObject>,
+    consts: Rc<Vec<PyObjectRef>>,
+    pub globals: PyObjectRef,
+    builtins: PyObjectRef,
+    /// The namespace of a module or class body.  Functions keep their locals in `fast`.
+    pub locals: Option<PyObjectRef>,
+    fast: Vec<Option<PyObjectRef>>,
+    /// Cells for `cellvars` followed by those for `freevars`.
+    cells: Vec<PyObjectRef>,
+    stack: Vec<PyObjectRef>,
+    blocks: Vec<Block>,
+    /// The next instruction.
+    lasti: usize,
+    /// The exception being raised is re-raised and already has this frame in its traceback.
+    reraise: bool,
+}
+
+impl Frame {
+    pub fn new(vm: &VirtualMachine, code: Rc<CodeObject>, globals: PyObjectRef, locals: Option<PyObjectRef>) -> Self {
+        let builtins = match vm.dict_get_str(&globals, "__builtins__") {
+            Some(module) if matches!(module.payload, Payload::Module { .. }) => {
+                module.dict.clone().unwrap_or_else(|| vm.builtins_dict())
+            }
+            Some(dict) if dict.as_dict().is_some() => dict,
+            _ => vm.builtins_dict(),
+        };
+        Self {
+            consts: vm.code_consts(&code),
+            fast: vec![None; code.varnames.len()],
+            cells: vec![],
+            stack: vec![],
+            blocks: vec![],
+            lasti: 0,
+            reraise: false,
+            code,
+            globals,
+            builtins,
+            locals,
+        }
+    }
+
+    pub(crate) fn fast_mut(&mut self) -> &mut Vec<Option<PyObjectRef>> {
+        &mut self.fast
+    }
+
+    /// Makes the cells of the code's cell variables, moving parameters that are cells into
+    /// theirs, followed by the closure's cells.
+    pub(crate) fn init_cells(&mut self, vm: &VirtualMachine, closure: Option<&PyObjectRef>) {
+        for name in &self.code.cellvars {
+            let value = match self.code.varnames.iter().position(|param| param == name) {
+                Some(idx) => self.fast[idx].take(),
+                None => None,
+            };
+            self.cells.push(vm.new_cell(value));
+        }
+        if let Some(closure) = closure.and_then(|closure| closure.as_tuple()) {
+            self.cells.extend(closure.iter().cloned());
+        }
+    }
+
+    fn push(&mut self, value: PyObjectRef) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> PyObjectRef {
+        self.stack.pop().expect("value stack underflow")
+    }
+
+    fn top(&self) -> &PyObjectRef {
+        self.stack.last().expect("empty value stack")
+    }
+
+    fn peek(&self, n: usize) -> &PyObjectRef {
+        &self.stack[self.stack.len() - n]
+    }
+
+    fn pop_n(&mut self, n: usize) -> Vec<PyObjectRef> {
+        self.stack.split_off(self.stack.len() - n)
+    }
+
+    fn name(&self, idx: u32) -> &str {
+        &self.code.names[idx as usize]
+    }
+
+    /// Runs until the code returns or yields, or an exception escapes it.
+    pub fn run(&mut self, vm: &VirtualMachine, resume: Resume) -> PyResult<ExecutionResult> {
+        vm.frames.borrow_mut().push(self.info());
+        let result = self.run_instructions(vm, resume);
+        vm.frames.borrow_mut().pop();
+        result
+    }
+
+    /// The globals, the first argument and the `__class__` cell of this frame.
+    fn info(&self) -> FrameInfo {
+        let first_arg = match self.code.varnames.first() {
+            Some(name) if self.code.argcount > 0 => match self.code.cellvars.iter().position(|cell| cell == name) {
+                Some(idx) => self.cell_contents(idx as u32),
+                None => self.fast[0].clone(),
+            },
+            _ => None,
+        };
+        let class_cell = self
+            .code
+            .freevars
+            .iter()
+            .position(|name| name == "__class__")
+            .and_then(|idx| self.cells.get(self.code.cellvars.len() + idx).cloned());
+        FrameInfo { globals: self.globals.clone(), first_arg, class_cell }
+    }
+
+    fn run_instructions(&mut self, vm: &VirtualMachine, resume: Resume) -> PyResult<ExecutionResult> {
+        let mut pending = match resume {
+            Resume::Start => None,
+            Resume::Send(value) => {
+                self.push(value);
+                None
+            }
+            Resume::Throw(exc) => match self.code.instructions.get(self.lasti) {
+                // Paused in a `yield from` or `await`: the exception goes to the delegate.
+                Some(Instruction::YieldFrom) => {
+                    self.lasti += 1;
+                    let receiver = self.top().clone();
+                    match vm.throw(&receiver, exc) {
+                        Ok(ExecutionResult::Return(value)) => {
+                            self.pop();
+                            self.push(value);
+                            None
+                        }
+                        Ok(ExecutionResult::Yield(value) | ExecutionResult::Await(value)) => {
+                            self.lasti -= 1;
+                            return Ok(ExecutionResult::Await(value));
+                        }
+                        Err(exc) => {
+                            self.pop();
+                            Some(exc)
+                        }
+                    }
+                }
+                _ => Some(exc),
+            },
+        };
+        loop {
+            let result = match pending.take() {
+                Some(exc) => Err(exc),
+                None => {
+                    let instruction = self.code.instructions[self.lasti];
+                    self.lasti += 1;
+                    self.execute(vm, instruction)
+                }
+            };
+            match result {
+                Ok(None) => {}
+                Ok(Some(result)) => return Ok(result),
+                Err(exc) => {
+                    if !std::mem::take(&mut self.reraise) {
+                        self.add_traceback(&exc);
+                        vm.chain_exception(&exc);
+                    }
+                    match self.blocks.pop() {
+                        Some(block) => {
+                            self.stack.truncate(block.level);
+                            self.push(exc);
+                            self.lasti = block.handler;
+                        }
+                        None => return Err(exc),
+                    }
+                }
+            }
+        }
+    }
+
+    fn add_traceback(&self, exc: &PyObjectRef) {
+        if let Some(state) = exc.as_exception() {
+            state.traceback.borrow_mut().push(TracebackEntry {
+                filename: self.code.filename.clone(),
+                line: self.code.line_number(self.lasti.saturating_sub(1)),
+                name: self.code.name.clone(),
+            });
+        }
+    }
+
+    /// Looks a name up in the local namespace, which a metaclass's `__prepare__` can have
+    /// made any mapping.
+    fn namespace_get(&self, vm: &VirtualMachine, namespace: &PyObjectRef, name: &str) -> PyResult<Option<PyObjectRef>> {
+        if namespace.as_dict().is_some() && !vm.has_special(namespace, "__getitem__") {
+            return Ok(vm.dict_get_str(namespace, name));
+        }
+        match vm.get_item(namespace, &vm.new_str(name.to_string())) {
+            Ok(value) => Ok(Some(value)),
+            Err(exc) if vm.isinstance(&exc, &vm.exceptions.key_error) => Ok(None),
+            Err(exc) => Err(exc),
+        }
+    }
+
+    fn load_global(&self, vm: &VirtualMachine, name: &str) -> PyResult {
+        if let Some(value) = vm.dict_get_str(&self.globals, name) {
+            return Ok(value);
+        }
+        match vm.dict_get_str(&self.builtins, name) {
+            Some(value) => Ok(value),
+            None => Err(vm.new_name_error(name)),
+        }
+    }
+
+    fn locals(&self, vm: &VirtualMachine) -> PyResult {
+        self.locals
+            .clone()
+            .ok_or_else(|| vm.new_exception_msg(&vm.exceptions.system_error, "no locals found".to_string()))
+    }
+
+    fn unbound_local(&self, vm: &VirtualMachine, name: &str) -> PyObjectRef {
+        vm.new_exception_msg(
+            &vm.exceptions.unbound_local_error,
+            format!("cannot access local variable '{}' where it is not associated with a value", name),
+        )
+    }
+
+    fn unbound_deref(&self, vm: &VirtualMachine, idx: u32) -> PyObjectRef {
+        let name = self.code.deref_name(idx);
+        if (idx as usize) < self.code.cellvars.len() {
+            return self.unbound_local(vm, name);
+        }
+        vm.new_exception_msg(
+            &vm.exceptions.name_error,
+            format!(
+                "cannot access free variable '{}' where it is not associated with a value in enclosing scope",
+                name
+            ),
+        )
+    }
+
+    fn cell_contents(&self, idx: u32) -> Option<PyObjectRef> {
+        match &self.cells[idx as usize].payload {
+            Payload::Cell(contents) => contents.borrow().clone(),
+            _ => unreachable!("not a cell"),
+        }
+    }
+
+    fn set_cell(&self, idx: u32, value: Option<PyObjectRef>) {
+        if let Payload::Cell(contents) = &self.cells[idx as usize].payload {
+            *contents.borrow_mut() = value;
+        }
+    }
+
+    fn jump(&mut self, target: u32) {
+        self.lasti = target as usize;
+    }
+
+    fn execute(&mut self, vm: &VirtualMachine, instruction: Instruction) -> PyResult<Option<ExecutionResult>> {
+        use Instruction::*;
+        match instruction {
+            Nop => {}
+            Pop => {
+                self.pop();
+            }
+            Copy(n) => {
+                let value = self.peek(n as usize).clone();
+                self.push(value);
+            }
+            Swap(n) => {
+                let len = self.stack.len();
+                self.stack.swap(len - 1, len - n as usize);
+            }
+
+            LoadConst(idx) => {
+                let value = self.consts[idx as usize].clone();
+                self.push(value);
+            }
+            LoadName(idx) => {
+                let name = self.name(idx).to_string();
+                let locals = self.locals(vm)?;
+                let value = match self.namespace_get(vm, &locals, &name)? {
+                    Some(value) => value,
+                    None => self.load_global(vm, &name)?,
+                };
+                self.push(value);
+            }
+            StoreName(idx) => {
+                let value = self.pop();
+                let locals = self.locals(vm)?;
+                let name = vm.new_str(self.name(idx).to_string());
+                vm.set_item(&locals, &name, &value)?;
+            }
+            DeleteName(idx) => {
+                let locals = self.locals(vm)?;
+                let name = self.name(idx).to_string();
+                match vm.del_item(&locals, &vm.new_str(name.clone())) {
+                    Err(exc) if vm.isinstance(&exc, &vm.exceptions.key_error) => return Err(vm.new_name_error(&name)),
+                    result => result?,
+                }
+            }
+            LoadGlobal(idx) => {
+                let value = self.load_global(vm, self.name(idx))?;
+                self.push(value);
+            }
+            StoreGlobal(idx) => {
+                let value = self.pop();
+                vm.dict_set_str(&self.globals, self.name(idx), value);
+            }
+            DeleteGlobal(idx) => {
+                let name = self.name(idx);
+                if vm.dict_del_str(&self.globals, name).is_none() {
+                    return Err(vm.new_name_error(name));
+                }
+            }
+            LoadFast(idx) => match &self.fast[idx as usize] {
+                Some(value) => {
+                    let value = value.clone();
+                    self.push(value);
+                }
+                None => return Err(self.unbound_local(vm, &self.code.varnames[idx as usize])),
+            },
+            StoreFast(idx) => {
+                let value = self.pop();
+                self.fast[idx as usize] = Some(value);
+            }
+            DeleteFast(idx) => {
+                if self.fast[idx as usize].take().is_none() {
+                    return Err(self.unbound_local(vm, &self.code.varnames[idx as usize]));
+                }
+            }
+            LoadDeref(idx) => match self.cell_contents(idx) {
+                Some(value) => self.push(value),
+                None => return Err(self.unbound_deref(vm, idx)),
+            },
+            StoreDeref(idx) => {
+                let value = self.pop();
+                self.set_cell(idx, Some(value));
+            }
+            DeleteDeref(idx) => {
+                if self.cell_contents(idx).is_none() {
+                    return Err(self.unbound_deref(vm, idx));
+                }
+                self.set_cell(idx, None);
+            }
+            LoadClassDeref(idx) => {
+                let locals = self.locals(vm)?;
+                let value = match self.namespace_get(vm, &locals, self.code.deref_name(idx))? {
+                    Some(value) => value,
+                    None => self.cell_contents(idx).ok_or_else(|| self.unbound_deref(vm, idx))?,
+                };
+                self.push(value);
+            }
+            LoadLocals => {
+                let locals = self.locals(vm)?;
+                self.push(locals);
+            }
+            LoadFromDictOrGlobals(idx) => {
+                let namespace = self.pop();
+                let value = match self.namespace_get(vm, &namespace, self.name(idx))? {
+                    Some(value) => value,
+                    None => self.load_global(vm, self.name(idx))?,
+                };
+                self.push(value);
+            }
+            LoadFromDictOrDeref(idx) => {
+                let namespace = self.pop();
+                let value = match self.namespace_get(vm, &namespace, self.code.deref_name(idx))? {
+                    Some(value) => value,
+                    None => self.cell_contents(idx).ok_or_else(|| self.unbound_deref(vm, idx))?,
+                };
+                self.push(value);
+            }
+            LoadClosure(idx) => {
+                let cell = self.cells[idx as usize].clone();
+                self.push(cell);
+            }
+            LoadAttr(idx) => {
+                let obj = self.pop();
+                let value = vm.get_attribute(&obj, self.name(idx))?;
+                self.push(value);
+            }
+            StoreAttr(idx) => {
+                let obj = self.pop();
+                let value = self.pop();
+                vm.set_attribute(&obj, self.name(idx), value)?;
+            }
+            DeleteAttr(idx) => {
+                let obj = self.pop();
+                vm.del_attribute(&obj, self.name(idx))?;
+            }
+            BinarySubscr => {
+                let key = self.pop();
+                let obj = self.pop();
+                let value = vm.get_item(&obj, &key)?;
+                self.push(value);
+            }
+            StoreSubscr => {
+                let key = self.pop();
+                let obj = self.pop();
+                let value = self.pop();
+                vm.set_item(&obj, &key, &value)?;
+            }
+            DeleteSubscr => {
+                let key = self.pop();
+                let obj = self.pop();
+                vm.del_item(&obj, &key)?;
+            }
+            BuildSlice(n) => {
+                let step = if n == 3 { self.pop() } else { vm.none() };
+                let stop = self.pop();
+                let start = self.pop();
+                let slice = vm.new_object(Payload::Slice { start, stop, step }, &vm.types.slice_type);
+                self.push(slice);
+            }
+
+            UnaryOp(op) => {
+                let value = self.pop();
+                let result = vm.unary_op(&value, op)?;
+                self.push(result);
+            }
+            BinaryOp(op) => {
+                let right = self.pop();
+                let left = self.pop();
+                let result = vm.binary_op(&left, &right, op)?;
+                self.push(result);
+            }
+            InplaceOp(op) => {
+                let right = self.pop();
+                let left = self.pop();
+                let result = vm.inplace_op(&left, &right, op)?;
+                self.push(result);
+            }
+            CompareOp(op) => {
+                let right = self.pop();
+                let left = self.pop();
+                let result = vm.rich_compare(&left, &right, op)?;
+                self.push(result);
+            }
+            IsOp(invert) => {
+                let right = self.pop();
+                let left = self.pop();
+                self.push(vm.new_bool(left.is(&right) != invert));
+            }
+            ContainsOp(invert) => {
+                let container = self.pop();
+                let item = self.pop();
+                let found = vm.contains(&container, &item)?;
+                self.push(vm.new_bool(found != invert));
+            }
+
+            BuildTuple(n) => {
+                let items = self.pop_n(n as usize);
+                self.push(vm.new_tuple(items));
+            }
+            BuildList(n) => {
+                let items = self.pop_n(n as usize);
+                self.push(vm.new_list(items));
+            }
+            BuildSet(n) => {
+                let items = self.pop_n(n as usize);
+                let set = vm.new_set();
+                for item in &items {
+                    vm.set_add(&set, item)?;
+                }
+                self.push(set);
+            }
+            BuildMap(n) => {
+                let items = self.pop_n(2 * n as usize);
+                let dict = vm.new_dict();
+                for pair in items.chunks(2) {
+                    vm.dict_set_item(&dict, &pair[0], &pair[1])?;
+                }
+                self.push(dict);
+            }
+            BuildString(n) => {
+                let parts = self.pop_n(n as usize);
+                let joined: String = parts.iter().filter_map(|part| part.as_str()).collect();
+                self.push(vm.new_str(joined));
+            }
+            ListAppend(n) => {
+                let item = self.pop();
+                if let Payload::List(items) = &self.peek(n as usize).payload {
+                    items.borrow_mut().push(item);
+                }
+            }
+            SetAdd(n) => {
+                let item = self.pop();
+                vm.set_add(self.peek(n as usize), &item)?;
+            }
+            MapAdd(n) => {
+                let value = self.pop();
+                let key = self.pop();
+                vm.dict_set_item(self.peek(n as usize), &key, &value)?;
+            }
+            ListExtend(n) => {
+                let iterable = self.pop();
+                let items = match vm.collect(&iterable) {
+                    Err(exc) if vm.isinstance(&exc, &vm.exceptions.type_error) && !vm.is_iterable(&iterable) => {
+                        return Err(vm.new_type_error(format!(
+                            "Value after * must be an iterable, not {}",
+                            vm.class_name(&iterable)
+                        )))
+                    }
+                    result => result?,
+                };
+                if let Payload::List(list) = &self.peek(n as usize).payload {
+                    list.borrow_mut().extend(items);
+                }
+            }
+            SetUpdate(n) => {
+                let iterable = self.pop();
+                for item in vm.collect(&iterable)? {
+                    vm.set_add(self.peek(n as usize), &item)?;
+                }
+            }
+            DictUpdate(n) => {
+                let source = self.pop();
+                if !vm.is_mapping(&source) {
+                    return Err(vm.new_type_error(format!("'{}' object is not a mapping", vm.class_name(&source))));
+                }
+                vm.dict_update(self.peek(n as usize), &source)?;
+            }
+            DictMerge(n) => {
+                let source = self.pop();
+                let callable = self.peek(n as usize + 2).clone();
+                let function = vm.callable_description(&callable);
+                if !vm.is_mapping(&source) {
+                    return Err(vm.new_type_error(format!(
+                        "{} argument after ** must be a mapping, not {}",
+                        function,
+                        vm.class_name(&source)
+                    )));
+                }
+                let target = self.peek(n as usize).clone();
+                let keys = match source.as_dict() {
+                    Some(table) if !vm.has_special(&source, "keys") => table.borrow().keys(),
+                    _ => vm.collect(&vm.call_method(&source, "keys", vec![])?)?,
+                };
+                for key in keys {
+                    if key.as_str().is_none() {
+                        return Err(vm.new_type_error(format!("{} keywords must be strings", function)));
+                    }
+                    if vm.dict_get_item(&target, &key)?.is_some() {
+                        return Err(vm.new_type_error(format!(
+                            "{} got multiple values for keyword argument '{}'",
+                            function,
+                            key.as_str().unwrap_or_default()
+                        )));
+                    }
+                    let value = vm.get_item(&source, &key)?;
+                    vm.dict_set_item(&target, &key, &value)?;
+                }
+            }
+            ListToTuple => {
+                let list = self.pop();
+                let items = vm.collect(&list)?;
+                self.push(vm.new_tuple(items));
+            }
+            FormatValue { conversion, has_spec } => {
+                let spec = if has_spec { self.pop() } else { vm.new_str(String::new()) };
+                let value = self.pop();
+                let conversion = match conversion {
+                    crate::compiler::bytecode::Conversion::None => None,
+                    crate::compiler::bytecode::Conversion::Str => Some('s'),
+                    crate::compiler::bytecode::Conversion::Repr => Some('r'),
+                    crate::compiler::bytecode::Conversion::Ascii => Some('a'),
+                };
+                let text = vm.format_field(&value, conversion, spec.as_str().unwrap_or_default())?;
+                self.push(vm.new_str(text));
+            }
+            UnpackSequence(n) => {
+                let value = self.pop();
+                let items = self.unpack(vm, &value, n as usize, None)?;
+                self.stack.extend(items.into_iter().rev());
+            }
+            UnpackEx { before, after } => {
+                let value = self.pop();
+                let items = self.unpack(vm, &value, before as usize, Some(after as usize))?;
+                self.stack.extend(items.into_iter().rev());
+            }
+
+            Jump(target) => self.jump(target),
+            PopJumpIfFalse(target) => {
+                let value = self.pop();
+                if !vm.is_true(&value)? {
+                    self.jump(target);
+                }
+            }
+            PopJumpIfTrue(target) => {
+                let value = self.pop();
+                if vm.is_true(&value)? {
+                    self.jump(target);
+                }
+            }
+            PopJumpIfNone(target) => {
+                if vm.is_none(&self.pop()) {
+                    self.jump(target);
+                }
+            }
+            PopJumpIfNotNone(target) => {
+                if !vm.is_none(&self.pop()) {
+                    self.jump(target);
+                }
+            }
+            JumpIfFalseOrPop(target) => {
+                if vm.is_true(self.top())? {
+                    self.pop();
+                } else {
+                    self.jump(target);
+                }
+            }
+            JumpIfTrueOrPop(target) => {
+                if vm.is_true(self.top())? {
+                    self.jump(target);
+                } else {
+                    self.pop();
+                }
+            }
+            GetIter => {
+                let iterable = self.pop();
+                let iter = vm.get_iter(&iterable)?;
+                self.push(iter);
+            }
+            ForIter(target) => {
+                let iter = self.top().clone();
+                match vm.next(&iter)? {
+                    Some(item) => self.push(item),
+                    None => {
+                        self.pop();
+                        self.jump(target);
+                    }
+                }
+            }
+
+            MakeFunction(flags) => {
+                let code = self.pop();
+                let closure = if flags & MAKE_CLOSURE != 0 { Some(self.pop()) } else { None };
+                let annotations = if flags & MAKE_ANNOTATIONS != 0 { Some(self.pop()) } else { None };
+                let kwdefaults = if flags & MAKE_KWDEFAULTS != 0 { Some(self.pop()) } else { None };
+                let defaults = if flags & MAKE_DEFAULTS != 0 { Some(self.pop()) } else { None };
+                let Payload::Code(code) = &code.payload else { unreachable!("MakeFunction without code") };
+                let function =
+                    vm.make_function(code.clone(), &self.globals, defaults, kwdefaults, annotations, closure);
+                self.push(function);
+            }
+            Call(n) => {
+                let args = self.pop_n(n as usize);
+                let callable = self.pop();
+                let result = vm.call(&callable, FuncArgs::new(args))?;
+                self.push(result);
+            }
+            CallKw(n) => {
+                let kwnames = self.pop();
+                let mut args = self.pop_n(n as usize);
+                let callable = self.pop();
+                let names = kwnames.as_tuple().expect("keyword names tuple");
+                let values = args.split_off(args.len() - names.len());
+                let kwargs = names
+                    .iter()
+                    .map(|name| name.as_str().expect("keyword name").to_string())
+                    .zip(values)
+                    .collect();
+                let result = vm.call(&callable, FuncArgs::with_kwargs(args, kwargs))?;
+                self.push(result);
+            }
+            CallFunctionEx(has_kwargs) => {
+                let kwargs = if has_kwargs { Some(self.pop()) } else { None };
+                let args = self.pop();
+                let callable = self.pop();
+                let args = match args.as_tuple() {
+                    Some(args) => args.to_vec(),
+                    None => vm.collect(&args)?,
+                };
+                let mut kwarg_pairs = vec![];
+                if let Some(kwargs) = kwargs {
+                    for (key, value) in kwargs.as_dict().expect("kwargs dict").borrow().items() {
+                        kwarg_pairs.push((key.as_str().unwrap_or_default().to_string(), value));
+                    }
+                }
+                let result = vm.call(&callable, FuncArgs::with_kwargs(args, kwarg_pairs))?;
+                self.push(result);
+            }
+            ReturnValue => {
+                let value = self.pop();
+                return Ok(Some(ExecutionResult::Return(value)));
+            }
+
+            YieldValue => {
+                let value = self.pop();
+                return Ok(Some(ExecutionResult::Yield(value)));
+            }
+            YieldFrom => {
+                let value = self.pop();
+                let receiver = self.top().clone();
+                match vm.send(&receiver, value)? {
+                    ExecutionResult::Return(value) => {
+                        self.pop();
+                        self.push(value);
+                    }
+                    ExecutionResult::Yield(value) | ExecutionResult::Await(value) => {
+                        // Resume at this instruction with the value sent in.
+                        self.lasti -= 1;
+                        return Ok(Some(ExecutionResult::Await(value)));
+                    }
+                }
+            }
+            GetYieldFromIter => {
+                let iterable = self.top().clone();
+                let iter = match &iterable.payload {
+                    Payload::Generator(generator) if generator.kind == GeneratorKind::Coroutine => {
+                        if self.code.flags & CO_COROUTINE == 0 {
+                            return Err(vm.new_type_error(
+                                "cannot 'yield from' a coroutine object in a non-coroutine generator".to_string(),
+                            ));
+                        }
+                        iterable
+                    }
+                    Payload::Generator(generator) if generator.kind == GeneratorKind::Generator => iterable,
+                    _ => vm.get_iter(&iterable)?,
+                };
+                self.pop();
+                self.push(iter);
+            }
+            GetAwaitable => {
+                let value = self.pop();
+                let awaitable = vm.get_awaitable(&value)?;
+                self.push(awaitable);
+            }
+            GetAiter => {
+                let value = self.pop();
+                let iter = match &value.payload {
+                    Payload::Generator(generator) if generator.kind == GeneratorKind::AsyncGenerator => value,
+                    _ => match vm.call_special(&value, "__aiter__", vec![])? {
+                        Some(iter) => iter,
+                        None => {
+                            return Err(vm.new_type_error(format!(
+                                "'async for' requires an object with __aiter__ method, got {}",
+                                vm.class_name(&value)
+                            )))
+                        }
+                    },
+                };
+                self.push(iter);
+            }
+            GetAnext => {
+                let iter = self.top().clone();
+                let awaitable = match &iter.payload {
+                    Payload::Generator(generator) if generator.kind == GeneratorKind::AsyncGenerator => {
+                        vm.new_async_gen_awaitable(&iter, vm.none())
+                    }
+                    _ => {
+                        let Some(next) = vm.call_special(&iter, "__anext__", vec![])? else {
+                            return Err(vm.new_type_error(format!(
+                                "'async for' requires an iterator with __anext__ method, got {}",
+                                vm.class_name(&iter)
+                            )));
+                        };
+                        match vm.get_awaitable(&next) {
+                            Ok(awaitable) => awaitable,
+                            Err(_) => {
+                                return Err(vm.new_type_error(format!(
+                                    "'async for' received an invalid object from __anext__: {}",
+                                    vm.class_name(&next)
+                                )))
+                            }
+                        }
+                    }
+                };
+                self.push(awaitable);
+            }
+            EndAsyncFor => {
+                let exc = self.pop();
+                if !vm.isinstance(&exc, &vm.exceptions.stop_async_iteration) {
+                    self.reraise = true;
+                    return Err(exc);
+                }
+                self.pop();
+            }
+
+            ImportName(idx) => {
+                let fromlist = self.pop();
+                let level = self.pop();
+                let level = level.as_int().and_then(|level| level.to_i64()).unwrap_or(0) as usize;
+                let module = vm.import_name(self.name(idx), &self.globals, &fromlist, level)?;
+                self.push(module);
+            }
+            ImportFrom(idx) => {
+                let module = self.top().clone();
+                let value = vm.import_from(&module, self.name(idx))?;
+                self.push(value);
+            }
+            ImportStar => {
+                let module = self.pop();
+                let locals = self.locals(vm)?;
+                vm.import_star(&module, &locals)?;
+            }
+
+            LoadBuildClass => match vm.dict_get_str(&self.builtins, "__build_class__") {
+                Some(build_class) => self.push(build_class),
+                None => return Err(vm.new_name_error("__build_class__")),
+            },
+            SetupAnnotations => {
+                let locals = self.locals(vm)?;
+                if self.namespace_get(vm, &locals, "__annotations__")?.is_none() {
+                    vm.set_item(&locals, &vm.new_str("__annotations__".to_string()), &vm.new_dict())?;
+                }
+            }
+            LoadAssertionError => self.push(vm.exceptions.assertion_error.clone()),
+
+            SetupFinally(target) => {
+                self.blocks.push(Block { handler: target as usize, level: self.stack.len() });
+            }
+            SetupWith(target) => {
+                self.blocks.push(Block { handler: target as usize, level: self.stack.len() - 1 });
+            }
+            PopBlock => {
+                self.blocks.pop();
+            }
+            PushExcInfo => {
+                let exc = self.pop();
+                let previous = vm.handled.replace(Some(exc.clone())).unwrap_or_else(|| vm.none());
+                self.push(previous);
+                self.push(exc);
+            }
+            PopExcept => {
+                let previous = self.pop();
+                *vm.handled.borrow_mut() = if vm.is_none(&previous) { None } else { Some(previous) };
+            }
+            Raise(argc) => {
+                let exc = match argc {
+                    0 => {
+                        self.reraise = true;
+                        return Err(vm.handled.borrow().clone().unwrap_or_else(|| {
+                            vm.new_runtime_error("No active exception to reraise".to_string())
+                        }));
+                    }
+                    1 => {
+                        let value = self.pop();
+                        vm.make_exception(&value)?
+                    }
+                    _ => {
+                        let cause = self.pop();
+                        let value = self.pop();
+                        let exc = vm.make_exception(&value)?;
+                        let cause = match vm.is_none(&cause) {
+                            true => None,
+                            false => Some(vm.make_exception(&cause).map_err(|_| {
+                                vm.new_type_error("exception causes must derive from BaseException".to_string())
+                            })?),
+                        };
+                        let state = exc.as_exception().expect("exception");
+                        *state.cause.borrow_mut() = cause;
+                        state.suppress_context.set(true);
+                        exc
+                    }
+                };
+                return Err(exc);
+            }
+            Reraise => {
+                let exc = self.pop();
+                self.reraise = true;
+                return Err(exc);
+            }
+            CheckExcMatch => {
+                let class = self.pop();
+                let matches = vm.exception_matches(self.top(), &class)?;
+                self.push(vm.new_bool(matches));
+            }
+            CheckEgMatch => {
+                let class = self.pop();
+                let exc = self.pop();
+                let (rest, matched) = vm.exception_group_match(&exc, &class)?;
+                if !vm.is_none(&matched) {
+                    *vm.handled.borrow_mut() = Some(matched.clone());
+                }
+                self.push(rest);
+                self.push(matched);
+            }
+            PrepReraiseStar => {
+                let raised = self.pop();
+                let original = self.pop();
+                let raised = vm.collect(&raised)?;
+                self.push(vm.prep_reraise_star(&original, raised)?);
+            }
+            BeforeWith => {
+                let manager = self.pop();
+                let (Some(enter), Some(exit)) =
+                    (vm.lookup_method(&manager, "__enter__"), vm.lookup_method(&manager, "__exit__"))
+                else {
+                    return Err(vm.new_type_error(format!(
+                        "'{}' object does not support the context manager protocol",
+                        vm.class_name(&manager)
+                    )));
+                };
+                let exit = vm.bind(&exit, Some(&manager), &manager.class())?;
+                let result = vm.call_bound(&enter, &manager, vec![])?;
+                self.push(exit);
+                self.push(result);
+            }
+            BeforeAsyncWith => {
+                let manager = self.pop();
+                let (Some(enter), Some(exit)) =
+                    (vm.lookup_method(&manager, "__aenter__"), vm.lookup_method(&manager, "__aexit__"))
+                else {
+                    return Err(vm.new_type_error(format!(
+                        "'{}' object does not support the asynchronous context manager protocol",
+                        vm.class_name(&manager)
+                    )));
+                };
+                let exit = vm.bind(&exit, Some(&manager), &manager.class())?;
+                let result = vm.call_bound(&enter, &manager, vec![])?;
+                self.push(exit);
+                self.push(result);
+            }
+            WithExceptStart => {
+                let exc = self.top().clone();
+                let exit = self.peek(3).clone();
+                let result = vm.call(&exit, FuncArgs::new(vec![exc.class(), exc, vm.none()]))?;
+                self.push(result);
+            }
+
+            MatchMapping => {
+                let is_mapping = vm.is_subclass(&self.top().class(), &vm.types.dict_type);
+                self.push(vm.new_bool(is_mapping));
+            }
+            MatchSequence => {
+                let class = self.top().class();
+                let is_sequence = [&vm.types.list_type, &vm.types.tuple_type, &vm.types.range_type]
+                    .iter()
+                    .any(|base| vm.is_subclass(&class, base));
+                self.push(vm.new_bool(is_sequence));
+            }
+            MatchKeys => {
+                let keys = self.top().clone();
+                let subject = self.peek(2).clone();
+                let mut values = vec![];
+                let mut matched = true;
+                for key in keys.as_tuple().expect("keys tuple") {
+                    match vm.dict_get_item(&subject, key)? {
+                        Some(value) => values.push(value),
+                        None => {
+                            matched = false;
+                            break;
+                        }
+                    }
+                }
+                self.push(if matched { vm.new_tuple(values) } else { vm.none() });
+            }
+            MatchClass(count) => {
+                let names = self.pop();
+                let class = self.pop();
+                let subject = self.pop();
+                let result = vm.match_class(&subject, &class, count as usize, &names)?;
+                self.push(result.unwrap_or_else(|| vm.none()));
+            }
+            GetLen => {
+                let len = vm.len(self.top())?;
+                self.push(vm.new_int(len as i64));
+            }
+            CopyDictWithoutKeys => {
+                let keys = self.pop();
+                let subject = self.top().clone();
+                let rest = vm.new_dict();
+                vm.dict_update(&rest, &subject)?;
+                for key in keys.as_tuple().expect("keys tuple") {
+                    vm.dict_del_item(&rest, key)?;
+                }
+                self.push(rest);
+            }
+
+            MakeTypeParam(flavor) => {
+                let default = self.pop();
+                let bound = self.pop();
+                let name = self.pop();
+                let param = vm.make_type_param(flavor, name, bound, default);
+                self.push(param);
+            }
+            MakeTypeAlias => {
+                let evaluate = self.pop();
+                let name = self.pop();
+                let params = self.pop();
+                let alias = vm.new_object(Payload::Object, &vm.types.type_alias_type);
+                let dict = alias.dict.as_ref().expect("type alias dict");
+                vm.dict_set_str(dict, "__name__", name);
+                vm.dict_set_str(dict, "__type_params__", params);
+                // `__value__` is evaluated the first time it is read.
+                vm.dict_set_str(dict, "__evaluate_value__", evaluate);
+                vm.dict_set_str(dict, "__module__", vm.dict_get_str(&self.globals, "__name__").unwrap_or_else(|| vm.none()));
+                self.push(alias);
+            }
+        }
+        Ok(None)
+    }
+
+    /// The items of `value` for an unpacking assignment: `before` of them, or with `after`
+    /// the items before a starred target, a list for it and the items after it.
+    fn unpack(
+        &self,
+        vm: &VirtualMachine,
+        value: &PyObjectRef,
+        before: usize,
+        after: Option<usize>,
+    ) -> PyResult<Vec<PyObjectRef>> {
+        let items = match vm.collect(value) {
+            Err(exc) if vm.isinstance(&exc, &vm.exceptions.type_error) && !vm.is_iterable(value) => {
+                return Err(vm.new_type_error(format!("cannot unpack non-iterable {} object", vm.class_name(value))))
+            }
+            result => result?,
+        };
+        match after {
+            None if items.len() > before => {
+                Err(vm.new_value_error(format!("too many values to unpack (expected {})", before)))
+            }
+            None if items.len() < before => Err(vm.new_value_error(format!(
+                "not enough values to unpack (expected {}, got {})",
+                before,
+                items.len()
+            ))),
+            None => Ok(items),
+            Some(after) if items.len() < before + after => Err(vm.new_value_error(format!(
+                "not enough values to unpack (expected at least {}, got {})",
+                before + after,
+                items.len()
+            ))),
+            Some(after) => {
+                let mut items = items;
+                let tail = items.split_off(items.len() - after);
+                let starred = items.split_off(before);
+                items.push(vm.new_list(starred));
+                items.extend(tail);
+                Ok(items)
+            }
+        }
+    }
+}
+
+impl VirtualMachine {
+    pub fn new_cell(&self, value: Option<PyObjectRef>) -> PyObjectRef {
+        self.new_object(Payload::Cell(std::cell::RefCell::new(value)), &self.types.cell_type)
+    }
+
+    /// A method found on the class of `obj`, whether a class statement or a builtin type
+    /// defined it.
+    pub(crate) fn lookup_method(&self, obj: &PyObjectRef, name: &str) -> Option<PyObjectRef> {
+        self.type_lookup(&obj.class(), name)
+    }
+
+    fn is_iterable(&self, obj: &PyObjectRef) -> bool {
+        self.has_special(obj, "__iter__")
+            || self.has_special(obj, "__getitem__")
+            || matches!(
+                obj.payload,
+                Payload::List(_)
+                    | Payload::Tuple(_)
+                    | Payload::Str(_)
+                    | Payload::Bytes(_)
+                    | Payload::Dict(_)
+                    | Payload::Set(_)
+                    | Payload::FrozenSet(_)
+                    | Payload::Range { .. }
+                    | Payload::Iterator(_)
+                    | Payload::Generator(_)
+            )
+    }
+
+    pub(crate) fn is_mapping(&self, obj: &PyObjectRef) -> bool {
+        obj.as_dict().is_some() || self.has_special(obj, "keys")
+    }
+
+    /// `module.f()` in messages about the arguments of a call to `callable`.
+    fn callable_description(&self, callable: &PyObjectRef) -> String {
+        match &callable.payload {
+            Payload::Function(function) => match function.module.borrow().as_str() {
+                Some(module) if module != "builtins" => format!("{}.{}()", module, function.qualname.borrow()),
+                _ => format!("{}()", function.qualname.borrow()),
+            },
+            Payload::BuiltinFunction(function) => format!("{}()", function.name),
+            Payload::BoundMethod { function, .. } => self.callable_description(function),
+            Payload::Type(ty) => format!("{}()", ty.name()),
+            _ => format!("{} object", self.class_name(callable)),
+        }
+    }
+
+    /// What `await value` iterates over.
+    pub(crate) fn get_awaitable(&self, value: &PyObjectRef) -> PyResult {
+        match &value.payload {
+            Payload::Generator(generator) if generator.kind == GeneratorKind::Coroutine => return Ok(value.clone()),
+            Payload::AsyncGenAwaitable { .. } => return Ok(value.clone()),
+            _ => {}
+        }
+        let Some(iter) = self.call_special(value, "__await__", vec![])? else {
+            return Err(self.new_type_error(format!(
+                "object {} can't be used in 'await' expression",
+                self.class_name(value)
+            )));
+        };
+        if matches!(&iter.payload, Payload::Generator(generator) if generator.kind == GeneratorKind::Coroutine) {
+            return Err(self.new_type_error("__await__() returned a coroutine".to_string()));
+        }
+        if !matches!(iter.payload, Payload::Iterator(_) | Payload::Generator(_)) && !self.has_special(&iter, "__next__") {
+            return Err(self.new_type_error(format!(
+                "__await__() returned non-iterator of type '{}'",
+                self.class_name(&iter)
+            )));
+        }
+        Ok(iter)
+    }
+
+    /// The attributes a class pattern matches against, or None if `subject` is not an
+    /// instance of `class`.
+    fn match_class(
+        &self,
+        subject: &PyObjectRef,
+        class: &PyObjectRef,
+        count: usize,
+        names: &PyObjectRef,
+    ) -> PyResult<Option<PyObjectRef>> {
+        let Some(ty) = class.as_type() else {
+            return Err(self.new_type_error("called match pattern must be a class".to_string()));
+        };
+        if !self.isinstance(subject, class) {
+            return Ok(None);
+        }
+        let mut values = vec![];
+        let mut seen: Vec<String> = vec![];
+        if count > 0 {
+            match self.type_lookup(class, "__match_args__") {
+                Some(match_args) => {
+                    let Some(match_args) = match_args.as_tuple() else {
+                        return Err(self.new_type_error(format!(
+                            "{}.__match_args__ must be a tuple (got {})",
+                            ty.name(),
+                            self.class_name(&match_args)
+                        )));
+                    };
+                    if count > match_args.len() {
+                        return Err(self.new_type_error(format!(
+                            "{}() accepts {} positional sub-pattern{} ({} given)",
+                            ty.name(),
+                            match_args.len(),
+                            if match_args.len() == 1 { "" } else { "s" },
+                            count
+                        )));
+                    }
+                    for name in &match_args[..count] {
+                        let Some(name) = name.as_str() else {
+                            return Err(self.new_type_error(format!(
+                                "__match_args__ elements must be strings (got {})",
+                                self.class_name(name)
+                            )));
+                        };
+                        seen.push(name.to_string());
+                    }
+                }
+                None if self.matches_self(class) => {
+                    if count > 1 {
+                        return Err(self.new_type_error(format!(
+                            "{}() accepts 1 positional sub-pattern ({} given)",
+                            ty.name(),
+                            count
+                        )));
+                    }
+                    values.push(subject.clone());
+                }
+                None => {
+                    return Err(self.new_type_error(format!(
+                        "{}() accepts 0 positional sub-patterns ({} given)",
+                        ty.name(),
+                        count
+                    )))
+                }
+            }
+        }
+        for name in names.as_tuple().expect("keyword names tuple") {
+            let name = name.as_str().expect("keyword name").to_string();
+            if seen.contains(&name) {
+                return Err(self.new_type_error(format!(
+                    "{}() got multiple sub-patterns for attribute '{}'",
+                    ty.name(),
+                    name
+                )));
+            }
+            seen.push(name);
+        }
+        for name in &seen {
+            match self.get_attribute_opt(subject, name)? {
+                Some(value) => values.push(value),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(self.new_tuple(values)))
+    }
+
+    /// Builtin types whose class patterns take the subject itself as their one positional
+    /// sub-pattern.
+    fn matches_self(&self, class: &PyObjectRef) -> bool {
+        let types = &self.types;
+        [
+            &types.bool_type,
+            &types.bytes_type,
+            &types.dict_type,
+            &types.float_type,
+            &types.frozenset_type,
+            &types.int_type,
+            &types.list_type,
+            &types.set_type,
+            &types.str_type,
+            &types.tuple_type,
+        ]
+        .iter()
+        .any(|builtin| self.is_subclass(class, builtin))
+    }
+
+    fn make_type_param(
+        &self,
+        flavor: TypeParamFlavor,
+        name: PyObjectRef,
+        bound: PyObjectRef,
+        default: PyObjectRef,
+    ) -> PyObjectRef {
+        let class = match flavor {
+            TypeParamFlavor::TypeVar | TypeParamFlavor::ConstrainedTypeVar => &self.types.typevar_type,
+            TypeParamFlavor::TypeVarTuple => &self.types.typevartuple_type,
+            TypeParamFlavor::ParamSpec => &self.types.paramspec_type,
+        };
+        let param = self.new_object(Payload::Object, class);
+        let dict = param.dict.as_ref().expect("type parameter dict");
+        self.dict_set_str(dict, "__name__", name);
+        if matches!(flavor, TypeParamFlavor::TypeVar | TypeParamFlavor::ConstrainedTypeVar) {
+            let (bound, constraints) = match flavor {
+                TypeParamFlavor::ConstrainedTypeVar => (self.none(), bound),
+                _ => (bound, self.new_tuple(vec![])),
+            };
+            self.dict_set_str(dict, "__bound__", bound);
+            self.dict_set_str(dict, "__constraints__", constraints);
+        }
+        self.dict_set_str(dict, "__default__", default);
+        param
+    }
+}
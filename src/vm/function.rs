@@ -0,0 +1,643 @@
+//! Calling objects: Python functions with CPython's argument binding, builtin functions,
+//! bound methods and classes, and running generators and coroutines.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::compiler::bytecode::{
+    Const, CO_ASYNC_GENERATOR, CO_COROUTINE, CO_GENERATOR, CO_NEWLOCALS, CO_VARARGS, CO_VARKEYWORDS,
+};
+use crate::compiler::CodeObject;
+
+use super::frame::{ExecutionResult, Frame, Resume};
+use super::object::{FuncArgs, Generator, GeneratorKind, Payload, PyFunction, PyObjectRef, PyResult};
+use super::VirtualMachine;
+
+/// "'a'", "'a' and 'b'" or "'a', 'b', and 'c'", as CPython lists missing arguments.
+fn quoted_list(names: &[&str]) -> String {
+    let quoted: Vec<String> = names.iter().map(|name| format!("'{}'", name)).collect();
+    match quoted.as_slice() {
+        [one] => one.clone(),
+        [first, second] => format!("{} and {}", first, second),
+        [rest @ .., last] => format!("{}, and {}", rest.join(", "), last),
+        [] => String::new(),
+    }
+}
+
+fn plural(count: usize, word: &str) -> String {
+    if count == 1 {
+        format!("{} {}", count, word)
+    } else {
+        format!("{} {}s", count, word)
+    }
+}
+
+impl VirtualMachine {
+    /// `callable(*args, **kwargs)`.
+    pub fn call(&self, callable: &PyObjectRef, args: FuncArgs) -> PyResult {
+        match &callable.payload {
+            Payload::Function(_) => self.invoke_function(callable, args),
+            Payload::BuiltinFunction(function) => (function.function)(self, args),
+            Payload::BoundMethod { function, receiver } => {
+                let mut all = Vec::with_capacity(args.args.len() + 1);
+                all.push(receiver.clone());
+                all.extend(args.args);
+                self.call(function, FuncArgs::with_kwargs(all, args.kwargs))
+            }
+            _ => match self.special_method(callable, "__call__") {
+                Some(method) => {
+                    let mut all = vec![callable.clone()];
+                    all.extend(args.args);
+                    match &method.payload {
+                        Payload::Function(_) | Payload::BuiltinFunction(_) => {
+                            self.call(&method, FuncArgs::with_kwargs(all, args.kwargs))
+                        }
+                        _ => {
+                            let bound = self.bind(&method, Some(callable), &callable.class())?;
+                            self.call(&bound, FuncArgs::with_kwargs(all[1..].to_vec(), args.kwargs))
+                        }
+                    }
+                }
+                None if callable.as_type().is_some() => self.call_type(callable, args),
+                None => Err(self.new_type_error(format!("'{}' object is not callable", self.class_name(callable)))),
+            },
+        }
+    }
+
+    pub fn is_callable(&self, obj: &PyObjectRef) -> bool {
+        matches!(
+            obj.payload,
+            Payload::Function(_) | Payload::BuiltinFunction(_) | Payload::BoundMethod { .. } | Payload::Type(_)
+        ) || self.has_special(obj, "__call__")
+    }
+
+    pub fn new_builtin_function(&self, name: &'static str, function: super::object::NativeFunction) -> PyObjectRef {
+        self.new_object(
+            Payload::BuiltinFunction(super::object::BuiltinFunction { name, function }),
+            &self.types.builtin_function_type,
+        )
+    }
+
+    /// Adds a method implemented in Rust to a builtin type.
+    pub(crate) fn add_method(&self, class: &PyObjectRef, name: &'static str, function: super::object::NativeFunction) {
+        let method = self.new_builtin_function(name, function);
+        self.dict_set_str(class.dict.as_ref().expect("type dict"), name, method);
+    }
+
+    /// The objects for the constants of `code`, made once per code object.
+    pub fn code_consts(&self, code: &Rc<CodeObject>) -> Rc<Vec<PyObjectRef>> {
+        let key = Rc::as_ptr(code);
+        if let Some((_, consts)) = self.const_cache.borrow().get(&key) {
+            return consts.clone();
+        }
+        let consts: Vec<PyObjectRef> = code
+            .consts
+            .iter()
+            .map(|constant| match constant {
+                Const::Value(value) => self.constant(value),
+                Const::Code(code) => self.new_object(Payload::Code(code.clone()), &self.types.code_type),
+            })
+            .collect();
+        let consts = Rc::new(consts);
+        self.const_cache.borrow_mut().insert(key, (code.clone(), consts.clone()));
+        consts
+    }
+
+    /// The function `MakeFunction` creates.
+    pub(crate) fn make_function(
+        &self,
+        code: Rc<CodeObject>,
+        globals: &PyObjectRef,
+        defaults: Option<PyObjectRef>,
+        kwdefaults: Option<PyObjectRef>,
+        annotations: Option<PyObjectRef>,
+        closure: Option<PyObjectRef>,
+    ) -> PyObjectRef {
+        let doc = match code.consts.first() {
+            Some(Const::Value(crate::ast::ConstantValue::Str(doc)))
+                if code.flags & CO_NEWLOCALS != 0 && !code.name.starts_with('<') =>
+            {
+                self.new_str(doc.clone())
+            }
+            _ => self.none(),
+        };
+        let module = self.dict_get_str(globals, "__name__").unwrap_or_else(|| self.none());
+        let function = PyFunction {
+            name: RefCell::new(code.name.clone()),
+            qualname: RefCell::new(code.qualname.clone()),
+            code,
+            globals: globals.clone(),
+            defaults: RefCell::new(defaults),
+            kwdefaults: RefCell::new(kwdefaults),
+            closure,
+            annotations: RefCell::new(annotations),
+            doc: RefCell::new(doc),
+            module: RefCell::new(module),
+        };
+        self.new_object(Payload::Function(function), &self.types.function_type)
+    }
+
+    fn invoke_function(&self, callable: &PyObjectRef, args: FuncArgs) -> PyResult {
+        let Payload::Function(function) = &callable.payload else { unreachable!("not a function") };
+        let code = function.code.clone();
+        let mut frame = Frame::new(self, code.clone(), function.globals.clone(), None);
+        self.bind_arguments(function, &mut frame, args)?;
+        frame.init_cells(self, function.closure.as_ref());
+        let kind = if code.flags & CO_COROUTINE != 0 {
+            Some(GeneratorKind::Coroutine)
+        } else if code.flags & CO_ASYNC_GENERATOR != 0 {
+            Some(GeneratorKind::AsyncGenerator)
+        } else if code.flags & CO_GENERATOR != 0 {
+            Some(GeneratorKind::Generator)
+        } else {
+            None
+        };
+        if let Some(kind) = kind {
+            let generator = Generator {
+                kind,
+                frame: RefCell::new(Some(Box::new(frame))),
+                running: Cell::new(false),
+                started: Cell::new(false),
+                name: function.name.borrow().clone(),
+                qualname: function.qualname.borrow().clone(),
+            };
+            let class = match kind {
+                GeneratorKind::Generator => &self.types.generator_type,
+                GeneratorKind::Coroutine => &self.types.coroutine_type,
+                GeneratorKind::AsyncGenerator => &self.types.async_generator_type,
+            };
+            return Ok(self.new_object(Payload::Generator(generator), class));
+        }
+        self.run_frame(&mut frame)
+    }
+
+    /// Runs a frame that cannot yield to completion.
+    pub fn run_frame(&self, frame: &mut Frame) -> PyResult {
+        let depth = self.depth.get();
+        if depth >= self.recursion_limit.get() {
+            return Err(self.new_exception_msg(
+                &self.exceptions.recursion_error,
+                "maximum recursion depth exceeded".to_string(),
+            ));
+        }
+        self.depth.set(depth + 1);
+        let result = frame.run(self, Resume::Start);
+        self.depth.set(depth);
+        match result? {
+            ExecutionResult::Return(value) => Ok(value),
+            ExecutionResult::Yield(_) | ExecutionResult::Await(_) => {
+                Err(self.new_exception_msg(&self.exceptions.system_error, "frame yielded".to_string()))
+            }
+        }
+    }
+
+    /// The class and object of a `super()` call without arguments: the `__class__` cell and
+    /// the first argument of the function that makes it.
+    pub(crate) fn super_arguments(&self) -> PyResult<(PyObjectRef, Option<PyObjectRef>)> {
+        let frames = self.frames.borrow();
+        let runtime_error = |msg: &str| self.new_exception_msg(&self.exceptions.runtime_error, msg.to_string());
+        let Some(frame) = frames.last() else {
+            return Err(runtime_error("super(): no current frame"));
+        };
+        let Some(first_arg) = frame.first_arg.clone() else {
+            return Err(runtime_error("super(): no arguments"));
+        };
+        let Some(cell) = &frame.class_cell else {
+            return Err(runtime_error("super(): __class__ cell not found"));
+        };
+        let class = match &cell.payload {
+            Payload::Cell(contents) => contents.borrow().clone(),
+            _ => None,
+        };
+        match class {
+            Some(class) => Ok((class, Some(first_arg))),
+            None => Err(runtime_error("super(): empty __class__ cell")),
+        }
+    }
+
+    /// Puts the arguments of a call into the fast locals of `frame`, the way CPython
+    /// matches them to parameters.
+    fn bind_arguments(&self, function: &PyFunction, frame: &mut Frame, mut args: FuncArgs) -> PyResult<()> {
+        let code = function.code.clone();
+        let qualname = function.qualname.borrow().clone();
+        let argcount = code.argcount as usize;
+        let posonly = code.posonly_argcount as usize;
+        let kwonly = code.kwonly_argcount as usize;
+        let has_varargs = code.flags & CO_VARARGS != 0;
+        let has_varkw = code.flags & CO_VARKEYWORDS != 0;
+        let fast = frame.fast_mut();
+
+        let given = args.args.len();
+        let extra = if given > argcount { args.args.split_off(argcount) } else { vec![] };
+        for (idx, value) in args.args.into_iter().enumerate() {
+            fast[idx] = Some(value);
+        }
+        let mut next = argcount + kwonly;
+        if has_varargs {
+            fast[next] = Some(self.new_tuple(extra.clone()));
+            next += 1;
+        } else if !extra.is_empty() {
+            let defaults = function.defaults.borrow().as_ref().and_then(|d| d.as_tuple().map(<[_]>::len)).unwrap_or(0);
+            let kw_given = args.kwargs.iter().filter(|(name, _)| {
+                code.varnames[argcount..argcount + kwonly].iter().any(|param| param == name)
+            });
+            let kw_given = kw_given.count();
+            let takes = if defaults > 0 {
+                format!("from {} to {}", argcount - defaults, argcount)
+            } else {
+                argcount.to_string()
+            };
+            let positional = if argcount == 1 && defaults == 0 { "positional argument" } else { "positional arguments" };
+            let was = if given == 1 { "was" } else { "were" };
+            let keyword = if kw_given > 0 {
+                format!(
+                    " positional argument{} (and {} keyword-only argument{})",
+                    if given == 1 { "" } else { "s" },
+                    kw_given,
+                    if kw_given == 1 { "" } else { "s" }
+                )
+            } else {
+                String::new()
+            };
+            return Err(self.new_type_error(format!(
+                "{}() takes {} {} but {}{} {} given",
+                qualname, takes, positional, given, keyword, was
+            )));
+        }
+        let varkw = if has_varkw {
+            let dict = self.new_dict();
+            fast[next] = Some(dict.clone());
+            Some(dict)
+        } else {
+            None
+        };
+
+        let mut posonly_as_keyword = vec![];
+        for (name, value) in std::mem::take(&mut args.kwargs) {
+            let slot = code.varnames[..argcount + kwonly].iter().position(|param| *param == name);
+            match slot {
+                Some(idx) if idx >= posonly => {
+                    if fast[idx].is_some() {
+                        return Err(self.new_type_error(format!(
+                            "{}() got multiple values for argument '{}'",
+                            qualname, name
+                        )));
+                    }
+                    fast[idx] = Some(value);
+                }
+                _ => match &varkw {
+                    Some(dict) => self.dict_set_item(dict, &self.new_str(name), &value)?,
+                    None if slot.is_some() => posonly_as_keyword.push(name),
+                    None => {
+                        return Err(self.new_type_error(format!(
+                            "{}() got an unexpected keyword argument '{}'",
+                            qualname, name
+                        )))
+                    }
+                },
+            }
+        }
+        if !posonly_as_keyword.is_empty() {
+            return Err(self.new_type_error(format!(
+                "{}() got some positional-only arguments passed as keyword arguments: '{}'",
+                qualname,
+                posonly_as_keyword.join(", ")
+            )));
+        }
+
+        if given < argcount {
+            let defaults = function.defaults.borrow().clone();
+            let defaults = defaults.as_ref().and_then(|d| d.as_tuple()).unwrap_or(&[]);
+            let first_default = argcount - defaults.len().min(argcount);
+            let mut missing = vec![];
+            for idx in given..argcount {
+                if fast[idx].is_none() {
+                    if idx >= first_default {
+                        fast[idx] = Some(defaults[idx - first_default].clone());
+                    } else {
+                        missing.push(code.varnames[idx].as_str());
+                    }
+                }
+            }
+            if !missing.is_empty() {
+                return Err(self.new_type_error(format!(
+                    "{}() missing {} required positional {}: {}",
+                    qualname,
+                    missing.len(),
+                    if missing.len() == 1 { "argument" } else { "arguments" },
+                    quoted_list(&missing)
+                )));
+            }
+        }
+        if kwonly > 0 {
+            let kwdefaults = function.kwdefaults.borrow().clone();
+            let mut missing = vec![];
+            let names = &code.varnames[argcount..argcount + kwonly];
+            for (slot, name) in fast[argcount..argcount + kwonly].iter_mut().zip(names) {
+                if slot.is_none() {
+                    match kwdefaults.as_ref().and_then(|d| self.dict_get_str(d, name)) {
+                        Some(value) => *slot = Some(value),
+                        None => missing.push(name.as_str()),
+                    }
+                }
+            }
+            if !missing.is_empty() {
+                return Err(self.new_type_error(format!(
+                    "{}() missing {}: {}",
+                    qualname,
+                    plural(missing.len(), "required keyword-only argument"),
+                    quoted_list(&missing)
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // Generators and coroutines
+
+    /// Resumes a generator, coroutine or async generator with a value or an exception.
+    pub fn resume_generator(&self, obj: &PyObjectRef, resume: Resume) -> PyResult<ExecutionResult> {
+        let Payload::Generator(generator) = &obj.payload else { unreachable!("not a generator") };
+        if generator.running.get() {
+            let what = if generator.kind == GeneratorKind::Generator { "generator" } else { "coroutine" };
+            return Err(self.new_value_error(format!("{} already executing", what)));
+        }
+        let frame = generator.frame.borrow_mut().take();
+        let Some(mut frame) = frame else {
+            return match resume {
+                Resume::Throw(exc) => Err(exc),
+                _ if generator.kind == GeneratorKind::Coroutine => {
+                    Err(self.new_runtime_error("cannot reuse already awaited coroutine".to_string()))
+                }
+                _ => Ok(ExecutionResult::Return(self.none())),
+            };
+        };
+        if !generator.started.get() {
+            if let Resume::Send(value) = &resume {
+                if !self.is_none(value) {
+                    *generator.frame.borrow_mut() = Some(frame);
+                    return Err(self.new_type_error(format!(
+                        "can't send non-None value to a just-started {}",
+                        generator.kind.name().replace('_', " ")
+                    )));
+                }
+            }
+        }
+        let resume = match resume {
+            Resume::Send(_) if !generator.started.get() => Resume::Start,
+            resume => resume,
+        };
+        generator.started.set(true);
+        generator.running.set(true);
+        let depth = self.depth.get();
+        self.depth.set(depth + 1);
+        let result = frame.run(self, resume);
+        self.depth.set(depth);
+        generator.running.set(false);
+        match result {
+            Ok(ExecutionResult::Yield(value)) => {
+                *generator.frame.borrow_mut() = Some(frame);
+                Ok(ExecutionResult::Yield(value))
+            }
+            Ok(ExecutionResult::Await(value)) => {
+                *generator.frame.borrow_mut() = Some(frame);
+                Ok(ExecutionResult::Await(value))
+            }
+            Ok(ExecutionResult::Return(value)) => Ok(ExecutionResult::Return(value)),
+            Err(exc) => {
+                // PEP 479: a StopIteration escaping the body would look like the end.
+                let (leaked, what) = match generator.kind {
+                    GeneratorKind::AsyncGenerator => (&self.exceptions.stop_async_iteration, "StopAsyncIteration"),
+                    _ => (&self.exceptions.stop_iteration, "StopIteration"),
+                };
+                let leaked = self.isinstance(&exc, leaked)
+                    || (generator.kind == GeneratorKind::AsyncGenerator
+                        && self.isinstance(&exc, &self.exceptions.stop_iteration));
+                if leaked {
+                    let what = if self.isinstance(&exc, &self.exceptions.stop_iteration) { "StopIteration" } else { what };
+                    return Err(self.new_runtime_error(format!(
+                        "{} raised {}",
+                        generator.kind.name().replace('_', " "),
+                        what
+                    )));
+                }
+                Err(exc)
+            }
+        }
+    }
+
+    /// `next(generator)`: the next value, or None once it returns.
+    pub fn generator_next(&self, obj: &PyObjectRef) -> PyResult<Option<PyObjectRef>> {
+        match self.resume_generator(obj, Resume::Send(self.none()))? {
+            ExecutionResult::Yield(value) | ExecutionResult::Await(value) => Ok(Some(value)),
+            ExecutionResult::Return(_) => Ok(None),
+        }
+    }
+
+    /// Sends `value` into the iterator a `yield from` or `await` delegates to.
+    pub(crate) fn send(&self, receiver: &PyObjectRef, value: PyObjectRef) -> PyResult<ExecutionResult> {
+        match &receiver.payload {
+            Payload::Generator(_) => {
+                self.resume_generator(receiver, Resume::Send(value))
+            }
+            Payload::AsyncGenAwaitable { .. } => self.async_gen_awaitable_send(receiver, Resume::Send(value)),
+            _ => {
+                let result = if self.is_none(&value) {
+                    match self.next(receiver) {
+                        Ok(Some(item)) => return Ok(ExecutionResult::Yield(item)),
+                        Ok(None) => return Ok(ExecutionResult::Return(self.none())),
+                        Err(exc) => Err(exc),
+                    }
+                } else {
+                    self.call_method(receiver, "send", vec![value])
+                };
+                self.stop_iteration_to_return(result)
+            }
+        }
+    }
+
+    /// Throws `exc` into the iterator a `yield from` or `await` delegates to.
+    pub(crate) fn throw(&self, receiver: &PyObjectRef, exc: PyObjectRef) -> PyResult<ExecutionResult> {
+        match &receiver.payload {
+            Payload::Generator(_) => self.resume_generator(receiver, Resume::Throw(exc)),
+            Payload::AsyncGenAwaitable { .. } => self.async_gen_awaitable_send(receiver, Resume::Throw(exc)),
+            _ => {
+                if self.isinstance(&exc, &self.exceptions.generator_exit) {
+                    if let Some(close) = self.get_attribute_opt(receiver, "close")? {
+                        self.call(&close, FuncArgs::default())?;
+                    }
+                    return Err(exc);
+                }
+                match self.get_attribute_opt(receiver, "throw")? {
+                    Some(throw) => {
+                        let result = self.call(&throw, FuncArgs::new(vec![exc]));
+                        self.stop_iteration_to_return(result)
+                    }
+                    None => Err(exc),
+                }
+            }
+        }
+    }
+
+    fn stop_iteration_to_return(&self, result: PyResult) -> PyResult<ExecutionResult> {
+        match result {
+            Ok(value) => Ok(ExecutionResult::Yield(value)),
+            Err(exc) if self.isinstance(&exc, &self.exceptions.stop_iteration) => {
+                Ok(ExecutionResult::Return(self.stop_iteration_value(&exc)))
+            }
+            Err(exc) => Err(exc),
+        }
+    }
+
+    /// The `value` of a StopIteration: its first argument or None.
+    pub fn stop_iteration_value(&self, exc: &PyObjectRef) -> PyObjectRef {
+        let args = self.exception_args(exc);
+        args.as_tuple().and_then(|args| args.first().cloned()).unwrap_or_else(|| self.none())
+    }
+
+    /// `generator.close()`.
+    pub fn close_generator(&self, obj: &PyObjectRef) -> PyResult {
+        let Payload::Generator(generator) = &obj.payload else { unreachable!("not a generator") };
+        if generator.frame.borrow().is_none() || !generator.started.get() {
+            *generator.frame.borrow_mut() = None;
+            return Ok(self.none());
+        }
+        let exit = self.new_exception(&self.exceptions.generator_exit, vec![]);
+        match self.resume_generator(obj, Resume::Throw(exit)) {
+            Ok(ExecutionResult::Yield(_) | ExecutionResult::Await(_)) => Err(self.new_runtime_error(format!(
+                "{} ignored GeneratorExit",
+                generator.kind.name().replace('_', " ")
+            ))),
+            Ok(ExecutionResult::Return(_)) => Ok(self.none()),
+            Err(exc)
+                if self.isinstance(&exc, &self.exceptions.generator_exit)
+                    || self.isinstance(&exc, &self.exceptions.stop_iteration) =>
+            {
+                Ok(self.none())
+            }
+            Err(exc) => Err(exc),
+        }
+    }
+
+    /// The awaitable `__anext__()` and `asend()` of an async generator return.
+    pub fn new_async_gen_awaitable(&self, generator: &PyObjectRef, value: PyObjectRef) -> PyObjectRef {
+        self.new_object(
+            Payload::AsyncGenAwaitable { generator: generator.clone(), value: RefCell::new(Some(value)) },
+            &self.types.async_generator_asend_type,
+        )
+    }
+
+    /// Drives an async generator one step for an `__anext__()` awaitable: a value the
+    /// generator yields ends the await with StopIteration, while what the generator
+    /// awaits is passed on to whoever awaits the awaitable.
+    pub(crate) fn async_gen_awaitable_send(&self, awaitable: &PyObjectRef, resume: Resume) -> PyResult<ExecutionResult> {
+        let Payload::AsyncGenAwaitable { generator, value } = &awaitable.payload else {
+            unreachable!("not an async generator awaitable")
+        };
+        let resume = match (value.borrow_mut().take(), resume) {
+            (Some(first), Resume::Send(_)) => Resume::Send(first),
+            (_, resume) => resume,
+        };
+        match self.resume_generator(generator, resume)? {
+            ExecutionResult::Yield(item) => Err(self.new_stop_iteration(Some(item))),
+            ExecutionResult::Await(item) => Ok(ExecutionResult::Yield(item)),
+            ExecutionResult::Return(_) => {
+                Err(self.new_exception(&self.exceptions.stop_async_iteration, vec![]))
+            }
+        }
+    }
+
+    /// Registers `send`, `throw`, `close` and the iterator methods of generators,
+    /// coroutines and async generators.
+    pub(crate) fn add_generator_methods(&self) {
+        fn receiver(vm: &VirtualMachine, args: &FuncArgs, name: &str) -> PyResult<PyObjectRef> {
+            match args.args.first() {
+                Some(obj) if matches!(obj.payload, Payload::Generator(_) | Payload::AsyncGenAwaitable { .. }) => {
+                    Ok(obj.clone())
+                }
+                _ => Err(vm.new_type_error(format!("descriptor '{}' requires a generator object", name))),
+            }
+        }
+        fn yielded(vm: &VirtualMachine, result: ExecutionResult) -> PyResult {
+            match result {
+                ExecutionResult::Yield(value) | ExecutionResult::Await(value) => Ok(value),
+                ExecutionResult::Return(value) => {
+                    Err(vm.new_stop_iteration(if vm.is_none(&value) { None } else { Some(value) }))
+                }
+            }
+        }
+        fn resume(vm: &VirtualMachine, obj: &PyObjectRef, resume: Resume) -> PyResult<ExecutionResult> {
+            match &obj.payload {
+                Payload::AsyncGenAwaitable { .. } => vm.async_gen_awaitable_send(obj, resume),
+                _ => vm.resume_generator(obj, resume),
+            }
+        }
+        fn gen_next(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+            let obj = receiver(vm, &args, "__next__")?;
+            let result = resume(vm, &obj, Resume::Send(vm.none()))?;
+            yielded(vm, result)
+        }
+        fn gen_send(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+            let obj = receiver(vm, &args, "send")?;
+            let value = args.args.get(1).cloned().unwrap_or_else(|| vm.none());
+            let result = resume(vm, &obj, Resume::Send(value))?;
+            yielded(vm, result)
+        }
+        fn gen_throw(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+            let obj = receiver(vm, &args, "throw")?;
+            let exc = match (args.args.get(1), args.args.get(2)) {
+                (Some(class), Some(value)) if class.as_type().is_some() && !vm.is_none(value) => {
+                    if vm.isinstance(value, class) {
+                        value.clone()
+                    } else {
+                        vm.call(class, FuncArgs::new(vec![value.clone()]))?
+                    }
+                }
+                (Some(value), _) => vm.make_exception(value)?,
+                (None, _) => return Err(vm.new_type_error("throw expected at least 1 argument, got 0".to_string())),
+            };
+            let result = resume(vm, &obj, Resume::Throw(exc))?;
+            yielded(vm, result)
+        }
+        fn gen_close(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+            let obj = receiver(vm, &args, "close")?;
+            vm.close_generator(&obj)
+        }
+        fn gen_iter(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+            receiver(vm, &args, "__iter__")
+        }
+        fn coroutine_await(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+            receiver(vm, &args, "__await__")
+        }
+        fn async_gen_anext(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+            let obj = receiver(vm, &args, "__anext__")?;
+            Ok(vm.new_async_gen_awaitable(&obj, vm.none()))
+        }
+        fn async_gen_asend(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+            let obj = receiver(vm, &args, "asend")?;
+            let value = args.args.get(1).cloned().unwrap_or_else(|| vm.none());
+            Ok(vm.new_async_gen_awaitable(&obj, value))
+        }
+        let types = &self.types;
+        for (class, iterator) in [(&types.generator_type, true), (&types.coroutine_type, false)] {
+            self.add_method(class, "send", gen_send);
+            self.add_method(class, "throw", gen_throw);
+            self.add_method(class, "close", gen_close);
+            if iterator {
+                self.add_method(class, "__iter__", gen_iter);
+                self.add_method(class, "__next__", gen_next);
+            } else {
+                self.add_method(class, "__await__", coroutine_await);
+            }
+        }
+        self.add_method(&types.async_generator_type, "__aiter__", gen_iter);
+        self.add_method(&types.async_generator_type, "__anext__", async_gen_anext);
+        self.add_method(&types.async_generator_type, "asend", async_gen_asend);
+        let asend = &types.async_generator_asend_type;
+        self.add_method(asend, "send", gen_send);
+        self.add_method(asend, "throw", gen_throw);
+        self.add_method(asend, "__iter__", gen_iter);
+        self.add_method(asend, "__next__", gen_next);
+        self.add_method(asend, "__await__", gen_iter);
+    }
+}
@@ -0,0 +1,232 @@
+//! The import system: modules in `sys.modules` and source files found on `sys.path`.
+
+use std::path::PathBuf;
+
+use super::object::{Payload, PyObjectRef, PyResult};
+use super::VirtualMachine;
+
+impl VirtualMachine {
+    /// `__import__(name, globals, fromlist=fromlist, level=level)`: imports the module and
+    /// its parents, and returns the top package for `import a.b` and the module itself
+    /// when there is a `fromlist`.
+    pub fn import_name(&self, name: &str, globals: &PyObjectRef, fromlist: &PyObjectRef, level: usize) -> PyResult {
+        let absolute = if level > 0 { self.resolve_relative(name, globals, level)? } else { name.to_string() };
+        if absolute.is_empty() {
+            return Err(self.new_value_error("Empty module name".to_string()));
+        }
+        let mut module = self.none();
+        let mut prefix = String::new();
+        for part in absolute.split('.') {
+            let parent = (!prefix.is_empty()).then(|| module.clone());
+            if !prefix.is_empty() {
+                prefix.push('.');
+            }
+            prefix.push_str(part);
+            module = self.import_module(&prefix, parent.as_ref())?;
+        }
+        let has_fromlist = !self.is_none(fromlist) && !self.collect(fromlist)?.is_empty();
+        if has_fromlist {
+            for item in self.collect(fromlist)? {
+                let Some(item) = item.as_str() else { continue };
+                if item != "*" && self.is_package(&module) && self.get_attribute_opt(&module, item)?.is_none() {
+                    let submodule = format!("{}.{}", absolute, item);
+                    match self.import_module(&submodule, Some(&module)) {
+                        Err(exc) if self.isinstance(&exc, &self.exceptions.module_not_found_error) => {}
+                        result => {
+                            result?;
+                        }
+                    }
+                }
+            }
+            return Ok(module);
+        }
+        if level > 0 {
+            return Ok(module);
+        }
+        let top = name.split('.').next().unwrap_or(name);
+        Ok(self.dict_get_str(&self.sys_modules, top).unwrap_or(module))
+    }
+
+    /// The absolute name of a relative import from the module whose globals are `globals`.
+    fn resolve_relative(&self, name: &str, globals: &PyObjectRef, level: usize) -> PyResult<String> {
+        let package = match self.dict_get_str(globals, "__package__").filter(|p| !self.is_none(p)) {
+            Some(package) => self.expect_str(&package, "package")?,
+            None => {
+                let module = self.dict_get_str(globals, "__name__").and_then(|n| n.as_str().map(str::to_string));
+                let module = module.unwrap_or_default();
+                if self.dict_get_str(globals, "__path__").is_some() {
+                    module
+                } else {
+                    module.rsplit_once('.').map(|(parent, _)| parent.to_string()).unwrap_or_default()
+                }
+            }
+        };
+        if package.is_empty() {
+            return Err(self.new_exception_msg(
+                &self.exceptions.import_error,
+                "attempted relative import with no known parent package".to_string(),
+            ));
+        }
+        let parts: Vec<&str> = package.split('.').collect();
+        if level > parts.len() {
+            return Err(self.new_exception_msg(
+                &self.exceptions.import_error,
+                "attempted relative import beyond top-level package".to_string(),
+            ));
+        }
+        let base = parts[..parts.len() - (level - 1)].join(".");
+        Ok(if name.is_empty() { base } else { format!("{}.{}", base, name) })
+    }
+
+    fn is_package(&self, module: &PyObjectRef) -> bool {
+        module.dict.as_ref().is_some_and(|dict| self.dict_get_str(dict, "__path__").is_some())
+    }
+
+    /// The module `name` from `sys.modules`, or loaded from its source and added there and
+    /// to its parent package.
+    fn import_module(&self, name: &str, parent: Option<&PyObjectRef>) -> PyResult {
+        if let Some(module) = self.dict_get_str(&self.sys_modules, name) {
+            if self.is_none(&module) {
+                return Err(self.new_exception_msg(
+                    &self.exceptions.module_not_found_error,
+                    format!("import of {} halted; None in sys.modules", name),
+                ));
+            }
+            return Ok(module);
+        }
+        let search = match parent {
+            Some(parent) => {
+                let path = parent.dict.as_ref().and_then(|dict| self.dict_get_str(dict, "__path__"));
+                match path {
+                    Some(path) => self.collect(&path)?,
+                    None => {
+                        return Err(self.new_exception_msg(
+                            &self.exceptions.module_not_found_error,
+                            format!(
+                                "No module named '{}'; '{}' is not a package",
+                                name,
+                                name.rsplit_once('.').map(|(p, _)| p).unwrap_or_default()
+                            ),
+                        ))
+                    }
+                }
+            }
+            None => self.collect(&self.sys_attribute("path")?)?,
+        };
+        let last = name.rsplit('.').next().unwrap_or(name);
+        for directory in search {
+            let Some(directory) = directory.as_str() else { continue };
+            let base = PathBuf::from(if directory.is_empty() { "." } else { directory });
+            let package_init = base.join(last).join("__init__.py");
+            if package_init.is_file() {
+                let package_dir = base.join(last).display().to_string();
+                return self.load_source_module(name, &package_init, Some(package_dir), parent);
+            }
+            let file = base.join(format!("{}.py", last));
+            if file.is_file() {
+                return self.load_source_module(name, &file, None, parent);
+            }
+        }
+        Err(self.new_exception_msg(&self.exceptions.module_not_found_error, format!("No module named '{}'", name)))
+    }
+
+    /// Runs the source file `path` as the module `name`.
+    fn load_source_module(
+        &self,
+        name: &str,
+        path: &std::path::Path,
+        package_dir: Option<String>,
+        parent: Option<&PyObjectRef>,
+    ) -> PyResult {
+        let source = match std::fs::read(path) {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            Err(err) => {
+                return Err(self.new_exception_msg(&self.exceptions.import_error, format!("{}: {}", path.display(), err)))
+            }
+        };
+        let filename = path.display().to_string();
+        let module = self.new_module(name);
+        let globals = module.dict.clone().expect("module dict");
+        self.dict_set_str(&globals, "__file__", self.new_str(filename.clone()));
+        self.dict_set_str(&globals, "__builtins__", self.builtins.clone());
+        let package = match &package_dir {
+            Some(directory) => {
+                self.dict_set_str(&globals, "__path__", self.new_list(vec![self.new_str(directory.clone())]));
+                name.to_string()
+            }
+            None => name.rsplit_once('.').map(|(parent, _)| parent.to_string()).unwrap_or_default(),
+        };
+        self.dict_set_str(&globals, "__package__", self.new_str(package));
+        self.dict_set_str(&self.sys_modules, name, module.clone());
+        let result = self.compile_source(&source, &filename).and_then(|code| self.run_code(code, &globals));
+        if let Err(exc) = result {
+            self.dict_del_str(&self.sys_modules, name);
+            return Err(exc);
+        }
+        // The module may have replaced itself in `sys.modules`.
+        let module = self.dict_get_str(&self.sys_modules, name).unwrap_or(module);
+        if let Some(parent) = parent {
+            let last = name.rsplit('.').next().unwrap_or(name);
+            self.set_attribute(parent, last, module.clone())?;
+        }
+        Ok(module)
+    }
+
+    /// `from module import name`.
+    pub fn import_from(&self, module: &PyObjectRef, name: &str) -> PyResult {
+        if let Some(value) = self.get_attribute_opt(module, name)? {
+            return Ok(value);
+        }
+        let module_name = self.get_attribute_opt(module, "__name__")?.and_then(|n| n.as_str().map(str::to_string));
+        if let Some(module_name) = &module_name {
+            if let Some(submodule) = self.dict_get_str(&self.sys_modules, &format!("{}.{}", module_name, name)) {
+                return Ok(submodule);
+            }
+        }
+        let module_name = module_name.unwrap_or_else(|| "<unknown module name>".to_string());
+        let location = match self.get_attribute_opt(module, "__file__")?.and_then(|f| f.as_str().map(str::to_string)) {
+            Some(file) => file,
+            None => "unknown location".to_string(),
+        };
+        Err(self.new_exception_msg(
+            &self.exceptions.import_error,
+            format!("cannot import name '{}' from '{}' ({})", name, module_name, location),
+        ))
+    }
+
+    /// `from module import *` into the namespace `locals`.
+    pub fn import_star(&self, module: &PyObjectRef, locals: &PyObjectRef) -> PyResult<()> {
+        let Some(dict) = &module.dict else {
+            return Ok(());
+        };
+        let names = match self.get_attribute_opt(module, "__all__")? {
+            Some(all) => self.collect(&all)?,
+            None => {
+                let keys = match &dict.payload {
+                    Payload::Dict(table) => table.borrow().keys(),
+                    _ => vec![],
+                };
+                keys.into_iter().filter(|key| key.as_str().is_some_and(|key| !key.starts_with('_'))).collect()
+            }
+        };
+        for name in names {
+            let Some(text) = name.as_str() else {
+                return Err(self.new_type_error(format!(
+                    "Item in {}.__all__ must be str, not {}",
+                    self.module_name(module),
+                    self.class_name(&name)
+                )));
+            };
+            let value = self.get_attribute(module, text)?;
+            self.set_item(locals, &name, &value)?;
+        }
+        Ok(())
+    }
+
+    fn module_name(&self, module: &PyObjectRef) -> String {
+        match &module.payload {
+            Payload::Module { name } => name.clone(),
+            _ => String::new(),
+        }
+    }
+}
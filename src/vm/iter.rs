@@ -0,0 +1,273 @@
+//! The iteration protocol and the builtin iterator objects.
+
+use std::cell::RefCell;
+
+use crate::bigint::BigInt;
+
+use super::dict::PyDict;
+use super::object::{DictViewKind, FuncArgs, IteratorState, Payload, PyObjectRef, PyResult};
+use super::VirtualMachine;
+
+impl VirtualMachine {
+    pub fn new_iterator(&self, state: IteratorState) -> PyObjectRef {
+        self.new_object(Payload::Iterator(RefCell::new(state)), &self.types.iterator_type)
+    }
+
+    /// `iter(obj)`.
+    pub fn get_iter(&self, obj: &PyObjectRef) -> PyResult {
+        if let Some(iter) = self.call_special(obj, "__iter__", vec![])? {
+            if !self.is_iterator(&iter) {
+                return Err(self.new_type_error(format!(
+                    "iter() returned non-iterator of type '{}'",
+                    self.class_name(&iter)
+                )));
+            }
+            return Ok(iter);
+        }
+        self.native_iter(obj)
+    }
+
+    /// An iterator over a builtin iterable.
+    pub(super) fn native_iter(&self, obj: &PyObjectRef) -> PyResult {
+        let state = match &obj.payload {
+            Payload::Iterator(_) => return Ok(obj.clone()),
+            Payload::Generator(generator) if generator.kind == super::object::GeneratorKind::Generator => {
+                return Ok(obj.clone())
+            }
+            Payload::List(_) | Payload::Tuple(_) | Payload::Str(_) | Payload::Bytes(_) => {
+                IteratorState::Sequence { seq: obj.clone(), index: 0 }
+            }
+            Payload::Range { start, stop, step } => {
+                IteratorState::Range { next: start.clone(), stop: stop.clone(), step: step.clone() }
+            }
+            Payload::Dict(table) | Payload::Set(table) => {
+                IteratorState::Dict { dict: obj.clone(), index: 0, len: table.borrow().len(), kind: DictViewKind::Keys, reversed: false }
+            }
+            Payload::FrozenSet(table) => {
+                IteratorState::Dict { dict: obj.clone(), index: 0, len: table.len(), kind: DictViewKind::Keys, reversed: false }
+            }
+            Payload::DictView { dict, kind } => {
+                let len = dict.as_dict().expect("dict").borrow().len();
+                IteratorState::Dict { dict: dict.clone(), index: 0, len, kind: *kind, reversed: false }
+            }
+            _ if self.special_method(obj, "__getitem__").is_some() => {
+                IteratorState::Sequence { seq: obj.clone(), index: 0 }
+            }
+            _ => return Err(self.new_type_error(format!("'{}' object is not iterable", self.class_name(obj)))),
+        };
+        Ok(self.new_iterator(state))
+    }
+
+    fn is_iterator(&self, obj: &PyObjectRef) -> bool {
+        matches!(obj.payload, Payload::Iterator(_) | Payload::Generator(_)) || self.has_special(obj, "__next__")
+    }
+
+    /// `next(iter)`, with None once the iterator is exhausted.
+    pub fn next(&self, iter: &PyObjectRef) -> PyResult<Option<PyObjectRef>> {
+        if let Some(method) = self.special_method(iter, "__next__") {
+            return match self.call_bound(&method, iter, vec![]) {
+                Ok(value) => Ok(Some(value)),
+                Err(exc) if self.isinstance(&exc, &self.exceptions.stop_iteration) => Ok(None),
+                Err(exc) => Err(exc),
+            };
+        }
+        self.native_next(iter)
+    }
+
+    /// The next item of a builtin iterator or generator.
+    pub(super) fn native_next(&self, iter: &PyObjectRef) -> PyResult<Option<PyObjectRef>> {
+        match &iter.payload {
+            Payload::Iterator(state) => self.iterator_next(state),
+            Payload::Generator(_) => self.generator_next(iter),
+            _ => Err(self.new_type_error(format!("'{}' object is not an iterator", self.class_name(iter)))),
+        }
+    }
+
+    fn iterator_next(&self, state: &RefCell<IteratorState>) -> PyResult<Option<PyObjectRef>> {
+        // The state is borrowed only to read and advance it, never across Python code,
+        // which could use the same iterator.
+        let step = match &mut *state.borrow_mut() {
+            IteratorState::Exhausted => return Ok(None),
+            IteratorState::Sequence { seq, index } => {
+                let item = match &seq.payload {
+                    _ if self.special_method(seq, "__getitem__").is_some() => None,
+                    Payload::List(items) => Some(items.borrow().get(*index).cloned()),
+                    Payload::Tuple(items) => Some(items.get(*index).cloned()),
+                    Payload::Str(s) => Some((*index < s.char_len()).then(|| self.new_str(s.char_at(*index).to_string()))),
+                    Payload::Bytes(b) => Some(b.get(*index).map(|b| self.new_int(*b as i64))),
+                    _ => None,
+                };
+                *index += 1;
+                match item {
+                    Some(item) => Step::Done(item),
+                    None => Step::GetItem(seq.clone(), *index - 1),
+                }
+            }
+            IteratorState::Range { next, stop, step } => {
+                let more = if step.is_negative() { *next > *stop } else { *next < *stop };
+                if !more {
+                    Step::Done(None)
+                } else {
+                    let value = next.clone();
+                    let (current, delta) = (value.to_i64(), step.to_i64());
+                    *next = match (current, delta) {
+                        (Some(current), Some(delta)) => match current.checked_add(delta) {
+                            Some(following) => BigInt::from(following),
+                            None => stop.clone(),
+                        },
+                        _ => stop.clone(),
+                    };
+                    Step::Done(Some(self.new_bigint(value)))
+                }
+            }
+            IteratorState::Dict { dict, index, len, kind, reversed } => {
+                let next = |table: &PyDict| {
+                    let entry = if *reversed { table.prev_entry(*index) } else { table.next_entry(*index) };
+                    let entry = entry.map(|(pos, entry)| (pos, entry.clone()));
+                    (table.len(), entry)
+                };
+                let (current_len, entry) = match &dict.payload {
+                    Payload::Dict(table) | Payload::Set(table) => next(&table.borrow()),
+                    Payload::FrozenSet(table) => next(table),
+                    _ => unreachable!("not a hash table"),
+                };
+                if current_len != *len {
+                    *len = usize::MAX;
+                    let what = if matches!(dict.payload, Payload::Dict(_)) { "dictionary" } else { "Set" };
+                    return Err(self.new_runtime_error(format!("{} changed size during iteration", what)));
+                }
+                match entry {
+                    Some((pos, entry)) => {
+                        *index = if *reversed { pos } else { pos + 1 };
+                        Step::Done(Some(match kind {
+                            DictViewKind::Keys => entry.key,
+                            DictViewKind::Values => entry.value,
+                            DictViewKind::Items => self.new_tuple(vec![entry.key, entry.value]),
+                        }))
+                    }
+                    None => Step::Done(None),
+                }
+            }
+            IteratorState::Enumerate { iter, count } => Step::Enumerate(iter.clone(), count.clone()),
+            IteratorState::Zip { iters } => Step::Zip(iters.clone()),
+            IteratorState::Map { function, iters } => Step::Map(function.clone(), iters.clone()),
+            IteratorState::Filter { function, iter } => Step::Filter(function.clone(), iter.clone()),
+            IteratorState::Reversed { seq, index } => {
+                if *index == 0 {
+                    Step::Done(None)
+                } else {
+                    *index -= 1;
+                    Step::GetItem(seq.clone(), *index)
+                }
+            }
+            IteratorState::Callable { function, sentinel } => Step::Callable(function.clone(), sentinel.clone()),
+        };
+        let result = match step {
+            Step::Done(item) => item,
+            Step::GetItem(seq, index) => {
+                match self.get_item(&seq, &self.new_int(index as i64)) {
+                    Ok(item) => Some(item),
+                    Err(exc)
+                        if self.isinstance(&exc, &self.exceptions.index_error)
+                            || self.isinstance(&exc, &self.exceptions.stop_iteration) =>
+                    {
+                        None
+                    }
+                    Err(exc) => return Err(exc),
+                }
+            }
+            Step::Enumerate(iter, count) => match self.next(&iter)? {
+                Some(item) => {
+                    if let IteratorState::Enumerate { count: stored, .. } = &mut *state.borrow_mut() {
+                        *stored = match count.to_i64().and_then(|c| c.checked_add(1)) {
+                            Some(following) => BigInt::from(following),
+                            None => count.clone(),
+                        };
+                    }
+                    Some(self.new_tuple(vec![self.new_bigint(count), item]))
+                }
+                None => None,
+            },
+            Step::Zip(iters) => {
+                let mut items = Vec::with_capacity(iters.len());
+                for iter in &iters {
+                    match self.next(iter)? {
+                        Some(item) => items.push(item),
+                        None => break,
+                    }
+                }
+                if items.len() == iters.len() && !iters.is_empty() {
+                    Some(self.new_tuple(items))
+                } else {
+                    None
+                }
+            }
+            Step::Map(function, iters) => {
+                let mut args = Vec::with_capacity(iters.len());
+                for iter in &iters {
+                    match self.next(iter)? {
+                        Some(item) => args.push(item),
+                        None => break,
+                    }
+                }
+                if args.len() == iters.len() {
+                    Some(self.call(&function, FuncArgs::new(args))?)
+                } else {
+                    None
+                }
+            }
+            Step::Filter(function, iter) => loop {
+                let Some(item) = self.next(&iter)? else { break None };
+                let keep = if self.is_none(&function) {
+                    self.is_true(&item)?
+                } else {
+                    let result = self.call(&function, FuncArgs::new(vec![item.clone()]))?;
+                    self.is_true(&result)?
+                };
+                if keep {
+                    break Some(item);
+                }
+            },
+            Step::Callable(function, sentinel) => {
+                let value = self.call(&function, FuncArgs::default())?;
+                if self.eq(&value, &sentinel)? {
+                    None
+                } else {
+                    Some(value)
+                }
+            }
+        };
+        if result.is_none() {
+            *state.borrow_mut() = IteratorState::Exhausted;
+        }
+        Ok(result)
+    }
+
+    /// All items of an iterable.
+    pub fn collect(&self, iterable: &PyObjectRef) -> PyResult<Vec<PyObjectRef>> {
+        if self.special_method(iterable, "__iter__").is_none() {
+            match &iterable.payload {
+                Payload::Tuple(items) => return Ok(items.clone()),
+                Payload::List(items) => return Ok(items.borrow().clone()),
+                _ => {}
+            }
+        }
+        let iter = self.get_iter(iterable)?;
+        let mut items = vec![];
+        while let Some(item) = self.next(&iter)? {
+            items.push(item);
+        }
+        Ok(items)
+    }
+}
+
+/// What advancing an iterator needs once its state is no longer borrowed.
+enum Step {
+    Done(Option<PyObjectRef>),
+    GetItem(PyObjectRef, usize),
+    Enumerate(PyObjectRef, BigInt),
+    Zip(Vec<PyObjectRef>),
+    Map(PyObjectRef, Vec<PyObjectRef>),
+    Filter(PyObjectRef, PyObjectRef),
+    Callable(PyObjectRef, PyObjectRef),
+}
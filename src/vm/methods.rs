@@ -0,0 +1,1439 @@
+//! Methods of the builtin types, kept in their type dicts.
+//!
+//! Operators on builtin objects work on the payloads directly (see `ops`).  These are the
+//! methods called by name, like `list.append` and `dict.get`, the `object` methods that
+//! explicit calls such as `object.__setattr__(self, name, value)` reach, and the special
+//! methods of the builtin types, which give the same operators by name.
+
+use std::cell::RefCell;
+
+use crate::bigint::BigInt;
+use crate::compiler::bytecode::{BinaryOperator, ComparisonOperator, UnaryOperator};
+
+use super::builtins::check_args;
+use super::dict::PyDict;
+use super::object::{DictViewKind, FuncArgs, NativeFunction, Payload, PyObjectRef, PyProperty, PyResult};
+use super::ops::{identity_hash, normalize_index};
+use super::VirtualMachine;
+
+/// Splits the receiver off the arguments of the method `type_name.name`, checking that
+/// `accepts` its payload.
+pub(super) fn receiver(
+    vm: &VirtualMachine,
+    mut args: FuncArgs,
+    type_name: &str,
+    name: &str,
+    accepts: fn(&Payload) -> bool,
+) -> PyResult<(PyObjectRef, FuncArgs)> {
+    if args.args.is_empty() {
+        return Err(vm.new_type_error(format!("unbound method {}.{}() needs an argument", type_name, name)));
+    }
+    let obj = args.args.remove(0);
+    if !accepts(&obj.payload) {
+        return Err(vm.new_type_error(format!(
+            "descriptor '{}' for '{}' objects doesn't apply to a '{}' object",
+            name,
+            type_name,
+            vm.class_name(&obj)
+        )));
+    }
+    Ok((obj, args))
+}
+
+fn list_items(list: &PyObjectRef) -> &RefCell<Vec<PyObjectRef>> {
+    match &list.payload {
+        Payload::List(items) => items,
+        _ => unreachable!("not a list"),
+    }
+}
+
+fn is_list(payload: &Payload) -> bool {
+    matches!(payload, Payload::List(_))
+}
+
+fn is_tuple(payload: &Payload) -> bool {
+    matches!(payload, Payload::Tuple(_))
+}
+
+fn is_dict(payload: &Payload) -> bool {
+    matches!(payload, Payload::Dict(_))
+}
+
+fn is_set(payload: &Payload) -> bool {
+    matches!(payload, Payload::Set(_))
+}
+
+fn is_any_set(payload: &Payload) -> bool {
+    matches!(payload, Payload::Set(_) | Payload::FrozenSet(_))
+}
+
+/// The bounds of `seq.index(x, start, stop)`, clamped to the sequence.
+fn index_bounds(vm: &VirtualMachine, args: &[PyObjectRef], len: usize) -> PyResult<(usize, usize)> {
+    let bound = |position: usize, default: usize| -> PyResult<usize> {
+        let Some(value) = args.get(position) else { return Ok(default) };
+        let value = vm.to_usize_index(value)?;
+        let value = if value < 0 { (value + len as i64).max(0) } else { value };
+        Ok((value as usize).min(len))
+    };
+    Ok((bound(1, 0)?, bound(2, len)?))
+}
+
+// object
+
+fn object_init(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = receiver(vm, args, "object", "__init__", |_| true)?;
+    if !args.args.is_empty() || !args.kwargs.is_empty() {
+        let class = obj.class();
+        if vm.lookup_user_method(&class, "__init__").is_some() {
+            return Err(vm.new_type_error(
+                "object.__init__() takes exactly one argument (the instance to initialize)".to_string(),
+            ));
+        }
+        if vm.lookup_user_method(&class, "__new__").is_none() {
+            return Err(vm.new_type_error(format!(
+                "{}.__init__() takes exactly one argument (the instance to initialize)",
+                class.as_type().expect("type").name()
+            )));
+        }
+    }
+    Ok(vm.none())
+}
+
+/// `object.__init_subclass__`, the end of the chain of `super().__init_subclass__()` calls,
+/// which no class keyword may reach.
+fn object_init_subclass(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (class, args) = receiver(vm, args, "object", "__init_subclass__", |payload| matches!(payload, Payload::Type(_)))?;
+    if !args.kwargs.is_empty() {
+        return Err(vm.new_type_error(format!(
+            "{}.__init_subclass__() takes no keyword arguments",
+            class.as_type().expect("type").name()
+        )));
+    }
+    if !args.args.is_empty() {
+        return Err(vm.new_type_error(format!(
+            "object.__init_subclass__() takes no arguments ({} given)",
+            args.args.len()
+        )));
+    }
+    Ok(vm.none())
+}
+
+/// `object.__new__(cls, ...)`, which creates the instance with the native constructor of
+/// the builtin type `cls` derives from.
+fn object_new(vm: &VirtualMachine, mut args: FuncArgs) -> PyResult {
+    if args.args.is_empty() {
+        return Err(vm.new_type_error("object.__new__(): not enough arguments".to_string()));
+    }
+    let class = args.args[0].clone();
+    let Some(ty) = class.as_type() else {
+        return Err(vm.new_type_error(format!(
+            "object.__new__(X): X is not a type object ({})",
+            vm.class_name(&class)
+        )));
+    };
+    let base = vm.solid_base(&class);
+    let native = base.as_type().and_then(|t| t.new).expect("builtin types have a __new__");
+    if base.is(&vm.types.object_type) {
+        let excess = args.args.len() > 1 || !args.kwargs.is_empty();
+        if excess && vm.lookup_user_method(&class, "__new__").is_some() {
+            return Err(vm.new_type_error(
+                "object.__new__() takes exactly one argument (the type to instantiate)".to_string(),
+            ));
+        }
+        if excess && vm.lookup_user_method(&class, "__init__").is_none() {
+            return Err(vm.new_type_error(format!("{}() takes no arguments", ty.name())));
+        }
+        args.args.truncate(1);
+        args.kwargs.clear();
+    }
+    native(vm, args)
+}
+
+/// `type.__init__`, which only checks the arguments: `type.__new__` did the work.
+fn type_init(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (_, args) = receiver(vm, args, "type", "__init__", |payload| matches!(payload, Payload::Type(_)))?;
+    match args.args.len() {
+        1 if !args.kwargs.is_empty() => Err(vm.new_type_error("type.__init__() takes no keyword arguments".to_string())),
+        1 | 3 => Ok(vm.none()),
+        _ => Err(vm.new_type_error("type.__init__() takes 1 or 3 arguments".to_string())),
+    }
+}
+
+/// `type.__call__`, which a metaclass's own `__call__` reaches through `super()`.
+fn type_call(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (class, args) = receiver(vm, args, "type", "__call__", |payload| matches!(payload, Payload::Type(_)))?;
+    vm.call_type(&class, args)
+}
+
+fn object_repr(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = receiver(vm, args, "object", "__repr__", |_| true)?;
+    check_args(vm, "object.__repr__", &args, 0, 0)?;
+    Ok(vm.new_str(vm.object_repr(&obj)))
+}
+
+fn object_str(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = receiver(vm, args, "object", "__str__", |_| true)?;
+    check_args(vm, "object.__str__", &args, 0, 0)?;
+    Ok(vm.new_str(vm.repr(&obj)?))
+}
+
+fn object_eq(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = receiver(vm, args, "object", "__eq__", |_| true)?;
+    check_args(vm, "__eq__", &args, 1, 1)?;
+    Ok(if obj.is(&args.args[0]) { vm.new_bool(true) } else { vm.not_implemented.clone() })
+}
+
+fn object_ne(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = receiver(vm, args, "object", "__ne__", |_| true)?;
+    check_args(vm, "__ne__", &args, 1, 1)?;
+    let other = &args.args[0];
+    let equal = match vm.special_method(&obj, "__eq__") {
+        Some(eq) => vm.call_bound(&eq, &obj, vec![other.clone()])?,
+        None if obj.is(other) => vm.new_bool(true),
+        None => vm.not_implemented.clone(),
+    };
+    if equal.is(&vm.not_implemented) {
+        return Ok(equal);
+    }
+    Ok(vm.new_bool(!vm.is_true(&equal)?))
+}
+
+fn object_hash(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = receiver(vm, args, "object", "__hash__", |_| true)?;
+    check_args(vm, "object.__hash__", &args, 0, 0)?;
+    Ok(vm.new_int(identity_hash(&obj)))
+}
+
+fn attribute_name(vm: &VirtualMachine, name: &PyObjectRef) -> PyResult<String> {
+    match name.as_str() {
+        Some(name) => Ok(name.to_string()),
+        None => Err(vm.new_type_error(format!(
+            "attribute name must be string, not '{}'",
+            vm.class_name(name)
+        ))),
+    }
+}
+
+fn object_getattribute(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = receiver(vm, args, "object", "__getattribute__", |_| true)?;
+    check_args(vm, "__getattribute__", &args, 1, 1)?;
+    vm.generic_getattr(&obj, &attribute_name(vm, &args.args[0])?)
+}
+
+fn object_setattr(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = receiver(vm, args, "object", "__setattr__", |_| true)?;
+    check_args(vm, "__setattr__", &args, 2, 2)?;
+    vm.generic_setattr(&obj, &attribute_name(vm, &args.args[0])?, Some(args.args[1].clone()))?;
+    Ok(vm.none())
+}
+
+fn object_delattr(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = receiver(vm, args, "object", "__delattr__", |_| true)?;
+    check_args(vm, "__delattr__", &args, 1, 1)?;
+    vm.generic_setattr(&obj, &attribute_name(vm, &args.args[0])?, None)?;
+    Ok(vm.none())
+}
+
+fn object_format(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = receiver(vm, args, "object", "__format__", |_| true)?;
+    check_args(vm, "object.__format__", &args, 1, 1)?;
+    let spec = vm.expect_str(&args.args[0], "__format__() argument")?;
+    if !spec.is_empty() {
+        return Err(vm.new_type_error(format!(
+            "unsupported format string passed to {}.__format__",
+            vm.class_name(&obj)
+        )));
+    }
+    Ok(vm.new_str(vm.to_str(&obj)?))
+}
+
+fn type_mro(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (class, args) = receiver(vm, args, "type", "mro", |payload| matches!(payload, Payload::Type(_)))?;
+    check_args(vm, "type.mro", &args, 0, 0)?;
+    let mro = class.as_type().expect("type").mro.borrow().clone();
+    Ok(vm.new_list(mro))
+}
+
+// int
+
+fn is_int(payload: &Payload) -> bool {
+    matches!(payload, Payload::Int(_) | Payload::Bool(_))
+}
+
+fn int_bit_length(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (int, args) = receiver(vm, args, "int", "bit_length", is_int)?;
+    check_args(vm, "int.bit_length", &args, 0, 0)?;
+    Ok(vm.new_int(int.as_int().expect("int").bit_length() as i64))
+}
+
+fn int_bit_count(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (int, args) = receiver(vm, args, "int", "bit_count", is_int)?;
+    check_args(vm, "int.bit_count", &args, 0, 0)?;
+    Ok(vm.new_int(int.as_int().expect("int").bit_count() as i64))
+}
+
+/// The `byteorder` and `signed` arguments of `to_bytes` and `from_bytes`, after the
+/// positional argument at `position`.
+fn byte_order(vm: &VirtualMachine, args: &mut FuncArgs, position: usize) -> PyResult<(bool, bool)> {
+    let signed = match args.take_kwarg("signed") {
+        Some(signed) => vm.is_true(&signed)?,
+        None => false,
+    };
+    let order = args.take_kwarg("byteorder").or_else(|| args.args.get(position).cloned());
+    let little = match order.as_ref().map(|order| order.as_str()) {
+        None | Some(Some("big")) => false,
+        Some(Some("little")) => true,
+        Some(Some(_)) => return Err(vm.new_value_error("byteorder must be either 'little' or 'big'".to_string())),
+        Some(None) => {
+            let order = order.expect("byteorder");
+            return Err(vm.new_type_error(format!(
+                "to_bytes() argument 'byteorder' must be str, not {}",
+                vm.class_name(&order)
+            )));
+        }
+    };
+    Ok((little, signed))
+}
+
+/// `int.to_bytes(length=1, byteorder='big', *, signed=False)`.
+fn int_to_bytes(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (int, mut args) = receiver(vm, args, "int", "to_bytes", is_int)?;
+    let length = args.take_kwarg("length").or_else(|| args.args.first().cloned());
+    let (little, signed) = byte_order(vm, &mut args, 1)?;
+    check_args(vm, "to_bytes", &args, 0, 2)?;
+    let length = match length {
+        Some(length) => vm.to_usize_index(&length)?,
+        None => 1,
+    };
+    if length < 0 {
+        return Err(vm.new_value_error("length argument must be non-negative".to_string()));
+    }
+    let value = int.as_int().expect("int");
+    match value.to_bytes(length as usize, little, signed) {
+        Some(bytes) => Ok(vm.new_bytes(bytes)),
+        None if value.is_negative() && !signed => {
+            Err(vm.new_overflow_error("can't convert negative int to unsigned".to_string()))
+        }
+        None => Err(vm.new_overflow_error("int too big to convert".to_string())),
+    }
+}
+
+/// `int.from_bytes(bytes, byteorder='big', *, signed=False)`, a class method.
+fn int_from_bytes(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (class, mut args) = receiver(vm, args, "int", "from_bytes", |payload| matches!(payload, Payload::Type(_)))?;
+    let source = args.take_kwarg("bytes").or_else(|| args.args.first().cloned());
+    let (little, signed) = byte_order(vm, &mut args, 1)?;
+    check_args(vm, "from_bytes", &args, 0, 2)?;
+    let Some(source) = source else {
+        return Err(vm.new_type_error("from_bytes() missing required argument 'bytes' (pos 1)".to_string()));
+    };
+    let bytes = match &source.payload {
+        Payload::Bytes(bytes) => bytes.clone(),
+        _ => {
+            let mut bytes = vec![];
+            for item in vm.collect(&source)? {
+                match vm.to_index(&item)?.to_i64().and_then(|byte| u8::try_from(byte).ok()) {
+                    Some(byte) => bytes.push(byte),
+                    None => return Err(vm.new_value_error("bytes must be in range(0, 256)".to_string())),
+                }
+            }
+            bytes
+        }
+    };
+    let value = vm.new_bigint(BigInt::from_bytes(&bytes, little, signed));
+    if class.is(&vm.types.int_type) {
+        return Ok(value);
+    }
+    vm.call(&class, FuncArgs::new(vec![value]))
+}
+
+// list
+
+fn list_init(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (list, args) = receiver(vm, args, "list", "__init__", is_list)?;
+    check_args(vm, "list", &args, 0, 1)?;
+    let items = match args.args.first() {
+        Some(iterable) => vm.collect(iterable)?,
+        None => vec![],
+    };
+    *list_items(&list).borrow_mut() = items;
+    Ok(vm.none())
+}
+
+fn list_append(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (list, args) = receiver(vm, args, "list", "append", is_list)?;
+    check_args(vm, "list.append", &args, 1, 1)?;
+    list_items(&list).borrow_mut().push(args.args[0].clone());
+    Ok(vm.none())
+}
+
+fn list_extend(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (list, args) = receiver(vm, args, "list", "extend", is_list)?;
+    check_args(vm, "list.extend", &args, 1, 1)?;
+    let items = vm.collect(&args.args[0])?;
+    list_items(&list).borrow_mut().extend(items);
+    Ok(vm.none())
+}
+
+fn list_insert(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (list, args) = receiver(vm, args, "list", "insert", is_list)?;
+    check_args(vm, "insert", &args, 2, 2)?;
+    let index = vm.to_usize_index(&args.args[0])?;
+    let mut items = list_items(&list).borrow_mut();
+    let len = items.len() as i64;
+    let index = if index < 0 { (index + len).max(0) } else { index.min(len) };
+    items.insert(index as usize, args.args[1].clone());
+    Ok(vm.none())
+}
+
+fn list_pop(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (list, args) = receiver(vm, args, "list", "pop", is_list)?;
+    check_args(vm, "pop", &args, 0, 1)?;
+    let index = match args.args.first() {
+        Some(index) => vm.to_index(index)?,
+        None => (-1).into(),
+    };
+    let mut items = list_items(&list).borrow_mut();
+    if items.is_empty() {
+        return Err(vm.new_index_error("pop from empty list".to_string()));
+    }
+    match normalize_index(&index, items.len()) {
+        Some(index) => Ok(items.remove(index)),
+        None => Err(vm.new_index_error("pop index out of range".to_string())),
+    }
+}
+
+/// The position of the first item of `list` equal to `value`, comparing a copy since
+/// `__eq__` may change the list.
+fn list_find(vm: &VirtualMachine, list: &PyObjectRef, value: &PyObjectRef, start: usize, stop: usize) -> PyResult<Option<usize>> {
+    let items = list_items(list).borrow().clone();
+    for (index, item) in items.iter().enumerate().take(stop).skip(start) {
+        if vm.same_or_eq(item, value)? {
+            return Ok(Some(index));
+        }
+    }
+    Ok(None)
+}
+
+fn list_remove(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (list, args) = receiver(vm, args, "list", "remove", is_list)?;
+    check_args(vm, "list.remove", &args, 1, 1)?;
+    let len = list_items(&list).borrow().len();
+    let Some(index) = list_find(vm, &list, &args.args[0], 0, len)? else {
+        return Err(vm.new_value_error("list.remove(x): x not in list".to_string()));
+    };
+    let mut items = list_items(&list).borrow_mut();
+    if index < items.len() {
+        items.remove(index);
+    }
+    Ok(vm.none())
+}
+
+fn list_index(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (list, args) = receiver(vm, args, "list", "index", is_list)?;
+    check_args(vm, "index", &args, 1, 3)?;
+    let (start, stop) = index_bounds(vm, &args.args, list_items(&list).borrow().len())?;
+    match list_find(vm, &list, &args.args[0], start, stop)? {
+        Some(index) => Ok(vm.new_int(index as i64)),
+        None => Err(vm.new_value_error(format!("{} is not in list", vm.repr(&args.args[0])?))),
+    }
+}
+
+fn count_equal(vm: &VirtualMachine, items: &[PyObjectRef], value: &PyObjectRef) -> PyResult {
+    let mut count = 0;
+    for item in items {
+        if vm.same_or_eq(item, value)? {
+            count += 1;
+        }
+    }
+    Ok(vm.new_int(count))
+}
+
+fn list_count(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (list, args) = receiver(vm, args, "list", "count", is_list)?;
+    check_args(vm, "list.count", &args, 1, 1)?;
+    let items = list_items(&list).borrow().clone();
+    count_equal(vm, &items, &args.args[0])
+}
+
+fn list_clear(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (list, args) = receiver(vm, args, "list", "clear", is_list)?;
+    check_args(vm, "list.clear", &args, 0, 0)?;
+    // Dropping the items can run code that looks at the list, so take them out first.
+    let items = std::mem::take(&mut *list_items(&list).borrow_mut());
+    drop(items);
+    Ok(vm.none())
+}
+
+fn list_copy(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (list, args) = receiver(vm, args, "list", "copy", is_list)?;
+    check_args(vm, "list.copy", &args, 0, 0)?;
+    let items = list_items(&list).borrow().clone();
+    Ok(vm.new_list(items))
+}
+
+fn list_reverse(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (list, args) = receiver(vm, args, "list", "reverse", is_list)?;
+    check_args(vm, "list.reverse", &args, 0, 0)?;
+    list_items(&list).borrow_mut().reverse();
+    Ok(vm.none())
+}
+
+/// `list.sort(*, key=None, reverse=False)`, which leaves the list empty while it sorts a
+/// copy of the items.
+fn list_sort(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (list, mut args) = receiver(vm, args, "list", "sort", is_list)?;
+    let key = args.take_kwarg("key").filter(|key| !vm.is_none(key));
+    let reverse = match args.take_kwarg("reverse") {
+        Some(reverse) => vm.is_true(&reverse)?,
+        None => false,
+    };
+    if let Some((name, _)) = args.kwargs.first() {
+        return Err(vm.new_type_error(format!("sort() got an unexpected keyword argument '{}'", name)));
+    }
+    if !args.args.is_empty() {
+        return Err(vm.new_type_error("sort() takes no positional arguments".to_string()));
+    }
+    let items = std::mem::take(&mut *list_items(&list).borrow_mut());
+    let sorted = vm.sort_items(items.clone(), key.as_ref(), reverse);
+    let modified = !list_items(&list).borrow().is_empty();
+    match sorted {
+        Ok(_) if modified => {
+            *list_items(&list).borrow_mut() = items;
+            Err(vm.new_value_error("list modified during sort".to_string()))
+        }
+        Ok(sorted) => {
+            *list_items(&list).borrow_mut() = sorted;
+            Ok(vm.none())
+        }
+        Err(exc) => {
+            *list_items(&list).borrow_mut() = items;
+            Err(exc)
+        }
+    }
+}
+
+// tuple
+
+fn tuple_index(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (tuple, args) = receiver(vm, args, "tuple", "index", is_tuple)?;
+    check_args(vm, "index", &args, 1, 3)?;
+    let items = tuple.as_tuple().expect("tuple");
+    let (start, stop) = index_bounds(vm, &args.args, items.len())?;
+    for (index, item) in items.iter().enumerate().take(stop).skip(start) {
+        if vm.same_or_eq(item, &args.args[0])? {
+            return Ok(vm.new_int(index as i64));
+        }
+    }
+    Err(vm.new_value_error("tuple.index(x): x not in tuple".to_string()))
+}
+
+fn tuple_count(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (tuple, args) = receiver(vm, args, "tuple", "count", is_tuple)?;
+    check_args(vm, "tuple.count", &args, 1, 1)?;
+    count_equal(vm, tuple.as_tuple().expect("tuple"), &args.args[0])
+}
+
+// dict
+
+fn dict_init(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (dict, args) = receiver(vm, args, "dict", "__init__", is_dict)?;
+    dict_update_from(vm, &dict, args, "dict")?;
+    Ok(vm.none())
+}
+
+/// The arguments of `dict(...)` and `dict.update(...)`: a mapping or iterable of pairs,
+/// then keyword arguments.
+fn dict_update_from(vm: &VirtualMachine, dict: &PyObjectRef, mut args: FuncArgs, name: &str) -> PyResult<()> {
+    let kwargs = std::mem::take(&mut args.kwargs);
+    check_args(vm, name, &args, 0, 1)?;
+    if let Some(source) = args.args.first() {
+        vm.dict_update(dict, source)?;
+    }
+    for (key, value) in kwargs {
+        vm.dict_set_item(dict, &vm.new_str(key), &value)?;
+    }
+    Ok(())
+}
+
+fn dict_get(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (dict, args) = receiver(vm, args, "dict", "get", is_dict)?;
+    check_args(vm, "get", &args, 1, 2)?;
+    let default = args.args.get(1).cloned().unwrap_or_else(|| vm.none());
+    Ok(vm.dict_get_item(&dict, &args.args[0])?.unwrap_or(default))
+}
+
+fn dict_view(vm: &VirtualMachine, args: FuncArgs, name: &str, kind: DictViewKind) -> PyResult {
+    let (dict, args) = receiver(vm, args, "dict", name, is_dict)?;
+    check_args(vm, &format!("dict.{}", name), &args, 0, 0)?;
+    let class = match kind {
+        DictViewKind::Keys => &vm.types.dict_keys_type,
+        DictViewKind::Values => &vm.types.dict_values_type,
+        DictViewKind::Items => &vm.types.dict_items_type,
+    };
+    Ok(vm.new_object(Payload::DictView { dict, kind }, class))
+}
+
+fn dict_keys(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    dict_view(vm, args, "keys", DictViewKind::Keys)
+}
+
+fn dict_values(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    dict_view(vm, args, "values", DictViewKind::Values)
+}
+
+fn dict_items(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    dict_view(vm, args, "items", DictViewKind::Items)
+}
+
+fn is_set_view(payload: &Payload) -> bool {
+    matches!(payload, Payload::DictView { kind: DictViewKind::Keys | DictViewKind::Items, .. })
+}
+
+fn dict_view_isdisjoint(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (view, args) = receiver(vm, args, "dict_keys", "isdisjoint", is_set_view)?;
+    check_args(vm, "isdisjoint", &args, 1, 1)?;
+    for item in vm.collect(&args.args[0])? {
+        if vm.contains(&view, &item)? {
+            return Ok(vm.new_bool(false));
+        }
+    }
+    Ok(vm.new_bool(true))
+}
+
+fn dict_pop(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (dict, args) = receiver(vm, args, "dict", "pop", is_dict)?;
+    check_args(vm, "pop", &args, 1, 2)?;
+    match (vm.dict_pop_item(&dict, &args.args[0])?, args.args.get(1)) {
+        (Some(value), _) => Ok(value),
+        (None, Some(default)) => Ok(default.clone()),
+        (None, None) => Err(vm.new_key_error(args.args[0].clone())),
+    }
+}
+
+fn dict_popitem(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (dict, args) = receiver(vm, args, "dict", "popitem", is_dict)?;
+    check_args(vm, "dict.popitem", &args, 0, 0)?;
+    let entry = dict.as_dict().expect("dict").borrow_mut().pop_last();
+    match entry {
+        Some(entry) => Ok(vm.new_tuple(vec![entry.key, entry.value])),
+        None => Err(vm.new_exception_msg(
+            &vm.exceptions.key_error,
+            "popitem(): dictionary is empty".to_string(),
+        )),
+    }
+}
+
+fn dict_setdefault(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (dict, args) = receiver(vm, args, "dict", "setdefault", is_dict)?;
+    check_args(vm, "setdefault", &args, 1, 2)?;
+    if let Some(value) = vm.dict_get_item(&dict, &args.args[0])? {
+        return Ok(value);
+    }
+    let default = args.args.get(1).cloned().unwrap_or_else(|| vm.none());
+    vm.dict_set_item(&dict, &args.args[0], &default)?;
+    Ok(default)
+}
+
+fn dict_update(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (dict, args) = receiver(vm, args, "dict", "update", is_dict)?;
+    dict_update_from(vm, &dict, args, "update")?;
+    Ok(vm.none())
+}
+
+fn dict_copy(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (dict, args) = receiver(vm, args, "dict", "copy", is_dict)?;
+    check_args(vm, "dict.copy", &args, 0, 0)?;
+    let table = dict.as_dict().expect("dict").borrow().clone();
+    Ok(vm.new_object(Payload::Dict(RefCell::new(table)), &vm.types.dict_type))
+}
+
+fn dict_clear(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (dict, args) = receiver(vm, args, "dict", "clear", is_dict)?;
+    check_args(vm, "dict.clear", &args, 0, 0)?;
+    let table = std::mem::replace(&mut *dict.as_dict().expect("dict").borrow_mut(), PyDict::new());
+    drop(table);
+    Ok(vm.none())
+}
+
+/// `dict.fromkeys(iterable, value=None)`, a class method.
+fn dict_fromkeys(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (class, args) = receiver(vm, args, "dict", "fromkeys", |payload| matches!(payload, Payload::Type(_)))?;
+    check_args(vm, "fromkeys", &args, 1, 2)?;
+    let value = args.args.get(1).cloned().unwrap_or_else(|| vm.none());
+    let dict = vm.call(&class, FuncArgs::default())?;
+    for key in vm.collect(&args.args[0])? {
+        vm.set_item(&dict, &key, &value)?;
+    }
+    Ok(dict)
+}
+
+// set and frozenset
+
+/// `other` as a set or frozenset, for the methods that take any iterable.
+fn as_set(vm: &VirtualMachine, other: &PyObjectRef) -> PyResult {
+    if is_any_set(&other.payload) {
+        return Ok(other.clone());
+    }
+    let table = vm.hash_table(&vm.collect(other)?)?;
+    Ok(vm.new_object(Payload::FrozenSet(table), &vm.types.frozenset_type))
+}
+
+/// A copy of a set or frozenset, of the same builtin type.
+fn copy_set(vm: &VirtualMachine, set: &PyObjectRef) -> PyObjectRef {
+    let table = vm.set_table(set).expect("set");
+    match &set.payload {
+        Payload::FrozenSet(_) => vm.new_object(Payload::FrozenSet(table), &vm.types.frozenset_type),
+        _ => vm.new_object(Payload::Set(RefCell::new(table)), &vm.types.set_type),
+    }
+}
+
+fn set_add(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (set, args) = receiver(vm, args, "set", "add", is_set)?;
+    check_args(vm, "set.add", &args, 1, 1)?;
+    vm.set_add(&set, &args.args[0])?;
+    Ok(vm.none())
+}
+
+fn set_discard(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (set, args) = receiver(vm, args, "set", "discard", is_set)?;
+    check_args(vm, "set.discard", &args, 1, 1)?;
+    vm.set_discard(&set, &args.args[0])?;
+    Ok(vm.none())
+}
+
+fn set_remove(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (set, args) = receiver(vm, args, "set", "remove", is_set)?;
+    check_args(vm, "set.remove", &args, 1, 1)?;
+    if !vm.set_discard(&set, &args.args[0])? {
+        return Err(vm.new_key_error(args.args[0].clone()));
+    }
+    Ok(vm.none())
+}
+
+fn set_pop(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (set, args) = receiver(vm, args, "set", "pop", is_set)?;
+    check_args(vm, "set.pop", &args, 0, 0)?;
+    let Payload::Set(table) = &set.payload else { unreachable!("not a set") };
+    let first = table.borrow().next_entry(0).map(|(pos, _)| pos);
+    match first.and_then(|pos| table.borrow_mut().remove(pos)) {
+        Some(entry) => Ok(entry.key),
+        None => Err(vm.new_exception_msg(&vm.exceptions.key_error, "pop from an empty set".to_string())),
+    }
+}
+
+fn set_clear(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (set, args) = receiver(vm, args, "set", "clear", is_set)?;
+    check_args(vm, "set.clear", &args, 0, 0)?;
+    let Payload::Set(table) = &set.payload else { unreachable!("not a set") };
+    let old = std::mem::replace(&mut *table.borrow_mut(), PyDict::new());
+    drop(old);
+    Ok(vm.none())
+}
+
+fn set_update(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (set, args) = receiver(vm, args, "set", "update", is_set)?;
+    if !args.kwargs.is_empty() {
+        return Err(vm.new_type_error("set.update() takes no keyword arguments".to_string()));
+    }
+    for other in &args.args {
+        for item in vm.collect(other)? {
+            vm.set_add(&set, &item)?;
+        }
+    }
+    Ok(vm.none())
+}
+
+fn set_copy(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (set, args) = receiver(vm, args, "set", "copy", is_any_set)?;
+    check_args(vm, "set.copy", &args, 0, 0)?;
+    Ok(copy_set(vm, &set))
+}
+
+/// `union`, `intersection`, `difference` and `symmetric_difference`, which fold the
+/// operator over their arguments.
+fn set_fold(vm: &VirtualMachine, args: FuncArgs, name: &str, op: BinaryOperator) -> PyResult {
+    let (set, args) = receiver(vm, args, "set", name, is_any_set)?;
+    if !args.kwargs.is_empty() {
+        return Err(vm.new_type_error(format!("set.{}() takes no keyword arguments", name)));
+    }
+    let mut result = copy_set(vm, &set);
+    for other in &args.args {
+        result = vm.set_operation(&result, &as_set(vm, other)?, op)?;
+    }
+    Ok(result)
+}
+
+fn set_union(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    set_fold(vm, args, "union", BinaryOperator::Or)
+}
+
+fn set_intersection(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    set_fold(vm, args, "intersection", BinaryOperator::And)
+}
+
+fn set_difference(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    set_fold(vm, args, "difference", BinaryOperator::Subtract)
+}
+
+fn set_symmetric_difference(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (set, args) = receiver(vm, args, "set", "symmetric_difference", is_any_set)?;
+    check_args(vm, "set.symmetric_difference", &args, 1, 1)?;
+    vm.set_operation(&copy_set(vm, &set), &as_set(vm, &args.args[0])?, BinaryOperator::Xor)
+}
+
+/// Whether every item of `a` is in `b`.
+fn all_in(vm: &VirtualMachine, a: &PyObjectRef, b: &PyObjectRef) -> PyResult<bool> {
+    for entry in vm.set_table(a).expect("set").iter() {
+        if !vm.set_contains(b, &entry.key)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn set_issubset(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (set, args) = receiver(vm, args, "set", "issubset", is_any_set)?;
+    check_args(vm, "set.issubset", &args, 1, 1)?;
+    Ok(vm.new_bool(all_in(vm, &set, &as_set(vm, &args.args[0])?)?))
+}
+
+fn set_issuperset(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (set, args) = receiver(vm, args, "set", "issuperset", is_any_set)?;
+    check_args(vm, "set.issuperset", &args, 1, 1)?;
+    Ok(vm.new_bool(all_in(vm, &as_set(vm, &args.args[0])?, &set)?))
+}
+
+fn set_isdisjoint(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (set, args) = receiver(vm, args, "set", "isdisjoint", is_any_set)?;
+    check_args(vm, "set.isdisjoint", &args, 1, 1)?;
+    let other = as_set(vm, &args.args[0])?;
+    for entry in vm.set_table(&set).expect("set").iter() {
+        if vm.set_contains(&other, &entry.key)? {
+            return Ok(vm.new_bool(false));
+        }
+    }
+    Ok(vm.new_bool(true))
+}
+
+// property
+
+/// A copy of a property with one of its functions replaced, for `@x.setter` and the like.
+fn property_copy(vm: &VirtualMachine, args: FuncArgs, name: &str) -> PyResult {
+    let (property, args) = receiver(vm, args, "property", name, |payload| matches!(payload, Payload::Property(_)))?;
+    check_args(vm, &format!("property.{}", name), &args, 1, 1)?;
+    let Payload::Property(old) = &property.payload else { unreachable!("not a property") };
+    let function = Some(args.args[0].clone()).filter(|function| !vm.is_none(function));
+    let mut copy = PyProperty {
+        name: RefCell::new(old.name.borrow().clone()),
+        getter: old.getter.clone(),
+        setter: old.setter.clone(),
+        deleter: old.deleter.clone(),
+        doc: old.doc.clone(),
+    };
+    match name {
+        "getter" => copy.getter = function,
+        "setter" => copy.setter = function,
+        _ => copy.deleter = function,
+    }
+    Ok(vm.new_object(Payload::Property(copy), &property.class()))
+}
+
+fn property_getter(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    property_copy(vm, args, "getter")
+}
+
+fn property_setter(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    property_copy(vm, args, "setter")
+}
+
+fn property_deleter(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    property_copy(vm, args, "deleter")
+}
+
+// Special methods
+//
+// The builtin types expose their operations as special methods too, so that
+// `"a".__len__()` and `super().__add__(other)` in a subclass work.  They call the payload
+// operations directly rather than `vm.len` and the like, which would find the subclass's
+// own override again.
+
+/// Splits the receiver off the arguments of the special method `name`, which takes `count`
+/// more.
+fn slot_args(
+    vm: &VirtualMachine,
+    mut args: FuncArgs,
+    name: &str,
+    count: usize,
+) -> PyResult<(PyObjectRef, Vec<PyObjectRef>)> {
+    if args.args.is_empty() {
+        return Err(vm.new_type_error(format!("descriptor '{}' needs an argument", name)));
+    }
+    let obj = args.args.remove(0);
+    check_args(vm, name, &args, count, count)?;
+    Ok((obj, args.args))
+}
+
+/// `a.__add__(b)` and the like, or `b.__radd__(a)` when `reflected`.
+fn binary_slot(vm: &VirtualMachine, args: FuncArgs, name: &str, op: BinaryOperator, reflected: bool) -> PyResult {
+    let (obj, args) = slot_args(vm, args, name, 1)?;
+    let (a, b) = if reflected { (&args[0], &obj) } else { (&obj, &args[0]) };
+    Ok(vm.native_binary(a, b, op)?.unwrap_or_else(|| vm.not_implemented.clone()))
+}
+
+fn inplace_slot(vm: &VirtualMachine, args: FuncArgs, name: &str, op: BinaryOperator) -> PyResult {
+    let (obj, args) = slot_args(vm, args, name, 1)?;
+    Ok(vm.native_inplace(&obj, &args[0], op)?.unwrap_or_else(|| vm.not_implemented.clone()))
+}
+
+fn compare_slot(vm: &VirtualMachine, args: FuncArgs, name: &str, op: ComparisonOperator) -> PyResult {
+    let (obj, args) = slot_args(vm, args, name, 1)?;
+    Ok(match vm.native_compare(&obj, &args[0], op)? {
+        Some(result) => vm.new_bool(result),
+        None => vm.not_implemented.clone(),
+    })
+}
+
+fn unary_slot(vm: &VirtualMachine, args: FuncArgs, name: &str, op: UnaryOperator) -> PyResult {
+    let (obj, _) = slot_args(vm, args, name, 0)?;
+    vm.native_unary(&obj, op)
+}
+
+fn slot_add(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__add__", BinaryOperator::Add, false)
+}
+
+fn slot_radd(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__radd__", BinaryOperator::Add, true)
+}
+
+fn slot_sub(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__sub__", BinaryOperator::Subtract, false)
+}
+
+fn slot_rsub(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__rsub__", BinaryOperator::Subtract, true)
+}
+
+fn slot_mul(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__mul__", BinaryOperator::Multiply, false)
+}
+
+fn slot_rmul(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__rmul__", BinaryOperator::Multiply, true)
+}
+
+fn slot_matmul(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__matmul__", BinaryOperator::MatrixMultiply, false)
+}
+
+fn slot_rmatmul(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__rmatmul__", BinaryOperator::MatrixMultiply, true)
+}
+
+fn slot_truediv(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__truediv__", BinaryOperator::TrueDivide, false)
+}
+
+fn slot_rtruediv(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__rtruediv__", BinaryOperator::TrueDivide, true)
+}
+
+fn slot_floordiv(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__floordiv__", BinaryOperator::FloorDivide, false)
+}
+
+fn slot_rfloordiv(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__rfloordiv__", BinaryOperator::FloorDivide, true)
+}
+
+fn slot_mod(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__mod__", BinaryOperator::Remainder, false)
+}
+
+fn slot_rmod(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__rmod__", BinaryOperator::Remainder, true)
+}
+
+fn slot_pow(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__pow__", BinaryOperator::Power, false)
+}
+
+fn slot_rpow(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__rpow__", BinaryOperator::Power, true)
+}
+
+fn slot_lshift(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__lshift__", BinaryOperator::LeftShift, false)
+}
+
+fn slot_rlshift(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__rlshift__", BinaryOperator::LeftShift, true)
+}
+
+fn slot_rshift(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__rshift__", BinaryOperator::RightShift, false)
+}
+
+fn slot_rrshift(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__rrshift__", BinaryOperator::RightShift, true)
+}
+
+fn slot_and(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__and__", BinaryOperator::And, false)
+}
+
+fn slot_rand(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__rand__", BinaryOperator::And, true)
+}
+
+fn slot_or(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__or__", BinaryOperator::Or, false)
+}
+
+fn slot_ror(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__ror__", BinaryOperator::Or, true)
+}
+
+fn slot_xor(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__xor__", BinaryOperator::Xor, false)
+}
+
+fn slot_rxor(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    binary_slot(vm, args, "__rxor__", BinaryOperator::Xor, true)
+}
+
+fn slot_iadd(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    inplace_slot(vm, args, "__iadd__", BinaryOperator::Add)
+}
+
+fn slot_imul(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    inplace_slot(vm, args, "__imul__", BinaryOperator::Multiply)
+}
+
+fn slot_iand(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    inplace_slot(vm, args, "__iand__", BinaryOperator::And)
+}
+
+fn slot_ior(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    inplace_slot(vm, args, "__ior__", BinaryOperator::Or)
+}
+
+fn slot_ixor(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    inplace_slot(vm, args, "__ixor__", BinaryOperator::Xor)
+}
+
+fn slot_isub(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    inplace_slot(vm, args, "__isub__", BinaryOperator::Subtract)
+}
+
+fn slot_lt(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    compare_slot(vm, args, "__lt__", ComparisonOperator::Less)
+}
+
+fn slot_le(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    compare_slot(vm, args, "__le__", ComparisonOperator::LessEqual)
+}
+
+fn slot_eq(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    compare_slot(vm, args, "__eq__", ComparisonOperator::Equal)
+}
+
+fn slot_ne(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    compare_slot(vm, args, "__ne__", ComparisonOperator::NotEqual)
+}
+
+fn slot_gt(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    compare_slot(vm, args, "__gt__", ComparisonOperator::Greater)
+}
+
+fn slot_ge(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    compare_slot(vm, args, "__ge__", ComparisonOperator::GreaterEqual)
+}
+
+fn slot_neg(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    unary_slot(vm, args, "__neg__", UnaryOperator::Negative)
+}
+
+fn slot_pos(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    unary_slot(vm, args, "__pos__", UnaryOperator::Positive)
+}
+
+fn slot_invert(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    unary_slot(vm, args, "__invert__", UnaryOperator::Invert)
+}
+
+fn slot_abs(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, _) = slot_args(vm, args, "__abs__", 0)?;
+    vm.native_abs(&obj)
+}
+
+fn slot_bool(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, _) = slot_args(vm, args, "__bool__", 0)?;
+    Ok(vm.new_bool(vm.native_bool(&obj)))
+}
+
+fn slot_int(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, _) = slot_args(vm, args, "__int__", 0)?;
+    Ok(vm.new_bigint(vm.to_int(&obj)?))
+}
+
+fn slot_float(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, _) = slot_args(vm, args, "__float__", 0)?;
+    Ok(vm.new_float(vm.to_float(&obj)?))
+}
+
+fn slot_index(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, _) = slot_args(vm, args, "__index__", 0)?;
+    Ok(vm.new_bigint(vm.to_index(&obj)?))
+}
+
+fn slot_hash(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, _) = slot_args(vm, args, "__hash__", 0)?;
+    Ok(vm.new_int(vm.native_hash(&obj)?))
+}
+
+fn slot_repr(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, _) = slot_args(vm, args, "__repr__", 0)?;
+    Ok(vm.new_str(vm.native_repr(&obj)?))
+}
+
+fn slot_str(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, _) = slot_args(vm, args, "__str__", 0)?;
+    Ok(vm.new_str(vm.native_str(&obj)?))
+}
+
+fn slot_len(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, _) = slot_args(vm, args, "__len__", 0)?;
+    Ok(vm.new_int(vm.native_len(&obj)? as i64))
+}
+
+fn slot_getitem(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = slot_args(vm, args, "__getitem__", 1)?;
+    vm.native_get_item(&obj, &args[0])
+}
+
+fn slot_setitem(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = slot_args(vm, args, "__setitem__", 2)?;
+    vm.native_set_item(&obj, &args[0], &args[1])?;
+    Ok(vm.none())
+}
+
+fn slot_delitem(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = slot_args(vm, args, "__delitem__", 1)?;
+    vm.native_del_item(&obj, &args[0])?;
+    Ok(vm.none())
+}
+
+fn slot_contains(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = slot_args(vm, args, "__contains__", 1)?;
+    Ok(vm.new_bool(vm.native_contains(&obj, &args[0])?))
+}
+
+fn slot_iter(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, _) = slot_args(vm, args, "__iter__", 0)?;
+    vm.native_iter(&obj)
+}
+
+fn slot_next(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, _) = slot_args(vm, args, "__next__", 0)?;
+    vm.native_next(&obj)?.ok_or_else(|| vm.new_stop_iteration(None))
+}
+
+fn slot_call(vm: &VirtualMachine, mut args: FuncArgs) -> PyResult {
+    if args.args.is_empty() {
+        return Err(vm.new_type_error("descriptor '__call__' needs an argument".to_string()));
+    }
+    let callable = args.args.remove(0);
+    vm.call(&callable, args)
+}
+
+/// The implementation of the special method `name` of the builtin types.
+fn special_slot(name: &str) -> NativeFunction {
+    match name {
+        "__add__" => slot_add,
+        "__radd__" => slot_radd,
+        "__sub__" => slot_sub,
+        "__rsub__" => slot_rsub,
+        "__mul__" => slot_mul,
+        "__rmul__" => slot_rmul,
+        "__matmul__" => slot_matmul,
+        "__rmatmul__" => slot_rmatmul,
+        "__truediv__" => slot_truediv,
+        "__rtruediv__" => slot_rtruediv,
+        "__floordiv__" => slot_floordiv,
+        "__rfloordiv__" => slot_rfloordiv,
+        "__mod__" => slot_mod,
+        "__rmod__" => slot_rmod,
+        "__pow__" => slot_pow,
+        "__rpow__" => slot_rpow,
+        "__lshift__" => slot_lshift,
+        "__rlshift__" => slot_rlshift,
+        "__rshift__" => slot_rshift,
+        "__rrshift__" => slot_rrshift,
+        "__and__" => slot_and,
+        "__rand__" => slot_rand,
+        "__or__" => slot_or,
+        "__ror__" => slot_ror,
+        "__xor__" => slot_xor,
+        "__rxor__" => slot_rxor,
+        "__iadd__" => slot_iadd,
+        "__imul__" => slot_imul,
+        "__iand__" => slot_iand,
+        "__ior__" => slot_ior,
+        "__ixor__" => slot_ixor,
+        "__isub__" => slot_isub,
+        "__lt__" => slot_lt,
+        "__le__" => slot_le,
+        "__eq__" => slot_eq,
+        "__ne__" => slot_ne,
+        "__gt__" => slot_gt,
+        "__ge__" => slot_ge,
+        "__neg__" => slot_neg,
+        "__pos__" => slot_pos,
+        "__invert__" => slot_invert,
+        "__abs__" => slot_abs,
+        "__bool__" => slot_bool,
+        "__int__" => slot_int,
+        "__float__" => slot_float,
+        "__index__" => slot_index,
+        "__hash__" => slot_hash,
+        "__repr__" => slot_repr,
+        "__str__" => slot_str,
+        "__len__" => slot_len,
+        "__getitem__" => slot_getitem,
+        "__setitem__" => slot_setitem,
+        "__delitem__" => slot_delitem,
+        "__contains__" => slot_contains,
+        "__iter__" => slot_iter,
+        "__next__" => slot_next,
+        "__call__" => slot_call,
+        _ => unreachable!("no special method {}", name),
+    }
+}
+
+impl VirtualMachine {
+    pub(crate) fn add_type_methods(&self) {
+        let types = &self.types;
+        let object = &types.object_type;
+        self.add_method(object, "__init__", object_init);
+        let new = self.new_builtin_function("__new__", object_new);
+        let new = self.new_object(Payload::StaticMethod(new), &types.staticmethod_type);
+        self.dict_set_str(object.dict.as_ref().expect("type dict"), "__new__", new);
+        self.add_method(object, "__repr__", object_repr);
+        self.add_method(object, "__str__", object_str);
+        self.add_method(object, "__eq__", object_eq);
+        self.add_method(object, "__ne__", object_ne);
+        self.add_method(object, "__hash__", object_hash);
+        self.add_method(object, "__getattribute__", object_getattribute);
+        self.add_method(object, "__setattr__", object_setattr);
+        self.add_method(object, "__delattr__", object_delattr);
+        self.add_method(object, "__format__", object_format);
+        let init_subclass = self.new_builtin_function("__init_subclass__", object_init_subclass);
+        let init_subclass = self.new_object(Payload::ClassMethod(init_subclass), &types.classmethod_type);
+        self.dict_set_str(object.dict.as_ref().expect("type dict"), "__init_subclass__", init_subclass);
+        self.add_method(&types.type_type, "__init__", type_init);
+        self.add_method(&types.type_type, "__call__", type_call);
+        self.add_method(&types.type_type, "mro", type_mro);
+
+        let int = &types.int_type;
+        self.add_method(int, "bit_length", int_bit_length);
+        self.add_method(int, "bit_count", int_bit_count);
+        self.add_method(int, "to_bytes", int_to_bytes);
+        let from_bytes = self.new_builtin_function("from_bytes", int_from_bytes);
+        let from_bytes = self.new_object(Payload::ClassMethod(from_bytes), &types.classmethod_type);
+        self.dict_set_str(int.dict.as_ref().expect("type dict"), "from_bytes", from_bytes);
+
+        let list = &types.list_type;
+        self.add_method(list, "__init__", list_init);
+        self.add_method(list, "append", list_append);
+        self.add_method(list, "extend", list_extend);
+        self.add_method(list, "insert", list_insert);
+        self.add_method(list, "pop", list_pop);
+        self.add_method(list, "remove", list_remove);
+        self.add_method(list, "index", list_index);
+        self.add_method(list, "count", list_count);
+        self.add_method(list, "clear", list_clear);
+        self.add_method(list, "copy", list_copy);
+        self.add_method(list, "reverse", list_reverse);
+        self.add_method(list, "sort", list_sort);
+
+        self.add_method(&types.tuple_type, "index", tuple_index);
+        self.add_method(&types.tuple_type, "count", tuple_count);
+
+        let dict = &types.dict_type;
+        self.add_method(dict, "__init__", dict_init);
+        self.add_method(dict, "get", dict_get);
+        self.add_method(dict, "keys", dict_keys);
+        self.add_method(dict, "values", dict_values);
+        self.add_method(dict, "items", dict_items);
+        self.add_method(dict, "pop", dict_pop);
+        self.add_method(dict, "popitem", dict_popitem);
+        self.add_method(dict, "setdefault", dict_setdefault);
+        self.add_method(dict, "update", dict_update);
+        self.add_method(dict, "copy", dict_copy);
+        self.add_method(dict, "clear", dict_clear);
+        let fromkeys = self.new_builtin_function("fromkeys", dict_fromkeys);
+        let fromkeys = self.new_object(Payload::ClassMethod(fromkeys), &types.classmethod_type);
+        self.dict_set_str(dict.dict.as_ref().expect("type dict"), "fromkeys", fromkeys);
+
+        let set = &types.set_type;
+        self.add_method(set, "add", set_add);
+        self.add_method(set, "discard", set_discard);
+        self.add_method(set, "remove", set_remove);
+        self.add_method(set, "pop", set_pop);
+        self.add_method(set, "clear", set_clear);
+        self.add_method(set, "update", set_update);
+        for class in [set, &types.frozenset_type] {
+            self.add_method(class, "copy", set_copy);
+            self.add_method(class, "union", set_union);
+            self.add_method(class, "intersection", set_intersection);
+            self.add_method(class, "difference", set_difference);
+            self.add_method(class, "symmetric_difference", set_symmetric_difference);
+            self.add_method(class, "issubset", set_issubset);
+            self.add_method(class, "issuperset", set_issuperset);
+            self.add_method(class, "isdisjoint", set_isdisjoint);
+        }
+        for class in [&types.dict_keys_type, &types.dict_items_type] {
+            self.add_method(class, "isdisjoint", dict_view_isdisjoint);
+        }
+
+        let property = &types.property_type;
+        self.add_method(property, "getter", property_getter);
+        self.add_method(property, "setter", property_setter);
+        self.add_method(property, "deleter", property_deleter);
+
+        let compare = ["__lt__", "__le__", "__eq__", "__ne__", "__gt__", "__ge__"];
+        let arithmetic = ["__add__", "__sub__", "__mul__", "__truediv__", "__floordiv__", "__mod__", "__pow__"];
+        let reflected = ["__radd__", "__rsub__", "__rmul__", "__rtruediv__", "__rfloordiv__", "__rmod__", "__rpow__"];
+        let bitwise = ["__and__", "__or__", "__xor__", "__rand__", "__ror__", "__rxor__"];
+        let sequence = ["__len__", "__getitem__", "__contains__", "__iter__"];
+        for class in [int, &types.float_type, &types.complex_type] {
+            self.add_special_methods(class, &compare);
+            self.add_special_methods(class, &arithmetic);
+            self.add_special_methods(class, &reflected);
+            self.add_special_methods(class, &["__neg__", "__pos__", "__abs__", "__bool__", "__hash__", "__repr__"]);
+        }
+        self.add_special_methods(int, &bitwise);
+        self.add_special_methods(int, &["__lshift__", "__rshift__", "__rlshift__", "__rrshift__", "__invert__"]);
+        self.add_special_methods(int, &["__int__", "__float__", "__index__"]);
+        self.add_special_methods(&types.float_type, &["__int__", "__float__"]);
+        for class in [&types.str_type, &types.bytes_type, &types.tuple_type, list] {
+            self.add_special_methods(class, &compare);
+            self.add_special_methods(class, &sequence);
+            self.add_special_methods(class, &["__add__", "__mul__", "__rmul__", "__repr__"]);
+        }
+        for class in [&types.str_type, &types.bytes_type] {
+            self.add_special_methods(class, &["__mod__", "__hash__"]);
+        }
+        self.add_special_methods(&types.str_type, &["__str__"]);
+        self.add_special_methods(&types.tuple_type, &["__hash__"]);
+        self.add_special_methods(list, &["__setitem__", "__delitem__", "__iadd__", "__imul__"]);
+        self.add_special_methods(dict, &compare);
+        self.add_special_methods(dict, &sequence);
+        self.add_special_methods(dict, &["__setitem__", "__delitem__", "__or__", "__ror__", "__ior__", "__repr__"]);
+        for class in [set, &types.frozenset_type] {
+            self.add_special_methods(class, &compare);
+            self.add_special_methods(class, &bitwise);
+            self.add_special_methods(class, &["__sub__", "__rsub__", "__len__", "__contains__", "__iter__", "__repr__"]);
+        }
+        self.add_special_methods(set, &["__iand__", "__ior__", "__ixor__", "__isub__"]);
+        self.add_special_methods(&types.frozenset_type, &["__hash__"]);
+        for class in [&types.dict_keys_type, &types.dict_items_type] {
+            self.add_special_methods(class, &compare);
+            self.add_special_methods(class, &bitwise);
+            self.add_special_methods(class, &["__sub__", "__rsub__"]);
+        }
+        // Mutable containers are unhashable, even though `object` has a `__hash__`.
+        for class in [list, dict, set] {
+            self.dict_set_str(class.dict.as_ref().expect("type dict"), "__hash__", self.none());
+        }
+        self.add_special_methods(&types.range_type, &compare);
+        self.add_special_methods(&types.range_type, &sequence);
+        self.add_special_methods(&types.range_type, &["__bool__", "__hash__", "__repr__"]);
+        self.add_special_methods(&types.none_type, &["__bool__", "__repr__"]);
+        self.add_special_methods(&types.iterator_type, &["__iter__", "__next__"]);
+        for class in [&types.function_type, &types.builtin_function_type, &types.method_type] {
+            self.add_special_methods(class, &["__call__"]);
+        }
+    }
+
+    /// Adds the special methods `names`, which work on the payloads of the builtin types.
+    fn add_special_methods(&self, class: &PyObjectRef, names: &[&'static str]) {
+        for name in names {
+            self.add_method(class, name, special_slot(name));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::VirtualMachine;
+
+    #[test]
+    fn builtin_methods_and_descriptors() {
+        let vm = VirtualMachine::capturing();
+        let source = "\
+items = [3, 1, 2]
+items.append(0)
+items.sort()
+d = {'a': 1}
+d.setdefault('b', 2)
+class A:
+    @property
+    def x(self):
+        return self.__dict__.get('x', 0) + 1
+    @x.setter
+    def x(self, value):
+        self.__dict__['x'] = value
+a = A()
+a.x = 10
+print(items, list(d.items()), a.x, {1, 2}.union([3]))
+";
+        vm.run_main(source, "<test>").unwrap_or_else(|_| panic!("the program raised"));
+        assert_eq!(vm.take_output(), "[0, 1, 2, 3] [('a', 1), ('b', 2)] 11 {1, 2, 3}\n");
+    }
+
+    #[test]
+    fn builtin_special_methods() {
+        let vm = VirtualMachine::capturing();
+        let source = "\
+class L(list):
+    def __len__(self):
+        return super().__len__() + 100
+    def __getitem__(self, i):
+        return super().__getitem__(i) * 2
+items = L([1, 2])
+items.__iadd__([3])
+print('a'.__len__(), str.__add__('a', 'b'), (5).__radd__(2), (5).__add__('a'), (1).__lt__(2))
+print(len(items), items[2], items.__contains__(3), list.__hash__, hasattr(5, '__len__'))
+print(iter([7]).__next__(), len.__call__('ab'), 'x'.__repr__(), (2).__float__())
+";
+        vm.run_main(source, "<test>").unwrap_or_else(|_| panic!("the program raised"));
+        assert_eq!(
+            vm.take_output(),
+            "1 ab 7 NotImplemented True\n103 6 True None False\n7 2 'x' 2.0\n"
+        );
+    }
+
+    #[test]
+    fn dict_views_combine_like_sets() {
+        let vm = VirtualMachine::capturing();
+        let source = "\
+d = {1: 'a', 2: 'b', 3: 'c'}
+print(d.keys() & {1, 5}, d.keys() | [4], d.keys() - {1}, [1, 9] - d.keys(), d.items() & {(2, 'b')})
+print(d.keys().isdisjoint([4]), d.items().isdisjoint([(1, 'a')]), d.keys() == {1, 2, 3}, {1} < d.keys())
+print(list(reversed(d)), list(reversed(d.values())), list(reversed(d.items()))[0])
+try:
+    d.values() & {1}
+except TypeError as e:
+    print(e)
+";
+        vm.run_main(source, "<test>").unwrap_or_else(|_| panic!("the program raised"));
+        assert_eq!(
+            vm.take_output(),
+            "{1} {1, 2, 3, 4} {2, 3} {9} {(2, 'b')}\nTrue False True True\n[3, 2, 1] ['c', 'b', 'a'] (3, 'c')\n\
+             unsupported operand type(s) for &: 'dict_values' and 'set'\n"
+        );
+    }
+}
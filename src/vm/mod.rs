@@ -0,0 +1,898 @@
+//! A virtual machine that runs the code objects the compiler produces.
+//!
+//! The `VirtualMachine` owns the builtin types, the `builtins` and `sys` modules and the
+//! state shared by all frames, such as the exception being handled.  Frames run on the Rust
+//! stack: a Python call is a Rust call, so the interpreter runs in a thread with a large
+//! stack and Python recursion is limited like CPython's.
+
+pub mod attributes;
+pub mod builtins;
+pub mod dict;
+pub mod exception_group;
+pub mod exceptions;
+pub mod float;
+pub mod format;
+pub mod frame;
+pub mod function;
+pub mod import;
+pub mod iter;
+pub mod methods;
+pub mod object;
+pub mod ops;
+pub mod stdlib;
+pub mod string;
+pub mod types;
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::ast::{ConstantValue, ParserError};
+use crate::bigint::BigInt;
+use crate::compiler::{compile, CodeObject, OptimizerOptions};
+use crate::parser::{parse_module, unclosed_bracket_error, TokenArena};
+use crate::tokenizer::{tokenize_located, TokError};
+
+use dict::PyDict;
+use exceptions::ExceptionTypes;
+use frame::Frame;
+use object::{Payload, PyInt, PyObject, PyObjectRef, PyResult};
+use string::PyStr;
+use types::Types;
+
+/// CPython's default `sys.getrecursionlimit()`.
+const RECURSION_LIMIT: usize = 1000;
+/// The highest `sys.setrecursionlimit()` that the interpreter thread's stack can take.
+const MAX_RECURSION_LIMIT: usize = 50_000;
+/// CPython 3.11's default limit on the digits of decimal str/int conversions, which keeps
+/// their quadratic cost bounded.
+const INT_MAX_STR_DIGITS: usize = 4300;
+/// The lowest non-zero limit `sys.set_int_max_str_digits()` accepts.
+const INT_MAX_STR_DIGITS_THRESHOLD: usize = 640;
+/// `sys.version`: the language version this interpreter follows.
+const PYTHON_VERSION: &str = "3.11.7 (rython3)";
+/// The stack of the interpreter thread, enough for `RECURSION_LIMIT` nested calls.
+const STACK_SIZE: usize = 512 * 1024 * 1024;
+const SMALL_INT_MIN: i64 = -5;
+const SMALL_INT_MAX: i64 = 256;
+
+/// A code object and the objects for its constants.
+type CachedConsts = (Rc<CodeObject>, Rc<Vec<PyObjectRef>>);
+
+/// Where `print` writes.
+enum Output {
+    /// Buffered on the way to the process's stdout.
+    Stdout(Vec<u8>),
+    /// Kept, for tests.
+    Captured(String),
+}
+
+pub struct VirtualMachine {
+    pub types: Types,
+    pub exceptions: ExceptionTypes,
+    pub builtins: PyObjectRef,
+    /// `sys.modules`.
+    pub sys_modules: PyObjectRef,
+    none: PyObjectRef,
+    true_value: PyObjectRef,
+    false_value: PyObjectRef,
+    pub(crate) not_implemented: PyObjectRef,
+    pub(crate) ellipsis: PyObjectRef,
+    small_ints: Vec<PyObjectRef>,
+    /// The exception an `except` or `finally` block is handling, which a bare `raise`
+    /// re-raises.
+    pub(crate) handled: RefCell<Option<PyObjectRef>>,
+    /// How many Python frames are running.
+    pub(crate) depth: Cell<usize>,
+    /// What zero-argument `super()` needs from each running frame, innermost last.
+    pub(crate) frames: RefCell<Vec<frame::FrameInfo>>,
+    pub(crate) recursion_limit: Cell<usize>,
+    /// `sys.get_int_max_str_digits()`, zero for no limit.
+    pub(crate) int_max_str_digits: Cell<usize>,
+    /// Containers whose repr is being built, to print `[...]` for ones that contain
+    /// themselves.
+    pub(crate) repr_active: RefCell<Vec<*const PyObject>>,
+    /// The constants of each code object that has run, keyed by address.  The code object
+    /// is kept alive alongside so that the address is not reused.
+    const_cache: RefCell<HashMap<*const CodeObject, CachedConsts>>,
+    stdout: RefCell<Output>,
+}
+
+impl VirtualMachine {
+    pub fn new() -> Self {
+        let types = Types::new();
+        let exceptions = ExceptionTypes::new(&types);
+        let plain = |payload: Payload, class: &PyObjectRef| PyObject::new(payload, class.clone(), None);
+        let new_dict = || plain(Payload::Dict(RefCell::new(PyDict::new())), &types.dict_type);
+        let builtins = PyObject::new(
+            Payload::Module { name: "builtins".to_string() },
+            types.module_type.clone(),
+            Some(new_dict()),
+        );
+        let vm = Self {
+            none: plain(Payload::None, &types.none_type),
+            true_value: plain(Payload::Bool(true), &types.bool_type),
+            false_value: plain(Payload::Bool(false), &types.bool_type),
+            not_implemented: plain(Payload::NotImplemented, &types.not_implemented_type),
+            ellipsis: plain(Payload::Ellipsis, &types.ellipsis_type),
+            small_ints: (SMALL_INT_MIN..=SMALL_INT_MAX)
+                .map(|i| plain(Payload::Int(PyInt::Small(i)), &types.int_type))
+                .collect(),
+            sys_modules: new_dict(),
+            builtins,
+            handled: RefCell::new(None),
+            depth: Cell::new(0),
+            frames: RefCell::new(vec![]),
+            recursion_limit: Cell::new(RECURSION_LIMIT),
+            int_max_str_digits: Cell::new(INT_MAX_STR_DIGITS),
+            repr_active: RefCell::new(vec![]),
+            const_cache: RefCell::new(HashMap::new()),
+            stdout: RefCell::new(Output::Stdout(vec![])),
+            types,
+            exceptions,
+        };
+        vm.add_generator_methods();
+        vm.add_type_methods();
+        vm.add_float_methods();
+        vm.add_str_methods();
+        vm.add_exception_methods();
+        vm.init_builtins();
+        vm.init_stdlib();
+        vm
+    }
+
+    /// A VM whose `print` output is kept for `take_output` instead of written out.
+    pub fn capturing() -> Self {
+        let vm = Self::new();
+        *vm.stdout.borrow_mut() = Output::Captured(String::new());
+        vm
+    }
+
+    // Constructors
+
+    pub fn none(&self) -> PyObjectRef {
+        self.none.clone()
+    }
+
+    pub fn is_none(&self, obj: &PyObjectRef) -> bool {
+        obj.is(&self.none)
+    }
+
+    pub fn new_bool(&self, value: bool) -> PyObjectRef {
+        if value {
+            self.true_value.clone()
+        } else {
+            self.false_value.clone()
+        }
+    }
+
+    pub fn new_int(&self, value: i64) -> PyObjectRef {
+        if (SMALL_INT_MIN..=SMALL_INT_MAX).contains(&value) {
+            return self.small_ints[(value - SMALL_INT_MIN) as usize].clone();
+        }
+        PyObject::new(Payload::Int(PyInt::Small(value)), self.types.int_type.clone(), None)
+    }
+
+    pub fn new_bigint(&self, value: BigInt) -> PyObjectRef {
+        match PyInt::from_bigint(value) {
+            PyInt::Small(small) => self.new_int(small),
+            big => PyObject::new(Payload::Int(big), self.types.int_type.clone(), None),
+        }
+    }
+
+    pub fn new_float(&self, value: f64) -> PyObjectRef {
+        PyObject::new(Payload::Float(value), self.types.float_type.clone(), None)
+    }
+
+    pub fn new_complex(&self, real: f64, imag: f64) -> PyObjectRef {
+        PyObject::new(Payload::Complex { real, imag }, self.types.complex_type.clone(), None)
+    }
+
+    pub fn new_str(&self, value: String) -> PyObjectRef {
+        PyObject::new(Payload::Str(PyStr::new(value)), self.types.str_type.clone(), None)
+    }
+
+    pub fn new_bytes(&self, value: Vec<u8>) -> PyObjectRef {
+        PyObject::new(Payload::Bytes(value), self.types.bytes_type.clone(), None)
+    }
+
+    pub fn new_tuple(&self, items: Vec<PyObjectRef>) -> PyObjectRef {
+        PyObject::new(Payload::Tuple(items), self.types.tuple_type.clone(), None)
+    }
+
+    pub fn new_list(&self, items: Vec<PyObjectRef>) -> PyObjectRef {
+        PyObject::new(Payload::List(RefCell::new(items)), self.types.list_type.clone(), None)
+    }
+
+    pub fn new_dict(&self) -> PyObjectRef {
+        PyObject::new(Payload::Dict(RefCell::new(PyDict::new())), self.types.dict_type.clone(), None)
+    }
+
+    pub fn new_set(&self) -> PyObjectRef {
+        PyObject::new(Payload::Set(RefCell::new(PyDict::new())), self.types.set_type.clone(), None)
+    }
+
+    pub fn new_module(&self, name: &str) -> PyObjectRef {
+        let module = self.new_object(Payload::Module { name: name.to_string() }, &self.types.module_type);
+        let dict = module.dict.as_ref().expect("module dict");
+        self.dict_set_str(dict, "__name__", self.new_str(name.to_string()));
+        self.dict_set_str(dict, "__doc__", self.none());
+        self.dict_set_str(dict, "__package__", self.none());
+        module
+    }
+
+    /// The object for a constant of a code object.
+    pub fn constant(&self, value: &ConstantValue) -> PyObjectRef {
+        match value {
+            ConstantValue::None => self.none(),
+            ConstantValue::Ellipsis => self.ellipsis.clone(),
+            ConstantValue::Bool(b) => self.new_bool(*b),
+            ConstantValue::Int(i) => self.new_bigint(i.clone()),
+            ConstantValue::Float(f) => self.new_float(*f),
+            ConstantValue::Complex { real, imag } => self.new_complex(*real, *imag),
+            ConstantValue::Str(s) => self.new_str(s.clone()),
+            ConstantValue::Bytes(b) => self.new_bytes(b.clone()),
+            ConstantValue::Tuple(items) => self.new_tuple(items.iter().map(|item| self.constant(item)).collect()),
+            ConstantValue::FrozenSet(items) => {
+                let items: Vec<PyObjectRef> = items.iter().map(|item| self.constant(item)).collect();
+                let table = self.hash_table(&items).expect("constants are hashable");
+                PyObject::new(Payload::FrozenSet(table), self.types.frozenset_type.clone(), None)
+            }
+        }
+    }
+
+    pub fn builtins_dict(&self) -> PyObjectRef {
+        self.builtins.dict.clone().expect("builtins dict")
+    }
+
+    /// The str value of `obj`, or a TypeError saying that `context` must be a str.
+    pub fn expect_str(&self, obj: &PyObjectRef, context: &str) -> PyResult<String> {
+        match obj.as_str() {
+            Some(s) => Ok(s.to_string()),
+            None => Err(self.new_type_error(format!("{} must be str, not {}", context, self.class_name(obj)))),
+        }
+    }
+
+    /// The repr of a type parameter or type alias, which keep their names in their dicts.
+    pub(crate) fn type_param_repr(&self, obj: &PyObjectRef) -> Option<String> {
+        let class = obj.class();
+        let types = &self.types;
+        let prefix = if class.is(&types.typevar_type) || class.is(&types.paramspec_type) {
+            "~"
+        } else if class.is(&types.typevartuple_type) || class.is(&types.type_alias_type) {
+            ""
+        } else {
+            return None;
+        };
+        let name = self.dict_get_str(obj.dict.as_ref()?, "__name__")?;
+        Some(format!("{}{}", prefix, name.as_str()?))
+    }
+
+    // Output
+
+    pub fn write_stdout(&self, text: &str) -> std::io::Result<()> {
+        let full = match &mut *self.stdout.borrow_mut() {
+            Output::Stdout(buffer) => {
+                buffer.extend_from_slice(text.as_bytes());
+                buffer.len() >= 8192
+            }
+            Output::Captured(captured) => {
+                captured.push_str(text);
+                false
+            }
+        };
+        if full {
+            self.flush_stdout()?;
+        }
+        Ok(())
+    }
+
+    pub fn flush_stdout(&self) -> std::io::Result<()> {
+        if let Output::Stdout(buffer) = &mut *self.stdout.borrow_mut() {
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(buffer)?;
+            buffer.clear();
+            stdout.flush()?;
+        }
+        Ok(())
+    }
+
+    /// What `print` wrote to a capturing VM.
+    pub fn take_output(&self) -> String {
+        match &mut *self.stdout.borrow_mut() {
+            Output::Captured(captured) => std::mem::take(captured),
+            Output::Stdout(_) => String::new(),
+        }
+    }
+
+    // Running programs
+
+    /// Compiles a module's source, raising SyntaxError, IndentationError or TabError the
+    /// way CPython does for source that does not compile.
+    pub fn compile_source(&self, source: &str, filename: &str) -> PyResult<Rc<CodeObject>> {
+        let tokens = match tokenize_located(source) {
+            Ok(tokens) => tokens,
+            Err((err, position)) => {
+                // A syntax error before the end of the source comes before a bracket left open.
+                if let TokError::UnclosedParen(_) = err {
+                    if let Some(err) = unclosed_bracket_error(source) {
+                        let (line, column) = (err.span.start.line, err.span.start.col);
+                        let end = (err.span.end.line == line && err.span.end.col > column).then_some(err.span.end.col);
+                        let class = &self.exceptions.syntax_error;
+                        return Err(self.new_syntax_error(class, err.message, filename, source, line, column, end));
+                    }
+                }
+                let class = match err {
+                    TokError::Dedent | TokError::TooDeep => &self.exceptions.indentation_error,
+                    TokError::TabSpace => &self.exceptions.tab_error,
+                    _ => &self.exceptions.syntax_error,
+                };
+                let text = source_line(source, position.line);
+                let column = match err {
+                    TokError::Dedent | TokError::TabSpace => text.trim_end_matches(['\r', '\n']).len(),
+                    _ => position.col,
+                };
+                return Err(self.new_syntax_error(class, err.to_string(), filename, source, position.line, column, None));
+            }
+        };
+        let arena = TokenArena::new(tokens);
+        let mut module = match parse_module(&arena, filename) {
+            Ok(module) => module,
+            Err(err) => {
+                let (message, line, column, end) = match &err {
+                    ParserError::SyntaxError(err) => {
+                        let end = (err.span.end.line == err.span.start.line && err.span.end.col > err.span.start.col)
+                            .then_some(err.span.end.col);
+                        (err.message.clone(), err.span.start.line, err.span.start.col, end)
+                    }
+                    ParserError::ParserError(err, _) => {
+                        ("invalid syntax".to_string(), err.location.start_pos.line, err.location.start_pos.column, None)
+                    }
+                    ParserError::TokenizerError(err, _) => (err.to_string(), 1, 0, None),
+                    ParserError::OperatorError => ("invalid syntax".to_string(), 1, 0, None),
+                };
+                let class = if message.starts_with("expected an indented block")
+                    || message.starts_with("unexpected indent")
+                    || message.starts_with("unindent")
+                {
+                    &self.exceptions.indentation_error
+                } else {
+                    &self.exceptions.syntax_error
+                };
+                return Err(self.new_syntax_error(class, message, filename, source, line, column, end));
+            }
+        };
+        match compile(&mut module, filename, &OptimizerOptions::default()) {
+            Ok(code) => Ok(Rc::new(code)),
+            Err(err) => {
+                let end = (err.span.end.line == err.span.start.line && err.span.end.col > err.span.start.col)
+                    .then_some(err.span.end.col);
+                Err(self.new_syntax_error(
+                    &self.exceptions.syntax_error,
+                    err.message,
+                    filename,
+                    source,
+                    err.span.start.line,
+                    err.span.start.col,
+                    end,
+                ))
+            }
+        }
+    }
+
+    /// Runs a code object in the namespace `globals`.
+    pub fn run_code(&self, code: Rc<CodeObject>, globals: &PyObjectRef) -> PyResult {
+        if self.dict_get_str(globals, "__builtins__").is_none() {
+            self.dict_set_str(globals, "__builtins__", self.builtins.clone());
+        }
+        let mut frame = Frame::new(self, code, globals.clone(), Some(globals.clone()));
+        self.run_frame(&mut frame)
+    }
+
+    /// Runs `source` as the `__main__` module.
+    pub fn run_main(&self, source: &str, filename: &str) -> PyResult {
+        let main = self.new_module("__main__");
+        let globals = main.dict.clone().expect("module dict");
+        self.dict_set_str(&globals, "__file__", self.new_str(filename.to_string()));
+        self.dict_set_str(&globals, "__builtins__", self.builtins.clone());
+        self.dict_set_item(&self.sys_modules, &self.new_str("__main__".to_string()), &main)?;
+        let code = self.compile_source(source, filename)?;
+        self.run_code(code, &globals)
+    }
+
+    /// The exit status for an exception that ended the program, printing it unless it is a
+    /// `SystemExit`.
+    fn exit_status(&self, exc: &PyObjectRef) -> i32 {
+        if !self.isinstance(exc, &self.exceptions.system_exit) {
+            self.print_exception(exc);
+            return 1;
+        }
+        let args = self.exception_args(exc);
+        let code = match args.as_tuple() {
+            Some([code]) => code.clone(),
+            Some([]) | None => return 0,
+            Some(_) => args.clone(),
+        };
+        if self.is_none(&code) {
+            return 0;
+        }
+        if let Some(status) = code.as_int() {
+            return status.to_i64().map(|status| status as i32).unwrap_or(-1);
+        }
+        let _ = self.flush_stdout();
+        let text = self.to_str(&code).unwrap_or_default();
+        let _ = writeln!(std::io::stderr(), "{}", text);
+        1
+    }
+}
+
+impl Default for VirtualMachine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Line `line` of `source`, counting from 1.
+fn source_line(source: &str, line: usize) -> &str {
+    source.lines().nth(line.saturating_sub(1)).unwrap_or("")
+}
+
+/// Runs the script at `path` like `python3 path` and returns the exit status.
+pub fn run_file(path: &str, argv: Vec<String>) -> i32 {
+    let absolute = std::fs::canonicalize(path).unwrap_or_else(|_| Path::new(path).to_path_buf());
+    let source = match std::fs::read(&absolute) {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        Err(err) => {
+            let display = absolute.display();
+            let errno = err.raw_os_error().unwrap_or(2);
+            let reason = match err.kind() {
+                std::io::ErrorKind::NotFound => "No such file or directory".to_string(),
+                std::io::ErrorKind::PermissionDenied => "Permission denied".to_string(),
+                _ => err.to_string(),
+            };
+            eprintln!("rython3: can't open file '{}': [Errno {}] {}", display, errno, reason);
+            return 2;
+        }
+    };
+    let filename = absolute.display().to_string();
+    let directory = absolute.parent().map(|dir| dir.display().to_string()).unwrap_or_default();
+    let run = move || {
+        let vm = VirtualMachine::new();
+        vm.set_argv(argv);
+        vm.set_script_directory(&directory);
+        let status = match vm.run_main(&source, &filename) {
+            Ok(_) => 0,
+            Err(exc) => vm.exit_status(&exc),
+        };
+        if vm.flush_stdout().is_err() {
+            return 120;
+        }
+        status
+    };
+    match std::thread::Builder::new().stack_size(STACK_SIZE).spawn(run) {
+        Ok(handle) => handle.join().unwrap_or(1),
+        Err(_) => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VirtualMachine;
+
+    fn run(source: &str) -> String {
+        let vm = VirtualMachine::capturing();
+        if let Err(exc) = vm.run_main(source, "<test>") {
+            panic!("{}", vm.exception_str(&exc).unwrap_or_default());
+        }
+        vm.take_output()
+    }
+
+    #[test]
+    fn runs_functions_loops_and_classes() {
+        let source = r#"
+class Counter:
+    def __init__(self, start=0):
+        self.value = start
+
+    def bump(self, by=1):
+        self.value += by
+        return self.value
+
+def total(*numbers, scale=1):
+    result = 0
+    for n in numbers:
+        if n < 0:
+            continue
+        if n > 100:
+            break
+        result += n
+    return result * scale
+
+c = Counter(start=5)
+while c.bump(by=2) < 10:
+    pass
+print(c.value, total(1, -2, 3, 500, 7, scale=2))
+try:
+    {}["missing"]
+except KeyError as e:
+    print("caught", repr(e))
+"#;
+        assert_eq!(run(source), "11 8\ncaught KeyError('missing')\n");
+    }
+
+    #[test]
+    fn closures_share_cells() {
+        let source = r#"
+def counter():
+    count = 0
+    def bump():
+        nonlocal count
+        count += 1
+        return count
+    return bump, lambda: count
+
+bump, get = counter()
+bump(); bump()
+cell = get.__closure__[0]
+print(get(), cell.cell_contents, [f() for f in [lambda: i for i in range(3)]])
+cell.cell_contents = 10
+print(bump(), get.__code__.co_freevars, counter.__code__.co_cellvars)
+"#;
+        assert_eq!(run(source), "2 2 [2, 2, 2]\n11 ('count',) ('count',)\n");
+    }
+
+    #[test]
+    fn classes_use_c3_and_super() {
+        let source = r#"
+class A:
+    def f(self): return "A"
+class B(A):
+    def f(self): return "B" + super().f()
+class C(A):
+    def f(self): return "C" + super().f()
+class D(B, C):
+    __slots__ = ("x",)
+    def __init_subclass__(cls, tag=None):
+        print("subclass", cls.__name__, tag)
+    def f(self): return "D" + super().f()
+class E(D, tag=1): pass
+print(D().f(), [k.__name__ for k in E.__mro__])
+try:
+    class X(A, B): pass
+except TypeError as e:
+    print(e)
+"#;
+        assert_eq!(
+            run(source),
+            "subclass E 1\nDBCA ['E', 'D', 'B', 'C', 'A', 'object']\n\
+             Cannot create a consistent method resolution\norder (MRO) for bases A, B\n"
+        );
+    }
+
+    #[test]
+    fn exceptions_chain_and_run_finally() {
+        let source = r#"
+def f():
+    try:
+        return "try"
+    finally:
+        print("finally")
+try:
+    try:
+        1 / 0
+    except ZeroDivisionError as e:
+        raise ValueError("bad") from e
+except ValueError as e:
+    print(f(), type(e.__cause__).__name__, e.__suppress_context__)
+try:
+    try:
+        raise KeyError("k")
+    except KeyError:
+        raise OSError(2, "missing", "x.txt")
+except FileNotFoundError as e:
+    print(e, repr(e.__context__), issubclass(OSError, Exception))
+"#;
+        assert_eq!(
+            run(source),
+            "finally\ntry ZeroDivisionError True\n\
+             [Errno 2] missing: 'x.txt' KeyError('k') True\n"
+        );
+    }
+
+    #[test]
+    fn except_star_splits_groups() {
+        let source = r#"
+eg = ExceptionGroup("eg", [ValueError(1), TypeError(2), ExceptionGroup("inner", [OSError(3), ValueError(4)])])
+try:
+    try:
+        raise eg
+    except* ValueError as e:
+        print(repr(e))
+    except* OSError:
+        raise KeyError("k")
+except ExceptionGroup as e:
+    print(repr(e), repr(e.exceptions[0].__context__))
+print(repr(eg.subgroup(lambda e: isinstance(e, OSError))), type(BaseExceptionGroup("", [ValueError()])).__name__)
+"#;
+        assert_eq!(
+            run(source),
+            "ExceptionGroup('eg', [ValueError(1), ExceptionGroup('inner', [ValueError(4)])])\n\
+             ExceptionGroup('', [KeyError('k'), ExceptionGroup('eg', [TypeError(2)])]) \
+             ExceptionGroup('eg', [ExceptionGroup('inner', [OSError(3)])])\n\
+             ExceptionGroup('eg', [ExceptionGroup('inner', [OSError(3)])]) ExceptionGroup\n"
+        );
+    }
+
+    #[test]
+    fn binds_type_parameters() {
+        let source = r#"
+def first[T](items, default: T = None) -> T:
+    return items[0] if items else default
+(t,) = first.__type_params__
+print(t.__name__, first.__annotations__["default"] is t, first.__annotations__["return"] is t, first([], 7))
+
+def base_for(param):
+    class Base:
+        pass
+    Base.param = param
+    return Base
+class Box[T: int](base_for(T)):
+    def get(self) -> T:
+        return T
+print(Box.param is Box.__type_params__[0], Box().get() is Box.param, Box.param.__bound__, Box.__qualname__)
+
+type Pair[T] = (T, T)
+type Later = Undefined
+Undefined = 1
+print(Pair.__value__[1] is Pair.__type_params__[0], Later.__value__)
+
+class Namespace:
+    Alias = int
+    def m[T](self, x: Alias) -> T:
+        pass
+print(Namespace.m.__annotations__["x"].__name__, Namespace.m.__qualname__)
+"#;
+        assert_eq!(run(source), "T True True 7\nTrue True <class 'int'> Box\nTrue 1\nint Namespace.m\n");
+    }
+
+    #[test]
+    fn binds_keyword_and_default_arguments() {
+        let source = r#"
+def f(a, b=2, /, c=3, *, d, e=5):
+    return (a, b, c, d, e)
+print(f(1, d=4), f(1, 20, c=30, d=40, e=50), f(1, 2, 3, d=4))
+def g(x, y=[]):
+    y.append(x)
+    return len(y)
+print(g(1), g(2), g(3, []))
+calls = [
+    lambda: f(d=1),
+    lambda: f(1, 2, 3, 4, d=5),
+    lambda: f(1, d=1, z=2),
+    lambda: f(1, c=1, d=1, **{"c": 2}),
+    lambda: f(a=1, d=2),
+]
+for call in calls:
+    try:
+        call()
+    except TypeError as e:
+        print(e)
+"#;
+        assert_eq!(
+            run(source),
+            "(1, 2, 3, 4, 5) (1, 20, 30, 40, 50) (1, 2, 3, 4, 5)\n\
+             1 2 1\n\
+             f() missing 1 required positional argument: 'a'\n\
+             f() takes from 1 to 3 positional arguments but 4 positional arguments (and 1 keyword-only argument) were given\n\
+             f() got an unexpected keyword argument 'z'\n\
+             __main__.f() got multiple values for keyword argument 'c'\n\
+             f() got some positional-only arguments passed as keyword arguments: 'a'\n"
+        );
+    }
+
+    #[test]
+    fn unpacks_star_arguments_in_calls() {
+        let source = r#"
+def show(*args, **kwargs):
+    return args, sorted(kwargs.items())
+args = [1, 2]
+kw = {"x": 1}
+print(show(*args, 3, *(4,), **kw, y=2, **{"z": 3}))
+def pair(a, b, *rest, key=None, **extra):
+    return a, b, rest, key, extra
+print(pair(*range(4), key="k", **{"q": 1}))
+print(pair(*"ab"), max(*[3, 9, 4]), list(zip(*[(1, 2), (3, 4)])))
+try:
+    show(**kw, **{"x": 2})
+except TypeError as e:
+    print(e)
+try:
+    pair(*[1])
+except TypeError as e:
+    print(e)
+"#;
+        assert_eq!(
+            run(source),
+            "((1, 2, 3, 4), [('x', 1), ('y', 2), ('z', 3)])\n\
+             (0, 1, (2, 3), 'k', {'q': 1})\n\
+             ('a', 'b', (), None, {}) 9 [(1, 3), (2, 4)]\n\
+             __main__.show() got multiple values for keyword argument 'x'\n\
+             pair() missing 1 required positional argument: 'b'\n"
+        );
+    }
+
+    #[test]
+    fn loops_break_continue_and_run_else() {
+        let source = r#"
+out = []
+for i in range(10):
+    if i % 2:
+        continue
+    if i > 6:
+        break
+    out.append(i)
+else:
+    out.append("no break")
+print(out)
+for i in range(3):
+    pass
+else:
+    print("for else", i)
+n = 0
+while n < 5:
+    n += 1
+    if n == 3:
+        continue
+    if n == 10:
+        break
+else:
+    print("while else", n)
+for a in range(3):
+    for b in range(3):
+        if b == 1:
+            break
+        if a == 1:
+            continue
+        print(a, b)
+    else:
+        print("inner else")
+def search(items, target):
+    for i, item in enumerate(items):
+        try:
+            if item == target:
+                break
+        finally:
+            print("checked", item)
+    else:
+        return -1
+    return i
+print(search("abc", "b"), search("abc", "z"))
+"#;
+        assert_eq!(
+            run(source),
+            "[0, 2, 4, 6]\n\
+             for else 2\n\
+             while else 5\n\
+             0 0\n\
+             2 0\n\
+             checked a\n\
+             checked b\n\
+             checked a\n\
+             checked b\n\
+             checked c\n\
+             1 -1\n"
+        );
+    }
+
+    #[test]
+    fn nested_functions_close_over_their_scopes() {
+        let source = r#"
+def counter():
+    count = 0
+    def inc(by=1):
+        nonlocal count
+        count += by
+        return count
+    def get():
+        return count
+    return inc, get
+inc, get = counter()
+inc(); inc(5)
+print(get(), inc.__qualname__)
+def adders():
+    return [lambda x, n=n: x + n for n in range(3)], [lambda x: x + n for n in range(3)]
+bound, late = adders()
+print([f(10) for f in bound], [f(10) for f in late])
+def outer():
+    x = "outer"
+    def middle():
+        def inner():
+            return x
+        return inner
+    x = "changed"
+    return middle()()
+print(outer())
+def make(depth):
+    if depth == 0:
+        return lambda: "leaf"
+    inner = make(depth - 1)
+    return lambda: "(" + inner() + ")"
+print(make(3)())
+x = "global"
+def shadow():
+    def read():
+        return x
+    return read()
+print(shadow())
+"#;
+        assert_eq!(
+            run(source),
+            "6 counter.<locals>.inc\n\
+             [10, 11, 12] [12, 12, 12]\n\
+             changed\n\
+             (((leaf)))\n\
+             global\n"
+        );
+    }
+
+    #[test]
+    fn errors_propagate_out_of_calls() {
+        let source = r#"
+def inner(n):
+    if n == 0:
+        raise ValueError("bottom")
+    return inner(n - 1)
+def middle():
+    try:
+        inner(3)
+    finally:
+        print("middle cleanup")
+try:
+    middle()
+except ValueError as e:
+    print("caught", e)
+def lookup(d, k):
+    return d[k]
+def safe(d, k):
+    try:
+        return lookup(d, k)
+    except KeyError as e:
+        return "missing " + repr(e.args[0])
+print(safe({"a": 1}, "a"), safe({}, "b"))
+def reraise():
+    try:
+        lookup({}, "k")
+    except KeyError:
+        raise RuntimeError("wrapped")
+try:
+    reraise()
+except RuntimeError as e:
+    print(e, type(e.__context__).__name__)
+def gen():
+    yield 1
+    raise StopIteration
+try:
+    list(gen())
+except RuntimeError as e:
+    print(e)
+class Boom:
+    def __init__(self):
+        raise AttributeError("from init")
+try:
+    Boom()
+except AttributeError as e:
+    print(e)
+"#;
+        assert_eq!(
+            run(source),
+            "middle cleanup\n\
+             caught bottom\n\
+             1 missing 'b'\n\
+             wrapped KeyError\n\
+             generator raised StopIteration\n\
+             from init\n"
+        );
+    }
+}
@@ -0,0 +1,421 @@
+//! The runtime representation of Python objects.
+//!
+//! Every object is an `Rc<PyObject>`: a class, an optional `__dict__` and a `Payload` with
+//! the data of its builtin type.  Instances of user classes get the payload of the builtin
+//! type they derive from, so an instance of a `list` subclass is a `Payload::List` with a
+//! dict.  Objects are never freed while a cycle through them exists, which is fine for a
+//! process that runs one program and exits.
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::rc::Rc;
+
+use crate::bigint::BigInt;
+use crate::compiler::CodeObject;
+
+use super::dict::PyDict;
+use super::frame::Frame;
+use super::string::PyStr;
+use super::VirtualMachine;
+
+pub type PyObjectRef = Rc<PyObject>;
+
+/// A raised exception travels as the `Err` of a `PyResult`.
+pub type PyResult<T = PyObjectRef> = Result<T, PyObjectRef>;
+
+pub type NativeFunction = fn(&VirtualMachine, FuncArgs) -> PyResult;
+
+pub struct PyObject {
+    class: RefCell<Option<PyObjectRef>>,
+    /// The instance `__dict__`, a dict object, for objects that have one.
+    pub dict: Option<PyObjectRef>,
+    /// The values of the `__slots__` of its class and their bases, None while unset.
+    pub slots: Box<[RefCell<Option<PyObjectRef>>]>,
+    pub payload: Payload,
+}
+
+impl PyObject {
+    pub fn new(payload: Payload, class: PyObjectRef, dict: Option<PyObjectRef>) -> PyObjectRef {
+        Self::with_slots(payload, class, dict, 0)
+    }
+
+    /// An object with room for `slots` values of `__slots__` members.
+    pub fn with_slots(payload: Payload, class: PyObjectRef, dict: Option<PyObjectRef>, slots: usize) -> PyObjectRef {
+        let slots = (0..slots).map(|_| RefCell::new(None)).collect();
+        Rc::new(Self { class: RefCell::new(Some(class)), dict, slots, payload })
+    }
+
+    /// An object whose class is set afterwards, for the type objects that are instances of
+    /// themselves.
+    pub(crate) fn new_unclassed(payload: Payload, dict: Option<PyObjectRef>) -> PyObjectRef {
+        Rc::new(Self { class: RefCell::new(None), dict, slots: Box::new([]), payload })
+    }
+
+    pub fn class(&self) -> PyObjectRef {
+        self.class.borrow().clone().expect("object without a class")
+    }
+
+    pub fn set_class(&self, class: PyObjectRef) {
+        *self.class.borrow_mut() = Some(class);
+    }
+
+    pub fn as_type(&self) -> Option<&PyType> {
+        match &self.payload {
+            Payload::Type(t) => Some(t),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match &self.payload {
+            Payload::Str(s) => Some(s.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn as_dict(&self) -> Option<&RefCell<PyDict>> {
+        match &self.payload {
+            Payload::Dict(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    pub fn as_tuple(&self) -> Option<&[PyObjectRef]> {
+        match &self.payload {
+            Payload::Tuple(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_exception(&self) -> Option<&ExceptionState> {
+        match &self.payload {
+            Payload::Exception(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// The value of an `int` or `bool`.
+    pub fn as_int(&self) -> Option<BigInt> {
+        match &self.payload {
+            Payload::Int(i) => Some(i.to_bigint()),
+            Payload::Bool(b) => Some(BigInt::from(*b as i64)),
+            _ => None,
+        }
+    }
+
+    /// The value of an `int` or `bool` that fits in an `i64`, without making a `BigInt`.
+    pub fn as_small_int(&self) -> Option<i64> {
+        match &self.payload {
+            Payload::Int(PyInt::Small(i)) => Some(*i),
+            Payload::Bool(b) => Some(*b as i64),
+            _ => None,
+        }
+    }
+
+    pub fn is(&self, other: &PyObject) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for PyObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{} object>", self.payload.kind_name())
+    }
+}
+
+pub enum Payload {
+    /// An instance of `object` or of a class with no other builtin base.
+    Object,
+    None,
+    NotImplemented,
+    Ellipsis,
+    Bool(bool),
+    Int(PyInt),
+    Float(f64),
+    Complex { real: f64, imag: f64 },
+    Str(PyStr),
+    Bytes(Vec<u8>),
+    Tuple(Vec<PyObjectRef>),
+    List(RefCell<Vec<PyObjectRef>>),
+    Dict(RefCell<PyDict>),
+    /// A set is a dict whose values are all None.
+    Set(RefCell<PyDict>),
+    FrozenSet(PyDict),
+    Range { start: BigInt, stop: BigInt, step: BigInt },
+    Slice { start: PyObjectRef, stop: PyObjectRef, step: PyObjectRef },
+    Iterator(RefCell<IteratorState>),
+    Function(PyFunction),
+    BuiltinFunction(BuiltinFunction),
+    BoundMethod { function: PyObjectRef, receiver: PyObjectRef },
+    StaticMethod(PyObjectRef),
+    ClassMethod(PyObjectRef),
+    Property(PyProperty),
+    /// The descriptor for one name in `__slots__`, kept at `index` in the instance's slots.
+    Member { name: String, owner: PyObjectRef, index: usize },
+    /// `super(this_class, obj)`: looks attributes up in the method resolution order of
+    /// `obj_type` after `this_class`.  `obj` is None for an unbound `super(this_class)`.
+    Super { this_class: PyObjectRef, obj: Option<PyObjectRef>, obj_type: Option<PyObjectRef> },
+    /// What `dict.keys()`, `values()` and `items()` return.
+    DictView { dict: PyObjectRef, kind: DictViewKind },
+    Code(Rc<CodeObject>),
+    Cell(RefCell<Option<PyObjectRef>>),
+    Generator(Generator),
+    /// What `__anext__()` and `asend()` of an async generator return: awaiting it runs the
+    /// generator to its next value, sending `value` in first.
+    AsyncGenAwaitable { generator: PyObjectRef, value: RefCell<Option<PyObjectRef>> },
+    Module { name: String },
+    Type(PyType),
+    Exception(ExceptionState),
+    /// `exc.__traceback__`: traceback entries, innermost first.  The object stands for the
+    /// last, outermost one and its `tb_next` for the rest.
+    Traceback(Vec<TracebackEntry>),
+}
+
+impl Payload {
+    fn kind_name(&self) -> &'static str {
+        match self {
+            Self::Object => "object",
+            Self::None => "NoneType",
+            Self::NotImplemented => "NotImplementedType",
+            Self::Ellipsis => "ellipsis",
+            Self::Bool(_) => "bool",
+            Self::Int(_) => "int",
+            Self::Float(_) => "float",
+            Self::Complex { .. } => "complex",
+            Self::Str(_) => "str",
+            Self::Bytes(_) => "bytes",
+            Self::Tuple(_) => "tuple",
+            Self::List(_) => "list",
+            Self::Dict(_) => "dict",
+            Self::Set(_) => "set",
+            Self::FrozenSet(_) => "frozenset",
+            Self::Range { .. } => "range",
+            Self::Slice { .. } => "slice",
+            Self::Iterator(_) => "iterator",
+            Self::Function(_) => "function",
+            Self::BuiltinFunction(_) => "builtin_function_or_method",
+            Self::BoundMethod { .. } => "method",
+            Self::StaticMethod(_) => "staticmethod",
+            Self::ClassMethod(_) => "classmethod",
+            Self::Property(_) => "property",
+            Self::Member { .. } => "member_descriptor",
+            Self::Super { .. } => "super",
+            Self::DictView { kind, .. } => kind.name(),
+            Self::Code(_) => "code",
+            Self::Cell(_) => "cell",
+            Self::Generator(_) => "generator",
+            Self::AsyncGenAwaitable { .. } => "async_generator_asend",
+            Self::Module { .. } => "module",
+            Self::Type(_) => "type",
+            Self::Exception(_) => "BaseException",
+            Self::Traceback(_) => "traceback",
+        }
+    }
+}
+
+/// Positional and keyword arguments of a call.
+#[derive(Default)]
+pub struct FuncArgs {
+    pub args: Vec<PyObjectRef>,
+    pub kwargs: Vec<(String, PyObjectRef)>,
+}
+
+impl FuncArgs {
+    pub fn new(args: Vec<PyObjectRef>) -> Self {
+        Self { args, kwargs: vec![] }
+    }
+
+    pub fn with_kwargs(args: Vec<PyObjectRef>, kwargs: Vec<(String, PyObjectRef)>) -> Self {
+        Self { args, kwargs }
+    }
+
+    /// Removes and returns the keyword argument `name`.
+    pub fn take_kwarg(&mut self, name: &str) -> Option<PyObjectRef> {
+        let idx = self.kwargs.iter().position(|(key, _)| key == name)?;
+        Some(self.kwargs.remove(idx).1)
+    }
+}
+
+impl From<Vec<PyObjectRef>> for FuncArgs {
+    fn from(args: Vec<PyObjectRef>) -> Self {
+        Self::new(args)
+    }
+}
+
+/// A function defined in Python.
+pub struct PyFunction {
+    pub code: Rc<CodeObject>,
+    pub globals: PyObjectRef,
+    pub name: RefCell<String>,
+    pub qualname: RefCell<String>,
+    /// A tuple of the defaults of the last positional parameters.
+    pub defaults: RefCell<Option<PyObjectRef>>,
+    /// A dict of the defaults of keyword-only parameters.
+    pub kwdefaults: RefCell<Option<PyObjectRef>>,
+    /// A tuple of cells for the code's free variables.
+    pub closure: Option<PyObjectRef>,
+    pub annotations: RefCell<Option<PyObjectRef>>,
+    pub doc: RefCell<PyObjectRef>,
+    pub module: RefCell<PyObjectRef>,
+}
+
+/// A function implemented in Rust.  In a type's dict it is a method, which gets the
+/// instance as its first argument.
+/// The value of an `int`: an `i64` while it fits, so that ordinary arithmetic does not
+/// allocate, and a `BigInt` only once it outgrows one.  `from_bigint` keeps the two apart,
+/// so a `Big` is never in `i64` range.
+#[derive(Clone, Debug)]
+pub enum PyInt {
+    Small(i64),
+    Big(BigInt),
+}
+
+impl PyInt {
+    pub fn from_bigint(value: BigInt) -> Self {
+        match value.to_i64() {
+            Some(small) => Self::Small(small),
+            None => Self::Big(value),
+        }
+    }
+
+    pub fn to_bigint(&self) -> BigInt {
+        match self {
+            Self::Small(i) => BigInt::from(*i),
+            Self::Big(i) => i.clone(),
+        }
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        match self {
+            Self::Small(i) => Some(*i),
+            Self::Big(_) => None,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        matches!(self, Self::Small(0))
+    }
+}
+
+pub struct BuiltinFunction {
+    pub name: &'static str,
+    pub function: NativeFunction,
+}
+
+/// A `property`: a data descriptor that calls its functions to get, set and delete the
+/// attribute.
+pub struct PyProperty {
+    /// The attribute name, from `__set_name__`, for error messages.
+    pub name: RefCell<Option<String>>,
+    pub getter: Option<PyObjectRef>,
+    pub setter: Option<PyObjectRef>,
+    pub deleter: Option<PyObjectRef>,
+    pub doc: PyObjectRef,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DictViewKind {
+    Keys,
+    Values,
+    Items,
+}
+
+impl DictViewKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Keys => "dict_keys",
+            Self::Values => "dict_values",
+            Self::Items => "dict_items",
+        }
+    }
+}
+
+pub struct PyType {
+    pub name: RefCell<String>,
+    pub qualname: RefCell<String>,
+    pub bases: RefCell<Vec<PyObjectRef>>,
+    /// The method resolution order, starting with the type itself.  Weak would be more
+    /// correct for the first entry, but types live as long as the program.
+    pub mro: RefCell<Vec<PyObjectRef>>,
+    /// Creates instances: the builtin `__new__` of the nearest builtin base.
+    pub new: Option<NativeFunction>,
+    /// Defined by a class statement rather than built in.
+    pub heap: bool,
+    /// Instances have a `__dict__`.
+    pub instance_dict: bool,
+    /// How many `__slots__` values instances carry, counting those of the bases.
+    pub slots: usize,
+}
+
+impl PyType {
+    pub fn name(&self) -> String {
+        self.name.borrow().clone()
+    }
+}
+
+/// One frame an exception passed through on its way out, innermost first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TracebackEntry {
+    pub filename: String,
+    pub line: usize,
+    pub name: String,
+}
+
+pub struct ExceptionState {
+    pub args: RefCell<PyObjectRef>,
+    /// Where it was raised first, then each frame it passed through.
+    pub traceback: RefCell<Vec<TracebackEntry>>,
+    /// `__cause__`, set by `raise ... from`.
+    pub cause: RefCell<Option<PyObjectRef>>,
+    /// `__context__`: the exception that was being handled when this one was raised.
+    pub context: RefCell<Option<PyObjectRef>>,
+    pub suppress_context: Cell<bool>,
+    /// `message` and the tuple `exceptions` of an exception group, fixed when it is created.
+    pub group: Option<(PyObjectRef, PyObjectRef)>,
+}
+
+/// What an iterator object walks over.
+pub enum IteratorState {
+    /// A list, tuple, str or bytes, by index.
+    Sequence { seq: PyObjectRef, index: usize },
+    Range { next: BigInt, stop: BigInt, step: BigInt },
+    /// Keys, values or items of a dict, or the items of a set, with the size it had when
+    /// iteration started.  `reversed` walks back from the end, with `index` just past the
+    /// next entry.
+    Dict { dict: PyObjectRef, index: usize, len: usize, kind: DictViewKind, reversed: bool },
+    Enumerate { iter: PyObjectRef, count: BigInt },
+    Zip { iters: Vec<PyObjectRef> },
+    Map { function: PyObjectRef, iters: Vec<PyObjectRef> },
+    Filter { function: PyObjectRef, iter: PyObjectRef },
+    Reversed { seq: PyObjectRef, index: usize },
+    /// `iter(callable, sentinel)`.
+    Callable { function: PyObjectRef, sentinel: PyObjectRef },
+    Exhausted,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GeneratorKind {
+    Generator,
+    Coroutine,
+    AsyncGenerator,
+}
+
+impl GeneratorKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Generator => "generator",
+            Self::Coroutine => "coroutine",
+            Self::AsyncGenerator => "async_generator",
+        }
+    }
+}
+
+pub struct Generator {
+    pub kind: GeneratorKind,
+    /// Taken out while the generator runs and dropped once it finishes.
+    pub frame: RefCell<Option<Box<Frame>>>,
+    pub running: Cell<bool>,
+    pub started: Cell<bool>,
+    pub name: String,
+    pub qualname: String,
+}
@@ -0,0 +1,1842 @@
+//! Operators and the container protocols.
+//!
+//! Builtin types are handled directly on their payloads.  Instances of classes defined in
+//! Python get their special methods (`__add__`, `__eq__`, `__getitem__` and so on) first,
+//! with the reflected method of the right operand tried first when its class is a subclass
+//! of the left operand's, as in CPython.
+
+use std::cell::RefCell;
+use std::cmp::Ordering;
+
+use crate::bigint::BigInt;
+use crate::compiler::bytecode::{BinaryOperator, ComparisonOperator, UnaryOperator};
+
+use super::dict::PyDict;
+use super::object::{DictViewKind, FuncArgs, Payload, PyInt, PyObjectRef, PyResult};
+use super::VirtualMachine;
+
+/// The modulus of CPython's numeric hash, `2**61 - 1`, which makes equal ints, floats and
+/// complex numbers hash alike.
+const HASH_MODULUS: u64 = (1 << 61) - 1;
+
+/// The largest int `**` and `<<` will make, in bits, beyond which they raise instead of
+/// trying to allocate it.
+const MAX_INT_BITS: u64 = 1 << 36;
+const HASH_INF: i64 = 314159;
+
+fn operator_symbol(op: BinaryOperator) -> &'static str {
+    match op {
+        BinaryOperator::Add => "+",
+        BinaryOperator::Subtract => "-",
+        BinaryOperator::Multiply => "*",
+        BinaryOperator::MatrixMultiply => "@",
+        BinaryOperator::TrueDivide => "/",
+        BinaryOperator::FloorDivide => "//",
+        BinaryOperator::Remainder => "%",
+        BinaryOperator::Power => "** or pow()",
+        BinaryOperator::LeftShift => "<<",
+        BinaryOperator::RightShift => ">>",
+        BinaryOperator::And => "&",
+        BinaryOperator::Or => "|",
+        BinaryOperator::Xor => "^",
+    }
+}
+
+/// The special method of an operator, and the reflected one.
+fn operator_methods(op: BinaryOperator) -> (&'static str, &'static str, &'static str) {
+    match op {
+        BinaryOperator::Add => ("__add__", "__radd__", "__iadd__"),
+        BinaryOperator::Subtract => ("__sub__", "__rsub__", "__isub__"),
+        BinaryOperator::Multiply => ("__mul__", "__rmul__", "__imul__"),
+        BinaryOperator::MatrixMultiply => ("__matmul__", "__rmatmul__", "__imatmul__"),
+        BinaryOperator::TrueDivide => ("__truediv__", "__rtruediv__", "__itruediv__"),
+        BinaryOperator::FloorDivide => ("__floordiv__", "__rfloordiv__", "__ifloordiv__"),
+        BinaryOperator::Remainder => ("__mod__", "__rmod__", "__imod__"),
+        BinaryOperator::Power => ("__pow__", "__rpow__", "__ipow__"),
+        BinaryOperator::LeftShift => ("__lshift__", "__rlshift__", "__ilshift__"),
+        BinaryOperator::RightShift => ("__rshift__", "__rrshift__", "__irshift__"),
+        BinaryOperator::And => ("__and__", "__rand__", "__iand__"),
+        BinaryOperator::Or => ("__or__", "__ror__", "__ior__"),
+        BinaryOperator::Xor => ("__xor__", "__rxor__", "__ixor__"),
+    }
+}
+
+pub(crate) fn comparison_symbol(op: ComparisonOperator) -> &'static str {
+    match op {
+        ComparisonOperator::Less => "<",
+        ComparisonOperator::LessEqual => "<=",
+        ComparisonOperator::Equal => "==",
+        ComparisonOperator::NotEqual => "!=",
+        ComparisonOperator::Greater => ">",
+        ComparisonOperator::GreaterEqual => ">=",
+    }
+}
+
+/// The special method of a comparison and the one to try on the right operand.
+fn comparison_methods(op: ComparisonOperator) -> (&'static str, &'static str) {
+    match op {
+        ComparisonOperator::Less => ("__lt__", "__gt__"),
+        ComparisonOperator::LessEqual => ("__le__", "__ge__"),
+        ComparisonOperator::Equal => ("__eq__", "__eq__"),
+        ComparisonOperator::NotEqual => ("__ne__", "__ne__"),
+        ComparisonOperator::Greater => ("__gt__", "__lt__"),
+        ComparisonOperator::GreaterEqual => ("__ge__", "__le__"),
+    }
+}
+
+fn ordering_matches(op: ComparisonOperator, ordering: Ordering) -> bool {
+    match op {
+        ComparisonOperator::Less => ordering == Ordering::Less,
+        ComparisonOperator::LessEqual => ordering != Ordering::Greater,
+        ComparisonOperator::Equal => ordering == Ordering::Equal,
+        ComparisonOperator::NotEqual => ordering != Ordering::Equal,
+        ComparisonOperator::Greater => ordering == Ordering::Greater,
+        ComparisonOperator::GreaterEqual => ordering != Ordering::Less,
+    }
+}
+
+/// A number operand, with bools as ints.
+enum Number {
+    Int(BigInt),
+    Float(f64),
+    Complex(f64, f64),
+}
+
+/// Keys and items views, which support the set operators.
+fn is_set_view(obj: &PyObjectRef) -> bool {
+    matches!(obj.payload, Payload::DictView { kind: DictViewKind::Keys | DictViewKind::Items, .. })
+}
+
+fn is_set_like(obj: &PyObjectRef) -> bool {
+    matches!(obj.payload, Payload::Set(_) | Payload::FrozenSet(_)) || is_set_view(obj)
+}
+
+fn number_of(obj: &PyObjectRef) -> Option<Number> {
+    match &obj.payload {
+        Payload::Bool(b) => Some(Number::Int(BigInt::from(*b as i64))),
+        Payload::Int(i) => Some(Number::Int(i.to_bigint())),
+        Payload::Float(f) => Some(Number::Float(*f)),
+        Payload::Complex { real, imag } => Some(Number::Complex(*real, *imag)),
+        _ => None,
+    }
+}
+
+/// `divmod` of two floats with Python's sign rules, as in CPython's `float_divmod`.
+pub(crate) fn float_divmod(x: f64, y: f64) -> (f64, f64) {
+    let mut rem = x % y;
+    let mut div = (x - rem) / y;
+    if rem != 0.0 {
+        if (y < 0.0) != (rem < 0.0) {
+            rem += y;
+            div -= 1.0;
+        }
+    } else {
+        rem = 0f64.copysign(y);
+    }
+    let floordiv = if div != 0.0 {
+        let mut floordiv = div.floor();
+        if div - floordiv > 0.5 {
+            floordiv += 1.0;
+        }
+        floordiv
+    } else {
+        0f64.copysign(x / y)
+    };
+    (floordiv, rem)
+}
+
+fn floor_div_i64(x: i64, y: i64) -> Option<i64> {
+    let q = x.checked_div(y)?;
+    if x % y != 0 && ((x < 0) != (y < 0)) {
+        Some(q - 1)
+    } else {
+        Some(q)
+    }
+}
+
+fn mod_i64(x: i64, y: i64) -> i64 {
+    let r = x.wrapping_rem(y);
+    if r != 0 && ((r < 0) != (y < 0)) {
+        r + y
+    } else {
+        r
+    }
+}
+
+/// Where `index` falls in a sequence of `len` items, counting negative indexes from the
+/// end.
+pub(crate) fn normalize_index(index: &BigInt, len: usize) -> Option<usize> {
+    let index = index.to_i64()?;
+    let index = if index < 0 { index + len as i64 } else { index };
+    if index < 0 || index >= len as i64 {
+        None
+    } else {
+        Some(index as usize)
+    }
+}
+
+impl VirtualMachine {
+    pub fn is_true(&self, obj: &PyObjectRef) -> PyResult<bool> {
+        match &obj.payload {
+            Payload::None => return Ok(false),
+            Payload::Bool(b) => return Ok(*b),
+            _ => {}
+        }
+        if let Some(result) = self.call_special(obj, "__bool__", vec![])? {
+            return match &result.payload {
+                Payload::Bool(b) => Ok(*b),
+                _ => Err(self.new_type_error(format!(
+                    "__bool__ should return bool, returned {}",
+                    self.class_name(&result)
+                ))),
+            };
+        }
+        if self.has_special(obj, "__len__") {
+            return Ok(self.len(obj)? > 0);
+        }
+        Ok(self.native_bool(obj))
+    }
+
+    /// The truth of a builtin value, which `bool.__bool__` and the like also give.
+    pub(super) fn native_bool(&self, obj: &PyObjectRef) -> bool {
+        match &obj.payload {
+            Payload::None => false,
+            Payload::Bool(b) => *b,
+            Payload::Int(i) => !i.is_zero(),
+            Payload::Float(f) => *f != 0.0,
+            Payload::Complex { real, imag } => *real != 0.0 || *imag != 0.0,
+            Payload::Str(s) => !s.is_empty(),
+            Payload::Bytes(b) => !b.is_empty(),
+            Payload::Tuple(items) => !items.is_empty(),
+            Payload::List(items) => !items.borrow().is_empty(),
+            Payload::Dict(d) | Payload::Set(d) => !d.borrow().is_empty(),
+            Payload::FrozenSet(d) => !d.is_empty(),
+            Payload::Range { .. } => self.range_len(obj) > 0,
+            _ => true,
+        }
+    }
+
+    pub fn not(&self, obj: &PyObjectRef) -> PyResult<bool> {
+        Ok(!self.is_true(obj)?)
+    }
+
+    // Hashing
+
+    pub fn hash(&self, obj: &PyObjectRef) -> PyResult<i64> {
+        if let Some(method) = self.special_method(obj, "__hash__") {
+            if self.is_none(&method) {
+                return Err(self.new_type_error(format!("unhashable type: '{}'", self.class_name(obj))));
+            }
+            let result = self.call_bound(&method, obj, vec![])?;
+            return match result.as_int() {
+                Some(value) => Ok(hash_int(&value)),
+                None => Err(self.new_type_error("__hash__ method should return an integer".to_string())),
+            };
+        }
+        self.native_hash(obj)
+    }
+
+    /// The hash of a builtin value, or of any other object by identity.
+    pub(super) fn native_hash(&self, obj: &PyObjectRef) -> PyResult<i64> {
+        Ok(match &obj.payload {
+            Payload::Bool(b) => *b as i64,
+            Payload::Int(PyInt::Small(i)) => hash_small_int(*i),
+            Payload::Int(PyInt::Big(i)) => hash_int(i),
+            Payload::Float(f) => hash_float(*f, obj),
+            Payload::Complex { real, imag } => {
+                let combined = hash_float(*real, obj).wrapping_add(hash_float(*imag, obj).wrapping_mul(1000003));
+                if combined == -1 {
+                    -2
+                } else {
+                    combined
+                }
+            }
+            Payload::Str(s) => hash_bytes(s.as_bytes()),
+            Payload::Bytes(b) => hash_bytes(b),
+            Payload::Tuple(items) => {
+                let hashes = items.iter().map(|item| self.hash(item)).collect::<PyResult<Vec<_>>>()?;
+                hash_tuple(&hashes)
+            }
+            Payload::FrozenSet(table) => hash_frozenset(table),
+            Payload::List(_) | Payload::Dict(_) | Payload::Set(_) | Payload::Slice { .. } => {
+                return Err(self.new_type_error(format!("unhashable type: '{}'", self.class_name(obj))))
+            }
+            Payload::Range { start, step, .. } => {
+                // Equal ranges hash alike: those with no items, or one, differ in
+                // parameters that do not matter.
+                let len = self.range_len(obj);
+                let parts = [BigInt::from(len as i64), start.clone(), step.clone()];
+                let hashes: Vec<i64> = match len {
+                    0 => vec![hash_int(&parts[0]), 0, 0],
+                    1 => vec![hash_int(&parts[0]), hash_int(start), 0],
+                    _ => parts.iter().map(hash_int).collect(),
+                };
+                hash_tuple(&hashes)
+            }
+            _ => identity_hash(obj),
+        })
+    }
+
+    // Comparisons
+
+    /// `a == b` as a bool.
+    pub fn eq(&self, a: &PyObjectRef, b: &PyObjectRef) -> PyResult<bool> {
+        let result = self.rich_compare(a, b, ComparisonOperator::Equal)?;
+        self.is_true(&result)
+    }
+
+    /// Equality as containers use it: the same object is always equal to itself.
+    pub fn same_or_eq(&self, a: &PyObjectRef, b: &PyObjectRef) -> PyResult<bool> {
+        if a.is(b) {
+            return Ok(true);
+        }
+        self.eq(a, b)
+    }
+
+    pub fn compare_bool(&self, a: &PyObjectRef, b: &PyObjectRef, op: ComparisonOperator) -> PyResult<bool> {
+        let result = self.rich_compare(a, b, op)?;
+        self.is_true(&result)
+    }
+
+    pub fn rich_compare(&self, a: &PyObjectRef, b: &PyObjectRef, op: ComparisonOperator) -> PyResult {
+        if let (Some(x), Some(y)) = (self.exact_small_int(a), self.exact_small_int(b)) {
+            return Ok(self.new_bool(ordering_matches(op, x.cmp(&y))));
+        }
+        let (method, reflected) = comparison_methods(op);
+        let a_class = a.class();
+        let b_class = b.class();
+        let reflected_first = !a_class.is(&b_class) && self.is_subclass(&b_class, &a_class);
+        if reflected_first {
+            if let Some(result) = self.try_special(b, reflected, a)? {
+                return Ok(result);
+            }
+        }
+        if let Some(result) = self.try_special(a, method, b)? {
+            return Ok(result);
+        }
+        if !reflected_first {
+            if let Some(result) = self.try_special(b, reflected, a)? {
+                return Ok(result);
+            }
+        }
+        if op == ComparisonOperator::NotEqual && self.has_special(a, "__eq__") {
+            if let Some(result) = self.try_special(a, "__eq__", b)? {
+                return Ok(self.new_bool(!self.is_true(&result)?));
+            }
+        }
+        if let Some(result) = self.native_compare(a, b, op)? {
+            return Ok(self.new_bool(result));
+        }
+        match op {
+            ComparisonOperator::Equal => Ok(self.new_bool(a.is(b))),
+            ComparisonOperator::NotEqual => Ok(self.new_bool(!a.is(b))),
+            _ => Err(self.new_type_error(format!(
+                "'{}' not supported between instances of '{}' and '{}'",
+                comparison_symbol(op),
+                self.class_name(a),
+                self.class_name(b)
+            ))),
+        }
+    }
+
+    /// Compares builtin values, or returns None if the types do not compare.
+    pub(super) fn native_compare(&self, a: &PyObjectRef, b: &PyObjectRef, op: ComparisonOperator) -> PyResult<Option<bool>> {
+        let is_eq = matches!(op, ComparisonOperator::Equal | ComparisonOperator::NotEqual);
+        if let (Some(x), Some(y)) = (number_of(a), number_of(b)) {
+            let ordering = match (x, y) {
+                (Number::Int(x), Number::Int(y)) => Some(x.cmp(&y)),
+                (Number::Int(x), Number::Float(y)) => compare_int_float(&x, y),
+                (Number::Float(x), Number::Int(y)) => compare_int_float(&y, x).map(Ordering::reverse),
+                (Number::Float(x), Number::Float(y)) => x.partial_cmp(&y),
+                (x, y) => {
+                    if !is_eq {
+                        return Ok(None);
+                    }
+                    let (xr, xi) = self.complex_parts(x);
+                    let (yr, yi) = self.complex_parts(y);
+                    let equal = xr == yr && xi == yi;
+                    return Ok(Some(equal == (op == ComparisonOperator::Equal)));
+                }
+            };
+            // NaN compares false with everything, except that it is `!=` to everything.
+            return Ok(Some(match ordering {
+                Some(ordering) => ordering_matches(op, ordering),
+                None => op == ComparisonOperator::NotEqual,
+            }));
+        }
+        let ordering = match (&a.payload, &b.payload) {
+            (Payload::None, Payload::None) if is_eq => Ordering::Equal,
+            (Payload::Str(x), Payload::Str(y)) => x.as_str().cmp(y.as_str()),
+            (Payload::Bytes(x), Payload::Bytes(y)) => x.cmp(y),
+            (Payload::Tuple(x), Payload::Tuple(y)) => return self.compare_sequences(x, y, op).map(Some),
+            (Payload::List(x), Payload::List(y)) => {
+                let (x, y) = (x.borrow().clone(), y.borrow().clone());
+                return self.compare_sequences(&x, &y, op).map(Some);
+            }
+            (Payload::Dict(x), Payload::Dict(y)) if is_eq => {
+                let equal = self.dicts_equal(&x.borrow().clone(), &y.borrow().clone())?;
+                return Ok(Some(equal == (op == ComparisonOperator::Equal)));
+            }
+            (Payload::Set(_) | Payload::FrozenSet(_), Payload::Set(_) | Payload::FrozenSet(_)) => {
+                return self.compare_sets(a, b, op).map(Some)
+            }
+            _ if (is_set_view(a) || is_set_view(b)) && is_set_like(a) && is_set_like(b) => {
+                return self.compare_sets(&self.view_set(a)?, &self.view_set(b)?, op).map(Some)
+            }
+            (Payload::Range { .. }, Payload::Range { .. }) if is_eq => {
+                let equal = self.ranges_equal(a, b);
+                return Ok(Some(equal == (op == ComparisonOperator::Equal)));
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(ordering_matches(op, ordering)))
+    }
+
+    fn complex_parts(&self, number: Number) -> (f64, f64) {
+        match number {
+            Number::Int(i) => (i.to_f64().unwrap_or(f64::INFINITY), 0.0),
+            Number::Float(f) => (f, 0.0),
+            Number::Complex(real, imag) => (real, imag),
+        }
+    }
+
+    /// Lexicographic comparison: the first items that differ decide, then the lengths.
+    fn compare_sequences(&self, x: &[PyObjectRef], y: &[PyObjectRef], op: ComparisonOperator) -> PyResult<bool> {
+        if x.len() != y.len() && matches!(op, ComparisonOperator::Equal | ComparisonOperator::NotEqual) {
+            return Ok(op == ComparisonOperator::NotEqual);
+        }
+        for (a, b) in x.iter().zip(y.iter()) {
+            if !self.same_or_eq(a, b)? {
+                return match op {
+                    ComparisonOperator::Equal => Ok(false),
+                    ComparisonOperator::NotEqual => Ok(true),
+                    _ => self.compare_bool(a, b, op),
+                };
+            }
+        }
+        Ok(ordering_matches(op, x.len().cmp(&y.len())))
+    }
+
+    fn dicts_equal(&self, x: &PyDict, y: &PyDict) -> PyResult<bool> {
+        if x.len() != y.len() {
+            return Ok(false);
+        }
+        for entry in x.iter() {
+            match self.table_lookup(y, entry.hash, &entry.key)? {
+                Some(pos) => {
+                    let other = y.entry(pos).expect("entry").value.clone();
+                    if !self.same_or_eq(&entry.value, &other)? {
+                        return Ok(false);
+                    }
+                }
+                None => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Set comparisons are subset tests.
+    fn compare_sets(&self, a: &PyObjectRef, b: &PyObjectRef, op: ComparisonOperator) -> PyResult<bool> {
+        let x = self.set_table(a).expect("set");
+        let y = self.set_table(b).expect("set");
+        let subset = |small: &PyDict, big: &PyDict| -> PyResult<bool> {
+            if small.len() > big.len() {
+                return Ok(false);
+            }
+            for entry in small.iter() {
+                if self.table_lookup(big, entry.hash, &entry.key)?.is_none() {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        };
+        Ok(match op {
+            ComparisonOperator::Equal => x.len() == y.len() && subset(&x, &y)?,
+            ComparisonOperator::NotEqual => !(x.len() == y.len() && subset(&x, &y)?),
+            ComparisonOperator::LessEqual => subset(&x, &y)?,
+            ComparisonOperator::Less => x.len() < y.len() && subset(&x, &y)?,
+            ComparisonOperator::GreaterEqual => subset(&y, &x)?,
+            ComparisonOperator::Greater => y.len() < x.len() && subset(&y, &x)?,
+        })
+    }
+
+    fn ranges_equal(&self, a: &PyObjectRef, b: &PyObjectRef) -> bool {
+        let (Payload::Range { start: s1, step: t1, .. }, Payload::Range { start: s2, step: t2, .. }) =
+            (&a.payload, &b.payload)
+        else {
+            return false;
+        };
+        let len = self.range_len(a);
+        len == self.range_len(b) && (len == 0 || (s1 == s2 && (len == 1 || t1 == t2)))
+    }
+
+    // Arithmetic
+
+    pub fn binary_op(&self, a: &PyObjectRef, b: &PyObjectRef, op: BinaryOperator) -> PyResult {
+        if let (Some(x), Some(y)) = (self.exact_small_int(a), self.exact_small_int(b)) {
+            if let Some(result) = self.small_int_binary(x, y, op)? {
+                return Ok(result);
+            }
+        }
+        let (method, reflected, _) = operator_methods(op);
+        let a_class = a.class();
+        let b_class = b.class();
+        let reflected_first = !a_class.is(&b_class) && self.is_subclass(&b_class, &a_class);
+        if reflected_first {
+            if let Some(result) = self.try_special(b, reflected, a)? {
+                return Ok(result);
+            }
+        }
+        if let Some(result) = self.try_special(a, method, b)? {
+            return Ok(result);
+        }
+        if !reflected_first && !a_class.is(&b_class) {
+            if let Some(result) = self.try_special(b, reflected, a)? {
+                return Ok(result);
+            }
+        }
+        if let Some(result) = self.native_binary(a, b, op)? {
+            return Ok(result);
+        }
+        let symbol = operator_symbol(op);
+        Err(self.new_type_error(format!(
+            "unsupported operand type(s) for {}: '{}' and '{}'",
+            symbol,
+            self.class_name(a),
+            self.class_name(b)
+        )))
+    }
+
+    /// `a op= b`: the in-place method if there is one, mutating lists, sets and dicts, and
+    /// the plain operator otherwise.
+    pub fn inplace_op(&self, a: &PyObjectRef, b: &PyObjectRef, op: BinaryOperator) -> PyResult {
+        if let (Some(x), Some(y)) = (self.exact_small_int(a), self.exact_small_int(b)) {
+            if let Some(result) = self.small_int_binary(x, y, op)? {
+                return Ok(result);
+            }
+        }
+        let (_, _, inplace) = operator_methods(op);
+        if let Some(result) = self.try_special(a, inplace, b)? {
+            return Ok(result);
+        }
+        if self.special_method(a, inplace).is_none() {
+            if let Some(result) = self.native_inplace(a, b, op)? {
+                return Ok(result);
+            }
+        }
+        self.binary_op(a, b, op)
+    }
+
+    /// Updates a list, set or dict in place, or returns None for the types that make a
+    /// new object instead.
+    pub(super) fn native_inplace(&self, a: &PyObjectRef, b: &PyObjectRef, op: BinaryOperator) -> PyResult<Option<PyObjectRef>> {
+        match (&a.payload, op) {
+            (Payload::List(items), BinaryOperator::Add) => {
+                let extra = self.collect(b)?;
+                items.borrow_mut().extend(extra);
+            }
+            (Payload::List(items), BinaryOperator::Multiply) => {
+                let Some(count) = self.repeat_count(b)? else { return Ok(None) };
+                let repeated = repeat(&items.borrow(), count);
+                *items.borrow_mut() = repeated;
+            }
+            (Payload::Set(_), BinaryOperator::Or | BinaryOperator::And | BinaryOperator::Xor | BinaryOperator::Subtract)
+                if self.set_table(b).is_some() =>
+            {
+                let result = self.native_binary(a, b, op)?.expect("set operation");
+                let table = self.set_table(&result).expect("set");
+                if let Payload::Set(target) = &a.payload {
+                    *target.borrow_mut() = table;
+                }
+            }
+            (Payload::Dict(_), BinaryOperator::Or) => self.dict_update(a, b)?,
+            _ => return Ok(None),
+        }
+        Ok(Some(a.clone()))
+    }
+
+    fn repeat_count(&self, count: &PyObjectRef) -> PyResult<Option<usize>> {
+        match count.as_int() {
+            Some(n) => match n.to_i64() {
+                Some(n) => Ok(Some(n.max(0) as usize)),
+                None if n.is_negative() => Ok(Some(0)),
+                None => Err(self.new_overflow_error("cannot fit 'int' into an index-sized integer".to_string())),
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub(super) fn native_binary(&self, a: &PyObjectRef, b: &PyObjectRef, op: BinaryOperator) -> PyResult<Option<PyObjectRef>> {
+        if let (Some(x), Some(y)) = (number_of(a), number_of(b)) {
+            if let (Payload::Bool(x), Payload::Bool(y)) = (&a.payload, &b.payload) {
+                match op {
+                    BinaryOperator::And => return Ok(Some(self.new_bool(*x & *y))),
+                    BinaryOperator::Or => return Ok(Some(self.new_bool(*x | *y))),
+                    BinaryOperator::Xor => return Ok(Some(self.new_bool(*x ^ *y))),
+                    _ => {}
+                }
+            }
+            return self.number_binary(x, y, op);
+        }
+        if op == BinaryOperator::Remainder {
+            match &a.payload {
+                Payload::Str(format) => return self.percent_format(format, b).map(|s| Some(self.new_str(s))),
+                Payload::Bytes(format) => return self.percent_format_bytes(format, b).map(|s| Some(self.new_bytes(s))),
+                _ => {}
+            }
+        }
+        let set_op = matches!(op, BinaryOperator::Or | BinaryOperator::And | BinaryOperator::Xor | BinaryOperator::Subtract);
+        if set_op && (is_set_view(a) || is_set_view(b)) {
+            // Keys and items views combine with any iterable, and always make a set.
+            return self.set_operation(&self.view_set(a)?, &self.view_set(b)?, op).map(Some);
+        }
+        let result = match (op, &a.payload, &b.payload) {
+            (BinaryOperator::Add, Payload::Str(x), Payload::Str(y)) => self.new_str(format!("{}{}", x, y)),
+            (BinaryOperator::Add, Payload::Bytes(x), Payload::Bytes(y)) => self.new_bytes([x.as_slice(), y].concat()),
+            (BinaryOperator::Add, Payload::Tuple(x), Payload::Tuple(y)) => self.new_tuple([x.as_slice(), y].concat()),
+            (BinaryOperator::Add, Payload::List(x), Payload::List(y)) => {
+                let items = [x.borrow().as_slice(), y.borrow().as_slice()].concat();
+                self.new_list(items)
+            }
+            (BinaryOperator::Multiply, Payload::Str(_) | Payload::Bytes(_) | Payload::Tuple(_) | Payload::List(_), _) => {
+                return self.sequence_repeat(a, b)
+            }
+            (BinaryOperator::Multiply, _, Payload::Str(_) | Payload::Bytes(_) | Payload::Tuple(_) | Payload::List(_))
+                if number_of(a).is_some() =>
+            {
+                return self.sequence_repeat(b, a)
+            }
+            (BinaryOperator::Or, Payload::Dict(x), Payload::Dict(_)) => {
+                let merged = self.new_dict();
+                *merged.as_dict().expect("dict").borrow_mut() = x.borrow().clone();
+                self.dict_update(&merged, b)?;
+                merged
+            }
+            (
+                BinaryOperator::Or | BinaryOperator::And | BinaryOperator::Xor | BinaryOperator::Subtract,
+                Payload::Set(_) | Payload::FrozenSet(_),
+                Payload::Set(_) | Payload::FrozenSet(_),
+            ) => self.set_operation(a, b, op)?,
+            _ => return Ok(None),
+        };
+        Ok(Some(result))
+    }
+
+    fn sequence_repeat(&self, seq: &PyObjectRef, count: &PyObjectRef) -> PyResult<Option<PyObjectRef>> {
+        let Some(count) = self.repeat_count(count)? else {
+            if number_of(count).is_some() || matches!(count.payload, Payload::Str(_) | Payload::Bytes(_) | Payload::List(_) | Payload::Tuple(_)) {
+                return Err(self.new_type_error(format!(
+                    "can't multiply sequence by non-int of type '{}'",
+                    self.class_name(count)
+                )));
+            }
+            return Ok(None);
+        };
+        Ok(Some(match &seq.payload {
+            Payload::Str(s) => self.new_str(s.repeat(count)),
+            Payload::Bytes(b) => self.new_bytes(b.repeat(count)),
+            Payload::Tuple(items) => self.new_tuple(repeat(items, count)),
+            Payload::List(items) => self.new_list(repeat(&items.borrow(), count)),
+            _ => return Ok(None),
+        }))
+    }
+
+    /// A new set of the items of an iterable, for the set operations of dict views.
+    fn view_set(&self, items: &PyObjectRef) -> PyResult {
+        let table = self.hash_table(&self.collect(items)?)?;
+        Ok(self.new_object(Payload::Set(RefCell::new(table)), &self.types.set_type))
+    }
+
+    pub(crate) fn set_operation(&self, a: &PyObjectRef, b: &PyObjectRef, op: BinaryOperator) -> PyResult {
+        let x = self.set_table(a).expect("set");
+        let y = self.set_table(b).expect("set");
+        let mut result = PyDict::new();
+        let none = self.none();
+        let mut add = |entry: &super::dict::DictEntry| result.push(entry.hash, entry.key.clone(), none.clone());
+        match op {
+            BinaryOperator::Or => {
+                for entry in x.iter() {
+                    add(entry);
+                }
+                for entry in y.iter() {
+                    if self.table_lookup(&x, entry.hash, &entry.key)?.is_none() {
+                        add(entry);
+                    }
+                }
+            }
+            BinaryOperator::And => {
+                for entry in x.iter() {
+                    if self.table_lookup(&y, entry.hash, &entry.key)?.is_some() {
+                        add(entry);
+                    }
+                }
+            }
+            BinaryOperator::Subtract => {
+                for entry in x.iter() {
+                    if self.table_lookup(&y, entry.hash, &entry.key)?.is_none() {
+                        add(entry);
+                    }
+                }
+            }
+            _ => {
+                for entry in x.iter() {
+                    if self.table_lookup(&y, entry.hash, &entry.key)?.is_none() {
+                        add(entry);
+                    }
+                }
+                for entry in y.iter() {
+                    if self.table_lookup(&x, entry.hash, &entry.key)?.is_none() {
+                        add(entry);
+                    }
+                }
+            }
+        }
+        Ok(match &a.payload {
+            Payload::FrozenSet(_) => self.new_object(Payload::FrozenSet(result), &self.types.frozenset_type),
+            _ => self.new_object(Payload::Set(RefCell::new(result)), &self.types.set_type),
+        })
+    }
+
+    fn number_binary(&self, x: Number, y: Number, op: BinaryOperator) -> PyResult<Option<PyObjectRef>> {
+        match (x, y) {
+            (Number::Int(x), Number::Int(y)) => self.int_binary(&x, &y, op),
+            (Number::Complex(xr, xi), y) => {
+                let (yr, yi) = self.complex_parts(y);
+                self.complex_binary((xr, xi), (yr, yi), op)
+            }
+            (x, Number::Complex(yr, yi)) => {
+                let (xr, xi) = self.complex_parts(x);
+                self.complex_binary((xr, xi), (yr, yi), op)
+            }
+            (x, y) => {
+                let x = self.number_to_f64(x)?;
+                let y = self.number_to_f64(y)?;
+                self.float_binary(x, y, op)
+            }
+        }
+    }
+
+    fn number_to_f64(&self, number: Number) -> PyResult<f64> {
+        match number {
+            Number::Int(i) => self.int_to_f64(&i),
+            Number::Float(f) => Ok(f),
+            Number::Complex(real, _) => Ok(real),
+        }
+    }
+
+    pub fn int_to_f64(&self, value: &BigInt) -> PyResult<f64> {
+        value.to_f64().ok_or_else(|| self.new_overflow_error("int too large to convert to float".to_string()))
+    }
+
+    fn int_binary(&self, x: &BigInt, y: &BigInt, op: BinaryOperator) -> PyResult<Option<PyObjectRef>> {
+        if op == BinaryOperator::MatrixMultiply {
+            return Ok(None);
+        }
+        if op == BinaryOperator::TrueDivide {
+            return Ok(Some(self.new_float(self.int_true_divide(x, y)?)));
+        }
+        if let (Some(a), Some(b)) = (x.to_i64(), y.to_i64()) {
+            if let Some(result) = self.small_int_binary(a, b, op)? {
+                return Ok(Some(result));
+            }
+        }
+        let result = match op {
+            BinaryOperator::Add => x + y,
+            BinaryOperator::Subtract => x - y,
+            BinaryOperator::Multiply => x * y,
+            BinaryOperator::FloorDivide => match x.div_mod_floor(y) {
+                Some((quotient, _)) => quotient,
+                None => return Err(self.new_zero_division_error("integer division or modulo by zero".to_string())),
+            },
+            BinaryOperator::Remainder => match x.div_mod_floor(y) {
+                Some((_, rem)) => rem,
+                None => return Err(self.new_zero_division_error("integer modulo by zero".to_string())),
+            },
+            BinaryOperator::Power => {
+                if y.is_negative() {
+                    if x.is_zero() {
+                        return Err(self.new_zero_division_error(
+                            "0.0 cannot be raised to a negative power".to_string(),
+                        ));
+                    }
+                    return self.float_binary(self.int_to_f64(x)?, self.int_to_f64(y)?, op);
+                }
+                match (x.to_i64(), y.to_i64()) {
+                    (Some(0 | 1), _) => x.clone(),
+                    (Some(-1), _) => BigInt::from(if y.is_odd() { -1 } else { 1 }),
+                    (_, Some(exp)) if (x.bit_length() as u128) * (exp as u128) < MAX_INT_BITS as u128 => {
+                        x.pow(exp as u64)
+                    }
+                    _ => return Err(self.new_exception(&self.exceptions.memory_error, vec![])),
+                }
+            }
+            BinaryOperator::LeftShift | BinaryOperator::RightShift if y.is_negative() => {
+                return Err(self.new_value_error("negative shift count".to_string()))
+            }
+            BinaryOperator::LeftShift => match y.to_i64() {
+                _ if x.is_zero() => BigInt::zero(),
+                Some(shift) if x.bit_length() + (shift as u64) < MAX_INT_BITS => x << shift as u64,
+                _ => return Err(self.new_overflow_error("too many digits in integer".to_string())),
+            },
+            BinaryOperator::RightShift => match y.to_i64() {
+                Some(shift) => x >> shift as u64,
+                None => BigInt::from(if x.is_negative() { -1 } else { 0 }),
+            },
+            BinaryOperator::And => x & y,
+            BinaryOperator::Or => x | y,
+            BinaryOperator::Xor => x ^ y,
+            BinaryOperator::TrueDivide | BinaryOperator::MatrixMultiply => unreachable!("handled above"),
+        };
+        Ok(Some(self.new_bigint(result)))
+    }
+
+    /// The fast path for ints that fit in a machine word: None when the result does not.
+    /// The value of an `int`, and not of a subclass that could override its operators, that
+    /// fits in an `i64`.  Operators on two of these skip the method lookup and `BigInt`s.
+    fn exact_small_int(&self, obj: &PyObjectRef) -> Option<i64> {
+        match &obj.payload {
+            Payload::Int(PyInt::Small(i)) if obj.class().is(&self.types.int_type) => Some(*i),
+            _ => None,
+        }
+    }
+
+    fn small_int_binary(&self, a: i64, b: i64, op: BinaryOperator) -> PyResult<Option<PyObjectRef>> {
+        let result = match op {
+            BinaryOperator::Add => a.checked_add(b),
+            BinaryOperator::Subtract => a.checked_sub(b),
+            BinaryOperator::Multiply => a.checked_mul(b),
+            BinaryOperator::FloorDivide => {
+                if b == 0 {
+                    return Err(self.new_zero_division_error("integer division or modulo by zero".to_string()));
+                }
+                floor_div_i64(a, b)
+            }
+            BinaryOperator::Remainder => {
+                if b == 0 {
+                    return Err(self.new_zero_division_error("integer modulo by zero".to_string()));
+                }
+                Some(mod_i64(a, b))
+            }
+            BinaryOperator::Power if b >= 0 => u32::try_from(b).ok().and_then(|b| a.checked_pow(b)),
+            BinaryOperator::LeftShift if (0..63).contains(&b) => {
+                if (a << b) >> b == a {
+                    Some(a << b)
+                } else {
+                    None
+                }
+            }
+            BinaryOperator::RightShift if b >= 0 => Some(if b >= 64 { if a < 0 { -1 } else { 0 } } else { a >> b }),
+            BinaryOperator::And => Some(a & b),
+            BinaryOperator::Or => Some(a | b),
+            BinaryOperator::Xor => Some(a ^ b),
+            _ => None,
+        };
+        Ok(result.map(|result| self.new_int(result)))
+    }
+
+    /// `x / y` for ints, rounded from a quotient with more bits than a float holds so that
+    /// ints too large for a float still divide.
+    fn int_true_divide(&self, x: &BigInt, y: &BigInt) -> PyResult<f64> {
+        if y.is_zero() {
+            return Err(self.new_zero_division_error("division by zero".to_string()));
+        }
+        if x.bit_length() <= 53 && y.bit_length() <= 53 {
+            return Ok(self.int_to_f64(x)? / self.int_to_f64(y)?);
+        }
+        // Scale so the quotient has about 66 bits, and keep a sticky bit for any remainder.
+        let scale = 66 + y.bit_length() as i64 - x.bit_length() as i64;
+        let (numerator, denominator) = if scale >= 0 {
+            (&x.abs() << scale as u64, y.abs())
+        } else {
+            (x.abs(), &y.abs() << (-scale) as u64)
+        };
+        let (quotient, rem) = numerator.div_mod_floor(&denominator).expect("non-zero divisor");
+        let quotient = if rem.is_zero() { quotient } else { &quotient | &BigInt::from(1) };
+        let half = -scale / 2;
+        let magnitude = quotient.to_f64().unwrap_or(f64::INFINITY) * 2f64.powi(half as i32) * 2f64.powi((-scale - half) as i32);
+        if magnitude.is_infinite() {
+            return Err(self.new_overflow_error("integer division result too large for a float".to_string()));
+        }
+        Ok(if x.is_negative() != y.is_negative() { -magnitude } else { magnitude })
+    }
+
+    fn float_binary(&self, x: f64, y: f64, op: BinaryOperator) -> PyResult<Option<PyObjectRef>> {
+        let result = match op {
+            BinaryOperator::Add => x + y,
+            BinaryOperator::Subtract => x - y,
+            BinaryOperator::Multiply => x * y,
+            BinaryOperator::TrueDivide => {
+                if y == 0.0 {
+                    return Err(self.new_zero_division_error("float division by zero".to_string()));
+                }
+                x / y
+            }
+            BinaryOperator::FloorDivide => {
+                if y == 0.0 {
+                    return Err(self.new_zero_division_error("float floor division by zero".to_string()));
+                }
+                float_divmod(x, y).0
+            }
+            BinaryOperator::Remainder => {
+                if y == 0.0 {
+                    return Err(self.new_zero_division_error("float modulo".to_string()));
+                }
+                float_divmod(x, y).1
+            }
+            BinaryOperator::Power => return self.float_pow(x, y).map(Some),
+            _ => return Ok(None),
+        };
+        Ok(Some(self.new_float(result)))
+    }
+
+    fn float_pow(&self, x: f64, y: f64) -> PyResult {
+        if x == 0.0 && y < 0.0 {
+            return Err(self.new_zero_division_error("0.0 cannot be raised to a negative power".to_string()));
+        }
+        if x < 0.0 && y.is_finite() && y.fract() != 0.0 {
+            // A negative number to a fractional power is complex.
+            return self.complex_binary((x, 0.0), (y, 0.0), BinaryOperator::Power).map(|r| r.expect("complex"));
+        }
+        let result = x.powf(y);
+        if result.is_infinite() && x.is_finite() && y.is_finite() {
+            return Err(self.new_overflow_error("(34, 'Numerical result out of range')".to_string()));
+        }
+        Ok(self.new_float(result))
+    }
+
+    fn complex_binary(&self, x: (f64, f64), y: (f64, f64), op: BinaryOperator) -> PyResult<Option<PyObjectRef>> {
+        let (a, b) = x;
+        let (c, d) = y;
+        let (real, imag) = match op {
+            BinaryOperator::Add => (a + c, b + d),
+            BinaryOperator::Subtract => (a - c, b - d),
+            BinaryOperator::Multiply => complex_product(x, y),
+            BinaryOperator::TrueDivide => {
+                if c == 0.0 && d == 0.0 {
+                    return Err(self.new_zero_division_error("complex division by zero".to_string()));
+                }
+                complex_quotient(x, y)
+            }
+            BinaryOperator::Power => {
+                if c == 0.0 && d == 0.0 {
+                    (1.0, 0.0)
+                } else if a == 0.0 && b == 0.0 {
+                    if d != 0.0 || c < 0.0 {
+                        return Err(self.new_zero_division_error(
+                            "0.0 to a negative or complex power".to_string(),
+                        ));
+                    }
+                    (0.0, 0.0)
+                } else if d == 0.0 && c == c.trunc() && c.abs() <= 100.0 {
+                    // Small integer powers multiply, as CPython does, so `1j ** 2` is exactly -1.
+                    let n = c as i64;
+                    let power = complex_power(x, n.unsigned_abs());
+                    if n < 0 { complex_quotient((1.0, 0.0), power) } else { power }
+                } else {
+                    let modulus = a.hypot(b);
+                    let angle = b.atan2(a);
+                    let length = modulus.powf(c) * (-d * angle).exp();
+                    let phase = angle * c + d * modulus.ln();
+                    (length * phase.cos(), length * phase.sin())
+                }
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(self.new_complex(real, imag)))
+    }
+
+    pub fn unary_op(&self, a: &PyObjectRef, op: UnaryOperator) -> PyResult {
+        let method = match op {
+            UnaryOperator::Not => return Ok(self.new_bool(!self.is_true(a)?)),
+            UnaryOperator::Positive => "__pos__",
+            UnaryOperator::Negative => "__neg__",
+            UnaryOperator::Invert => "__invert__",
+        };
+        if let Some(result) = self.call_special(a, method, vec![])? {
+            return Ok(result);
+        }
+        self.native_unary(a, op)
+    }
+
+    /// `+a`, `-a` or `~a` of a builtin number.
+    pub(super) fn native_unary(&self, a: &PyObjectRef, op: UnaryOperator) -> PyResult {
+        let result = match (op, number_of(a)) {
+            (UnaryOperator::Positive, Some(Number::Int(i))) => Some(self.new_bigint(i)),
+            (UnaryOperator::Negative, Some(Number::Int(i))) => Some(self.new_bigint(-i)),
+            (UnaryOperator::Invert, Some(Number::Int(i))) => Some(self.new_bigint(!&i)),
+            (UnaryOperator::Positive, Some(Number::Float(f))) => Some(self.new_float(f)),
+            (UnaryOperator::Negative, Some(Number::Float(f))) => Some(self.new_float(-f)),
+            (UnaryOperator::Positive, Some(Number::Complex(r, i))) => Some(self.new_complex(r, i)),
+            (UnaryOperator::Negative, Some(Number::Complex(r, i))) => Some(self.new_complex(-r, -i)),
+            _ => None,
+        };
+        result.ok_or_else(|| {
+            let symbol = match op {
+                UnaryOperator::Positive => "+",
+                UnaryOperator::Negative => "-",
+                _ => "~",
+            };
+            self.new_type_error(format!("bad operand type for unary {}: '{}'", symbol, self.class_name(a)))
+        })
+    }
+
+    /// `abs(a)` of a builtin number.
+    pub(crate) fn native_abs(&self, a: &PyObjectRef) -> PyResult {
+        match number_of(a) {
+            Some(Number::Int(int)) => Ok(self.new_bigint(if int.is_negative() { -int } else { int })),
+            Some(Number::Float(f)) => Ok(self.new_float(f.abs())),
+            Some(Number::Complex(real, imag)) => Ok(self.new_float(real.hypot(imag))),
+            None => Err(self.new_type_error(format!("bad operand type for abs(): '{}'", self.class_name(a)))),
+        }
+    }
+
+    // Conversions
+
+    /// `operator.index()`: the int value of an int or of an object with `__index__`.
+    pub fn to_index(&self, obj: &PyObjectRef) -> PyResult<BigInt> {
+        if let Some(value) = obj.as_int() {
+            return Ok(value);
+        }
+        if let Some(result) = self.call_special(obj, "__index__", vec![])? {
+            return result.as_int().ok_or_else(|| {
+                self.new_type_error(format!("__index__ returned non-int (type {})", self.class_name(&result)))
+            });
+        }
+        Err(self.new_type_error(format!(
+            "'{}' object cannot be interpreted as an integer",
+            self.class_name(obj)
+        )))
+    }
+
+    /// An index that has to fit in a machine word.
+    pub fn to_usize_index(&self, obj: &PyObjectRef) -> PyResult<i64> {
+        self.to_index(obj)?.to_i64().ok_or_else(|| {
+            self.new_exception_msg(
+                &self.exceptions.index_error,
+                format!("cannot fit '{}' into an index-sized integer", self.class_name(obj)),
+            )
+        })
+    }
+
+    /// `int(obj)`.
+    pub fn to_int(&self, obj: &PyObjectRef) -> PyResult<BigInt> {
+        match &obj.payload {
+            Payload::Int(i) => return Ok(i.to_bigint()),
+            Payload::Bool(b) => return Ok(BigInt::from(*b as i64)),
+            Payload::Float(f) => return self.float_to_int(*f),
+            Payload::Str(s) => return self.parse_int(s, 10),
+            Payload::Bytes(b) => return self.parse_int(&String::from_utf8_lossy(b), 10),
+            _ => {}
+        }
+        for method in ["__int__", "__index__", "__trunc__"] {
+            if let Some(result) = self.call_special(obj, method, vec![])? {
+                return result.as_int().ok_or_else(|| {
+                    self.new_type_error(format!("{} returned non-int (type {})", method, self.class_name(&result)))
+                });
+            }
+        }
+        Err(self.new_type_error(format!(
+            "int() argument must be a string, a bytes-like object or a real number, not '{}'",
+            self.class_name(obj)
+        )))
+    }
+
+    pub fn float_to_int(&self, value: f64) -> PyResult<BigInt> {
+        if value.is_nan() {
+            return Err(self.new_value_error("cannot convert float NaN to integer".to_string()));
+        }
+        if value.is_infinite() {
+            return Err(self.new_overflow_error("cannot convert float infinity to integer".to_string()));
+        }
+        Ok(BigInt::from_f64(value).expect("finite float"))
+    }
+
+    /// `float(obj)` for a number or an object with `__float__`.
+    pub fn to_float(&self, obj: &PyObjectRef) -> PyResult<f64> {
+        match &obj.payload {
+            Payload::Float(f) => return Ok(*f),
+            Payload::Int(PyInt::Small(i)) => return Ok(*i as f64),
+            Payload::Int(PyInt::Big(i)) => return self.int_to_f64(i),
+            Payload::Bool(b) => return Ok(*b as i64 as f64),
+            _ => {}
+        }
+        if let Some(result) = self.call_special(obj, "__float__", vec![])? {
+            return match &result.payload {
+                Payload::Float(f) => Ok(*f),
+                _ => Err(self.new_type_error(format!(
+                    "{}.__float__ returned non-float (type {})",
+                    self.class_name(obj),
+                    self.class_name(&result)
+                ))),
+            };
+        }
+        if self.has_special(obj, "__index__") {
+            return self.int_to_f64(&self.to_index(obj)?);
+        }
+        Err(self.new_type_error(format!("must be real number, not {}", self.class_name(obj))))
+    }
+
+    /// `int(text, base)`, with base 0 meaning the base given by the prefix.
+    pub fn parse_int(&self, text: &str, base: u32) -> PyResult<BigInt> {
+        let invalid = || {
+            let shown = self.repr(&self.new_str(text.to_string())).unwrap_or_default();
+            self.new_value_error(format!("invalid literal for int() with base {}: {}", base, shown))
+        };
+        let trimmed = text.trim();
+        let (negative, unsigned) = match trimmed.as_bytes().first() {
+            Some(b'-') => (true, &trimmed[1..]),
+            Some(b'+') => (false, &trimmed[1..]),
+            _ => (false, trimmed),
+        };
+        let lower = unsigned.to_ascii_lowercase();
+        let prefixed = |prefix: &str| lower.starts_with(prefix);
+        let (radix, digits) = match base {
+            0 if prefixed("0x") => (16, &unsigned[2..]),
+            0 if prefixed("0o") => (8, &unsigned[2..]),
+            0 if prefixed("0b") => (2, &unsigned[2..]),
+            0 => {
+                if unsigned.len() > 1 && unsigned.starts_with('0') && unsigned.chars().any(|c| c != '0' && c != '_') {
+                    return Err(invalid());
+                }
+                (10, unsigned)
+            }
+            16 if prefixed("0x") => (16, &unsigned[2..]),
+            8 if prefixed("0o") => (8, &unsigned[2..]),
+            2 if prefixed("0b") => (2, &unsigned[2..]),
+            base => (base, unsigned),
+        };
+        // Underscores may separate digits, and follow a base prefix.
+        let digits = if radix != 10 && digits.len() != unsigned.len() {
+            digits.strip_prefix('_').unwrap_or(digits)
+        } else {
+            digits
+        };
+        if digits.is_empty() || digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+            return Err(invalid());
+        }
+        let cleaned: String = digits.chars().filter(|c| *c != '_').collect();
+        let limit = self.int_max_str_digits.get();
+        if limit > 0 && !radix.is_power_of_two() && cleaned.len() > limit {
+            return Err(self.new_value_error(format!(
+                "Exceeds the limit ({} digits) for integer string conversion: value has {} digits; \
+                 use sys.set_int_max_str_digits() to increase the limit",
+                limit,
+                cleaned.len()
+            )));
+        }
+        let magnitude = BigInt::from_str_radix(&cleaned, radix).ok_or_else(invalid)?;
+        Ok(if negative { -magnitude } else { magnitude })
+    }
+
+    /// `float(text)`.
+    pub fn parse_float(&self, text: &str) -> PyResult<f64> {
+        let invalid = || {
+            let shown = self.repr(&self.new_str(text.to_string())).unwrap_or_default();
+            self.new_value_error(format!("could not convert string to float: {}", shown))
+        };
+        let trimmed = text.trim();
+        let (sign, unsigned) = match trimmed.as_bytes().first() {
+            Some(b'-') => (-1.0, &trimmed[1..]),
+            Some(b'+') => (1.0, &trimmed[1..]),
+            _ => (1.0, trimmed),
+        };
+        match unsigned.to_ascii_lowercase().as_str() {
+            "inf" | "infinity" => return Ok(sign * f64::INFINITY),
+            "nan" => return Ok(sign * f64::NAN),
+            _ => {}
+        }
+        let valid_underscores = unsigned.split('_').count() == 1
+            || unsigned
+                .as_bytes()
+                .windows(3)
+                .filter(|w| w[1] == b'_')
+                .all(|w| w[0].is_ascii_digit() && w[2].is_ascii_digit());
+        if !valid_underscores || unsigned.starts_with('_') || unsigned.ends_with('_') {
+            return Err(invalid());
+        }
+        let cleaned: String = unsigned.chars().filter(|c| *c != '_').collect();
+        if cleaned.is_empty() || !cleaned.chars().all(|c| c.is_ascii_digit() || ".eE+-".contains(c)) {
+            return Err(invalid());
+        }
+        cleaned.parse::<f64>().map(|value| sign * value).map_err(|_| invalid())
+    }
+
+    // Containers
+
+    pub fn len(&self, obj: &PyObjectRef) -> PyResult<usize> {
+        if let Some(result) = self.call_special(obj, "__len__", vec![])? {
+            let value = self.to_index(&result)?;
+            if value.is_negative() {
+                return Err(self.new_value_error("__len__() should return >= 0".to_string()));
+            }
+            return value.to_i64().map(|len| len as usize).ok_or_else(|| {
+                self.new_overflow_error("cannot fit 'int' into an index-sized integer".to_string())
+            });
+        }
+        self.native_len(obj)
+    }
+
+    /// The length of a builtin container.
+    pub(super) fn native_len(&self, obj: &PyObjectRef) -> PyResult<usize> {
+        Ok(match &obj.payload {
+            Payload::Str(s) => s.char_len(),
+            Payload::Bytes(b) => b.len(),
+            Payload::Tuple(items) => items.len(),
+            Payload::List(items) => items.borrow().len(),
+            Payload::Dict(d) | Payload::Set(d) => d.borrow().len(),
+            Payload::FrozenSet(d) => d.len(),
+            Payload::DictView { dict, .. } => dict.as_dict().expect("dict").borrow().len(),
+            Payload::Range { .. } => self.range_len(obj),
+            _ => return Err(self.new_type_error(format!("object of type '{}' has no len()", self.class_name(obj)))),
+        })
+    }
+
+    pub fn range_len(&self, range: &PyObjectRef) -> usize {
+        let Payload::Range { start, stop, step } = &range.payload else { return 0 };
+        let (Some(start), Some(stop), Some(step)) = (start.to_i64(), stop.to_i64(), step.to_i64()) else {
+            return 0;
+        };
+        let (start, stop, step) = (start as i128, stop as i128, step as i128);
+        let len = if step > 0 && start < stop {
+            (stop - start - 1) / step + 1
+        } else if step < 0 && start > stop {
+            (start - stop - 1) / (-step) + 1
+        } else {
+            0
+        };
+        len as usize
+    }
+
+    /// The `i`th item of a range, which must be in bounds.
+    pub fn range_item(&self, range: &PyObjectRef, index: usize) -> PyObjectRef {
+        let Payload::Range { start, step, .. } = &range.payload else { unreachable!("not a range") };
+        let value = start.to_i64().unwrap_or(0) as i128 + step.to_i64().unwrap_or(0) as i128 * index as i128;
+        self.new_int(value as i64)
+    }
+
+    /// `start, stop, step` of a slice applied to a sequence of `len` items, as CPython's
+    /// `PySlice_AdjustIndices` computes them, with the number of items it selects.
+    pub fn slice_indices(&self, slice: &PyObjectRef, len: usize) -> PyResult<(i64, i64, i64, usize)> {
+        let Payload::Slice { start, stop, step } = &slice.payload else { unreachable!("not a slice") };
+        let len = len as i64;
+        let index = |value: &PyObjectRef| -> PyResult<Option<i64>> {
+            if self.is_none(value) {
+                return Ok(None);
+            }
+            let value = self.to_index(value).map_err(|_| {
+                self.new_type_error(
+                    "slice indices must be integers or None or have an __index__ method".to_string(),
+                )
+            })?;
+            Ok(Some(value.to_i64().unwrap_or(if value.is_negative() { i64::MIN / 2 } else { i64::MAX / 2 })))
+        };
+        let step = index(step)?.unwrap_or(1);
+        if step == 0 {
+            return Err(self.new_value_error("slice step cannot be zero".to_string()));
+        }
+        let adjust = |value: Option<i64>, default: i64| -> i64 {
+            match value {
+                None => default,
+                Some(v) if v < 0 => {
+                    let v = v + len;
+                    if v < 0 {
+                        if step < 0 {
+                            -1
+                        } else {
+                            0
+                        }
+                    } else {
+                        v
+                    }
+                }
+                Some(v) if v >= len => {
+                    if step < 0 {
+                        len - 1
+                    } else {
+                        len
+                    }
+                }
+                Some(v) => v,
+            }
+        };
+        let (start, stop) = if step < 0 {
+            (adjust(index(start)?, len - 1), adjust(index(stop)?, -1))
+        } else {
+            (adjust(index(start)?, 0), adjust(index(stop)?, len))
+        };
+        let count = if step < 0 {
+            if stop < start {
+                (start - stop - 1) / (-step) + 1
+            } else {
+                0
+            }
+        } else if start < stop {
+            (stop - start - 1) / step + 1
+        } else {
+            0
+        };
+        Ok((start, stop, step, count as usize))
+    }
+
+    fn slice_positions(&self, slice: &PyObjectRef, len: usize) -> PyResult<Vec<usize>> {
+        let (start, _, step, count) = self.slice_indices(slice, len)?;
+        Ok((0..count as i64).map(|i| (start + i * step) as usize).collect())
+    }
+
+    fn index_error_for(&self, obj: &PyObjectRef) -> PyObjectRef {
+        let kind = match &obj.payload {
+            Payload::Str(_) => "string",
+            Payload::Bytes(_) => "index",
+            Payload::Range { .. } => "range object",
+            Payload::List(_) => "list",
+            _ => "tuple",
+        };
+        self.new_index_error(format!("{} index out of range", kind))
+    }
+
+    fn sequence_index(&self, obj: &PyObjectRef, key: &PyObjectRef, len: usize) -> PyResult<usize> {
+        let index = match key.as_int() {
+            Some(index) => index,
+            None if self.has_special(key, "__index__") => self.to_index(key)?,
+            None => {
+                let kind = match &obj.payload {
+                    Payload::Str(_) => {
+                        return Err(self.new_type_error(format!(
+                            "string indices must be integers, not '{}'",
+                            self.class_name(key)
+                        )))
+                    }
+                    Payload::Bytes(_) => "byte",
+                    Payload::Range { .. } => "range",
+                    Payload::List(_) => "list",
+                    _ => "tuple",
+                };
+                return Err(self.new_type_error(format!(
+                    "{} indices must be integers or slices, not {}",
+                    kind,
+                    self.class_name(key)
+                )));
+            }
+        };
+        normalize_index(&index, len).ok_or_else(|| self.index_error_for(obj))
+    }
+
+    pub fn get_item(&self, obj: &PyObjectRef, key: &PyObjectRef) -> PyResult {
+        if let Some(result) = self.call_special(obj, "__getitem__", vec![key.clone()])? {
+            return Ok(result);
+        }
+        self.native_get_item(obj, key)
+    }
+
+    /// `obj[key]` of a builtin container, or of a class through `__class_getitem__`.
+    pub(super) fn native_get_item(&self, obj: &PyObjectRef, key: &PyObjectRef) -> PyResult {
+        let is_slice = matches!(key.payload, Payload::Slice { .. });
+        match &obj.payload {
+            Payload::List(items) => {
+                let items = items.borrow();
+                if is_slice {
+                    let picked = self.slice_positions(key, items.len())?.into_iter().map(|i| items[i].clone()).collect();
+                    return Ok(self.new_list(picked));
+                }
+                let idx = self.sequence_index(obj, key, items.len())?;
+                Ok(items[idx].clone())
+            }
+            Payload::Tuple(items) => {
+                if is_slice {
+                    let picked = self.slice_positions(key, items.len())?.into_iter().map(|i| items[i].clone()).collect();
+                    return Ok(self.new_tuple(picked));
+                }
+                let idx = self.sequence_index(obj, key, items.len())?;
+                Ok(items[idx].clone())
+            }
+            Payload::Str(s) => {
+                if is_slice {
+                    let (start, _, step, count) = self.slice_indices(key, s.char_len())?;
+                    return Ok(self.new_str(s.slice(start, step, count)));
+                }
+                let idx = self.sequence_index(obj, key, s.char_len())?;
+                Ok(self.new_str(s.char_at(idx).to_string()))
+            }
+            Payload::Bytes(b) => {
+                if is_slice {
+                    let picked = self.slice_positions(key, b.len())?.into_iter().map(|i| b[i]).collect();
+                    return Ok(self.new_bytes(picked));
+                }
+                let idx = self.sequence_index(obj, key, b.len())?;
+                Ok(self.new_int(b[idx] as i64))
+            }
+            Payload::Range { start, step, .. } => {
+                let len = self.range_len(obj);
+                if is_slice {
+                    let (first, _, slice_step, count) = self.slice_indices(key, len)?;
+                    let (start, step) = (start.to_i64().unwrap_or(0), step.to_i64().unwrap_or(1));
+                    let new_start = start + first * step;
+                    let new_step = step * slice_step;
+                    let new_stop = new_start + count as i64 * new_step;
+                    return Ok(self.new_object(
+                        Payload::Range {
+                            start: BigInt::from(new_start),
+                            stop: BigInt::from(new_stop),
+                            step: BigInt::from(new_step),
+                        },
+                        &self.types.range_type,
+                    ));
+                }
+                let idx = self.sequence_index(obj, key, len)?;
+                Ok(self.range_item(obj, idx))
+            }
+            Payload::Dict(_) => match self.dict_get_item(obj, key)? {
+                Some(value) => Ok(value),
+                None => {
+                    if let Some(value) = self.call_special(obj, "__missing__", vec![key.clone()])? {
+                        return Ok(value);
+                    }
+                    Err(self.new_key_error(key.clone()))
+                }
+            },
+            Payload::Type(_) => {
+                if let Some(method) = self.type_lookup(obj, "__class_getitem__") {
+                    let method = match &method.payload {
+                        Payload::ClassMethod(function) => function.clone(),
+                        _ => method,
+                    };
+                    return self.call(&method, FuncArgs::new(vec![obj.clone(), key.clone()]));
+                }
+                Err(self.new_type_error(format!(
+                    "type '{}' is not subscriptable",
+                    obj.as_type().expect("type").name()
+                )))
+            }
+            _ => Err(self.new_type_error(format!("'{}' object is not subscriptable", self.class_name(obj)))),
+        }
+    }
+
+    pub fn set_item(&self, obj: &PyObjectRef, key: &PyObjectRef, value: &PyObjectRef) -> PyResult<()> {
+        if self.call_special(obj, "__setitem__", vec![key.clone(), value.clone()])?.is_some() {
+            return Ok(());
+        }
+        self.native_set_item(obj, key, value)
+    }
+
+    /// `obj[key] = value` of a list or dict.
+    pub(super) fn native_set_item(&self, obj: &PyObjectRef, key: &PyObjectRef, value: &PyObjectRef) -> PyResult<()> {
+        match &obj.payload {
+            Payload::List(items) => {
+                if matches!(key.payload, Payload::Slice { .. }) {
+                    let values = self.collect(value)?;
+                    let len = items.borrow().len();
+                    let (start, stop, step, count) = self.slice_indices(key, len)?;
+                    let mut items = items.borrow_mut();
+                    if step == 1 {
+                        let stop = stop.max(start) as usize;
+                        items.splice(start as usize..stop, values);
+                        return Ok(());
+                    }
+                    if values.len() != count {
+                        return Err(self.new_value_error(format!(
+                            "attempt to assign sequence of size {} to extended slice of size {}",
+                            values.len(),
+                            count
+                        )));
+                    }
+                    for (i, value) in values.into_iter().enumerate() {
+                        items[(start + i as i64 * step) as usize] = value;
+                    }
+                    return Ok(());
+                }
+                let len = items.borrow().len();
+                let idx = self
+                    .sequence_index(obj, key, len)
+                    .map_err(|err| match self.isinstance(&err, &self.exceptions.index_error) {
+                        true => self.new_index_error("list assignment index out of range".to_string()),
+                        false => err,
+                    })?;
+                items.borrow_mut()[idx] = value.clone();
+                Ok(())
+            }
+            Payload::Dict(_) => self.dict_set_item(obj, key, value),
+            _ => Err(self.new_type_error(format!(
+                "'{}' object does not support item assignment",
+                self.class_name(obj)
+            ))),
+        }
+    }
+
+    pub fn del_item(&self, obj: &PyObjectRef, key: &PyObjectRef) -> PyResult<()> {
+        if self.call_special(obj, "__delitem__", vec![key.clone()])?.is_some() {
+            return Ok(());
+        }
+        self.native_del_item(obj, key)
+    }
+
+    /// `del obj[key]` of a list or dict.
+    pub(super) fn native_del_item(&self, obj: &PyObjectRef, key: &PyObjectRef) -> PyResult<()> {
+        match &obj.payload {
+            Payload::List(items) => {
+                let len = items.borrow().len();
+                if matches!(key.payload, Payload::Slice { .. }) {
+                    let mut positions = self.slice_positions(key, len)?;
+                    positions.sort_unstable();
+                    let mut items = items.borrow_mut();
+                    for pos in positions.into_iter().rev() {
+                        items.remove(pos);
+                    }
+                    return Ok(());
+                }
+                let idx = self
+                    .sequence_index(obj, key, len)
+                    .map_err(|err| match self.isinstance(&err, &self.exceptions.index_error) {
+                        true => self.new_index_error("list assignment index out of range".to_string()),
+                        false => err,
+                    })?;
+                items.borrow_mut().remove(idx);
+                Ok(())
+            }
+            Payload::Dict(_) => {
+                if self.dict_del_item(obj, key)? {
+                    Ok(())
+                } else {
+                    Err(self.new_key_error(key.clone()))
+                }
+            }
+            _ => Err(self.new_type_error(format!(
+                "'{}' object doesn't support item deletion",
+                self.class_name(obj)
+            ))),
+        }
+    }
+
+    /// `item in container`.
+    pub fn contains(&self, container: &PyObjectRef, item: &PyObjectRef) -> PyResult<bool> {
+        if let Some(result) = self.call_special(container, "__contains__", vec![item.clone()])? {
+            return self.is_true(&result);
+        }
+        self.native_contains(container, item)
+    }
+
+    /// `item in container` for a builtin container, or by iterating over anything else.
+    pub(super) fn native_contains(&self, container: &PyObjectRef, item: &PyObjectRef) -> PyResult<bool> {
+        match &container.payload {
+            Payload::Str(haystack) => match &item.payload {
+                Payload::Str(needle) => Ok(haystack.contains(needle.as_str())),
+                _ => Err(self.new_type_error(format!(
+                    "'in <string>' requires string as left operand, not {}",
+                    self.class_name(item)
+                ))),
+            },
+            Payload::Bytes(haystack) => match &item.payload {
+                Payload::Bytes(needle) => {
+                    Ok(needle.is_empty() || haystack.windows(needle.len()).any(|w| w == needle.as_slice()))
+                }
+                _ => {
+                    let byte = self.to_index(item)?;
+                    Ok(byte.to_i64().is_some_and(|b| haystack.contains(&(b as u8)) && (0..256).contains(&b)))
+                }
+            },
+            Payload::Dict(_) => Ok(self.dict_get_item(container, item)?.is_some()),
+            Payload::DictView { dict, kind: DictViewKind::Keys } => Ok(self.dict_get_item(dict, item)?.is_some()),
+            Payload::DictView { dict, kind: DictViewKind::Items } => {
+                let Some([key, value]) = item.as_tuple() else {
+                    return Ok(false);
+                };
+                match self.dict_get_item(dict, key)? {
+                    Some(found) => self.same_or_eq(&found, value),
+                    None => Ok(false),
+                }
+            }
+            Payload::Set(_) | Payload::FrozenSet(_) => self.set_contains(container, item),
+            Payload::Range { start, stop, step } if matches!(item.payload, Payload::Int(_) | Payload::Bool(_)) => {
+                let value = item.as_int().expect("int").to_i64();
+                let (Some(value), Some(start), Some(stop), Some(step)) =
+                    (value, start.to_i64(), stop.to_i64(), step.to_i64())
+                else {
+                    return Ok(false);
+                };
+                let inside = if step > 0 { start <= value && value < stop } else { stop < value && value <= start };
+                Ok(inside && (value - start) % step == 0)
+            }
+            Payload::Tuple(items) => {
+                for candidate in items {
+                    if self.same_or_eq(candidate, item)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            Payload::List(items) => {
+                let mut idx = 0;
+                loop {
+                    let candidate = match items.borrow().get(idx) {
+                        Some(candidate) => candidate.clone(),
+                        None => return Ok(false),
+                    };
+                    if self.same_or_eq(&candidate, item)? {
+                        return Ok(true);
+                    }
+                    idx += 1;
+                }
+            }
+            _ => {
+                let iter = self.get_iter(container).map_err(|_| {
+                    self.new_type_error(format!(
+                        "argument of type '{}' is not iterable",
+                        self.class_name(container)
+                    ))
+                })?;
+                while let Some(candidate) = self.next(&iter)? {
+                    if self.same_or_eq(&candidate, item)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+        }
+    }
+}
+
+fn complex_product((a, b): (f64, f64), (c, d): (f64, f64)) -> (f64, f64) {
+    (a * c - b * d, a * d + b * c)
+}
+
+/// `x / y` scaled by the larger part of `y`, as CPython divides complex numbers, so that
+/// large and small divisors neither overflow nor lose precision.
+fn complex_quotient((a, b): (f64, f64), (c, d): (f64, f64)) -> (f64, f64) {
+    if c.abs() >= d.abs() {
+        let ratio = d / c;
+        let denominator = c + d * ratio;
+        ((a + b * ratio) / denominator, (b - a * ratio) / denominator)
+    } else if d.abs() >= c.abs() {
+        let ratio = c / d;
+        let denominator = c * ratio + d;
+        ((a * ratio + b) / denominator, (b * ratio - a) / denominator)
+    } else {
+        // One of the parts is NaN.
+        (f64::NAN, f64::NAN)
+    }
+}
+
+/// `x ** n` by repeated squaring.
+fn complex_power(x: (f64, f64), n: u64) -> (f64, f64) {
+    let mut result = (1.0, 0.0);
+    let mut square = x;
+    let mut mask = 1u64;
+    while mask > 0 && n >= mask {
+        if n & mask != 0 {
+            result = complex_product(result, square);
+        }
+        mask <<= 1;
+        square = complex_product(square, square);
+    }
+    result
+}
+
+fn repeat(items: &[PyObjectRef], count: usize) -> Vec<PyObjectRef> {
+    let mut result = Vec::with_capacity(items.len() * count);
+    for _ in 0..count {
+        result.extend(items.iter().cloned());
+    }
+    result
+}
+
+/// Compares an int with a float exactly: neither is rounded to the other's type.
+fn compare_int_float(x: &BigInt, y: f64) -> Option<Ordering> {
+    if y.is_nan() {
+        return None;
+    }
+    if y.is_infinite() {
+        return Some(if y > 0.0 { Ordering::Less } else { Ordering::Greater });
+    }
+    if x.bit_length() <= 53 {
+        return x.to_f64().expect("small int").partial_cmp(&y);
+    }
+    // Compare with the integer part, then let the fraction break a tie.
+    let whole = BigInt::from_f64(y).expect("finite float");
+    Some(x.cmp(&whole).then_with(|| 0.0.partial_cmp(&y.fract()).expect("finite fraction")))
+}
+
+pub(crate) fn hash_int(value: &BigInt) -> i64 {
+    if let Some(small) = value.to_i64() {
+        return hash_small_int(small);
+    }
+    let (_, rem) = value.abs().div_mod_floor(&BigInt::from(HASH_MODULUS as i64)).expect("non-zero modulus");
+    let rem = rem.to_i64().expect("reduced hash");
+    signed_hash(rem, value.is_negative())
+}
+
+fn hash_small_int(value: i64) -> i64 {
+    signed_hash((value.unsigned_abs() % HASH_MODULUS) as i64, value < 0)
+}
+
+/// The hash of an int whose magnitude reduces to `magnitude`, with -1 kept for errors.
+fn signed_hash(magnitude: i64, negative: bool) -> i64 {
+    match (magnitude, negative) {
+        (1, true) => -2,
+        (_, true) => -magnitude,
+        _ => magnitude,
+    }
+}
+
+/// CPython's `_Py_HashDouble`: integral floats hash like the equal int.
+fn hash_float(value: f64, obj: &PyObjectRef) -> i64 {
+    if value.is_nan() {
+        return identity_hash(obj);
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { HASH_INF } else { -HASH_INF };
+    }
+    let (mut mantissa, mut exponent) = frexp(value.abs());
+    let sign: i64 = if value < 0.0 { -1 } else { 1 };
+    let mut x: u64 = 0;
+    while mantissa != 0.0 {
+        x = ((x << 28) & HASH_MODULUS) | x >> (61 - 28);
+        mantissa *= 268435456.0;
+        exponent -= 28;
+        let digit = mantissa as u64;
+        mantissa -= digit as f64;
+        x += digit;
+        if x >= HASH_MODULUS {
+            x -= HASH_MODULUS;
+        }
+    }
+    let exponent = if exponent >= 0 { exponent % 61 } else { 60 - ((-1 - exponent) % 61) };
+    let exponent = exponent as u32;
+    x = ((x << exponent) & HASH_MODULUS) | x >> (61 - exponent);
+    let result = x as i64 * sign;
+    if result == -1 {
+        -2
+    } else {
+        result
+    }
+}
+
+/// Splits a finite positive float into a mantissa in `[0.5, 1)` and a power of two.
+pub(crate) fn frexp(value: f64) -> (f64, i32) {
+    if value == 0.0 {
+        return (0.0, 0);
+    }
+    let bits = value.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i32;
+    if exponent == 0 {
+        // Subnormal: scale into the normal range first.
+        let (mantissa, exponent) = frexp(value * 2f64.powi(64));
+        return (mantissa, exponent - 64);
+    }
+    let mantissa = f64::from_bits((bits & !(0x7ff << 52)) | (1022 << 52));
+    (mantissa, exponent - 1022)
+}
+
+pub(crate) fn hash_bytes(bytes: &[u8]) -> i64 {
+    // FNV-1a: deterministic, which CPython's randomized hash is not required to be.
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    let result = hash as i64;
+    if result == -1 {
+        -2
+    } else {
+        result
+    }
+}
+
+/// CPython's tuple hash, a variant of xxHash.
+fn hash_tuple(hashes: &[i64]) -> i64 {
+    const PRIME_1: u64 = 11400714785074694791;
+    const PRIME_2: u64 = 14029467366897019727;
+    const PRIME_5: u64 = 2870177450012600261;
+    let mut acc = PRIME_5;
+    for hash in hashes {
+        acc = acc.wrapping_add((*hash as u64).wrapping_mul(PRIME_2));
+        acc = acc.rotate_left(31);
+        acc = acc.wrapping_mul(PRIME_1);
+    }
+    acc = acc.wrapping_add(hashes.len() as u64 ^ (PRIME_5 ^ 3527539));
+    if acc as i64 == -1 {
+        1546275796
+    } else {
+        acc as i64
+    }
+}
+
+/// CPython's frozenset hash, which does not depend on the order of the items.
+fn hash_frozenset(table: &PyDict) -> i64 {
+    let shuffle = |h: u64| ((h ^ 89869747) ^ (h << 16)).wrapping_mul(3644798167);
+    let mut hash: u64 = 0;
+    for entry in table.iter() {
+        hash ^= shuffle(entry.hash as u64);
+    }
+    hash ^= (table.len() as u64 + 1).wrapping_mul(1927868237);
+    hash ^= (hash >> 11) ^ (hash >> 25);
+    hash = hash.wrapping_mul(69069).wrapping_add(907133923);
+    if hash as i64 == -1 {
+        590923713
+    } else {
+        hash as i64
+    }
+}
+
+pub(crate) fn identity_hash(obj: &PyObjectRef) -> i64 {
+    (std::rc::Rc::as_ptr(obj) as usize >> 4) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::object::{Payload, PyInt, PyObjectRef};
+    use super::super::VirtualMachine;
+    use crate::bigint::BigInt;
+    use crate::compiler::bytecode::BinaryOperator;
+
+    fn is_small(obj: &PyObjectRef) -> bool {
+        matches!(obj.payload, Payload::Int(PyInt::Small(_)))
+    }
+
+    #[test]
+    fn ints_grow_and_shrink_at_the_i64_boundary() {
+        let vm = VirtualMachine::capturing();
+        let (max, min, one) = (vm.new_int(i64::MAX), vm.new_int(i64::MIN), vm.new_int(1));
+        let op = |a: &PyObjectRef, b: &PyObjectRef, op| vm.binary_op(a, b, op).unwrap_or_else(|_| panic!("the op raised"));
+        let above = op(&max, &one, BinaryOperator::Add);
+        assert!(!is_small(&above));
+        assert_eq!(above.as_int(), Some(&BigInt::from(i64::MAX) + &BigInt::from(1)));
+        let back = op(&above, &one, BinaryOperator::Subtract);
+        assert!(is_small(&back));
+        assert_eq!(back.as_small_int(), Some(i64::MAX));
+        let below = op(&min, &one, BinaryOperator::Subtract);
+        assert!(!is_small(&below));
+        assert!(is_small(&op(&below, &one, BinaryOperator::Add)));
+        assert!(!is_small(&op(&min, &vm.new_int(-1), BinaryOperator::FloorDivide)));
+        assert!(!is_small(&op(&max, &vm.new_int(2), BinaryOperator::Multiply)));
+
+        let source = "\
+big = 2 ** 63
+print(big, big - 1, -2 ** 63 - 1, (-2 ** 63) // -1, abs(-2 ** 63), -(-2 ** 63), 3 ** 40)
+n = 2 ** 62
+n += n
+print(n, n == 2 ** 63, n - 1 < n, hash(n - 1) == hash(9223372036854775807), {n - 1: 'x'}[2 ** 63 - 1])
+print(hash(-2 ** 61), hash(-1), 2 ** 64 // 2 ** 32, (1 << 63) >> 1, float(2 ** 63 - 1), 10 ** 20 % 7)
+";
+        vm.run_main(source, "<test>").unwrap_or_else(|_| panic!("the program raised"));
+        assert_eq!(
+            vm.take_output(),
+            "9223372036854775808 9223372036854775807 -9223372036854775809 9223372036854775808 \
+             9223372036854775808 9223372036854775808 12157665459056928801\n\
+             9223372036854775808 True True True x\n\
+             -2 -2 4294967296 4611686018427387904 9.223372036854776e+18 2\n"
+        );
+    }
+
+    #[test]
+    fn small_complex_powers_multiply() {
+        let vm = VirtualMachine::capturing();
+        let source = "\
+print(1j ** 2, 1j ** 3, (1 + 1j) ** -2, (2 + 3j) ** 0, (1.5 - 2j) ** 7, 1j ** 2.0)
+print((1 + 2j) / (3 + 4j), (1e300 + 1e300j) / (1e300 + 1e300j))
+";
+        vm.run_main(source, "<test>").unwrap_or_else(|_| panic!("the program raised"));
+        assert_eq!(
+            vm.take_output(),
+            "(-1+0j) (-0-1j) -0.5j (1+0j) (597.2109375-125.96875j) (-1+0j)\n(0.44+0.08j) (1+0j)\n"
+        );
+    }
+}
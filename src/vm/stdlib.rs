@@ -0,0 +1,202 @@
+//! The modules implemented in Rust: `sys` and `time`.
+
+use std::io::Write;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::Lazy;
+
+use super::object::{FuncArgs, Payload, PyObjectRef, PyResult};
+use super::VirtualMachine;
+
+/// The start of `time.perf_counter()` and `time.monotonic()`.
+static START: Lazy<Instant> = Lazy::new(Instant::now);
+
+/// `write()` of `sys.stdout` and `sys.stderr`.
+fn stream_write(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let [stream, text] = args.args.as_slice() else {
+        return Err(vm.new_type_error(format!("write() takes exactly one argument ({} given)", args.args.len().saturating_sub(1))));
+    };
+    let Some(text) = text.as_str() else {
+        return Err(vm.new_type_error(format!("write() argument must be str, not {}", vm.class_name(text))));
+    };
+    let written = if vm.is_stderr(stream) {
+        vm.flush_stdout().and_then(|_| std::io::stderr().write_all(text.as_bytes()))
+    } else {
+        vm.write_stdout(text)
+    };
+    if let Err(err) = written {
+        return Err(vm.new_exception_msg(&vm.exceptions.os_error, format!("[Errno {}] {}", err.raw_os_error().unwrap_or(5), err)));
+    }
+    Ok(vm.new_int(text.chars().count() as i64))
+}
+
+fn stream_flush(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    if !args.args.first().is_some_and(|stream| vm.is_stderr(stream)) {
+        let _ = vm.flush_stdout();
+    }
+    Ok(vm.none())
+}
+
+fn sys_exit(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    if args.args.len() > 1 {
+        return Err(vm.new_type_error(format!("exit expected at most 1 argument, got {}", args.args.len())));
+    }
+    Err(vm.new_exception(&vm.exceptions.system_exit, args.args))
+}
+
+fn sys_getrecursionlimit(vm: &VirtualMachine, _args: FuncArgs) -> PyResult {
+    Ok(vm.new_int(vm.recursion_limit.get() as i64))
+}
+
+fn sys_setrecursionlimit(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let [limit] = args.args.as_slice() else {
+        return Err(vm.new_type_error(format!("setrecursionlimit() takes exactly one argument ({} given)", args.args.len())));
+    };
+    if matches!(limit.payload, Payload::Float(_)) {
+        return Err(vm.new_type_error("'float' object cannot be interpreted as an integer".to_string()));
+    }
+    let limit = vm.to_index(limit)?.to_i64().unwrap_or(i64::MAX);
+    if limit < 1 {
+        return Err(vm.new_value_error("recursion limit must be greater or equal than 1".to_string()));
+    }
+    vm.recursion_limit.set(limit.min(super::MAX_RECURSION_LIMIT as i64) as usize);
+    Ok(vm.none())
+}
+
+fn sys_get_int_max_str_digits(vm: &VirtualMachine, _args: FuncArgs) -> PyResult {
+    Ok(vm.new_int(vm.int_max_str_digits.get() as i64))
+}
+
+fn sys_set_int_max_str_digits(vm: &VirtualMachine, mut args: FuncArgs) -> PyResult {
+    let keyword = args.take_kwarg("maxdigits");
+    let maxdigits = match (keyword.as_ref(), args.args.as_slice()) {
+        (Some(maxdigits), []) | (None, [maxdigits]) => maxdigits.clone(),
+        _ => {
+            return Err(vm.new_type_error(
+                "set_int_max_str_digits() takes exactly one argument (maxdigits)".to_string(),
+            ))
+        }
+    };
+    let limit = vm.to_index(&maxdigits)?.to_i64().unwrap_or(i64::MAX);
+    if limit != 0 && limit < super::INT_MAX_STR_DIGITS_THRESHOLD as i64 {
+        return Err(vm.new_value_error(format!(
+            "maxdigits must be 0 or larger than {}",
+            super::INT_MAX_STR_DIGITS_THRESHOLD
+        )));
+    }
+    vm.int_max_str_digits.set(limit as usize);
+    Ok(vm.none())
+}
+
+fn time_time(vm: &VirtualMachine, _args: FuncArgs) -> PyResult {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    Ok(vm.new_float(now.as_secs_f64()))
+}
+
+fn time_time_ns(vm: &VirtualMachine, _args: FuncArgs) -> PyResult {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    Ok(vm.new_int(now.as_nanos() as i64))
+}
+
+fn time_perf_counter(vm: &VirtualMachine, _args: FuncArgs) -> PyResult {
+    Ok(vm.new_float(START.elapsed().as_secs_f64()))
+}
+
+fn time_sleep(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let [seconds] = args.args.as_slice() else {
+        return Err(vm.new_type_error(format!("time.sleep() takes exactly one argument ({} given)", args.args.len())));
+    };
+    let seconds = vm.to_float(seconds)?;
+    if seconds < 0.0 {
+        return Err(vm.new_value_error("sleep length must be non-negative".to_string()));
+    }
+    let _ = vm.flush_stdout();
+    std::thread::sleep(std::time::Duration::from_secs_f64(seconds));
+    Ok(vm.none())
+}
+
+impl VirtualMachine {
+    pub(crate) fn init_stdlib(&self) {
+        let sys = self.new_module("sys");
+        let dict = sys.dict.clone().expect("module dict");
+        let stream_type = self.new_builtin_type("_io", "TextIOWrapper", true);
+        self.add_method(&stream_type, "write", stream_write);
+        self.add_method(&stream_type, "flush", stream_flush);
+        for name in ["stdout", "stderr"] {
+            let stream = self.new_object(Payload::Object, &stream_type);
+            let stream_dict = stream.dict.as_ref().expect("stream dict");
+            self.dict_set_str(stream_dict, "name", self.new_str(format!("<{}>", name)));
+            self.dict_set_str(stream_dict, "encoding", self.new_str("utf-8".to_string()));
+            self.dict_set_str(&dict, name, stream.clone());
+            self.dict_set_str(&dict, &format!("__{}__", name), stream);
+        }
+        let functions: &[(&'static str, super::object::NativeFunction)] = &[
+            ("exit", sys_exit),
+            ("get_int_max_str_digits", sys_get_int_max_str_digits),
+            ("getrecursionlimit", sys_getrecursionlimit),
+            ("set_int_max_str_digits", sys_set_int_max_str_digits),
+            ("setrecursionlimit", sys_setrecursionlimit),
+        ];
+        for (name, function) in functions {
+            self.dict_set_str(&dict, name, self.new_builtin_function(name, *function));
+        }
+        self.dict_set_str(&dict, "modules", self.sys_modules.clone());
+        self.dict_set_str(&dict, "path", self.new_list(vec![]));
+        self.dict_set_str(&dict, "argv", self.new_list(vec![]));
+        self.dict_set_str(&dict, "platform", self.new_str(std::env::consts::OS.to_string()));
+        self.dict_set_str(&dict, "byteorder", self.new_str("little".to_string()));
+        self.dict_set_str(&dict, "maxsize", self.new_int(i64::MAX));
+        self.dict_set_str(&dict, "version", self.new_str(super::PYTHON_VERSION.to_string()));
+        self.add_module(sys);
+
+        let time = self.new_module("time");
+        let dict = time.dict.clone().expect("module dict");
+        let functions: &[(&'static str, super::object::NativeFunction)] = &[
+            ("time", time_time),
+            ("time_ns", time_time_ns),
+            ("perf_counter", time_perf_counter),
+            ("monotonic", time_perf_counter),
+            ("sleep", time_sleep),
+        ];
+        for (name, function) in functions {
+            self.dict_set_str(&dict, name, self.new_builtin_function(name, *function));
+        }
+        self.add_module(time);
+    }
+
+    fn add_module(&self, module: PyObjectRef) {
+        let Payload::Module { name } = &module.payload else { unreachable!("not a module") };
+        self.dict_set_str(&self.sys_modules, name, module.clone());
+    }
+
+    fn sys_module(&self) -> PyObjectRef {
+        self.dict_get_str(&self.sys_modules, "sys").expect("sys is imported at startup")
+    }
+
+    /// `sys.<name>`, which programs can replace.
+    pub fn sys_attribute(&self, name: &str) -> PyResult {
+        let sys = self.sys_module();
+        match self.dict_get_str(sys.dict.as_ref().expect("module dict"), name) {
+            Some(value) => Ok(value),
+            None => Err(self.new_runtime_error(format!("lost sys.{}", name))),
+        }
+    }
+
+    fn is_stderr(&self, stream: &PyObjectRef) -> bool {
+        let sys = self.sys_module();
+        self.dict_get_str(sys.dict.as_ref().expect("module dict"), "__stderr__").is_some_and(|stderr| stderr.is(stream))
+    }
+
+    pub fn set_argv(&self, argv: Vec<String>) {
+        let argv = self.new_list(argv.into_iter().map(|arg| self.new_str(arg)).collect());
+        self.dict_set_str(self.sys_module().dict.as_ref().expect("module dict"), "argv", argv);
+    }
+
+    /// Puts the directory of the script first on `sys.path`, as `python3 script.py` does.
+    pub fn set_script_directory(&self, directory: &str) {
+        let path = self.sys_attribute("path").expect("sys.path");
+        if let Payload::List(items) = &path.payload {
+            items.borrow_mut().insert(0, self.new_str(directory.to_string()));
+        }
+    }
+}
@@ -0,0 +1,1219 @@
+//! The `str` type and its methods.
+//!
+//! A `PyStr` is UTF-8 text that is indexed by code point.  An ASCII string indexes its bytes
+//! directly; any other string keeps the byte offset of each code point as well, so indexing
+//! and slicing stay O(1) as they are for CPython's compact strings.
+
+use std::fmt;
+use std::ops::Deref;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use super::builtins::check_args;
+use super::format::is_printable;
+use super::methods::receiver;
+use super::object::{FuncArgs, NativeFunction, Payload, PyObjectRef, PyResult};
+use super::VirtualMachine;
+
+pub struct PyStr {
+    value: String,
+    /// The byte offset of each code point, for strings that are not ASCII.
+    offsets: Option<Box<[usize]>>,
+}
+
+impl PyStr {
+    pub fn new(value: String) -> Self {
+        let offsets = if value.is_ascii() { None } else { Some(value.char_indices().map(|(idx, _)| idx).collect()) };
+        Self { value, offsets }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.value
+    }
+
+    /// The number of code points, `len(s)`.
+    pub fn char_len(&self) -> usize {
+        match &self.offsets {
+            Some(offsets) => offsets.len(),
+            None => self.value.len(),
+        }
+    }
+
+    /// The byte offset of code point `index`, which may be the length.
+    pub fn byte_offset(&self, index: usize) -> usize {
+        match &self.offsets {
+            Some(offsets) => offsets.get(index).copied().unwrap_or(self.value.len()),
+            None => index.min(self.value.len()),
+        }
+    }
+
+    /// The code point index of a byte offset on a character boundary.
+    pub fn char_index(&self, byte_offset: usize) -> usize {
+        match &self.offsets {
+            Some(offsets) => offsets.partition_point(|offset| *offset < byte_offset),
+            None => byte_offset,
+        }
+    }
+
+    pub fn char_at(&self, index: usize) -> char {
+        let start = self.byte_offset(index);
+        self.value[start..].chars().next().expect("index in range")
+    }
+
+    /// The code points `start..end`.
+    pub fn substring(&self, start: usize, end: usize) -> &str {
+        &self.value[self.byte_offset(start)..self.byte_offset(end.max(start))]
+    }
+
+    /// The code points of a slice with `count` items from `start` in steps of `step`.
+    pub fn slice(&self, start: i64, step: i64, count: usize) -> String {
+        if step == 1 {
+            return self.substring(start as usize, start as usize + count).to_string();
+        }
+        (0..count as i64).map(|i| self.char_at((start + i * step) as usize)).collect()
+    }
+}
+
+impl Deref for PyStr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.value
+    }
+}
+
+impl fmt::Display for PyStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.value)
+    }
+}
+
+impl From<String> for PyStr {
+    fn from(value: String) -> Self {
+        Self::new(value)
+    }
+}
+
+/// The characters `str.isspace()` accepts: Unicode white space and the ASCII information
+/// separators.
+fn is_space(c: char) -> bool {
+    c.is_whitespace() || ('\x1c'..='\x1f').contains(&c)
+}
+
+/// The line boundaries of `str.splitlines()`.
+fn is_line_break(c: char) -> bool {
+    matches!(c, '\n' | '\r' | '\x0b' | '\x0c' | '\x1c' | '\x1d' | '\x1e' | '\u{85}' | '\u{2028}' | '\u{2029}')
+}
+
+static LETTER: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\p{L}$").expect("regex"));
+static DECIMAL: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\p{Nd}$").expect("regex"));
+static NUMBER: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[\p{Nd}\p{Nl}\p{No}]$").expect("regex"));
+static IDENTIFIER: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^[\p{XID_Start}_]\p{XID_Continue}*$").expect("regex"));
+
+fn in_class(class: &Regex, c: char) -> bool {
+    let mut buf = [0; 4];
+    class.is_match(c.encode_utf8(&mut buf))
+}
+
+fn is_alpha(c: char) -> bool {
+    c.is_ascii_alphabetic() || !c.is_ascii() && in_class(&LETTER, c)
+}
+
+fn is_decimal(c: char) -> bool {
+    c.is_ascii_digit() || !c.is_ascii() && in_class(&DECIMAL, c)
+}
+
+/// Decimal digits and the other characters with a digit value, such as superscripts.
+fn is_digit(c: char) -> bool {
+    is_decimal(c)
+        || matches!(
+            c as u32,
+            0xb2 | 0xb3 | 0xb9 | 0x1369..=0x1371 | 0x19da | 0x2070 | 0x2074..=0x2079 | 0x2080..=0x2089
+                | 0x2460..=0x2468 | 0x2474..=0x247c | 0x2488..=0x2490 | 0x24ea | 0x24f5..=0x24fd | 0x24ff
+                | 0x2776..=0x277e | 0x2780..=0x2788 | 0x278a..=0x2792 | 0x10a40..=0x10a43 | 0x1f100..=0x1f10a
+        )
+}
+
+/// Digits, letter numbers such as Roman numerals, fractions and the CJK numerals.
+fn is_numeric(c: char) -> bool {
+    is_digit(c) || !c.is_ascii() && (in_class(&NUMBER, c) || "〇一二三四五六七八九十百千万萬億亿兆零壹貳贰叁參肆伍陸陆柒捌玖拾佰仟廿卅卌".contains(c))
+}
+
+/// The titlecase letters, which are neither upper nor lower case.
+fn is_titlecase(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x1c5 | 0x1c8 | 0x1cb | 0x1f2 | 0x1f88..=0x1f8f | 0x1f98..=0x1f9f | 0x1fa8..=0x1faf | 0x1fbc | 0x1fcc | 0x1ffc
+    )
+}
+
+fn is_cased(c: char) -> bool {
+    c.is_lowercase() || c.is_uppercase() || is_titlecase(c)
+}
+
+/// Appends the titlecase form of `c`: a single titlecase letter where Unicode has one,
+/// otherwise the uppercase form with the letters after its first cased one in lower case,
+/// as in `'ß'.title() == 'Ss'`.
+fn push_title(out: &mut String, c: char) {
+    let title = match c as u32 {
+        0x1c4..=0x1c6 => Some('\u{1c5}'),
+        0x1c7..=0x1c9 => Some('\u{1c8}'),
+        0x1ca..=0x1cc => Some('\u{1cb}'),
+        0x1f1..=0x1f3 => Some('\u{1f2}'),
+        code @ (0x1f80..=0x1f87 | 0x1f90..=0x1f97 | 0x1fa0..=0x1fa7) => char::from_u32(code + 8),
+        0x1fb3 => Some('\u{1fbc}'),
+        0x1fc3 => Some('\u{1fcc}'),
+        0x1ff3 => Some('\u{1ffc}'),
+        _ if is_titlecase(c) => Some(c),
+        _ => None,
+    };
+    if let Some(title) = title {
+        out.push(title);
+        return;
+    }
+    let mut seen_cased = false;
+    for upper in c.to_uppercase() {
+        if seen_cased {
+            out.extend(upper.to_lowercase());
+        } else {
+            out.push(upper);
+        }
+        seen_cased |= is_cased(upper);
+    }
+}
+
+/// Appends the lowercase form of `chars[idx]`.  A capital sigma that ends a word becomes
+/// the final form `ς`.
+fn push_lower(out: &mut String, chars: &[char], idx: usize) {
+    let c = chars[idx];
+    let final_sigma = c == 'Σ'
+        && idx > 0
+        && is_cased(chars[idx - 1])
+        && !chars.get(idx + 1).is_some_and(|next| is_cased(*next));
+    if final_sigma {
+        out.push('ς');
+    } else {
+        out.extend(c.to_lowercase());
+    }
+}
+
+/// Appends the case folded form of `c`: lower case, except where full case folding differs
+/// from it, as in `'ß'.casefold() == 'ss'`.
+fn push_casefold(out: &mut String, c: char) {
+    match c as u32 {
+        // Dotless i folds to itself and Cherokee folds to its upper case letters.
+        0x131 | 0x13a0..=0x13f5 => out.push(c),
+        code @ 0x13f8..=0x13fd => out.push(char::from_u32(code - 8).expect("Cherokee letter")),
+        code @ 0xab70..=0xabbf => out.push(char::from_u32(code - 0xab70 + 0x13a0).expect("Cherokee letter")),
+        _ => {
+            for lower in c.to_lowercase() {
+                for upper in lower.to_uppercase() {
+                    out.extend(upper.to_lowercase());
+                }
+            }
+        }
+    }
+}
+
+fn title(s: &str) -> String {
+    let chars: Vec<char> = s.chars().collect();
+    let mut out = String::with_capacity(s.len());
+    let mut previous_cased = false;
+    for (idx, c) in chars.iter().enumerate() {
+        if previous_cased {
+            push_lower(&mut out, &chars, idx);
+        } else {
+            push_title(&mut out, *c);
+        }
+        previous_cased = is_cased(*c);
+    }
+    out
+}
+
+fn is_title(s: &str) -> bool {
+    let mut cased = false;
+    let mut previous_cased = false;
+    for c in s.chars() {
+        if c.is_uppercase() || is_titlecase(c) {
+            if previous_cased {
+                return false;
+            }
+            previous_cased = true;
+            cased = true;
+        } else if c.is_lowercase() {
+            if !previous_cased {
+                return false;
+            }
+            previous_cased = true;
+            cased = true;
+        } else {
+            previous_cased = false;
+        }
+    }
+    cased
+}
+
+/// `str.split()` and `str.rsplit()` without a separator: runs of white space separate
+/// the parts, and the part left after `limit` splits keeps its inner white space.
+fn split_whitespace(s: &str, limit: usize, reverse: bool) -> Vec<&str> {
+    let mut parts = vec![];
+    if reverse {
+        let mut rest = s.trim_end_matches(is_space);
+        while !rest.is_empty() {
+            if parts.len() == limit {
+                parts.push(rest);
+                break;
+            }
+            match rest.char_indices().rev().find(|(_, c)| is_space(*c)) {
+                Some((pos, c)) => {
+                    parts.push(&rest[pos + c.len_utf8()..]);
+                    rest = rest[..pos].trim_end_matches(is_space);
+                }
+                None => {
+                    parts.push(rest);
+                    break;
+                }
+            }
+        }
+        parts.reverse();
+    } else {
+        let mut rest = s.trim_start_matches(is_space);
+        while !rest.is_empty() {
+            if parts.len() == limit {
+                parts.push(rest);
+                break;
+            }
+            match rest.find(is_space) {
+                Some(pos) => {
+                    parts.push(&rest[..pos]);
+                    rest = rest[pos..].trim_start_matches(is_space);
+                }
+                None => {
+                    parts.push(rest);
+                    break;
+                }
+            }
+        }
+    }
+    parts
+}
+
+fn expand_tabs(s: &str, tabsize: i64) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut column = 0i64;
+    for c in s.chars() {
+        match c {
+            '\t' => {
+                if tabsize > 0 {
+                    let spaces = tabsize - column % tabsize;
+                    out.push_str(&" ".repeat(spaces as usize));
+                    column += spaces;
+                }
+            }
+            '\n' | '\r' => {
+                out.push(c);
+                column = 0;
+            }
+            _ => {
+                out.push(c);
+                column += 1;
+            }
+        }
+    }
+    out
+}
+
+/// How an unencodable character appears in a `UnicodeEncodeError` message and in the
+/// output of the `backslashreplace` error handler.
+fn escape_char(c: char) -> String {
+    match c as u32 {
+        code @ 0..=0xff => format!("\\x{:02x}", code),
+        code @ 0x100..=0xffff => format!("\\u{:04x}", code),
+        code => format!("\\U{:08x}", code),
+    }
+}
+
+fn is_str(payload: &Payload) -> bool {
+    matches!(payload, Payload::Str(_))
+}
+
+fn as_pystr(obj: &PyObjectRef) -> &PyStr {
+    match &obj.payload {
+        Payload::Str(s) => s,
+        _ => unreachable!("not a str"),
+    }
+}
+
+/// A str argument, with the error most str methods give for other types.
+fn str_arg<'a>(vm: &VirtualMachine, obj: &'a PyObjectRef) -> PyResult<&'a PyStr> {
+    match &obj.payload {
+        Payload::Str(s) => Ok(s),
+        _ => Err(vm.new_type_error(format!("must be str, not {}", vm.class_name(obj)))),
+    }
+}
+
+/// Checks the argument count of the methods that report it as `takes at most N arguments
+/// (M given)`.
+fn check_count(vm: &VirtualMachine, name: &str, args: &FuncArgs, min: usize, max: usize) -> PyResult<()> {
+    if !args.kwargs.is_empty() {
+        return Err(vm.new_type_error(format!("{}() takes no keyword arguments", name)));
+    }
+    let count = args.args.len();
+    let (bound, limit) = match count {
+        _ if count < min => ("least", min),
+        _ if count > max => ("most", max),
+        _ => return Ok(()),
+    };
+    Err(vm.new_type_error(format!(
+        "{}() takes at {} {} argument{} ({} given)",
+        name,
+        bound,
+        limit,
+        if limit == 1 { "" } else { "s" },
+        count
+    )))
+}
+
+/// The arguments of a method that takes each of `names` by position or by keyword.
+fn named_args(vm: &VirtualMachine, name: &str, mut args: FuncArgs, names: &[&str]) -> PyResult<Vec<Option<PyObjectRef>>> {
+    if args.args.len() > names.len() {
+        return Err(vm.new_type_error(format!(
+            "{}() takes at most {} argument{} ({} given)",
+            name,
+            names.len(),
+            if names.len() == 1 { "" } else { "s" },
+            args.args.len()
+        )));
+    }
+    let mut values: Vec<Option<PyObjectRef>> = (0..names.len()).map(|idx| args.args.get(idx).cloned()).collect();
+    for (idx, key) in names.iter().enumerate() {
+        if let Some(value) = args.take_kwarg(key) {
+            if values[idx].is_some() {
+                return Err(vm.new_type_error(format!(
+                    "argument for {}() given by name ('{}') and position ({})",
+                    name,
+                    key,
+                    idx + 1
+                )));
+            }
+            values[idx] = Some(value);
+        }
+    }
+    if let Some((key, _)) = args.kwargs.first() {
+        return Err(vm.new_type_error(format!("'{}' is an invalid keyword argument for {}()", key, name)));
+    }
+    Ok(values)
+}
+
+/// An int argument such as a width or a count, clamped to the i64 range.
+fn int_arg(vm: &VirtualMachine, obj: &PyObjectRef) -> PyResult<i64> {
+    let value = vm.to_index(obj)?;
+    Ok(value.to_i64().unwrap_or(if value.is_negative() { i64::MIN } else { i64::MAX }))
+}
+
+/// The `start` and `end` arguments of `find()` and similar methods as code point indices,
+/// adjusted as slice bounds are, except that `start` may lie past the end.
+fn find_bounds(
+    vm: &VirtualMachine,
+    s: &PyStr,
+    start: Option<&PyObjectRef>,
+    end: Option<&PyObjectRef>,
+) -> PyResult<(usize, usize)> {
+    let len = s.char_len() as i64;
+    let index = |value: Option<&PyObjectRef>, default: i64| -> PyResult<i64> {
+        let Some(value) = value.filter(|value| !vm.is_none(value)) else { return Ok(default) };
+        let value = vm.to_index(value).map_err(|_| {
+            vm.new_type_error("slice indices must be integers or None or have an __index__ method".to_string())
+        })?;
+        let value = value.to_i64().unwrap_or(if value.is_negative() { i64::MIN / 2 } else { i64::MAX / 2 });
+        Ok(if value < 0 { (value + len).max(0) } else { value })
+    };
+    let start = index(start, 0)?;
+    let end = index(end, len)?.min(len);
+    Ok((start as usize, end as usize))
+}
+
+/// `find()` and `rfind()`: the code point index of `sub` in `s[start:end]`, or -1.
+fn find(vm: &VirtualMachine, s: &PyStr, name: &str, args: &FuncArgs, reverse: bool) -> PyResult<i64> {
+    check_count(vm, name, args, 1, 3)?;
+    let sub = str_arg(vm, &args.args[0])?;
+    let (start, end) = find_bounds(vm, s, args.args.get(1), args.args.get(2))?;
+    if start > end {
+        return Ok(-1);
+    }
+    let haystack = s.substring(start, end);
+    let found = if reverse { haystack.rfind(sub.as_str()) } else { haystack.find(sub.as_str()) };
+    Ok(match found {
+        Some(pos) => s.char_index(s.byte_offset(start) + pos) as i64,
+        None => -1,
+    })
+}
+
+fn str_find(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = receiver(vm, args, "str", "find", is_str)?;
+    Ok(vm.new_int(find(vm, as_pystr(&obj), "find", &args, false)?))
+}
+
+fn str_rfind(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = receiver(vm, args, "str", "rfind", is_str)?;
+    Ok(vm.new_int(find(vm, as_pystr(&obj), "rfind", &args, true)?))
+}
+
+fn str_index(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = receiver(vm, args, "str", "index", is_str)?;
+    match find(vm, as_pystr(&obj), "index", &args, false)? {
+        -1 => Err(vm.new_value_error("substring not found".to_string())),
+        idx => Ok(vm.new_int(idx)),
+    }
+}
+
+fn str_rindex(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = receiver(vm, args, "str", "rindex", is_str)?;
+    match find(vm, as_pystr(&obj), "rindex", &args, true)? {
+        -1 => Err(vm.new_value_error("substring not found".to_string())),
+        idx => Ok(vm.new_int(idx)),
+    }
+}
+
+fn str_count(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = receiver(vm, args, "str", "count", is_str)?;
+    check_count(vm, "count", &args, 1, 3)?;
+    let s = as_pystr(&obj);
+    let sub = str_arg(vm, &args.args[0])?;
+    let (start, end) = find_bounds(vm, s, args.args.get(1), args.args.get(2))?;
+    if start > end {
+        return Ok(vm.new_int(0));
+    }
+    let count = match sub.is_empty() {
+        true => end - start + 1,
+        false => s.substring(start, end).matches(sub.as_str()).count(),
+    };
+    Ok(vm.new_int(count as i64))
+}
+
+/// `startswith()` and `endswith()`, which take a str or a tuple of them.
+fn affix_match(vm: &VirtualMachine, args: FuncArgs, name: &str, suffix: bool) -> PyResult {
+    let (obj, args) = receiver(vm, args, "str", name, is_str)?;
+    check_count(vm, name, &args, 1, 3)?;
+    let s = as_pystr(&obj);
+    let (start, end) = find_bounds(vm, s, args.args.get(1), args.args.get(2))?;
+    let affixes = match &args.args[0].payload {
+        Payload::Str(_) => vec![args.args[0].clone()],
+        Payload::Tuple(items) => items.clone(),
+        _ => {
+            return Err(vm.new_type_error(format!(
+                "{} first arg must be str or a tuple of str, not {}",
+                name,
+                vm.class_name(&args.args[0])
+            )))
+        }
+    };
+    for affix in &affixes {
+        let Payload::Str(affix) = &affix.payload else {
+            return Err(vm.new_type_error(format!(
+                "tuple for {} must only contain str, not {}",
+                name,
+                vm.class_name(affix)
+            )));
+        };
+        if start > end {
+            continue;
+        }
+        let part = s.substring(start, end);
+        if suffix && part.ends_with(affix.as_str()) || !suffix && part.starts_with(affix.as_str()) {
+            return Ok(vm.new_bool(true));
+        }
+    }
+    Ok(vm.new_bool(false))
+}
+
+fn str_startswith(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    affix_match(vm, args, "startswith", false)
+}
+
+fn str_endswith(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    affix_match(vm, args, "endswith", true)
+}
+
+fn split(vm: &VirtualMachine, args: FuncArgs, name: &str, reverse: bool) -> PyResult {
+    let (obj, args) = receiver(vm, args, "str", name, is_str)?;
+    let s = as_pystr(&obj);
+    let values = named_args(vm, name, args, &["sep", "maxsplit"])?;
+    let sep = match &values[0] {
+        Some(sep) if !vm.is_none(sep) => match &sep.payload {
+            Payload::Str(sep) => Some(sep.as_str()),
+            _ => return Err(vm.new_type_error(format!("must be str or None, not {}", vm.class_name(sep)))),
+        },
+        _ => None,
+    };
+    let maxsplit = match &values[1] {
+        Some(maxsplit) => int_arg(vm, maxsplit)?,
+        None => -1,
+    };
+    let limit = if maxsplit < 0 { usize::MAX } else { maxsplit as usize };
+    let parts: Vec<&str> = match sep {
+        Some("") => return Err(vm.new_value_error("empty separator".to_string())),
+        Some(sep) if reverse => {
+            let mut parts: Vec<&str> = s.rsplitn(limit.saturating_add(1), sep).collect();
+            parts.reverse();
+            parts
+        }
+        Some(sep) => s.splitn(limit.saturating_add(1), sep).collect(),
+        None => split_whitespace(s, limit, reverse),
+    };
+    Ok(vm.new_list(parts.into_iter().map(|part| vm.new_str(part.to_string())).collect()))
+}
+
+fn str_split(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    split(vm, args, "split", false)
+}
+
+fn str_rsplit(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    split(vm, args, "rsplit", true)
+}
+
+fn str_splitlines(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = receiver(vm, args, "str", "splitlines", is_str)?;
+    let s = as_pystr(&obj);
+    let values = named_args(vm, "splitlines", args, &["keepends"])?;
+    let keepends = match &values[0] {
+        Some(keepends) => int_arg(vm, keepends)? != 0,
+        None => false,
+    };
+    let mut lines = vec![];
+    let mut start = 0;
+    let mut chars = s.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        if !is_line_break(c) {
+            continue;
+        }
+        let mut end = idx + c.len_utf8();
+        if c == '\r' && chars.peek().is_some_and(|(_, next)| *next == '\n') {
+            chars.next();
+            end += 1;
+        }
+        lines.push(vm.new_str(s[start..if keepends { end } else { idx }].to_string()));
+        start = end;
+    }
+    if start < s.len() {
+        lines.push(vm.new_str(s[start..].to_string()));
+    }
+    Ok(vm.new_list(lines))
+}
+
+fn str_join(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = receiver(vm, args, "str", "join", is_str)?;
+    check_args(vm, "str.join", &args, 1, 1)?;
+    let iter = vm
+        .get_iter(&args.args[0])
+        .map_err(|_| vm.new_type_error("can only join an iterable".to_string()))?;
+    let mut out = String::new();
+    for (idx, item) in vm.collect(&iter)?.iter().enumerate() {
+        let Payload::Str(text) = &item.payload else {
+            return Err(vm.new_type_error(format!(
+                "sequence item {}: expected str instance, {} found",
+                idx,
+                vm.class_name(item)
+            )));
+        };
+        if idx > 0 {
+            out.push_str(as_pystr(&obj));
+        }
+        out.push_str(text);
+    }
+    Ok(vm.new_str(out))
+}
+
+fn strip(vm: &VirtualMachine, args: FuncArgs, name: &str, left: bool, right: bool) -> PyResult {
+    let (obj, args) = receiver(vm, args, "str", name, is_str)?;
+    check_args(vm, name, &args, 0, 1)?;
+    let chars: Option<Vec<char>> = match args.args.first() {
+        Some(chars) if !vm.is_none(chars) => match &chars.payload {
+            Payload::Str(chars) => Some(chars.chars().collect()),
+            _ => return Err(vm.new_type_error(format!("{} arg must be None or str", name))),
+        },
+        _ => None,
+    };
+    let stripped = |c: char| match &chars {
+        Some(chars) => chars.contains(&c),
+        None => is_space(c),
+    };
+    let mut result = as_pystr(&obj).as_str();
+    if left {
+        result = result.trim_start_matches(stripped);
+    }
+    if right {
+        result = result.trim_end_matches(stripped);
+    }
+    Ok(vm.new_str(result.to_string()))
+}
+
+fn str_strip(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    strip(vm, args, "strip", true, true)
+}
+
+fn str_lstrip(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    strip(vm, args, "lstrip", true, false)
+}
+
+fn str_rstrip(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    strip(vm, args, "rstrip", false, true)
+}
+
+fn str_replace(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = receiver(vm, args, "str", "replace", is_str)?;
+    check_args(vm, "replace", &args, 2, 3)?;
+    let text_arg = |idx: usize| match &args.args[idx].payload {
+        Payload::Str(text) => Ok(text.as_str()),
+        _ => Err(vm.new_type_error(format!(
+            "replace() argument {} must be str, not {}",
+            idx + 1,
+            vm.class_name(&args.args[idx])
+        ))),
+    };
+    let (old, new) = (text_arg(0)?, text_arg(1)?);
+    let count = match args.args.get(2) {
+        Some(count) => int_arg(vm, count)?,
+        None => -1,
+    };
+    let s = as_pystr(&obj);
+    let result = if count < 0 { s.replace(old, new) } else { s.replacen(old, new, count as usize) };
+    Ok(vm.new_str(result))
+}
+
+fn partition(vm: &VirtualMachine, args: FuncArgs, name: &str, reverse: bool) -> PyResult {
+    let (obj, args) = receiver(vm, args, "str", name, is_str)?;
+    check_args(vm, &format!("str.{}", name), &args, 1, 1)?;
+    let s = as_pystr(&obj).as_str();
+    let sep = str_arg(vm, &args.args[0])?.as_str();
+    if sep.is_empty() {
+        return Err(vm.new_value_error("empty separator".to_string()));
+    }
+    let found = if reverse { s.rsplit_once(sep) } else { s.split_once(sep) };
+    let (before, sep, after) = match found {
+        Some((before, after)) => (before, sep, after),
+        None if reverse => ("", "", s),
+        None => (s, "", ""),
+    };
+    Ok(vm.new_tuple([before, sep, after].iter().map(|part| vm.new_str(part.to_string())).collect()))
+}
+
+fn str_partition(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    partition(vm, args, "partition", false)
+}
+
+fn str_rpartition(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    partition(vm, args, "rpartition", true)
+}
+
+fn remove_affix(vm: &VirtualMachine, args: FuncArgs, name: &str, suffix: bool) -> PyResult {
+    let (obj, args) = receiver(vm, args, "str", name, is_str)?;
+    check_args(vm, &format!("str.{}", name), &args, 1, 1)?;
+    let Payload::Str(affix) = &args.args[0].payload else {
+        return Err(vm.new_type_error(format!(
+            "{}() argument must be str, not {}",
+            name,
+            vm.class_name(&args.args[0])
+        )));
+    };
+    let s = as_pystr(&obj).as_str();
+    let result = if suffix { s.strip_suffix(affix.as_str()) } else { s.strip_prefix(affix.as_str()) };
+    Ok(vm.new_str(result.unwrap_or(s).to_string()))
+}
+
+fn str_removeprefix(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    remove_affix(vm, args, "removeprefix", false)
+}
+
+fn str_removesuffix(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    remove_affix(vm, args, "removesuffix", true)
+}
+
+/// A method that maps the string to a new one and takes no arguments.
+fn transform(vm: &VirtualMachine, args: FuncArgs, name: &str, map: fn(&str) -> String) -> PyResult {
+    let (obj, args) = receiver(vm, args, "str", name, is_str)?;
+    check_args(vm, &format!("str.{}", name), &args, 0, 0)?;
+    Ok(vm.new_str(map(as_pystr(&obj))))
+}
+
+fn str_upper(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    transform(vm, args, "upper", str::to_uppercase)
+}
+
+fn str_lower(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    transform(vm, args, "lower", str::to_lowercase)
+}
+
+fn str_casefold(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    transform(vm, args, "casefold", |s| {
+        let mut out = String::with_capacity(s.len());
+        s.chars().for_each(|c| push_casefold(&mut out, c));
+        out
+    })
+}
+
+fn str_title(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    transform(vm, args, "title", title)
+}
+
+fn str_capitalize(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    transform(vm, args, "capitalize", |s| {
+        let chars: Vec<char> = s.chars().collect();
+        let mut out = String::with_capacity(s.len());
+        for idx in 0..chars.len() {
+            match idx {
+                0 => push_title(&mut out, chars[0]),
+                _ => push_lower(&mut out, &chars, idx),
+            }
+        }
+        out
+    })
+}
+
+fn str_swapcase(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    transform(vm, args, "swapcase", |s| {
+        let chars: Vec<char> = s.chars().collect();
+        let mut out = String::with_capacity(s.len());
+        for (idx, c) in chars.iter().enumerate() {
+            if c.is_uppercase() {
+                push_lower(&mut out, &chars, idx);
+            } else if c.is_lowercase() {
+                out.extend(c.to_uppercase());
+            } else {
+                out.push(*c);
+            }
+        }
+        out
+    })
+}
+
+/// An `is...()` method that takes no arguments.
+fn predicate(vm: &VirtualMachine, args: FuncArgs, name: &str, test: fn(&str) -> bool) -> PyResult {
+    let (obj, args) = receiver(vm, args, "str", name, is_str)?;
+    check_args(vm, &format!("str.{}", name), &args, 0, 0)?;
+    Ok(vm.new_bool(test(as_pystr(&obj))))
+}
+
+/// Whether `s` is not empty and `test` holds for each of its characters.
+fn all_chars(s: &str, test: fn(char) -> bool) -> bool {
+    !s.is_empty() && s.chars().all(test)
+}
+
+fn str_isalpha(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    predicate(vm, args, "isalpha", |s| all_chars(s, is_alpha))
+}
+
+fn str_isalnum(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    predicate(vm, args, "isalnum", |s| all_chars(s, |c| is_alpha(c) || is_numeric(c)))
+}
+
+fn str_isdecimal(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    predicate(vm, args, "isdecimal", |s| all_chars(s, is_decimal))
+}
+
+fn str_isdigit(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    predicate(vm, args, "isdigit", |s| all_chars(s, is_digit))
+}
+
+fn str_isnumeric(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    predicate(vm, args, "isnumeric", |s| all_chars(s, is_numeric))
+}
+
+fn str_isspace(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    predicate(vm, args, "isspace", |s| all_chars(s, is_space))
+}
+
+fn str_isprintable(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    predicate(vm, args, "isprintable", |s| s.chars().all(is_printable))
+}
+
+fn str_isascii(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    predicate(vm, args, "isascii", str::is_ascii)
+}
+
+fn str_isidentifier(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    predicate(vm, args, "isidentifier", |s| IDENTIFIER.is_match(s))
+}
+
+fn str_isupper(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    predicate(vm, args, "isupper", |s| {
+        s.chars().any(char::is_uppercase) && !s.chars().any(|c| c.is_lowercase() || is_titlecase(c))
+    })
+}
+
+fn str_islower(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    predicate(vm, args, "islower", |s| {
+        s.chars().any(char::is_lowercase) && !s.chars().any(|c| c.is_uppercase() || is_titlecase(c))
+    })
+}
+
+fn str_istitle(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    predicate(vm, args, "istitle", is_title)
+}
+
+/// `ljust()`, `rjust()` and `center()`.
+fn justify(vm: &VirtualMachine, args: FuncArgs, name: &str, align: char) -> PyResult {
+    let (obj, args) = receiver(vm, args, "str", name, is_str)?;
+    check_args(vm, name, &args, 1, 2)?;
+    let width = int_arg(vm, &args.args[0])?.max(0) as usize;
+    let fill = match args.args.get(1) {
+        None => ' ',
+        Some(fill) => match &fill.payload {
+            Payload::Str(text) if text.char_len() == 1 => text.char_at(0),
+            Payload::Str(_) => {
+                return Err(vm.new_type_error("The fill character must be exactly one character long".to_string()))
+            }
+            _ => {
+                return Err(vm.new_type_error(format!(
+                    "{}() argument 2 must be str, not {}",
+                    name,
+                    vm.class_name(fill)
+                )))
+            }
+        },
+    };
+    let s = as_pystr(&obj);
+    let len = s.char_len();
+    if width <= len {
+        return Ok(vm.new_str(s.to_string()));
+    }
+    let margin = width - len;
+    let left = match align {
+        '<' => 0,
+        '>' => margin,
+        _ => margin / 2 + (margin & width & 1),
+    };
+    let fill_str = |count: usize| fill.to_string().repeat(count);
+    Ok(vm.new_str(format!("{}{}{}", fill_str(left), s, fill_str(margin - left))))
+}
+
+fn str_ljust(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    justify(vm, args, "ljust", '<')
+}
+
+fn str_rjust(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    justify(vm, args, "rjust", '>')
+}
+
+fn str_center(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    justify(vm, args, "center", '^')
+}
+
+fn str_zfill(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = receiver(vm, args, "str", "zfill", is_str)?;
+    check_args(vm, "str.zfill", &args, 1, 1)?;
+    let width = int_arg(vm, &args.args[0])?.max(0) as usize;
+    let s = as_pystr(&obj);
+    if width <= s.char_len() {
+        return Ok(vm.new_str(s.to_string()));
+    }
+    let zeros = "0".repeat(width - s.char_len());
+    let result = match s.strip_prefix(['+', '-']) {
+        Some(digits) => format!("{}{}{}", &s[..1], zeros, digits),
+        None => format!("{}{}", zeros, s),
+    };
+    Ok(vm.new_str(result))
+}
+
+fn str_expandtabs(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = receiver(vm, args, "str", "expandtabs", is_str)?;
+    let values = named_args(vm, "expandtabs", args, &["tabsize"])?;
+    let tabsize = match &values[0] {
+        Some(tabsize) => int_arg(vm, tabsize)?,
+        None => 8,
+    };
+    Ok(vm.new_str(expand_tabs(as_pystr(&obj), tabsize)))
+}
+
+fn str_translate(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = receiver(vm, args, "str", "translate", is_str)?;
+    check_args(vm, "str.translate", &args, 1, 1)?;
+    let table = &args.args[0];
+    let s = as_pystr(&obj);
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        let mapped = match vm.get_item(table, &vm.new_int(c as i64)) {
+            Ok(mapped) => mapped,
+            Err(err) if vm.exception_matches(&err, &vm.exceptions.lookup_error)? => {
+                out.push(c);
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        match &mapped.payload {
+            Payload::None => {}
+            Payload::Str(text) => out.push_str(text),
+            _ => match mapped.as_int() {
+                Some(code) => match code.to_i64().and_then(|code| u32::try_from(code).ok()).and_then(char::from_u32) {
+                    Some(c) => out.push(c),
+                    None => {
+                        return Err(vm.new_value_error("character mapping must be in range(0x110000)".to_string()))
+                    }
+                },
+                None => {
+                    return Err(vm.new_type_error("character mapping must return integer, None or str".to_string()))
+                }
+            },
+        }
+    }
+    Ok(vm.new_str(out))
+}
+
+/// `str.maketrans(x[, y[, z]])`: a table for `translate()` from a dict, or from two
+/// strings of equal length and a string of characters to delete.
+fn str_maketrans(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    check_args(vm, "maketrans", &args, 1, 3)?;
+    let table = vm.new_dict();
+    let str_param = |idx: usize| match &args.args[idx].payload {
+        Payload::Str(text) => Ok(text),
+        _ => Err(vm.new_type_error(format!(
+            "maketrans() argument {} must be str, not {}",
+            idx + 1,
+            vm.class_name(&args.args[idx])
+        ))),
+    };
+    if args.args.len() == 1 {
+        let Some(mapping) = args.args[0].as_dict() else {
+            return Err(vm.new_type_error(
+                "if you give only one argument to maketrans it must be a dict".to_string(),
+            ));
+        };
+        let items = mapping.borrow().items();
+        for (key, value) in items {
+            let key = match &key.payload {
+                Payload::Str(text) if text.char_len() == 1 => vm.new_int(text.char_at(0) as i64),
+                Payload::Str(_) => {
+                    return Err(vm.new_value_error("string keys in translate table must be of length 1".to_string()))
+                }
+                _ if key.as_int().is_some() => key.clone(),
+                _ => {
+                    return Err(vm.new_type_error("keys in translate table must be strings or integers".to_string()))
+                }
+            };
+            vm.dict_set_item(&table, &key, &value)?;
+        }
+        return Ok(table);
+    }
+    let (from, to) = (str_param(0)?, str_param(1)?);
+    if from.char_len() != to.char_len() {
+        return Err(vm.new_value_error("the first two maketrans arguments must have equal length".to_string()));
+    }
+    for (a, b) in from.chars().zip(to.chars()) {
+        vm.dict_set_item(&table, &vm.new_int(a as i64), &vm.new_int(b as i64))?;
+    }
+    if args.args.len() == 3 {
+        for c in str_param(2)?.chars() {
+            vm.dict_set_item(&table, &vm.new_int(c as i64), &vm.none())?;
+        }
+    }
+    Ok(table)
+}
+
+/// Encodes to a codec that has one byte per code point below `limit`, handling the other
+/// code points as the `errors` handler says.
+fn encode_bytes(vm: &VirtualMachine, s: &str, codec: &str, limit: u32, errors: &str) -> PyResult<Vec<u8>> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut out = Vec::with_capacity(chars.len());
+    let mut idx = 0;
+    while idx < chars.len() {
+        if (chars[idx] as u32) < limit {
+            out.push(chars[idx] as u8);
+            idx += 1;
+            continue;
+        }
+        let mut end = idx + 1;
+        while end < chars.len() && chars[end] as u32 >= limit {
+            end += 1;
+        }
+        let unencodable = &chars[idx..end];
+        match errors {
+            "strict" => {
+                let reason = format!("ordinal not in range({})", limit);
+                let msg = match unencodable {
+                    [c] => format!("'{}' codec can't encode character '{}' in position {}: {}", codec, escape_char(*c), idx, reason),
+                    _ => format!("'{}' codec can't encode characters in position {}-{}: {}", codec, idx, end - 1, reason),
+                };
+                return Err(vm.new_exception_msg(&vm.exceptions.unicode_encode_error, msg));
+            }
+            "ignore" => {}
+            "replace" => out.extend(unencodable.iter().map(|_| b'?')),
+            "xmlcharrefreplace" => unencodable.iter().for_each(|c| out.extend(format!("&#{};", *c as u32).bytes())),
+            "backslashreplace" => unencodable.iter().for_each(|c| out.extend(escape_char(*c).bytes())),
+            _ => {
+                return Err(vm.new_exception_msg(
+                    &vm.exceptions.lookup_error,
+                    format!("unknown error handler name '{}'", errors),
+                ))
+            }
+        }
+        idx = end;
+    }
+    Ok(out)
+}
+
+fn str_encode(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = receiver(vm, args, "str", "encode", is_str)?;
+    let values = named_args(vm, "encode", args, &["encoding", "errors"])?;
+    let text_arg = |idx: usize, name: &str, default: &str| match &values[idx] {
+        None => Ok(default.to_string()),
+        Some(value) => match &value.payload {
+            Payload::Str(text) => Ok(text.to_string()),
+            _ => Err(vm.new_type_error(format!(
+                "encode() argument '{}' must be str, not {}",
+                name,
+                vm.class_name(value)
+            ))),
+        },
+    };
+    let encoding = text_arg(0, "encoding", "utf-8")?;
+    let errors = text_arg(1, "errors", "strict")?;
+    // Codec names are looked up in lower case with punctuation as underscores.
+    let normalized: String = encoding
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '.' { c } else { '_' })
+        .collect();
+    let s = as_pystr(&obj);
+    let bytes = match normalized.as_str() {
+        "utf_8" | "utf8" | "u8" | "utf" | "cp65001" => s.as_bytes().to_vec(),
+        "latin_1" | "latin1" | "latin" | "l1" | "iso_8859_1" | "iso8859_1" | "8859" | "cp819" => {
+            encode_bytes(vm, s, "latin-1", 0x100, &errors)?
+        }
+        "ascii" | "us_ascii" | "646" => encode_bytes(vm, s, "ascii", 0x80, &errors)?,
+        _ => {
+            return Err(vm.new_exception_msg(&vm.exceptions.lookup_error, format!("unknown encoding: {}", encoding)))
+        }
+    };
+    Ok(vm.new_bytes(bytes))
+}
+
+fn str_format(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = receiver(vm, args, "str", "format", is_str)?;
+    let kwargs = vm.new_dict();
+    for (name, value) in args.kwargs {
+        vm.dict_set_str(&kwargs, &name, value);
+    }
+    Ok(vm.new_str(vm.str_format(as_pystr(&obj), Some(&args.args), &kwargs)?))
+}
+
+fn str_format_map(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (obj, args) = receiver(vm, args, "str", "format_map", is_str)?;
+    check_args(vm, "str.format_map", &args, 1, 1)?;
+    Ok(vm.new_str(vm.str_format(as_pystr(&obj), None, &args.args[0])?))
+}
+
+impl VirtualMachine {
+    pub(crate) fn add_str_methods(&self) {
+        let str_type = &self.types.str_type;
+        let methods: &[(&'static str, NativeFunction)] = &[
+            ("find", str_find),
+            ("rfind", str_rfind),
+            ("index", str_index),
+            ("rindex", str_rindex),
+            ("count", str_count),
+            ("startswith", str_startswith),
+            ("endswith", str_endswith),
+            ("split", str_split),
+            ("rsplit", str_rsplit),
+            ("splitlines", str_splitlines),
+            ("join", str_join),
+            ("strip", str_strip),
+            ("lstrip", str_lstrip),
+            ("rstrip", str_rstrip),
+            ("replace", str_replace),
+            ("partition", str_partition),
+            ("rpartition", str_rpartition),
+            ("removeprefix", str_removeprefix),
+            ("removesuffix", str_removesuffix),
+            ("upper", str_upper),
+            ("lower", str_lower),
+            ("casefold", str_casefold),
+            ("title", str_title),
+            ("capitalize", str_capitalize),
+            ("swapcase", str_swapcase),
+            ("isalpha", str_isalpha),
+            ("isalnum", str_isalnum),
+            ("isdecimal", str_isdecimal),
+            ("isdigit", str_isdigit),
+            ("isnumeric", str_isnumeric),
+            ("isspace", str_isspace),
+            ("isprintable", str_isprintable),
+            ("isascii", str_isascii),
+            ("isidentifier", str_isidentifier),
+            ("isupper", str_isupper),
+            ("islower", str_islower),
+            ("istitle", str_istitle),
+            ("ljust", str_ljust),
+            ("rjust", str_rjust),
+            ("center", str_center),
+            ("zfill", str_zfill),
+            ("expandtabs", str_expandtabs),
+            ("translate", str_translate),
+            ("encode", str_encode),
+            ("format", str_format),
+            ("format_map", str_format_map),
+        ];
+        for (name, function) in methods {
+            self.add_method(str_type, name, *function);
+        }
+        let maketrans = self.new_builtin_function("maketrans", str_maketrans);
+        let maketrans = self.new_object(Payload::StaticMethod(maketrans), &self.types.staticmethod_type);
+        self.dict_set_str(str_type.dict.as_ref().expect("type dict"), "maketrans", maketrans);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::VirtualMachine;
+    use super::{expand_tabs, split_whitespace, title, PyStr};
+
+    #[test]
+    fn non_ascii_strings_index_by_code_point() {
+        let s = PyStr::from("aé€𝄞b".to_string());
+        assert_eq!(s.char_len(), 5);
+        assert_eq!(s.char_at(3), '𝄞');
+        assert_eq!(s.substring(1, 4), "é€𝄞");
+        assert_eq!(s.slice(4, -2, 3), "b€a");
+        assert_eq!(s.char_index(s.byte_offset(4)), 4);
+    }
+
+    #[test]
+    fn splitting_and_case_mapping() {
+        assert_eq!(split_whitespace("  a b  c  ", 1, false), vec!["a", "b  c  "]);
+        assert_eq!(split_whitespace("  a b  c  ", 1, true), vec!["  a b", "c"]);
+        assert_eq!(title("they're ǆungla ßa ΑΣ"), "They'Re ǅungla Ssa Ας");
+        assert_eq!(expand_tabs("a\tbc\t\n\td", 4), "a   bc  \n    d");
+    }
+
+    #[test]
+    fn format_fields() {
+        let vm = VirtualMachine::capturing();
+        let source = "\
+class P:
+    x = 3
+    def __format__(self, spec):
+        return 'P' + spec
+print('{} {}'.format(1, 'a'), '{1}{0}{1}'.format('a', 'b'), '{x}-{y!r}'.format(x=1, y='q'))
+print('{0[1]} {0[a]} {{}}'.format({1: 'one', 'a': 'A'}), '{0.x:03d}'.format(P), '{:xyz}'.format(P()))
+print('{:>{}}|'.format('ab', 5), '{:.{p}f}'.format(3.14159, p=2), '{!a:>4}|'.format('é'))
+print('{name}'.format_map({'name': 'N'}))
+for template in ['{', '{}{0}', '{3}', '{!x}', '{:{:{}}}']:
+    try:
+        template.format(1, 2, 3)
+    except (ValueError, IndexError) as e:
+        print(e)
+";
+        vm.run_main(source, "<test>").unwrap_or_else(|_| panic!("the program raised"));
+        assert_eq!(
+            vm.take_output(),
+            "1 a bab 1-'q'\none A {} 003 Pxyz\n   ab| 3.14 '\\xe9'|\nN\n\
+             Single '{' encountered in format string\n\
+             cannot switch from automatic field numbering to manual field specification\n\
+             Replacement index 3 out of range for positional args tuple\n\
+             Unknown conversion specifier x\n\
+             Max string recursion exceeded\n"
+        );
+    }
+}
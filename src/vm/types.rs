@@ -0,0 +1,826 @@
+//! Type objects for the builtin types and the `__new__` of the ones that can be called.
+//!
+//! `type`, `object` and `dict` refer to each other, so they are made without classes and the
+//! classes are filled in once all three exist.
+
+use std::cell::RefCell;
+
+use crate::bigint::BigInt;
+
+use super::dict::PyDict;
+use super::object::{FuncArgs, NativeFunction, Payload, PyInt, PyObject, PyObjectRef, PyProperty, PyResult, PyType};
+use super::string::PyStr;
+use super::VirtualMachine;
+
+pub struct Types {
+    pub type_type: PyObjectRef,
+    pub object_type: PyObjectRef,
+    pub dict_type: PyObjectRef,
+    pub none_type: PyObjectRef,
+    pub not_implemented_type: PyObjectRef,
+    pub ellipsis_type: PyObjectRef,
+    pub bool_type: PyObjectRef,
+    pub int_type: PyObjectRef,
+    pub float_type: PyObjectRef,
+    pub complex_type: PyObjectRef,
+    pub str_type: PyObjectRef,
+    pub bytes_type: PyObjectRef,
+    pub tuple_type: PyObjectRef,
+    pub list_type: PyObjectRef,
+    pub set_type: PyObjectRef,
+    pub frozenset_type: PyObjectRef,
+    pub range_type: PyObjectRef,
+    pub slice_type: PyObjectRef,
+    pub iterator_type: PyObjectRef,
+    pub function_type: PyObjectRef,
+    pub builtin_function_type: PyObjectRef,
+    pub method_type: PyObjectRef,
+    pub staticmethod_type: PyObjectRef,
+    pub classmethod_type: PyObjectRef,
+    pub property_type: PyObjectRef,
+    pub member_descriptor_type: PyObjectRef,
+    pub super_type: PyObjectRef,
+    pub traceback_type: PyObjectRef,
+    pub dict_keys_type: PyObjectRef,
+    pub dict_values_type: PyObjectRef,
+    pub dict_items_type: PyObjectRef,
+    pub code_type: PyObjectRef,
+    pub cell_type: PyObjectRef,
+    pub generator_type: PyObjectRef,
+    pub coroutine_type: PyObjectRef,
+    pub async_generator_type: PyObjectRef,
+    pub async_generator_asend_type: PyObjectRef,
+    pub module_type: PyObjectRef,
+    pub typevar_type: PyObjectRef,
+    pub typevartuple_type: PyObjectRef,
+    pub paramspec_type: PyObjectRef,
+    pub type_alias_type: PyObjectRef,
+}
+
+/// A builtin type with the given base, whose dict is an object of class `dict_type` if that
+/// exists yet.
+fn builtin_type(
+    name: &str,
+    base: Option<&PyObjectRef>,
+    new: Option<NativeFunction>,
+    instance_dict: bool,
+    dict_type: Option<&PyObjectRef>,
+) -> PyObjectRef {
+    let dict = PyObject::new_unclassed(Payload::Dict(RefCell::new(PyDict::new())), None);
+    if let Some(dict_type) = dict_type {
+        dict.set_class(dict_type.clone());
+    }
+    let bases: Vec<PyObjectRef> = base.into_iter().cloned().collect();
+    let ty = PyType {
+        name: RefCell::new(name.to_string()),
+        qualname: RefCell::new(name.to_string()),
+        bases: RefCell::new(bases.clone()),
+        mro: RefCell::new(vec![]),
+        new,
+        heap: false,
+        instance_dict,
+        slots: 0,
+    };
+    let obj = PyObject::new_unclassed(Payload::Type(ty), Some(dict));
+    let mut mro = vec![obj.clone()];
+    if let Some(base) = base {
+        mro.extend(base.as_type().expect("base type").mro.borrow().iter().cloned());
+    }
+    *obj.as_type().expect("type").mro.borrow_mut() = mro;
+    if let Some(dict_type) = dict_type {
+        obj.set_class(type_of_types(dict_type));
+    }
+    obj
+}
+
+/// `type` itself, found as the class of `dict`.
+fn type_of_types(dict_type: &PyObjectRef) -> PyObjectRef {
+    dict_type.class()
+}
+
+impl Types {
+    pub(crate) fn new() -> Self {
+        let object_type = builtin_type("object", None, Some(object_new), false, None);
+        let type_type = builtin_type("type", Some(&object_type), Some(type_new), true, None);
+        let dict_type = builtin_type("dict", Some(&object_type), Some(dict_new), false, None);
+        for ty in [&object_type, &type_type, &dict_type] {
+            ty.set_class(type_type.clone());
+            ty.dict.as_ref().expect("type dict").set_class(dict_type.clone());
+        }
+        let make = |name: &str, base: &PyObjectRef, new: Option<NativeFunction>| {
+            builtin_type(name, Some(base), new, false, Some(&dict_type))
+        };
+        let with_dict = |name: &str, new: Option<NativeFunction>| {
+            builtin_type(name, Some(&object_type), new, true, Some(&dict_type))
+        };
+        let int_type = make("int", &object_type, Some(int_new));
+        Self {
+            none_type: make("NoneType", &object_type, None),
+            not_implemented_type: make("NotImplementedType", &object_type, None),
+            ellipsis_type: make("ellipsis", &object_type, None),
+            bool_type: make("bool", &int_type, Some(bool_new)),
+            float_type: make("float", &object_type, Some(float_new)),
+            complex_type: make("complex", &object_type, Some(complex_new)),
+            str_type: make("str", &object_type, Some(str_new)),
+            bytes_type: make("bytes", &object_type, Some(bytes_new)),
+            tuple_type: make("tuple", &object_type, Some(tuple_new)),
+            list_type: make("list", &object_type, Some(list_new)),
+            set_type: make("set", &object_type, Some(set_new)),
+            frozenset_type: make("frozenset", &object_type, Some(frozenset_new)),
+            range_type: make("range", &object_type, Some(range_new)),
+            slice_type: make("slice", &object_type, Some(slice_new)),
+            iterator_type: make("iterator", &object_type, None),
+            function_type: with_dict("function", None),
+            builtin_function_type: make("builtin_function_or_method", &object_type, None),
+            method_type: make("method", &object_type, None),
+            staticmethod_type: with_dict("staticmethod", Some(staticmethod_new)),
+            classmethod_type: with_dict("classmethod", Some(classmethod_new)),
+            property_type: make("property", &object_type, Some(property_new)),
+            member_descriptor_type: make("member_descriptor", &object_type, None),
+            super_type: make("super", &object_type, Some(super_new)),
+            traceback_type: make("traceback", &object_type, None),
+            dict_keys_type: make("dict_keys", &object_type, None),
+            dict_values_type: make("dict_values", &object_type, None),
+            dict_items_type: make("dict_items", &object_type, None),
+            code_type: make("code", &object_type, None),
+            cell_type: make("cell", &object_type, None),
+            generator_type: make("generator", &object_type, None),
+            coroutine_type: make("coroutine", &object_type, None),
+            async_generator_type: make("async_generator", &object_type, None),
+            async_generator_asend_type: make("async_generator_asend", &object_type, None),
+            module_type: with_dict("module", None),
+            typevar_type: with_dict("TypeVar", None),
+            typevartuple_type: with_dict("TypeVarTuple", None),
+            paramspec_type: with_dict("ParamSpec", None),
+            type_alias_type: with_dict("TypeAliasType", None),
+            int_type,
+            type_type,
+            object_type,
+            dict_type,
+        }
+    }
+
+    /// The builtin types that are in `builtins` under their own name.
+    pub fn builtin_names(&self) -> Vec<(&'static str, &PyObjectRef)> {
+        vec![
+            ("object", &self.object_type),
+            ("type", &self.type_type),
+            ("dict", &self.dict_type),
+            ("bool", &self.bool_type),
+            ("int", &self.int_type),
+            ("float", &self.float_type),
+            ("complex", &self.complex_type),
+            ("str", &self.str_type),
+            ("bytes", &self.bytes_type),
+            ("tuple", &self.tuple_type),
+            ("list", &self.list_type),
+            ("set", &self.set_type),
+            ("frozenset", &self.frozenset_type),
+            ("range", &self.range_type),
+            ("slice", &self.slice_type),
+            ("staticmethod", &self.staticmethod_type),
+            ("classmethod", &self.classmethod_type),
+            ("property", &self.property_type),
+            ("super", &self.super_type),
+        ]
+    }
+}
+
+impl VirtualMachine {
+    /// A builtin type of a module other than `builtins`, such as `sys.stdout`'s.
+    pub(crate) fn new_builtin_type(&self, module: &str, name: &str, instance_dict: bool) -> PyObjectRef {
+        let types = &self.types;
+        let class = builtin_type(name, Some(&types.object_type), None, instance_dict, Some(&types.dict_type));
+        self.dict_set_str(class.dict.as_ref().expect("type dict"), "__module__", self.new_str(module.to_string()));
+        class
+    }
+
+    /// A new instance dict for an instance of `class`, if its instances have one.
+    pub fn instance_dict(&self, class: &PyObjectRef) -> Option<PyObjectRef> {
+        match class.as_type() {
+            Some(ty) if ty.instance_dict => Some(self.new_dict()),
+            _ => None,
+        }
+    }
+
+    /// An object of `class` with `payload`, with an instance dict if the class has them
+    /// and room for the values of its `__slots__`.
+    pub fn new_object(&self, payload: Payload, class: &PyObjectRef) -> PyObjectRef {
+        let slots = class.as_type().map_or(0, |ty| ty.slots);
+        PyObject::with_slots(payload, class.clone(), self.instance_dict(class), slots)
+    }
+
+    /// `class(*args, **kwargs)`: creates the instance with the nearest `__new__` and, if it
+    /// is an instance of `class`, initializes it with the nearest `__init__`.
+    pub fn call_type(&self, class: &PyObjectRef, mut args: FuncArgs) -> PyResult {
+        if class.is(&self.types.type_type) && args.args.len() == 1 && args.kwargs.is_empty() {
+            return Ok(args.args[0].class());
+        }
+        let ty = class.as_type().expect("type");
+        let user_new = self.lookup_user_method(class, "__new__");
+        let user_init = self.lookup_user_method(class, "__init__");
+        let instance = match &user_new {
+            Some(new) => {
+                let new = match &new.payload {
+                    Payload::StaticMethod(function) => function.clone(),
+                    _ => new.clone(),
+                };
+                let mut new_args = vec![class.clone()];
+                new_args.extend(args.args.iter().cloned());
+                self.call(&new, FuncArgs::with_kwargs(new_args, args.kwargs.clone()))?
+            }
+            None => {
+                let native = ty
+                    .mro
+                    .borrow()
+                    .iter()
+                    .find_map(|base| base.as_type().and_then(|t| t.new))
+                    .expect("object has a __new__");
+                if user_init.is_some() && self.solid_base(class).is(&self.types.object_type) {
+                    // Only `object.__new__` itself is given no arguments when `__init__` takes
+                    // them.
+                    native(self, FuncArgs::new(vec![class.clone()]))?
+                } else {
+                    let mut new_args = vec![class.clone()];
+                    new_args.append(&mut args.args.clone());
+                    native(self, FuncArgs::with_kwargs(new_args, args.kwargs.clone()))?
+                }
+            }
+        };
+        if !self.isinstance(&instance, class) {
+            return Ok(instance);
+        }
+        if let Some(init) = user_init {
+            let mut init_args = vec![instance.clone()];
+            init_args.append(&mut args.args);
+            let result = self.call(&init, FuncArgs::with_kwargs(init_args, std::mem::take(&mut args.kwargs)))?;
+            if !self.is_none(&result) {
+                return Err(self.new_type_error(format!(
+                    "__init__() should return None, not '{}'",
+                    self.class_name(&result)
+                )));
+            }
+        }
+        Ok(instance)
+    }
+
+    /// A `__new__` or `__init__` defined by a class statement, found before any builtin
+    /// type in the method resolution order.
+    pub(crate) fn lookup_user_method(&self, class: &PyObjectRef, name: &str) -> Option<PyObjectRef> {
+        let mro = class.as_type()?.mro.borrow().clone();
+        for base in mro {
+            if !base.as_type().is_some_and(|t| t.heap) {
+                return None;
+            }
+            if let Some(value) = self.dict_get_str(base.dict.as_ref()?, name) {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    /// The builtin type whose payload instances of `class` carry.
+    pub fn solid_base(&self, class: &PyObjectRef) -> PyObjectRef {
+        let mro = class.as_type().expect("type").mro.borrow().clone();
+        mro.into_iter().find(|base| !base.as_type().is_some_and(|t| t.heap)).expect("object is in every mro")
+    }
+
+    fn check_no_args(&self, name: &str, args: &FuncArgs, max: usize) -> PyResult<()> {
+        if !args.kwargs.is_empty() {
+            return Err(self.new_type_error(format!("{}() takes no keyword arguments", name)));
+        }
+        if args.args.len() > max + 1 {
+            return Err(self.new_type_error(format!(
+                "{} expected at most {} argument{}, got {}",
+                name,
+                max,
+                if max == 1 { "" } else { "s" },
+                args.args.len() - 1
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn split_class(args: FuncArgs) -> (PyObjectRef, FuncArgs) {
+    let mut args = args;
+    let class = args.args.remove(0);
+    (class, args)
+}
+
+fn object_new(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (class, args) = split_class(args);
+    if !args.args.is_empty() || !args.kwargs.is_empty() {
+        let name = class.as_type().expect("type").name();
+        return Err(vm.new_type_error(format!("{}() takes no arguments", name)));
+    }
+    Ok(vm.new_object(Payload::Object, &class))
+}
+
+fn type_new(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (metatype, args) = split_class(args);
+    match args.args.len() {
+        1 if args.kwargs.is_empty() => Ok(args.args[0].class()),
+        3 => {
+            let name = vm.expect_str(&args.args[0], "type.__new__() argument 1")?;
+            let bases = match args.args[1].as_tuple() {
+                Some(bases) => bases.to_vec(),
+                None => return Err(vm.new_type_error("type.__new__() argument 2 must be tuple".to_string())),
+            };
+            let namespace = &args.args[2];
+            if namespace.as_dict().is_none() {
+                return Err(vm.new_type_error("type.__new__() argument 3 must be dict".to_string()));
+            }
+            vm.new_class(&metatype, &name, bases, namespace, args.kwargs)
+        }
+        _ => Err(vm.new_type_error("type() takes 1 or 3 arguments".to_string())),
+    }
+}
+
+/// `super()`, `super(type)` or `super(type, obj)`.  Without arguments the class comes from
+/// the `__class__` cell of the calling function and the object from its first argument.
+fn super_new(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (class, args) = split_class(args);
+    if !args.kwargs.is_empty() {
+        return Err(vm.new_type_error("super() takes no keyword arguments".to_string()));
+    }
+    let (this_class, obj) = match args.args.len() {
+        0 => vm.super_arguments()?,
+        1 => (args.args[0].clone(), None),
+        2 => (args.args[0].clone(), Some(args.args[1].clone())),
+        n => return Err(vm.new_type_error(format!("super() expected at most 2 arguments, got {}", n))),
+    };
+    if this_class.as_type().is_none() {
+        return Err(vm.new_type_error(format!(
+            "super() argument 1 must be a type, not {}",
+            vm.class_name(&this_class)
+        )));
+    }
+    let obj_type = match &obj {
+        Some(obj) if obj.as_type().is_some() && vm.is_subclass(obj, &this_class) => Some(obj.clone()),
+        Some(obj) if vm.isinstance(obj, &this_class) => Some(obj.class()),
+        Some(_) => {
+            return Err(vm.new_type_error("super(type, obj): obj must be an instance or subtype of type".to_string()))
+        }
+        None => None,
+    };
+    Ok(vm.new_object(Payload::Super { this_class, obj, obj_type }, &class))
+}
+
+fn dict_new(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (class, args) = split_class(args);
+    vm.check_no_args("dict", &FuncArgs::new(args.args.clone()), 1)?;
+    let dict = vm.new_object(Payload::Dict(RefCell::new(PyDict::new())), &class);
+    if let Some(source) = args.args.first() {
+        vm.dict_update(&dict, source)?;
+    }
+    for (key, value) in args.kwargs {
+        vm.dict_set_item(&dict, &vm.new_str(key), &value)?;
+    }
+    Ok(dict)
+}
+
+fn int_new(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (class, mut args) = split_class(args);
+    let base = args.take_kwarg("base");
+    vm.check_no_args("int", &args, 2)?;
+    let base = base.or_else(|| args.args.get(1).cloned());
+    let value = match (args.args.first(), base) {
+        (None, None) => BigInt::zero(),
+        (None, Some(_)) => return Err(vm.new_type_error("int() missing string argument".to_string())),
+        (Some(value), None) => vm.to_int(value)?,
+        (Some(value), Some(base)) => {
+            let base = vm.to_index(&base)?.to_i64().unwrap_or(-1);
+            let text = match &value.payload {
+                Payload::Str(s) => s.to_string(),
+                Payload::Bytes(b) => String::from_utf8_lossy(b).to_string(),
+                _ => return Err(vm.new_type_error("int() can't convert non-string with explicit base".to_string())),
+            };
+            if base != 0 && !(2..=36).contains(&base) {
+                return Err(vm.new_value_error("int() base must be >= 2 and <= 36, or 0".to_string()));
+            }
+            vm.parse_int(&text, base as u32)?
+        }
+    };
+    if class.is(&vm.types.int_type) {
+        return Ok(vm.new_bigint(value));
+    }
+    Ok(vm.new_object(Payload::Int(PyInt::from_bigint(value)), &class))
+}
+
+fn bool_new(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (_, args) = split_class(args);
+    vm.check_no_args("bool", &args, 1)?;
+    match args.args.first() {
+        Some(value) => Ok(vm.new_bool(vm.is_true(value)?)),
+        None => Ok(vm.new_bool(false)),
+    }
+}
+
+fn float_new(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (class, args) = split_class(args);
+    vm.check_no_args("float", &args, 1)?;
+    let value = match args.args.first() {
+        None => 0.0,
+        Some(value) => match &value.payload {
+            Payload::Str(s) => vm.parse_float(s)?,
+            _ => vm.to_float(value)?,
+        },
+    };
+    Ok(vm.new_object(Payload::Float(value), &class))
+}
+
+fn complex_new(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (class, args) = split_class(args);
+    vm.check_no_args("complex", &args, 2)?;
+    let part = |idx: usize| -> PyResult<(f64, f64)> {
+        match args.args.get(idx) {
+            None => Ok((0.0, 0.0)),
+            Some(value) => match &value.payload {
+                Payload::Complex { real, imag } => Ok((*real, *imag)),
+                _ => Ok((vm.to_float(value)?, 0.0)),
+            },
+        }
+    };
+    let (real, real_imag) = part(0)?;
+    let (imag_real, imag_imag) = part(1)?;
+    Ok(vm.new_object(Payload::Complex { real: real - imag_imag, imag: real_imag + imag_real }, &class))
+}
+
+fn str_new(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (class, args) = split_class(args);
+    vm.check_no_args("str", &args, 1)?;
+    let value = match args.args.first() {
+        Some(value) => vm.to_str(value)?,
+        None => String::new(),
+    };
+    if class.is(&vm.types.str_type) {
+        return Ok(vm.new_str(value));
+    }
+    Ok(vm.new_object(Payload::Str(PyStr::new(value)), &class))
+}
+
+fn bytes_new(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (class, args) = split_class(args);
+    vm.check_no_args("bytes", &args, 1)?;
+    let value = match args.args.first() {
+        None => vec![],
+        Some(value) => match &value.payload {
+            Payload::Bytes(b) => b.clone(),
+            Payload::Int(_) => {
+                let len = vm.to_index(value)?.to_i64().unwrap_or(-1);
+                if len < 0 {
+                    return Err(vm.new_value_error("negative count".to_string()));
+                }
+                vec![0; len as usize]
+            }
+            Payload::Str(_) => return Err(vm.new_type_error("string argument without an encoding".to_string())),
+            _ => {
+                let mut bytes = vec![];
+                for item in vm.collect(value)? {
+                    match vm.to_index(&item)?.to_i64() {
+                        Some(byte @ 0..=255) => bytes.push(byte as u8),
+                        _ => return Err(vm.new_value_error("bytes must be in range(0, 256)".to_string())),
+                    }
+                }
+                bytes
+            }
+        },
+    };
+    Ok(vm.new_object(Payload::Bytes(value), &class))
+}
+
+fn tuple_new(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (class, args) = split_class(args);
+    vm.check_no_args("tuple", &args, 1)?;
+    let items = match args.args.first() {
+        Some(iterable) => vm.collect(iterable)?,
+        None => vec![],
+    };
+    if class.is(&vm.types.tuple_type) {
+        return Ok(vm.new_tuple(items));
+    }
+    Ok(vm.new_object(Payload::Tuple(items), &class))
+}
+
+fn list_new(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (class, args) = split_class(args);
+    vm.check_no_args("list", &args, 1)?;
+    let items = match args.args.first() {
+        Some(iterable) => vm.collect(iterable)?,
+        None => vec![],
+    };
+    Ok(vm.new_object(Payload::List(RefCell::new(items)), &class))
+}
+
+fn set_new(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (class, args) = split_class(args);
+    vm.check_no_args("set", &args, 1)?;
+    let set = vm.new_object(Payload::Set(RefCell::new(PyDict::new())), &class);
+    if let Some(iterable) = args.args.first() {
+        for item in vm.collect(iterable)? {
+            vm.set_add(&set, &item)?;
+        }
+    }
+    Ok(set)
+}
+
+fn frozenset_new(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (class, args) = split_class(args);
+    vm.check_no_args("frozenset", &args, 1)?;
+    let items = match args.args.first() {
+        Some(iterable) => vm.collect(iterable)?,
+        None => vec![],
+    };
+    let table = vm.hash_table(&items)?;
+    Ok(vm.new_object(Payload::FrozenSet(table), &class))
+}
+
+fn range_new(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (class, args) = split_class(args);
+    if !args.kwargs.is_empty() {
+        return Err(vm.new_type_error("range() takes no keyword arguments".to_string()));
+    }
+    let ints = args.args.iter().map(|arg| vm.to_index(arg)).collect::<PyResult<Vec<_>>>()?;
+    let (start, stop, step) = match ints.as_slice() {
+        [stop] => (BigInt::zero(), stop.clone(), BigInt::from(1)),
+        [start, stop] => (start.clone(), stop.clone(), BigInt::from(1)),
+        [start, stop, step] => (start.clone(), stop.clone(), step.clone()),
+        [] => return Err(vm.new_type_error("range expected at least 1 argument, got 0".to_string())),
+        _ => return Err(vm.new_type_error(format!("range expected at most 3 arguments, got {}", ints.len()))),
+    };
+    if step.is_zero() {
+        return Err(vm.new_value_error("range() arg 3 must not be zero".to_string()));
+    }
+    Ok(vm.new_object(Payload::Range { start, stop, step }, &class))
+}
+
+fn slice_new(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (class, args) = split_class(args);
+    let none = || vm.none();
+    let (start, stop, step) = match args.args.as_slice() {
+        [stop] => (none(), stop.clone(), none()),
+        [start, stop] => (start.clone(), stop.clone(), none()),
+        [start, stop, step] => (start.clone(), stop.clone(), step.clone()),
+        _ => return Err(vm.new_type_error(format!("slice expected 1 to 3 arguments, got {}", args.args.len()))),
+    };
+    Ok(vm.new_object(Payload::Slice { start, stop, step }, &class))
+}
+
+fn staticmethod_new(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (class, args) = split_class(args);
+    match args.args.as_slice() {
+        [function] => Ok(vm.new_object(Payload::StaticMethod(function.clone()), &class)),
+        _ => Err(vm.new_type_error(format!("staticmethod expected 1 argument, got {}", args.args.len()))),
+    }
+}
+
+fn classmethod_new(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (class, args) = split_class(args);
+    match args.args.as_slice() {
+        [function] => Ok(vm.new_object(Payload::ClassMethod(function.clone()), &class)),
+        _ => Err(vm.new_type_error(format!("classmethod expected 1 argument, got {}", args.args.len()))),
+    }
+}
+
+/// `property(fget=None, fset=None, fdel=None, doc=None)`.
+fn property_new(vm: &VirtualMachine, args: FuncArgs) -> PyResult {
+    let (class, mut args) = split_class(args);
+    let mut take = |position: usize, name: &str| {
+        let value = args.take_kwarg(name).or_else(|| args.args.get(position).cloned());
+        value.filter(|value| !vm.is_none(value))
+    };
+    let (getter, setter, deleter, doc) = (take(0, "fget"), take(1, "fset"), take(2, "fdel"), take(3, "doc"));
+    if let Some((name, _)) = args.kwargs.first() {
+        return Err(vm.new_type_error(format!("property() got an unexpected keyword argument '{}'", name)));
+    }
+    if args.args.len() > 4 {
+        return Err(vm.new_type_error(format!(
+            "property() takes at most 4 arguments ({} given)",
+            args.args.len()
+        )));
+    }
+    let doc = match (doc, &getter) {
+        (Some(doc), _) => doc,
+        (None, Some(getter)) => vm.get_attribute_opt(getter, "__doc__")?.unwrap_or_else(|| vm.none()),
+        (None, None) => vm.none(),
+    };
+    let property = PyProperty { name: RefCell::new(None), getter, setter, deleter, doc };
+    Ok(vm.new_object(Payload::Property(property), &class))
+}
+
+impl VirtualMachine {
+    /// A class made by a class statement or `type(name, bases, namespace)`.  The keywords
+    /// of the class statement go to the `__init_subclass__` of its bases.
+    pub fn new_class(
+        &self,
+        metatype: &PyObjectRef,
+        name: &str,
+        bases: Vec<PyObjectRef>,
+        namespace: &PyObjectRef,
+        kwargs: Vec<(String, PyObjectRef)>,
+    ) -> PyResult {
+        let bases = if bases.is_empty() { vec![self.types.object_type.clone()] } else { bases };
+        for base in &bases {
+            let Some(ty) = base.as_type() else {
+                return Err(self.new_type_error(format!("bases must be types, not '{}'", self.class_name(base))));
+            };
+            if (!ty.heap && ty.new.is_none()) || base.is(&self.types.bool_type) {
+                return Err(self.new_type_error(format!("type '{}' is not an acceptable base type", ty.name())));
+            }
+        }
+        let dict = self.new_dict();
+        let entries = namespace.as_dict().expect("namespace dict").borrow().clone();
+        *dict.as_dict().expect("dict").borrow_mut() = entries;
+        let qualname = match self.dict_get_str(&dict, "__qualname__") {
+            Some(qualname) => {
+                self.dict_del_str(&dict, "__qualname__");
+                self.expect_str(&qualname, "type __qualname__")?
+            }
+            None => name.to_string(),
+        };
+        // A class that defines equality but not hashing is unhashable.
+        if self.dict_get_str(&dict, "__eq__").is_some() && self.dict_get_str(&dict, "__hash__").is_none() {
+            self.dict_set_str(&dict, "__hash__", self.none());
+        }
+        // A class made by calling `type` belongs to the module that calls it.
+        if self.dict_get_str(&dict, "__module__").is_none() {
+            let globals = self.frames.borrow().last().map(|frame| frame.globals.clone());
+            if let Some(module) = globals.and_then(|globals| self.dict_get_str(&globals, "__name__")) {
+                self.dict_set_str(&dict, "__module__", module);
+            }
+        }
+        // These are implicitly static and class methods.
+        for (method, static_method) in [("__new__", true), ("__init_subclass__", false), ("__class_getitem__", false)] {
+            if let Some(function) = self.dict_get_str(&dict, method).filter(|f| matches!(f.payload, Payload::Function(_))) {
+                let wrapped = match static_method {
+                    true => self.new_object(Payload::StaticMethod(function), &self.types.staticmethod_type),
+                    false => self.new_object(Payload::ClassMethod(function), &self.types.classmethod_type),
+                };
+                self.dict_set_str(&dict, method, wrapped);
+            }
+        }
+        let base_slots = bases.iter().map(|base| base.as_type().expect("type").slots).max().unwrap_or(0);
+        let (slot_names, instance_dict) = match self.dict_get_str(&dict, "__slots__") {
+            Some(slots) => self.slot_names(name, &slots, &dict, &bases)?,
+            None => (vec![], true),
+        };
+        let ty = PyType {
+            name: RefCell::new(name.to_string()),
+            qualname: RefCell::new(qualname),
+            bases: RefCell::new(bases.clone()),
+            mro: RefCell::new(vec![]),
+            new: None,
+            heap: true,
+            instance_dict,
+            slots: base_slots + slot_names.len(),
+        };
+        let class = PyObject::new(Payload::Type(ty), metatype.clone(), Some(dict.clone()));
+        let mro = self.compute_mro(&class, &bases)?;
+        *class.as_type().expect("type").mro.borrow_mut() = mro;
+        for (offset, slot) in slot_names.into_iter().enumerate() {
+            let member = Payload::Member { name: slot.clone(), owner: class.clone(), index: base_slots + offset };
+            self.dict_set_str(&dict, &slot, self.new_object(member, &self.types.member_descriptor_type));
+        }
+        // Methods that use `super()` or `__class__` close over this cell.
+        if let Some(cell) = self.dict_del_str(&dict, "__classcell__") {
+            match &cell.payload {
+                Payload::Cell(content) => *content.borrow_mut() = Some(class.clone()),
+                _ => {
+                    return Err(self.new_type_error(format!(
+                        "__classcell__ must be a nonlocal cell, not {}",
+                        self.repr(&cell.class())?
+                    )))
+                }
+            }
+        }
+        self.set_names(&class)?;
+        // `super(class, class).__init_subclass__(**kwargs)`.
+        let parents = class.as_type().expect("type").mro.borrow()[1..].to_vec();
+        let init_subclass = parents.iter().find_map(|base| self.dict_get_str(base.dict.as_ref()?, "__init_subclass__"));
+        if let Some(init_subclass) = init_subclass {
+            let init_subclass = self.bind(&init_subclass, None, &class)?;
+            self.call(&init_subclass, FuncArgs::with_kwargs(vec![], kwargs))?;
+        }
+        Ok(class)
+    }
+
+    /// The member names that `__slots__` gives a new class, mangled like private names, and
+    /// whether its instances still have a `__dict__`.
+    fn slot_names(
+        &self,
+        class_name: &str,
+        slots: &PyObjectRef,
+        dict: &PyObjectRef,
+        bases: &[PyObjectRef],
+    ) -> PyResult<(Vec<String>, bool)> {
+        let slots = match slots.as_str() {
+            Some(_) => vec![slots.clone()],
+            None => self.collect(slots)?,
+        };
+        let mut instance_dict = bases.iter().any(|base| base.as_type().expect("type").instance_dict);
+        let mut names = vec![];
+        for slot in slots {
+            let Some(slot) = slot.as_str() else {
+                return Err(self.new_type_error(format!(
+                    "__slots__ items must be strings, not '{}'",
+                    self.class_name(&slot)
+                )));
+            };
+            match slot {
+                "__dict__" => instance_dict = true,
+                "__weakref__" => {}
+                _ if slot.starts_with("__") && !slot.ends_with("__") => {
+                    names.push(format!("_{}{}", class_name.trim_start_matches('_'), slot))
+                }
+                _ => names.push(slot.to_string()),
+            }
+        }
+        for name in &names {
+            if self.dict_get_str(dict, name).is_some() {
+                return Err(self.new_value_error(format!("'{}' in __slots__ conflicts with class variable", name)));
+            }
+        }
+        Ok((names, instance_dict))
+    }
+
+    /// Tells the attributes of a new class their names, through `__set_name__`.
+    fn set_names(&self, class: &PyObjectRef) -> PyResult<()> {
+        let items = class.dict.as_ref().expect("class dict").as_dict().expect("dict").borrow().items();
+        for (name, value) in items {
+            if let Payload::Property(property) = &value.payload {
+                if let Some(name) = name.as_str() {
+                    *property.name.borrow_mut() = Some(name.to_string());
+                }
+            } else if let Some(set_name) = self.special_method(&value, "__set_name__") {
+                self.call_bound(&set_name, &value, vec![class.clone(), name.clone()])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The C3 linearization of a new class: the class, then a merge of the bases' orders
+    /// and the bases list that keeps every local precedence order.
+    fn compute_mro(&self, class: &PyObjectRef, bases: &[PyObjectRef]) -> PyResult<Vec<PyObjectRef>> {
+        for (idx, base) in bases.iter().enumerate() {
+            if bases[..idx].iter().any(|seen| seen.is(base)) {
+                let name = base.as_type().expect("type").name();
+                return Err(self.new_type_error(format!("duplicate base class {}", name)));
+            }
+        }
+        let mut to_merge: Vec<Vec<PyObjectRef>> =
+            bases.iter().map(|base| base.as_type().expect("type").mro.borrow().clone()).collect();
+        to_merge.push(bases.to_vec());
+        let mut mro = vec![class.clone()];
+        loop {
+            to_merge.retain(|seq| !seq.is_empty());
+            if to_merge.is_empty() {
+                return Ok(mro);
+            }
+            // The first head that is not in the tail of any sequence comes next.
+            let next = to_merge.iter().map(|seq| &seq[0]).find(|head| {
+                !to_merge.iter().any(|seq| seq[1..].iter().any(|entry| entry.is(head)))
+            });
+            let Some(next) = next.cloned() else {
+                let mut heads: Vec<PyObjectRef> = vec![];
+                for seq in &to_merge {
+                    if !heads.iter().any(|head| head.is(&seq[0])) {
+                        heads.push(seq[0].clone());
+                    }
+                }
+                let names: Vec<String> = heads.iter().map(|head| head.as_type().expect("type").name()).collect();
+                return Err(self.new_type_error(format!(
+                    "Cannot create a consistent method resolution\norder (MRO) for bases {}",
+                    names.join(", ")
+                )));
+            };
+            for seq in &mut to_merge {
+                if seq[0].is(&next) {
+                    seq.remove(0);
+                }
+            }
+            mro.push(next);
+        }
+    }
+
+    pub fn is_subclass(&self, class: &PyObjectRef, base: &PyObjectRef) -> bool {
+        match class.as_type() {
+            Some(ty) => ty.mro.borrow().iter().any(|entry| entry.is(base)),
+            None => false,
+        }
+    }
+
+    pub fn isinstance(&self, obj: &PyObjectRef, class: &PyObjectRef) -> bool {
+        self.is_subclass(&obj.class(), class)
+    }
+
+    /// Finds `name` in the dicts along the method resolution order of `class`.
+    pub fn type_lookup(&self, class: &PyObjectRef, name: &str) -> Option<PyObjectRef> {
+        let mro = class.as_type()?.mro.borrow().clone();
+        mro.iter().find_map(|base| self.dict_get_str(base.dict.as_ref()?, name))
+    }
+
+    pub fn class_name(&self, obj: &PyObjectRef) -> String {
+        obj.class().as_type().map(|t| t.name()).unwrap_or_default()
+    }
+}